@@ -0,0 +1,239 @@
+//! Offline activation, for machines that never reach the vendor.
+//!
+//! [`Engine::activation_request`] produces an [`ActivationRequest`]: the
+//! engine's fingerprint and a fresh nonce, to be carried to the vendor on
+//! removable media, armored (see [`crate::armor`]). The vendor answers with
+//! an [`Activation`] for the same fingerprint and nonce, naming the license
+//! granted and its validity window, signed with its Dilithium5 key.
+//! [`Engine::install_activation`] checks the response against
+//! [`EngineConfig::vendor_key`] and records it as an `approval` event.
+//! An engine with a vendor key refuses every operation that needs a
+//! [`Permission`](crate::rbac::Permission) with [`CoreError::Unauthorized`]
+//! until then, and again once the activation expires.
+//!
+//! The nonce carries a tag keyed by the engine's identity key, so the
+//! engine recognizes its own requests without remembering them. A response
+//! still installs after a restart as long as the identity persists, and a
+//! saved response can be installed again at each start. A response for
+//! another engine, or for a request this engine never made, does not.
+//!
+//! [`EngineConfig::vendor_key`]: crate::EngineConfig::vendor_key
+
+use crate::audit::OpType;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+
+pub const REQUEST_MAGIC: &[u8; 4] = b"TCAQ";
+pub const ACTIVATION_MAGIC: &[u8; 4] = b"TCAV";
+pub const ACTIVATION_VERSION: u8 = 1;
+
+fn read_header(r: &mut Reader<'_>, magic: &[u8; 4]) -> CoreResult<()> {
+    if r.take(4)? != magic {
+        return Err(CoreError::Format("bad activation magic"));
+    }
+    if r.take(1)?[0] != ACTIVATION_VERSION {
+        return Err(CoreError::Format("unsupported activation version"));
+    }
+    Ok(())
+}
+
+/// What an air-gapped engine sends its vendor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivationRequest {
+    pub fingerprint: [u8; 32],
+    /// 16 random bytes, then a 16-byte tag over them keyed by the engine.
+    pub nonce: [u8; 32],
+    /// When the request was made, in Unix seconds by the engine's clock.
+    pub timestamp: u64,
+}
+
+impl ActivationRequest {
+    /// `magic(4) | version(1) | fingerprint(32) | nonce(32) | timestamp(8)`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(77);
+        out.extend_from_slice(REQUEST_MAGIC);
+        out.push(ACTIVATION_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        read_header(&mut r, REQUEST_MAGIC)?;
+        let request = ActivationRequest { fingerprint: r.array()?, nonce: r.array()?, timestamp: u64::from_be_bytes(r.array()?) };
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes after activation request"));
+        }
+        Ok(request)
+    }
+
+    /// The vendor's answer: `license`, valid from `not_before` to
+    /// `not_after` (Unix seconds, both inclusive), for the engine that
+    /// made this request.
+    pub fn grant(&self, license: &str, not_before: u64, not_after: u64) -> Activation {
+        Activation { fingerprint: self.fingerprint, nonce: self.nonce, license: license.to_string(), not_before, not_after }
+    }
+}
+
+/// The signed part of a vendor's [`SignedActivation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activation {
+    pub fingerprint: [u8; 32],
+    /// Nonce of the [`ActivationRequest`] this answers.
+    pub nonce: [u8; 32],
+    pub license: String,
+    /// Validity window in Unix seconds, both ends inclusive.
+    pub not_before: u64,
+    pub not_after: u64,
+}
+
+impl Activation {
+    /// `magic(4) | version(1) | fingerprint(32) | nonce(32) | not_before(8) |
+    ///  not_after(8) | license_len(2) | license`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(87 + self.license.len());
+        out.extend_from_slice(ACTIVATION_MAGIC);
+        out.push(ACTIVATION_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.not_before.to_be_bytes());
+        out.extend_from_slice(&self.not_after.to_be_bytes());
+        out.extend_from_slice(&(self.license.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.license.as_bytes());
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        read_header(r, ACTIVATION_MAGIC)?;
+        let (fingerprint, nonce) = (r.array()?, r.array()?);
+        let not_before = u64::from_be_bytes(r.array()?);
+        let not_after = u64::from_be_bytes(r.array()?);
+        let len = u16::from_be_bytes(r.array()?) as usize;
+        let license = String::from_utf8(r.take(len)?.to_vec()).map_err(|_| CoreError::Format("license is not UTF-8"))?;
+        Ok(Activation { fingerprint, nonce, license, not_before, not_after })
+    }
+
+    /// True if `now` (Unix seconds) is within the validity window.
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        (self.not_before..=self.not_after).contains(&now)
+    }
+
+    /// Signs with the vendor's Dilithium5 secret key.
+    pub fn sign(self, vendor_secret_key: &[u8]) -> CoreResult<SignedActivation> {
+        if self.license.len() > u16::MAX as usize {
+            return Err(CoreError::Config("license must be at most 65535 bytes".into()));
+        }
+        let signature = crypto::sign(vendor_secret_key, &self.to_bytes())?;
+        Ok(SignedActivation { activation: self, signature })
+    }
+}
+
+/// An [`Activation`] with the vendor's Dilithium5 signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedActivation {
+    pub activation: Activation,
+    pub signature: Vec<u8>,
+}
+
+impl SignedActivation {
+    /// `body | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.activation.to_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let activation = Activation::read(&mut r)?;
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("activation without signature"));
+        }
+        Ok(SignedActivation { activation, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `vendor_pk`.
+    pub fn verify(&self, vendor_pk: &[u8]) -> bool {
+        crypto::verify_signature(vendor_pk, &self.activation.to_bytes(), &self.signature)
+    }
+}
+
+impl Engine {
+    /// A fresh request for the vendor to answer with an activation.
+    pub fn activation_request(&self) -> CoreResult<ActivationRequest> {
+        self.ensure_open()?;
+        let mut nonce = [0u8; 32];
+        entropy::fill(&mut nonce[..16])?;
+        let tag = self.nonce_tag(nonce[..16].try_into().expect("16 bytes"))?;
+        nonce[16..].copy_from_slice(&tag);
+        Ok(ActivationRequest { fingerprint: self.fingerprint, nonce, timestamp: self.clock().now_ms() / 1000 })
+    }
+
+    // Fails once the engine is closed, which zeroes the identity key.
+    fn nonce_tag(&self, random: &[u8; 16]) -> CoreResult<[u8; 16]> {
+        self.ensure_open()?;
+        let key = blake3::derive_key("titancore activation nonce v1", &self.signing_key.1.unwrapped());
+        let mut hasher = blake3::Hasher::new_keyed(&key);
+        hasher.update(&self.fingerprint);
+        hasher.update(random);
+        Ok(hasher.finalize().as_bytes()[..16].try_into().expect("16 bytes"))
+    }
+
+    /// Checks `response` and installs it as this engine's activation,
+    /// recorded as an `approval` event. Fails with [`CoreError::Config`]
+    /// without [`EngineConfig::vendor_key`] set, and with
+    /// [`CoreError::Certificate`] if the signature does not verify under
+    /// it, the response names another engine or a request this engine did
+    /// not make, or it is not valid now.
+    ///
+    /// [`EngineConfig::vendor_key`]: crate::EngineConfig::vendor_key
+    pub fn install_activation(&mut self, response: &SignedActivation) -> CoreResult<()> {
+        let res = self.check_activation(response);
+        self.audited(OpType::Approval, &response.activation.to_bytes(), res)?;
+        self.activation = Some(response.clone());
+        Ok(())
+    }
+
+    fn check_activation(&self, response: &SignedActivation) -> CoreResult<()> {
+        let vendor_key = self.vendor_key.as_ref().ok_or_else(|| CoreError::Config("no vendor key configured".into()))?;
+        if !response.verify(vendor_key) {
+            return Err(CoreError::Certificate("activation signature does not verify"));
+        }
+        let activation = &response.activation;
+        if activation.fingerprint != self.fingerprint {
+            return Err(CoreError::Certificate("activation is for another engine"));
+        }
+        let (random, tag) = activation.nonce.split_at(16);
+        if self.nonce_tag(random.try_into().expect("16 bytes"))? != tag {
+            return Err(CoreError::Certificate("activation answers a request this engine did not make"));
+        }
+        if !activation.is_valid_at(self.clock().now_ms() / 1000) {
+            return Err(CoreError::Certificate("activation is not valid now"));
+        }
+        Ok(())
+    }
+
+    /// The activation installed with [`Engine::install_activation`].
+    pub fn activation(&self) -> Option<&SignedActivation> {
+        self.activation.as_ref()
+    }
+
+    /// True if an activation is installed and valid by the engine's clock.
+    pub fn is_activated(&self) -> bool {
+        self.activation.as_ref().is_some_and(|a| a.activation.is_valid_at(self.clock().now_ms() / 1000))
+    }
+
+    /// Fails with [`CoreError::Unauthorized`] if the engine has a vendor key
+    /// but no valid activation.
+    pub(crate) fn ensure_activated(&self) -> CoreResult<()> {
+        match self.vendor_key {
+            Some(_) if !self.is_activated() => Err(CoreError::Unauthorized),
+            _ => Ok(()),
+        }
+    }
+}
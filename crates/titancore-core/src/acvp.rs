@@ -0,0 +1,263 @@
+//! Runner for NIST ACVP test vector files.
+//!
+//! [`run`] takes one ACVP vector set (the `internalProjection.json` of the
+//! ACVP-Server `gen-val` files, or a prompt merged with its expected
+//! results) and checks every test case against this crate's primitives:
+//! `ACVP-AES-GCM-SIV` (128- and 256-bit keys), `SHA2-*` (AFT and standard
+//! MCT) and `KDA` / `HKDF` (AFT and VAL, concatenated fixed info).
+//!
+//! Groups the crate cannot run are reported as skipped with the reason,
+//! never as passed: `ML-KEM` and `ML-DSA`, because the engine implements
+//! the round-3 CRYSTALS-Kyber and Dilithium submissions, which FIPS 203 and
+//! 204 changed; `ACVP-AES-GCM`, which the engine does not use; SHA2 large
+//! data and alternate-MCT groups; and bit-oriented messages.
+
+use crate::error::{CoreError, CoreResult};
+use aes_gcm_siv::{Aes128GcmSiv, Aes256GcmSiv, Nonce, aead::{Aead, KeyInit, Payload}};
+use hkdf::Hkdf;
+use serde_json::Value;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512, Sha512_224, Sha512_256};
+
+/// Test groups left unrun: `(tgId, test count, reason)`.
+pub type Skipped = (u64, usize, String);
+
+/// Outcome of one vector set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcvpReport {
+    pub algorithm: String,
+    pub mode: Option<String>,
+    pub revision: String,
+    pub passed: usize,
+    /// `tcId`s whose result differed from the expected one.
+    pub failed: Vec<u64>,
+    pub skipped: Vec<Skipped>,
+}
+
+impl AcvpReport {
+    /// Every test case was run and passed.
+    pub fn all_passed(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+enum Group {
+    Ran(Vec<(u64, bool)>),
+    Skip(String),
+}
+
+/// Runs the vector set in `json`. Fails with [`CoreError::Format`] if it is
+/// not an ACVP vector set or lacks expected results, and with
+/// [`CoreError::Config`] for an algorithm this runner does not know.
+pub fn run(json: &str) -> CoreResult<AcvpReport> {
+    let value: Value = serde_json::from_str(json).map_err(|_| CoreError::Format("bad ACVP JSON"))?;
+    // Files from the ACVP protocol are `[{"acvVersion": ..}, {vector set}]`.
+    let set = match &value {
+        Value::Array(items) => items.iter().find(|v| v.get("testGroups").is_some()),
+        Value::Object(_) => Some(&value),
+        _ => None,
+    }.ok_or(CoreError::Format("no ACVP test groups"))?;
+    let algorithm = str_field(set, "algorithm")?.to_string();
+    let mode = set.get("mode").and_then(Value::as_str).map(str::to_string);
+    let revision = set.get("revision").and_then(Value::as_str).unwrap_or_default().to_string();
+    let groups = set.get("testGroups").and_then(Value::as_array).ok_or(CoreError::Format("no ACVP test groups"))?;
+
+    let mut report = AcvpReport { algorithm, mode, revision, passed: 0, failed: Vec::new(), skipped: Vec::new() };
+    for group in groups {
+        let tg_id = u64_field(group, "tgId")?;
+        let tests = group.get("tests").and_then(Value::as_array).ok_or(CoreError::Format("test group without tests"))?;
+        let outcome = match (report.algorithm.as_str(), report.mode.as_deref()) {
+            ("ACVP-AES-GCM-SIV", _) => aes_gcm_siv_group(group, tests)?,
+            ("ACVP-AES-GCM", _) => Group::Skip("AES-GCM is not implemented; the engine's AES mode is AES-GCM-SIV".into()),
+            (alg, _) if alg.starts_with("SHA2-") => sha2_group(alg, group, tests)?,
+            ("KDA", Some("HKDF")) => hkdf_group(group, tests)?,
+            ("ML-KEM", _) | ("ML-DSA", _) => Group::Skip(
+                "the engine implements round-3 CRYSTALS-Kyber/Dilithium, not FIPS 203/204".into()),
+            (alg, mode) => {
+                let name = mode.map_or_else(|| alg.to_string(), |m| format!("{} / {}", alg, m));
+                return Err(CoreError::Config(format!("unsupported ACVP algorithm: {}", name)));
+            }
+        };
+        match outcome {
+            Group::Ran(results) => for (tc_id, ok) in results {
+                if ok {
+                    report.passed += 1;
+                } else {
+                    report.failed.push(tc_id);
+                }
+            },
+            Group::Skip(reason) => report.skipped.push((tg_id, tests.len(), reason)),
+        }
+    }
+    Ok(report)
+}
+
+fn aes_gcm_siv_group(group: &Value, tests: &[Value]) -> CoreResult<Group> {
+    let encrypt = match str_field(group, "direction")? {
+        "encrypt" => true,
+        "decrypt" => false,
+        _ => return Err(CoreError::Format("bad direction")),
+    };
+    let mut results = Vec::with_capacity(tests.len());
+    for test in tests {
+        let (key, iv, aad) = (hex_field(test, "key")?, hex_field(test, "iv")?, hex_field(test, "aad")?);
+        if iv.len() != 12 {
+            return Ok(Group::Skip(format!("{}-bit IVs are not supported", iv.len() * 8)));
+        }
+        let nonce = Nonce::from_slice(&iv);
+        let mut ct = hex_field(test, "ct")?;
+        // Some files carry the tag separately rather than appended to `ct`.
+        if test.get("tag").is_some() {
+            ct.extend_from_slice(&hex_field(test, "tag")?);
+        }
+        let ok = if encrypt {
+            let pt = hex_field(test, "pt")?;
+            let payload = Payload { msg: &pt, aad: &aad };
+            let out = match key.len() {
+                16 => Aes128GcmSiv::new_from_slice(&key).map_err(|_| CoreError::Format("bad key"))?.encrypt(nonce, payload),
+                32 => Aes256GcmSiv::new_from_slice(&key).map_err(|_| CoreError::Format("bad key"))?.encrypt(nonce, payload),
+                _ => return Ok(Group::Skip(format!("{}-bit keys are not supported", key.len() * 8))),
+            };
+            out.ok() == Some(ct)
+        } else {
+            let payload = Payload { msg: &ct, aad: &aad };
+            let out = match key.len() {
+                16 => Aes128GcmSiv::new_from_slice(&key).map_err(|_| CoreError::Format("bad key"))?.decrypt(nonce, payload),
+                32 => Aes256GcmSiv::new_from_slice(&key).map_err(|_| CoreError::Format("bad key"))?.decrypt(nonce, payload),
+                _ => return Ok(Group::Skip(format!("{}-bit keys are not supported", key.len() * 8))),
+            };
+            match test.get("testPassed").and_then(Value::as_bool) {
+                Some(false) => out.is_err(),
+                _ => out.ok() == Some(hex_field(test, "pt")?),
+            }
+        };
+        results.push((u64_field(test, "tcId")?, ok));
+    }
+    Ok(Group::Ran(results))
+}
+
+fn sha2_group(algorithm: &str, group: &Value, tests: &[Value]) -> CoreResult<Group> {
+    let hash: fn(&[u8]) -> Vec<u8> = match algorithm {
+        "SHA2-224" => |m| Sha224::digest(m).to_vec(),
+        "SHA2-256" => |m| Sha256::digest(m).to_vec(),
+        "SHA2-384" => |m| Sha384::digest(m).to_vec(),
+        "SHA2-512" => |m| Sha512::digest(m).to_vec(),
+        "SHA2-512/224" => |m| Sha512_224::digest(m).to_vec(),
+        "SHA2-512/256" => |m| Sha512_256::digest(m).to_vec(),
+        other => return Err(CoreError::Config(format!("unsupported ACVP algorithm: {}", other))),
+    };
+    let mut results = Vec::with_capacity(tests.len());
+    match str_field(group, "testType")? {
+        "AFT" => for test in tests {
+            if u64_field(test, "len")? % 8 != 0 {
+                return Ok(Group::Skip("bit-oriented messages are not supported".into()));
+            }
+            results.push((u64_field(test, "tcId")?, hash(&hex_field(test, "msg")?) == hex_field(test, "md")?));
+        },
+        "MCT" => {
+            if group.get("mctVersion").and_then(Value::as_str).is_some_and(|v| v != "standard") {
+                return Ok(Group::Skip("only the standard Monte Carlo test is supported".into()));
+            }
+            for test in tests {
+                let expected = test.get("resultsArray").and_then(Value::as_array).ok_or(CoreError::Format("MCT without results"))?;
+                let mut seed = hex_field(test, "msg")?;
+                let mut ok = !expected.is_empty();
+                for result in expected {
+                    let (mut a, mut b, mut c) = (seed.clone(), seed.clone(), seed);
+                    for _ in 0..1000 {
+                        let md = hash(&[&a[..], &b[..], &c[..]].concat());
+                        (a, b, c) = (b, c, md);
+                    }
+                    ok &= c == hex_field(result, "md")?;
+                    seed = c;
+                }
+                results.push((u64_field(test, "tcId")?, ok));
+            }
+        }
+        "LDT" => return Ok(Group::Skip("large data tests are not run".into())),
+        other => return Ok(Group::Skip(format!("test type {} is not supported", other))),
+    }
+    Ok(Group::Ran(results))
+}
+
+fn hkdf_group(group: &Value, tests: &[Value]) -> CoreResult<Group> {
+    let config = group.get("kdfConfiguration").ok_or(CoreError::Format("HKDF group without kdfConfiguration"))?;
+    let encoding = str_field(config, "fixedInfoEncoding")?;
+    if encoding != "concatenation" {
+        return Ok(Group::Skip(format!("fixed info encoding {} is not supported", encoding)));
+    }
+    let hmac = str_field(config, "hmacAlg")?;
+    let pattern = str_field(config, "fixedInfoPattern")?;
+    let validate = match str_field(group, "testType")? {
+        "AFT" => false,
+        "VAL" => true,
+        other => return Ok(Group::Skip(format!("test type {} is not supported", other))),
+    };
+    let mut results = Vec::with_capacity(tests.len());
+    for test in tests {
+        let params = test.get("kdfParameter").ok_or(CoreError::Format("HKDF test without kdfParameter"))?;
+        let l = u64_field(params, "l")?;
+        if l % 8 != 0 {
+            return Ok(Group::Skip("bit-oriented key lengths are not supported".into()));
+        }
+        let Some(info) = fixed_info(pattern, test, params, l)? else {
+            return Ok(Group::Skip(format!("fixed info pattern {} is not supported", pattern)));
+        };
+        let (salt, z) = (hex_field(params, "salt")?, hex_field(params, "z")?);
+        let mut dkm = vec![0u8; (l / 8) as usize];
+        let expanded = match hmac {
+            "SHA2-224" => Hkdf::<Sha224>::new(Some(&salt), &z).expand(&info, &mut dkm),
+            "SHA2-256" => Hkdf::<Sha256>::new(Some(&salt), &z).expand(&info, &mut dkm),
+            "SHA2-384" => Hkdf::<Sha384>::new(Some(&salt), &z).expand(&info, &mut dkm),
+            "SHA2-512" => Hkdf::<Sha512>::new(Some(&salt), &z).expand(&info, &mut dkm),
+            "SHA2-512/224" => Hkdf::<Sha512_224>::new(Some(&salt), &z).expand(&info, &mut dkm),
+            "SHA2-512/256" => Hkdf::<Sha512_256>::new(Some(&salt), &z).expand(&info, &mut dkm),
+            other => return Ok(Group::Skip(format!("HMAC {} is not supported", other))),
+        };
+        let matches = expanded.is_ok() && dkm == hex_field(test, "dkm")?;
+        let ok = if validate {
+            let expected = test.get("testPassed").and_then(Value::as_bool).ok_or(CoreError::Format("VAL test without testPassed"))?;
+            matches == expected
+        } else {
+            matches
+        };
+        results.push((u64_field(test, "tcId")?, ok));
+    }
+    Ok(Group::Ran(results))
+}
+
+// SP 800-56C fixed info from `pattern`, or None if it names a field this
+// runner does not build.
+fn fixed_info(pattern: &str, test: &Value, params: &Value, l: u64) -> CoreResult<Option<Vec<u8>>> {
+    let mut out = Vec::new();
+    for token in pattern.split("||") {
+        match token {
+            "uPartyInfo" | "vPartyInfo" => {
+                let party = if token == "uPartyInfo" { "fixedInfoPartyU" } else { "fixedInfoPartyV" };
+                let info = test.get(party).ok_or(CoreError::Format("missing party info"))?;
+                out.extend_from_slice(&hex_field(info, "partyId")?);
+                if info.get("ephemeralData").is_some() {
+                    out.extend_from_slice(&hex_field(info, "ephemeralData")?);
+                }
+            }
+            "l" => out.extend_from_slice(&(l as u32).to_be_bytes()),
+            "algorithmId" | "label" | "context" | "t" | "salt" | "iv" => out.extend_from_slice(&hex_field(params, token)?),
+            _ => match token.strip_prefix("literal[").and_then(|t| t.strip_suffix(']')) {
+                Some(literal) => out.extend_from_slice(&hex::decode(literal).map_err(|_| CoreError::Format("bad literal"))?),
+                None => return Ok(None),
+            },
+        }
+    }
+    Ok(Some(out))
+}
+
+fn str_field<'a>(v: &'a Value, name: &str) -> CoreResult<&'a str> {
+    v.get(name).and_then(Value::as_str).ok_or(CoreError::Format("missing ACVP field"))
+}
+
+fn u64_field(v: &Value, name: &str) -> CoreResult<u64> {
+    v.get(name).and_then(Value::as_u64).ok_or(CoreError::Format("missing ACVP field"))
+}
+
+fn hex_field(v: &Value, name: &str) -> CoreResult<Vec<u8>> {
+    hex::decode(str_field(v, name)?).map_err(|_| CoreError::Format("bad hex in ACVP field"))
+}
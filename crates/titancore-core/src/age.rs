@@ -0,0 +1,274 @@
+//! Export to the age v1 file format (<https://age-encryption.org/v1>) with a
+//! post-quantum recipient stanza, so engine output can travel through tooling
+//! built around age files.
+//!
+//! The stanza is `-> titancore-kyber1024` with body `kem_ct | wrapped_key`:
+//! the 16-byte file key sealed with ChaCha20-Poly1305 (zero nonce) under
+//! `HKDF-SHA256(salt = kem_ct, ikm = shared secret, info = STANZA_INFO)`.
+//! Header MAC, payload key and STREAM chunking follow the age spec, so only
+//! the stanza needs a plugin on the age side.
+
+use crate::audit::{self, CiphertextBinding, OpType, Outcome};
+use crate::cert;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use crate::rbac::Permission;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine as _;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+pub const AGE_VERSION_LINE: &str = "age-encryption.org/v1";
+pub const STANZA_TYPE: &str = "titancore-kyber1024";
+const STANZA_INFO: &[u8] = b"age-encryption.org/v1/titancore-kyber1024";
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+const ARMOR_END: &str = "-----END AGE ENCRYPTED FILE-----";
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const WRAPPED_KEY_LEN: usize = 16 + TAG_LEN;
+
+impl Engine {
+    /// Encrypts `data` to the Kyber public key as an age file (ASCII-armored
+    /// if `armor`) and records it like [`Engine::seal`]. The audit link binds
+    /// the stanza's KEM ciphertext, the payload nonce and the payload.
+    pub fn seal_age(&self, data: &[u8], pk_bytes: &[u8], armor: bool) -> CoreResult<(Vec<u8>, String)> {
+        let res = self.try_seal_age(data, pk_bytes, armor);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_seal_age(&self, data: &[u8], pk_bytes: &[u8], armor: bool) -> CoreResult<(Vec<u8>, String)> {
+        self.check_rate_limit()?;
+        let pk = self.recipient_key(pk_bytes, data.len() as u64)?;
+        let ctr = self.next_counters(1)?;
+        let mut file_key = Zeroizing::new([0u8; 16]);
+        entropy::fill(file_key.as_mut())?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let wrap_key = stanza_key(shared_secret.as_bytes(), kem_ct.as_bytes())?;
+        let mut body = kem_ct.as_bytes().to_vec();
+        body.extend_from_slice(&crypto::chacha_seal(&wrap_key, &[0u8; 12], file_key.as_ref())?);
+
+        let mut header = format!("{}\n-> {}\n", AGE_VERSION_LINE, STANZA_TYPE);
+        push_wrapped(&mut header, &STANDARD_NO_PAD.encode(&body));
+        header.push_str("---");
+        let mac = header_mac(&file_key, header.as_bytes())?;
+        header.push(' ');
+        header.push_str(&STANDARD_NO_PAD.encode(mac));
+        header.push('\n');
+
+        let mut nonce = [0u8; 16];
+        entropy::fill_hedged(&mut nonce, file_key.as_ref(), data);
+        let payload = self.install(|| seal_payload(&file_key, &nonce, data))?;
+        let mut file = header.into_bytes();
+        file.extend_from_slice(&nonce);
+        file.extend_from_slice(&payload);
+
+        let bound = match self.ct_binding {
+            CiphertextBinding::Full => payload,
+            CiphertextBinding::Digest => self.hashing(payload.len(), || audit::ciphertext_digest(&payload)).to_vec(),
+        };
+        let evidence = self.install(|| self.append_to_audit(ctr, &cert::key_id(pk_bytes), &nonce, &bound, kem_ct.as_bytes()))?;
+        if armor {
+            file = armor_encode(&file).into_bytes();
+        }
+        Ok((file, evidence))
+    }
+
+    /// Decrypts an age file (binary or armored) addressed to this Kyber
+    /// secret key through a `titancore-kyber1024` stanza; other stanzas are
+    /// skipped. Records a `decrypt` event bound to the matching KEM
+    /// ciphertext.
+    pub fn open_age(&self, file: &[u8], sk_bytes: &[u8]) -> CoreResult<Vec<u8>> {
+        let res = self.permit(Permission::Decrypt).and_then(|_| open_age(file, sk_bytes));
+        let (plaintext, kem_ct) = match res {
+            Ok(ok) => ok,
+            Err(e) => return self.audited(OpType::Decrypt, &[], Err(e)),
+        };
+        self.record_event(OpType::Decrypt, Outcome::Success, &kem_ct)?;
+        Ok(plaintext)
+    }
+}
+
+/// Decrypts an age file without an engine; returns the plaintext and the KEM
+/// ciphertext of the stanza that opened it.
+pub fn open_age(file: &[u8], sk_bytes: &[u8]) -> CoreResult<(Vec<u8>, Vec<u8>)> {
+    let dearmored;
+    let file = if file.starts_with(ARMOR_BEGIN.as_bytes()) {
+        dearmored = armor_decode(file)?;
+        &dearmored[..]
+    } else {
+        file
+    };
+    let header = parse_header(file)?;
+    let sk = crypto::parse_secret_key(sk_bytes)?;
+    for body in &header.stanzas {
+        if body.len() != kyber1024::ciphertext_bytes() + WRAPPED_KEY_LEN {
+            continue;
+        }
+        let (kem_bytes, wrapped) = body.split_at(kyber1024::ciphertext_bytes());
+        let kem_ct = kyber1024::Ciphertext::from_bytes(kem_bytes).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        let shared_secret = crypto::decapsulate(&kem_ct, &sk);
+        let wrap_key = stanza_key(shared_secret.as_bytes(), kem_bytes)?;
+        // Kyber's implicit rejection makes a foreign stanza fail here.
+        let Ok(key) = crypto::chacha_open(&wrap_key, &[0u8; 12], wrapped) else { continue };
+        let file_key: Zeroizing<[u8; 16]> = Zeroizing::new(key.try_into().map_err(|_| CoreError::Decryption)?);
+        let expected = header_mac(&file_key, &file[..header.mac_input_len])?;
+        if !crypto::ct_eq(&expected, &header.mac) {
+            return Err(CoreError::Decryption);
+        }
+        let rest = &file[header.len..];
+        if rest.len() < 16 {
+            return Err(CoreError::Format("truncated"));
+        }
+        let (nonce, payload) = rest.split_at(16);
+        return Ok((open_payload(&file_key, nonce, payload)?, kem_bytes.to_vec()));
+    }
+    Err(CoreError::Decryption)
+}
+
+fn stanza_key(shared_secret: &[u8], kem_ct: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(kem_ct), shared_secret).expand(STANZA_INFO, key.as_mut()).map_err(|_| CoreError::Kdf)?;
+    Ok(key)
+}
+
+fn header_mac(file_key: &[u8; 16], header: &[u8]) -> CoreResult<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, file_key).expand(b"header", key.as_mut()).map_err(|_| CoreError::Kdf)?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_ref()).map_err(|_| CoreError::Kdf)?;
+    mac.update(header);
+    Ok(mac.finalize().into_bytes().into())
+}
+
+fn payload_key(file_key: &[u8; 16], nonce: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(nonce), file_key).expand(b"payload", key.as_mut()).map_err(|_| CoreError::Kdf)?;
+    Ok(key)
+}
+
+// STREAM nonce: 11-byte big-endian chunk counter, then 1 on the last chunk.
+fn chunk_nonce(index: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[3..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = u8::from(last);
+    nonce
+}
+
+fn seal_payload(file_key: &[u8; 16], nonce: &[u8], data: &[u8]) -> CoreResult<Vec<u8>> {
+    let key = payload_key(file_key, nonce)?;
+    let mut out = Vec::with_capacity(data.len() + (data.len() / CHUNK_SIZE + 1) * TAG_LEN);
+    let chunks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(CHUNK_SIZE).collect() };
+    let last = chunks.len() - 1;
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        out.extend_from_slice(&crypto::chacha_seal(&key, &chunk_nonce(i as u64, i == last), chunk)?);
+    }
+    Ok(out)
+}
+
+fn open_payload(file_key: &[u8; 16], nonce: &[u8], payload: &[u8]) -> CoreResult<Vec<u8>> {
+    let key = payload_key(file_key, nonce)?;
+    if payload.is_empty() {
+        return Err(CoreError::Format("missing final chunk"));
+    }
+    let chunks: Vec<&[u8]> = payload.chunks(CHUNK_SIZE + TAG_LEN).collect();
+    let last = chunks.len() - 1;
+    let mut out = Vec::with_capacity(payload.len());
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let pt = crypto::chacha_open(&key, &chunk_nonce(i as u64, i == last), chunk)?;
+        // Only an empty file may end in an empty chunk.
+        if pt.is_empty() && i > 0 {
+            return Err(CoreError::Format("empty final chunk"));
+        }
+        out.extend_from_slice(&pt);
+    }
+    Ok(out)
+}
+
+struct Header {
+    stanzas: Vec<Vec<u8>>,
+    mac: [u8; 32],
+    // Bytes up to and including "---", which the MAC covers.
+    mac_input_len: usize,
+    // Bytes up to and including the MAC line's newline.
+    len: usize,
+}
+
+fn parse_header(file: &[u8]) -> CoreResult<Header> {
+    let bad = CoreError::Format("bad age header");
+    let mut pos = 0;
+    let next_line = |pos: &mut usize| -> CoreResult<&str> {
+        let nl = file[*pos..].iter().position(|&b| b == b'\n').ok_or(CoreError::Format("bad age header"))?;
+        let line = std::str::from_utf8(&file[*pos..*pos + nl]).map_err(|_| CoreError::Format("bad age header"))?;
+        *pos += nl + 1;
+        Ok(line)
+    };
+    if next_line(&mut pos)? != AGE_VERSION_LINE {
+        return Err(CoreError::Format("unsupported version"));
+    }
+    let mut stanzas = Vec::new();
+    loop {
+        let start = pos;
+        let line = next_line(&mut pos)?;
+        if let Some(mac) = line.strip_prefix("--- ") {
+            let mac = STANDARD_NO_PAD.decode(mac).map_err(|_| bad.clone())?;
+            return Ok(Header {
+                stanzas,
+                mac: mac.try_into().map_err(|_| bad.clone())?,
+                mac_input_len: start + 3,
+                len: pos,
+            });
+        }
+        let args = line.strip_prefix("-> ").ok_or(bad.clone())?;
+        // Bodies end at the first line shorter than 64 columns.
+        let mut body = String::new();
+        loop {
+            let chunk = next_line(&mut pos)?;
+            body.push_str(chunk);
+            if chunk.len() < 64 {
+                break;
+            }
+        }
+        let body = STANDARD_NO_PAD.decode(&body).map_err(|_| bad.clone())?;
+        if args.split(' ').next() == Some(STANZA_TYPE) {
+            stanzas.push(body);
+        }
+    }
+}
+
+fn push_wrapped(out: &mut String, b64: &str) {
+    let mut rest = b64;
+    loop {
+        let (line, tail) = rest.split_at(rest.len().min(64));
+        out.push_str(line);
+        out.push('\n');
+        if line.len() < 64 {
+            break;
+        }
+        rest = tail;
+    }
+}
+
+fn armor_encode(file: &[u8]) -> String {
+    let mut out = format!("{}\n", ARMOR_BEGIN);
+    let b64 = STANDARD.encode(file);
+    for line in b64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        out.push('\n');
+    }
+    out.push_str(ARMOR_END);
+    out.push('\n');
+    out
+}
+
+fn armor_decode(text: &[u8]) -> CoreResult<Vec<u8>> {
+    let bad = CoreError::Format("bad age armor");
+    let text = std::str::from_utf8(text).map_err(|_| bad.clone())?;
+    let body = text.trim().strip_prefix(ARMOR_BEGIN).and_then(|t| t.strip_suffix(ARMOR_END)).ok_or(bad.clone())?;
+    let b64: String = body.lines().map(str::trim).collect();
+    STANDARD.decode(b64).map_err(|_| bad)
+}
@@ -0,0 +1,184 @@
+use super::Anchor;
+use crate::audit::checkpoint::SignedCheckpoint;
+use crate::error::{CoreError, CoreResult};
+use crate::time;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn storage_err(e: ureq::Error) -> CoreError {
+    CoreError::Storage(format!("anchor: {}", e))
+}
+
+/// POSTs each checkpoint as JSON (see [`SignedCheckpoint::to_json`]) to a
+/// transparency-log-style endpoint. Any 2xx response counts as accepted.
+pub struct HttpAnchor {
+    url: String,
+    bearer: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl HttpAnchor {
+    pub fn new(url: impl Into<String>) -> Self {
+        HttpAnchor { url: url.into(), bearer: None, agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build() }
+    }
+
+    /// Sends `Authorization: Bearer <token>` with every request.
+    pub fn with_bearer(mut self, token: impl Into<String>) -> Self {
+        self.bearer = Some(token.into());
+        self
+    }
+}
+
+impl Anchor for HttpAnchor {
+    fn publish(&self, checkpoint: &SignedCheckpoint) -> CoreResult<()> {
+        let mut req = self.agent.post(&self.url).set("Content-Type", "application/json");
+        if let Some(token) = &self.bearer {
+            req = req.set("Authorization", &format!("Bearer {}", token));
+        }
+        req.send_string(&checkpoint.to_json()).map_err(storage_err)?;
+        Ok(())
+    }
+}
+
+/// Writes each checkpoint to its own S3 object,
+/// `<prefix><fingerprint>/<counter:020>.json`, with `If-None-Match: *` so an
+/// existing checkpoint is never overwritten. Pair it with a bucket under
+/// Object Lock (or a deny-delete policy) for an append-only record.
+///
+/// Requests are path-style and signed with SigV4, so any S3-compatible
+/// endpoint works.
+pub struct S3Anchor {
+    endpoint: String,
+    host: String,
+    region: String,
+    bucket: String,
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl S3Anchor {
+    /// `endpoint` is the service URL, e.g. `https://s3.eu-west-1.amazonaws.com`.
+    pub fn new(endpoint: &str, region: &str, bucket: &str, access_key: &str, secret_key: &str) -> CoreResult<Self> {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint.split_once("://").map(|(_, rest)| rest)
+            .filter(|h| !h.is_empty() && !h.contains('/'))
+            .ok_or_else(|| CoreError::Config(format!("bad S3 endpoint: {}", endpoint)))?
+            .to_string();
+        Ok(S3Anchor {
+            endpoint,
+            host,
+            region: region.into(),
+            bucket: bucket.into(),
+            prefix: String::new(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            session_token: None,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        })
+    }
+
+    /// Key prefix, e.g. `"audit/"`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Session token for temporary credentials.
+    pub fn with_session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    pub fn object_key(&self, checkpoint: &SignedCheckpoint) -> String {
+        let c = &checkpoint.checkpoint;
+        format!("{}{}/{:020}.json", self.prefix, hex::encode(c.fingerprint), c.counter)
+    }
+}
+
+impl Anchor for S3Anchor {
+    fn publish(&self, checkpoint: &SignedCheckpoint) -> CoreResult<()> {
+        let body = checkpoint.to_json();
+        let path = format!("/{}/{}", uri_encode(&self.bucket), uri_encode(&self.object_key(checkpoint)));
+        let payload_hash = hex::encode(Sha256::digest(body.as_bytes()));
+        let (date, amz_date) = amz_dates(time::unix_millis() / 1000);
+
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("if-none-match", "*".to_string()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("PUT\n{}\n\n{}\n{}\n{}", path, canonical_headers, signed_headers, payload_hash);
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes())),
+        );
+        let mut key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature,
+        );
+
+        let mut req = self.agent.put(&format!("{}{}", self.endpoint, path))
+            .set("Authorization", &authorization)
+            .set("Content-Type", "application/json");
+        for (k, v) in headers.iter().filter(|(k, _)| *k != "host") {
+            req = req.set(k, v);
+        }
+        req.send_string(&body).map_err(storage_err)?;
+        Ok(())
+    }
+}
+
+fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(msg);
+    mac.finalize().into_bytes().into()
+}
+
+// SigV4 path encoding: unreserved characters and '/' pass through.
+fn uri_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// `(YYYYMMDD, YYYYMMDDTHHMMSSZ)` in UTC.
+fn amz_dates(unix: u64) -> (String, String) {
+    let (days, rem) = ((unix / 86_400) as i64, unix % 86_400);
+    // Days-to-civil conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let stamp = format!("{}T{:02}{:02}{:02}Z", date, rem / 3600, rem % 3600 / 60, rem % 60);
+    (date, stamp)
+}
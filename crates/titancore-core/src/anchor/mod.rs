@@ -0,0 +1,114 @@
+//! Publishing signed chain heads outside the audit log, so rewriting the log
+//! (even with write access to it) contradicts checkpoints held elsewhere.
+
+use crate::audit::checkpoint::SignedCheckpoint;
+use crate::error::CoreResult;
+use parking_lot::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "anchor-http")]
+mod http;
+#[cfg(feature = "anchor-http")]
+pub use http::{HttpAnchor, S3Anchor};
+
+/// Destination for signed checkpoints.
+pub trait Anchor: Send + Sync {
+    fn publish(&self, checkpoint: &SignedCheckpoint) -> CoreResult<()>;
+}
+
+impl<F> Anchor for F
+where
+    F: Fn(&SignedCheckpoint) -> CoreResult<()> + Send + Sync,
+{
+    fn publish(&self, checkpoint: &SignedCheckpoint) -> CoreResult<()> {
+        self(checkpoint)
+    }
+}
+
+/// Counts from an [`AnchorWorker`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnchorStats {
+    pub published: u64,
+    pub failed: u64,
+    pub last_counter: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Mutex<Option<SignedCheckpoint>>,
+    wake: Condvar,
+    stop: AtomicBool,
+    published: AtomicU64,
+    failed: AtomicU64,
+    last_counter: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Publishes checkpoints from a dedicated thread so slow or unavailable
+/// anchors never stall the crypto path. Only the newest pending checkpoint
+/// is kept: a later head commits to everything before it, so anchoring it
+/// supersedes any it replaces.
+pub struct AnchorWorker {
+    shared: Arc<Shared>,
+}
+
+impl AnchorWorker {
+    pub fn spawn(anchor: Box<dyn Anchor>) -> CoreResult<Self> {
+        let shared = Arc::new(Shared::default());
+        let worker = shared.clone();
+        std::thread::Builder::new()
+            .name("titan-anchor".into())
+            .spawn(move || anchor_loop(anchor, worker))
+            .map_err(|e| crate::error::CoreError::Config(format!("anchor thread: {}", e)))?;
+        Ok(AnchorWorker { shared })
+    }
+
+    /// Queues `checkpoint`, replacing any not yet published.
+    pub fn offer(&self, checkpoint: SignedCheckpoint) {
+        *self.shared.latest.lock() = Some(checkpoint);
+        self.shared.wake.notify_one();
+    }
+
+    pub fn stats(&self) -> AnchorStats {
+        AnchorStats {
+            published: self.shared.published.load(Ordering::Relaxed),
+            failed: self.shared.failed.load(Ordering::Relaxed),
+            last_counter: self.shared.last_counter.load(Ordering::Relaxed),
+            last_error: self.shared.last_error.lock().clone(),
+        }
+    }
+}
+
+fn anchor_loop(anchor: Box<dyn Anchor>, shared: Arc<Shared>) {
+    loop {
+        let next = {
+            let mut latest = shared.latest.lock();
+            while latest.is_none() && !shared.stop.load(Ordering::Acquire) {
+                shared.wake.wait(&mut latest);
+            }
+            latest.take()
+        };
+        let Some(checkpoint) = next else { return };
+        match anchor.publish(&checkpoint) {
+            Ok(()) => {
+                shared.published.fetch_add(1, Ordering::Relaxed);
+                shared.last_counter.store(checkpoint.checkpoint.counter, Ordering::Relaxed);
+            }
+            Err(e) => {
+                shared.failed.fetch_add(1, Ordering::Relaxed);
+                *shared.last_error.lock() = Some(e.to_string());
+            }
+        }
+    }
+}
+
+impl Drop for AnchorWorker {
+    fn drop(&mut self) {
+        // Not joined: the anchor may need locks held by whoever drops us
+        // (e.g. the Python GIL). The worker publishes what is pending and exits.
+        self.shared.stop.store(true, Ordering::Release);
+        self.shared.wake.notify_one();
+    }
+}
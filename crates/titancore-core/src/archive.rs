@@ -0,0 +1,337 @@
+//! Multi-entry archives under one encapsulation.
+//!
+//! An [`ArchiveWriter`] seals a sequence of named entries into a single
+//! archive. One Kyber encapsulation yields the archive key; entry `i` is
+//! sealed in chunks like a stream under its own key, HKDF of the archive
+//! key with info `ENTRY_INFO || i`, and an encrypted index of names,
+//! offsets and sizes follows the last entry. An [`ArchiveReader`] decrypts
+//! only the index and then the entries asked for, seeking past the rest.
+//!
+//! Layout: `magic(4) | version(1) | suite(1) | counter(8) | fingerprint(32) |
+//! kem_len(2) | kem_ct | ext | chunk_size(4)` header, then each entry's
+//! `len(4) | ciphertext` frames (chunk AAD `index || last`, as in streams),
+//! then the index frame and a `index_offset(8) | magic(4)` footer. The
+//! index is sealed with AAD = header, so the header cannot be altered.
+
+use crate::audit::{self, OpType, Outcome};
+use crate::cert;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams};
+use crate::rbac::Permission;
+use crate::stream::{chunk_aad, chunk_index, chunk_nonce, read_array, read_chunk, read_frame, MAX_CHUNK_SIZE, TAG_LEN};
+use crate::suite::Suite;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use std::io::{Read, Seek, SeekFrom, Write};
+use zeroize::Zeroizing;
+
+pub const ARCHIVE_MAGIC: &[u8; 4] = b"TCAR";
+pub const ARCHIVE_VERSION: u8 = 1;
+const ENTRY_INFO: &[u8] = b"titancore archive entry";
+const INDEX_INFO: &[u8] = b"titancore archive index";
+
+/// Name and plaintext size of one archive entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    offset: u64,
+    length: u64,
+}
+
+fn derive_key(archive_key: &[u8; 32], info: &[u8], index: Option<u32>) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut info = info.to_vec();
+    if let Some(i) = index {
+        info.extend_from_slice(&i.to_be_bytes());
+    }
+    let mut key = Zeroizing::new([0u8; 32]);
+    Kdf::HkdfSha256.derive(archive_key, &[], &info, key.as_mut())?;
+    Ok(key)
+}
+
+// `count(4)`, then per entry `name_len(2) | name | offset(8) | length(8) | size(8)`.
+fn encode_index(entries: &[ArchiveEntry]) -> Vec<u8> {
+    let mut out = (entries.len() as u32).to_be_bytes().to_vec();
+    for e in entries {
+        out.extend_from_slice(&(e.name.len() as u16).to_be_bytes());
+        out.extend_from_slice(e.name.as_bytes());
+        out.extend_from_slice(&e.offset.to_be_bytes());
+        out.extend_from_slice(&e.length.to_be_bytes());
+        out.extend_from_slice(&e.size.to_be_bytes());
+    }
+    out
+}
+
+fn decode_index(bytes: &[u8], index_offset: u64) -> CoreResult<Vec<ArchiveEntry>> {
+    let mut r = Reader { buf: bytes };
+    let count = u32::from_be_bytes(r.array()?);
+    let mut entries: Vec<ArchiveEntry> = Vec::new();
+    for _ in 0..count {
+        let len = u16::from_be_bytes(r.array()?) as usize;
+        let name = String::from_utf8(r.take(len)?.to_vec()).map_err(|_| CoreError::Format("bad entry name"))?;
+        let offset = u64::from_be_bytes(r.array()?);
+        let length = u64::from_be_bytes(r.array()?);
+        let size = u64::from_be_bytes(r.array()?);
+        let start = entries.last().map_or(0, |prev| prev.offset + prev.length);
+        if offset < start || offset.checked_add(length).is_none_or(|end| end > index_offset) {
+            return Err(CoreError::Format("bad archive index"));
+        }
+        entries.push(ArchiveEntry { name, size, offset, length });
+    }
+    if !r.buf.is_empty() {
+        return Err(CoreError::Format("trailing bytes"));
+    }
+    Ok(entries)
+}
+
+/// Seals entries into an archive as they are added; nothing is readable
+/// until [`ArchiveWriter::finish`] writes the index. After an error the
+/// archive is unusable.
+pub struct ArchiveWriter<'a, W: Write> {
+    engine: &'a Engine,
+    writer: W,
+    key: Zeroizing<[u8; 32]>,
+    header: Vec<u8>,
+    counter: u64,
+    kem_ct: Vec<u8>,
+    key_id: [u8; 32],
+    chunk_size: usize,
+    offset: u64,
+    entries: Vec<ArchiveEntry>,
+    digest: blake3::Hasher,
+}
+
+impl<W: Write> ArchiveWriter<'_, W> {
+    /// Seals everything from `reader` as entry `name`; returns its size.
+    /// Names must be unique and at most 65535 bytes.
+    pub fn add<R: Read>(&mut self, name: &str, reader: R) -> CoreResult<u64> {
+        let res = self.try_add(name, reader);
+        self.engine.audited(OpType::Encrypt, &self.kem_ct, res)
+    }
+
+    fn try_add<R: Read>(&mut self, name: &str, mut reader: R) -> CoreResult<u64> {
+        if name.len() > u16::MAX as usize {
+            return Err(CoreError::Config("entry names must be at most 65535 bytes".into()));
+        }
+        if self.entries.iter().any(|e| e.name == name) {
+            return Err(CoreError::Config(format!("duplicate archive entry: {}", name)));
+        }
+        let index = u32::try_from(self.entries.len()).map_err(|_| CoreError::Config("too many archive entries".into()))?;
+        let key = derive_key(&self.key, ENTRY_INFO, Some(index))?;
+        let start = self.offset;
+        let mut size = 0u64;
+        let mut next = read_chunk(&mut reader, self.chunk_size)?;
+        let mut chunk = 0u64;
+        loop {
+            let cur = std::mem::replace(&mut next, read_chunk(&mut reader, self.chunk_size)?);
+            let last = next.is_empty();
+            let idx = chunk_index(chunk)?;
+            let ct = crypto::aead_seal_aad(&key, &chunk_nonce(&[0u8; 8], idx), &cur, &chunk_aad(idx, last))?;
+            self.write_frame(&ct)?;
+            size += cur.len() as u64;
+            chunk += 1;
+            if last { break; }
+        }
+        self.entries.push(ArchiveEntry { name: name.to_string(), size, offset: start, length: self.offset - start });
+        Ok(size)
+    }
+
+    fn write_frame(&mut self, ct: &[u8]) -> CoreResult<()> {
+        self.writer.write_all(&(ct.len() as u32).to_be_bytes())?;
+        self.writer.write_all(ct)?;
+        let digest = &mut self.digest;
+        self.engine.hashing(ct.len(), || audit::hash_payload(digest, ct));
+        self.offset += 4 + ct.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the index and footer and records the archive in the audit
+    /// chain, bound to a digest of every entry. Returns the evidence hash.
+    pub fn finish(mut self) -> CoreResult<String> {
+        let res = self.try_finish();
+        self.engine.audited(OpType::Encrypt, &self.kem_ct, res)
+    }
+
+    fn try_finish(&mut self) -> CoreResult<String> {
+        let index_offset = self.offset;
+        let key = derive_key(&self.key, INDEX_INFO, None)?;
+        let ct = crypto::aead_seal_aad(&key, &[0u8; 12], &encode_index(&self.entries), &self.header)?;
+        self.write_frame(&ct)?;
+        self.writer.write_all(&index_offset.to_be_bytes())?;
+        self.writer.write_all(ARCHIVE_MAGIC)?;
+        self.writer.flush()?;
+        let digest: [u8; 32] = self.digest.finalize().into();
+        self.engine.append_to_audit(self.counter, &self.key_id, &[], &digest, &self.kem_ct)
+    }
+}
+
+/// An opened archive: the index is decrypted, entries on demand.
+pub struct ArchiveReader<R> {
+    reader: R,
+    key: Zeroizing<[u8; 32]>,
+    chunk_size: usize,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Decrypts entry `name` into `writer`; returns its size. Output
+    /// written before an authentication failure must be discarded.
+    pub fn extract<W: Write>(&mut self, name: &str, mut writer: W) -> CoreResult<u64> {
+        let index = self.entries.iter().position(|e| e.name == name)
+            .ok_or_else(|| CoreError::Config(format!("no archive entry named {}", name)))?;
+        let entry = &self.entries[index];
+        let key = derive_key(&self.key, ENTRY_INFO, Some(index as u32))?;
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let mut frames = (&mut self.reader).take(entry.length);
+        let max_frame = self.chunk_size + TAG_LEN;
+        let mut next = read_frame(&mut frames, max_frame)?;
+        let mut chunk = 0u64;
+        let mut total = 0u64;
+        while let Some(ct) = next.take() {
+            next = read_frame(&mut frames, max_frame)?;
+            let idx = chunk_index(chunk)?;
+            let pt = crypto::aead_open_aad(&key, &chunk_nonce(&[0u8; 8], idx), &ct, &chunk_aad(idx, next.is_none()))?;
+            writer.write_all(&pt)?;
+            total += pt.len() as u64;
+            chunk += 1;
+        }
+        if chunk == 0 || total != entry.size {
+            return Err(CoreError::Format("truncated archive entry"));
+        }
+        writer.flush()?;
+        Ok(total)
+    }
+}
+
+impl Engine {
+    /// Starts an archive to `pk_bytes` on `writer`. A non-empty `context` is
+    /// mixed into the archive key as in [`Engine::seal_with_context`]. The
+    /// archive counts as one request against the rate limit.
+    pub fn archive_writer<W: Write>(&self, writer: W, pk_bytes: &[u8], chunk_size: usize, context: &[u8]) -> CoreResult<ArchiveWriter<'_, W>> {
+        let res = self.try_archive_writer(writer, pk_bytes, chunk_size, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_archive_writer<W: Write>(&self, mut writer: W, pk_bytes: &[u8], chunk_size: usize, context: &[u8]) -> CoreResult<ArchiveWriter<'_, W>> {
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(CoreError::Config(format!("chunk size must be 1..={}", MAX_CHUNK_SIZE)));
+        }
+        self.check_rate_limit()?;
+        let pk = self.recipient_key(pk_bytes, 0)?;
+        let counter = self.next_counters(1)?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let kdf = self.kdf.for_message(shared_secret.as_bytes(), kem_ct.as_bytes());
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, counter, &kdf, context)?;
+
+        let mut header = Vec::with_capacity(52 + kem_ct.as_bytes().len());
+        header.extend_from_slice(ARCHIVE_MAGIC);
+        header.push(ARCHIVE_VERSION);
+        header.push(Suite::GcmSivCounter.wire_id(kdf.algorithm));
+        header.extend_from_slice(&counter.to_be_bytes());
+        header.extend_from_slice(&self.fingerprint);
+        header.extend_from_slice(&(kem_ct.as_bytes().len() as u16).to_be_bytes());
+        header.extend_from_slice(kem_ct.as_bytes());
+        header.extend_from_slice(&kdf.encode_ext());
+        header.extend_from_slice(&(chunk_size as u32).to_be_bytes());
+        writer.write_all(&header)?;
+
+        Ok(ArchiveWriter {
+            engine: self,
+            writer,
+            key,
+            offset: header.len() as u64,
+            header,
+            counter,
+            kem_ct: kem_ct.as_bytes().to_vec(),
+            key_id: cert::key_id(pk_bytes),
+            chunk_size,
+            entries: Vec::new(),
+            digest: blake3::Hasher::new(),
+        })
+    }
+
+    /// Opens an archive from [`Engine::archive_writer`], decrypting only its
+    /// index. Records a `decrypt` event bound to the archive's KEM
+    /// ciphertext.
+    pub fn open_archive<R: Read + Seek>(&self, mut reader: R, sk_bytes: &[u8], context: &[u8]) -> CoreResult<ArchiveReader<R>> {
+        let header = match self.permit(Permission::Decrypt).and_then(|_| ArchiveHeader::read_from(&mut reader)) {
+            Ok(header) => header,
+            Err(e) => return self.audited(OpType::Decrypt, &[], Err(e)),
+        };
+        let res = header.open_index(&mut reader, sk_bytes, context);
+        let (key, entries) = self.audited(OpType::Decrypt, &header.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &header.kem_ct)?;
+        Ok(ArchiveReader { reader, key, chunk_size: header.chunk_size, entries })
+    }
+}
+
+struct ArchiveHeader {
+    /// As read, for the index AAD.
+    bytes: Vec<u8>,
+    fingerprint: [u8; 32],
+    counter: u64,
+    kem_ct: Vec<u8>,
+    kdf: KdfParams,
+    chunk_size: usize,
+}
+
+impl ArchiveHeader {
+    fn read_from<R: Read>(r: &mut R) -> CoreResult<Self> {
+        let mut bytes = Vec::new();
+        let mut take = |n: usize| -> CoreResult<Vec<u8>> {
+            let mut buf = vec![0u8; n];
+            r.read_exact(&mut buf).map_err(|_| CoreError::Format("truncated"))?;
+            bytes.extend_from_slice(&buf);
+            Ok(buf)
+        };
+        if take(4)? != ARCHIVE_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if take(1)?[0] != ARCHIVE_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let algorithm = match Suite::from_wire_id(take(1)?[0])? {
+            (Suite::GcmSivCounter, kdf) => kdf,
+            _ => return Err(CoreError::Format("unsupported archive suite")),
+        };
+        let mut fixed = take(42)?;
+        let counter = u64::from_be_bytes(fixed[..8].try_into().expect("8 bytes"));
+        let fingerprint = fixed[8..40].try_into().expect("32 bytes");
+        let kem_len = u16::from_be_bytes([fixed[40], fixed[41]]) as usize;
+        crypto::check_kem_len(kem_len)?;
+        let kem_ct = take(kem_len)?;
+        fixed = take(2)?;
+        let kdf = KdfParams::decode_ext(algorithm, &take(u16::from_be_bytes([fixed[0], fixed[1]]) as usize)?)?;
+        let chunk_size = u32::from_be_bytes(take(4)?.try_into().expect("4 bytes")) as usize;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(CoreError::Format("bad chunk size"));
+        }
+        Ok(ArchiveHeader { bytes, fingerprint, counter, kem_ct, kdf, chunk_size })
+    }
+
+    fn open_index<R: Read + Seek>(&self, reader: &mut R, sk_bytes: &[u8], context: &[u8]) -> CoreResult<(Zeroizing<[u8; 32]>, Vec<ArchiveEntry>)> {
+        let sk = crypto::parse_secret_key(sk_bytes)?;
+        let ct = kyber1024::Ciphertext::from_bytes(&self.kem_ct).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        let shared_secret = crypto::decapsulate(&ct, &sk);
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, self.counter, &self.kdf, context)?;
+
+        let end = reader.seek(SeekFrom::End(-12)).map_err(|_| CoreError::Format("truncated"))?;
+        let index_offset = u64::from_be_bytes(read_array(reader)?);
+        if read_array::<4, _>(reader)? != *ARCHIVE_MAGIC || index_offset < self.bytes.len() as u64 || index_offset > end {
+            return Err(CoreError::Format("bad archive footer"));
+        }
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut frame = reader.take(end - index_offset);
+        let ct = read_frame(&mut frame, u32::MAX as usize)?.ok_or(CoreError::Format("missing archive index"))?;
+        let index_key = derive_key(&key, INDEX_INFO, None)?;
+        let index = crypto::aead_open_aad(&index_key, &[0u8; 12], &ct, &self.bytes)?;
+        Ok((key, decode_index(&index, index_offset)?))
+    }
+}
@@ -0,0 +1,159 @@
+//! PEM-like ASCII armor for envelopes, keys, checkpoints and evidence
+//! bundles, so they survive email, tickets and copy-paste:
+//!
+//! ```text
+//! -----BEGIN TITAN ENVELOPE-----
+//! <base64, 64 columns>
+//! =<base64 of the CRC-24>
+//! -----END TITAN ENVELOPE-----
+//! ```
+//!
+//! The checksum line is OpenPGP's (RFC 4880 §6.1). It only catches damage in
+//! transit; authenticity still comes from the AEAD tag or signature inside.
+
+use crate::error::{CoreError, CoreResult};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+
+const LINE_LEN: usize = 64;
+const CRC24_INIT: u32 = 0xb7_04ce;
+const CRC24_POLY: u32 = 0x186_4cfb;
+
+/// What an armored block holds; names the `BEGIN`/`END` lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmorKind {
+    /// An envelope, native or `COSE_Encrypt`.
+    Envelope,
+    PublicKey,
+    SecretKey,
+    SigningPublicKey,
+    SigningSecretKey,
+    Checkpoint,
+    Evidence,
+    Certificate,
+    /// One sheet of a [`crate::recovery_kit::RecoveryKit`].
+    RecoveryKit,
+    /// An [`crate::activation::ActivationRequest`].
+    ActivationRequest,
+    /// A vendor's [`crate::activation::SignedActivation`].
+    Activation,
+}
+
+impl ArmorKind {
+    const ALL: [ArmorKind; 11] = [
+        ArmorKind::Envelope, ArmorKind::PublicKey, ArmorKind::SecretKey, ArmorKind::SigningPublicKey,
+        ArmorKind::SigningSecretKey, ArmorKind::Checkpoint, ArmorKind::Evidence, ArmorKind::Certificate,
+        ArmorKind::RecoveryKit, ArmorKind::ActivationRequest, ArmorKind::Activation,
+    ];
+
+    /// Label after `TITAN ` in the armor lines.
+    pub fn label(self) -> &'static str {
+        match self {
+            ArmorKind::Envelope => "ENVELOPE",
+            ArmorKind::PublicKey => "PUBLIC KEY",
+            ArmorKind::SecretKey => "SECRET KEY",
+            ArmorKind::SigningPublicKey => "SIGNING PUBLIC KEY",
+            ArmorKind::SigningSecretKey => "SIGNING SECRET KEY",
+            ArmorKind::Checkpoint => "CHECKPOINT",
+            ArmorKind::Evidence => "EVIDENCE",
+            ArmorKind::Certificate => "CERTIFICATE",
+            ArmorKind::RecoveryKit => "RECOVERY KIT",
+            ArmorKind::ActivationRequest => "ACTIVATION REQUEST",
+            ArmorKind::Activation => "ACTIVATION",
+        }
+    }
+
+    /// Lower-case, dash-separated name, e.g. `"signing-public-key"`.
+    pub fn name(self) -> String {
+        self.label().to_ascii_lowercase().replace(' ', "-")
+    }
+
+    pub fn parse(name: &str) -> Option<ArmorKind> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    fn from_label(label: &str) -> Option<ArmorKind> {
+        Self::ALL.into_iter().find(|k| k.label() == label)
+    }
+}
+
+/// OpenPGP CRC-24.
+pub fn crc24(data: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xff_ffff
+}
+
+pub fn armor(kind: ArmorKind, bytes: &[u8]) -> String {
+    let mut out = format!("-----BEGIN TITAN {}-----\n", kind.label());
+    let b64 = STANDARD.encode(bytes);
+    for line in b64.as_bytes().chunks(LINE_LEN) {
+        out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        out.push('\n');
+    }
+    out.push('=');
+    out.push_str(&STANDARD.encode(&crc24(bytes).to_be_bytes()[1..]));
+    out.push_str(&format!("\n-----END TITAN {}-----\n", kind.label()));
+    out
+}
+
+/// True if `bytes` contain a `BEGIN TITAN` line.
+pub fn is_armored(bytes: &[u8]) -> bool {
+    bytes.windows(17).any(|w| w == b"-----BEGIN TITAN ")
+}
+
+/// Decodes the first armored block in `text`; text around it (an email
+/// body, say) is ignored. The checksum line may be missing, but if present
+/// it must match.
+pub fn dearmor(text: &[u8]) -> CoreResult<(ArmorKind, Vec<u8>)> {
+    let bad = CoreError::Format("bad armor");
+    let text = std::str::from_utf8(text).map_err(|_| bad.clone())?;
+    let mut lines = text.lines().map(str::trim).skip_while(|l| !l.starts_with("-----BEGIN TITAN "));
+    let label = lines.next()
+        .and_then(|l| l.strip_prefix("-----BEGIN TITAN "))
+        .and_then(|l| l.strip_suffix("-----"))
+        .ok_or(bad.clone())?;
+    let kind = ArmorKind::from_label(label).ok_or(CoreError::Format("unknown armor label"))?;
+    let end = format!("-----END TITAN {}-----", label);
+    let (mut b64, mut checksum) = (String::new(), None);
+    for line in lines.by_ref() {
+        if line == end {
+            let bytes = STANDARD.decode(&b64).map_err(|_| bad.clone())?;
+            if let Some(checksum) = checksum {
+                if checksum != crc24(&bytes) {
+                    return Err(CoreError::Format("armor checksum mismatch"));
+                }
+            }
+            return Ok((kind, bytes));
+        }
+        if checksum.is_some() {
+            return Err(bad);
+        }
+        // A body line can also start with '=' when only padding wrapped onto
+        // it, but never five characters long.
+        match line.strip_prefix('=').filter(|_| line.len() == 5) {
+            Some(crc) => {
+                let crc = STANDARD.decode(crc).ok().filter(|c| c.len() == 3).ok_or(bad.clone())?;
+                checksum = Some(u32::from_be_bytes([0, crc[0], crc[1], crc[2]]));
+            }
+            None => b64.push_str(line),
+        }
+    }
+    Err(CoreError::Format("unterminated armor"))
+}
+
+/// [`dearmor`], requiring a block of `kind`.
+pub fn dearmor_as(kind: ArmorKind, text: &[u8]) -> CoreResult<Vec<u8>> {
+    match dearmor(text)? {
+        (found, bytes) if found == kind => Ok(bytes),
+        _ => Err(CoreError::Format("unexpected armor label")),
+    }
+}
@@ -0,0 +1,255 @@
+//! Platform attestation: binding the engine to measured boot state.
+//!
+//! A [`TpmQuote`] is a TPM 2.0 quote over selected PCRs, taken by the host
+//! (e.g. `tpm2_quote -m quote.msg -s quote.sig`) with its attestation key.
+//! Given as [`crate::EngineConfig::tpm_quote`], its PCR digest is mixed
+//! into the hardware fingerprint (see [`Engine::fingerprint_with_pcrs`]),
+//! so a changed boot chain yields a different engine identity and a
+//! snapshot taken before no longer restores.
+//!
+//! [`Engine::attest`] signs an [`Attestation`] with the checkpoint key:
+//! fingerprint, license, chain head and counter, a verifier's nonce, and
+//! the quote. The quote's own signature is by the TPM's attestation key,
+//! which this crate does not verify; check it with the TPM vendor's tools
+//! (e.g. `tpm2_checkquote`) against the AK you enrolled.
+//!
+//! Layout: `magic(4) | version(1) | fingerprint(32) | counter(8) | head(32) |
+//! timestamp(8) | license_len(2) | license | nonce_len(2) | nonce |
+//! has_quote(1) [| attest_len(4) | attest | sig_len(4) | signature]`, then, as
+//! with checkpoints, `pk_len(2) | public_key | signature`.
+
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::rbac::Permission;
+
+pub const ATTESTATION_MAGIC: &[u8; 4] = b"TCAT";
+pub const ATTESTATION_VERSION: u8 = 1;
+/// Longest verifier nonce accepted, as for a TPM's `qualifyingData`.
+pub const MAX_NONCE_LEN: usize = 64;
+
+const TPM_GENERATED_VALUE: u32 = 0xff54_4347;
+const TPM_ST_ATTEST_QUOTE: u16 = 0x8018;
+
+/// A TPM 2.0 quote: the marshalled `TPMS_ATTEST` and the attestation key's
+/// signature over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TpmQuote {
+    pub attest: Vec<u8>,
+    pub signature: Vec<u8>,
+    extra_data: Vec<u8>,
+    pcr_selection: Vec<(u16, Vec<u8>)>,
+    pcr_digest: Vec<u8>,
+}
+
+impl TpmQuote {
+    /// Parses `attest`; fails with [`CoreError::Format`] unless it is a
+    /// quote.
+    pub fn new(attest: Vec<u8>, signature: Vec<u8>) -> CoreResult<Self> {
+        let mut r = Reader { buf: &attest };
+        if u32::from_be_bytes(r.array()?) != TPM_GENERATED_VALUE {
+            return Err(CoreError::Format("not a TPM attestation"));
+        }
+        if u16::from_be_bytes(r.array()?) != TPM_ST_ATTEST_QUOTE {
+            return Err(CoreError::Format("TPM attestation is not a quote"));
+        }
+        let signer_len = u16::from_be_bytes(r.array()?) as usize;
+        r.take(signer_len)?;
+        let extra_len = u16::from_be_bytes(r.array()?) as usize;
+        let extra_data = r.take(extra_len)?.to_vec();
+        // clockInfo (clock, resetCount, restartCount, safe), firmwareVersion
+        r.take(17 + 8)?;
+        let count = u32::from_be_bytes(r.array()?);
+        let mut pcr_selection = Vec::new();
+        for _ in 0..count {
+            let hash = u16::from_be_bytes(r.array()?);
+            let size = r.take(1)?[0] as usize;
+            pcr_selection.push((hash, r.take(size)?.to_vec()));
+        }
+        let digest_len = u16::from_be_bytes(r.array()?) as usize;
+        let pcr_digest = r.take(digest_len)?.to_vec();
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        Ok(TpmQuote { attest, signature, extra_data, pcr_selection, pcr_digest })
+    }
+
+    /// Digest of the quoted PCR values.
+    pub fn pcr_digest(&self) -> &[u8] {
+        &self.pcr_digest
+    }
+
+    /// The qualifying data (nonce) the quote was taken with.
+    pub fn extra_data(&self) -> &[u8] {
+        &self.extra_data
+    }
+
+    /// Quoted PCR indices per TPM hash algorithm id (`0x000b` is SHA-256).
+    pub fn pcrs(&self) -> Vec<(u16, Vec<u32>)> {
+        self.pcr_selection.iter().map(|(hash, bitmap)| {
+            let pcrs = (0..bitmap.len() as u32 * 8).filter(|&i| bitmap[i as usize / 8] & (1 << (i % 8)) != 0).collect();
+            (*hash, pcrs)
+        }).collect()
+    }
+}
+
+/// "Engine `fingerprint`, licensed as `license`, had chain head `head`
+/// after entry `counter`, on a platform in the state `quote` shows."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub fingerprint: [u8; 32],
+    pub counter: u64,
+    pub head: [u8; 32],
+    pub timestamp: u64,
+    pub license: String,
+    pub nonce: Vec<u8>,
+    pub quote: Option<TpmQuote>,
+}
+
+impl Attestation {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(90 + self.license.len() + self.nonce.len());
+        out.extend_from_slice(ATTESTATION_MAGIC);
+        out.push(ATTESTATION_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.head);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.extend_from_slice(&(self.license.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.license.as_bytes());
+        out.extend_from_slice(&(self.nonce.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.nonce);
+        match &self.quote {
+            None => out.push(0),
+            Some(quote) => {
+                out.push(1);
+                out.extend_from_slice(&(quote.attest.len() as u32).to_be_bytes());
+                out.extend_from_slice(&quote.attest);
+                out.extend_from_slice(&(quote.signature.len() as u32).to_be_bytes());
+                out.extend_from_slice(&quote.signature);
+            }
+        }
+        out
+    }
+
+    // Parses the body at the start of `r`, leaving the rest.
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != ATTESTATION_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != ATTESTATION_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let fingerprint = r.array()?;
+        let counter = u64::from_be_bytes(r.array()?);
+        let head = r.array()?;
+        let timestamp = u64::from_be_bytes(r.array()?);
+        let license_len = u16::from_be_bytes(r.array()?) as usize;
+        let license = String::from_utf8(r.take(license_len)?.to_vec()).map_err(|_| CoreError::Format("license is not UTF-8"))?;
+        let nonce_len = u16::from_be_bytes(r.array()?) as usize;
+        let nonce = r.take(nonce_len)?.to_vec();
+        let quote = match r.take(1)?[0] {
+            0 => None,
+            1 => {
+                let attest_len = u32::from_be_bytes(r.array()?) as usize;
+                let attest = r.take(attest_len)?.to_vec();
+                let sig_len = u32::from_be_bytes(r.array()?) as usize;
+                Some(TpmQuote::new(attest, r.take(sig_len)?.to_vec())?)
+            }
+            _ => return Err(CoreError::Format("bad quote flag")),
+        };
+        Ok(Attestation { fingerprint, counter, head, timestamp, license, nonce, quote })
+    }
+}
+
+/// An [`Attestation`] signed with the engine's checkpoint key. The embedded
+/// key is informational: verify against a key you trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedAttestation {
+    pub attestation: Attestation,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedAttestation {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.attestation.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let attestation = Attestation::read(&mut r)?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("attestation without signature"));
+        }
+        Ok(SignedAttestation { attestation, public_key, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk` and the
+    /// attestation answers `nonce`. The TPM quote still needs checking
+    /// against the attestation key.
+    pub fn verify(&self, trusted_pk: &[u8], nonce: &[u8]) -> bool {
+        self.attestation.nonce == nonce
+            && crypto::verify_signature(trusted_pk, &self.attestation.to_bytes(), &self.signature)
+    }
+}
+
+impl Engine {
+    /// Hardware fingerprint from `hw_info` and `seed` bound to a PCR
+    /// digest, as an engine built with [`crate::EngineConfig::tpm_quote`]
+    /// has.
+    pub fn fingerprint_with_pcrs(hw_info: &str, seed: &str, pcr_digest: &[u8]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(hw_info.as_bytes());
+        hasher.update(seed.as_bytes());
+        hasher.update(b"tpm-pcrs");
+        hasher.update(pcr_digest);
+        hasher.finalize().into()
+    }
+
+    /// The quote given in [`crate::EngineConfig::tpm_quote`], if any.
+    pub fn tpm_quote(&self) -> Option<&TpmQuote> {
+        self.tpm_quote.as_ref()
+    }
+
+    /// Signs an attestation of this engine's identity, `license` and chain
+    /// head, answering the verifier's `nonce`, with the checkpoint key.
+    /// `quote` replaces the configured quote, e.g. a fresh one taken over
+    /// the same nonce; its PCR digest must match the configured one.
+    pub fn attest(&self, license: &str, nonce: &[u8], quote: Option<TpmQuote>) -> CoreResult<SignedAttestation> {
+        self.ensure_open()?;
+        self.permit(Permission::Sign)?;
+        if nonce.len() > MAX_NONCE_LEN {
+            return Err(CoreError::Config(format!("nonce longer than {} bytes", MAX_NONCE_LEN)));
+        }
+        if license.len() > u16::MAX as usize {
+            return Err(CoreError::Config("license too long".into()));
+        }
+        let quote = match (quote, &self.tpm_quote) {
+            (Some(quote), Some(configured)) if quote.pcr_digest != configured.pcr_digest => {
+                return Err(CoreError::Config("quote PCR digest differs from the engine's".into()));
+            }
+            (Some(_), None) => return Err(CoreError::Config("engine has no TPM quote configured".into())),
+            (quote, configured) => quote.or_else(|| configured.clone()),
+        };
+        let cp = self.head_checkpoint();
+        let attestation = Attestation {
+            fingerprint: self.fingerprint,
+            counter: cp.counter,
+            head: cp.head,
+            timestamp: cp.timestamp,
+            license: license.to_string(),
+            nonce: nonce.to_vec(),
+            quote,
+        };
+        let signature = crypto::sign(&self.signing_key.1.unwrapped(), &attestation.to_bytes())?;
+        Ok(SignedAttestation { attestation, public_key: self.signing_key.0.clone(), signature })
+    }
+}
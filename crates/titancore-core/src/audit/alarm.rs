@@ -0,0 +1,157 @@
+//! Signed records of the audit log diverging from the engine's own view of
+//! its chain.
+//!
+//! [`crate::Engine::check_chain`] (run periodically by the watchdog in
+//! [`crate::watchdog`]) reads the tail back from the sink and compares it
+//! with the head the engine holds in memory. When they disagree the engine
+//! signs an [`Alarm`] with its checkpoint key and hands it to
+//! [`AuditSink::append_alarm`](super::AuditSink::append_alarm), outside the
+//! chain it is reporting on.
+//!
+//! Layout: `magic(4) | version(1) | fingerprint(32) | timestamp_ms(8) |
+//! reason(1) | expected_head(32) | expected_seq(8) | found_head(32) |
+//! found_seq(8)`, then, as with checkpoints, `pk_len(2) | public_key |
+//! signature`.
+
+use crate::crypto;
+use crate::error::{CoreError, CoreResult};
+
+pub const ALARM_MAGIC: &[u8; 4] = b"TCAM";
+pub const ALARM_VERSION: u8 = 1;
+const BODY_LEN: usize = 4 + 1 + 32 + 8 + 1 + 32 + 8 + 32 + 8;
+
+/// What the check found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmReason {
+    /// The last stored entry is not the head the engine holds.
+    HeadMismatch,
+    /// Stored entries in the tail do not link to each other.
+    TailBroken,
+    /// The sink holds no entries although the engine has written some.
+    LogMissing,
+    /// The tail could not be read back or parsed.
+    Unreadable,
+}
+
+impl AlarmReason {
+    pub fn code(self) -> u8 {
+        match self {
+            AlarmReason::HeadMismatch => 1,
+            AlarmReason::TailBroken => 2,
+            AlarmReason::LogMissing => 3,
+            AlarmReason::Unreadable => 4,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(AlarmReason::HeadMismatch),
+            2 => Some(AlarmReason::TailBroken),
+            3 => Some(AlarmReason::LogMissing),
+            4 => Some(AlarmReason::Unreadable),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlarmReason::HeadMismatch => "head-mismatch",
+            AlarmReason::TailBroken => "tail-broken",
+            AlarmReason::LogMissing => "log-missing",
+            AlarmReason::Unreadable => "unreadable",
+        }
+    }
+}
+
+/// "At `timestamp_ms` engine `fingerprint` held head `expected_head` after
+/// entry `expected_seq`, but its log showed `found_head` after
+/// `found_seq`." The found fields are zero when nothing could be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    pub fingerprint: [u8; 32],
+    pub timestamp_ms: u64,
+    pub reason: AlarmReason,
+    pub expected_head: [u8; 32],
+    pub expected_seq: u64,
+    pub found_head: [u8; 32],
+    pub found_seq: u64,
+}
+
+impl Alarm {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(BODY_LEN);
+        out.extend_from_slice(ALARM_MAGIC);
+        out.push(ALARM_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        out.push(self.reason.code());
+        out.extend_from_slice(&self.expected_head);
+        out.extend_from_slice(&self.expected_seq.to_be_bytes());
+        out.extend_from_slice(&self.found_head);
+        out.extend_from_slice(&self.found_seq.to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        if bytes.len() != BODY_LEN || &bytes[..4] != ALARM_MAGIC {
+            return Err(CoreError::Format("bad alarm"));
+        }
+        if bytes[4] != ALARM_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let arr32 = |i: usize| -> [u8; 32] { bytes[i..i + 32].try_into().expect("32 bytes") };
+        let u64_at = |i: usize| u64::from_be_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        Ok(Alarm {
+            fingerprint: arr32(5),
+            timestamp_ms: u64_at(37),
+            reason: AlarmReason::from_code(bytes[45]).ok_or(CoreError::Format("unknown alarm reason"))?,
+            expected_head: arr32(46),
+            expected_seq: u64_at(78),
+            found_head: arr32(86),
+            found_seq: u64_at(118),
+        })
+    }
+
+    pub fn sign(self, public_key: &[u8], secret_key: &[u8]) -> CoreResult<SignedAlarm> {
+        let signature = crypto::sign(secret_key, &self.to_bytes())?;
+        Ok(SignedAlarm { alarm: self, public_key: public_key.to_vec(), signature })
+    }
+}
+
+/// An [`Alarm`] with a Dilithium5 signature and the signer's public key.
+/// The embedded key is informational: verify against a key you trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedAlarm {
+    pub alarm: Alarm,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedAlarm {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.alarm.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        if bytes.len() < BODY_LEN + 2 {
+            return Err(CoreError::Format("truncated alarm"));
+        }
+        let alarm = Alarm::from_bytes(&bytes[..BODY_LEN])?;
+        let pk_len = u16::from_be_bytes([bytes[BODY_LEN], bytes[BODY_LEN + 1]]) as usize;
+        let rest = &bytes[BODY_LEN + 2..];
+        if rest.len() <= pk_len {
+            return Err(CoreError::Format("truncated alarm"));
+        }
+        Ok(SignedAlarm { alarm, public_key: rest[..pk_len].to_vec(), signature: rest[pk_len..].to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.alarm.to_bytes(), &self.signature)
+    }
+}
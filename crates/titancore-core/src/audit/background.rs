@@ -0,0 +1,227 @@
+use super::{AuditEntry, AuditQuery, AuditRecord, AuditSink, BatchRoot, Recovery, SignedAlarm, SignedGenesis, SignedSnapshot};
+use crate::error::{CoreError, CoreResult};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+enum Msg {
+    Entry(AuditEntry),
+    Root(BatchRoot),
+    Genesis(SignedGenesis),
+    Snapshot(SignedSnapshot),
+    Snapshots(SyncSender<CoreResult<Vec<SignedSnapshot>>>),
+    Alarm(SignedAlarm),
+    Tail(usize, SyncSender<CoreResult<Vec<AuditEntry>>>),
+    Flush(SyncSender<CoreResult<()>>),
+    Query(AuditQuery, SyncSender<CoreResult<Vec<AuditRecord>>>),
+}
+
+/// Snapshot of a [`BackgroundSink`] queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    pub depth: usize,
+    pub capacity: usize,
+    pub high_water: usize,
+    pub written: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    depth: AtomicUsize,
+    high_water: AtomicUsize,
+    written: AtomicU64,
+    // First write error from the writer thread; reported on the next call.
+    failure: Mutex<Option<CoreError>>,
+}
+
+/// Hands entries to a dedicated writer thread so the crypto path never waits
+/// on disk. The queue is bounded: when it is full, `append` blocks until the
+/// writer catches up, so evidence is never dropped. `flush` waits until every
+/// queued entry has been written and the inner sink flushed.
+///
+/// An entry returned from `append` is queued, not persisted: a write error is
+/// raised by the next `append`/`flush`, and entries still queued at a crash
+/// are lost. Call `flush` where evidence must be durable before returning.
+pub struct BackgroundSink {
+    tx: Option<SyncSender<Msg>>,
+    capacity: usize,
+    recovery: Option<Recovery>,
+    genesis: Mutex<Option<SignedGenesis>>,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl BackgroundSink {
+    /// Runs the inner sink's startup recovery and reads its genesis record,
+    /// then moves it to the writer thread.
+    pub fn new(inner: Box<dyn AuditSink>, capacity: usize) -> CoreResult<Self> {
+        let recovery = inner.resume()?;
+        let genesis = Mutex::new(inner.genesis()?);
+        let capacity = capacity.max(1);
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let shared = Arc::new(Shared::default());
+        let worker_shared = shared.clone();
+        let worker = std::thread::Builder::new()
+            .name("titan-audit-writer".into())
+            .spawn(move || writer_loop(inner, rx, worker_shared))
+            .map_err(|e| CoreError::Config(format!("audit writer thread: {}", e)))?;
+        Ok(BackgroundSink { tx: Some(tx), capacity, recovery, genesis, shared, worker: Some(worker) })
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            depth: self.shared.depth.load(Ordering::Relaxed),
+            capacity: self.capacity,
+            high_water: self.shared.high_water.load(Ordering::Relaxed),
+            written: self.shared.written.load(Ordering::Relaxed),
+        }
+    }
+
+    fn send(&self, msg: Msg) -> CoreResult<()> {
+        self.tx.as_ref().expect("sender lives until drop").send(msg)
+            .map_err(|_| CoreError::Storage("audit writer stopped".into()))
+    }
+
+    fn take_failure(&self) -> CoreResult<()> {
+        match self.shared.failure.lock().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn writer_loop(inner: Box<dyn AuditSink>, rx: Receiver<Msg>, shared: Arc<Shared>) {
+    for msg in rx {
+        match msg {
+            Msg::Entry(entry) => {
+                let res = inner.append(&entry);
+                shared.depth.fetch_sub(1, Ordering::Relaxed);
+                match res {
+                    Ok(()) => { shared.written.fetch_add(1, Ordering::Relaxed); }
+                    Err(e) => { shared.failure.lock().get_or_insert(e); }
+                }
+            }
+            Msg::Root(root) => {
+                if let Err(e) = inner.append_root(&root) {
+                    shared.failure.lock().get_or_insert(e);
+                }
+            }
+            Msg::Genesis(genesis) => {
+                if let Err(e) = inner.append_genesis(&genesis) {
+                    shared.failure.lock().get_or_insert(e);
+                }
+            }
+            Msg::Snapshot(snapshot) => {
+                if let Err(e) = inner.append_snapshot(&snapshot) {
+                    shared.failure.lock().get_or_insert(e);
+                }
+            }
+            Msg::Snapshots(reply) => {
+                let _ = reply.send(inner.snapshots());
+            }
+            Msg::Alarm(alarm) => {
+                if let Err(e) = inner.append_alarm(&alarm) {
+                    shared.failure.lock().get_or_insert(e);
+                }
+            }
+            Msg::Tail(n, reply) => {
+                let _ = reply.send(inner.tail(n));
+            }
+            Msg::Flush(reply) => {
+                let _ = reply.send(inner.flush());
+            }
+            Msg::Query(query, reply) => {
+                let _ = reply.send(inner.query(&query));
+            }
+        }
+    }
+    let _ = inner.flush();
+}
+
+impl AuditSink for BackgroundSink {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()> {
+        self.take_failure()?;
+        let depth = self.shared.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.shared.high_water.fetch_max(depth, Ordering::Relaxed);
+        self.send(Msg::Entry(entry.clone())).inspect_err(|_| {
+            self.shared.depth.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+
+    fn append_root(&self, root: &BatchRoot) -> CoreResult<()> {
+        self.take_failure()?;
+        self.send(Msg::Root(root.clone()))
+    }
+
+    fn append_genesis(&self, genesis: &SignedGenesis) -> CoreResult<()> {
+        self.take_failure()?;
+        *self.genesis.lock() = Some(genesis.clone());
+        self.send(Msg::Genesis(genesis.clone()))
+    }
+
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        Ok(self.genesis.lock().clone())
+    }
+
+    fn append_snapshot(&self, snapshot: &SignedSnapshot) -> CoreResult<()> {
+        self.take_failure()?;
+        self.send(Msg::Snapshot(snapshot.clone()))
+    }
+
+    fn snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.send(Msg::Snapshots(reply_tx))?;
+        reply_rx.recv().map_err(|_| CoreError::Storage("audit writer stopped".into()))?
+    }
+
+    fn append_alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        self.take_failure()?;
+        self.send(Msg::Alarm(alarm.clone()))
+    }
+
+    /// Queued behind every entry appended so far, so it sees them all.
+    fn tail(&self, n: usize) -> CoreResult<Vec<AuditEntry>> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.send(Msg::Tail(n, reply_tx))?;
+        reply_rx.recv().map_err(|_| CoreError::Storage("audit writer stopped".into()))?
+    }
+
+    fn resume(&self) -> CoreResult<Option<Recovery>> {
+        Ok(self.recovery.clone())
+    }
+
+    fn flush(&self) -> CoreResult<()> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.send(Msg::Flush(reply_tx))?;
+        let flushed = reply_rx.recv().map_err(|_| CoreError::Storage("audit writer stopped".into()))?;
+        self.take_failure()?;
+        flushed
+    }
+
+    /// Entries are still queued when `append` returns.
+    fn durable(&self) -> bool {
+        false
+    }
+
+    /// Runs on the writer thread after everything queued so far, so the
+    /// result includes those entries.
+    fn query(&self, query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.send(Msg::Query(query.clone(), reply_tx))?;
+        reply_rx.recv().map_err(|_| CoreError::Storage("audit writer stopped".into()))?
+    }
+}
+
+impl Drop for BackgroundSink {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain the queue and exit.
+        self.tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
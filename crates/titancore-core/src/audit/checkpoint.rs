@@ -0,0 +1,141 @@
+//! Signed statements of an engine's chain head, for publication outside the
+//! log (anchoring) and for handing to auditors.
+
+use super::BatchRoot;
+use crate::crypto;
+use crate::error::{CoreError, CoreResult};
+
+pub const CHECKPOINT_MAGIC: &[u8; 4] = b"TCCP";
+/// Version 2 adds the optional batch root; version 1 bodies still parse.
+pub const CHECKPOINT_VERSION: u8 = 2;
+const V1_BODY_LEN: usize = 4 + 1 + 32 + 8 + 32 + 8;
+const BATCH_LEN: usize = 8 + 8 + 8 + 32;
+
+/// "Engine `fingerprint` had chain head `head` after entry `counter`", and,
+/// when Merkle batching is on, "`batch` is a sealed batch of that chain".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub fingerprint: [u8; 32],
+    pub counter: u64,
+    pub head: [u8; 32],
+    pub timestamp: u64,
+    pub batch: Option<BatchRoot>,
+}
+
+impl Checkpoint {
+    /// Canonical signed bytes:
+    /// `magic(4) | version(1) | fingerprint(32) | counter(8) | head(32) | timestamp(8)
+    ///  | has_batch(1) [| first(8) | last(8) | size(8) | root(32)]`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(V1_BODY_LEN + 1 + BATCH_LEN);
+        out.extend_from_slice(CHECKPOINT_MAGIC);
+        out.push(CHECKPOINT_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.head);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        match &self.batch {
+            None => out.push(0),
+            Some(b) => {
+                out.push(1);
+                out.extend_from_slice(&b.first_counter.to_be_bytes());
+                out.extend_from_slice(&b.last_counter.to_be_bytes());
+                out.extend_from_slice(&b.size.to_be_bytes());
+                out.extend_from_slice(&b.root);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        if body_len(bytes)? != bytes.len() {
+            return Err(CoreError::Format("bad checkpoint"));
+        }
+        let arr32 = |i: usize| -> [u8; 32] { bytes[i..i + 32].try_into().expect("32 bytes") };
+        let u64_at = |i: usize| u64::from_be_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        let batch = (bytes.len() > V1_BODY_LEN + 1).then(|| BatchRoot {
+            first_counter: u64_at(86),
+            last_counter: u64_at(94),
+            size: u64_at(102),
+            root: arr32(110),
+        });
+        Ok(Checkpoint { fingerprint: arr32(5), counter: u64_at(37), head: arr32(45), timestamp: u64_at(77), batch })
+    }
+
+    pub fn sign(self, public_key: &[u8], secret_key: &[u8]) -> CoreResult<SignedCheckpoint> {
+        let signature = crypto::sign(secret_key, &self.to_bytes())?;
+        Ok(SignedCheckpoint { checkpoint: self, public_key: public_key.to_vec(), signature })
+    }
+}
+
+// Length of the checkpoint body at the start of `bytes`.
+fn body_len(bytes: &[u8]) -> CoreResult<usize> {
+    if bytes.len() < V1_BODY_LEN || &bytes[..4] != CHECKPOINT_MAGIC {
+        return Err(CoreError::Format("bad checkpoint"));
+    }
+    match bytes[4] {
+        1 => Ok(V1_BODY_LEN),
+        2 => match bytes.get(V1_BODY_LEN) {
+            Some(0) => Ok(V1_BODY_LEN + 1),
+            Some(1) => Ok(V1_BODY_LEN + 1 + BATCH_LEN),
+            _ => Err(CoreError::Format("bad checkpoint")),
+        },
+        _ => Err(CoreError::Format("unsupported version")),
+    }
+}
+
+/// A [`Checkpoint`] with a Dilithium5 signature and the signer's public key.
+/// The embedded key is informational: verify against a key you trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedCheckpoint {
+    pub checkpoint: Checkpoint,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedCheckpoint {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.checkpoint.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let body = body_len(bytes)?;
+        if bytes.len() < body + 2 {
+            return Err(CoreError::Format("truncated checkpoint"));
+        }
+        let checkpoint = Checkpoint::from_bytes(&bytes[..body])?;
+        let pk_len = u16::from_be_bytes([bytes[body], bytes[body + 1]]) as usize;
+        let rest = &bytes[body + 2..];
+        if rest.len() < pk_len {
+            return Err(CoreError::Format("truncated checkpoint"));
+        }
+        Ok(SignedCheckpoint { checkpoint, public_key: rest[..pk_len].to_vec(), signature: rest[pk_len..].to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.checkpoint.to_bytes(), &self.signature)
+    }
+
+    /// JSON object with hex-encoded fields, for HTTP and log endpoints.
+    pub fn to_json(&self) -> String {
+        let c = &self.checkpoint;
+        let batch = match &c.batch {
+            Some(b) => format!(
+                "{{\"first_counter\":{},\"last_counter\":{},\"size\":{},\"root\":\"{}\"}}",
+                b.first_counter, b.last_counter, b.size, hex::encode(b.root),
+            ),
+            None => "null".into(),
+        };
+        format!(
+            "{{\"version\":{},\"fingerprint\":\"{}\",\"counter\":{},\"head\":\"{}\",\"timestamp\":{},\"batch\":{},\"public_key\":\"{}\",\"signature\":\"{}\"}}",
+            CHECKPOINT_VERSION, hex::encode(c.fingerprint), c.counter, hex::encode(c.head), c.timestamp, batch,
+            hex::encode(&self.public_key), hex::encode(&self.signature),
+        )
+    }
+}
@@ -0,0 +1,614 @@
+use super::{AuditEntry, AuditQuery, AuditRecord, AuditSink, BatchRoot, LogVerification, LogVerifier, Recovery, SignedAlarm, SignedGenesis, SignedSnapshot};
+use std::collections::VecDeque;
+use crate::error::{CoreError, CoreResult};
+use crate::time::Instant;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+/// Entries re-validated from the end of the log on startup.
+pub const DEFAULT_RECOVERY_TAIL: usize = 64;
+// Upper bound on one line: two hashes, three u64s, op, outcome, two flags,
+// role, reason, operation ID, correlation ID, key ID (hex), 13 separators
+// and the newline.
+const MAX_LINE_LEN: u64 = 64 + 64 + 20 + 20 + 8 + 12 + 20 + 1 + 1
+    + crate::rbac::MAX_ROLE_LEN as u64 + 14 + 32 + crate::operation::MAX_CORRELATION_LEN as u64 + 64 + 14;
+/// First bytes of a binary log, followed by [`LOG_VERSION`].
+pub const LOG_MAGIC: &[u8; 4] = b"TCAL";
+pub const LOG_VERSION: u8 = 1;
+const HEADER_LEN: u64 = 5;
+
+/// When the file sink forces entries to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Write and `fdatasync` every entry: evidence is durable before the
+    /// operation returns. Throughput is bounded by disk sync latency.
+    #[default]
+    Always,
+    /// Write every entry immediately but sync only once `entries` writes have
+    /// accumulated or `interval` has passed since the last sync. A process
+    /// crash loses nothing (the page cache survives it); a power loss or
+    /// kernel crash can lose up to one window of entries.
+    Periodic { entries: usize, interval: Duration },
+    /// Hold entries in memory and write + sync them only on `flush()` (or
+    /// drop). Highest throughput; any crash loses everything since the last
+    /// flush, and the in-memory chain head runs ahead of the file until then.
+    Buffered,
+}
+
+/// How the file sink lays out entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// One line per entry (see [`AuditEntry::to_line`]).
+    #[default]
+    Text,
+    /// [`LOG_MAGIC`] and [`LOG_VERSION`], then `len(2) | record` per entry
+    /// (see [`AuditEntry::to_record`]). About half the size of the text
+    /// form and read without parsing text; records are framed by length,
+    /// so no field or byte sequence can pass for the start of an entry.
+    Binary,
+}
+
+/// Append-only log, one line or record per entry in its [`LogFormat`].
+/// Readers detect the format of an existing log from its first bytes;
+/// startup recovery refuses a log in the other format.
+pub struct FileSink {
+    path: String,
+    policy: SyncPolicy,
+    recovery_tail: usize,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    format: LogFormat,
+    file: Option<File>,
+    pending: Vec<u8>,
+    unsynced: usize,
+    last_sync: Instant,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self::with_policy(path, SyncPolicy::Always)
+    }
+
+    pub fn with_policy(path: impl Into<String>, policy: SyncPolicy) -> Self {
+        let state = FileState { format: LogFormat::Text, file: None, pending: Vec::new(), unsynced: 0, last_sync: Instant::now() };
+        FileSink { path: path.into(), policy, recovery_tail: DEFAULT_RECOVERY_TAIL, state: Mutex::new(state) }
+    }
+
+    /// Number of trailing entries [`FileSink::recover`] validates on startup.
+    pub fn with_recovery_tail(mut self, entries: usize) -> Self {
+        self.recovery_tail = entries.max(1);
+        self
+    }
+
+    /// Layout for a new log; an existing one must already use it.
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.state.get_mut().format = format;
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.state.lock().format
+    }
+
+    pub fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Sidecar holding one [`BatchRoot`] line per Merkle batch.
+    pub fn roots_path(&self) -> String {
+        format!("{}.roots", self.path)
+    }
+
+    /// Sidecar holding the log's [`SignedGenesis`] record.
+    pub fn genesis_path(&self) -> String {
+        format!("{}.genesis", self.path)
+    }
+
+    /// Sidecar holding one `offset|snapshot` line per [`SignedSnapshot`],
+    /// the offset being the log's length when it was taken.
+    pub fn snapshots_path(&self) -> String {
+        format!("{}.snapshots", self.path)
+    }
+
+    /// Sidecar holding one hex [`SignedAlarm`] per line.
+    pub fn alarms_path(&self) -> String {
+        format!("{}.alarms", self.path)
+    }
+
+    // Sidecar lines that parse, in order.
+    fn snapshot_lines(&self) -> CoreResult<Vec<(u64, SignedSnapshot)>> {
+        let text = match std::fs::read_to_string(self.snapshots_path()) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(text.lines()
+            .filter_map(|line| {
+                let (offset, record) = line.split_once('|')?;
+                let snapshot = SignedSnapshot::from_bytes(&hex::decode(record).ok()?).ok()?;
+                Some((offset.parse().ok()?, snapshot))
+            })
+            .collect())
+    }
+
+    /// Verifies the log with a [`LogVerifier`]: from the start, or, given a
+    /// snapshot from this log's sidecar that the caller already trusts (say
+    /// [`LogVerification::latest`] from the last run), from the entry after
+    /// it without reading what comes before. Snapshots must be signed by
+    /// `trusted_pk`, so verify from the start again after rotating keys.
+    /// Every line after the starting point must parse; the log is flushed
+    /// first.
+    pub fn verify_log(&self, trusted_pk: &[u8], from: Option<&SignedSnapshot>) -> CoreResult<LogVerification> {
+        self.flush()?;
+        let lines = self.snapshot_lines()?;
+        let offset = match from {
+            Some(from) => lines.iter().find(|(_, s)| s == from).map(|(offset, _)| *offset)
+                .ok_or_else(|| CoreError::Config("snapshot is not recorded for this log".into()))?,
+            None => 0,
+        };
+        let mut verifier = LogVerifier::new(trusted_pk, from, lines.into_iter().map(|(_, s)| s).collect())?;
+        let (file, format) = match self.open_log()? {
+            Some(log) => log,
+            None if from.is_none() => return verifier.finish(),
+            None => return Err(CoreError::Storage(format!("{} not found", self.path))),
+        };
+        each_entry(file, format, offset, |entry| {
+            verifier.push(&entry.ok_or_else(|| CoreError::Storage("unparseable audit log entry".into()))?)
+        })?;
+        verifier.finish()
+    }
+
+    // The log and its format (ours, if it is empty); `None` if there is none.
+    fn open_log(&self) -> CoreResult<Option<(File, LogFormat)>> {
+        let mut file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let format = detect_format(&mut file)?.unwrap_or_else(|| self.format());
+        Ok(Some((file, format)))
+    }
+
+    /// Checks the last `tail` entries for torn or corrupt lines (or
+    /// records) left by a crash: every one must be complete, parse and link
+    /// to its predecessor's head. (Counters need not increase: concurrent
+    /// operations reserve counters before they reach the chain.) The first
+    /// bad one and everything after it is appended to `<log>.damaged`, the
+    /// log is truncated to the last valid entry and that entry's head is
+    /// returned so the chain resumes from it. `None` means there is no log
+    /// yet; a log in the other [`LogFormat`] is a [`CoreError::Config`].
+    pub fn recover(&self, tail: usize) -> CoreResult<Option<Recovery>> {
+        let mut file = match std::fs::OpenOptions::new().read(true).write(true).open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let format = self.format();
+        if detect_format(&mut file)?.is_some_and(|found| found != format) {
+            let expected = match format { LogFormat::Text => "text", LogFormat::Binary => "binary" };
+            return Err(CoreError::Config(format!("audit log {} is not in {} format", self.path, expected)));
+        }
+        let len = file.metadata()?.len();
+        let (start, scan) = match format {
+            LogFormat::Text => {
+                let window = (tail as u64 + 1) * MAX_LINE_LEN;
+                let start = len.saturating_sub(window);
+                let scan = scan_tail(&mut file, start)?;
+                if scan.valid.is_none() && start > 0 {
+                    // Nothing valid in the window: rescan from the beginning.
+                    (0, scan_tail(&mut file, 0)?)
+                } else {
+                    (start, scan)
+                }
+            }
+            // A torn header leaves nothing to keep.
+            LogFormat::Binary if len < HEADER_LEN => (0, TailScan { valid: None, checked: 0, max_counter: 0, good_len: 0 }),
+            LogFormat::Binary => {
+                // Records cannot be found from an arbitrary offset, so hop
+                // along the length prefixes to the last `tail + 1`.
+                let start = record_starts(&mut file, len, tail + 1)?.front().copied().unwrap_or(HEADER_LEN);
+                let scan = scan_records(&mut file, start)?;
+                if scan.valid.is_none() && start > HEADER_LEN {
+                    (HEADER_LEN, scan_records(&mut file, HEADER_LEN)?)
+                } else {
+                    (start, scan)
+                }
+            }
+        };
+
+        let mut recovery = match scan.valid {
+            Some(last) => Recovery {
+                head: last.curr,
+                counter: scan.max_counter,
+                seq: last.seq,
+                timestamp_ms: last.timestamp_ms,
+                ..Default::default()
+            },
+            None => Recovery::default(),
+        };
+        recovery.entries_checked = scan.checked;
+        let good_end = start + scan.good_len;
+        if good_end < len {
+            let quarantine = format!("{}.damaged", self.path);
+            let mut damaged = Vec::with_capacity((len - good_end) as usize);
+            file.seek(SeekFrom::Start(good_end))?;
+            file.read_to_end(&mut damaged)?;
+            if format == LogFormat::Text && damaged.last() != Some(&b'\n') {
+                damaged.push(b'\n');
+            }
+            let mut out = std::fs::OpenOptions::new().create(true).append(true).open(&quarantine)?;
+            out.write_all(&damaged)?;
+            out.sync_data()?;
+            file.set_len(good_end)?;
+            file.sync_data()?;
+            recovery.quarantined_bytes = len - good_end;
+            recovery.quarantine_path = Some(quarantine);
+        }
+        Ok(Some(recovery))
+    }
+}
+
+struct TailScan {
+    valid: Option<AuditEntry>,
+    checked: usize,
+    max_counter: u64,
+    // Bytes from the scan start up to the end of the last valid line.
+    good_len: u64,
+}
+
+/// Syncs the directory holding `path`, so a file just created or renamed
+/// into it survives a crash. A no-op where directories cannot be opened.
+pub(crate) fn sync_parent_dir(path: &std::path::Path) -> CoreResult<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn scan_tail(file: &mut File, start: u64) -> CoreResult<TailScan> {
+    let mut buf = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.read_to_end(&mut buf)?;
+
+    // Mid-file windows begin inside a line; skip to the next one.
+    let mut pos = 0;
+    if start > 0 {
+        match buf.iter().position(|&b| b == b'\n') {
+            Some(nl) => pos = nl + 1,
+            None => return Ok(TailScan { valid: None, checked: 0, max_counter: 0, good_len: 0 }),
+        }
+    }
+    let mut scan = TailScan { valid: None, checked: 0, max_counter: 0, good_len: pos as u64 };
+    let mut prev: Option<AuditEntry> = None;
+    while pos < buf.len() {
+        let Some(nl) = buf[pos..].iter().position(|&b| b == b'\n') else { break };
+        let line = &buf[pos..pos + nl];
+        let entry = std::str::from_utf8(line).ok().and_then(AuditEntry::parse_line);
+        let Some(entry) = entry else { break };
+        // A zero `prev` starts a new segment: logs written before chains
+        // resumed across restarts begin one per process start.
+        let linked = entry.prev == [0u8; 32] || match &prev {
+            Some(p) => entry.prev == p.curr,
+            None => start > 0,
+        };
+        if !linked {
+            break;
+        }
+        scan.checked += 1;
+        scan.max_counter = scan.max_counter.max(entry.counter);
+        pos += nl + 1;
+        scan.good_len = pos as u64;
+        prev = Some(entry);
+    }
+    scan.valid = prev;
+    Ok(scan)
+}
+
+// `len(2) | record` from `start` (a record boundary) on, as `scan_tail`
+// reads lines; the first record may follow one outside the scan.
+fn scan_records(file: &mut File, start: u64) -> CoreResult<TailScan> {
+    let mut buf = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.read_to_end(&mut buf)?;
+
+    let mut scan = TailScan { valid: None, checked: 0, max_counter: 0, good_len: 0 };
+    let mut prev: Option<AuditEntry> = None;
+    let mut pos = 0;
+    while pos + 2 <= buf.len() {
+        let end = pos + 2 + u16::from_be_bytes([buf[pos], buf[pos + 1]]) as usize;
+        let Some(entry) = buf.get(pos + 2..end).and_then(AuditEntry::parse_record) else { break };
+        let linked = entry.prev == [0u8; 32] || match &prev {
+            Some(p) => entry.prev == p.curr,
+            None => start > HEADER_LEN,
+        };
+        if !linked {
+            break;
+        }
+        scan.checked += 1;
+        scan.max_counter = scan.max_counter.max(entry.counter);
+        pos = end;
+        scan.good_len = pos as u64;
+        prev = Some(entry);
+    }
+    scan.valid = prev;
+    Ok(scan)
+}
+
+// Offsets of the last `keep` records of a binary log of `len` bytes.
+fn record_starts(file: &mut File, len: u64, keep: usize) -> CoreResult<VecDeque<u64>> {
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(HEADER_LEN))?;
+    let mut starts = VecDeque::with_capacity(keep + 1);
+    let mut pos = HEADER_LEN;
+    while pos < len {
+        starts.push_back(pos);
+        if starts.len() > keep {
+            starts.pop_front();
+        }
+        let mut prefix = [0u8; 2];
+        if reader.read_exact(&mut prefix).is_err() {
+            break;
+        }
+        let body = u16::from_be_bytes(prefix) as u64;
+        reader.seek_relative(body as i64)?;
+        pos += 2 + body;
+    }
+    Ok(starts)
+}
+
+// `None` for an empty file. A prefix of the header (a crash while creating
+// the log) counts as binary.
+fn detect_format(file: &mut File) -> CoreResult<Option<LogFormat>> {
+    let mut head = Vec::with_capacity(HEADER_LEN as usize);
+    file.seek(SeekFrom::Start(0))?;
+    Read::take(&mut *file, HEADER_LEN).read_to_end(&mut head)?;
+    if head.is_empty() {
+        return Ok(None);
+    }
+    let magic = &head[..head.len().min(4)];
+    if !LOG_MAGIC.starts_with(magic) {
+        return Ok(Some(LogFormat::Text));
+    }
+    if head.len() == HEADER_LEN as usize && head[4] != LOG_VERSION {
+        return Err(CoreError::Format("unsupported audit log version"));
+    }
+    Ok(Some(LogFormat::Binary))
+}
+
+// Calls `f` with each entry from `offset` (a line or record boundary, zero
+// for the first) on; `None` stands for a line or record that does not
+// parse, including a torn last one.
+fn each_entry(file: File, format: LogFormat, offset: u64, mut f: impl FnMut(Option<AuditEntry>) -> CoreResult<()>) -> CoreResult<()> {
+    let mut reader = BufReader::new(file);
+    match format {
+        LogFormat::Text => {
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line)? > 0 {
+                let text = line.strip_suffix(b"\n").unwrap_or(&line);
+                f(std::str::from_utf8(text).ok().and_then(AuditEntry::parse_line))?;
+                line.clear();
+            }
+        }
+        LogFormat::Binary => {
+            reader.seek(SeekFrom::Start(offset.max(HEADER_LEN)))?;
+            let mut body = Vec::new();
+            while !reader.fill_buf()?.is_empty() {
+                let mut prefix = [0u8; 2];
+                if reader.read_exact(&mut prefix).is_err() {
+                    return f(None);
+                }
+                body.resize(u16::from_be_bytes(prefix) as usize, 0);
+                if reader.read_exact(&mut body).is_err() {
+                    return f(None);
+                }
+                f(AuditEntry::parse_record(&body))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl FileState {
+    fn file(&mut self, path: &str) -> CoreResult<&mut File> {
+        if self.file.is_none() {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)
+                .map_err(|e| CoreError::Storage(format!("Storage error: {}", e)))?;
+            if self.format == LogFormat::Binary && file.metadata()?.len() == 0 {
+                let mut header = LOG_MAGIC.to_vec();
+                header.push(LOG_VERSION);
+                file.write_all(&header).map_err(|_| CoreError::Storage("Write fail".into()))?;
+            }
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("opened above"))
+    }
+
+    fn write(&mut self, path: &str, bytes: &[u8]) -> CoreResult<()> {
+        self.file(path)?.write_all(bytes).map_err(|_| CoreError::Storage("Write fail".into()))?;
+        self.unsynced += 1;
+        Ok(())
+    }
+
+    fn sync(&mut self, path: &str) -> CoreResult<()> {
+        self.file(path)?.sync_data().map_err(|_| CoreError::Storage("Sync fail".into()))?;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+impl AuditSink for FileSink {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()> {
+        let mut state = self.state.lock();
+        let line = match state.format {
+            LogFormat::Text => entry.to_line().into_bytes(),
+            LogFormat::Binary => {
+                let record = entry.to_record();
+                let mut out = Vec::with_capacity(2 + record.len());
+                out.extend_from_slice(&(record.len() as u16).to_be_bytes());
+                out.extend_from_slice(&record);
+                out
+            }
+        };
+        match self.policy {
+            SyncPolicy::Always => {
+                state.write(&self.path, &line)?;
+                state.sync(&self.path)
+            }
+            SyncPolicy::Periodic { entries, interval } => {
+                state.write(&self.path, &line)?;
+                if state.unsynced >= entries.max(1) || state.last_sync.elapsed() >= interval {
+                    state.sync(&self.path)?;
+                }
+                Ok(())
+            }
+            SyncPolicy::Buffered => {
+                state.pending.extend_from_slice(&line);
+                Ok(())
+            }
+        }
+    }
+
+    fn append_root(&self, root: &BatchRoot) -> CoreResult<()> {
+        // Roots cover entries that must already be on disk.
+        self.flush()?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.roots_path())?;
+        file.write_all(root.to_line().as_bytes()).map_err(|_| CoreError::Storage("Write fail".into()))?;
+        file.sync_data().map_err(|_| CoreError::Storage("Sync fail".into()))
+    }
+
+    /// Only called for an empty log, so a record left by a log that was
+    /// removed is replaced.
+    fn append_genesis(&self, genesis: &SignedGenesis) -> CoreResult<()> {
+        let path = self.genesis_path();
+        let mut file = File::create(&path)?;
+        file.write_all(&genesis.to_bytes()).map_err(|_| CoreError::Storage("Write fail".into()))?;
+        file.sync_data().map_err(|_| CoreError::Storage("Sync fail".into()))?;
+        sync_parent_dir(path.as_ref())
+    }
+
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        match std::fs::read(self.genesis_path()) {
+            Ok(bytes) => SignedGenesis::from_bytes(&bytes).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn append_snapshot(&self, snapshot: &SignedSnapshot) -> CoreResult<()> {
+        // The offset must count every entry the snapshot covers.
+        self.flush()?;
+        let offset = match std::fs::metadata(&self.path) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let line = format!("{}|{}\n", offset, hex::encode(snapshot.to_bytes()));
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.snapshots_path())?;
+        file.write_all(line.as_bytes()).map_err(|_| CoreError::Storage("Write fail".into()))?;
+        file.sync_data().map_err(|_| CoreError::Storage("Sync fail".into()))
+    }
+
+    fn snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        Ok(self.snapshot_lines()?.into_iter().map(|(_, s)| s).collect())
+    }
+
+    fn append_alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.alarms_path())?;
+        file.write_all(format!("{}\n", hex::encode(alarm.to_bytes())).as_bytes()).map_err(|_| CoreError::Storage("Write fail".into()))?;
+        file.sync_data().map_err(|_| CoreError::Storage("Sync fail".into()))
+    }
+
+    /// Flushes, then reads back from the end of the log; an entry that does
+    /// not parse is a [`CoreError::Storage`].
+    fn tail(&self, n: usize) -> CoreResult<Vec<AuditEntry>> {
+        self.flush()?;
+        let Some((mut file, format)) = self.open_log()? else { return Ok(Vec::new()) };
+        let len = file.metadata()?.len();
+        let offset = match format {
+            LogFormat::Text => {
+                // A window start inside a line skips to the next one.
+                let start = len.saturating_sub((n as u64 + 1) * MAX_LINE_LEN);
+                let mut skipped = Vec::new();
+                file.seek(SeekFrom::Start(start))?;
+                if start > 0 {
+                    BufReader::new(&mut file).read_until(b'\n', &mut skipped)?;
+                }
+                start + skipped.len() as u64
+            }
+            LogFormat::Binary => record_starts(&mut file, len, n)?.front().copied().unwrap_or(HEADER_LEN),
+        };
+        let mut entries = VecDeque::with_capacity(n + 1);
+        each_entry(file, format, offset, |entry| {
+            entries.push_back(entry.ok_or_else(|| CoreError::Storage("unparseable audit log entry".into()))?);
+            if entries.len() > n {
+                entries.pop_front();
+            }
+            Ok(())
+        })?;
+        Ok(entries.into())
+    }
+
+    fn resume(&self) -> CoreResult<Option<Recovery>> {
+        self.recover(self.recovery_tail)
+    }
+
+    /// Scans the whole log after flushing it. The log holds one engine's
+    /// entries, so records carry an empty `engine` label; unparseable
+    /// entries are skipped, as recovery quarantines them.
+    fn query(&self, query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        self.flush()?;
+        let Some((file, format)) = self.open_log()? else { return Ok(Vec::new()) };
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut out = Vec::new();
+        each_entry(file, format, 0, |entry| {
+            if let Some(entry) = entry.filter(|e| out.len() < limit && query.matches("", e)) {
+                out.push(AuditRecord { engine: String::new(), entry });
+            }
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    fn flush(&self) -> CoreResult<()> {
+        let mut state = self.state.lock();
+        if !state.pending.is_empty() {
+            let pending = std::mem::take(&mut state.pending);
+            if let Err(e) = state.write(&self.path, &pending) {
+                state.pending = pending;
+                return Err(e);
+            }
+        }
+        if state.unsynced > 0 {
+            state.sync(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Only [`SyncPolicy::Always`] syncs before `append` returns.
+    fn durable(&self) -> bool {
+        self.policy == SyncPolicy::Always
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,136 @@
+//! The signed record an audit log starts with.
+//!
+//! A new engine writing to an empty sink signs a [`Genesis`] naming its
+//! fingerprint, checkpoint public key, license, a hash of its configuration
+//! (see [`crate::Engine::config_hash`]) and start time, stores it with
+//! [`AuditSink::append_genesis`](super::AuditSink::append_genesis), and
+//! makes a `genesis` event bound to its digest the first entry of the chain.
+//! A verifier holding the record then knows which engine, configuration and
+//! license produced the entries that follow, and [`SignedGenesis::links`]
+//! ties it to that first entry.
+//!
+//! Layout: `magic(4) | version(1) | fingerprint(32) | config_hash(32) |
+//! timestamp_ms(8) | license_len(2) | license | pk_len(2) | public_key`,
+//! then the signature.
+
+use super::{AuditEntry, OpType, Outcome};
+use crate::crypto;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+
+pub const GENESIS_MAGIC: &[u8; 4] = b"TCGN";
+pub const GENESIS_VERSION: u8 = 1;
+
+/// "Engine `fingerprint`, holding checkpoint key `public_key`, licensed as
+/// `license`, started a log at `timestamp_ms` with the configuration
+/// hashing to `config_hash`."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Genesis {
+    pub fingerprint: [u8; 32],
+    pub public_key: Vec<u8>,
+    pub license: String,
+    pub config_hash: [u8; 32],
+    pub timestamp_ms: u64,
+}
+
+impl Genesis {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(81 + self.license.len() + self.public_key.len());
+        out.extend_from_slice(GENESIS_MAGIC);
+        out.push(GENESIS_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.config_hash);
+        out.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        out.extend_from_slice(&(self.license.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.license.as_bytes());
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out
+    }
+
+    // Parses the body at the start of `r`, leaving the signature.
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != GENESIS_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != GENESIS_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let fingerprint = r.array()?;
+        let config_hash = r.array()?;
+        let timestamp_ms = u64::from_be_bytes(r.array()?);
+        let license_len = u16::from_be_bytes(r.array()?) as usize;
+        let license = String::from_utf8(r.take(license_len)?.to_vec()).map_err(|_| CoreError::Format("license is not UTF-8"))?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        Ok(Genesis { fingerprint, public_key, license, config_hash, timestamp_ms })
+    }
+
+    /// What the first chain entry binds as its subject.
+    pub fn digest(&self) -> [u8; 32] {
+        blake3::derive_key("titancore audit genesis v1", &self.to_bytes())
+    }
+
+    pub fn sign(self, secret_key: &[u8]) -> CoreResult<SignedGenesis> {
+        if self.license.len() > u16::MAX as usize {
+            return Err(CoreError::Config("license too long".into()));
+        }
+        let signature = crypto::sign(secret_key, &self.to_bytes())?;
+        Ok(SignedGenesis { genesis: self, signature })
+    }
+}
+
+/// A [`Genesis`] with a Dilithium5 signature by its own `public_key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedGenesis {
+    pub genesis: Genesis,
+    pub signature: Vec<u8>,
+}
+
+impl SignedGenesis {
+    /// `body | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.genesis.to_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let genesis = Genesis::read(&mut r)?;
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("genesis without signature"));
+        }
+        Ok(SignedGenesis { genesis, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`. The record is
+    /// self-signed, so checking it against its own key only shows it is
+    /// intact; pin the engine's key to learn who wrote it.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.genesis.to_bytes(), &self.signature)
+    }
+
+    /// True if `entry` is the chain's first entry and records this genesis.
+    pub fn links(&self, entry: &AuditEntry) -> bool {
+        let g = &self.genesis;
+        entry.prev == [0u8; 32]
+            && entry.op == OpType::Genesis
+            && entry.outcome == Outcome::Success
+            && entry.curr == super::event_hash(&[0u8; 32], entry.counter, &g.fingerprint, OpType::Genesis, Outcome::Success, &g.digest())
+    }
+
+    /// JSON object with hex-encoded fields, for HTTP and log endpoints.
+    pub fn to_json(&self) -> String {
+        let g = &self.genesis;
+        serde_json::json!({
+            "version": GENESIS_VERSION,
+            "fingerprint": hex::encode(g.fingerprint),
+            "public_key": hex::encode(&g.public_key),
+            "license": g.license,
+            "config_hash": hex::encode(g.config_hash),
+            "timestamp_ms": g.timestamp_ms,
+            "signature": hex::encode(&self.signature),
+        }).to_string()
+    }
+}
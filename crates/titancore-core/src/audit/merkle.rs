@@ -0,0 +1,226 @@
+//! Merkle roots over batches of audit entries, with RFC 6962-style inclusion
+//! proofs so a single operation can be checked against a published root
+//! without the rest of the log.
+//!
+//! Leaf = BLAKE3(0x00 || counter || entry head); node = BLAKE3(0x01 || left || right).
+
+use super::AuditEntry;
+use crate::error::{CoreError, CoreResult};
+
+/// Root over the entries with counters `first_counter..=last_counter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchRoot {
+    pub first_counter: u64,
+    pub last_counter: u64,
+    pub size: u64,
+    pub root: [u8; 32],
+}
+
+impl BatchRoot {
+    /// Line form used by the `.roots` sidecar: `first|last|size|root`.
+    pub fn to_line(&self) -> String {
+        format!("{}|{}|{}|{}\n", self.first_counter, self.last_counter, self.size, hex::encode(self.root))
+    }
+
+    pub fn parse_line(line: &str) -> Option<BatchRoot> {
+        let mut fields = line.split('|');
+        let first_counter = fields.next()?.parse().ok()?;
+        let last_counter = fields.next()?.parse().ok()?;
+        let size = fields.next()?.parse().ok()?;
+        let mut root = [0u8; 32];
+        hex::decode_to_slice(fields.next()?, &mut root).ok()?;
+        Some(BatchRoot { first_counter, last_counter, size, root })
+    }
+}
+
+/// Audit path from one entry's leaf to its batch root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub counter: u64,
+    pub head: [u8; 32],
+    pub index: u64,
+    pub size: u64,
+    pub path: Vec<[u8; 32]>,
+}
+
+impl InclusionProof {
+    /// `counter(8) | head(32) | index(8) | size(8) | n(2) | path(32 * n)`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(58 + 32 * self.path.len());
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.head);
+        out.extend_from_slice(&self.index.to_be_bytes());
+        out.extend_from_slice(&self.size.to_be_bytes());
+        out.extend_from_slice(&(self.path.len() as u16).to_be_bytes());
+        for node in &self.path {
+            out.extend_from_slice(node);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        if bytes.len() < 58 {
+            return Err(CoreError::Format("truncated proof"));
+        }
+        let u64_at = |i: usize| u64::from_be_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        let mut head = [0u8; 32];
+        head.copy_from_slice(&bytes[8..40]);
+        let n = u16::from_be_bytes([bytes[56], bytes[57]]) as usize;
+        let rest = &bytes[58..];
+        if rest.len() != 32 * n {
+            return Err(CoreError::Format("bad proof length"));
+        }
+        let path = rest.chunks_exact(32).map(|c| c.try_into().expect("32 bytes")).collect();
+        Ok(InclusionProof { counter: u64_at(0), head, index: u64_at(40), size: u64_at(48), path })
+    }
+}
+
+pub fn leaf_hash(counter: u64, head: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0x00]);
+    hasher.update(&counter.to_be_bytes());
+    hasher.update(head);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// Largest power of two strictly below n (n >= 2).
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - (n - 1).leading_zeros() - 1)
+}
+
+/// Tree root over leaf hashes. Panics on an empty slice.
+pub fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    match leaves.len() {
+        0 => panic!("empty Merkle tree"),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+fn path(index: usize, leaves: &[[u8; 32]], out: &mut Vec<[u8; 32]>) {
+    let n = leaves.len();
+    if n <= 1 {
+        return;
+    }
+    let k = split(n);
+    if index < k {
+        path(index, &leaves[..k], out);
+        out.push(root(&leaves[k..]));
+    } else {
+        path(index - k, &leaves[k..], out);
+        out.push(root(&leaves[..k]));
+    }
+}
+
+/// Builds the root and the proof for `counter` from one batch's entries.
+pub fn prove(entries: &[AuditEntry], counter: u64) -> Option<(InclusionProof, [u8; 32])> {
+    let index = entries.iter().position(|e| e.counter == counter)?;
+    let leaves: Vec<_> = entries.iter().map(|e| leaf_hash(e.counter, &e.curr)).collect();
+    let mut nodes = Vec::new();
+    path(index, &leaves, &mut nodes);
+    let proof = InclusionProof {
+        counter,
+        head: entries[index].curr,
+        index: index as u64,
+        size: leaves.len() as u64,
+        path: nodes,
+    };
+    Some((proof, root(&leaves)))
+}
+
+/// Checks that `proof` leads from its entry to `root`.
+pub fn verify_inclusion(proof: &InclusionProof, root: &[u8; 32]) -> bool {
+    if proof.index >= proof.size {
+        return false;
+    }
+    let (mut fn_, mut sn) = (proof.index, proof.size - 1);
+    let mut r = leaf_hash(proof.counter, &proof.head);
+    for p in &proof.path {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && r == *root
+}
+
+/// Accumulates entries into fixed-size batches and keeps the sealed batches
+/// so entries from this process can be proven later.
+pub(crate) struct MerkleBatcher {
+    batch_size: usize,
+    pending: Vec<AuditEntry>,
+    sealed: Vec<(BatchRoot, Vec<AuditEntry>)>,
+}
+
+impl MerkleBatcher {
+    pub(crate) fn new(batch_size: usize) -> Self {
+        MerkleBatcher { batch_size: batch_size.max(1), pending: Vec::new(), sealed: Vec::new() }
+    }
+
+    pub(crate) fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Adds an entry; returns the batch root once the batch is full.
+    pub(crate) fn push(&mut self, entry: AuditEntry) -> Option<BatchRoot> {
+        self.pending.push(entry);
+        if self.pending.len() >= self.batch_size { self.seal() } else { None }
+    }
+
+    /// Closes the current partial batch, if any.
+    pub(crate) fn seal(&mut self) -> Option<BatchRoot> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let entries = std::mem::take(&mut self.pending);
+        let leaves: Vec<_> = entries.iter().map(|e| leaf_hash(e.counter, &e.curr)).collect();
+        let batch = BatchRoot {
+            first_counter: entries[0].counter,
+            last_counter: entries[entries.len() - 1].counter,
+            size: entries.len() as u64,
+            root: root(&leaves),
+        };
+        self.sealed.push((batch.clone(), entries));
+        Some(batch)
+    }
+
+    pub(crate) fn prove(&self, counter: u64) -> Option<(InclusionProof, BatchRoot)> {
+        let (_, proof, batch) = self.evidence(counter)?;
+        Some((proof, batch))
+    }
+
+    /// The entry with `counter`, its proof and its batch root.
+    pub(crate) fn evidence(&self, counter: u64) -> Option<(AuditEntry, InclusionProof, BatchRoot)> {
+        let (batch, entries) = self.sealed.iter()
+            .find(|(b, _)| (b.first_counter..=b.last_counter).contains(&counter))?;
+        let (proof, _) = prove(entries, counter)?;
+        let entry = entries[proof.index as usize].clone();
+        Some((entry, proof, batch.clone()))
+    }
+
+    /// Most recently sealed batch.
+    pub(crate) fn latest(&self) -> Option<&BatchRoot> {
+        self.sealed.last().map(|(b, _)| b)
+    }
+}
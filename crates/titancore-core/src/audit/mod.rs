@@ -0,0 +1,764 @@
+use crate::error::{CoreError, CoreResult};
+use parking_lot::Mutex;
+
+pub mod alarm;
+#[cfg(not(target_arch = "wasm32"))]
+mod background;
+pub mod checkpoint;
+#[cfg(feature = "fs")]
+mod file;
+pub mod genesis;
+pub mod merkle;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod syslog;
+pub use alarm::{Alarm, AlarmReason, SignedAlarm};
+#[cfg(not(target_arch = "wasm32"))]
+pub use background::{BackgroundSink, QueueStats, DEFAULT_QUEUE_CAPACITY};
+#[cfg(feature = "fs")]
+pub use file::{FileSink, LogFormat, SyncPolicy, DEFAULT_RECOVERY_TAIL};
+#[cfg(feature = "fs")]
+pub(crate) use file::sync_parent_dir;
+pub use genesis::{Genesis, SignedGenesis};
+pub use merkle::{BatchRoot, InclusionProof};
+pub use snapshot::{LogVerification, LogVerifier, SignedSnapshot, Snapshot};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteSink;
+#[cfg(not(target_arch = "wasm32"))]
+pub use syslog::{SyslogSink, SyslogTarget};
+
+/// What an audited operation was.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpType {
+    #[default]
+    Encrypt,
+    Decrypt,
+    Sign,
+    Keygen,
+    Rekey,
+    /// A data key recovered through the escrow key.
+    Escrow,
+    /// A quorum of approvers authorizing a restricted decryption.
+    Approval,
+    /// The first entry of a log, bound to its [`SignedGenesis`].
+    Genesis,
+}
+
+/// How an audited operation ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Outcome {
+    #[default]
+    Success,
+    RateLimited,
+    KeyInvalid,
+    Failed,
+}
+
+impl OpType {
+    const ALL: [OpType; 8] = [
+        OpType::Encrypt, OpType::Decrypt, OpType::Sign, OpType::Keygen, OpType::Rekey, OpType::Escrow, OpType::Approval,
+        OpType::Genesis,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OpType::Encrypt => "encrypt",
+            OpType::Decrypt => "decrypt",
+            OpType::Sign => "sign",
+            OpType::Keygen => "keygen",
+            OpType::Rekey => "rekey",
+            OpType::Escrow => "escrow",
+            OpType::Approval => "approval",
+            OpType::Genesis => "genesis",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<OpType> {
+        Self::ALL.into_iter().find(|op| op.as_str() == s)
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<OpType> {
+        Self::ALL.get(code as usize).copied()
+    }
+}
+
+impl Outcome {
+    const ALL: [Outcome; 4] = [Outcome::Success, Outcome::RateLimited, Outcome::KeyInvalid, Outcome::Failed];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::RateLimited => "rate-limited",
+            Outcome::KeyInvalid => "key-invalid",
+            Outcome::Failed => "failed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Outcome> {
+        Self::ALL.into_iter().find(|o| o.as_str() == s)
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Outcome> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    /// Outcome recorded for an operation that failed with `err`.
+    pub fn of(err: &CoreError) -> Outcome {
+        match err {
+            CoreError::RateLimited => Outcome::RateLimited,
+            CoreError::InvalidKey | CoreError::KeyFormat(_) | CoreError::Revoked => Outcome::KeyInvalid,
+            _ => Outcome::Failed,
+        }
+    }
+}
+
+/// Why an operation failed, recorded with its failure entry
+/// ([`AuditEntry::reason`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    /// Over the engine's request quota.
+    RateLimited = 1,
+    /// Over the per-key limit from [`crate::Engine::set_key_limit`].
+    KeyLimit,
+    /// Past the key's lifetime usage cap.
+    UsageCap,
+    /// Refused by policy: a missing step-up grant or quorum, a role without
+    /// the permission, an unknown caller token, a restricted envelope or an
+    /// envelope's decryption constraints.
+    Denied,
+    InvalidKey,
+    Revoked,
+    /// Input that does not parse.
+    Malformed,
+    Certificate,
+    /// Ciphertext that failed authentication.
+    Decryption,
+    /// A setting or argument the engine rejects, including suites outside
+    /// FIPS mode and calls on a closed engine.
+    Config,
+    RekeyRequired,
+    Entropy,
+    /// Anything else, e.g. a failed self test.
+    Internal,
+}
+
+impl FailureReason {
+    const ALL: [FailureReason; 13] = [
+        FailureReason::RateLimited, FailureReason::KeyLimit, FailureReason::UsageCap, FailureReason::Denied,
+        FailureReason::InvalidKey, FailureReason::Revoked, FailureReason::Malformed, FailureReason::Certificate,
+        FailureReason::Decryption, FailureReason::Config, FailureReason::RekeyRequired, FailureReason::Entropy,
+        FailureReason::Internal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureReason::RateLimited => "rate-limited",
+            FailureReason::KeyLimit => "key-limit",
+            FailureReason::UsageCap => "usage-cap",
+            FailureReason::Denied => "denied",
+            FailureReason::InvalidKey => "invalid-key",
+            FailureReason::Revoked => "revoked",
+            FailureReason::Malformed => "malformed",
+            FailureReason::Certificate => "certificate",
+            FailureReason::Decryption => "decryption",
+            FailureReason::Config => "config",
+            FailureReason::RekeyRequired => "rekey-required",
+            FailureReason::Entropy => "entropy",
+            FailureReason::Internal => "internal",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<FailureReason> {
+        Self::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    /// Non-zero; zero stands for no reason where one byte is always
+    /// written.
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<FailureReason> {
+        Self::ALL.into_iter().find(|r| r.code() == code)
+    }
+
+    /// FailureReason recorded for an operation that failed with `err`.
+    pub fn of(err: &CoreError) -> FailureReason {
+        match err {
+            CoreError::RateLimited => FailureReason::RateLimited,
+            CoreError::Unauthorized | CoreError::Constraint(_) => FailureReason::Denied,
+            CoreError::InvalidKey | CoreError::KeyFormat(_) => FailureReason::InvalidKey,
+            CoreError::Revoked => FailureReason::Revoked,
+            CoreError::Format(_) | CoreError::Mnemonic(_) => FailureReason::Malformed,
+            CoreError::Certificate(_) => FailureReason::Certificate,
+            CoreError::Decryption => FailureReason::Decryption,
+            CoreError::Config(_) => FailureReason::Config,
+            CoreError::RekeyRequired(_) => FailureReason::RekeyRequired,
+            CoreError::Entropy | CoreError::DegradedEntropy(_) => FailureReason::Entropy,
+            CoreError::Kdf | CoreError::Encryption | CoreError::Storage(_) | CoreError::SelfTest(_) => FailureReason::Internal,
+        }
+    }
+}
+
+/// One link of the audit hash chain.
+///
+/// Only what the link hashes cover is authenticated: `prev`, `counter`,
+/// `op`, `outcome`, `reason` and the operation's subject. The timestamp,
+/// flags, role, operation and correlation IDs and key ID are recorded
+/// beside the link, so anyone who can write the log can change them
+/// without breaking the chain. They serve search and correlation, not
+/// evidence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub prev: [u8; 32],
+    pub curr: [u8; 32],
+    pub counter: u64,
+    /// Wall-clock UTC time in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub op: OpType,
+    pub outcome: Outcome,
+    /// Position in the chain, continued across restarts. Unlike the wall
+    /// clock it never goes backwards, and unlike `counter` it follows chain
+    /// order. Zero for entries read from logs that predate it.
+    pub seq: u64,
+    /// The wall clock read earlier than the previous entry's timestamp
+    /// (e.g. an NTP step); `timestamp_ms` is recorded as read.
+    pub clock_regressed: bool,
+    /// Written by an engine in FIPS mode (see [`crate::fips`]). False for
+    /// entries read from logs that predate it.
+    pub fips: bool,
+    /// Role of the caller the entry was written for (see [`crate::rbac`]);
+    /// `None` outside a caller scope. Like the timestamp it is recorded
+    /// beside the link, not hashed into it, and so not authenticated.
+    pub role: Option<String>,
+    /// Why the operation failed; `None` for successes and entries from
+    /// logs that predate it. Hashed into the link (see [`failure_hash`]).
+    pub reason: Option<FailureReason>,
+    /// The operation that wrote the entry (see [`crate::operation`]);
+    /// `None` for entries from logs that predate it. Not hashed, so not
+    /// authenticated.
+    pub operation_id: Option<[u8; 16]>,
+    /// The caller's correlation ID for that operation, if it gave one. Not
+    /// hashed, so not authenticated.
+    pub correlation_id: Option<String>,
+    /// [`key_id`](crate::cert::key_id) of the key the operation used: the
+    /// recipient key sealed to, refused or pinned, or the key generated.
+    /// `None` for other entries and logs that predate it. Not hashed, so
+    /// not authenticated.
+    pub key_id: Option<[u8; 32]>,
+}
+
+impl AuditEntry {
+    /// Text form used by the flat-file log:
+    /// `prev|curr|counter|timestamp_ms|op|outcome|seq|clock_regressed(0/1)|fips(0/1)[|role[|reason[|operation|correlation[|key_id]]]]`,
+    /// the role only when there is one or a field follows, the reason only
+    /// on failure entries or when the IDs follow, the operation (hex) and
+    /// correlation IDs when there are any or a key ID follows, and the key
+    /// ID (hex) when there is one; each is empty if absent.
+    pub fn to_line(&self) -> String {
+        let role = self.role.as_deref().unwrap_or_default();
+        let key_id = self.key_id.map(|id| format!("|{}", hex::encode(id))).unwrap_or_default();
+        let role = match self.reason {
+            _ if self.operation_id.is_some() || self.correlation_id.is_some() || self.key_id.is_some() => format!(
+                "|{}|{}|{}|{}{}", role, self.reason.map_or("", FailureReason::as_str),
+                self.operation_id.map(hex::encode).unwrap_or_default(), self.correlation_id.as_deref().unwrap_or_default(), key_id,
+            ),
+            Some(reason) => format!("|{}|{}", role, reason.as_str()),
+            None if self.role.is_some() => format!("|{}", role),
+            None => String::new(),
+        };
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}{}\n",
+            hex::encode(self.prev), hex::encode(self.curr), self.counter, self.timestamp_ms,
+            self.op.as_str(), self.outcome.as_str(), self.seq, u8::from(self.clock_regressed), u8::from(self.fips), role,
+        )
+    }
+
+    /// Parses one line of the flat-file format (without the newline). Older
+    /// lines carry a timestamp in seconds and no sequence; those without
+    /// op/outcome fields read as successful encryptions, those without
+    /// the FIPS flag as written outside FIPS mode, those without a role
+    /// as written outside a caller scope, and those without a reason, IDs
+    /// or key ID as giving none.
+    pub fn parse_line(line: &str) -> Option<AuditEntry> {
+        let fields: Vec<&str> = line.split('|').collect();
+        if !matches!(fields.len(), 4 | 6 | 8 | 9 | 10 | 11 | 13 | 14) {
+            return None;
+        }
+        let prev = parse_hash(fields[0])?;
+        let curr = parse_hash(fields[1])?;
+        let counter = fields[2].parse().ok()?;
+        let timestamp: u64 = fields[3].parse().ok()?;
+        let (op, outcome) = match fields.len() {
+            4 => (OpType::Encrypt, Outcome::Success),
+            _ => (OpType::parse(fields[4])?, Outcome::parse(fields[5])?),
+        };
+        let flag = |s: &str| match s { "0" => Some(false), "1" => Some(true), _ => None };
+        let (timestamp_ms, seq, clock_regressed) = match fields.len() {
+            8.. => {
+                (timestamp, fields[6].parse().ok()?, flag(fields[7])?)
+            }
+            _ => (timestamp.checked_mul(1000)?, 0, false),
+        };
+        let fips = match fields.len() {
+            9.. => flag(fields[8])?,
+            _ => false,
+        };
+        let role = match fields.len() {
+            10 => Some(parse_role(fields[9])?),
+            11.. => match fields[9] { "" => None, role => Some(parse_role(role)?) },
+            _ => None,
+        };
+        let reason = match fields.len() {
+            11 => Some(FailureReason::parse(fields[10])?),
+            13.. => match fields[10] { "" => None, reason => Some(FailureReason::parse(reason)?) },
+            _ => None,
+        };
+        let (operation_id, correlation_id) = match fields.len() {
+            13.. => {
+                let operation_id = match fields[11] { "" => None, id => Some(parse_operation_id(id)?) };
+                let correlation_id = match fields[12] { "" => None, id => Some(parse_correlation(id)?) };
+                (operation_id, correlation_id)
+            }
+            _ => (None, None),
+        };
+        let key_id = match fields.len() {
+            14 => Some(parse_hash(fields[13])?),
+            _ => None,
+        };
+        Some(AuditEntry {
+            prev, curr, counter, timestamp_ms, op, outcome, seq, clock_regressed, fips, role, reason, operation_id, correlation_id, key_id,
+        })
+    }
+
+    /// Record body used by the binary log:
+    /// `prev(32) | curr(32) | counter(8) | timestamp_ms(8) | seq(8) | op(1) |
+    /// outcome(1) | flags(1) [| reason(1)] [| operation(16)] [| correlation_len(1) |
+    /// correlation] [| key_id(32)] [| role_len(1) | role]`, flag bit 0
+    /// `clock_regressed`, bit 1 `fips`, and bits 2 to 5 set when the reason
+    /// byte, the operation ID, the correlation ID and the key ID follow;
+    /// [`RECORD_LEN`] bytes without any of them or a role.
+    pub fn to_record(&self) -> Vec<u8> {
+        let mut out = vec![0u8; RECORD_LEN];
+        out[..32].copy_from_slice(&self.prev);
+        out[32..64].copy_from_slice(&self.curr);
+        out[64..72].copy_from_slice(&self.counter.to_be_bytes());
+        out[72..80].copy_from_slice(&self.timestamp_ms.to_be_bytes());
+        out[80..88].copy_from_slice(&self.seq.to_be_bytes());
+        out[88] = self.op.code();
+        out[89] = self.outcome.code();
+        out[90] = u8::from(self.clock_regressed) | u8::from(self.fips) << 1 | u8::from(self.reason.is_some()) << 2
+            | u8::from(self.operation_id.is_some()) << 3 | u8::from(self.correlation_id.is_some()) << 4
+            | u8::from(self.key_id.is_some()) << 5;
+        if let Some(reason) = self.reason {
+            out.push(reason.code());
+        }
+        if let Some(id) = &self.operation_id {
+            out.extend_from_slice(id);
+        }
+        if let Some(id) = &self.correlation_id {
+            out.push(id.len() as u8);
+            out.extend_from_slice(id.as_bytes());
+        }
+        if let Some(id) = &self.key_id {
+            out.extend_from_slice(id);
+        }
+        if let Some(role) = &self.role {
+            out.push(role.len() as u8);
+            out.extend_from_slice(role.as_bytes());
+        }
+        out
+    }
+
+    /// Parses a record body; anything but [`RECORD_LEN`] bytes with known
+    /// codes and flags, followed by the fields the flags announce and
+    /// nothing or exactly one valid role, is rejected.
+    pub fn parse_record(bytes: &[u8]) -> Option<AuditEntry> {
+        let (bytes, mut rest) = bytes.split_first_chunk::<RECORD_LEN>()?;
+        let flags = bytes[90];
+        if flags > 63 {
+            return None;
+        }
+        let reason = match flags & 4 {
+            0 => None,
+            _ => {
+                let (&code, tail) = rest.split_first()?;
+                rest = tail;
+                Some(FailureReason::from_code(code)?)
+            }
+        };
+        let operation_id = match flags & 8 {
+            0 => None,
+            _ => {
+                let (id, tail) = rest.split_first_chunk::<16>()?;
+                rest = tail;
+                Some(*id)
+            }
+        };
+        let correlation_id = match flags & 16 {
+            0 => None,
+            _ => {
+                let (&len, tail) = rest.split_first()?;
+                let (id, tail) = tail.split_at_checked(len as usize)?;
+                rest = tail;
+                Some(parse_correlation(std::str::from_utf8(id).ok()?)?)
+            }
+        };
+        let key_id = match flags & 32 {
+            0 => None,
+            _ => {
+                let (id, tail) = rest.split_first_chunk::<32>()?;
+                rest = tail;
+                Some(*id)
+            }
+        };
+        let role = match rest.split_first() {
+            None => None,
+            Some((&len, role)) if role.len() == len as usize => Some(parse_role(std::str::from_utf8(role).ok()?)?),
+            Some(_) => return None,
+        };
+        let u64_at = |i: usize| u64::from_be_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        Some(AuditEntry {
+            prev: bytes[..32].try_into().expect("32 bytes"),
+            curr: bytes[32..64].try_into().expect("32 bytes"),
+            counter: u64_at(64),
+            timestamp_ms: u64_at(72),
+            seq: u64_at(80),
+            op: OpType::from_code(bytes[88])?,
+            outcome: Outcome::from_code(bytes[89])?,
+            clock_regressed: flags & 1 != 0,
+            fips: flags & 2 != 0,
+            role,
+            reason,
+            operation_id,
+            correlation_id,
+            key_id,
+        })
+    }
+}
+
+/// Length of an [`AuditEntry::to_record`] body.
+pub const RECORD_LEN: usize = 91;
+
+pub(crate) fn parse_role(s: &str) -> Option<String> {
+    crate::rbac::valid_role(s).then(|| s.to_string())
+}
+
+pub(crate) fn parse_correlation(s: &str) -> Option<String> {
+    crate::operation::valid_correlation(s).then(|| s.to_string())
+}
+
+pub(crate) fn parse_operation_id(s: &str) -> Option<[u8; 16]> {
+    let mut out = [0u8; 16];
+    hex::decode_to_slice(s, &mut out).ok()?;
+    Some(out)
+}
+
+fn parse_hash(s: &str) -> Option<[u8; 32]> {
+    let mut out = [0u8; 32];
+    hex::decode_to_slice(s, &mut out).ok()?;
+    Some(out)
+}
+
+/// Chain state a sink found on startup, used to continue the chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// Head of the last valid entry.
+    pub head: [u8; 32],
+    /// Highest counter among the entries checked.
+    pub counter: u64,
+    /// Sequence number of the last valid entry.
+    pub seq: u64,
+    /// Timestamp of the last valid entry, for clock-regression checks.
+    pub timestamp_ms: u64,
+    /// Entries checked from the tail.
+    pub entries_checked: usize,
+    /// Bytes moved out of the log because they failed validation.
+    pub quarantined_bytes: u64,
+    /// Where the quarantined bytes went, if any.
+    pub quarantine_path: Option<String>,
+}
+
+/// Filters for [`AuditSink::query`]. Unset fields match every entry;
+/// ranges are inclusive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditQuery {
+    pub counter_from: Option<u64>,
+    pub counter_to: Option<u64>,
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    /// Label of the engine that recorded the entry (see [`AuditRecord`]).
+    pub engine: Option<String>,
+    /// [`AuditEntry::key_id`]: entries for operations that used this key.
+    pub key_id: Option<[u8; 32]>,
+    pub op: Option<OpType>,
+    pub outcome: Option<Outcome>,
+    pub operation_id: Option<[u8; 16]>,
+    pub correlation_id: Option<String>,
+    /// At most this many entries, in chain order.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Whether `entry`, recorded by `engine`, passes every filter but the
+    /// limit; for sinks that search by scanning.
+    pub fn matches(&self, engine: &str, entry: &AuditEntry) -> bool {
+        self.counter_from.is_none_or(|v| entry.counter >= v)
+            && self.counter_to.is_none_or(|v| entry.counter <= v)
+            && self.since_ms.is_none_or(|v| entry.timestamp_ms >= v)
+            && self.until_ms.is_none_or(|v| entry.timestamp_ms <= v)
+            && self.engine.as_ref().is_none_or(|v| v == engine)
+            && self.key_id.is_none_or(|v| entry.key_id == Some(v))
+            && self.op.is_none_or(|v| entry.op == v)
+            && self.outcome.is_none_or(|v| entry.outcome == v)
+            && self.operation_id.is_none_or(|v| entry.operation_id == Some(v))
+            && self.correlation_id.as_ref().is_none_or(|v| entry.correlation_id.as_ref() == Some(v))
+    }
+}
+
+/// An entry returned by [`AuditSink::query`], with the label of the engine
+/// that recorded it: usually its hex fingerprint, empty for sinks that hold
+/// one engine's entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub engine: String,
+    pub entry: AuditEntry,
+}
+
+/// Payloads at least this large are hashed with BLAKE3's multithreaded mode.
+pub const PARALLEL_HASH_THRESHOLD: usize = 128 * 1024;
+
+/// How the ciphertext is bound into an audit link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CiphertextBinding {
+    /// The link hashes the full ciphertext.
+    #[default]
+    Full,
+    /// The link hashes `BLAKE3(ciphertext)`, computed right after encryption
+    /// on the worker thread, so the chain lock never re-reads large payloads.
+    /// Verifiers must use the same binding.
+    Digest,
+}
+
+/// Chain link hash: BLAKE3(prev || counter || fingerprint || kem_ct || nonce || ct).
+/// Under [`CiphertextBinding::Digest`], `ct` is the ciphertext digest.
+pub fn entry_hash(prev: &[u8; 32], ctr: u64, fingerprint: &[u8; 32], kem_ct: &[u8], nonce: &[u8], ct: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(prev);
+    hasher.update(&ctr.to_be_bytes());
+    hasher.update(fingerprint);
+    hasher.update(kem_ct);
+    hasher.update(nonce);
+    hash_payload(&mut hasher, ct);
+    hasher.finalize().into()
+}
+
+/// Link hash for entries that record no ciphertext (denials, decryptions,
+/// key events). Domain-separated from [`entry_hash`]; `subject` identifies
+/// what the operation acted on (e.g. the KEM ciphertext opened) and may be
+/// empty.
+pub fn event_hash(prev: &[u8; 32], ctr: u64, fingerprint: &[u8; 32], op: OpType, outcome: Outcome, subject: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("titancore audit event v1");
+    hasher.update(prev);
+    hasher.update(&ctr.to_be_bytes());
+    hasher.update(fingerprint);
+    hasher.update(&[op.code(), outcome.code()]);
+    hasher.update(subject);
+    hasher.finalize().into()
+}
+
+/// [`event_hash`] for a failed operation, which also binds the `reason`.
+/// Domain-separated from both other link hashes.
+pub fn failure_hash(prev: &[u8; 32], ctr: u64, fingerprint: &[u8; 32], op: OpType, outcome: Outcome, reason: FailureReason,
+                    subject: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("titancore audit failure v1");
+    hasher.update(prev);
+    hasher.update(&ctr.to_be_bytes());
+    hasher.update(fingerprint);
+    hasher.update(&[op.code(), outcome.code(), reason.code()]);
+    hasher.update(subject);
+    hasher.finalize().into()
+}
+
+pub fn ciphertext_digest(ct: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hash_payload(&mut hasher, ct);
+    hasher.finalize().into()
+}
+
+pub(crate) fn hash_payload(hasher: &mut blake3::Hasher, data: &[u8]) {
+    #[cfg(feature = "parallel")]
+    if data.len() >= PARALLEL_HASH_THRESHOLD {
+        hasher.update_rayon(data);
+        return;
+    }
+    hasher.update(data);
+}
+
+/// Destination for audit entries. The engine only hashes and chains; where the
+/// entries end up (file, memory, host callback) is up to the sink.
+pub trait AuditSink: Send + Sync {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()>;
+
+    /// Records a Merkle root over a batch of entries already appended.
+    fn append_root(&self, _root: &BatchRoot) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// Stores the record a new log starts with, before its first entry.
+    fn append_genesis(&self, _genesis: &SignedGenesis) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// The stored genesis record, for sinks that keep one.
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        Ok(None)
+    }
+
+    /// Records a snapshot of the chain after the entries already appended.
+    fn append_snapshot(&self, _snapshot: &SignedSnapshot) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// Stored snapshots in log order, for sinks that keep them.
+    fn snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        Ok(Vec::new())
+    }
+
+    /// Records that the stored chain diverged from the engine's.
+    fn append_alarm(&self, _alarm: &SignedAlarm) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// The last `n` entries as stored, oldest first, read back after
+    /// making appended entries visible; for sinks that can.
+    fn tail(&self, _n: usize) -> CoreResult<Vec<AuditEntry>> {
+        Err(CoreError::Config("audit sink cannot read back its entries".into()))
+    }
+
+    /// Makes every entry appended so far durable. Sinks that persist
+    /// synchronously have nothing to do.
+    fn flush(&self) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// True if an entry is durable once `append` returns. Sinks that sync
+    /// in batches or write behind a queue return false, and are only
+    /// durable after [`AuditSink::flush`].
+    fn durable(&self) -> bool {
+        true
+    }
+
+    /// Validates previously persisted entries and returns where the chain
+    /// left off. Called once when an engine is constructed over the sink.
+    fn resume(&self) -> CoreResult<Option<Recovery>> {
+        Ok(None)
+    }
+
+    /// Entries matching `query`, for sinks that index what they store.
+    fn query(&self, _query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        Err(CoreError::Config("audit sink does not support queries".into()))
+    }
+}
+
+/// Discards entries; for callers that keep evidence elsewhere.
+pub struct NullSink;
+
+impl AuditSink for NullSink {
+    fn append(&self, _entry: &AuditEntry) -> CoreResult<()> {
+        Ok(())
+    }
+}
+
+/// Buffers entries in memory so the host can drain and persist them.
+#[derive(Default)]
+pub struct MemorySink {
+    entries: Mutex<Vec<AuditEntry>>,
+    genesis: Mutex<Option<SignedGenesis>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn drain(&self) -> Vec<AuditEntry> {
+        std::mem::take(&mut *self.entries.lock())
+    }
+}
+
+impl AuditSink for MemorySink {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()> {
+        self.entries.lock().push(entry.clone());
+        Ok(())
+    }
+
+    fn append_genesis(&self, genesis: &SignedGenesis) -> CoreResult<()> {
+        *self.genesis.lock() = Some(genesis.clone());
+        Ok(())
+    }
+
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        Ok(self.genesis.lock().clone())
+    }
+}
+
+impl<T: AuditSink + ?Sized> AuditSink for std::sync::Arc<T> {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()> {
+        (**self).append(entry)
+    }
+
+    fn append_root(&self, root: &BatchRoot) -> CoreResult<()> {
+        (**self).append_root(root)
+    }
+
+    fn append_genesis(&self, genesis: &SignedGenesis) -> CoreResult<()> {
+        (**self).append_genesis(genesis)
+    }
+
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        (**self).genesis()
+    }
+
+    fn append_snapshot(&self, snapshot: &SignedSnapshot) -> CoreResult<()> {
+        (**self).append_snapshot(snapshot)
+    }
+
+    fn snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        (**self).snapshots()
+    }
+
+    fn append_alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        (**self).append_alarm(alarm)
+    }
+
+    fn tail(&self, n: usize) -> CoreResult<Vec<AuditEntry>> {
+        (**self).tail(n)
+    }
+
+    fn flush(&self) -> CoreResult<()> {
+        (**self).flush()
+    }
+
+    fn durable(&self) -> bool {
+        (**self).durable()
+    }
+
+    fn resume(&self) -> CoreResult<Option<Recovery>> {
+        (**self).resume()
+    }
+
+    fn query(&self, query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        (**self).query(query)
+    }
+}
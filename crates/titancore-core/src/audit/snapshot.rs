@@ -0,0 +1,258 @@
+//! Signed snapshots of the chain, so a long log can be verified from the
+//! last trusted point instead of from its first entry.
+//!
+//! With [`crate::EngineConfig::snapshot_every`] the engine signs a
+//! [`Snapshot`] with its checkpoint key every that many entries: the entry
+//! count (`seq`), the chain head (the hash accumulated over every link so
+//! far) and the Merkle root over the entries since the previous snapshot,
+//! and hands it to [`AuditSink::append_snapshot`](super::AuditSink::append_snapshot).
+//! [`LogVerifier`] walks entries from a trusted snapshot (or the start),
+//! checking links, sequence numbers and every later snapshot's head, root
+//! and signature; the file sink records where each snapshot falls so
+//! [`crate::FileSink::verify_log`] can seek straight to it.
+//!
+//! Layout: `magic(4) | version(1) | fingerprint(32) | first_seq(8) | seq(8) |
+//! counter(8) | head(32) | root(32) | timestamp_ms(8)`, then, as with
+//! checkpoints, `pk_len(2) | public_key | signature`.
+
+use super::merkle;
+use super::AuditEntry;
+use crate::crypto;
+use crate::error::{CoreError, CoreResult};
+
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"TCSN";
+pub const SNAPSHOT_VERSION: u8 = 1;
+const BODY_LEN: usize = 4 + 1 + 32 + 8 + 8 + 8 + 32 + 32 + 8;
+
+/// "After entry `seq` engine `fingerprint` had chain head `head`, and
+/// entries `first_seq..=seq` have Merkle root `root`."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub fingerprint: [u8; 32],
+    pub first_seq: u64,
+    pub seq: u64,
+    /// Highest counter issued so far.
+    pub counter: u64,
+    pub head: [u8; 32],
+    pub root: [u8; 32],
+    pub timestamp_ms: u64,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(BODY_LEN);
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.push(SNAPSHOT_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.first_seq.to_be_bytes());
+        out.extend_from_slice(&self.seq.to_be_bytes());
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.head);
+        out.extend_from_slice(&self.root);
+        out.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        if bytes.len() != BODY_LEN || &bytes[..4] != SNAPSHOT_MAGIC {
+            return Err(CoreError::Format("bad snapshot"));
+        }
+        if bytes[4] != SNAPSHOT_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let arr32 = |i: usize| -> [u8; 32] { bytes[i..i + 32].try_into().expect("32 bytes") };
+        let u64_at = |i: usize| u64::from_be_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        Ok(Snapshot {
+            fingerprint: arr32(5),
+            first_seq: u64_at(37),
+            seq: u64_at(45),
+            counter: u64_at(53),
+            head: arr32(61),
+            root: arr32(93),
+            timestamp_ms: u64_at(125),
+        })
+    }
+
+    pub fn sign(self, public_key: &[u8], secret_key: &[u8]) -> CoreResult<SignedSnapshot> {
+        let signature = crypto::sign(secret_key, &self.to_bytes())?;
+        Ok(SignedSnapshot { snapshot: self, public_key: public_key.to_vec(), signature })
+    }
+}
+
+/// A [`Snapshot`] with a Dilithium5 signature and the signer's public key.
+/// The embedded key is informational: verify against a key you trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedSnapshot {
+    pub snapshot: Snapshot,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedSnapshot {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.snapshot.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        if bytes.len() < BODY_LEN + 2 {
+            return Err(CoreError::Format("truncated snapshot"));
+        }
+        let snapshot = Snapshot::from_bytes(&bytes[..BODY_LEN])?;
+        let pk_len = u16::from_be_bytes([bytes[BODY_LEN], bytes[BODY_LEN + 1]]) as usize;
+        let rest = &bytes[BODY_LEN + 2..];
+        if rest.len() <= pk_len {
+            return Err(CoreError::Format("truncated snapshot"));
+        }
+        Ok(SignedSnapshot { snapshot, public_key: rest[..pk_len].to_vec(), signature: rest[pk_len..].to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.snapshot.to_bytes(), &self.signature)
+    }
+}
+
+/// Leaves since the last snapshot, kept by the engine.
+pub(crate) struct SnapshotBatcher {
+    every: u64,
+    first_seq: u64,
+    leaves: Vec<[u8; 32]>,
+}
+
+impl SnapshotBatcher {
+    pub(crate) fn new(every: u64) -> Self {
+        SnapshotBatcher { every: every.max(1), first_seq: 0, leaves: Vec::new() }
+    }
+
+    pub(crate) fn every(&self) -> u64 {
+        self.every
+    }
+
+    /// Adds `entry`; once `every` entries are in, returns the first seq and
+    /// root for a snapshot after it and starts over.
+    pub(crate) fn push(&mut self, entry: &AuditEntry) -> Option<(u64, [u8; 32])> {
+        if self.leaves.is_empty() {
+            self.first_seq = entry.seq;
+        }
+        self.leaves.push(merkle::leaf_hash(entry.counter, &entry.curr));
+        if (self.leaves.len() as u64) < self.every {
+            return None;
+        }
+        let root = merkle::root(&self.leaves);
+        self.leaves.clear();
+        Some((self.first_seq, root))
+    }
+}
+
+/// What [`LogVerifier::finish`] found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogVerification {
+    pub entries_checked: u64,
+    pub snapshots_checked: usize,
+    /// Head and seq after the last entry.
+    pub head: [u8; 32],
+    pub seq: u64,
+    /// The last snapshot checked (or the one verification started from),
+    /// to start from next time.
+    pub latest: Option<SignedSnapshot>,
+}
+
+/// Checks entries in log order: each links to the one before, sequence
+/// numbers increase by one, and every snapshot after the starting point is
+/// signed by the trusted key and matches the head and the Merkle root of
+/// the entries it covers. Failures are [`CoreError::Storage`] naming the
+/// seq where the log stops matching.
+pub struct LogVerifier {
+    trusted_pk: Vec<u8>,
+    pending: std::collections::VecDeque<SignedSnapshot>,
+    leaves: Vec<[u8; 32]>,
+    head: [u8; 32],
+    seq: u64,
+    started: bool,
+    entries_checked: u64,
+    snapshots_checked: usize,
+    latest: Option<SignedSnapshot>,
+}
+
+impl LogVerifier {
+    /// Starts after `from`, which the caller already trusts, or at the
+    /// beginning of the log. `snapshots` are the log's snapshots in order;
+    /// those not after `from` are ignored.
+    pub fn new(trusted_pk: &[u8], from: Option<&SignedSnapshot>, snapshots: Vec<SignedSnapshot>) -> CoreResult<Self> {
+        if let Some(from) = from {
+            if !from.verify(trusted_pk) {
+                return Err(CoreError::Storage("starting snapshot has a bad signature".into()));
+            }
+        }
+        let start = from.map_or(0, |s| s.snapshot.seq);
+        Ok(LogVerifier {
+            trusted_pk: trusted_pk.to_vec(),
+            pending: snapshots.into_iter().filter(|s| s.snapshot.first_seq > start).collect(),
+            leaves: Vec::new(),
+            head: from.map_or([0u8; 32], |s| s.snapshot.head),
+            seq: start,
+            started: from.is_some(),
+            entries_checked: 0,
+            snapshots_checked: 0,
+            latest: from.cloned(),
+        })
+    }
+
+    pub fn push(&mut self, entry: &AuditEntry) -> CoreResult<()> {
+        let broken = |what: &str| CoreError::Storage(format!("audit log {} at seq {}", what, entry.seq));
+        // As in recovery, a zero `prev` at the start (or in logs written
+        // before chains resumed across restarts) begins a new segment.
+        let linked = entry.prev == self.head || (entry.prev == [0u8; 32] && (!self.started || entry.seq == 0));
+        if !linked {
+            return Err(broken("chain broken"));
+        }
+        if entry.seq != 0 && entry.seq != self.seq + 1 {
+            return Err(broken("sequence gap"));
+        }
+        self.started = true;
+        self.head = entry.curr;
+        self.seq = entry.seq;
+        self.entries_checked += 1;
+
+        let Some(next) = self.pending.front() else { return Ok(()) };
+        if entry.seq < next.snapshot.first_seq {
+            return Ok(());
+        }
+        self.leaves.push(merkle::leaf_hash(entry.counter, &entry.curr));
+        if entry.seq < next.snapshot.seq {
+            return Ok(());
+        }
+        let next = self.pending.pop_front().expect("front checked above");
+        let s = &next.snapshot;
+        if !next.verify(&self.trusted_pk) {
+            return Err(CoreError::Storage(format!("snapshot at seq {} has a bad signature", s.seq)));
+        }
+        if s.head != self.head || s.root != merkle::root(&self.leaves) {
+            return Err(CoreError::Storage(format!("snapshot at seq {} does not match the log", s.seq)));
+        }
+        self.leaves.clear();
+        self.snapshots_checked += 1;
+        self.latest = Some(next);
+        Ok(())
+    }
+
+    /// Fails if a snapshot lies beyond the last entry, i.e. the log was
+    /// cut short after it was signed.
+    pub fn finish(self) -> CoreResult<LogVerification> {
+        if let Some(missing) = self.pending.front() {
+            return Err(CoreError::Storage(format!("audit log ends before the snapshot at seq {}", missing.snapshot.seq)));
+        }
+        Ok(LogVerification {
+            entries_checked: self.entries_checked,
+            snapshots_checked: self.snapshots_checked,
+            head: self.head,
+            seq: self.seq,
+            latest: self.latest,
+        })
+    }
+}
@@ -0,0 +1,355 @@
+use super::{AuditEntry, AuditQuery, AuditRecord, AuditSink, BatchRoot, FailureReason, OpType, Outcome, Recovery, SignedAlarm, SignedGenesis, SignedSnapshot, DEFAULT_RECOVERY_TAIL};
+use crate::error::{CoreError, CoreResult};
+use parking_lot::Mutex;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+
+// Tables only; indexes follow once older databases are migrated.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS audit_entries (
+    id INTEGER PRIMARY KEY,
+    engine TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    counter INTEGER NOT NULL,
+    timestamp_ms INTEGER NOT NULL,
+    op TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    prev TEXT NOT NULL,
+    curr TEXT NOT NULL,
+    clock_regressed INTEGER NOT NULL,
+    fips INTEGER NOT NULL DEFAULT 0,
+    role TEXT,
+    reason TEXT,
+    operation_id TEXT,
+    correlation_id TEXT,
+    key_id TEXT
+);
+CREATE TABLE IF NOT EXISTS audit_roots (
+    id INTEGER PRIMARY KEY,
+    engine TEXT NOT NULL DEFAULT '',
+    first_counter INTEGER NOT NULL,
+    last_counter INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    root TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS audit_genesis (
+    engine TEXT PRIMARY KEY,
+    record BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS audit_snapshots (
+    id INTEGER PRIMARY KEY,
+    engine TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    record BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS audit_alarms (
+    id INTEGER PRIMARY KEY,
+    engine TEXT NOT NULL,
+    timestamp_ms INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    record BLOB NOT NULL
+);
+";
+
+const INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS audit_entries_engine ON audit_entries (engine);
+CREATE INDEX IF NOT EXISTS audit_entries_counter ON audit_entries (counter);
+CREATE INDEX IF NOT EXISTS audit_entries_timestamp ON audit_entries (timestamp_ms);
+CREATE INDEX IF NOT EXISTS audit_entries_key_id ON audit_entries (key_id);
+CREATE INDEX IF NOT EXISTS audit_entries_op ON audit_entries (op);
+CREATE INDEX IF NOT EXISTS audit_entries_correlation_id ON audit_entries (correlation_id);
+CREATE INDEX IF NOT EXISTS audit_roots_engine ON audit_roots (engine, last_counter);
+CREATE INDEX IF NOT EXISTS audit_snapshots_engine ON audit_snapshots (engine, seq);
+";
+
+/// Audit entries in an SQLite database, one row per entry with indexed
+/// `engine`, `counter`, `timestamp_ms`, `key_id` and `op` columns, so
+/// [`AuditSink::query`] can search a long history without a full scan.
+/// Hashes and key IDs are stored as hex, ops and outcomes by name, so the
+/// tables read the same as the flat-file log.
+///
+/// Every entry is committed (WAL, `synchronous=FULL`) before `append`
+/// returns. Several engines may share one database; the `engine` label
+/// tells their entries, roots, snapshots and alarms apart.
+pub struct SqliteSink {
+    conn: Mutex<Connection>,
+    engine: String,
+    recovery_tail: usize,
+}
+
+fn db_err(e: rusqlite::Error) -> CoreError {
+    CoreError::Storage(format!("Storage error: {}", e))
+}
+
+/// SQLite integers are signed; larger values are refused rather than
+/// stored negative.
+fn sql_int(v: u64) -> CoreResult<i64> {
+    i64::try_from(v).map_err(|_| CoreError::Storage(format!("{} does not fit an SQLite integer", v)))
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> CoreResult<bool> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?1", table), [column], |row| row.get(0))
+        .map_err(db_err)
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database at `path`; entries are labelled
+    /// `engine`, usually the hex engine fingerprint.
+    pub fn open(path: impl AsRef<std::path::Path>, engine: impl Into<String>) -> CoreResult<Self> {
+        let conn = Connection::open(path).map_err(db_err)?;
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(db_err)?;
+        conn.pragma_update(None, "synchronous", "FULL").map_err(db_err)?;
+        conn.execute_batch(SCHEMA).map_err(db_err)?;
+        // Databases from before key IDs were recorded call the engine label
+        // `key_id`, and index it under that name.
+        for table in ["audit_entries", "audit_genesis", "audit_snapshots", "audit_alarms"] {
+            if !has_column(&conn, table, "engine")? {
+                conn.execute_batch(&format!(
+                    "DROP INDEX IF EXISTS {table}_key_id; ALTER TABLE {table} RENAME COLUMN key_id TO engine;"
+                )).map_err(db_err)?;
+            }
+        }
+        // Those from before the FIPS flag, the caller role, the failure
+        // reason, the operation IDs, the key ID and per-engine roots lack
+        // their columns.
+        let columns = [
+            ("audit_entries", "fips", "fips INTEGER NOT NULL DEFAULT 0"), ("audit_entries", "role", "role TEXT"),
+            ("audit_entries", "reason", "reason TEXT"), ("audit_entries", "operation_id", "operation_id TEXT"),
+            ("audit_entries", "correlation_id", "correlation_id TEXT"), ("audit_entries", "key_id", "key_id TEXT"),
+            ("audit_roots", "engine", "engine TEXT NOT NULL DEFAULT ''"),
+        ];
+        for (table, column, decl) in columns {
+            if !has_column(&conn, table, column)? {
+                conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {}", table, decl)).map_err(db_err)?;
+            }
+        }
+        conn.execute_batch(INDEXES).map_err(db_err)?;
+        Ok(SqliteSink { conn: Mutex::new(conn), engine: engine.into(), recovery_tail: DEFAULT_RECOVERY_TAIL })
+    }
+
+    /// Number of this engine's latest entries whose links
+    /// [`AuditSink::resume`] checks on startup.
+    pub fn with_recovery_tail(mut self, entries: usize) -> Self {
+        self.recovery_tail = entries.max(1);
+        self
+    }
+
+    pub fn engine(&self) -> &str {
+        &self.engine
+    }
+}
+
+fn read_record(row: &rusqlite::Row<'_>) -> rusqlite::Result<CoreResult<AuditRecord>> {
+    let engine: String = row.get(0)?;
+    let seq: i64 = row.get(1)?;
+    let counter: i64 = row.get(2)?;
+    let timestamp_ms: i64 = row.get(3)?;
+    let op: String = row.get(4)?;
+    let outcome: String = row.get(5)?;
+    let prev: String = row.get(6)?;
+    let curr: String = row.get(7)?;
+    let clock_regressed: bool = row.get(8)?;
+    let fips: bool = row.get(9)?;
+    let role: Option<String> = row.get(10)?;
+    let reason: Option<String> = row.get(11)?;
+    let reason = match reason {
+        Some(reason) => FailureReason::parse(&reason).map(Some),
+        None => Some(None),
+    };
+    let operation_id: Option<String> = row.get(12)?;
+    let operation_id = match operation_id {
+        Some(id) => super::parse_operation_id(&id).map(Some),
+        None => Some(None),
+    };
+    let correlation_id: Option<String> = row.get(13)?;
+    let key_id: Option<String> = row.get(14)?;
+    let key_id = match key_id {
+        Some(id) => super::parse_hash(&id).map(Some),
+        None => Some(None),
+    };
+    let fields = (super::parse_hash(&prev), super::parse_hash(&curr), OpType::parse(&op), Outcome::parse(&outcome), reason, operation_id, key_id);
+    let (Some(prev), Some(curr), Some(op), Some(outcome), Some(reason), Some(operation_id), Some(key_id)) = fields else {
+        return Ok(Err(malformed()));
+    };
+    let (Ok(counter), Ok(timestamp_ms), Ok(seq)) = (u64::try_from(counter), u64::try_from(timestamp_ms), u64::try_from(seq)) else {
+        return Ok(Err(malformed()));
+    };
+    let entry = AuditEntry {
+        prev, curr, counter, timestamp_ms, op, outcome, seq, clock_regressed, fips, role, reason, operation_id, correlation_id, key_id,
+    };
+    Ok(Ok(AuditRecord { engine, entry }))
+}
+
+fn malformed() -> CoreError {
+    CoreError::Storage("malformed audit database row".into())
+}
+
+const COLUMNS: &str = "engine, seq, counter, timestamp_ms, op, outcome, prev, curr, clock_regressed, fips, role, reason, operation_id, correlation_id, \
+                       key_id";
+
+impl AuditSink for SqliteSink {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()> {
+        self.conn.lock().execute(
+            &format!("INSERT INTO audit_entries ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)", COLUMNS),
+            params![
+                self.engine, sql_int(entry.seq)?, sql_int(entry.counter)?, sql_int(entry.timestamp_ms)?, entry.op.as_str(),
+                entry.outcome.as_str(), hex::encode(entry.prev), hex::encode(entry.curr), entry.clock_regressed,
+                entry.fips, entry.role, entry.reason.map(FailureReason::as_str), entry.operation_id.map(hex::encode),
+                entry.correlation_id, entry.key_id.map(hex::encode),
+            ],
+        ).map_err(db_err)?;
+        Ok(())
+    }
+
+    fn append_root(&self, root: &BatchRoot) -> CoreResult<()> {
+        self.conn.lock().execute(
+            "INSERT INTO audit_roots (engine, first_counter, last_counter, size, root) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![self.engine, sql_int(root.first_counter)?, sql_int(root.last_counter)?, sql_int(root.size)?, hex::encode(root.root)],
+        ).map_err(db_err)?;
+        Ok(())
+    }
+
+    /// One record per engine label; a new one replaces it.
+    fn append_genesis(&self, genesis: &SignedGenesis) -> CoreResult<()> {
+        self.conn.lock().execute(
+            "INSERT OR REPLACE INTO audit_genesis (engine, record) VALUES (?1, ?2)",
+            params![self.engine, genesis.to_bytes()],
+        ).map_err(db_err)?;
+        Ok(())
+    }
+
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        let record: Option<Vec<u8>> = self.conn.lock()
+            .query_row("SELECT record FROM audit_genesis WHERE engine = ?1", [&self.engine], |row| row.get(0))
+            .optional().map_err(db_err)?;
+        record.map(|bytes| SignedGenesis::from_bytes(&bytes)).transpose()
+    }
+
+    fn append_snapshot(&self, snapshot: &SignedSnapshot) -> CoreResult<()> {
+        self.conn.lock().execute(
+            "INSERT INTO audit_snapshots (engine, seq, record) VALUES (?1, ?2, ?3)",
+            params![self.engine, sql_int(snapshot.snapshot.seq)?, snapshot.to_bytes()],
+        ).map_err(db_err)?;
+        Ok(())
+    }
+
+    fn snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare("SELECT record FROM audit_snapshots WHERE engine = ?1 ORDER BY id").map_err(db_err)?;
+        let records = stmt.query_map([&self.engine], |row| row.get::<_, Vec<u8>>(0)).map_err(db_err)?;
+        let mut out = Vec::new();
+        for record in records {
+            out.push(SignedSnapshot::from_bytes(&record.map_err(db_err)?)?);
+        }
+        Ok(out)
+    }
+
+    fn append_alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        self.conn.lock().execute(
+            "INSERT INTO audit_alarms (engine, timestamp_ms, reason, record) VALUES (?1, ?2, ?3, ?4)",
+            params![self.engine, sql_int(alarm.alarm.timestamp_ms)?, alarm.alarm.reason.as_str(), alarm.to_bytes()],
+        ).map_err(db_err)?;
+        Ok(())
+    }
+
+    fn tail(&self, n: usize) -> CoreResult<Vec<AuditEntry>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare(&format!("SELECT {} FROM audit_entries WHERE engine = ?1 ORDER BY id DESC LIMIT ?2", COLUMNS))
+            .map_err(db_err)?;
+        let rows = stmt.query_map(params![self.engine, sql_int(n as u64)?], read_record).map_err(db_err)?;
+        let mut tail = Vec::new();
+        for row in rows {
+            tail.push(row.map_err(db_err)??.entry);
+        }
+        tail.reverse();
+        Ok(tail)
+    }
+
+    /// Resumes from this engine's latest entry after checking that the last
+    /// `recovery_tail` entries link up. Rows are committed whole, so unlike
+    /// the flat file there is nothing torn to quarantine; a broken link
+    /// means the database was edited and is an error.
+    fn resume(&self) -> CoreResult<Option<Recovery>> {
+        let conn = self.conn.lock();
+        let max_counter: Option<i64> = conn
+            .query_row("SELECT MAX(counter) FROM audit_entries WHERE engine = ?1", [&self.engine], |row| row.get(0))
+            .optional().map_err(db_err)?.flatten();
+        let Some(max_counter) = max_counter else { return Ok(None) };
+        let mut stmt = conn
+            .prepare(&format!("SELECT {} FROM audit_entries WHERE engine = ?1 ORDER BY id DESC LIMIT ?2", COLUMNS))
+            .map_err(db_err)?;
+        let rows = stmt.query_map(params![self.engine, sql_int(self.recovery_tail as u64)?], read_record).map_err(db_err)?;
+        let mut tail = Vec::new();
+        for row in rows {
+            tail.push(row.map_err(db_err)??.entry);
+        }
+        tail.reverse();
+        if let Some(broken) = tail.windows(2).find(|w| w[1].prev != [0u8; 32] && w[1].prev != w[0].curr) {
+            return Err(CoreError::Storage(format!("audit database chain broken at seq {}", broken[1].seq)));
+        }
+        let last = tail.last().expect("an engine with a max counter has entries");
+        Ok(Some(Recovery {
+            head: last.curr,
+            counter: u64::try_from(max_counter).map_err(|_| malformed())?,
+            seq: last.seq,
+            timestamp_ms: last.timestamp_ms,
+            entries_checked: tail.len(),
+            ..Default::default()
+        }))
+    }
+
+    fn query(&self, query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        let mut clauses = Vec::new();
+        let mut values = Vec::new();
+        let mut filter = |clause: &str, value: Value| {
+            values.push(value);
+            clauses.push(format!("{} ?{}", clause, values.len()));
+        };
+        // Nothing stored exceeds i64::MAX, so larger bounds clamp to it.
+        let int = |v: u64| Value::Integer(i64::try_from(v).unwrap_or(i64::MAX));
+        if let Some(v) = query.counter_from {
+            filter("counter >=", int(v));
+        }
+        if let Some(v) = query.counter_to {
+            filter("counter <=", int(v));
+        }
+        if let Some(v) = query.since_ms {
+            filter("timestamp_ms >=", int(v));
+        }
+        if let Some(v) = query.until_ms {
+            filter("timestamp_ms <=", int(v));
+        }
+        if let Some(v) = &query.engine {
+            filter("engine =", Value::Text(v.clone()));
+        }
+        if let Some(v) = query.key_id {
+            filter("key_id =", Value::Text(hex::encode(v)));
+        }
+        if let Some(v) = query.op {
+            filter("op =", Value::Text(v.as_str().into()));
+        }
+        if let Some(v) = query.outcome {
+            filter("outcome =", Value::Text(v.as_str().into()));
+        }
+        if let Some(v) = query.operation_id {
+            filter("operation_id =", Value::Text(hex::encode(v)));
+        }
+        if let Some(v) = &query.correlation_id {
+            filter("correlation_id =", Value::Text(v.clone()));
+        }
+        let mut sql = format!("SELECT {} FROM audit_entries", COLUMNS);
+        if !clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY id");
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&sql).map_err(db_err)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), read_record).map_err(db_err)?;
+        rows.map(|row| row.map_err(db_err)?).collect()
+    }
+}
@@ -0,0 +1,244 @@
+use super::{AuditEntry, AuditQuery, AuditRecord, AuditSink, BatchRoot, Outcome, Recovery, SignedAlarm, SignedGenesis, SignedSnapshot};
+use crate::error::{CoreError, CoreResult};
+use crate::time::rfc3339_millis;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Facility 13, "log audit" (RFC 5424 section 6.2.1).
+pub const DEFAULT_FACILITY: u8 = 13;
+/// Structured-data id carrying the entry fields. 32473 is the private
+/// enterprise number RFC 5612 sets aside for documentation.
+pub const SD_ID: &str = "titancore@32473";
+#[cfg(unix)]
+pub const DEV_LOG: &str = "/dev/log";
+#[cfg(unix)]
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Where a [`SyslogSink`] sends its copies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTarget {
+    /// RFC 5424 over UDP (RFC 5426) to `host:port`.
+    Udp(String),
+    /// RFC 5424 datagrams to a local socket such as [`DEV_LOG`].
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    /// The systemd journal's native protocol, one field per entry column.
+    #[cfg(unix)]
+    Journald,
+}
+
+impl SyslogTarget {
+    /// `udp://host:port`, `unix:///path`, `syslog` (the local
+    /// [`DEV_LOG`]) or `journald`.
+    pub fn parse(s: &str) -> Option<SyslogTarget> {
+        if let Some(addr) = s.strip_prefix("udp://") {
+            return Some(SyslogTarget::Udp(addr.to_string()));
+        }
+        #[cfg(unix)]
+        {
+            if let Some(path) = s.strip_prefix("unix://") {
+                return Some(SyslogTarget::Unix(path.into()));
+            }
+            match s {
+                "syslog" => return Some(SyslogTarget::Unix(DEV_LOG.into())),
+                "journald" => return Some(SyslogTarget::Journald),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(UnixDatagram, std::path::PathBuf),
+}
+
+/// Mirrors every entry the inner sink accepts to syslog or journald, so a
+/// SIEM sees the evidence stream as it is written. The inner sink stays the
+/// record: it is written first, everything else (roots, flush, recovery,
+/// queries) goes to it alone, and a copy that cannot be sent is counted in
+/// [`SyslogSink::failed`] rather than failing the operation.
+pub struct SyslogSink {
+    inner: Box<dyn AuditSink>,
+    target: SyslogTarget,
+    transport: Transport,
+    app_name: String,
+    hostname: String,
+    facility: u8,
+    forwarded: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl SyslogSink {
+    pub fn new(inner: Box<dyn AuditSink>, target: SyslogTarget) -> CoreResult<Self> {
+        let transport = match &target {
+            SyslogTarget::Udp(addr) => {
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                socket.connect(addr.as_str())?;
+                Transport::Udp(socket)
+            }
+            #[cfg(unix)]
+            SyslogTarget::Unix(path) => Transport::Unix(UnixDatagram::unbound()?, path.clone()),
+            #[cfg(unix)]
+            SyslogTarget::Journald => Transport::Unix(UnixDatagram::unbound()?, JOURNALD_SOCKET.into()),
+        };
+        Ok(SyslogSink {
+            inner,
+            target,
+            transport,
+            app_name: "titancore".into(),
+            hostname: "-".into(),
+            facility: DEFAULT_FACILITY,
+            forwarded: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        })
+    }
+
+    /// APP-NAME, or `SYSLOG_IDENTIFIER` for journald. Default `titancore`.
+    pub fn with_app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = app_name.into();
+        self
+    }
+
+    /// HOSTNAME field; the default `-` lets the collector fill it in.
+    pub fn with_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    pub fn with_facility(mut self, facility: u8) -> CoreResult<Self> {
+        if facility > 23 {
+            return Err(CoreError::Config(format!("syslog facility out of range: {}", facility)));
+        }
+        self.facility = facility;
+        Ok(self)
+    }
+
+    pub fn target(&self) -> &SyslogTarget {
+        &self.target
+    }
+
+    /// Copies sent so far.
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.load(Ordering::Relaxed)
+    }
+
+    /// Copies that could not be sent.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// RFC 5424 message for `entry`, with the entry in structured data.
+    pub fn format_rfc5424(&self, entry: &AuditEntry) -> String {
+        format!(
+            "<{}>1 {} {} {} {} audit [{} counter=\"{}\" seq=\"{}\" op=\"{}\" outcome=\"{}\" prev=\"{}\" curr=\"{}\" clock_regressed=\"{}\" fips=\"{}\"{}{}{}{}{}] {} {}",
+            u16::from(self.facility) * 8 + u16::from(severity(entry.outcome)), rfc3339_millis(entry.timestamp_ms),
+            self.hostname, self.app_name, std::process::id(), SD_ID, entry.counter, entry.seq, entry.op.as_str(),
+            entry.outcome.as_str(), hex::encode(entry.prev), hex::encode(entry.curr), u8::from(entry.clock_regressed),
+            u8::from(entry.fips), entry.role.as_deref().map(|r| format!(" role=\"{}\"", r)).unwrap_or_default(),
+            entry.reason.map(|r| format!(" reason=\"{}\"", r.as_str())).unwrap_or_default(),
+            entry.operation_id.map(|id| format!(" operation_id=\"{}\"", hex::encode(id))).unwrap_or_default(),
+            entry.correlation_id.as_deref().map(|id| format!(" correlation_id=\"{}\"", id)).unwrap_or_default(),
+            entry.key_id.map(|id| format!(" key_id=\"{}\"", hex::encode(id))).unwrap_or_default(),
+            entry.op.as_str(), entry.outcome.as_str(),
+        )
+    }
+
+    /// Native journal protocol datagram for `entry`.
+    pub fn format_journald(&self, entry: &AuditEntry) -> String {
+        format!(
+            "MESSAGE=titancore audit {} {}\nPRIORITY={}\nSYSLOG_FACILITY={}\nSYSLOG_IDENTIFIER={}\n\
+             TITANCORE_COUNTER={}\nTITANCORE_SEQ={}\nTITANCORE_TIMESTAMP_MS={}\nTITANCORE_OP={}\nTITANCORE_OUTCOME={}\n\
+             TITANCORE_PREV={}\nTITANCORE_CURR={}\nTITANCORE_CLOCK_REGRESSED={}\nTITANCORE_FIPS={}\n{}{}{}{}{}",
+            entry.op.as_str(), entry.outcome.as_str(), severity(entry.outcome), self.facility, self.app_name,
+            entry.counter, entry.seq, entry.timestamp_ms, entry.op.as_str(), entry.outcome.as_str(),
+            hex::encode(entry.prev), hex::encode(entry.curr), u8::from(entry.clock_regressed), u8::from(entry.fips),
+            entry.role.as_deref().map(|r| format!("TITANCORE_ROLE={}\n", r)).unwrap_or_default(),
+            entry.reason.map(|r| format!("TITANCORE_REASON={}\n", r.as_str())).unwrap_or_default(),
+            entry.operation_id.map(|id| format!("TITANCORE_OPERATION_ID={}\n", hex::encode(id))).unwrap_or_default(),
+            entry.correlation_id.as_deref().map(|id| format!("TITANCORE_CORRELATION_ID={}\n", id)).unwrap_or_default(),
+            entry.key_id.map(|id| format!("TITANCORE_KEY_ID={}\n", hex::encode(id))).unwrap_or_default(),
+        )
+    }
+
+    fn forward(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let message = match self.target {
+            #[cfg(unix)]
+            SyslogTarget::Journald => self.format_journald(entry),
+            _ => self.format_rfc5424(entry),
+        };
+        match &self.transport {
+            Transport::Udp(socket) => socket.send(message.as_bytes())?,
+            #[cfg(unix)]
+            Transport::Unix(socket, path) => socket.send_to(message.as_bytes(), path)?,
+        };
+        Ok(())
+    }
+}
+
+// Informational for successes, warning for anything refused or failed.
+fn severity(outcome: Outcome) -> u8 {
+    match outcome {
+        Outcome::Success => 6,
+        _ => 4,
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn append(&self, entry: &AuditEntry) -> CoreResult<()> {
+        self.inner.append(entry)?;
+        match self.forward(entry) {
+            Ok(()) => self.forwarded.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.failed.fetch_add(1, Ordering::Relaxed),
+        };
+        Ok(())
+    }
+
+    fn append_root(&self, root: &BatchRoot) -> CoreResult<()> {
+        self.inner.append_root(root)
+    }
+
+    fn append_genesis(&self, genesis: &SignedGenesis) -> CoreResult<()> {
+        self.inner.append_genesis(genesis)
+    }
+
+    fn genesis(&self) -> CoreResult<Option<SignedGenesis>> {
+        self.inner.genesis()
+    }
+
+    fn append_snapshot(&self, snapshot: &SignedSnapshot) -> CoreResult<()> {
+        self.inner.append_snapshot(snapshot)
+    }
+
+    fn snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        self.inner.snapshots()
+    }
+
+    fn append_alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        self.inner.append_alarm(alarm)
+    }
+
+    fn tail(&self, n: usize) -> CoreResult<Vec<AuditEntry>> {
+        self.inner.tail(n)
+    }
+
+    fn flush(&self) -> CoreResult<()> {
+        self.inner.flush()
+    }
+
+    fn durable(&self) -> bool {
+        self.inner.durable()
+    }
+
+    fn resume(&self) -> CoreResult<Option<Recovery>> {
+        self.inner.resume()
+    }
+
+    fn query(&self, query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        self.inner.query(query)
+    }
+}
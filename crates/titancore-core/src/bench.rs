@@ -0,0 +1,157 @@
+//! Throughput and latency of the engine's building blocks on this machine.
+//!
+//! [`Engine::benchmark`] times Kyber-1024 encapsulation and decapsulation,
+//! session-key derivation under the engine's KDF, sealing and opening of
+//! one payload under the engine's AEAD suite, and audit writes (chain link under the
+//! engine's ciphertext binding, then the append) to a scratch sink, for
+//! sizing deployments. Nothing is recorded in the engine's own audit chain
+//! and no request counts against the rate limit. [`BenchReport::aes_hardware`]
+//! shows whether the CPU offers the AES instructions the AEAD uses.
+
+use crate::audit::{self, AuditEntry, AuditSink, CiphertextBinding, OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::suite::Suite;
+use crate::error::{CoreError, CoreResult};
+use crate::time::Instant;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use std::time::Duration;
+
+/// Latencies kept per measurement for the percentiles.
+const MAX_SAMPLES: usize = 100_000;
+
+/// One measurement. Latencies are in microseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub ops: u64,
+    pub ops_per_sec: f64,
+    /// Payload bytes per second, for the AEAD and audit writes; zero for
+    /// the others.
+    pub bytes_per_sec: f64,
+    pub mean_us: f64,
+    pub p50_us: f64,
+    pub p99_us: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub payload_size: usize,
+    pub suite: Suite,
+    /// AES-NI and carry-less multiply (x86) or the ARMv8 AES extension are
+    /// available, so the AES-GCM-SIV suites run in hardware.
+    pub aes_hardware: bool,
+    pub results: Vec<BenchResult>,
+}
+
+impl Engine {
+    /// Runs every measurement for about `duration` split evenly between
+    /// them, sealing payloads of `payload_size` bytes and writing audit
+    /// entries to `scratch` (not the engine's sink).
+    pub fn benchmark(&self, payload_size: usize, duration: Duration, scratch: &dyn AuditSink) -> CoreResult<BenchReport> {
+        if payload_size as u64 > crate::engine::MAX_MESSAGE_LEN {
+            return Err(CoreError::Config("payload exceeds the AEAD message limit".into()));
+        }
+        let each = duration / 6;
+        let (pk, sk) = kyber1024::keypair();
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let kdf = self.kdf.for_message(shared_secret.as_bytes(), kem_ct.as_bytes());
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, 1, &kdf, &[])?;
+        let mut payload = vec![0u8; payload_size];
+        entropy::fill(&mut payload)?;
+        let nonce = self.suite.nonce(1, &key, &payload);
+        let sealed = self.suite.seal(&key, &nonce, &payload)?;
+        let size = payload_size as f64;
+
+        let mut results = vec![
+            measure("kem_encapsulate", each, 0.0, || {
+                crypto::encapsulate(&pk);
+                Ok(())
+            })?,
+            measure("kem_decapsulate", each, 0.0, || {
+                crypto::decapsulate(&kem_ct, &sk);
+                Ok(())
+            })?,
+            measure("kdf", each, 0.0, || crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, 1, &kdf, &[]).map(drop))?,
+            measure("aead_seal", each, size, || self.suite.seal(&key, &nonce, &payload).map(drop))?,
+            measure("aead_open", each, size, || self.suite.open(&key, &nonce, &sealed).map(drop))?,
+        ];
+        let (mut prev, mut ctr) = ([0u8; 32], 0u64);
+        results.push(measure("audit_write", each, size, || {
+            ctr += 1;
+            let digest;
+            let bound = match self.ct_binding {
+                CiphertextBinding::Full => &sealed[..],
+                CiphertextBinding::Digest => {
+                    digest = audit::ciphertext_digest(&sealed);
+                    &digest[..]
+                }
+            };
+            let curr = audit::entry_hash(&prev, ctr, &self.fingerprint, kem_ct.as_bytes(), &nonce, bound);
+            let entry = AuditEntry {
+                prev,
+                curr,
+                counter: ctr,
+                timestamp_ms: self.clock().now_ms(),
+                op: OpType::Encrypt,
+                outcome: Outcome::Success,
+                seq: ctr,
+                clock_regressed: false,
+                fips: self.fips_mode,
+                role: None,
+                reason: None,
+                operation_id: None,
+                correlation_id: None,
+                key_id: None,
+            };
+            prev = curr;
+            scratch.append(&entry)
+        })?);
+        scratch.flush()?;
+        Ok(BenchReport { payload_size, suite: self.suite, aes_hardware: aes_hardware(), results })
+    }
+}
+
+// Runs `op` repeatedly for `budget` (at least once).
+fn measure(name: &'static str, budget: Duration, bytes_per_op: f64, mut op: impl FnMut() -> CoreResult<()>) -> CoreResult<BenchResult> {
+    let mut samples = Vec::new();
+    let mut ops = 0u64;
+    let start = Instant::now();
+    loop {
+        let t = Instant::now();
+        op()?;
+        let took = t.elapsed();
+        ops += 1;
+        if samples.len() < MAX_SAMPLES {
+            samples.push(took);
+        }
+        if start.elapsed() >= budget {
+            break;
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    samples.sort_unstable();
+    let us = |d: Duration| d.as_secs_f64() * 1e6;
+    let pct = |p: f64| us(samples[((samples.len() - 1) as f64 * p).round() as usize]);
+    let ops_per_sec = ops as f64 / elapsed;
+    Ok(BenchResult {
+        name,
+        ops,
+        ops_per_sec,
+        bytes_per_sec: ops_per_sec * bytes_per_op,
+        mean_us: samples.iter().map(|&d| us(d)).sum::<f64>() / samples.len() as f64,
+        p50_us: pct(0.5),
+        p99_us: pct(0.99),
+    })
+}
+
+fn aes_hardware() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return std::arch::is_x86_feature_detected!("aes") && std::arch::is_x86_feature_detected!("pclmulqdq");
+    #[cfg(target_arch = "aarch64")]
+    return std::arch::is_aarch64_feature_detected!("aes");
+    #[allow(unreachable_code)]
+    false
+}
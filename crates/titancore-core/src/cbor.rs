@@ -0,0 +1,143 @@
+//! Minimal CBOR (RFC 8949) for the COSE encodings: integers, byte and text
+//! strings, arrays, maps and tags, definite lengths only. Encoding always
+//! uses the shortest argument form, so equal values give equal bytes.
+
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    Int(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+}
+
+impl Value {
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Int(n) if *n >= 0 => head(out, 0, *n as u64),
+            Value::Int(n) => head(out, 1, !*n as u64),
+            Value::Bytes(b) => {
+                head(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Value::Text(s) => {
+                head(out, 3, s.len() as u64);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Value::Array(items) => {
+                head(out, 4, items.len() as u64);
+                items.iter().for_each(|item| item.encode(out));
+            }
+            Value::Map(entries) => {
+                head(out, 5, entries.len() as u64);
+                for (k, v) in entries {
+                    k.encode(out);
+                    v.encode(out);
+                }
+            }
+            Value::Tag(tag, inner) => {
+                head(out, 6, *tag);
+                inner.encode(out);
+            }
+        }
+    }
+
+    /// Decodes exactly one item; trailing bytes are an error.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> CoreResult<Value> {
+        let mut r = Reader { buf: bytes };
+        let value = decode(&mut r, 0)?;
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing CBOR data"));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Value under integer `label` in a map.
+    pub(crate) fn get(&self, label: i64) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| *k == Value::Int(label)).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+fn decode(r: &mut Reader, depth: usize) -> CoreResult<Value> {
+    let bad = CoreError::Format("bad CBOR");
+    if depth > MAX_DEPTH {
+        return Err(CoreError::Format("CBOR nested too deeply"));
+    }
+    let initial = r.take(1)?[0];
+    let n = match initial & 0x1f {
+        info @ 0..=23 => info as u64,
+        24 => r.take(1)?[0] as u64,
+        25 => u16::from_be_bytes(r.array()?) as u64,
+        26 => u32::from_be_bytes(r.array()?) as u64,
+        27 => u64::from_be_bytes(r.array()?),
+        _ => return Err(CoreError::Format("unsupported CBOR item")),
+    };
+    // Every item takes at least a byte, so no count can exceed what is left.
+    let count = |r: &Reader| usize::try_from(n).ok().filter(|&len| len <= r.buf.len()).ok_or(CoreError::Format("truncated"));
+    Ok(match initial >> 5 {
+        0 => Value::Int(i64::try_from(n).map_err(|_| bad)?),
+        1 => Value::Int(-1 - i64::try_from(n).map_err(|_| bad)?),
+        2 => Value::Bytes(r.take(count(r)?)?.to_vec()),
+        3 => Value::Text(String::from_utf8(r.take(count(r)?)?.to_vec()).map_err(|_| bad)?),
+        4 => Value::Array((0..count(r)?).map(|_| decode(r, depth + 1)).collect::<CoreResult<_>>()?),
+        5 => Value::Map((0..count(r)?).map(|_| Ok((decode(r, depth + 1)?, decode(r, depth + 1)?))).collect::<CoreResult<_>>()?),
+        6 => Value::Tag(n, Box::new(decode(r, depth + 1)?)),
+        _ => return Err(CoreError::Format("unsupported CBOR item")),
+    })
+}
@@ -0,0 +1,496 @@
+//! Multi-party key generation ceremonies.
+//!
+//! A [`Ceremony`] names its participants' Dilithium5 keys and what the key
+//! is for. Each participant draws 32 bytes of entropy into a
+//! [`Contribution`] and first sends only a signed [`Commitment`] to it, a
+//! BLAKE3 hash bound to the ceremony. Once every commitment is in, each
+//! participant sends a signed [`Reveal`] of their entropy, which also names
+//! the full set of commitments it answers. No one can pick their entropy
+//! after seeing anyone else's. As long as one participant's entropy is
+//! secret and random, so is the key.
+//!
+//! [`Engine::run_ceremony`] checks every commitment and reveal and derives
+//! the key with HKDF-SHA256 over the entropy, in participant order. It
+//! then records a `keygen` audit event whose subject is the
+//! [`CeremonyTranscript`]: who took part, what each committed to and a
+//! check value of the key. The transcript comes back signed with the
+//! engine's identity key, so anyone can later match the key to the
+//! ceremony that made it.
+//!
+//! Reveals carry key material. Send them only to the engine running the
+//! ceremony. The key is 32 bytes for both purposes. An escrow key pair
+//! cannot be derived from it, because Kyber key generation here draws its
+//! own randomness. For [`CeremonyPurpose::EscrowKey`] the key is meant to
+//! wrap the escrow secret key in a recovery kit
+//! ([`crate::KitProtection::Wrapped`]).
+
+use crate::audit::{OpType, Outcome};
+use crate::cert::key_id;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Kdf;
+use crate::stepup::SensitiveOp;
+use pqcrypto_dilithium::dilithium5;
+use std::collections::HashSet;
+use zeroize::Zeroizing;
+
+pub const CEREMONY_MAGIC: &[u8; 4] = b"TCCS";
+pub const COMMITMENT_MAGIC: &[u8; 4] = b"TCCC";
+pub const REVEAL_MAGIC: &[u8; 4] = b"TCCR";
+pub const TRANSCRIPT_MAGIC: &[u8; 4] = b"TCCT";
+pub const CEREMONY_VERSION: u8 = 1;
+/// Most participants in one ceremony.
+pub const MAX_PARTICIPANTS: usize = 255;
+
+/// What the ceremony's key is for; part of its derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CeremonyPurpose {
+    MasterKek,
+    EscrowKey,
+}
+
+impl CeremonyPurpose {
+    pub const ALL: [CeremonyPurpose; 2] = [CeremonyPurpose::MasterKek, CeremonyPurpose::EscrowKey];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CeremonyPurpose::MasterKek => "master_kek",
+            CeremonyPurpose::EscrowKey => "escrow_key",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<CeremonyPurpose> {
+        Self::ALL.into_iter().find(|p| p.as_str() == s)
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            CeremonyPurpose::MasterKek => 1,
+            CeremonyPurpose::EscrowKey => 2,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<CeremonyPurpose> {
+        Self::ALL.into_iter().find(|p| p.code() == code)
+    }
+}
+
+/// One ceremony: a random ID, the purpose and the participants' Dilithium5
+/// public keys, in the order their entropy is combined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ceremony {
+    pub id: [u8; 16],
+    pub purpose: CeremonyPurpose,
+    participants: Vec<Vec<u8>>,
+}
+
+impl Ceremony {
+    /// A new ceremony among `participants`: 2 to [`MAX_PARTICIPANTS`]
+    /// distinct keys.
+    pub fn new(purpose: CeremonyPurpose, participants: Vec<Vec<u8>>) -> CoreResult<Self> {
+        let mut id = [0u8; 16];
+        entropy::fill(&mut id)?;
+        let ceremony = Ceremony { id, purpose, participants };
+        ceremony.check()?;
+        Ok(ceremony)
+    }
+
+    fn check(&self) -> CoreResult<()> {
+        if !(2..=MAX_PARTICIPANTS).contains(&self.participants.len()) {
+            return Err(CoreError::Config(format!("a ceremony needs 2 to {} participants", MAX_PARTICIPANTS)));
+        }
+        let mut seen = HashSet::new();
+        for pk in &self.participants {
+            if pk.len() != dilithium5::public_key_bytes() {
+                return Err(CoreError::InvalidKey);
+            }
+            if !seen.insert(key_id(pk)) {
+                return Err(CoreError::Config("ceremony participant listed twice".into()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn participants(&self) -> &[Vec<u8>] {
+        &self.participants
+    }
+
+    /// `magic(4) | version(1) | purpose(1) | id(16) | count(1) | (pk_len(2) | public_key)*`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(23 + self.participants.iter().map(|pk| 2 + pk.len()).sum::<usize>());
+        out.extend_from_slice(CEREMONY_MAGIC);
+        out.push(CEREMONY_VERSION);
+        out.push(self.purpose.code());
+        out.extend_from_slice(&self.id);
+        out.push(self.participants.len() as u8);
+        for pk in &self.participants {
+            out.extend_from_slice(&(pk.len() as u16).to_be_bytes());
+            out.extend_from_slice(pk);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        read_header(&mut r, CEREMONY_MAGIC)?;
+        let purpose = CeremonyPurpose::from_code(r.take(1)?[0]).ok_or(CoreError::Format("unknown ceremony purpose"))?;
+        let id = r.array()?;
+        let count = r.take(1)?[0];
+        let participants = (0..count).map(|_| {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            Ok(r.take(len)?.to_vec())
+        }).collect::<CoreResult<Vec<_>>>()?;
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes after ceremony"));
+        }
+        let ceremony = Ceremony { id, purpose, participants };
+        ceremony.check()?;
+        Ok(ceremony)
+    }
+
+    /// What commitments are bound to: BLAKE3 of [`Ceremony::to_bytes`].
+    pub fn digest(&self) -> [u8; 32] {
+        blake3::hash(&self.to_bytes()).into()
+    }
+
+    fn position(&self, participant: &[u8; 32]) -> Option<usize> {
+        self.participants.iter().position(|pk| key_id(pk) == *participant)
+    }
+
+    // Each participant's commitment, in participant order, with its
+    // signature checked.
+    fn ordered<'a>(&self, commitments: &'a [Commitment]) -> CoreResult<Vec<&'a Commitment>> {
+        let mut ordered = vec![None; self.participants.len()];
+        for c in commitments {
+            let i = self.position(&c.participant).ok_or_else(|| CoreError::Config(format!("{} is not a ceremony participant", hex::encode(c.participant))))?;
+            if ordered[i].replace(c).is_some() {
+                return Err(CoreError::Config(format!("{} committed twice", hex::encode(c.participant))));
+            }
+            if !crypto::verify_signature(&self.participants[i], &c.signed_bytes(), &c.signature) {
+                return Err(CoreError::InvalidKey);
+            }
+        }
+        ordered.into_iter().zip(&self.participants)
+            .map(|(c, pk)| c.ok_or_else(|| CoreError::Config(format!("no commitment from {}", hex::encode(key_id(pk))))))
+            .collect()
+    }
+}
+
+fn read_header(r: &mut Reader<'_>, magic: &[u8; 4]) -> CoreResult<()> {
+    if r.take(4)? != magic {
+        return Err(CoreError::Format("bad magic"));
+    }
+    if r.take(1)?[0] != CEREMONY_VERSION {
+        return Err(CoreError::Format("unsupported version"));
+    }
+    Ok(())
+}
+
+fn commitment_digest(ceremony: &[u8; 32], participant: &[u8; 32], entropy: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("titancore ceremony commitment v1");
+    hasher.update(ceremony);
+    hasher.update(participant);
+    hasher.update(entropy);
+    hasher.finalize().into()
+}
+
+// BLAKE3 over every commitment, in participant order.
+fn round_digest(commitments: &[&Commitment]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("titancore ceremony round v1");
+    for c in commitments {
+        hasher.update(&c.to_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// A participant's secret entropy, kept between committing and revealing.
+pub struct Contribution {
+    ceremony: [u8; 32],
+    participant: [u8; 32],
+    entropy: Zeroizing<[u8; 32]>,
+}
+
+impl Contribution {
+    /// Fresh entropy for `participant` (their Dilithium5 public key) in
+    /// `ceremony`.
+    pub fn new(ceremony: &Ceremony, participant: &[u8]) -> CoreResult<Self> {
+        let participant = key_id(participant);
+        if ceremony.position(&participant).is_none() {
+            return Err(CoreError::Config(format!("{} is not a ceremony participant", hex::encode(participant))));
+        }
+        let mut entropy = Zeroizing::new([0u8; 32]);
+        entropy::fill(entropy.as_mut())?;
+        Ok(Contribution { ceremony: ceremony.digest(), participant, entropy })
+    }
+
+    /// The signed commitment to send first.
+    pub fn commit(&self, secret_key: &[u8]) -> CoreResult<Commitment> {
+        let mut commitment = Commitment {
+            participant: self.participant,
+            digest: commitment_digest(&self.ceremony, &self.participant, &self.entropy),
+            signature: Vec::new(),
+        };
+        commitment.signature = crypto::sign(secret_key, &commitment.signed_bytes())?;
+        Ok(commitment)
+    }
+
+    /// The signed reveal, once `commitments` holds every participant's
+    /// commitment, this one's among them. Fails with
+    /// [`CoreError::Config`] if any is missing, and with
+    /// [`CoreError::InvalidKey`] if one does not verify.
+    pub fn reveal(&self, ceremony: &Ceremony, commitments: &[Commitment], secret_key: &[u8]) -> CoreResult<Reveal> {
+        if ceremony.digest() != self.ceremony {
+            return Err(CoreError::Config("contribution is for another ceremony".into()));
+        }
+        let ordered = ceremony.ordered(commitments)?;
+        let own = ordered.iter().find(|c| c.participant == self.participant).expect("every participant has committed");
+        if own.digest != commitment_digest(&self.ceremony, &self.participant, &self.entropy) {
+            return Err(CoreError::Config("commitments hold another commitment of this participant".into()));
+        }
+        let mut reveal = Reveal { participant: self.participant, round: round_digest(&ordered), entropy: self.entropy.clone(), signature: Vec::new() };
+        reveal.signature = crypto::sign(secret_key, &reveal.signed_bytes())?;
+        Ok(reveal)
+    }
+}
+
+/// A participant's signed hash of their entropy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commitment {
+    /// [`key_id`] of the participant's key.
+    pub participant: [u8; 32],
+    pub digest: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl Commitment {
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(69);
+        out.extend_from_slice(COMMITMENT_MAGIC);
+        out.push(CEREMONY_VERSION);
+        out.extend_from_slice(&self.participant);
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    /// `magic(4) | version(1) | participant(32) | digest(32) | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        read_header(&mut r, COMMITMENT_MAGIC)?;
+        let (participant, digest) = (r.array()?, r.array()?);
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("commitment without signature"));
+        }
+        Ok(Commitment { participant, digest, signature: r.buf.to_vec() })
+    }
+}
+
+/// A participant's signed entropy, naming the commitments it answers.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Reveal {
+    /// [`key_id`] of the participant's key.
+    pub participant: [u8; 32],
+    /// BLAKE3 over every commitment of the ceremony, in participant order.
+    pub round: [u8; 32],
+    pub entropy: Zeroizing<[u8; 32]>,
+    pub signature: Vec<u8>,
+}
+
+impl Reveal {
+    fn signed_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(Vec::with_capacity(101));
+        out.extend_from_slice(REVEAL_MAGIC);
+        out.push(CEREMONY_VERSION);
+        out.extend_from_slice(&self.participant);
+        out.extend_from_slice(&self.round);
+        out.extend_from_slice(self.entropy.as_ref());
+        out
+    }
+
+    /// `magic(4) | version(1) | participant(32) | round(32) | entropy(32) | signature`
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        read_header(&mut r, REVEAL_MAGIC)?;
+        let (participant, round) = (r.array()?, r.array()?);
+        let entropy = Zeroizing::new(r.array()?);
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("reveal without signature"));
+        }
+        Ok(Reveal { participant, round, entropy, signature: r.buf.to_vec() })
+    }
+}
+
+/// The record of a finished ceremony: which engine ran it, when, under
+/// which audit entry, each participant's commitment and a check value of
+/// the key (BLAKE3 derived from it, which reveals nothing of it).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CeremonyTranscript {
+    pub ceremony: [u8; 16],
+    pub purpose: CeremonyPurpose,
+    pub fingerprint: [u8; 32],
+    /// Counter of the `keygen` audit entry over this transcript.
+    pub counter: u64,
+    pub timestamp: u64,
+    /// `(participant key ID, commitment digest)`, in participant order.
+    pub commitments: Vec<([u8; 32], [u8; 32])>,
+    pub key_check: [u8; 32],
+}
+
+impl CeremonyTranscript {
+    /// `magic(4) | version(1) | purpose(1) | ceremony(16) | fingerprint(32) | counter(8) |
+    ///  timestamp(8) | key_check(32) | count(1) | (participant(32) | digest(32))*`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(103 + 64 * self.commitments.len());
+        out.extend_from_slice(TRANSCRIPT_MAGIC);
+        out.push(CEREMONY_VERSION);
+        out.push(self.purpose.code());
+        out.extend_from_slice(&self.ceremony);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.extend_from_slice(&self.key_check);
+        out.push(self.commitments.len() as u8);
+        for (participant, digest) in &self.commitments {
+            out.extend_from_slice(participant);
+            out.extend_from_slice(digest);
+        }
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        read_header(r, TRANSCRIPT_MAGIC)?;
+        let purpose = CeremonyPurpose::from_code(r.take(1)?[0]).ok_or(CoreError::Format("unknown ceremony purpose"))?;
+        let (ceremony, fingerprint) = (r.array()?, r.array()?);
+        let counter = u64::from_be_bytes(r.array()?);
+        let timestamp = u64::from_be_bytes(r.array()?);
+        let key_check = r.array()?;
+        let count = r.take(1)?[0];
+        let commitments = (0..count).map(|_| Ok((r.array()?, r.array()?))).collect::<CoreResult<Vec<_>>>()?;
+        Ok(CeremonyTranscript { ceremony, purpose, fingerprint, counter, timestamp, commitments, key_check })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let transcript = Self::read(&mut r)?;
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes after transcript"));
+        }
+        Ok(transcript)
+    }
+
+    /// True if `key` is the key the ceremony produced.
+    pub fn matches(&self, key: &[u8; 32]) -> bool {
+        key_check(key) == self.key_check
+    }
+}
+
+fn key_check(key: &[u8; 32]) -> [u8; 32] {
+    blake3::derive_key("titancore ceremony key check v1", key)
+}
+
+/// A [`CeremonyTranscript`] with the engine's Dilithium5 signature and
+/// public key. The embedded key is informational: verify against a key you
+/// trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTranscript {
+    pub transcript: CeremonyTranscript,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedTranscript {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.transcript.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let transcript = CeremonyTranscript::read(&mut r)?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        Ok(SignedTranscript { transcript, public_key, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.transcript.to_bytes(), &self.signature)
+    }
+}
+
+impl Engine {
+    /// Finishes `ceremony` from every participant's commitment and reveal:
+    /// returns the key and the signed transcript, recorded as a `keygen`
+    /// event whose subject is the transcript. Fails with
+    /// [`CoreError::Config`] if a participant's commitment or reveal is
+    /// missing or does not match, and with [`CoreError::InvalidKey`] if a
+    /// signature does not verify. Needs a [`SensitiveOp::KeyExport`] grant
+    /// under step-up.
+    pub fn run_ceremony(&self, ceremony: &Ceremony, commitments: &[Commitment], reveals: &[Reveal]) -> CoreResult<(Zeroizing<[u8; 32]>, SignedTranscript)> {
+        let res = self.try_run_ceremony(ceremony, commitments, reveals);
+        self.audited(OpType::Keygen, &ceremony.id, res)
+    }
+
+    fn try_run_ceremony(&self, ceremony: &Ceremony, commitments: &[Commitment], reveals: &[Reveal]) -> CoreResult<(Zeroizing<[u8; 32]>, SignedTranscript)> {
+        self.consume_step_up(SensitiveOp::KeyExport)?;
+        let ordered = ceremony.ordered(commitments)?;
+        let (digest, round) = (ceremony.digest(), round_digest(&ordered));
+        let mut entropy: Vec<Option<&Reveal>> = vec![None; ordered.len()];
+        for reveal in reveals {
+            let i = ceremony.position(&reveal.participant)
+                .ok_or_else(|| CoreError::Config(format!("{} is not a ceremony participant", hex::encode(reveal.participant))))?;
+            if entropy[i].replace(reveal).is_some() {
+                return Err(CoreError::Config(format!("{} revealed twice", hex::encode(reveal.participant))));
+            }
+            if !crypto::verify_signature(&ceremony.participants[i], &reveal.signed_bytes(), &reveal.signature) {
+                return Err(CoreError::InvalidKey);
+            }
+            if reveal.round != round {
+                return Err(CoreError::Config(format!("{} revealed against other commitments", hex::encode(reveal.participant))));
+            }
+            if commitment_digest(&digest, &reveal.participant, &reveal.entropy) != ordered[i].digest {
+                return Err(CoreError::Config(format!("reveal of {} does not match its commitment", hex::encode(reveal.participant))));
+            }
+        }
+        let mut ikm = Zeroizing::new(Vec::with_capacity(32 * ordered.len()));
+        for (reveal, c) in entropy.iter().zip(&ordered) {
+            let reveal = reveal.ok_or_else(|| CoreError::Config(format!("no reveal from {}", hex::encode(c.participant))))?;
+            ikm.extend_from_slice(reveal.entropy.as_ref());
+        }
+        let mut key = Zeroizing::new([0u8; 32]);
+        let info = [&b"titancore ceremony key v1"[..], &[ceremony.purpose.code()], &round].concat();
+        Kdf::HkdfSha256.derive(&ikm, &digest, &info, &mut key[..])?;
+
+        let counter = self.next_counters(1)?;
+        let transcript = CeremonyTranscript {
+            ceremony: ceremony.id,
+            purpose: ceremony.purpose,
+            fingerprint: *self.fingerprint(),
+            counter,
+            timestamp: self.clock().now_ms() / 1000,
+            commitments: ordered.iter().map(|c| (c.participant, c.digest)).collect(),
+            key_check: key_check(&key),
+        };
+        self.record_event_at(counter, OpType::Keygen, Outcome::Success, &transcript.to_bytes())?;
+        let signature = crypto::sign(&self.signing_key.1.unwrapped(), &transcript.to_bytes())?;
+        Ok((key, SignedTranscript { transcript, public_key: self.signing_key.0.clone(), signature }))
+    }
+}
@@ -0,0 +1,212 @@
+//! Lightweight certificates attributing Dilithium5 keys to organizations
+//! and engines, so checkpoints and signatures can be traced to a known
+//! engine.
+//!
+//! An engine certificate binds an engine's checkpoint signing key to its
+//! fingerprint and license serial. A CA certificate binds a vendor or
+//! organization key to its name and may issue further certificates. Chains
+//! run leaf first and end at a key the verifier already trusts.
+
+use crate::audit::checkpoint::SignedCheckpoint;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+
+pub const CERT_MAGIC: &[u8; 4] = b"TCRT";
+pub const CERT_VERSION: u8 = 1;
+
+/// Identifies a public key: `BLAKE3(public_key)`.
+pub fn key_id(public_key: &[u8]) -> [u8; 32] {
+    blake3::hash(public_key).into()
+}
+
+/// The signed part of a [`Certificate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateBody {
+    pub serial: u64,
+    /// [`key_id`] of the issuing key; set by [`CertificateBody::sign`].
+    pub issuer: [u8; 32],
+    /// Organization name for a CA, license serial for an engine.
+    pub subject: String,
+    /// Engine fingerprint; `None` marks a CA certificate.
+    pub fingerprint: Option<[u8; 32]>,
+    pub public_key: Vec<u8>,
+    /// Validity window in Unix seconds, both ends inclusive.
+    pub not_before: u64,
+    pub not_after: u64,
+}
+
+impl CertificateBody {
+    pub fn is_ca(&self) -> bool {
+        self.fingerprint.is_none()
+    }
+
+    /// `magic(4) | version(1) | kind(1) | serial(8) | issuer(32) | [fingerprint(32) |]
+    ///  not_before(8) | not_after(8) | subject_len(2) | subject | pk_len(2) | public_key`,
+    /// where `kind` is 0 for a CA and 1 for an engine.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128 + self.subject.len() + self.public_key.len());
+        out.extend_from_slice(CERT_MAGIC);
+        out.push(CERT_VERSION);
+        out.push(u8::from(!self.is_ca()));
+        out.extend_from_slice(&self.serial.to_be_bytes());
+        out.extend_from_slice(&self.issuer);
+        if let Some(fingerprint) = &self.fingerprint {
+            out.extend_from_slice(fingerprint);
+        }
+        out.extend_from_slice(&self.not_before.to_be_bytes());
+        out.extend_from_slice(&self.not_after.to_be_bytes());
+        out.extend_from_slice(&(self.subject.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.subject.as_bytes());
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let body = Self::read(&mut r)?;
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        Ok(body)
+    }
+
+    fn read(r: &mut Reader) -> CoreResult<Self> {
+        if r.take(4)? != CERT_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != CERT_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let is_engine = match r.take(1)?[0] {
+            0 => false,
+            1 => true,
+            _ => return Err(CoreError::Format("bad certificate kind")),
+        };
+        let serial = u64::from_be_bytes(r.array()?);
+        let issuer = r.array()?;
+        let fingerprint = if is_engine { Some(r.array()?) } else { None };
+        let not_before = u64::from_be_bytes(r.array()?);
+        let not_after = u64::from_be_bytes(r.array()?);
+        let subject_len = u16::from_be_bytes(r.array()?) as usize;
+        let subject = String::from_utf8(r.take(subject_len)?.to_vec()).map_err(|_| CoreError::Format("bad certificate subject"))?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        Ok(CertificateBody { serial, issuer, subject, fingerprint, public_key, not_before, not_after })
+    }
+
+    /// Signs with the issuer's Dilithium5 key. For a self-signed root, pass
+    /// the body's own keypair.
+    pub fn sign(mut self, issuer_public_key: &[u8], issuer_secret_key: &[u8]) -> CoreResult<Certificate> {
+        if self.subject.len() > u16::MAX as usize || self.public_key.len() > u16::MAX as usize {
+            return Err(CoreError::Config("certificate subject and key must be at most 65535 bytes".into()));
+        }
+        self.issuer = key_id(issuer_public_key);
+        let signature = crypto::sign(issuer_secret_key, &self.to_bytes())?;
+        Ok(Certificate { body: self, signature })
+    }
+}
+
+/// A [`CertificateBody`] with the issuer's Dilithium5 signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub body: CertificateBody,
+    pub signature: Vec<u8>,
+}
+
+impl Certificate {
+    /// `body | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.body.to_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let body = CertificateBody::read(&mut r)?;
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("certificate without signature"));
+        }
+        Ok(Certificate { body, signature: r.buf.to_vec() })
+    }
+
+    /// [`key_id`] of the certified key.
+    pub fn key_id(&self) -> [u8; 32] {
+        key_id(&self.body.public_key)
+    }
+
+    /// True if `issuer_pk` is the named issuer and its signature is valid.
+    pub fn verify_signature(&self, issuer_pk: &[u8]) -> bool {
+        key_id(issuer_pk) == self.body.issuer
+            && crypto::verify_signature(issuer_pk, &self.body.to_bytes(), &self.signature)
+    }
+}
+
+/// Checks `chain` (leaf first) at Unix time `now`: every certificate is
+/// within its validity window, every issuer is a CA, and each signature
+/// verifies under the next certificate's key up to one issued by a key in
+/// `trusted_roots`. Certificates after that one are ignored. Returns the
+/// leaf.
+pub fn verify_chain<'a>(chain: &'a [Certificate], trusted_roots: &[Vec<u8>], now: u64) -> CoreResult<&'a Certificate> {
+    let leaf = chain.first().ok_or(CoreError::Certificate("empty chain"))?;
+    for (i, cert) in chain.iter().enumerate() {
+        if now < cert.body.not_before || now > cert.body.not_after {
+            return Err(CoreError::Certificate("outside validity window"));
+        }
+        if i > 0 && !cert.body.is_ca() {
+            return Err(CoreError::Certificate("issuer is not a CA"));
+        }
+        if let Some(root) = trusted_roots.iter().find(|pk| key_id(pk) == cert.body.issuer) {
+            if !cert.verify_signature(root) {
+                return Err(CoreError::Certificate("bad signature"));
+            }
+            return Ok(leaf);
+        }
+        if cert.body.issuer == cert.key_id() {
+            return Err(CoreError::Certificate("untrusted self-signed certificate"));
+        }
+        let issuer = chain.get(i + 1).ok_or(CoreError::Certificate("chain does not reach a trusted root"))?;
+        if !cert.verify_signature(&issuer.body.public_key) {
+            return Err(CoreError::Certificate("bad signature or chain out of order"));
+        }
+    }
+    Err(CoreError::Certificate("chain does not reach a trusted root"))
+}
+
+/// Verifies `chain` and that `checkpoint` comes from the engine its leaf
+/// certifies: same fingerprint, signed by the certified key. Returns the
+/// leaf, whose subject is the engine's license serial.
+pub fn verify_attributed_checkpoint<'a>(checkpoint: &SignedCheckpoint, chain: &'a [Certificate], trusted_roots: &[Vec<u8>],
+                                        now: u64) -> CoreResult<&'a Certificate> {
+    let leaf = verify_chain(chain, trusted_roots, now)?;
+    let Some(fingerprint) = leaf.body.fingerprint else {
+        return Err(CoreError::Certificate("leaf is not an engine certificate"));
+    };
+    if fingerprint != checkpoint.checkpoint.fingerprint {
+        return Err(CoreError::Certificate("checkpoint is from a different engine"));
+    }
+    if !checkpoint.verify(&leaf.body.public_key) {
+        return Err(CoreError::Certificate("checkpoint not signed by the certified key"));
+    }
+    Ok(leaf)
+}
+
+impl Engine {
+    /// Unsigned engine certificate for this engine's fingerprint and
+    /// checkpoint signing key, for a CA to [`CertificateBody::sign`]. Only
+    /// meaningful with a long-lived key from [`Engine::set_signing_keypair`].
+    pub fn certificate_request(&self, license_serial: &str, serial: u64, not_before: u64, not_after: u64) -> CertificateBody {
+        CertificateBody {
+            serial,
+            issuer: [0; 32],
+            subject: license_serial.to_string(),
+            fingerprint: Some(self.fingerprint),
+            public_key: self.checkpoint_public_key().to_vec(),
+            not_before,
+            not_after,
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Mutually authenticated secure channel between two engines: a three
+//! message handshake in the style of KEMTLS, then an encrypted transport.
+//!
+//! ```text
+//! initiator                                   responder
+//! hello   = random_i | ephemeral Kyber pk  ->
+//!                                          <- reply   = random_r | kem_ct | pk_r | sig_r
+//! confirm = pk_i | sig_i                   ->
+//! ```
+//!
+//! The responder encapsulates to the initiator's ephemeral key; both sides
+//! sign the transcript hash with their Dilithium5 identity key, and each
+//! only accepts a peer key it was configured with. Transport keys are
+//! `HKDF-SHA256(salt = transcript hash, ikm = shared secret, info)`, one per
+//! direction. Records are AES-256-GCM-SIV with the record number as nonce,
+//! so a dropped, replayed or reordered record fails to open.
+
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::guarded::{Guarded, SecretBytes};
+use crate::idle::IdleKey;
+use crate::kdf::Kdf;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use std::io::{Read, Write};
+
+pub const CHANNEL_MAGIC: &[u8; 4] = b"TCCH";
+pub const CHANNEL_VERSION: u8 = 1;
+/// Largest plaintext one record may carry.
+pub const MAX_RECORD: usize = 16 * 1024 * 1024;
+
+const HELLO: u8 = 1;
+const REPLY: u8 = 2;
+const CONFIRM: u8 = 3;
+const RESPONDER_LABEL: &[u8] = b"titancore channel v1 responder";
+const INITIATOR_LABEL: &[u8] = b"titancore channel v1 initiator";
+const TAG_LEN: usize = 16;
+
+fn header(kind: u8) -> Vec<u8> {
+    let mut out = CHANNEL_MAGIC.to_vec();
+    out.push(CHANNEL_VERSION);
+    out.push(kind);
+    out
+}
+
+fn read_header(r: &mut Reader, kind: u8) -> CoreResult<()> {
+    if r.take(4)? != CHANNEL_MAGIC {
+        return Err(CoreError::Format("bad magic"));
+    }
+    if r.take(1)?[0] != CHANNEL_VERSION {
+        return Err(CoreError::Format("unsupported version"));
+    }
+    if r.take(1)?[0] != kind {
+        return Err(CoreError::Format("unexpected handshake message"));
+    }
+    Ok(())
+}
+
+fn put_key(out: &mut Vec<u8>, public_key: &[u8]) {
+    out.extend_from_slice(&(public_key.len() as u16).to_be_bytes());
+    out.extend_from_slice(public_key);
+}
+
+fn read_key<'a>(r: &mut Reader<'a>) -> CoreResult<&'a [u8]> {
+    let len = u16::from_be_bytes(r.array()?) as usize;
+    r.take(len)
+}
+
+fn transcript(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    for part in parts {
+        hasher.update(&(part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn signed(label: &[u8], hash: &[u8; 32]) -> Vec<u8> {
+    [label, hash].concat()
+}
+
+fn check_identity(public_key: &[u8], secret_key: &[u8]) -> CoreResult<()> {
+    if public_key.len() > u16::MAX as usize {
+        return Err(CoreError::InvalidKey);
+    }
+    let probe = crypto::sign(secret_key, b"titancore channel key check")?;
+    if !crypto::verify_signature(public_key, b"titancore channel key check", &probe) {
+        return Err(CoreError::Config("channel signing keys do not match".into()));
+    }
+    Ok(())
+}
+
+/// Opening side of the handshake. Call [`ChannelInitiator::hello`], send it,
+/// then pass the reply to [`ChannelInitiator::finish`]; or let
+/// [`ChannelInitiator::connect`] run it over a stream.
+pub struct ChannelInitiator {
+    public_key: Vec<u8>,
+    secret_key: IdleKey,
+    responder_key: Vec<u8>,
+    // hello and ephemeral secret once sent.
+    sent: Option<(Vec<u8>, SecretBytes)>,
+}
+
+impl ChannelInitiator {
+    /// Authenticates with the Dilithium5 keypair and only accepts a responder
+    /// holding `responder_public_key`.
+    pub fn new(public_key: &[u8], secret_key: &[u8], responder_public_key: &[u8]) -> CoreResult<Self> {
+        check_identity(public_key, secret_key)?;
+        Ok(ChannelInitiator {
+            public_key: public_key.to_vec(),
+            secret_key: IdleKey::new(secret_key),
+            responder_key: responder_public_key.to_vec(),
+            sent: None,
+        })
+    }
+
+    /// `magic(4) | version(1) | 1 | random(32) | ephemeral_pk`
+    pub fn hello(&mut self) -> CoreResult<Vec<u8>> {
+        if self.sent.is_some() {
+            return Err(CoreError::Config("handshake already started".into()));
+        }
+        let mut random = [0u8; 32];
+        crate::entropy::fill(&mut random)?;
+        let (ephemeral_pk, ephemeral_sk) = crypto::generate_keypair();
+        let mut out = header(HELLO);
+        out.extend_from_slice(&random);
+        out.extend_from_slice(&ephemeral_pk);
+        self.sent = Some((out.clone(), SecretBytes::new(&ephemeral_sk)));
+        Ok(out)
+    }
+
+    /// Checks the responder's reply and returns the confirmation to send
+    /// along with the transport. Fails with [`CoreError::Unauthorized`] if
+    /// the responder is not the expected key.
+    pub fn finish(&mut self, reply: &[u8]) -> CoreResult<(Vec<u8>, SecureTransport)> {
+        let (hello, ephemeral_sk) = self.sent.take().ok_or(CoreError::Config("hello not sent".into()))?;
+        let mut r = Reader { buf: reply };
+        read_header(&mut r, REPLY)?;
+        r.take(32)?;
+        let kem_ct = kyber1024::Ciphertext::from_bytes(r.take(kyber1024::ciphertext_bytes())?)
+            .map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        if read_key(&mut r)? != self.responder_key.as_slice() {
+            return Err(CoreError::Unauthorized);
+        }
+        let body = &reply[..reply.len() - r.buf.len()];
+        if !crypto::verify_signature(&self.responder_key, &signed(RESPONDER_LABEL, &transcript(&[&hello, body])), r.buf) {
+            return Err(CoreError::Unauthorized);
+        }
+
+        let mut confirm = header(CONFIRM);
+        put_key(&mut confirm, &self.public_key);
+        let signature = crypto::sign(&self.secret_key.unwrapped(), &signed(INITIATOR_LABEL, &transcript(&[&hello, reply, &confirm])))?;
+        confirm.extend_from_slice(&signature);
+
+        let sk = crypto::parse_secret_key(&ephemeral_sk)?;
+        let shared_secret = crypto::decapsulate(&kem_ct, &sk);
+        let session_id = transcript(&[&hello, reply, &confirm]);
+        let transport = SecureTransport::new(shared_secret.as_bytes(), session_id, true, self.responder_key.clone())?;
+        Ok((confirm, transport))
+    }
+
+    /// Runs the handshake over `stream` with length-prefixed frames.
+    pub fn connect<S: Read + Write>(mut self, mut stream: S) -> CoreResult<SecureChannel<S>> {
+        write_frame(&mut stream, &self.hello()?)?;
+        let reply = read_frame(&mut stream)?;
+        let (confirm, transport) = self.finish(&reply)?;
+        write_frame(&mut stream, &confirm)?;
+        Ok(SecureChannel { stream, transport })
+    }
+}
+
+/// Accepting side of the handshake: [`ChannelResponder::reply`] to the
+/// hello, then [`ChannelResponder::finish`] with the confirmation; or
+/// [`ChannelResponder::accept`] over a stream.
+pub struct ChannelResponder {
+    public_key: Vec<u8>,
+    secret_key: IdleKey,
+    trusted_initiators: Vec<Vec<u8>>,
+    replied: Option<Replied>,
+}
+
+struct Replied {
+    hello: Vec<u8>,
+    reply: Vec<u8>,
+    shared_secret: SecretBytes,
+}
+
+impl ChannelResponder {
+    /// Authenticates with the Dilithium5 keypair and only accepts initiators
+    /// holding one of `trusted_initiators`.
+    pub fn new(public_key: &[u8], secret_key: &[u8], trusted_initiators: Vec<Vec<u8>>) -> CoreResult<Self> {
+        check_identity(public_key, secret_key)?;
+        if trusted_initiators.is_empty() {
+            return Err(CoreError::Config("no trusted initiators".into()));
+        }
+        Ok(ChannelResponder {
+            public_key: public_key.to_vec(),
+            secret_key: IdleKey::new(secret_key),
+            trusted_initiators,
+            replied: None,
+        })
+    }
+
+    /// `magic(4) | version(1) | 2 | random(32) | kem_ct | pk_len(2) | public_key | signature`
+    pub fn reply(&mut self, hello: &[u8]) -> CoreResult<Vec<u8>> {
+        if self.replied.is_some() {
+            return Err(CoreError::Config("handshake already started".into()));
+        }
+        let mut r = Reader { buf: hello };
+        read_header(&mut r, HELLO)?;
+        r.take(32)?;
+        if r.buf.len() != kyber1024::public_key_bytes() {
+            return Err(CoreError::Format("bad ephemeral key"));
+        }
+        let ephemeral_pk = crypto::parse_public_key(r.buf)?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&ephemeral_pk);
+
+        let mut random = [0u8; 32];
+        crate::entropy::fill(&mut random)?;
+        let mut out = header(REPLY);
+        out.extend_from_slice(&random);
+        out.extend_from_slice(kem_ct.as_bytes());
+        put_key(&mut out, &self.public_key);
+        let signature = crypto::sign(&self.secret_key.unwrapped(), &signed(RESPONDER_LABEL, &transcript(&[hello, &out])))?;
+        out.extend_from_slice(&signature);
+        self.replied = Some(Replied { hello: hello.to_vec(), reply: out.clone(), shared_secret: SecretBytes::new(shared_secret.as_bytes()) });
+        Ok(out)
+    }
+
+    /// Checks the initiator's confirmation. Fails with
+    /// [`CoreError::Unauthorized`] for an untrusted key or bad signature.
+    pub fn finish(&mut self, confirm: &[u8]) -> CoreResult<SecureTransport> {
+        let Replied { hello, reply, shared_secret } = self.replied.take().ok_or(CoreError::Config("hello not answered".into()))?;
+        let mut r = Reader { buf: confirm };
+        read_header(&mut r, CONFIRM)?;
+        let initiator = read_key(&mut r)?;
+        if !self.trusted_initiators.iter().any(|pk| pk.as_slice() == initiator) {
+            return Err(CoreError::Unauthorized);
+        }
+        let body = &confirm[..confirm.len() - r.buf.len()];
+        if !crypto::verify_signature(initiator, &signed(INITIATOR_LABEL, &transcript(&[&hello, &reply, body])), r.buf) {
+            return Err(CoreError::Unauthorized);
+        }
+        let session_id = transcript(&[&hello, &reply, confirm]);
+        SecureTransport::new(&shared_secret, session_id, false, initiator.to_vec())
+    }
+
+    /// Runs the handshake over `stream` with length-prefixed frames.
+    pub fn accept<S: Read + Write>(mut self, mut stream: S) -> CoreResult<SecureChannel<S>> {
+        let hello = read_frame(&mut stream)?;
+        write_frame(&mut stream, &self.reply(&hello)?)?;
+        let transport = self.finish(&read_frame(&mut stream)?)?;
+        Ok(SecureChannel { stream, transport })
+    }
+}
+
+/// Record protection after a completed handshake. Records must be opened in
+/// the order they were sealed.
+pub struct SecureTransport {
+    send_key: Guarded<[u8; 32]>,
+    recv_key: Guarded<[u8; 32]>,
+    send_seq: u64,
+    recv_seq: u64,
+    session_id: [u8; 32],
+    peer_public_key: Vec<u8>,
+}
+
+impl SecureTransport {
+    fn new(shared_secret: &[u8], session_id: [u8; 32], initiator: bool, peer_public_key: Vec<u8>) -> CoreResult<Self> {
+        let (mut i2r, mut r2i) = (Guarded::new(&[0u8; 32]), Guarded::new(&[0u8; 32]));
+        Kdf::HkdfSha256.derive(shared_secret, &session_id, INITIATOR_LABEL, i2r.as_mut())?;
+        Kdf::HkdfSha256.derive(shared_secret, &session_id, RESPONDER_LABEL, r2i.as_mut())?;
+        let (send_key, recv_key) = if initiator { (i2r, r2i) } else { (r2i, i2r) };
+        Ok(SecureTransport { send_key, recv_key, send_seq: 0, recv_seq: 0, session_id, peer_public_key })
+    }
+
+    fn nonce(seq: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&seq.to_be_bytes());
+        nonce
+    }
+
+    /// Encrypts the next outgoing record.
+    pub fn seal(&mut self, data: &[u8]) -> CoreResult<Vec<u8>> {
+        if data.len() > MAX_RECORD {
+            return Err(CoreError::Config(format!("records are limited to {} bytes", MAX_RECORD)));
+        }
+        if self.send_seq == u64::MAX {
+            return Err(CoreError::RekeyRequired("channel record counter exhausted"));
+        }
+        let record = crypto::aead_seal(&self.send_key, &Self::nonce(self.send_seq), data)?;
+        self.send_seq += 1;
+        Ok(record)
+    }
+
+    /// Decrypts the next incoming record.
+    pub fn open(&mut self, record: &[u8]) -> CoreResult<Vec<u8>> {
+        if self.recv_seq == u64::MAX {
+            return Err(CoreError::RekeyRequired("channel record counter exhausted"));
+        }
+        let data = crypto::aead_open(&self.recv_key, &Self::nonce(self.recv_seq), record)?;
+        self.recv_seq += 1;
+        Ok(data)
+    }
+
+    /// [`SecureTransport::seal`] as a `len(4) | record` frame, the framing
+    /// [`SecureChannel`] writes.
+    pub fn seal_frame(&mut self, data: &[u8]) -> CoreResult<Vec<u8>> {
+        let mut frame = Vec::new();
+        write_frame(&mut frame, &self.seal(data)?)?;
+        Ok(frame)
+    }
+
+    /// Opens one complete frame from [`SecureTransport::seal_frame`].
+    pub fn open_frame(&mut self, mut frame: &[u8]) -> CoreResult<Vec<u8>> {
+        let record = read_frame(&mut frame).map_err(|_| CoreError::Format("truncated"))?;
+        if !frame.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        self.open(&record)
+    }
+
+    /// Transcript hash, identical on both ends; usable as a channel binding.
+    pub fn session_id(&self) -> &[u8; 32] {
+        &self.session_id
+    }
+
+    /// The authenticated peer's Dilithium5 key.
+    pub fn peer_public_key(&self) -> &[u8] {
+        &self.peer_public_key
+    }
+}
+
+/// A [`SecureTransport`] over a byte stream, framing each record as
+/// `len(4) | record`.
+pub struct SecureChannel<S> {
+    stream: S,
+    transport: SecureTransport,
+}
+
+impl<S: Read + Write> SecureChannel<S> {
+    pub fn send(&mut self, data: &[u8]) -> CoreResult<()> {
+        let record = self.transport.seal(data)?;
+        write_frame(&mut self.stream, &record)
+    }
+
+    pub fn recv(&mut self) -> CoreResult<Vec<u8>> {
+        let record = read_frame(&mut self.stream)?;
+        self.transport.open(&record)
+    }
+
+    pub fn transport(&self) -> &SecureTransport {
+        &self.transport
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+fn write_frame(stream: &mut impl Write, frame: &[u8]) -> CoreResult<()> {
+    stream.write_all(&(frame.len() as u32).to_be_bytes())?;
+    stream.write_all(frame)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_frame(stream: &mut impl Read) -> CoreResult<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_RECORD + TAG_LEN {
+        return Err(CoreError::Format("oversized channel frame"));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+impl Engine {
+    /// Initiator authenticated by this engine's checkpoint signing key, to
+    /// the engine whose checkpoint key is `responder_public_key`. Fails with
+    /// [`CoreError::Revoked`] if that key is revoked.
+    pub fn channel_initiator(&self, responder_public_key: &[u8]) -> CoreResult<ChannelInitiator> {
+        self.ensure_not_revoked(responder_public_key)?;
+        ChannelInitiator::new(&self.signing_key.0, &self.signing_key.1.unwrapped(), responder_public_key)
+    }
+
+    /// Responder authenticated by this engine's checkpoint signing key,
+    /// accepting the engines whose checkpoint keys are `trusted_initiators`.
+    /// Revoked keys are dropped from the list.
+    pub fn channel_responder(&self, trusted_initiators: Vec<Vec<u8>>) -> CoreResult<ChannelResponder> {
+        let requested = trusted_initiators.len();
+        let mut trusted = Vec::with_capacity(requested);
+        for pk in trusted_initiators {
+            match self.ensure_not_revoked(&pk) {
+                Ok(()) => trusted.push(pk),
+                Err(CoreError::Revoked) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if requested > 0 && trusted.is_empty() {
+            return Err(CoreError::Revoked);
+        }
+        ChannelResponder::new(&self.signing_key.0, &self.signing_key.1.unwrapped(), trusted)
+    }
+}
+
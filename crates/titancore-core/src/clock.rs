@@ -0,0 +1,123 @@
+//! Time sources for audit timestamps, checkpoints and rate limiting.
+
+use crate::time::{self, Instant};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where the engine reads time from.
+pub trait Clock: Send + Sync {
+    /// UTC wall-clock time in milliseconds since the Unix epoch.
+    fn now_ms(&self) -> u64;
+
+    /// Time since an arbitrary fixed origin that never goes backwards; used
+    /// for rate limiting.
+    fn monotonic(&self) -> Duration;
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Clock({}ms)", self.now_ms())
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now_ms(&self) -> u64 {
+        (**self).now_ms()
+    }
+
+    fn monotonic(&self) -> Duration {
+        (**self).monotonic()
+    }
+}
+
+/// The host's clocks: wall time from the system, monotonic time from
+/// [`Instant`].
+#[derive(Debug)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { origin: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        time::unix_millis()
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests. Wall and
+/// monotonic time advance together; [`FixedClock::set`] can move wall time
+/// backwards to simulate a clock step, but monotonic time never regresses.
+#[derive(Debug)]
+pub struct FixedClock {
+    now_ms: AtomicU64,
+    monotonic_ms: AtomicU64,
+}
+
+impl FixedClock {
+    pub fn new(now_ms: u64) -> Self {
+        FixedClock { now_ms: AtomicU64::new(now_ms), monotonic_ms: AtomicU64::new(0) }
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        let prev = self.now_ms.swap(now_ms, Ordering::Relaxed);
+        self.monotonic_ms.fetch_add(now_ms.saturating_sub(prev), Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let ms = by.as_millis() as u64;
+        self.now_ms.fetch_add(ms, Ordering::Relaxed);
+        self.monotonic_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::Relaxed)
+    }
+
+    fn monotonic(&self) -> Duration {
+        Duration::from_millis(self.monotonic_ms.load(Ordering::Relaxed))
+    }
+}
+
+/// Shifts another clock's wall time by a fixed offset, e.g. the measured
+/// difference between the local clock and a trusted time source on an
+/// air-gapped host. Monotonic time is the inner clock's.
+#[derive(Debug)]
+pub struct OffsetClock<C> {
+    inner: C,
+    offset_ms: i64,
+}
+
+impl<C: Clock> OffsetClock<C> {
+    pub fn new(inner: C, offset_ms: i64) -> Self {
+        OffsetClock { inner, offset_ms }
+    }
+}
+
+impl<C: Clock> Clock for OffsetClock<C> {
+    fn now_ms(&self) -> u64 {
+        self.inner.now_ms().saturating_add_signed(self.offset_ms)
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.inner.monotonic()
+    }
+}
@@ -0,0 +1,251 @@
+//! Decryption constraints carried inside an envelope.
+//!
+//! [`Engine::seal_constrained`] signs a set of [`Constraints`] with the
+//! sealing engine's identity key and records them in the envelope header.
+//! The signed bytes are also mixed into the input keying material, so they
+//! cannot be stripped or changed without breaking the key. Before it
+//! decrypts, every engine open path checks that the signer is one it
+//! trusts, its own identity key or one of
+//! [`EngineConfig::constraint_signers`], and then the envelope against its
+//! own state:
+//!
+//! - `not_after`: the engine's clock, in Unix seconds, must not be past it;
+//! - `fingerprints`: if any are listed, the engine's fingerprint must be one;
+//! - `role`: the caller's role (see [`crate::rbac`]) must be this one.
+//!
+//! A violation fails with [`CoreError::Constraint`], recorded as a `denied`
+//! decryption. [`Envelope::open`](crate::Envelope::open) refuses
+//! constrained envelopes outright, since it has no engine state to check
+//! them against. The constraints are policy, not cryptography: they hold
+//! against anyone who opens the envelope through an engine, not against
+//! the holder of the secret key with their own code.
+//!
+//! [`EngineConfig::constraint_signers`]: crate::EngineConfig::constraint_signers
+
+use crate::audit::OpType;
+use crate::cert::key_id;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::{Envelope, Reader};
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Marks;
+
+pub const CONSTRAINTS_MAGIC: &[u8; 4] = b"TCDC";
+pub const CONSTRAINTS_VERSION: u8 = 1;
+/// Most fingerprints one envelope may allow.
+pub const MAX_FINGERPRINTS: usize = 255;
+/// Longest required role, in bytes.
+pub const MAX_ROLE_LEN: usize = 255;
+
+/// Where, when and by whom an envelope may be opened. An unset field does
+/// not constrain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Constraints {
+    /// Last Unix second, by the opening engine's clock, the envelope opens.
+    pub not_after: Option<u64>,
+    /// Fingerprints of the engines that may open the envelope; empty for any.
+    pub fingerprints: Vec<[u8; 32]>,
+    /// Role the caller must hold.
+    pub role: Option<String>,
+}
+
+impl Constraints {
+    pub fn is_empty(&self) -> bool {
+        *self == Constraints::default()
+    }
+
+    /// `magic(4) | version(1) | has_not_after(1) | not_after(8) | count(1) |
+    ///  fingerprint(32)* | role_len(1) | role`; an empty role stands for none.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let role = self.role.as_deref().unwrap_or_default();
+        let mut out = Vec::with_capacity(16 + 32 * self.fingerprints.len() + role.len());
+        out.extend_from_slice(CONSTRAINTS_MAGIC);
+        out.push(CONSTRAINTS_VERSION);
+        out.push(u8::from(self.not_after.is_some()));
+        out.extend_from_slice(&self.not_after.unwrap_or_default().to_be_bytes());
+        out.push(self.fingerprints.len() as u8);
+        self.fingerprints.iter().for_each(|fp| out.extend_from_slice(fp));
+        out.push(role.len() as u8);
+        out.extend_from_slice(role.as_bytes());
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != CONSTRAINTS_MAGIC {
+            return Err(CoreError::Format("bad constraints magic"));
+        }
+        if r.take(1)?[0] != CONSTRAINTS_VERSION {
+            return Err(CoreError::Format("unsupported constraints version"));
+        }
+        let has_not_after = r.take(1)?[0];
+        let not_after = u64::from_be_bytes(r.array()?);
+        let not_after = match has_not_after {
+            0 if not_after == 0 => None,
+            1 => Some(not_after),
+            _ => return Err(CoreError::Format("bad constraints deadline")),
+        };
+        let count = r.take(1)?[0];
+        let fingerprints = (0..count).map(|_| r.array()).collect::<CoreResult<Vec<_>>>()?;
+        let role_len = r.take(1)?[0] as usize;
+        let role = std::str::from_utf8(r.take(role_len)?).map_err(|_| CoreError::Format("constraint role is not UTF-8"))?;
+        let role = (!role.is_empty()).then(|| role.to_string());
+        Ok(Constraints { not_after, fingerprints, role })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let constraints = Self::read(&mut r)?;
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes after constraints"));
+        }
+        Ok(constraints)
+    }
+
+    fn check(&self) -> CoreResult<()> {
+        if self.is_empty() {
+            return Err(CoreError::Config("no decryption constraints given".into()));
+        }
+        if self.fingerprints.len() > MAX_FINGERPRINTS {
+            return Err(CoreError::Config(format!("at most {} fingerprints may be allowed", MAX_FINGERPRINTS)));
+        }
+        match self.role.as_deref() {
+            Some(role) if !(1..=MAX_ROLE_LEN).contains(&role.len()) => {
+                Err(CoreError::Config(format!("required roles are 1 to {} bytes", MAX_ROLE_LEN)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// [`Constraints`] with the sealing engine's Dilithium5 signature and
+/// public key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedConstraints {
+    pub constraints: Constraints,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedConstraints {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.constraints.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let constraints = Constraints::read(&mut r)?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        Ok(SignedConstraints { constraints, public_key, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.constraints.to_bytes(), &self.signature)
+    }
+
+    /// What goes into the input keying material: a hash of every signed
+    /// byte, signer included.
+    pub(crate) fn digest(&self) -> [u8; 32] {
+        blake3::derive_key("titancore decryption constraints v1", &self.to_bytes())
+    }
+}
+
+impl Engine {
+    /// [`Engine::seal_with_context`] under `constraints`, signed with this
+    /// engine's identity key. Fails with [`CoreError::Config`] if no
+    /// constraint is set, more than [`MAX_FINGERPRINTS`] are allowed or the
+    /// role is longer than [`MAX_ROLE_LEN`].
+    pub fn seal_constrained(&self, data: &[u8], pk_bytes: &[u8], context: &[u8], constraints: &Constraints) -> CoreResult<(Envelope, String)> {
+        let res = constraints.check().and_then(|_| {
+            let signature = crypto::sign(&self.signing_key.1.unwrapped(), &constraints.to_bytes())?;
+            let signed = SignedConstraints { constraints: constraints.clone(), public_key: self.signing_key.0.clone(), signature };
+            self.try_seal(data, pk_bytes, context, Marks { constraints: Some(&signed), ..Marks::default() })
+        });
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    /// Checks `envelope`'s constraints, if any, against this engine: its
+    /// clock, its fingerprint and the caller's role. Fails with
+    /// [`CoreError::Constraint`] on the first one violated, if the signer is
+    /// neither this engine's identity key nor one of
+    /// [`EngineConfig::constraint_signers`](crate::EngineConfig::constraint_signers),
+    /// or if the signature does not verify under it.
+    pub fn check_constraints(&self, envelope: &Envelope) -> CoreResult<()> {
+        let Some(signed) = &envelope.kdf.constraints else { return Ok(()) };
+        let trusted = signed.public_key == self.signing_key.0 || self.constraint_signers.contains(&signed.public_key);
+        if !trusted {
+            return Err(CoreError::Constraint(format!("unknown constraint signer {}", hex::encode(key_id(&signed.public_key)))));
+        }
+        if !signed.verify(&signed.public_key) {
+            return Err(CoreError::Constraint("signature does not verify".into()));
+        }
+        let constraints = &signed.constraints;
+        if let Some(not_after) = constraints.not_after {
+            if self.clock().now_ms() / 1000 > not_after {
+                return Err(CoreError::Constraint(format!("envelope expired at {}", not_after)));
+            }
+        }
+        if !constraints.fingerprints.is_empty() && !constraints.fingerprints.contains(self.fingerprint()) {
+            return Err(CoreError::Constraint(format!("engine {} is not allowed", hex::encode(self.fingerprint()))));
+        }
+        if let Some(role) = &constraints.role {
+            if self.caller_role().as_ref() != Some(role) {
+                return Err(CoreError::Constraint(format!("caller lacks role {:?}", role)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::MemorySink;
+    use crate::EngineConfig;
+
+    fn engine(constraint_signers: Vec<Vec<u8>>) -> Engine {
+        let config = EngineConfig { constraint_signers, ..EngineConfig::default() };
+        Engine::with_config("hw", "seed", Box::new(MemorySink::new()), config).unwrap()
+    }
+
+    fn sealed(engine: &Engine) -> (Envelope, Vec<u8>) {
+        let (pk, sk) = crypto::generate_keypair();
+        let constraints = Constraints { not_after: Some(u64::MAX), ..Constraints::default() };
+        let (envelope, _) = engine.seal_constrained(b"payload", &pk, &[], &constraints).unwrap();
+        (envelope, sk.to_vec())
+    }
+
+    #[test]
+    fn own_signature_is_accepted() {
+        let engine = engine(Vec::new());
+        let (envelope, sk) = sealed(&engine);
+        assert_eq!(engine.open(&envelope, &sk).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn resigned_constraints_are_refused() {
+        let engine = engine(Vec::new());
+        let (mut envelope, sk) = sealed(&engine);
+        let (pk, signing_sk) = crypto::generate_signing_keypair();
+        let constraints = Constraints { not_after: Some(u64::MAX), role: Some("anyone".into()), ..Constraints::default() };
+        let signature = crypto::sign(&signing_sk, &constraints.to_bytes()).unwrap();
+        envelope.kdf.constraints = Some(SignedConstraints { constraints, public_key: pk, signature });
+        assert!(matches!(engine.check_constraints(&envelope), Err(CoreError::Constraint(_))));
+        assert!(matches!(engine.open(&envelope, &sk), Err(CoreError::Constraint(_))));
+    }
+
+    #[test]
+    fn pinned_signer_is_accepted() {
+        let sealer = engine(Vec::new());
+        let (envelope, _) = sealed(&sealer);
+        let signer = envelope.kdf.constraints.as_ref().unwrap().public_key.clone();
+        assert!(matches!(engine(Vec::new()).check_constraints(&envelope), Err(CoreError::Constraint(_))));
+        engine(vec![signer]).check_constraints(&envelope).unwrap();
+    }
+}
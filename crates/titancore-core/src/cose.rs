@@ -0,0 +1,279 @@
+//! COSE (RFC 9052) encodings for constrained-device integrations: engine
+//! ciphertexts as `COSE_Encrypt` and checkpoints as Dilithium5 `COSE_Sign1`.
+//!
+//! A `COSE_Encrypt` has a single recipient whose ciphertext is the Kyber
+//! ciphertext. The recipient's unprotected header carries what else an
+//! [`Envelope`](crate::Envelope) records for key derivation: the counter,
+//! the engine fingerprint (as `kid`), the KDF and its salts. The content key
+//! is the envelope session key, and the AEAD's AAD is the `Enc_structure`
+//! over the body's protected header, as the RFC requires. None of these
+//! algorithms has a registered COSE identifier, so they use private-use
+//! values.
+
+use crate::audit::checkpoint::Checkpoint;
+use crate::audit::{self, CiphertextBinding, OpType, Outcome};
+use crate::cbor::Value;
+use crate::cert;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams, DEFAULT_KDF_INFO};
+use crate::rbac::Permission;
+use crate::suite::Suite;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+
+pub const ALG_KYBER1024: i64 = -65601;
+pub const ALG_A256GCMSIV: i64 = -65602;
+pub const ALG_XC20P: i64 = -65603;
+pub const ALG_DILITHIUM5: i64 = -65604;
+
+pub const TAG_COSE_ENCRYPT: u64 = 96;
+pub const TAG_COSE_SIGN1: u64 = 18;
+
+const HDR_ALG: i64 = 1;
+const HDR_KID: i64 = 4;
+const HDR_IV: i64 = 5;
+const HDR_COUNTER: i64 = -65700;
+const HDR_KDF: i64 = -65701;
+const HDR_MESSAGE_SALT: i64 = -65702;
+const HDR_KDF_SALT: i64 = -65703;
+const HDR_KDF_INFO: i64 = -65704;
+
+/// True if `bytes` start like a tagged `COSE_Encrypt` or `COSE_Sign1`,
+/// which no native encoding does.
+pub fn is_cose(bytes: &[u8]) -> bool {
+    matches!(bytes, [0xd8, 0x60, ..] | [0xd2, ..])
+}
+
+fn alg_header(alg: i64) -> Vec<u8> {
+    Value::Map(vec![(Value::Int(HDR_ALG), Value::Int(alg))]).to_bytes()
+}
+
+fn protected_alg(protected: &[u8]) -> CoreResult<i64> {
+    Value::from_bytes(protected)?.get(HDR_ALG).and_then(Value::as_int).ok_or(CoreError::Format("COSE header without alg"))
+}
+
+fn enc_structure(protected: &[u8]) -> Vec<u8> {
+    Value::Array(vec![Value::Text("Encrypt".into()), Value::Bytes(protected.to_vec()), Value::Bytes(Vec::new())]).to_bytes()
+}
+
+fn sig_structure(protected: &[u8], payload: &[u8]) -> Vec<u8> {
+    Value::Array(vec![
+        Value::Text("Signature1".into()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(payload.to_vec()),
+    ]).to_bytes()
+}
+
+fn untag(bytes: &[u8], tag: u64) -> CoreResult<Vec<Value>> {
+    match Value::from_bytes(bytes)? {
+        Value::Tag(t, inner) if t == tag => match *inner {
+            Value::Array(items) => Ok(items),
+            _ => Err(CoreError::Format("bad COSE structure")),
+        },
+        _ => Err(CoreError::Format("unexpected COSE tag")),
+    }
+}
+
+/// A single-recipient `COSE_Encrypt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoseEncrypt {
+    /// Serialized body protected header, kept verbatim because it is in the AAD.
+    pub protected: Vec<u8>,
+    pub suite: Suite,
+    pub iv: Vec<u8>,
+    /// AEAD output, tag included.
+    pub ciphertext: Vec<u8>,
+    pub counter: u64,
+    pub fingerprint: [u8; 32],
+    pub kdf: KdfParams,
+    pub kem_ct: Vec<u8>,
+}
+
+impl CoseEncrypt {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut recipient_header = vec![
+            (Value::Int(HDR_KID), Value::Bytes(self.fingerprint.to_vec())),
+            (Value::Int(HDR_COUNTER), Value::Int(self.counter as i64)),
+            (Value::Int(HDR_KDF), Value::Text(self.kdf.algorithm.name().into())),
+        ];
+        if let Some(salt) = &self.kdf.message_salt {
+            recipient_header.push((Value::Int(HDR_MESSAGE_SALT), Value::Bytes(salt.to_vec())));
+        }
+        if !self.kdf.salt.is_empty() {
+            recipient_header.push((Value::Int(HDR_KDF_SALT), Value::Bytes(self.kdf.salt.clone())));
+        }
+        if self.kdf.info != DEFAULT_KDF_INFO {
+            recipient_header.push((Value::Int(HDR_KDF_INFO), Value::Bytes(self.kdf.info.clone())));
+        }
+        let recipient = Value::Array(vec![
+            Value::Bytes(alg_header(ALG_KYBER1024)),
+            Value::Map(recipient_header),
+            Value::Bytes(self.kem_ct.clone()),
+        ]);
+        Value::Tag(TAG_COSE_ENCRYPT, Box::new(Value::Array(vec![
+            Value::Bytes(self.protected.clone()),
+            Value::Map(vec![(Value::Int(HDR_IV), Value::Bytes(self.iv.clone()))]),
+            Value::Bytes(self.ciphertext.clone()),
+            Value::Array(vec![recipient]),
+        ]))).to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let bad = CoreError::Format("bad COSE_Encrypt");
+        let [protected, unprotected, ciphertext, recipients] = &untag(bytes, TAG_COSE_ENCRYPT)?[..] else {
+            return Err(bad);
+        };
+        let protected = protected.as_bytes().ok_or(bad.clone())?.to_vec();
+        let suite = match protected_alg(&protected)? {
+            ALG_A256GCMSIV => Suite::GcmSivCounter,
+            ALG_XC20P => Suite::XChaCha20Poly1305,
+            _ => return Err(CoreError::Format("unsupported COSE content alg")),
+        };
+        let [recipient] = recipients.as_array().ok_or(bad.clone())? else {
+            return Err(CoreError::Format("COSE_Encrypt must have one recipient"));
+        };
+        let [r_protected, header, kem_ct] = recipient.as_array().ok_or(bad.clone())? else {
+            return Err(bad);
+        };
+        if protected_alg(r_protected.as_bytes().ok_or(bad.clone())?)? != ALG_KYBER1024 {
+            return Err(CoreError::Format("unsupported COSE recipient alg"));
+        }
+        let field = |label: i64| header.get(label).map(|v| v.as_bytes().map(<[u8]>::to_vec).ok_or(bad.clone())).transpose();
+        let algorithm = match header.get(HDR_KDF) {
+            Some(Value::Text(name)) => Kdf::parse(name).ok_or(CoreError::Format("unknown KDF"))?,
+            _ => return Err(bad),
+        };
+        let kdf = KdfParams {
+            algorithm,
+            salt: field(HDR_KDF_SALT)?.unwrap_or_default(),
+            info: field(HDR_KDF_INFO)?.unwrap_or_else(|| DEFAULT_KDF_INFO.to_vec()),
+            message_salt: field(HDR_MESSAGE_SALT)?.map(|s| s.try_into().map_err(|_| CoreError::Format("bad message salt"))).transpose()?,
+            restricted: false,
+            schema: None,
+            epoch: None,
+            constraints: None,
+        };
+        let counter = header.get(HDR_COUNTER).and_then(Value::as_int).and_then(|c| u64::try_from(c).ok()).ok_or(bad.clone())?;
+        let iv = unprotected.get(HDR_IV).and_then(Value::as_bytes).ok_or(bad.clone())?.to_vec();
+        if iv.len() != suite.nonce_len() {
+            return Err(CoreError::Format("bad COSE IV length"));
+        }
+        Ok(CoseEncrypt {
+            protected,
+            suite,
+            iv,
+            ciphertext: ciphertext.as_bytes().ok_or(bad.clone())?.to_vec(),
+            counter,
+            fingerprint: field(HDR_KID)?.ok_or(bad.clone())?.try_into().map_err(|_| bad.clone())?,
+            kdf,
+            kem_ct: kem_ct.as_bytes().ok_or(bad)?.to_vec(),
+        })
+    }
+
+    /// Decapsulates with the recipient's Kyber secret key and decrypts.
+    /// `context` must match the one the message was sealed under.
+    pub fn open(&self, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let sk = crypto::parse_secret_key(sk_bytes)?;
+        let kem_ct = kyber1024::Ciphertext::from_bytes(&self.kem_ct)
+            .map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        let shared_secret = crypto::decapsulate(&kem_ct, &sk);
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, self.counter, &self.kdf, context)?;
+        self.suite.open_aad(&key, &self.iv, &self.ciphertext, &enc_structure(&self.protected))
+    }
+}
+
+/// `COSE_Sign1` over `payload` with a Dilithium5 secret key.
+pub fn sign1(secret_key: &[u8], payload: &[u8]) -> CoreResult<Vec<u8>> {
+    let protected = alg_header(ALG_DILITHIUM5);
+    let signature = crypto::sign(secret_key, &sig_structure(&protected, payload))?;
+    Ok(Value::Tag(TAG_COSE_SIGN1, Box::new(Value::Array(vec![
+        Value::Bytes(protected),
+        Value::Map(Vec::new()),
+        Value::Bytes(payload.to_vec()),
+        Value::Bytes(signature),
+    ]))).to_bytes())
+}
+
+/// Payload of a `COSE_Sign1` from [`sign1`], or `None` if its signature
+/// does not verify under `trusted_pk`.
+pub fn verify_sign1(bytes: &[u8], trusted_pk: &[u8]) -> CoreResult<Option<Vec<u8>>> {
+    let bad = CoreError::Format("bad COSE_Sign1");
+    let [protected, _, payload, signature] = &untag(bytes, TAG_COSE_SIGN1)?[..] else {
+        return Err(bad);
+    };
+    let (Some(protected), Some(payload), Some(signature)) = (protected.as_bytes(), payload.as_bytes(), signature.as_bytes()) else {
+        return Err(bad);
+    };
+    if protected_alg(protected)? != ALG_DILITHIUM5 {
+        return Err(CoreError::Format("unsupported COSE signature alg"));
+    }
+    Ok(crypto::verify_signature(trusted_pk, &sig_structure(protected, payload), signature).then(|| payload.to_vec()))
+}
+
+/// Checkpoint in a `COSE_Sign1` from [`Engine::checkpoint_cose`], or `None`
+/// if the signature does not verify under `trusted_pk`.
+pub fn verify_checkpoint(bytes: &[u8], trusted_pk: &[u8]) -> CoreResult<Option<Checkpoint>> {
+    verify_sign1(bytes, trusted_pk)?.map(|payload| Checkpoint::from_bytes(&payload)).transpose()
+}
+
+impl Engine {
+    /// Encrypts `data` to the Kyber public key as a `COSE_Encrypt` and
+    /// records it like [`Engine::seal_with_context`]. The audit link binds
+    /// the KEM ciphertext, IV and AEAD output.
+    pub fn seal_cose(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(CoseEncrypt, String)> {
+        let res = self.try_seal_cose(data, pk_bytes, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_seal_cose(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(CoseEncrypt, String)> {
+        let alg = match self.suite {
+            Suite::GcmSivCounter | Suite::GcmSivRandom => ALG_A256GCMSIV,
+            Suite::XChaCha20Poly1305 => ALG_XC20P,
+            Suite::Registered(_) => return Err(CoreError::Config(format!("suite {} has no COSE algorithm", self.suite.name()))),
+        };
+        self.check_rate_limit()?;
+        let pk = self.recipient_key(pk_bytes, data.len() as u64)?;
+        let ctr = self.next_counters(1)?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let kdf = self.kdf.for_message(shared_secret.as_bytes(), kem_ct.as_bytes());
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, ctr, &kdf, context)?;
+
+        let protected = alg_header(alg);
+        let iv = self.suite.nonce(ctr, &key, data);
+        let ciphertext = self.install(|| self.suite.seal_aad(&key, &iv, data, &enc_structure(&protected)))?;
+        let bound = match self.ct_binding {
+            CiphertextBinding::Full => ciphertext.clone(),
+            CiphertextBinding::Digest => self.hashing(ciphertext.len(), || audit::ciphertext_digest(&ciphertext)).to_vec(),
+        };
+        let evidence = self.install(|| self.append_to_audit(ctr, &cert::key_id(pk_bytes), &iv, &bound, kem_ct.as_bytes()))?;
+        let message = CoseEncrypt {
+            protected,
+            suite: self.suite,
+            iv,
+            ciphertext,
+            counter: ctr,
+            fingerprint: self.fingerprint,
+            kdf,
+            kem_ct: kem_ct.as_bytes().to_vec(),
+        };
+        Ok((message, evidence))
+    }
+
+    /// Decrypts a `COSE_Encrypt` from [`Engine::seal_cose`], recording a
+    /// `decrypt` event bound to its KEM ciphertext.
+    pub fn open_cose(&self, message: &CoseEncrypt, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let res = self.permit(Permission::Decrypt).and_then(|_| message.open(sk_bytes, context));
+        let plaintext = self.audited(OpType::Decrypt, &message.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &message.kem_ct)?;
+        Ok(plaintext)
+    }
+
+    /// [`Engine::checkpoint`] as a `COSE_Sign1` whose payload is the
+    /// checkpoint's canonical bytes.
+    pub fn checkpoint_cose(&self) -> CoreResult<Vec<u8>> {
+        sign1(&self.signing_key.1.unwrapped(), &self.head_checkpoint().to_bytes())
+    }
+}
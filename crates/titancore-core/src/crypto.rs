@@ -0,0 +1,223 @@
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::KdfParams;
+use crate::secret::Wiped;
+use aes_gcm_siv::{Aes256GcmSiv, Key, Nonce, aead::{Aead, AeadInPlace, KeyInit, Payload}};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305, XNonce};
+use pqcrypto_dilithium::dilithium5;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{PublicKey as KEMPublicKey, SecretKey as KEMSecretKey};
+use pqcrypto_traits::sign::{DetachedSignature, PublicKey as SignPublicKey, SecretKey as SignSecretKey};
+use zeroize::Zeroizing;
+
+/// Fresh Kyber-1024 recipient keypair as `(public, secret)` bytes.
+pub fn generate_keypair() -> (Vec<u8>, Zeroizing<Vec<u8>>) {
+    let (pk, sk) = kyber1024::keypair();
+    let sk = Wiped::new(sk);
+    (pk.as_bytes().to_vec(), Zeroizing::new(sk.as_bytes().to_vec()))
+}
+
+/// Fresh Dilithium5 signing keypair as `(public, secret)` bytes.
+pub fn generate_signing_keypair() -> (Vec<u8>, Zeroizing<Vec<u8>>) {
+    let (pk, sk) = dilithium5::keypair();
+    let sk = Wiped::new(sk);
+    (pk.as_bytes().to_vec(), Zeroizing::new(sk.as_bytes().to_vec()))
+}
+
+/// Detached Dilithium5 signature over `msg`.
+pub fn sign(sk_bytes: &[u8], msg: &[u8]) -> CoreResult<Vec<u8>> {
+    let sk = dilithium5::SecretKey::from_bytes(sk_bytes).map(Wiped::new).map_err(|_| CoreError::InvalidKey)?;
+    Ok(dilithium5::detached_sign(msg, &sk).as_bytes().to_vec())
+}
+
+pub fn verify_signature(pk_bytes: &[u8], msg: &[u8], sig: &[u8]) -> bool {
+    let (Ok(pk), Ok(sig)) = (dilithium5::PublicKey::from_bytes(pk_bytes), dilithium5::DetachedSignature::from_bytes(sig)) else {
+        return false;
+    };
+    dilithium5::verify_detached_signature(&sig, msg, &pk).is_ok()
+}
+
+// Kyber-1024 public key: four polynomials of 256 12-bit coefficients, then
+// the 32-byte matrix seed.
+const KYBER_Q: u16 = 3329;
+const POLYVEC_BYTES: usize = 4 * 384;
+const PUBLIC_KEY_BYTES: usize = POLYVEC_BYTES + 32;
+
+/// Parses a Kyber-1024 public key, checking its structure as well as its
+/// length; fails with [`CoreError::InvalidKey`].
+pub(crate) fn parse_public_key(bytes: &[u8]) -> CoreResult<kyber1024::PublicKey> {
+    check_public_key(bytes).map_err(|_| CoreError::InvalidKey)?;
+    kyber1024::PublicKey::from_bytes(bytes).map_err(|_| CoreError::InvalidKey)
+}
+
+/// Checks `bytes` as a Kyber-1024 recipient public key and, if given, that
+/// its [`key_id`](crate::cert::key_id) is `expected_fingerprint`. Unlike
+/// the checks made when sealing, a failure is a [`CoreError::KeyFormat`]
+/// saying what is wrong. Returns the key bytes.
+pub fn import_public_key(bytes: &[u8], expected_fingerprint: Option<&[u8; 32]>) -> CoreResult<Vec<u8>> {
+    check_public_key(bytes).map_err(CoreError::KeyFormat)?;
+    if let Some(expected) = expected_fingerprint {
+        let found = crate::cert::key_id(bytes);
+        if &found != expected {
+            return Err(CoreError::KeyFormat(format!("fingerprint mismatch: expected {}, found {}",
+                                                    hex::encode(expected), hex::encode(found))));
+        }
+    }
+    Ok(bytes.to_vec())
+}
+
+// Length, every coefficient of the encoded vector reduced mod q, and not
+// the all-zero vector, which no key generation produces.
+fn check_public_key(bytes: &[u8]) -> Result<(), String> {
+    if bytes.len() != PUBLIC_KEY_BYTES {
+        return Err(format!("expected {} bytes, got {}", PUBLIC_KEY_BYTES, bytes.len()));
+    }
+    let polyvec = &bytes[..POLYVEC_BYTES];
+    for (i, b) in polyvec.chunks_exact(3).enumerate() {
+        let coeffs = [u16::from(b[0]) | (u16::from(b[1] & 0x0f) << 8), (u16::from(b[1]) >> 4) | (u16::from(b[2]) << 4)];
+        for (j, c) in coeffs.into_iter().enumerate() {
+            if c >= KYBER_Q {
+                let n = 2 * i + j;
+                return Err(format!("coefficient {} of polynomial {} is {}, not below q = {}", n % 256, n / 256, c, KYBER_Q));
+            }
+        }
+    }
+    if polyvec.iter().all(|&b| b == 0) {
+        return Err("polynomial vector is all zero".into());
+    }
+    Ok(())
+}
+
+/// Equality of MACs, tags and digests in time that depends only on the
+/// lengths, so a mismatch does not show how many leading bytes matched.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && std::hint::black_box(a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y))) == 0
+}
+
+/// Checks a header's KEM ciphertext length before the ciphertext is read.
+pub(crate) fn check_kem_len(len: usize) -> CoreResult<()> {
+    if len != kyber1024::ciphertext_bytes() {
+        return Err(CoreError::Format("bad KEM ciphertext length"));
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_secret_key(bytes: &[u8]) -> CoreResult<Wiped<kyber1024::SecretKey>> {
+    kyber1024::SecretKey::from_bytes(bytes).map(Wiped::new).map_err(|_| CoreError::InvalidKey)
+}
+
+/// [`kyber1024::encapsulate`] with the shared secret wiped after use.
+pub(crate) fn encapsulate(pk: &kyber1024::PublicKey) -> (Wiped<kyber1024::SharedSecret>, kyber1024::Ciphertext) {
+    let (shared_secret, kem_ct) = kyber1024::encapsulate(pk);
+    (Wiped::new(shared_secret), kem_ct)
+}
+
+/// [`kyber1024::decapsulate`] with the shared secret wiped after use.
+pub(crate) fn decapsulate(kem_ct: &kyber1024::Ciphertext, sk: &kyber1024::SecretKey) -> Wiped<kyber1024::SharedSecret> {
+    Wiped::new(kyber1024::decapsulate(kem_ct, sk))
+}
+
+/// Session key = KDF(salt, shared secret || fingerprint || counter [|| message salt] [|| 0x01] [|| 0x02 || schema]
+/// [|| 0x03 || epoch] [|| 0x04 || constraints digest], info),
+/// with HKDF-SHA256 by default and `context` mixed into the info (see
+/// [`KdfParams::info_for`]).
+pub(crate) fn derive_session_key(shared_secret: &[u8], fingerprint: &[u8; 32], ctr: u64, params: &KdfParams, context: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut ikm = Zeroizing::new(Vec::with_capacity(104));
+    ikm.extend_from_slice(shared_secret);
+    ikm.extend_from_slice(fingerprint);
+    ikm.extend_from_slice(&ctr.to_be_bytes());
+    if let Some(salt) = &params.message_salt {
+        ikm.extend_from_slice(salt);
+    }
+    // Restricted marker; the odd length keeps it distinct from any salt.
+    if params.restricted {
+        ikm.push(1);
+    }
+    if let Some(schema) = &params.schema {
+        ikm.push(2);
+        ikm.extend_from_slice(schema);
+    }
+    if let Some(epoch) = &params.epoch {
+        ikm.push(3);
+        ikm.extend_from_slice(&epoch.to_bytes());
+    }
+    if let Some(constraints) = &params.constraints {
+        ikm.push(4);
+        ikm.extend_from_slice(&constraints.digest());
+    }
+
+    let mut sess_key = Zeroizing::new([0u8; 32]);
+    params.algorithm.derive(&ikm, &params.salt, &params.info_for(context)?, sess_key.as_mut())?;
+    Ok(sess_key)
+}
+
+/// Counter-prefixed nonce: 8 bytes of counter, 4 random bytes.
+pub(crate) fn counter_nonce(ctr: u64, key: &[u8], data: &[u8]) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(&ctr.to_be_bytes());
+    entropy::fill_hedged(&mut nonce[8..], key, data);
+    nonce
+}
+
+pub(crate) fn aead_seal(key: &[u8; 32], nonce: &[u8; 12], data: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = Aes256GcmSiv::new(Key::<Aes256GcmSiv>::from_slice(key));
+    cipher.encrypt(Nonce::from_slice(nonce), data).map_err(|_| CoreError::Encryption)
+}
+
+pub(crate) fn aead_open(key: &[u8; 32], nonce: &[u8; 12], ct: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = Aes256GcmSiv::new(Key::<Aes256GcmSiv>::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(nonce), ct).map_err(|_| CoreError::Decryption)
+}
+
+pub(crate) fn aead_seal_aad(key: &[u8; 32], nonce: &[u8; 12], data: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = Aes256GcmSiv::new(Key::<Aes256GcmSiv>::from_slice(key));
+    cipher.encrypt(Nonce::from_slice(nonce), Payload { msg: data, aad }).map_err(|_| CoreError::Encryption)
+}
+
+pub(crate) fn aead_open_aad(key: &[u8; 32], nonce: &[u8; 12], ct: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = Aes256GcmSiv::new(Key::<Aes256GcmSiv>::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ct, aad }).map_err(|_| CoreError::Decryption)
+}
+
+/// Encrypts `buf` in place and returns the tag.
+pub(crate) fn aead_seal_in_place(key: &[u8; 32], nonce: &[u8; 12], buf: &mut [u8], aad: &[u8]) -> CoreResult<[u8; 16]> {
+    let cipher = Aes256GcmSiv::new(Key::<Aes256GcmSiv>::from_slice(key));
+    let tag = cipher.encrypt_in_place_detached(Nonce::from_slice(nonce), aad, buf).map_err(|_| CoreError::Encryption)?;
+    Ok(tag.into())
+}
+
+/// Decrypts `buf` in place, so the plaintext is never copied elsewhere.
+pub(crate) fn aead_open_in_place(key: &[u8; 32], nonce: &[u8; 12], buf: &mut [u8], tag: &[u8; 16], aad: &[u8]) -> CoreResult<()> {
+    let cipher = Aes256GcmSiv::new(Key::<Aes256GcmSiv>::from_slice(key));
+    cipher.decrypt_in_place_detached(Nonce::from_slice(nonce), aad, buf, tag.into()).map_err(|_| CoreError::Decryption)
+}
+
+pub(crate) fn xchacha_seal(key: &[u8; 32], nonce: &[u8; 24], data: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.encrypt(XNonce::from_slice(nonce), data).map_err(|_| CoreError::Encryption)
+}
+
+pub(crate) fn xchacha_open(key: &[u8; 32], nonce: &[u8; 24], ct: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.decrypt(XNonce::from_slice(nonce), ct).map_err(|_| CoreError::Decryption)
+}
+
+pub(crate) fn xchacha_seal_aad(key: &[u8; 32], nonce: &[u8; 24], data: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.encrypt(XNonce::from_slice(nonce), Payload { msg: data, aad }).map_err(|_| CoreError::Encryption)
+}
+
+pub(crate) fn xchacha_open_aad(key: &[u8; 32], nonce: &[u8; 24], ct: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ct, aad }).map_err(|_| CoreError::Decryption)
+}
+
+pub(crate) fn chacha_seal(key: &[u8; 32], nonce: &[u8; 12], data: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.encrypt(chacha20poly1305::Nonce::from_slice(nonce), data).map_err(|_| CoreError::Encryption)
+}
+
+pub(crate) fn chacha_open(key: &[u8; 32], nonce: &[u8; 12], ct: &[u8]) -> CoreResult<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.decrypt(chacha20poly1305::Nonce::from_slice(nonce), ct).map_err(|_| CoreError::Decryption)
+}
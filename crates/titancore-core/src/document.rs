@@ -0,0 +1,317 @@
+//! Field-level protection of JSON documents, driven by JSONPath.
+//!
+//! A [`DocumentPolicy`] maps JSONPath expressions to a [`Protection`], and
+//! [`Engine::apply_policy`] rewrites every value each one selects:
+//!
+//! - `encrypt` seals the value's JSON to the recipient key, under the
+//!   value's normalized path (RFC 9535, e.g. `$['users'][0]['ssn']`) as
+//!   context, and replaces it with `"tcenc:"` and the base64 envelope. Open
+//!   it with [`Engine::open_with_context`] under the same path, so a field
+//!   moved elsewhere in the document does not open.
+//! - `blind_index` replaces the value with `"tcbi:"` and a keyed BLAKE3
+//!   hash of its JSON, bound to the rule's expression: equal values under
+//!   one rule give equal tokens, so they can still be looked up.
+//! - `mask` replaces the value with [`MASK`].
+//!
+//! Rules apply in the order added. Once a value is replaced, rules that
+//! select inside it find nothing there. Expressions support `$`, `.name`,
+//! `['name']`, `[n]` (negative from the end), `*`, `[*]` and `..`
+//! (descendants); filters and slices are not supported.
+
+use crate::engine::Engine;
+use crate::error::{CoreError, CoreResult};
+use crate::operation;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde_json::Value;
+use std::collections::HashSet;
+use zeroize::Zeroizing;
+
+/// Prefix of an encrypted field.
+pub const ENCRYPTED_PREFIX: &str = "tcenc:";
+/// Prefix of a blind index.
+pub const BLIND_INDEX_PREFIX: &str = "tcbi:";
+/// What a masked field becomes.
+pub const MASK: &str = "****";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    Encrypt,
+    BlindIndex,
+    Mask,
+}
+
+impl Protection {
+    pub const ALL: [Protection; 3] = [Protection::Encrypt, Protection::BlindIndex, Protection::Mask];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protection::Encrypt => "encrypt",
+            Protection::BlindIndex => "blind_index",
+            Protection::Mask => "mask",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Protection> {
+        Self::ALL.into_iter().find(|p| p.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    selector: Selector,
+    descendants: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// JSONPath expressions and the protection each applies.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentPolicy {
+    rules: Vec<(String, Vec<Segment>, Protection)>,
+}
+
+impl DocumentPolicy {
+    pub fn new() -> Self {
+        DocumentPolicy::default()
+    }
+
+    /// Adds a rule. Fails with [`CoreError::Config`] if `path` is not a
+    /// supported expression.
+    pub fn add(&mut self, path: &str, protection: Protection) -> CoreResult<()> {
+        let segments = parse_path(path).ok_or_else(|| CoreError::Config(format!("bad JSONPath {:?}", path)))?;
+        self.rules.push((path.to_string(), segments, protection));
+        Ok(())
+    }
+
+    /// Each rule's expression and protection, in the order added.
+    pub fn rules(&self) -> impl Iterator<Item = (&str, Protection)> {
+        self.rules.iter().map(|(path, _, protection)| (path.as_str(), *protection))
+    }
+
+    fn needs_index_key(&self) -> bool {
+        self.rules.iter().any(|(_, _, p)| *p == Protection::BlindIndex)
+    }
+}
+
+fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        let descendants = rest.starts_with("..");
+        rest = if descendants { &rest[2..] } else { rest };
+        let (selector, tail) = if let Some(body) = rest.strip_prefix('[') {
+            let end = body.find(']')?;
+            (parse_bracket(&body[..end])?, &body[end + 1..])
+        } else {
+            let body = if descendants { rest } else { rest.strip_prefix('.')? };
+            let end = body.find(['.', '[']).unwrap_or(body.len());
+            let (name, tail) = body.split_at(end);
+            let selector = match name {
+                "*" => Selector::Wildcard,
+                _ if valid_name(name) => Selector::Name(name.to_string()),
+                _ => return None,
+            };
+            (selector, tail)
+        };
+        segments.push(Segment { selector, descendants });
+        rest = tail;
+    }
+    Some(segments)
+}
+
+fn parse_bracket(body: &str) -> Option<Selector> {
+    let body = body.trim();
+    if body == "*" {
+        return Some(Selector::Wildcard);
+    }
+    for quote in ['\'', '"'] {
+        if let Some(name) = body.strip_prefix(quote).and_then(|b| b.strip_suffix(quote)) {
+            return (!name.contains(quote)).then(|| Selector::Name(name.to_string()));
+        }
+    }
+    body.parse().ok().map(Selector::Index)
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || !c.is_ascii())
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || !c.is_ascii())
+}
+
+// The children of `value` that `selector` picks, in document order.
+fn select(value: &Value, selector: &Selector) -> Vec<Step> {
+    match (value, selector) {
+        (Value::Object(map), Selector::Name(name)) if map.contains_key(name) => vec![Step::Key(name.clone())],
+        (Value::Array(items), Selector::Index(i)) => {
+            let i = if *i < 0 { items.len() as i64 + i } else { *i };
+            match usize::try_from(i) {
+                Ok(i) if i < items.len() => vec![Step::Index(i)],
+                _ => Vec::new(),
+            }
+        }
+        (Value::Object(map), Selector::Wildcard) => map.keys().cloned().map(Step::Key).collect(),
+        (Value::Array(items), Selector::Wildcard) => (0..items.len()).map(Step::Index).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn child<'a>(value: &'a Value, step: &Step) -> Option<&'a Value> {
+    match (value, step) {
+        (Value::Object(map), Step::Key(key)) => map.get(key),
+        (Value::Array(items), Step::Index(i)) => items.get(*i),
+        _ => None,
+    }
+}
+
+fn child_mut<'a>(value: &'a mut Value, step: &Step) -> Option<&'a mut Value> {
+    match (value, step) {
+        (Value::Object(map), Step::Key(key)) => map.get_mut(key),
+        (Value::Array(items), Step::Index(i)) => items.get_mut(*i),
+        _ => None,
+    }
+}
+
+// Picks `selector` at `value` and, for a descendant segment, at every node
+// below it, parents before children.
+fn descend(value: &Value, at: &[Step], selector: &Selector, descendants: bool, out: &mut Vec<Vec<Step>>) {
+    for step in select(value, selector) {
+        out.push([at, &[step]].concat());
+    }
+    if descendants {
+        for step in select(value, &Selector::Wildcard) {
+            if let Some(next) = child(value, &step) {
+                descend(next, &[at, &[step]].concat(), selector, true, out);
+            }
+        }
+    }
+}
+
+/// Locations `segments` selects in `doc`, in document order.
+fn locate(doc: &Value, segments: &[Segment]) -> Vec<Vec<Step>> {
+    let mut found = vec![Vec::new()];
+    for segment in segments {
+        let mut next = Vec::new();
+        for at in &found {
+            if let Some(value) = resolve(doc, at) {
+                descend(value, at, &segment.selector, segment.descendants, &mut next);
+            }
+        }
+        // Overlapping descendant segments can reach a node twice.
+        let mut seen = HashSet::new();
+        next.retain(|at| seen.insert(at.clone()));
+        found = next;
+    }
+    found
+}
+
+fn resolve<'a>(doc: &'a Value, at: &[Step]) -> Option<&'a Value> {
+    at.iter().try_fold(doc, child)
+}
+
+fn resolve_mut<'a>(doc: &'a mut Value, at: &[Step]) -> Option<&'a mut Value> {
+    at.iter().try_fold(doc, child_mut)
+}
+
+/// The normalized path (RFC 9535) of `at`, e.g. `$['users'][0]`.
+fn normalized(at: &[Step]) -> String {
+    let mut out = String::from("$");
+    for step in at {
+        match step {
+            Step::Key(key) => {
+                out.push_str("['");
+                for c in key.chars() {
+                    match c {
+                        '\'' => out.push_str("\\'"),
+                        '\\' => out.push_str("\\\\"),
+                        c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                    }
+                }
+                out.push_str("']");
+            }
+            Step::Index(i) => out.push_str(&format!("[{}]", i)),
+        }
+    }
+    out
+}
+
+fn blind_index(key: &[u8; 32], rule: &str, value: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new_keyed(key);
+    hasher.update(b"titancore blind index v1");
+    hasher.update(&(rule.len() as u64).to_be_bytes());
+    hasher.update(rule.as_bytes());
+    hasher.update(value);
+    format!("{}{}", BLIND_INDEX_PREFIX, hasher.finalize().to_hex())
+}
+
+impl Engine {
+    /// Rewrites each value of `document` that a rule of `policy` selects,
+    /// sealing encrypted fields to `pk_bytes` and hashing blind indexes
+    /// under `index_key`. Returns the new document and the evidence of each
+    /// sealed field, in the order they were sealed. The fields sealed share
+    /// one operation ID. Fails with [`CoreError::Config`] if `policy` has a
+    /// `blind_index` rule and no `index_key` is given; if any field fails
+    /// to seal, the whole call fails.
+    pub fn apply_policy(&self, document: &Value, policy: &DocumentPolicy, pk_bytes: &[u8], index_key: Option<&[u8; 32]>) -> CoreResult<(Value, Vec<String>)> {
+        if policy.needs_index_key() && index_key.is_none() {
+            return Err(CoreError::Config("blind_index rules need an index key".into()));
+        }
+        let _op = operation::implicit();
+        let mut doc = document.clone();
+        let mut evidence = Vec::new();
+        for (rule, segments, protection) in &policy.rules {
+            for at in locate(&doc, segments) {
+                let Some(value) = resolve_mut(&mut doc, &at) else { continue };
+                let (replacement, ev) = self.protect_value(*protection, value, rule, &normalized(&at), pk_bytes, index_key)?;
+                evidence.extend(ev);
+                *value = Value::String(replacement);
+            }
+        }
+        Ok((doc, evidence))
+    }
+
+    /// The protected form of `value` at normalized `path`, under `rule`,
+    /// and the evidence if it was sealed.
+    pub(crate) fn protect_value(&self, protection: Protection, value: &Value, rule: &str, path: &str, pk_bytes: &[u8],
+                                index_key: Option<&[u8; 32]>) -> CoreResult<(String, Option<String>)> {
+        let json = || serde_json::to_vec(value).map(Zeroizing::new).map_err(|_| CoreError::Format("unserializable field"));
+        match protection {
+            Protection::Mask => Ok((MASK.to_string(), None)),
+            Protection::BlindIndex => {
+                let key = index_key.ok_or_else(|| CoreError::Config("blind_index rules need an index key".into()))?;
+                Ok((blind_index(key, rule, &json()?), None))
+            }
+            Protection::Encrypt => {
+                let (envelope, evidence) = self.seal_with_context(&json()?, pk_bytes, path.as_bytes())?;
+                Ok((format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(envelope.to_bytes())), Some(evidence)))
+            }
+        }
+    }
+}
+
+/// The normalized path of the top-level member `name`, e.g. `$['ssn']`.
+pub(crate) fn member_path(name: &str) -> String {
+    normalized(&[Step::Key(name.to_string())])
+}
+
+/// Whether `value` has the form `protection` leaves behind.
+pub(crate) fn is_protected(protection: Protection, value: &Value) -> bool {
+    match (protection, value.as_str()) {
+        (Protection::Encrypt, Some(s)) => s.starts_with(ENCRYPTED_PREFIX),
+        (Protection::BlindIndex, Some(s)) => s.starts_with(BLIND_INDEX_PREFIX),
+        (Protection::Mask, Some(s)) => s == MASK,
+        _ => false,
+    }
+}
@@ -0,0 +1,121 @@
+//! Windows DPAPI protection for secrets kept on the local machine.
+//!
+//! [`protect`] encrypts a secret under a key Windows derives from the
+//! user's logon credentials ([`DpapiScope::User`]) or from the machine
+//! ([`DpapiScope::Machine`], readable by any account on it), so a key file
+//! is bound to the account or host without a passphrase. Optional entropy
+//! must be passed again to [`unprotect`]. `write_protected` and
+//! `read_protected` (feature `fs`) do the same for a file, e.g. a wrapping
+//! key or the counter snapshot a host persists between runs.
+//!
+//! The engine itself keeps no key file; this is for hosts that store the
+//! checkpoint keypair, escrow shares or [`crate::EngineState`] themselves.
+
+use crate::error::{CoreError, CoreResult};
+use std::ptr;
+use windows_sys::Win32::Foundation::LocalFree;
+use windows_sys::Win32::Security::Cryptography::{
+    CryptProtectData, CryptUnprotectData, CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+};
+use zeroize::Zeroizing;
+
+/// Whose credentials a secret is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DpapiScope {
+    /// The current user, on any machine their profile roams to.
+    #[default]
+    User,
+    /// Any account on this machine.
+    Machine,
+}
+
+impl DpapiScope {
+    pub fn name(self) -> &'static str {
+        match self {
+            DpapiScope::User => "user",
+            DpapiScope::Machine => "machine",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<DpapiScope> {
+        match name {
+            "user" => Some(DpapiScope::User),
+            "machine" => Some(DpapiScope::Machine),
+            _ => None,
+        }
+    }
+}
+
+/// Encrypts `data` under the DPAPI key for `scope`. Never prompts.
+pub fn protect(data: &[u8], scope: DpapiScope, entropy: &[u8]) -> CoreResult<Vec<u8>> {
+    let flags = match scope {
+        DpapiScope::User => CRYPTPROTECT_UI_FORBIDDEN,
+        DpapiScope::Machine => CRYPTPROTECT_UI_FORBIDDEN | CRYPTPROTECT_LOCAL_MACHINE,
+    };
+    let input = blob(data)?;
+    let entropy_blob = blob(entropy)?;
+    let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: ptr::null_mut() };
+    // SAFETY: the input blobs borrow live slices that DPAPI only reads;
+    // the output is allocated by DPAPI and released by `take_output`.
+    let ok = unsafe {
+        CryptProtectData(&input, ptr::null(), entropy_ptr(entropy, &entropy_blob), ptr::null(), ptr::null(), flags, &mut output)
+    };
+    if ok == 0 {
+        return Err(CoreError::Encryption);
+    }
+    Ok(take_output(output).to_vec())
+}
+
+/// Decrypts a blob from [`protect`]; fails with [`CoreError::Decryption`]
+/// under another account or machine, or with other entropy.
+pub fn unprotect(blob_bytes: &[u8], entropy: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+    let input = blob(blob_bytes)?;
+    let entropy_blob = blob(entropy)?;
+    let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: ptr::null_mut() };
+    // SAFETY: as in `protect`.
+    let ok = unsafe {
+        CryptUnprotectData(&input, ptr::null_mut(), entropy_ptr(entropy, &entropy_blob), ptr::null(), ptr::null(),
+                           CRYPTPROTECT_UI_FORBIDDEN, &mut output)
+    };
+    if ok == 0 {
+        return Err(CoreError::Decryption);
+    }
+    Ok(take_output(output))
+}
+
+/// Writes `data` to `path` protected for `scope`.
+#[cfg(feature = "fs")]
+pub fn write_protected(path: impl AsRef<std::path::Path>, data: &[u8], scope: DpapiScope) -> CoreResult<()> {
+    Ok(std::fs::write(path, protect(data, scope, &[])?)?)
+}
+
+/// Reads a file written by [`write_protected`].
+#[cfg(feature = "fs")]
+pub fn read_protected(path: impl AsRef<std::path::Path>) -> CoreResult<Zeroizing<Vec<u8>>> {
+    unprotect(&std::fs::read(path)?, &[])
+}
+
+fn blob(data: &[u8]) -> CoreResult<CRYPT_INTEGER_BLOB> {
+    let len = u32::try_from(data.len()).map_err(|_| CoreError::Config("DPAPI data exceeds 4 GiB".into()))?;
+    Ok(CRYPT_INTEGER_BLOB { cbData: len, pbData: data.as_ptr() as *mut u8 })
+}
+
+fn entropy_ptr(entropy: &[u8], blob: &CRYPT_INTEGER_BLOB) -> *const CRYPT_INTEGER_BLOB {
+    if entropy.is_empty() { ptr::null() } else { blob as *const _ }
+}
+
+// Copies a DPAPI output blob and wipes and frees the original.
+fn take_output(output: CRYPT_INTEGER_BLOB) -> Zeroizing<Vec<u8>> {
+    if output.pbData.is_null() {
+        return Zeroizing::new(Vec::new());
+    }
+    // SAFETY: on success DPAPI returns `cbData` bytes at `pbData`,
+    // allocated with LocalAlloc.
+    unsafe {
+        let data = std::slice::from_raw_parts_mut(output.pbData, output.cbData as usize);
+        let copy = Zeroizing::new(data.to_vec());
+        zeroize::Zeroize::zeroize(data);
+        LocalFree(output.pbData as _);
+        copy
+    }
+}
@@ -0,0 +1,1256 @@
+use crate::activation::SignedActivation;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::anchor::{Anchor, AnchorStats, AnchorWorker};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::audit::{BackgroundSink, QueueStats};
+use crate::attest::TpmQuote;
+use crate::audit::alarm::{Alarm, AlarmReason, SignedAlarm};
+use crate::audit::checkpoint::{Checkpoint, SignedCheckpoint};
+use crate::audit::genesis::{Genesis, SignedGenesis};
+use crate::audit::merkle::MerkleBatcher;
+use crate::audit::snapshot::{SignedSnapshot, Snapshot, SnapshotBatcher};
+use crate::audit::{self, AuditEntry, AuditQuery, AuditRecord, AuditSink, BatchRoot, CiphertextBinding, FailureReason, InclusionProof, OpType, Outcome, Recovery};
+use crate::clock::{Clock, SystemClock};
+use crate::cert;
+use crate::crypto;
+use crate::entropy;
+use crate::envelope::{Envelope, TAG_LEN};
+use crate::epoch;
+use crate::escrow;
+use crate::evidence::{EvidenceBundle, LinkData};
+use crate::error::{self, CoreError, CoreResult, ErrorContext};
+use crate::fips;
+use crate::idle::IdleKey;
+use crate::idempotency::IdempotencyCache;
+use crate::identity::Identity;
+use crate::kdf::{Kdf, KdfParams, Marks};
+use crate::kem::{self, Kem};
+use crate::operation;
+use crate::quorum::QuorumPolicy;
+use crate::ratelimit::{RateLimiter, SlidingWindow};
+use crate::rollback::HighWaterStore;
+use crate::rbac::{Permission, RolePolicy};
+use crate::revocation::RevocationChecker;
+use crate::stepup::{SensitiveOp, StepUp};
+use crate::suite::Suite;
+use crate::time::Instant;
+use crate::usage::{MemoryUsageStore, UsageCap, UsageStore};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::watchdog::{AlarmHandler, Watchdog, WatchdogStats};
+use parking_lot::Mutex;
+use pqcrypto_kyber::kyber1024;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+// --- GLOBAL STATE ---
+pub(crate) static OPERATION_CTR: AtomicU64 = AtomicU64::new(0);
+pub const RATE_LIMIT_WINDOW: u64 = 3;
+pub const MAX_BURST_REQUESTS: usize = 15;
+/// How often a waiting call retries the rate limiter.
+pub const RATE_LIMIT_POLL: Duration = Duration::from_millis(20);
+/// Highest operation counter the engine issues. The headroom below 2^64
+/// keeps batch reservations from wrapping the counter into reused nonces.
+pub const COUNTER_LIMIT: u64 = u64::MAX - (1 << 32);
+/// Largest plaintext sealed in one AEAD call (RFC 8452's AES-GCM-SIV limit).
+pub const MAX_MESSAGE_LEN: u64 = 1 << 36;
+/// Most plaintext bytes protected by one derived session key. Only streams,
+/// which seal many chunks under one key, can approach it.
+pub const MAX_KEY_VOLUME: u64 = 1 << 44;
+
+/// Construction-time engine settings.
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfig {
+    /// Worker threads for batch and streaming operations. `None` shares
+    /// rayon's global pool; ignored without the `parallel` feature.
+    pub worker_threads: Option<usize>,
+    /// Threads for hashing payloads of [`audit::PARALLEL_HASH_THRESHOLD`]
+    /// bytes or more (ciphertext digests and full-ciphertext links). `None`
+    /// hashes on the pool the operation runs on; ignored without the
+    /// `parallel` feature.
+    pub hash_threads: Option<usize>,
+    /// Hand audit entries to one background writer thread through a queue
+    /// of this many entries (see [`BackgroundSink`]) instead of writing on
+    /// the calling thread. Ignored on wasm32.
+    pub audit_queue: Option<usize>,
+    /// What the audit link hashes for each ciphertext.
+    pub ct_binding: CiphertextBinding,
+    /// Emit a Merkle root every this many audit entries. Sealed batches are
+    /// kept in memory so `prove_inclusion` can answer for this process.
+    pub merkle_batch: Option<usize>,
+    /// Sign a snapshot of the chain every this many audit entries, so the
+    /// log can be verified from the latest one (see
+    /// [`crate::audit::snapshot`]).
+    pub snapshot_every: Option<u64>,
+    /// Time source for audit timestamps, checkpoints and the rate limiter.
+    /// `None` uses [`SystemClock`].
+    pub clock: Option<Arc<dyn Clock>>,
+    /// AEAD and nonce scheme for envelopes. Streams always use their own
+    /// prefix-and-index nonces under AES-256-GCM-SIV.
+    pub suite: Suite,
+    /// KDF, salt and info for session keys; recorded in each header along
+    /// with a fresh per-message salt.
+    pub kdf: KdfParams,
+    /// [`Kem::id`] of the KEM envelopes are sealed under: a built-in one or
+    /// one added with [`kem::register`]. `None` uses Kyber-1024. Other
+    /// formats always use Kyber-1024.
+    pub kem: Option<u8>,
+    /// Shred source files with this many overwrite passes once
+    /// `seal_file` or `encrypt_tree` has encrypted them. Needs the `fs`
+    /// feature.
+    pub shred_sources: Option<u32>,
+    /// Where request quotas are kept. `None` uses an in-process
+    /// [`SlidingWindow`]; share one backend (e.g. `RedisRateLimiter`) to
+    /// give several engines one quota.
+    pub rate_limiter: Option<Arc<dyn RateLimiter>>,
+    /// Key the quota is counted under, e.g. a tenant id. `None` uses the
+    /// hex fingerprint.
+    pub rate_limit_key: Option<String>,
+    /// Over quota, wait up to this long for capacity instead of failing
+    /// with [`CoreError::RateLimited`] straight away. The calling thread
+    /// sleeps; ignored on wasm32. The window moves with the engine clock, so
+    /// under a [`crate::FixedClock`] a wait always runs to the deadline.
+    pub rate_limit_wait: Option<Duration>,
+    /// Where lifetime key usage is counted (see [`crate::usage`]). `None`
+    /// counts in memory for this engine only.
+    pub usage_store: Option<Arc<dyn UsageStore>>,
+    /// TPM quote over the PCRs the engine is bound to. Its PCR digest is
+    /// mixed into the fingerprint (see [`Engine::fingerprint_with_pcrs`])
+    /// and the quote is carried in [`Engine::attest`] statements.
+    pub tpm_quote: Option<TpmQuote>,
+    /// Allow only approved suites and KDFs and run the power-on self tests
+    /// before starting (see [`crate::fips`]). Entries are marked
+    /// [`AuditEntry::fips`].
+    pub fips_mode: bool,
+    /// Leave failed and denied operations out of the audit chain instead of
+    /// recording each with its [`FailureReason`].
+    pub omit_failures: bool,
+    /// Remember envelopes sealed under an idempotency key for this long
+    /// (see [`crate::idempotency`]). `None` turns idempotency keys off.
+    pub idempotency_window: Option<Duration>,
+    /// License the engine runs under, recorded in the genesis record of a
+    /// new log (see [`crate::audit::genesis`]). `None` records an empty one.
+    pub license: Option<String>,
+    /// Identity keypair for checkpoints, attestations and channels. `None`
+    /// generates an ephemeral one, which verifiers cannot pin across
+    /// restarts.
+    pub identity: Option<Identity>,
+    /// Dilithium5 key the vendor signs activations with (see
+    /// [`crate::activation`]). With it set, every operation that needs a
+    /// [`Permission`] fails with [`CoreError::Unauthorized`] until a valid
+    /// activation is installed. `None` refuses every activation and gates
+    /// nothing.
+    pub vendor_key: Option<Vec<u8>>,
+    /// Dilithium5 keys, besides this engine's own identity key, whose
+    /// signatures on decryption constraints [`Engine::check_constraints`]
+    /// accepts: the identities of the engines that seal to this one, and
+    /// this engine's own earlier identities.
+    pub constraint_signers: Vec<Vec<u8>>,
+    /// Where the signed high-water mark of the chain is kept (see
+    /// [`crate::rollback`]). `None` does not check for rollback. Needs
+    /// [`EngineConfig::identity`]. Over a sink that is not
+    /// [durable](AuditSink::durable) on append, the mark only moves on
+    /// [`Engine::flush_audit`] and [`Engine::close`].
+    pub high_water: Option<Arc<dyn HighWaterStore>>,
+    /// Start even if the chain is behind the high-water mark, moving the
+    /// counter past it and recording the override.
+    pub allow_rollback: bool,
+}
+
+/// What [`Engine::info`] reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineInfo {
+    /// Crate version.
+    pub version: &'static str,
+    pub fingerprint: [u8; 32],
+    pub suite: Suite,
+    pub kdf: Kdf,
+    /// [`Kem::id`] envelopes are sealed under.
+    pub kem: u8,
+    pub ct_binding: CiphertextBinding,
+    pub fips_mode: bool,
+    /// See [`Engine::config_hash`].
+    pub config_hash: [u8; 32],
+    pub closed: bool,
+}
+
+/// Binding-agnostic engine: KEM + AEAD sealing with a chained audit trail
+/// written to a pluggable [`AuditSink`].
+pub struct Engine {
+    pub(crate) fingerprint: [u8;32],
+    rate_limiter: Arc<dyn RateLimiter>,
+    rate_limit_key: String,
+    rate_limit_wait: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    pub(crate) sink: Arc<dyn AuditSink>,
+    pub(crate) chain: Arc<Mutex<ChainHead>>,
+    pub(crate) merkle: Option<Mutex<MerkleBatcher>>,
+    pub(crate) snapshots: Option<Mutex<SnapshotBatcher>>,
+    recovery: Option<Recovery>,
+    pub(crate) ct_binding: CiphertextBinding,
+    pub(crate) suite: Suite,
+    pub(crate) kdf: KdfParams,
+    pub(crate) kem: Arc<dyn Kem>,
+    pub(crate) signing_key: (Vec<u8>, IdleKey),
+    pub(crate) revocation: Option<RevocationChecker>,
+    pub(crate) escrow: Option<Vec<u8>>,
+    pub(crate) quorum: Option<QuorumPolicy>,
+    pub(crate) step_up: Option<StepUp>,
+    /// Installed role policy and its id; see [`crate::rbac`].
+    pub(crate) roles: Option<(u64, RolePolicy)>,
+    pub(crate) key_limits: HashMap<[u8; 32], SlidingWindow>,
+    pub(crate) usage: Arc<dyn UsageStore>,
+    pub(crate) usage_caps: HashMap<[u8; 32], UsageCap>,
+    pub(crate) usage_lock: Mutex<()>,
+    pub(crate) tpm_quote: Option<TpmQuote>,
+    pub(crate) fips_mode: bool,
+    pub(crate) omit_failures: bool,
+    pub(crate) idempotency_window: Option<Duration>,
+    pub(crate) idempotency: Mutex<IdempotencyCache>,
+    genesis: Option<SignedGenesis>,
+    pub(crate) vendor_key: Option<Vec<u8>>,
+    pub(crate) constraint_signers: Vec<Vec<u8>>,
+    pub(crate) activation: Option<SignedActivation>,
+    pub(crate) high_water: Option<Arc<dyn HighWaterStore>>,
+    // Sequence number of the last mark stored.
+    pub(crate) high_water_seq: Mutex<u64>,
+    pub(crate) allow_rollback: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    anchoring: Option<Anchoring>,
+    #[cfg(not(target_arch = "wasm32"))]
+    watchdog: Option<Watchdog>,
+    #[cfg(feature = "parallel")]
+    pub(crate) pool: Option<rayon::ThreadPool>,
+    #[cfg(feature = "parallel")]
+    pub(crate) hash_pool: Option<rayon::ThreadPool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) queue: Option<Arc<BackgroundSink>>,
+    closed: bool,
+    /// Process the engine was built in; see [`Engine::ensure_open`].
+    pid: u32,
+    #[cfg(feature = "fs")]
+    pub(crate) shred_sources: Option<u32>,
+}
+
+#[derive(Default)]
+pub(crate) struct ChainHead {
+    pub(crate) head: [u8;32],
+    pub(crate) counter: u64,
+    pub(crate) seq: u64,
+    pub(crate) last_ms: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Anchoring {
+    worker: AnchorWorker,
+    every: u64,
+    since_last: AtomicU64,
+}
+
+impl Engine {
+    pub fn new(hw_info: &str, seed: &str, sink: Box<dyn AuditSink>) -> CoreResult<Self> {
+        Self::with_config(hw_info, seed, sink, EngineConfig::default())
+    }
+
+    /// Builds an engine over `sink`. An empty sink starts a new log, whose
+    /// first entry records a signed [`SignedGenesis`]; otherwise the chain
+    /// resumes where the sink left off.
+    pub fn with_config(hw_info: &str, seed: &str, sink: Box<dyn AuditSink>, config: EngineConfig) -> CoreResult<Self> {
+        let license = config.license.clone().unwrap_or_default();
+        let mut engine = Self::build(hw_info, seed, sink, config)?;
+        let overridden = engine.check_rollback()?;
+        if engine.recovery.is_none() {
+            engine.write_genesis(license)?;
+        }
+        engine.record_rollback_override(overridden)?;
+        Ok(engine)
+    }
+
+    /// [`Engine::with_config`] without starting a log over an empty sink,
+    /// for callers that continue a chain from elsewhere.
+    pub(crate) fn build(hw_info: &str, seed: &str, sink: Box<dyn AuditSink>, config: EngineConfig) -> CoreResult<Self> {
+        let fingerprint = match &config.tpm_quote {
+            Some(quote) => Self::fingerprint_with_pcrs(hw_info, seed, quote.pcr_digest()),
+            None => Self::fingerprint_for(hw_info, seed),
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let (sink, queue): (Box<dyn AuditSink>, _) = match config.audit_queue {
+            Some(capacity) => {
+                let queue = Arc::new(BackgroundSink::new(sink, capacity)?);
+                (Box::new(queue.clone()), Some(queue))
+            }
+            None => (sink, None),
+        };
+        let sink: Arc<dyn AuditSink> = sink.into();
+
+        // Resume the chain (and keep counters moving forward) from whatever
+        // the sink already holds.
+        let recovery = sink.resume()?;
+        let head = recovery.as_ref().map_or_else(ChainHead::default, |r| ChainHead {
+            head: r.head,
+            counter: r.counter,
+            seq: r.seq,
+            last_ms: r.timestamp_ms,
+        });
+        if let Some(r) = &recovery {
+            OPERATION_CTR.fetch_max(r.counter, Ordering::Relaxed);
+        }
+        let genesis = match recovery {
+            Some(_) => sink.genesis()?,
+            None => None,
+        };
+
+        config.kdf.validate()?;
+        if config.high_water.is_some() && config.identity.is_none() {
+            return Err(CoreError::Config("a high-water mark needs a persistent identity".into()));
+        }
+        let kem_id = config.kem.unwrap_or(kem::KYBER1024);
+        let kem = kem::find(kem_id).ok_or_else(|| CoreError::Config(format!("no KEM registered as id {}", kem_id)))?;
+        if config.fips_mode {
+            fips::check_approved(config.suite, config.kdf.algorithm)?;
+            fips::check_approved_kem(kem.as_ref())?;
+            fips::self_test()?;
+        } else {
+            entropy::self_test()?;
+        }
+
+        #[cfg(feature = "parallel")]
+        let pool = match config.worker_threads {
+            Some(n) => Some(rayon::ThreadPoolBuilder::new().num_threads(n).thread_name(|i| format!("titan-worker-{}", i)).build()
+                .map_err(|e| CoreError::Config(e.to_string()))?),
+            None => None,
+        };
+        #[cfg(feature = "parallel")]
+        let hash_pool = match config.hash_threads {
+            Some(n) => Some(rayon::ThreadPoolBuilder::new().num_threads(n).thread_name(|i| format!("titan-hash-{}", i)).build()
+                .map_err(|e| CoreError::Config(e.to_string()))?),
+            None => None,
+        };
+
+        Ok(Engine {
+            fingerprint,
+            rate_limiter: config.rate_limiter.unwrap_or_else(|| Arc::new(SlidingWindow::default())),
+            rate_limit_key: config.rate_limit_key.unwrap_or_else(|| hex::encode(fingerprint)),
+            rate_limit_wait: config.rate_limit_wait,
+            clock: config.clock.unwrap_or_else(|| Arc::new(SystemClock::new())),
+            sink,
+            chain: Arc::new(Mutex::new(head)),
+            merkle: config.merkle_batch.map(|n| Mutex::new(MerkleBatcher::new(n))),
+            snapshots: config.snapshot_every.map(|n| Mutex::new(SnapshotBatcher::new(n))),
+            recovery,
+            ct_binding: config.ct_binding,
+            suite: config.suite,
+            kdf: config.kdf,
+            kem,
+            signing_key: config.identity.unwrap_or_else(Identity::generate).into_keypair(),
+            revocation: None,
+            escrow: None,
+            quorum: None,
+            step_up: None,
+            roles: None,
+            key_limits: HashMap::new(),
+            usage: config.usage_store.unwrap_or_else(|| Arc::new(MemoryUsageStore::new())),
+            usage_caps: HashMap::new(),
+            usage_lock: Mutex::new(()),
+            tpm_quote: config.tpm_quote,
+            fips_mode: config.fips_mode,
+            omit_failures: config.omit_failures,
+            idempotency_window: config.idempotency_window,
+            idempotency: Mutex::new(IdempotencyCache::default()),
+            genesis,
+            vendor_key: config.vendor_key,
+            constraint_signers: config.constraint_signers,
+            activation: None,
+            high_water: config.high_water,
+            high_water_seq: Mutex::new(0),
+            allow_rollback: config.allow_rollback,
+            #[cfg(not(target_arch = "wasm32"))]
+            anchoring: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            watchdog: None,
+            #[cfg(feature = "parallel")]
+            pool,
+            #[cfg(feature = "parallel")]
+            hash_pool,
+            #[cfg(not(target_arch = "wasm32"))]
+            queue,
+            #[cfg(feature = "fs")]
+            shred_sources: config.shred_sources,
+            closed: false,
+            pid: entropy::process_id(),
+        })
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    pub fn fingerprint(&self) -> &[u8;32] {
+        &self.fingerprint
+    }
+
+    /// Version, identity and algorithm settings, e.g. for a health check.
+    pub fn info(&self) -> EngineInfo {
+        EngineInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            fingerprint: self.fingerprint,
+            suite: self.suite,
+            kdf: self.kdf.algorithm,
+            kem: self.kem.id(),
+            ct_binding: self.ct_binding,
+            fips_mode: self.fips_mode,
+            config_hash: self.config_hash(),
+            closed: self.closed,
+        }
+    }
+
+    pub fn fips_mode(&self) -> bool {
+        self.fips_mode
+    }
+
+    /// Hash of the settings that shape what the engine writes: suite, KDF
+    /// parameters, ciphertext binding, Merkle batch size, FIPS mode, the
+    /// PCR digest of the TPM quote and the KEM, unless it is Kyber-1024.
+    pub fn config_hash(&self) -> [u8;32] {
+        let mut hasher = blake3::Hasher::new_derive_key("titancore engine config v1");
+        let mut field = |bytes: &[u8]| {
+            hasher.update(&(bytes.len() as u32).to_be_bytes());
+            hasher.update(bytes);
+        };
+        field(self.suite.name().as_bytes());
+        field(self.kdf.algorithm.name().as_bytes());
+        field(&self.kdf.salt);
+        field(&self.kdf.info);
+        field(&[matches!(self.ct_binding, CiphertextBinding::Digest) as u8, self.fips_mode as u8]);
+        field(&self.merkle.as_ref().map_or(0, |m| m.lock().batch_size() as u64).to_be_bytes());
+        field(self.tpm_quote.as_ref().map_or(&[][..], |q| q.pcr_digest()));
+        // Left out for Kyber-1024, so hashes from before the choice existed
+        // still match.
+        if self.kem.id() != kem::KYBER1024 {
+            field(self.kem.name().as_bytes());
+        }
+        hasher.finalize().into()
+    }
+
+    /// The record the audit log starts with: written by this engine if it
+    /// started the log, read back from the sink otherwise. `None` for a
+    /// sink that keeps none, or a log started before genesis records.
+    pub fn genesis(&self) -> Option<&SignedGenesis> {
+        self.genesis.as_ref()
+    }
+
+    // Signs the genesis record, stores it and records it as the first
+    // chain entry.
+    fn write_genesis(&mut self, license: String) -> CoreResult<()> {
+        let genesis = Genesis {
+            fingerprint: self.fingerprint,
+            public_key: self.signing_key.0.clone(),
+            license,
+            config_hash: self.config_hash(),
+            timestamp_ms: self.clock.now_ms(),
+        }.sign(&self.signing_key.1.unwrapped())?;
+        self.sink.append_genesis(&genesis)?;
+        self.record_event(OpType::Genesis, Outcome::Success, &genesis.genesis.digest())?;
+        self.genesis = Some(genesis);
+        Ok(())
+    }
+
+    /// In FIPS mode, fails with [`CoreError::Config`] for data sealed under
+    /// a suite or KDF that is not approved.
+    pub(crate) fn check_approved(&self, suite: Suite, kdf: Kdf) -> CoreResult<()> {
+        match self.fips_mode {
+            true => fips::check_approved(suite, kdf),
+            false => Ok(()),
+        }
+    }
+
+    /// [`Engine::check_approved`] for an envelope, its KEM included.
+    pub(crate) fn check_envelope_approved(&self, envelope: &Envelope) -> CoreResult<()> {
+        self.check_approved(envelope.suite, envelope.kdf.algorithm)?;
+        match self.fips_mode {
+            true => fips::check_approved_kem(kem::get(envelope.kem)?.as_ref()),
+            false => Ok(()),
+        }
+    }
+
+    /// Hardware fingerprint an engine built from `hw_info` and `seed` will
+    /// have, e.g. to label a sink before the engine exists.
+    pub fn fingerprint_for(hw_info: &str, seed: &str) -> [u8;32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(hw_info.as_bytes());
+        hasher.update(seed.as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// What startup recovery found in the sink, if it holds prior entries.
+    pub fn recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
+    }
+
+    /// Audit entries matching `query`; needs a sink that supports queries,
+    /// such as `SqliteSink` (indexed) or `FileSink` (a full scan).
+    pub fn query_audit(&self, query: &AuditQuery) -> CoreResult<Vec<AuditRecord>> {
+        self.sink.query(query)
+    }
+
+    /// Snapshots the sink holds, oldest first (see
+    /// [`EngineConfig::snapshot_every`]).
+    pub fn audit_snapshots(&self) -> CoreResult<Vec<SignedSnapshot>> {
+        self.sink.snapshots()
+    }
+
+    /// Current chain head.
+    pub fn chain_head(&self) -> [u8;32] {
+        self.chain.lock().head
+    }
+
+    /// Consults `checker` before encrypting to a recipient and in
+    /// [`Engine::verify_checkpoint`]. Replaces any previous checker.
+    /// Recorded as a `rekey` event. Needs a [`SensitiveOp::PolicyChange`]
+    /// grant under step-up, and the `admin` permission once a role policy
+    /// is installed.
+    pub fn set_revocation_checker(&mut self, checker: RevocationChecker) -> CoreResult<()> {
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, b"revocation", res)?;
+        self.record_event(OpType::Rekey, Outcome::Success, b"revocation")?;
+        self.revocation = Some(checker);
+        Ok(())
+    }
+
+    /// Checks the caller may encrypt, parses a recipient public key, checks
+    /// it is not revoked and counts one use protecting `bytes` against its
+    /// key limit and usage cap.
+    pub(crate) fn recipient_key(&self, pk_bytes: &[u8], bytes: u64) -> CoreResult<kyber1024::PublicKey> {
+        self.admit_recipient(pk_bytes, bytes, |pk_bytes| self.parse_recipient(pk_bytes))
+    }
+
+    /// [`Engine::recipient_key`] for a public key of the engine's KEM, which
+    /// envelopes are sealed under.
+    pub(crate) fn envelope_recipient(&self, pk_bytes: &[u8], bytes: u64) -> CoreResult<()> {
+        self.admit_recipient(pk_bytes, bytes, |pk_bytes| {
+            self.kem.check_public_key(pk_bytes)?;
+            self.ensure_not_revoked(pk_bytes)
+        })
+    }
+
+    fn admit_recipient<T>(&self, pk_bytes: &[u8], bytes: u64, parse: impl FnOnce(&[u8]) -> CoreResult<T>) -> CoreResult<T> {
+        let key_id = cert::key_id(pk_bytes);
+        let res = self.permit(Permission::Encrypt).and_then(|_| {
+            let pk = parse(pk_bytes)?;
+            self.check_key_limit(OpType::Encrypt, &key_id)?;
+            self.count_key_use(OpType::Encrypt, &key_id, bytes)?;
+            Ok(pk)
+        });
+        res.inspect_err(|e| error::note(e, ErrorContext { key_id: Some(key_id), ..ErrorContext::default() }))
+    }
+
+    /// [`Engine::recipient_key`] for callers that already counted the use.
+    pub(crate) fn parse_recipient(&self, pk_bytes: &[u8]) -> CoreResult<kyber1024::PublicKey> {
+        let pk = crypto::parse_public_key(pk_bytes)?;
+        self.ensure_not_revoked(pk_bytes)?;
+        Ok(pk)
+    }
+
+    /// [`SignedCheckpoint::verify`], failing with [`CoreError::Revoked`] if
+    /// `trusted_pk` has been revoked.
+    pub fn verify_checkpoint(&self, checkpoint: &SignedCheckpoint, trusted_pk: &[u8]) -> CoreResult<bool> {
+        self.ensure_not_revoked(trusted_pk)?;
+        Ok(checkpoint.verify(trusted_pk))
+    }
+
+    /// Replaces the checkpoint signing key. Each engine starts with a fresh
+    /// ephemeral Dilithium5 key unless given [`EngineConfig::identity`];
+    /// install a long-lived one so verifiers can pin it across restarts.
+    /// The change is recorded as a `rekey` audit event. To replace a key
+    /// verifiers already pin, use [`Engine::rotate_identity`], which
+    /// cross-signs the change. Envelopes this engine constrained under the
+    /// old key open again only with it in
+    /// [`EngineConfig::constraint_signers`]. Needs a
+    /// [`SensitiveOp::PolicyChange`] grant under step-up, and the `admin`
+    /// permission once a role policy is installed.
+    pub fn set_signing_keypair(&mut self, public_key: &[u8], secret_key: &[u8]) -> CoreResult<()> {
+        let res = self.consume_step_up(SensitiveOp::PolicyChange).and_then(|_| Identity::new(public_key, secret_key));
+        let identity = self.audited(OpType::Rekey, public_key, res)?;
+        self.signing_key = identity.into_keypair();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.sync_watchdog_key();
+        self.record_event(OpType::Rekey, Outcome::Success, public_key)?;
+        Ok(())
+    }
+
+    /// Fresh keypair for the engine's KEM, recorded as a `keygen` audit
+    /// event bound to the public key.
+    pub fn generate_keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+        let (pk, sk) = self.kem.keypair()?;
+        self.record_key_event(OpType::Keygen, Outcome::Success, &pk, &cert::key_id(&pk))?;
+        Ok((pk, sk))
+    }
+
+    /// Public half of the checkpoint signing key.
+    pub fn checkpoint_public_key(&self) -> &[u8] {
+        &self.signing_key.0
+    }
+
+    /// Both halves of the checkpoint signing key, for installing on another
+    /// engine with [`Engine::set_signing_keypair`]. Needs a
+    /// [`SensitiveOp::KeyExport`] grant under step-up; recorded as a
+    /// `keygen` event bound to the public key.
+    pub fn export_signing_keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+        let res = self.consume_step_up(SensitiveOp::KeyExport);
+        self.audited(OpType::Keygen, &self.signing_key.0, res)?;
+        self.record_event(OpType::Keygen, Outcome::Success, &self.signing_key.0)?;
+        Ok((self.signing_key.0.clone(), Zeroizing::new(self.signing_key.1.unwrapped().to_vec())))
+    }
+
+    /// Signs the current chain head and, with Merkle batching, the most
+    /// recently sealed batch root. Checkpoints attest the log itself and are
+    /// not logged as `sign` events.
+    pub fn checkpoint(&self) -> CoreResult<SignedCheckpoint> {
+        self.ensure_open()?;
+        self.head_checkpoint().sign(&self.signing_key.0, &self.signing_key.1.unwrapped())
+    }
+
+    /// Flushes the audit trail (publishing to the anchor, if any), signs a
+    /// final checkpoint of the head and returns it, then drops the secret
+    /// half of the checkpoint signing key and the step-up secrets. Afterwards every
+    /// operation that records evidence or signs fails with
+    /// [`CoreError::Config`].
+    pub fn close(&mut self) -> CoreResult<SignedCheckpoint> {
+        self.ensure_open()?;
+        self.flush_audit()?;
+        let checkpoint = self.checkpoint()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            // The worker still publishes the pending final checkpoint.
+            self.anchoring = None;
+            self.watchdog = None;
+        }
+        self.closed = true;
+        self.signing_key.1 = IdleKey::default();
+        self.step_up = None;
+        Ok(checkpoint)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Fails once the engine is closed, and in a forked child: the child's
+    /// copy shares the parent's chain head and sink, so anything it recorded
+    /// would fork the log. Build a new engine in each process instead.
+    pub(crate) fn ensure_open(&self) -> CoreResult<()> {
+        if self.closed {
+            return Err(CoreError::Config("engine is closed".into()));
+        }
+        if self.pid != entropy::process_id() {
+            return Err(CoreError::Config(format!("engine belongs to process {}; open a new one in this process", self.pid)));
+        }
+        Ok(())
+    }
+
+    /// Unsigned [`Engine::checkpoint`] body, for other signature encodings.
+    pub(crate) fn head_checkpoint(&self) -> Checkpoint {
+        let (head, counter, batch) = self.chain_snapshot();
+        self.unsigned_checkpoint(head, counter, batch)
+    }
+
+    // Head, counter and latest sealed batch, read under the chain lock so
+    // the batch never runs ahead of the head.
+    fn chain_snapshot(&self) -> ([u8;32], u64, Option<BatchRoot>) {
+        let chain = self.chain.lock();
+        let batch = self.merkle.as_ref().and_then(|m| m.lock().latest().cloned());
+        (chain.head, chain.counter, batch)
+    }
+
+    /// Evidence for the entry with `counter`: the entry, its inclusion proof
+    /// and a checkpoint signing its batch root. Pass the operation's
+    /// envelope to also bind the entry to it. `None` under the same
+    /// conditions as [`Engine::prove_inclusion`].
+    pub fn export_evidence(&self, counter: u64, envelope: Option<&Envelope>) -> CoreResult<Option<EvidenceBundle>> {
+        let Some(merkle) = &self.merkle else { return Ok(None) };
+        let Some((entry, proof, batch)) = merkle.lock().evidence(counter) else { return Ok(None) };
+        let link = match envelope {
+            Some(env) => {
+                if env.counter != counter || env.fingerprint != self.fingerprint
+                    || env.evidence_hash_with(&entry.prev, self.ct_binding) != entry.curr {
+                    return Err(CoreError::Format("envelope does not match audit entry"));
+                }
+                let bound = match self.ct_binding {
+                    CiphertextBinding::Full => env.ciphertext.clone(),
+                    CiphertextBinding::Digest => self.hashing(env.ciphertext.len(), || audit::ciphertext_digest(&env.ciphertext)).to_vec(),
+                };
+                Some(LinkData { kem_ct: env.kem_ct.clone(), nonce: env.nonce.clone(), bound })
+            }
+            None => None,
+        };
+        let (head, head_counter, _) = self.chain_snapshot();
+        let checkpoint = self.sign_checkpoint(head, head_counter, Some(batch))?;
+        Ok(Some(EvidenceBundle { entry, proof, checkpoint, link }))
+    }
+
+    pub(crate) fn sign_checkpoint(&self, head: [u8;32], counter: u64, batch: Option<BatchRoot>) -> CoreResult<SignedCheckpoint> {
+        self.ensure_open()?;
+        self.unsigned_checkpoint(head, counter, batch).sign(&self.signing_key.0, &self.signing_key.1.unwrapped())
+    }
+
+    fn unsigned_checkpoint(&self, head: [u8;32], counter: u64, batch: Option<BatchRoot>) -> Checkpoint {
+        Checkpoint { fingerprint: self.fingerprint, counter, head, timestamp: self.clock.now_ms() / 1000, batch }
+    }
+
+    /// Publishes a signed checkpoint to `anchor` every `every` audit entries
+    /// and on each [`Engine::flush_audit`]. Publishing runs on its own thread
+    /// and never blocks sealing; when the anchor falls behind, only the
+    /// newest pending checkpoint is kept. Replaces any previous anchor.
+    ///
+    /// Checkpoints can cover entries a relaxed [`crate::SyncPolicy`] has not
+    /// yet synced; after a crash the anchored head may then be ahead of the
+    /// log, which is visible rather than silent.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_anchor(&mut self, anchor: Box<dyn Anchor>, every: u64) -> CoreResult<()> {
+        let worker = AnchorWorker::spawn(anchor)?;
+        self.anchoring = Some(Anchoring { worker, every: every.max(1), since_last: AtomicU64::new(0) });
+        Ok(())
+    }
+
+    /// Publication counts for the installed anchor, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn anchor_stats(&self) -> Option<AnchorStats> {
+        self.anchoring.as_ref().map(|a| a.worker.stats())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn offer_checkpoint(&self, force: bool) -> CoreResult<()> {
+        let Some(anchoring) = &self.anchoring else { return Ok(()) };
+        if !force && anchoring.since_last.fetch_add(1, Ordering::Relaxed) + 1 < anchoring.every {
+            return Ok(());
+        }
+        anchoring.since_last.store(0, Ordering::Relaxed);
+        let (head, counter, batch) = self.chain_snapshot();
+        anchoring.worker.offer(self.sign_checkpoint(head, counter, batch)?);
+        Ok(())
+    }
+
+    /// Reads the last `tail` entries back from the sink and compares them
+    /// with the chain head held in memory. On divergence, signs an
+    /// [`Alarm`] with the checkpoint key, stores it with
+    /// [`AuditSink::append_alarm`] and returns it. Seals wait while the
+    /// check runs. Fails with [`CoreError::Config`] for a sink that cannot
+    /// read back its entries; assumes this engine is the sink's only writer.
+    pub fn check_chain(&self, tail: usize) -> CoreResult<Option<SignedAlarm>> {
+        self.ensure_open()?;
+        let probe = self.probe();
+        let Some(alarm) = probe.inspect(tail)? else { return Ok(None) };
+        let signed = alarm.sign(&self.signing_key.0, &self.signing_key.1.unwrapped())?;
+        probe.store(&signed)?;
+        Ok(Some(signed))
+    }
+
+    /// Runs [`Engine::check_chain`] every `interval` on its own thread,
+    /// passing each new alarm to `handler` after storing it. A divergence
+    /// that persists is reported once. Replaces any previous watchdog;
+    /// stopped by [`Engine::stop_watchdog`] and [`Engine::close`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_watchdog(&mut self, interval: Duration, tail: usize, handler: Box<dyn AlarmHandler>) -> CoreResult<()> {
+        self.ensure_open()?;
+        if let Err(e @ CoreError::Config(_)) = self.sink.tail(0) {
+            return Err(e);
+        }
+        self.watchdog = Some(Watchdog::spawn(self.probe(), self.signing_key.clone(), interval, tail, handler)?);
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_watchdog(&mut self) {
+        self.watchdog = None;
+    }
+
+    /// Check and alarm counts for the running watchdog, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watchdog_stats(&self) -> Option<WatchdogStats> {
+        self.watchdog.as_ref().map(|w| w.stats())
+    }
+
+    // The watchdog signs with its own copy of the checkpoint key.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn sync_watchdog_key(&self) {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_signing_key(self.signing_key.clone());
+        }
+    }
+
+    pub(crate) fn probe(&self) -> ChainProbe {
+        ChainProbe { fingerprint: self.fingerprint, chain: self.chain.clone(), sink: self.sink.clone(), clock: self.clock.clone() }
+    }
+
+    /// Forces buffered or not-yet-synced audit entries to storage, closing
+    /// the current Merkle batch first so every flushed entry has a root.
+    pub fn flush_audit(&self) -> CoreResult<()> {
+        if let Some(merkle) = &self.merkle {
+            let _chain = self.chain.lock();
+            if let Some(root) = merkle.lock().seal() {
+                self.sink.append_root(&root)?;
+            }
+        }
+        self.sink.flush()?;
+        self.advance_high_water()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.offer_checkpoint(true)?;
+        Ok(())
+    }
+
+    /// Background writer queue metrics, if [`EngineConfig::audit_queue`] is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn audit_queue_stats(&self) -> Option<QueueStats> {
+        self.queue.as_ref().map(|q| q.stats())
+    }
+
+    /// Inclusion proof for the entry with `counter` and the root of its
+    /// batch. `None` if Merkle batching is off, the counter is unknown to
+    /// this process, or its batch has not been sealed yet.
+    pub fn prove_inclusion(&self, counter: u64) -> Option<(InclusionProof, BatchRoot)> {
+        self.merkle.as_ref()?.lock().prove(counter)
+    }
+
+    /// Encrypts `data` to the Kyber public key and records the operation in
+    /// the audit chain. Returns the envelope and the new chain head (hex).
+    /// Denials and failures are recorded too.
+    pub fn seal(&self, data: &[u8], pk_bytes: &[u8]) -> CoreResult<(Envelope, String)> {
+        self.seal_with_context(data, pk_bytes, &[])
+    }
+
+    /// Like [`Engine::seal`], mixing a domain-separation `context` (e.g.
+    /// `b"backups"`) into the session key. Unlike AAD it is not carried in
+    /// the envelope: only a recipient passing the same context can open it.
+    pub fn seal_with_context(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Envelope, String)> {
+        let res = self.try_seal(data, pk_bytes, context, Marks::default());
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    pub(crate) fn try_seal(&self, data: &[u8], pk_bytes: &[u8], context: &[u8], marks: Marks) -> CoreResult<(Envelope, String)> {
+        // Rate limit check
+        self.check_rate_limit()?;
+
+        let current_ctr = self.next_counters(1)?;
+        self.envelope_recipient(pk_bytes, data.len() as u64)?;
+        let (envelope, digest) = self.install(|| self.seal_one(current_ctr, pk_bytes, data, context, marks))?;
+
+        // Audit log
+        let bound = digest.as_ref().map_or(&envelope.ciphertext[..], |d| &d[..]);
+        let evidence = self.install(|| self.append_to_audit(current_ctr, &cert::key_id(pk_bytes), &envelope.nonce, bound, &envelope.kem_ct))?;
+        Ok((envelope, evidence))
+    }
+
+    /// [`Engine::seal_with_context`], returning the authentication tag apart
+    /// from the envelope (see [`Envelope::detach_tag`]).
+    pub fn seal_detached(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Envelope, [u8; TAG_LEN], String)> {
+        let (mut envelope, evidence) = self.seal_with_context(data, pk_bytes, context)?;
+        let tag = envelope.detach_tag()?;
+        Ok((envelope, tag, evidence))
+    }
+
+    /// Like [`Engine::open_with_context`] for an envelope whose tag was
+    /// detached on sealing.
+    pub fn open_detached(&self, envelope: &Envelope, tag: &[u8; TAG_LEN], sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let mut envelope = envelope.clone();
+        envelope.attach_tag(tag);
+        self.open_with_context(&envelope, sk_bytes, context)
+    }
+
+    /// Seals every item to the same recipient. Encryption runs on the worker
+    /// pool; results and audit entries keep the input order. A batch counts
+    /// as one request against the rate limit.
+    pub fn seal_many<T: AsRef<[u8]> + Sync>(&self, items: &[T], pk_bytes: &[u8]) -> CoreResult<Vec<CoreResult<(Envelope, String)>>> {
+        self.seal_many_with_context(items, pk_bytes, &[])
+    }
+
+    pub fn seal_many_with_context<T: AsRef<[u8]> + Sync>(&self, items: &[T], pk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<CoreResult<(Envelope, String)>>> {
+        let _op = operation::implicit();
+        let res = self.try_seal_many(items, pk_bytes, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_seal_many<T: AsRef<[u8]> + Sync>(&self, items: &[T], pk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<CoreResult<(Envelope, String)>>> {
+        self.check_rate_limit()?;
+        self.envelope_recipient(pk_bytes, items.iter().map(|d| d.as_ref().len() as u64).sum())?;
+        let base_ctr = self.next_counters(items.len() as u64)?;
+        let key_id = cert::key_id(pk_bytes);
+
+        let sealed = self.par_map(items, |i, data| self.seal_one(base_ctr + i as u64, pk_bytes, data.as_ref(), context, Marks::default()));
+        Ok(sealed.into_iter().map(|res| {
+            let (envelope, digest) = res?;
+            let bound = digest.as_ref().map_or(&envelope.ciphertext[..], |d| &d[..]);
+            let evidence = self.install(|| self.append_to_audit(envelope.counter, &key_id, &envelope.nonce, bound, &envelope.kem_ct))?;
+            Ok((envelope, evidence))
+        }).collect())
+    }
+
+    /// Decrypts an envelope sealed to this engine's recipients and records
+    /// the attempt, successful or not, as a `decrypt` event bound to the
+    /// envelope's KEM ciphertext.
+    pub fn open(&self, envelope: &Envelope, sk_bytes: &[u8]) -> CoreResult<Vec<u8>> {
+        self.open_with_context(envelope, sk_bytes, &[])
+    }
+
+    pub fn open_with_context(&self, envelope: &Envelope, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let res = self.permit(Permission::Decrypt)
+            .and_then(|_| self.check_envelope_approved(envelope))
+            .and_then(|_| self.check_constraints(envelope))
+            .and_then(|_| envelope.open_unconstrained(sk_bytes, context))
+            .inspect_err(|e| error::note(e, ErrorContext { suite: Some(envelope.suite), ..ErrorContext::default() }));
+        let plaintext = self.audited(OpType::Decrypt, &envelope.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &envelope.kem_ct)?;
+        Ok(plaintext)
+    }
+
+    /// Decrypts every envelope with the same secret key. Decryption runs on
+    /// the worker pool; results and `decrypt` events, one per envelope as
+    /// [`Engine::open`] records them, keep the input order. Only a
+    /// malformed secret key fails the whole batch.
+    pub fn open_many(&self, envelopes: &[Envelope], sk_bytes: &[u8]) -> CoreResult<Vec<CoreResult<Vec<u8>>>> {
+        self.open_many_with_context(envelopes, sk_bytes, &[])
+    }
+
+    pub fn open_many_with_context(&self, envelopes: &[Envelope], sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<CoreResult<Vec<u8>>>> {
+        let _op = operation::implicit();
+        let res = self.permit(Permission::Decrypt).and_then(|_| kem::check_secret_key(sk_bytes));
+        self.audited(OpType::Decrypt, &[], res)?;
+        let opened = self.par_map(envelopes, |_, envelope| {
+            self.check_envelope_approved(envelope)
+                .and_then(|_| self.check_constraints(envelope))
+                .and_then(|_| envelope.open_unconstrained(sk_bytes, context))
+        });
+        let opened = opened.into_iter().zip(envelopes).map(|(res, envelope)| {
+            let plaintext = self.audited(OpType::Decrypt, &envelope.kem_ct, res)?;
+            self.record_event(OpType::Decrypt, Outcome::Success, &envelope.kem_ct)?;
+            Ok(plaintext)
+        }).collect::<Vec<_>>();
+        error::clear_context();
+        Ok(opened)
+    }
+
+    /// Appends an entry for an operation that produced no ciphertext (see
+    /// [`audit::event_hash`]). Returns the new chain head (hex).
+    pub fn record_event(&self, op: OpType, outcome: Outcome, subject: &[u8]) -> CoreResult<String> {
+        let ctr = self.next_counters(1)?;
+        self.record_event_at(ctr, op, outcome, subject)
+    }
+
+    /// [`Engine::record_event`] under a counter already reserved with
+    /// [`Engine::next_counters`], e.g. one carried in the output it records.
+    pub(crate) fn record_event_at(&self, ctr: u64, op: OpType, outcome: Outcome, subject: &[u8]) -> CoreResult<String> {
+        self.append_link(ctr, op, outcome, None, None, |prev| audit::event_hash(prev, ctr, &self.fingerprint, op, outcome, subject))
+    }
+
+    /// [`Engine::record_event`] for an operation on the key `key_id`, which
+    /// the entry carries as [`AuditEntry::key_id`].
+    pub(crate) fn record_key_event(&self, op: OpType, outcome: Outcome, subject: &[u8], key_id: &[u8; 32]) -> CoreResult<String> {
+        let ctr = self.next_counters(1)?;
+        self.append_link(ctr, op, outcome, None, Some(*key_id), |prev| audit::event_hash(prev, ctr, &self.fingerprint, op, outcome, subject))
+    }
+
+    /// Appends an entry for a failed `op` with its `reason` (see
+    /// [`audit::failure_hash`]), unless [`EngineConfig::omit_failures`] is
+    /// set. Returns the entry's counter, `None` if it was omitted.
+    pub fn record_failure(&self, op: OpType, outcome: Outcome, reason: FailureReason, subject: &[u8]) -> CoreResult<Option<u64>> {
+        self.append_failure(op, outcome, reason, subject, None)
+    }
+
+    fn append_failure(&self, op: OpType, outcome: Outcome, reason: FailureReason, subject: &[u8], key_id: Option<[u8; 32]>)
+                      -> CoreResult<Option<u64>> {
+        if self.omit_failures {
+            return Ok(None);
+        }
+        let ctr = self.next_counters(1)?;
+        self.append_link(ctr, op, outcome, Some(reason), key_id, |prev| {
+            audit::failure_hash(prev, ctr, &self.fingerprint, op, outcome, reason, subject)
+        })?;
+        Ok(Some(ctr))
+    }
+
+    /// Records `err`, a refusal of `op` over the key `key_id`, with the key
+    /// as subject and key ID, and hands it back.
+    pub(crate) fn refuse_key<R>(&self, op: OpType, outcome: Outcome, reason: FailureReason, key_id: &[u8; 32], err: CoreError) -> CoreResult<R> {
+        let counter = self.append_failure(op, outcome, reason, key_id, Some(*key_id))?;
+        let operation_id = counter.and(operation::last_id());
+        error::note(&err, ErrorContext { op: Some(op), counter, operation_id, suite: Some(self.suite), key_id: Some(*key_id) });
+        Err(err)
+    }
+
+    /// Records a failed `op` before handing the error back, and notes the
+    /// operation and entry for [`error::take_context`]. Errors from the
+    /// audit sink itself are passed through unrecorded.
+    pub fn audited<R>(&self, op: OpType, subject: &[u8], res: CoreResult<R>) -> CoreResult<R> {
+        match &res {
+            Err(CoreError::Storage(_)) => {}
+            Err(e) => {
+                let counter = self.record_failure(op, Outcome::of(e), FailureReason::of(e), subject).ok().flatten();
+                let operation_id = counter.and(operation::last_id());
+                error::note(e, ErrorContext { op: Some(op), counter, operation_id, suite: Some(self.suite), key_id: None });
+            }
+            Ok(_) => error::clear_context(),
+        }
+        res
+    }
+
+    /// Reserves `n` consecutive operation counters and returns the first.
+    /// Fails with [`CoreError::RekeyRequired`] rather than pass
+    /// [`COUNTER_LIMIT`], and once the engine is closed or in a forked child.
+    pub(crate) fn next_counters(&self, n: u64) -> CoreResult<u64> {
+        self.ensure_open()?;
+        OPERATION_CTR
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| c.checked_add(n).filter(|&end| end <= COUNTER_LIMIT))
+            .map(|prev| prev + 1)
+            .map_err(|_| CoreError::RekeyRequired("operation counter exhausted"))
+    }
+
+    /// Returns the envelope and, under [`CiphertextBinding::Digest`], the
+    /// ciphertext digest the audit link should bind.
+    pub(crate) fn seal_one(&self, ctr: u64, pk_bytes: &[u8], data: &[u8], context: &[u8], marks: Marks) -> CoreResult<(Envelope, Option<[u8;32]>)> {
+        if data.len() as u64 > MAX_MESSAGE_LEN {
+            return Err(CoreError::RekeyRequired("message exceeds the per-key volume; use a stream"));
+        }
+
+        // PQC Key Encapsulation (Kyber-1024 unless configured otherwise)
+        let (mut shared_secret, pqc_ct) = self.kem.encapsulate(pk_bytes)?;
+
+        // Derive AES session key using HKDF
+        let kdf = KdfParams {
+            restricted: marks.restricted,
+            schema: marks.schema,
+            epoch: marks.epoch.map(|(epoch, _)| epoch),
+            constraints: marks.constraints.cloned(),
+            ..self.kdf.for_message(&shared_secret, &pqc_ct)
+        };
+        if let Some((_, key)) = marks.epoch {
+            shared_secret = epoch::bind(&shared_secret, key);
+        }
+        let sess_key = crypto::derive_session_key(&shared_secret, &self.fingerprint, ctr, &kdf, context)?;
+
+        // AES-256-GCM-SIV encryption
+        let nonce = self.suite.nonce(ctr, &sess_key, data);
+        let ct = self.suite.seal(&sess_key, &nonce, data)?;
+        let digest = match self.ct_binding {
+            CiphertextBinding::Full => None,
+            CiphertextBinding::Digest => Some(self.hashing(ct.len(), || audit::ciphertext_digest(&ct))),
+        };
+        let escrow = self.escrow.as_ref()
+            .map(|pk| escrow::wrap(pk, &sess_key, &self.fingerprint, ctr, &pqc_ct))
+            .transpose()?;
+
+        let envelope = Envelope {
+            suite: self.suite,
+            kdf,
+            kem: self.kem.id(),
+            counter: ctr,
+            fingerprint: self.fingerprint,
+            kem_ct: pqc_ct,
+            nonce,
+            ciphertext: ct,
+            escrow,
+        };
+        Ok((envelope, digest))
+    }
+
+    /// Runs `f` inside the worker pool so nested rayon work (e.g. parallel
+    /// hashing) uses it rather than the global pool. `f` acts for the
+    /// calling thread's caller and operation.
+    pub(crate) fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = &self.pool {
+            let (caller, op) = (crate::rbac::current(), operation::current());
+            let (out, last) = pool.install(|| crate::rbac::scoped(caller, || operation::scoped(op, f)));
+            operation::set_last_id(last);
+            return out;
+        }
+        f()
+    }
+
+    /// Runs `f`, which hashes `len` payload bytes, on the hashing pool if
+    /// one is configured and the payload is large enough to hash in
+    /// parallel.
+    pub(crate) fn hashing<R: Send>(&self, len: usize, f: impl FnOnce() -> R + Send) -> R {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = self.hash_pool.as_ref().filter(|_| len >= audit::PARALLEL_HASH_THRESHOLD) {
+            return pool.install(f);
+        }
+        let _ = len;
+        f()
+    }
+
+    /// Maps `f` over `items` on the worker pool, preserving order, acting
+    /// for the calling thread's caller and operation.
+    pub(crate) fn par_map<T: Sync, R: Send>(&self, items: &[T], f: impl Fn(usize, &T) -> R + Sync + Send) -> Vec<R> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            let (caller, op) = (crate::rbac::current(), operation::current());
+            let run = || items.par_iter().enumerate()
+                .map(|(i, t)| crate::rbac::scoped(caller.clone(), || operation::scoped(op.clone(), || f(i, t)).0)).collect();
+            match &self.pool {
+                Some(pool) => pool.install(run),
+                None => run(),
+            }
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            items.iter().enumerate().map(|(i, t)| f(i, t)).collect()
+        }
+    }
+
+    /// Takes one request from the quota, waiting up to
+    /// [`EngineConfig::rate_limit_wait`] for one, or fails with
+    /// [`CoreError::RateLimited`].
+    pub(crate) fn check_rate_limit(&self) -> CoreResult<()> {
+        let wait = if cfg!(target_arch = "wasm32") { None } else { self.rate_limit_wait };
+        let deadline = wait.map(|w| Instant::now() + w);
+        loop {
+            if self.rate_limiter.acquire(&self.rate_limit_key, self.clock.monotonic())? {
+                return Ok(());
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now < deadline => std::thread::sleep((deadline - now).min(RATE_LIMIT_POLL)),
+                _ => return Err(CoreError::RateLimited),
+            }
+        }
+    }
+
+    /// How long over-quota calls wait for capacity, if they wait.
+    pub fn rate_limit_wait(&self) -> Option<Duration> {
+        self.rate_limit_wait
+    }
+
+    pub fn set_rate_limit_wait(&mut self, wait: Option<Duration>) {
+        self.rate_limit_wait = wait;
+    }
+
+    /// Key this engine's requests are counted under.
+    pub fn rate_limit_key(&self) -> &str {
+        &self.rate_limit_key
+    }
+
+    /// Appends the `encrypt` entry for a ciphertext sealed to the recipient
+    /// key `key_id`.
+    pub(crate) fn append_to_audit(&self, ctr: u64, key_id: &[u8; 32], nonce: &[u8], ct: &[u8], pqc_ct: &[u8]) -> CoreResult<String> {
+        self.append_link(ctr, OpType::Encrypt, Outcome::Success, None, Some(*key_id), |prev| {
+            self.hashing(ct.len(), || audit::entry_hash(prev, ctr, &self.fingerprint, pqc_ct, nonce, ct))
+        })
+    }
+
+    fn append_link(&self, ctr: u64, op: OpType, outcome: Outcome, reason: Option<FailureReason>, key_id: Option<[u8; 32]>,
+                   link: impl FnOnce(&[u8;32]) -> [u8;32]) -> CoreResult<String> {
+        let mut chain_guard = self.chain.lock();
+        let prev_h = chain_guard.head;
+
+        let curr_h = link(&prev_h);
+
+        let now_ms = self.clock.now_ms();
+        let (operation_id, correlation_id) = operation::for_entry();
+        let entry = AuditEntry {
+            prev: prev_h,
+            curr: curr_h,
+            counter: ctr,
+            timestamp_ms: now_ms,
+            op,
+            outcome,
+            seq: chain_guard.seq + 1,
+            clock_regressed: now_ms < chain_guard.last_ms,
+            fips: self.fips_mode,
+            role: self.caller_role(),
+            reason,
+            operation_id: Some(operation_id),
+            correlation_id,
+            key_id,
+        };
+        self.sink.append(&entry)?;
+        if let Some(snapshots) = &self.snapshots {
+            if let Some((first_seq, root)) = snapshots.lock().push(&entry) {
+                let snapshot = Snapshot {
+                    fingerprint: self.fingerprint,
+                    first_seq,
+                    seq: entry.seq,
+                    counter: chain_guard.counter.max(ctr),
+                    head: curr_h,
+                    root,
+                    timestamp_ms: now_ms,
+                };
+                self.sink.append_snapshot(&snapshot.sign(&self.signing_key.0, &self.signing_key.1.unwrapped())?)?;
+            }
+        }
+        if let Some(merkle) = &self.merkle {
+            if let Some(root) = merkle.lock().push(entry) {
+                self.sink.append_root(&root)?;
+            }
+        }
+
+        *chain_guard = ChainHead {
+            head: curr_h,
+            counter: chain_guard.counter.max(ctr),
+            seq: chain_guard.seq + 1,
+            last_ms: chain_guard.last_ms.max(now_ms),
+        };
+        drop(chain_guard);
+        if self.sink.durable() {
+            self.advance_high_water()?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.offer_checkpoint(false)?;
+        Ok(hex::encode(curr_h))
+    }
+}
+
+/// The parts of an engine a chain check reads, shared with the watchdog
+/// thread.
+pub(crate) struct ChainProbe {
+    fingerprint: [u8;32],
+    chain: Arc<Mutex<ChainHead>>,
+    sink: Arc<dyn AuditSink>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ChainProbe {
+    /// Compares the stored tail with the head under the chain lock, so no
+    /// entry is in flight. Sink errors other than [`CoreError::Config`]
+    /// become an [`AlarmReason::Unreadable`] alarm.
+    pub(crate) fn inspect(&self, tail: usize) -> CoreResult<Option<Alarm>> {
+        let chain = self.chain.lock();
+        let alarm = |reason, found: Option<&AuditEntry>| Alarm {
+            fingerprint: self.fingerprint,
+            timestamp_ms: self.clock.now_ms(),
+            reason,
+            expected_head: chain.head,
+            expected_seq: chain.seq,
+            found_head: found.map_or([0u8; 32], |e| e.curr),
+            found_seq: found.map_or(0, |e| e.seq),
+        };
+        let entries = match self.sink.flush().and_then(|()| self.sink.tail(tail.max(1))) {
+            Ok(entries) => entries,
+            Err(e @ CoreError::Config(_)) => return Err(e),
+            Err(_) => return Ok(Some(alarm(AlarmReason::Unreadable, None))),
+        };
+        let Some(last) = entries.last() else {
+            return Ok((chain.head != [0u8; 32]).then(|| alarm(AlarmReason::LogMissing, None)));
+        };
+        // Links as in [`crate::LogVerifier`]; a zero `prev` at seq 0 is a
+        // segment from before chains resumed across restarts.
+        let broken = entries.windows(2).find(|w| {
+            let linked = w[1].prev == w[0].curr || (w[1].prev == [0u8; 32] && w[1].seq == 0);
+            !linked || (w[1].seq != 0 && w[1].seq != w[0].seq + 1)
+        });
+        if let Some(w) = broken {
+            return Ok(Some(alarm(AlarmReason::TailBroken, Some(&w[1]))));
+        }
+        if last.curr != chain.head || (last.seq != 0 && last.seq != chain.seq) {
+            return Ok(Some(alarm(AlarmReason::HeadMismatch, Some(last))));
+        }
+        Ok(None)
+    }
+
+    /// Stores `alarm` outside the chain it reports on.
+    pub(crate) fn store(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        self.sink.append_alarm(alarm)
+    }
+}
@@ -0,0 +1,177 @@
+//! Optional local DRBG that mixes extra entropy sources with the OS source,
+//! for deployments that do not want to rely on any single one.
+//!
+//! The generator is keyed BLAKE3: each request outputs
+//! `BLAKE3-XOF(key, "out" | n)` and replaces the key with
+//! `BLAKE3(key, "next" | n)`, so earlier output cannot be recovered from a
+//! later state. It reseeds every [`RESEED_INTERVAL`] bytes from 32
+//! health-tested OS bytes plus 32 bytes from each configured source, and
+//! before the first request in a forked child, so parent and child never
+//! share output. A source failing at reseed fails the request.
+
+use crate::error::{CoreError, CoreResult};
+use crate::secret;
+use zeroize::Zeroizing;
+
+/// Output bytes between reseeds.
+pub const RESEED_INTERVAL: u64 = 1 << 16;
+const SEED_CONTEXT: &str = "titancore entropy mixing v1";
+const SOURCE_BYTES: usize = 32;
+
+/// Sources mixed in besides the OS.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntropySources {
+    /// The x86-64 `RDSEED` instruction.
+    pub rdseed: bool,
+    /// TPM 2.0 `GetRandom` through a resource-manager device such as
+    /// `/dev/tpmrm0`.
+    pub tpm_device: Option<String>,
+    /// A file of operator-supplied seed material, read once when mixing is
+    /// enabled.
+    pub seed_file: Option<String>,
+}
+
+pub(super) struct Mixer {
+    sources: EntropySources,
+    seed_material: Zeroizing<Vec<u8>>,
+    key: Zeroizing<[u8; 32]>,
+    requests: u64,
+    since_reseed: u64,
+    /// Process the key was last reseeded in; a forked child holds a copy
+    /// of its parent's key and must reseed before its first draw.
+    pid: u32,
+}
+
+impl Mixer {
+    /// Checks every configured source once and reads the seed file.
+    pub(super) fn new(sources: EntropySources) -> CoreResult<Self> {
+        if sources.rdseed {
+            rdseed(&mut [0u8; SOURCE_BYTES])?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(device) = &sources.tpm_device {
+            tpm_get_random(device, &mut [0u8; SOURCE_BYTES])
+                .map_err(|e| CoreError::Config(format!("TPM {}: {}", device, e)))?;
+        }
+        #[cfg(target_arch = "wasm32")]
+        if sources.tpm_device.is_some() {
+            return Err(CoreError::Config("TPM entropy is not available on this platform".into()));
+        }
+        let seed_material = match &sources.seed_file {
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(path) => Zeroizing::new(std::fs::read(path)
+                .map_err(|e| CoreError::Config(format!("seed file {}: {}", path, e)))?),
+            #[cfg(target_arch = "wasm32")]
+            Some(_) => return Err(CoreError::Config("seed files are not available on this platform".into())),
+            None => Zeroizing::new(Vec::new()),
+        };
+        Ok(Mixer { sources, seed_material, key: Zeroizing::new([0u8; 32]), requests: 0, since_reseed: RESEED_INTERVAL, pid: 0 })
+    }
+
+    pub(super) fn sources(&self) -> &EntropySources {
+        &self.sources
+    }
+
+    pub(super) fn needs_reseed(&self) -> bool {
+        self.since_reseed >= RESEED_INTERVAL || self.pid != super::process_id()
+    }
+
+    /// New key = BLAKE3-derive_key(old key | OS | RDSEED | TPM | seed file).
+    pub(super) fn reseed(&mut self, os: &[u8]) -> CoreResult<()> {
+        let mut hasher = blake3::Hasher::new_derive_key(SEED_CONTEXT);
+        hasher.update(self.key.as_ref());
+        hasher.update(os);
+        let mut buf = Zeroizing::new([0u8; SOURCE_BYTES]);
+        if self.sources.rdseed {
+            rdseed(buf.as_mut())?;
+            hasher.update(buf.as_ref());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(device) = &self.sources.tpm_device {
+            tpm_get_random(device, buf.as_mut())?;
+            hasher.update(buf.as_ref());
+        }
+        hasher.update(&self.seed_material);
+        secret::finish_blake3(hasher, self.key.as_mut());
+        self.since_reseed = 0;
+        self.pid = super::process_id();
+        Ok(())
+    }
+
+    pub(super) fn generate(&mut self, out: &mut [u8]) {
+        let n = self.requests.to_be_bytes();
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update(b"out");
+        hasher.update(&n);
+        secret::finish_blake3(hasher, out);
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update(b"next");
+        hasher.update(&n);
+        secret::finish_blake3(hasher, self.key.as_mut());
+        self.requests += 1;
+        self.since_reseed += out.len() as u64;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rdseed(out: &mut [u8]) -> CoreResult<()> {
+    if !std::arch::is_x86_feature_detected!("rdseed") {
+        return Err(CoreError::Config("RDSEED is not supported by this CPU".into()));
+    }
+    for chunk in out.chunks_mut(8) {
+        // RDSEED reports underflow when the conditioner is drained; retry
+        // briefly before giving up.
+        let word = (0..128).find_map(|_| {
+            let mut word = 0u64;
+            // SAFETY: the CPU supports RDSEED (checked above).
+            (unsafe { rdseed_step(&mut word) } == 1).then_some(word)
+        });
+        let word = word.ok_or(CoreError::DegradedEntropy("RDSEED returned no data"))?;
+        chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "rdseed")]
+unsafe fn rdseed_step(word: &mut u64) -> i32 {
+    std::arch::x86_64::_rdseed64_step(word)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn rdseed(_out: &mut [u8]) -> CoreResult<()> {
+    Err(CoreError::Config("RDSEED is only available on x86-64".into()))
+}
+
+// TPM2_GetRandom: `tag(2) = TPM_ST_NO_SESSIONS | size(4) | command_code(4) |
+// bytes_requested(2)`; the response carries a TPM2B_DIGEST after a 10-byte
+// header. A TPM returns at most one digest's worth per call.
+#[cfg(not(target_arch = "wasm32"))]
+fn tpm_get_random(device: &str, out: &mut [u8]) -> CoreResult<()> {
+    use std::io::{Read, Write};
+    let fail = || CoreError::DegradedEntropy("TPM GetRandom failed");
+    let mut tpm = std::fs::OpenOptions::new().read(true).write(true).open(device)
+        .map_err(|_| CoreError::DegradedEntropy("TPM unavailable"))?;
+    let mut filled = 0;
+    while filled < out.len() {
+        let want = (out.len() - filled) as u16;
+        let mut cmd = Vec::with_capacity(12);
+        cmd.extend_from_slice(&0x8001u16.to_be_bytes());
+        cmd.extend_from_slice(&12u32.to_be_bytes());
+        cmd.extend_from_slice(&0x0000_017bu32.to_be_bytes());
+        cmd.extend_from_slice(&want.to_be_bytes());
+        tpm.write_all(&cmd).map_err(|_| fail())?;
+        let mut resp = [0u8; 4096];
+        let n = tpm.read(&mut resp).map_err(|_| fail())?;
+        if n < 12 || resp[6..10] != [0, 0, 0, 0] {
+            return Err(fail());
+        }
+        let got = (u16::from_be_bytes([resp[10], resp[11]]) as usize).min(out.len() - filled);
+        if got == 0 || n < 12 + got {
+            return Err(fail());
+        }
+        out[filled..filled + got].copy_from_slice(&resp[12..12 + got]);
+        filled += got;
+    }
+    Ok(())
+}
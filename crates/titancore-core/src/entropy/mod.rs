@@ -0,0 +1,232 @@
+//! Health-tested access to the OS entropy source.
+//!
+//! Every nonce and salt the engine draws goes through [`fill`], which runs the
+//! SP 800-90B continuous tests (repetition count and adaptive proportion) over
+//! the output bytes. A startup battery runs when an engine is built and again
+//! every [`RETEST_INTERVAL`] bytes. Any failure, including the source itself
+//! failing, latches the process into a degraded state: later draws fail with
+//! [`CoreError::DegradedEntropy`] instead of silently continuing. Kyber
+//! encapsulation draws from the OS through its own RNG and is not covered.
+//!
+//! With [`enable_mixing`], draws come from a local DRBG seeded from the
+//! tested OS output and extra sources instead (see [`mixing`]).
+//!
+//! Nonces and message salts are drawn with [`fill_hedged`] instead, which
+//! hashes the fresh bytes together with the key and message they are for.
+//! A source that repeats itself without tripping the tests then still
+//! yields distinct nonces for distinct messages, and one that fails leaves
+//! them derived from key and message alone: deterministic, so equal
+//! messages under one key show as equal, but never a reused nonce for two
+//! different messages. Key generation still needs [`fill`]. Dilithium5
+//! signing in this build derives its randomness from the key and message
+//! and draws nothing.
+
+use crate::error::{CoreError, CoreResult};
+use crate::secret;
+use parking_lot::Mutex;
+use zeroize::Zeroizing;
+
+pub mod mixing;
+pub use mixing::EntropySources;
+use mixing::Mixer;
+
+/// Bytes drawn by the startup battery.
+pub const STARTUP_SAMPLES: usize = 1024;
+/// Bytes drawn between repeats of the startup battery.
+pub const RETEST_INTERVAL: u64 = 1 << 20;
+// Cutoffs for H = 8 bits of entropy per byte at a false-alarm rate of
+// α = 2^-40, by the SP 800-90B formulas: 1 + ceil(40 / H) for the
+// repetition count test, and 1 + CRITBINOM(W, 2^-H, 1 - α) for the adaptive
+// proportion test over a window of W = 512. (The standard's table gives 13
+// for this window, but at α = 2^-20.)
+const RCT_CUTOFF: u32 = 6;
+const APT_WINDOW: u32 = 512;
+const APT_CUTOFF: u32 = 19;
+const HEDGE_CONTEXT: &str = "titancore hedged nonce v1";
+
+/// Snapshot of the health-test state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntropyHealth {
+    /// Bytes tested since the process started.
+    pub samples: u64,
+    /// Startup batteries run (at startup and periodically).
+    pub batteries: u64,
+    /// Why the source was declared degraded, if it was.
+    pub degraded: Option<&'static str>,
+    /// Draws go through the mixing DRBG.
+    pub mixing: bool,
+    /// [`fill_hedged`] calls that got no fresh bytes and derived their
+    /// output from key and message alone.
+    pub hedged_fallbacks: u64,
+}
+
+struct State {
+    health: EntropyHealth,
+    since_battery: u64,
+    rct_last: u8,
+    rct_run: u32,
+    apt_first: u8,
+    apt_seen: u32,
+    apt_count: u32,
+    mixer: Option<Mixer>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    health: EntropyHealth { samples: 0, batteries: 0, degraded: None, mixing: false, hedged_fallbacks: 0 },
+    since_battery: 0,
+    rct_last: 0,
+    rct_run: 0,
+    apt_first: 0,
+    apt_seen: 0,
+    apt_count: 0,
+    mixer: None,
+});
+
+impl State {
+    fn test(&mut self, bytes: &[u8]) -> CoreResult<()> {
+        for &b in bytes {
+            if self.health.samples > 0 && b == self.rct_last {
+                self.rct_run += 1;
+                if self.rct_run >= RCT_CUTOFF {
+                    return Err(self.degrade("repetition count test failed"));
+                }
+            } else {
+                self.rct_last = b;
+                self.rct_run = 1;
+            }
+
+            if self.apt_seen == 0 {
+                self.apt_first = b;
+                self.apt_count = 1;
+            } else if b == self.apt_first {
+                self.apt_count += 1;
+                if self.apt_count >= APT_CUTOFF {
+                    return Err(self.degrade("adaptive proportion test failed"));
+                }
+            }
+            self.apt_seen = (self.apt_seen + 1) % APT_WINDOW;
+            self.health.samples += 1;
+        }
+        Ok(())
+    }
+
+    fn battery(&mut self) -> CoreResult<()> {
+        let mut buf = [0u8; STARTUP_SAMPLES];
+        self.draw(&mut buf)?;
+        self.health.batteries += 1;
+        self.since_battery = 0;
+        Ok(())
+    }
+
+    fn draw(&mut self, buf: &mut [u8]) -> CoreResult<()> {
+        if let Some(why) = self.health.degraded {
+            return Err(CoreError::DegradedEntropy(why));
+        }
+        if getrandom::getrandom(buf).is_err() {
+            self.degrade("entropy source failed");
+            return Err(CoreError::Entropy);
+        }
+        self.test(buf)
+    }
+
+    fn draw_mixed(&mut self, mixer: &mut Mixer, buf: &mut [u8]) -> CoreResult<()> {
+        if let Some(why) = self.health.degraded {
+            return Err(CoreError::DegradedEntropy(why));
+        }
+        if mixer.needs_reseed() {
+            let mut os = Zeroizing::new([0u8; 32]);
+            self.draw(os.as_mut())?;
+            if let Err(e) = mixer.reseed(os.as_ref()) {
+                if let CoreError::DegradedEntropy(why) = e {
+                    self.degrade(why);
+                }
+                return Err(e);
+            }
+        }
+        mixer.generate(buf);
+        Ok(())
+    }
+
+    fn degrade(&mut self, why: &'static str) -> CoreError {
+        self.health.degraded = Some(why);
+        CoreError::DegradedEntropy(why)
+    }
+}
+
+/// Fills `buf` from the OS source after health-testing the output.
+pub fn fill(buf: &mut [u8]) -> CoreResult<()> {
+    let mut state = STATE.lock();
+    if state.health.batteries == 0 || state.since_battery >= RETEST_INTERVAL {
+        state.battery()?;
+    }
+    match state.mixer.take() {
+        Some(mut mixer) => {
+            let res = state.draw_mixed(&mut mixer, buf);
+            state.mixer = Some(mixer);
+            res?;
+            state.since_battery += buf.len() as u64;
+            Ok(())
+        }
+        None => {
+            state.draw(buf)?;
+            state.since_battery += buf.len() as u64;
+            Ok(())
+        }
+    }
+}
+
+/// Fills `buf` with BLAKE3 (in key derivation mode) over 32 fresh bytes
+/// from [`fill`], `secret` and `message`. If the source fails or is
+/// degraded, the fresh bytes are left out and the fallback is counted in
+/// [`EntropyHealth::hedged_fallbacks`]; the draw itself never fails.
+/// `secret` must be a key the output is used under, so the output stays
+/// unpredictable without fresh bytes.
+pub fn fill_hedged(buf: &mut [u8], secret: &[u8], message: &[u8]) {
+    let mut fresh = Zeroizing::new([0u8; 32]);
+    let drawn = fill(fresh.as_mut()).is_ok();
+    if !drawn {
+        STATE.lock().health.hedged_fallbacks += 1;
+    }
+    let mut hasher = blake3::Hasher::new_derive_key(HEDGE_CONTEXT);
+    hasher.update(&[u8::from(drawn)]);
+    if drawn {
+        hasher.update(fresh.as_ref());
+    }
+    hasher.update(&(secret.len() as u64).to_be_bytes());
+    hasher.update(secret);
+    hasher.update(message);
+    secret::finish_blake3(hasher, buf);
+}
+
+/// Routes every later draw through a DRBG that also mixes in `sources`.
+/// Each source is exercised once here, so a missing device or CPU feature
+/// is reported up front. Replaces any earlier configuration.
+pub fn enable_mixing(sources: EntropySources) -> CoreResult<()> {
+    let mixer = Mixer::new(sources)?;
+    let mut state = STATE.lock();
+    state.mixer = Some(mixer);
+    state.health.mixing = true;
+    Ok(())
+}
+
+/// Sources currently mixed in, if mixing is enabled.
+pub fn mixing_sources() -> Option<EntropySources> {
+    STATE.lock().mixer.as_ref().map(|m| m.sources().clone())
+}
+
+/// Id of the calling process, to notice a fork; 0 on wasm32.
+pub(crate) fn process_id() -> u32 {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::process::id();
+    #[cfg(target_arch = "wasm32")]
+    0
+}
+
+/// Runs the startup battery now. Called when an engine is constructed.
+pub fn self_test() -> CoreResult<()> {
+    STATE.lock().battery()
+}
+
+pub fn health() -> EntropyHealth {
+    STATE.lock().health.clone()
+}
@@ -0,0 +1,216 @@
+use crate::audit::{self, CiphertextBinding};
+use crate::crypto;
+use crate::epoch;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams};
+use crate::kem::{self, KYBER1024};
+use crate::suite::Suite;
+
+pub const ENVELOPE_MAGIC: &[u8; 4] = b"TCEV";
+/// Version 2 added the suite byte, version 3 the KDF (in the suite byte's
+/// high nibble, see [`Suite::wire_id`]) and extension block, version 4 the
+/// KEM byte. Envelopes that need neither (no per-message salt, default
+/// suite and KDF) are still written as version 1, and Kyber-1024 envelopes
+/// as version 3 at most.
+pub const ENVELOPE_VERSION: u8 = 4;
+/// Authentication tag length of every suite; the tag ends the ciphertext.
+pub const TAG_LEN: usize = 16;
+
+/// Self-contained ciphertext: everything a recipient holding the KEM secret
+/// key needs to re-derive the session key and decrypt.
+///
+/// Wire layout (big-endian):
+/// `magic(4) | version(1) | [suite(1) |] [kem(1) |] counter(8) | fingerprint(32) | kem_len(2) | kem_ct | [ext |] nonce | ciphertext`
+/// where the suite byte (from version 2) fixes the nonce length, the KEM
+/// byte (version 4) names the [`kem::Kem`] and `ext`
+/// (version 3) carries non-default [`KdfParams`], including the per-message
+/// salt every engine-sealed envelope has, and the escrow wrap if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub suite: Suite,
+    pub kdf: KdfParams,
+    /// [`kem::Kem::id`] of the KEM that produced `kem_ct`.
+    pub kem: u8,
+    pub counter: u64,
+    pub fingerprint: [u8; 32],
+    pub kem_ct: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    /// Session key wrapped to the sealing engine's escrow key (see
+    /// [`crate::escrow`]); `None` without escrow.
+    pub escrow: Option<Vec<u8>>,
+}
+
+impl Envelope {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(48 + self.kem_ct.len() + self.nonce.len() + self.ciphertext.len());
+        out.extend_from_slice(ENVELOPE_MAGIC);
+        let legacy = self.suite == Suite::GcmSivCounter && self.kdf.is_default() && self.escrow.is_none() && self.kem == KYBER1024;
+        if legacy {
+            out.push(1);
+        } else if self.kem == KYBER1024 {
+            out.push(3);
+            out.push(self.suite.wire_id(self.kdf.algorithm));
+        } else {
+            out.push(ENVELOPE_VERSION);
+            out.push(self.suite.wire_id(self.kdf.algorithm));
+            out.push(self.kem);
+        }
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&(self.kem_ct.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.kem_ct);
+        if !legacy {
+            out.extend_from_slice(&self.kdf.encode_ext_with(self.escrow.as_deref()));
+        }
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.ciphertext);
+        out
+    }
+
+    /// Parses untrusted input: every length is checked before it is used,
+    /// the KEM ciphertext must be exactly as long as the recorded KEM's
+    /// (Kyber-1024 before version 4), and an extension
+    /// field may appear only once. Malformed input fails with
+    /// [`CoreError::Format`]; nothing here panics or allocates more than
+    /// `bytes` holds.
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != ENVELOPE_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        let version = r.take(1)?[0];
+        let (suite, algorithm) = match version {
+            1 => (Suite::GcmSivCounter, Kdf::HkdfSha256),
+            2..=ENVELOPE_VERSION => Suite::from_wire_id(r.take(1)?[0])?,
+            _ => return Err(CoreError::Format("unsupported version")),
+        };
+        let kem = match version {
+            4.. => r.take(1)?[0],
+            _ => KYBER1024,
+        };
+        let counter = u64::from_be_bytes(r.array()?);
+        let fingerprint = r.array()?;
+        let kem_len = u16::from_be_bytes(r.array()?) as usize;
+        check_kem_len(kem, kem_len)?;
+        let kem_ct = r.take(kem_len)?.to_vec();
+        let (kdf, escrow) = match version {
+            3.. => {
+                let ext_len = u16::from_be_bytes(r.array()?) as usize;
+                KdfParams::decode_ext_with(algorithm, r.take(ext_len)?)?
+            }
+            _ => (KdfParams { algorithm, ..KdfParams::default() }, None),
+        };
+        let nonce = r.take(suite.nonce_len())?.to_vec();
+        Ok(Envelope { suite, kdf, kem, counter, fingerprint, kem_ct, nonce, ciphertext: r.buf.to_vec(), escrow })
+    }
+
+    /// Recomputes the audit chain link this envelope produced on top of `prev`.
+    /// Lets any holder of the envelope check the evidence returned by `seal`.
+    pub fn evidence_hash(&self, prev: &[u8; 32]) -> [u8; 32] {
+        self.evidence_hash_with(prev, CiphertextBinding::Full)
+    }
+
+    pub fn evidence_hash_with(&self, prev: &[u8; 32], binding: CiphertextBinding) -> [u8; 32] {
+        match binding {
+            CiphertextBinding::Full => audit::entry_hash(prev, self.counter, &self.fingerprint, &self.kem_ct, &self.nonce, &self.ciphertext),
+            CiphertextBinding::Digest => {
+                let digest = audit::ciphertext_digest(&self.ciphertext);
+                audit::entry_hash(prev, self.counter, &self.fingerprint, &self.kem_ct, &self.nonce, &digest)
+            }
+        }
+    }
+
+    /// Removes the authentication tag from the ciphertext and returns it,
+    /// so the ciphertext is exactly as long as the plaintext and the tag
+    /// can be stored elsewhere. The envelope opens again once
+    /// [`Envelope::attach_tag`] restores it; evidence, too, covers the
+    /// ciphertext with its tag.
+    pub fn detach_tag(&mut self) -> CoreResult<[u8; TAG_LEN]> {
+        let at = self.ciphertext.len().checked_sub(TAG_LEN).ok_or(CoreError::Format("ciphertext shorter than a tag"))?;
+        let tag = self.ciphertext[at..].try_into().expect("TAG_LEN bytes");
+        self.ciphertext.truncate(at);
+        Ok(tag)
+    }
+
+    pub fn attach_tag(&mut self, tag: &[u8; TAG_LEN]) {
+        self.ciphertext.extend_from_slice(tag);
+    }
+
+    /// Decapsulates with the recipient's secret key and decrypts.
+    pub fn open(&self, sk_bytes: &[u8]) -> CoreResult<Vec<u8>> {
+        self.open_with_context(sk_bytes, &[])
+    }
+
+    /// Like [`Envelope::open`] for envelopes sealed under a domain-separation
+    /// `context`; a different context fails authentication. Restricted
+    /// envelopes fail with [`CoreError::Unauthorized`]: they only open
+    /// through [`Engine::open_restricted`](crate::Engine::open_restricted).
+    /// Envelopes sealed in an epoch fail with [`CoreError::Config`]: they
+    /// also need the epoch key, through
+    /// [`Engine::open_epoch`](crate::Engine::open_epoch). Envelopes with
+    /// decryption constraints fail with [`CoreError::Constraint`]: only an
+    /// engine can check them (see [`crate::constraint`]).
+    pub fn open_with_context(&self, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        if self.kdf.constraints.is_some() {
+            return Err(CoreError::Constraint("envelope has decryption constraints; open it through an engine".into()));
+        }
+        self.open_unconstrained(sk_bytes, context)
+    }
+
+    /// [`Envelope::open_with_context`] once an engine has checked the
+    /// envelope's constraints.
+    pub(crate) fn open_unconstrained(&self, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        if self.kdf.restricted {
+            return Err(CoreError::Unauthorized);
+        }
+        self.decrypt(sk_bytes, context)
+    }
+
+    pub(crate) fn decrypt(&self, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        if self.kdf.epoch.is_some() {
+            return Err(CoreError::Config("envelope was sealed in an epoch; open it with its epoch key".into()));
+        }
+        self.decrypt_in_epoch(sk_bytes, context, None)
+    }
+
+    pub(crate) fn decrypt_in_epoch(&self, sk_bytes: &[u8], context: &[u8], epoch_key: Option<&[u8; 32]>) -> CoreResult<Vec<u8>> {
+        let mut shared_secret = kem::get(self.kem)?.decapsulate(sk_bytes, &self.kem_ct)?;
+        if let Some(key) = epoch_key {
+            shared_secret = epoch::bind(&shared_secret, key);
+        }
+        let sess_key = crypto::derive_session_key(&shared_secret, &self.fingerprint, self.counter, &self.kdf, context)?;
+        self.suite.open(&sess_key, &self.nonce, &self.ciphertext)
+    }
+}
+
+/// Checks a header's KEM ciphertext length against the KEM recorded as
+/// `kem`, before the ciphertext is read.
+pub(crate) fn check_kem_len(kem: u8, len: usize) -> CoreResult<()> {
+    match kem {
+        KYBER1024 => crypto::check_kem_len(len),
+        _ if kem::get(kem)?.ciphertext_len() == len => Ok(()),
+        _ => Err(CoreError::Format("bad KEM ciphertext length")),
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> CoreResult<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(CoreError::Format("truncated"));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> CoreResult<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+}
@@ -0,0 +1,387 @@
+//! Time-scoped data keys that can be shredded one epoch at a time.
+//!
+//! [`EpochKeys`] derives a key for every hour, day or month (its
+//! [`Granularity`]) from one random master seed. [`Engine::seal_epoch`]
+//! mixes the current epoch's key into an envelope's key derivation and
+//! records the [`EpochId`] in its header. Such an envelope opens only
+//! through [`Engine::open_epoch`], with both the recipient's secret key and
+//! the epoch key. [`EpochKeys::shred`] deletes one epoch's key and
+//! [`EpochKeys::shred_before`] every key before an epoch. From then on,
+//! everything sealed in those epochs is gone, even to the holder of the
+//! recipient key.
+//!
+//! The keys are the leaves of a binary tree, 32 levels deep, over the
+//! epoch index; each node's children are keyed BLAKE3 of its seed. The
+//! store keeps only the nodes it still needs: shredding an epoch replaces
+//! the node above it with the siblings along its path, so no seed that
+//! remains leads to the deleted key. A store stays small, at most 32 nodes
+//! per shredded epoch.
+//!
+//! Shredding takes effect once every earlier copy of the store is
+//! destroyed. [`EpochKeys::save`] replaces the file, but backups or
+//! snapshots of it still hold the old keys.
+//!
+//! Wrapped layout: `magic(4) | version(1) | nonce(12) | ciphertext` over
+//! `granularity(1) | count(4) | (depth(1) | prefix(4) | seed(32))*`, the
+//! header bound as AES-256-GCM-SIV AAD.
+
+use crate::audit::{OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::{Envelope, Reader, TAG_LEN};
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, Marks};
+use crate::rbac::Permission;
+use crate::time;
+use zeroize::Zeroizing;
+
+pub const EPOCH_KEYS_MAGIC: &[u8; 4] = b"TCEK";
+pub const EPOCH_KEYS_VERSION: u8 = 1;
+/// Encoded length of an [`EpochId`].
+pub const EPOCH_ID_LEN: usize = 5;
+
+const WRAP_INFO: &[u8] = b"titancore epoch keys wrap v1";
+const DEPTH: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Granularity {
+    Hour,
+    Day,
+    Month,
+}
+
+impl Granularity {
+    pub const ALL: [Granularity; 3] = [Granularity::Hour, Granularity::Day, Granularity::Month];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Granularity::Hour => "hour",
+            Granularity::Day => "day",
+            Granularity::Month => "month",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Granularity> {
+        Self::ALL.into_iter().find(|g| g.as_str() == s)
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            Granularity::Hour => 1,
+            Granularity::Day => 2,
+            Granularity::Month => 3,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Granularity> {
+        Self::ALL.into_iter().find(|g| g.code() == code)
+    }
+}
+
+/// One epoch: hours, days or months since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EpochId {
+    pub granularity: Granularity,
+    pub index: u32,
+}
+
+impl EpochId {
+    /// The epoch holding Unix time `unix_secs`.
+    pub fn at(granularity: Granularity, unix_secs: u64) -> CoreResult<Self> {
+        let index = match granularity {
+            Granularity::Hour => unix_secs / 3600,
+            Granularity::Day => unix_secs / 86_400,
+            Granularity::Month => {
+                let (year, month, _) = time::civil_from_days((unix_secs / 86_400) as i64);
+                ((year - 1970) * 12 + i64::from(month) - 1) as u64
+            }
+        };
+        let index = u32::try_from(index).map_err(|_| CoreError::Config("time is past the last epoch".into()))?;
+        Ok(EpochId { granularity, index })
+    }
+
+    /// Unix time at which the epoch starts.
+    pub fn start(&self) -> u64 {
+        let index = u64::from(self.index);
+        match self.granularity {
+            Granularity::Hour => index * 3600,
+            Granularity::Day => index * 86_400,
+            Granularity::Month => {
+                let (year, month) = (1970 + (index / 12) as i64, (index % 12) as u32 + 1);
+                time::days_from_civil(year, month, 1) as u64 * 86_400
+            }
+        }
+    }
+
+    /// `granularity(1) | index(4)`
+    pub fn to_bytes(&self) -> [u8; EPOCH_ID_LEN] {
+        let mut out = [0u8; EPOCH_ID_LEN];
+        out[0] = self.granularity.code();
+        out[1..].copy_from_slice(&self.index.to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let bytes: &[u8; EPOCH_ID_LEN] = bytes.try_into().map_err(|_| CoreError::Format("bad epoch id"))?;
+        let granularity = Granularity::from_code(bytes[0]).ok_or(CoreError::Format("unknown epoch granularity"))?;
+        Ok(EpochId { granularity, index: u32::from_be_bytes(bytes[1..].try_into().expect("4 bytes")) })
+    }
+}
+
+// A subtree: every index whose top `depth` bits are `prefix`.
+#[derive(Clone)]
+struct Node {
+    depth: u8,
+    prefix: u32,
+    seed: Zeroizing<[u8; 32]>,
+}
+
+impl Node {
+    // First and last index under the node.
+    fn range(&self) -> (u64, u64) {
+        let span = 1u64 << (DEPTH - self.depth);
+        let first = u64::from(self.prefix) * span;
+        (first, first + span - 1)
+    }
+
+    fn covers(&self, index: u32) -> bool {
+        let (first, last) = self.range();
+        (first..=last).contains(&u64::from(index))
+    }
+
+    fn child(&self, bit: u32) -> Node {
+        let mut hasher = blake3::Hasher::new_keyed(&self.seed);
+        hasher.update(b"titancore epoch tree v1");
+        hasher.update(&[bit as u8]);
+        Node { depth: self.depth + 1, prefix: (self.prefix << 1) | bit, seed: Zeroizing::new(hasher.finalize().into()) }
+    }
+}
+
+// Bit of `index` that picks the child below `depth`.
+fn bit(index: u32, depth: u8) -> u32 {
+    (index >> (DEPTH - depth - 1)) & 1
+}
+
+/// The epoch keys of one granularity that have not been shredded.
+pub struct EpochKeys {
+    granularity: Granularity,
+    nodes: Vec<Node>,
+}
+
+impl EpochKeys {
+    /// A fresh store from a random master seed.
+    pub fn new(granularity: Granularity) -> CoreResult<Self> {
+        let mut seed = Zeroizing::new([0u8; 32]);
+        entropy::fill(seed.as_mut())?;
+        Ok(EpochKeys { granularity, nodes: vec![Node { depth: 0, prefix: 0, seed }] })
+    }
+
+    pub fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// The current epoch at Unix time `unix_secs`.
+    pub fn epoch_at(&self, unix_secs: u64) -> CoreResult<EpochId> {
+        EpochId::at(self.granularity, unix_secs)
+    }
+
+    /// True unless `epoch` has been shredded or has another granularity.
+    pub fn holds(&self, epoch: &EpochId) -> bool {
+        epoch.granularity == self.granularity && self.nodes.iter().any(|n| n.covers(epoch.index))
+    }
+
+    pub(crate) fn key(&self, epoch: &EpochId) -> CoreResult<Zeroizing<[u8; 32]>> {
+        self.check(epoch)?;
+        let mut node = self.nodes.iter().find(|n| n.covers(epoch.index)).ok_or_else(|| shredded(epoch))?.clone();
+        while node.depth < DEPTH {
+            node = node.child(bit(epoch.index, node.depth));
+        }
+        let mut hasher = blake3::Hasher::new_keyed(&node.seed);
+        hasher.update(b"titancore epoch key v1");
+        hasher.update(&epoch.to_bytes());
+        Ok(Zeroizing::new(hasher.finalize().into()))
+    }
+
+    /// Deletes the key of `epoch`. Returns false if it was already gone.
+    pub fn shred(&mut self, epoch: &EpochId) -> CoreResult<bool> {
+        self.check(epoch)?;
+        let Some(i) = self.nodes.iter().position(|n| n.covers(epoch.index)) else { return Ok(false) };
+        let mut node = self.nodes.swap_remove(i);
+        while node.depth < DEPTH {
+            let b = bit(epoch.index, node.depth);
+            self.nodes.push(node.child(b ^ 1));
+            node = node.child(b);
+        }
+        Ok(true)
+    }
+
+    /// Deletes the key of every epoch before `epoch`, which is kept.
+    pub fn shred_before(&mut self, epoch: &EpochId) -> CoreResult<()> {
+        self.check(epoch)?;
+        let index = u64::from(epoch.index);
+        self.nodes.retain(|n| n.range().1 >= index);
+        if let Some(i) = self.nodes.iter().position(|n| n.covers(epoch.index)) {
+            let mut node = self.nodes.swap_remove(i);
+            while node.depth < DEPTH {
+                let b = bit(epoch.index, node.depth);
+                if b == 0 {
+                    self.nodes.push(node.child(1));
+                }
+                node = node.child(b);
+            }
+            self.nodes.push(node);
+        }
+        Ok(())
+    }
+
+    fn check(&self, epoch: &EpochId) -> CoreResult<()> {
+        match epoch.granularity == self.granularity {
+            true => Ok(()),
+            false => Err(CoreError::Config(format!("these epoch keys are per {}", self.granularity.as_str()))),
+        }
+    }
+
+    fn body(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(Vec::with_capacity(5 + 37 * self.nodes.len()));
+        out.push(self.granularity.code());
+        out.extend_from_slice(&(self.nodes.len() as u32).to_be_bytes());
+        for node in &self.nodes {
+            out.push(node.depth);
+            out.extend_from_slice(&node.prefix.to_be_bytes());
+            out.extend_from_slice(node.seed.as_ref());
+        }
+        out
+    }
+
+    fn from_body(body: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: body };
+        let granularity = Granularity::from_code(r.take(1)?[0]).ok_or(CoreError::Format("unknown epoch granularity"))?;
+        let count = u32::from_be_bytes(r.array()?);
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            let depth = r.take(1)?[0];
+            let prefix = u32::from_be_bytes(r.array()?);
+            if depth > DEPTH || (depth < DEPTH && prefix >> depth != 0) {
+                return Err(CoreError::Format("bad epoch key node"));
+            }
+            nodes.push(Node { depth, prefix, seed: Zeroizing::new(r.array()?) });
+        }
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes after epoch keys"));
+        }
+        Ok(EpochKeys { granularity, nodes })
+    }
+
+    /// The store encrypted under a 32-byte `wrapping_key`, as
+    /// [`crate::Identity::wrap`] does for an identity.
+    pub fn wrap(&self, wrapping_key: &[u8; 32]) -> CoreResult<Vec<u8>> {
+        let mut out = wrap_header().to_vec();
+        let key = wrap_key(wrapping_key)?;
+        let body = self.body();
+        let mut nonce = [0u8; 12];
+        entropy::fill_hedged(&mut nonce, key.as_ref(), &body);
+        let ct = crypto::aead_seal_aad(&key, &nonce, &body, &out)?;
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ct);
+        Ok(out)
+    }
+
+    /// Reverses [`EpochKeys::wrap`]; fails with [`CoreError::Decryption`]
+    /// under another key.
+    pub fn unwrap(bytes: &[u8], wrapping_key: &[u8; 32]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != EPOCH_KEYS_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != EPOCH_KEYS_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let nonce = r.array()?;
+        if r.buf.len() < TAG_LEN {
+            return Err(CoreError::Format("truncated"));
+        }
+        let key = wrap_key(wrapping_key)?;
+        let body = Zeroizing::new(crypto::aead_open_aad(&key, &nonce, r.buf, &wrap_header())?);
+        Self::from_body(&body)
+    }
+
+    /// Writes the wrapped store to `path`, replacing it atomically.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>, wrapping_key: &[u8; 32]) -> CoreResult<()> {
+        use std::io::Write;
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&self.wrap(wrapping_key)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a store written by [`EpochKeys::save`].
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<std::path::Path>, wrapping_key: &[u8; 32]) -> CoreResult<Self> {
+        Self::unwrap(&std::fs::read(path)?, wrapping_key)
+    }
+}
+
+fn shredded(epoch: &EpochId) -> CoreError {
+    CoreError::Config(format!("{} epoch {} has been shredded", epoch.granularity.as_str(), epoch.index))
+}
+
+fn wrap_header() -> [u8; 5] {
+    let mut out = [0u8; 5];
+    out[..4].copy_from_slice(EPOCH_KEYS_MAGIC);
+    out[4] = EPOCH_KEYS_VERSION;
+    out
+}
+
+fn wrap_key(wrapping_key: &[u8; 32]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Kdf::HkdfSha256.derive(wrapping_key, &[], WRAP_INFO, &mut key[..])?;
+    Ok(key)
+}
+
+/// The KEM shared secret with an epoch key mixed in, so the session key
+/// needs both.
+pub(crate) fn bind(shared_secret: &[u8], epoch_key: &[u8; 32]) -> Zeroizing<Vec<u8>> {
+    let mut hasher = blake3::Hasher::new_keyed(epoch_key);
+    hasher.update(b"titancore epoch bind v1");
+    hasher.update(shared_secret);
+    Zeroizing::new(hasher.finalize().as_bytes().to_vec())
+}
+
+impl Engine {
+    /// [`Engine::seal_with_context`] under the key of the current epoch of
+    /// `keys`, by the engine's clock.
+    pub fn seal_epoch(&self, data: &[u8], pk_bytes: &[u8], context: &[u8], keys: &EpochKeys) -> CoreResult<(Envelope, String)> {
+        let res = keys.epoch_at(self.clock().now_ms() / 1000);
+        let epoch = self.audited(OpType::Encrypt, &[], res)?;
+        self.seal_in_epoch(data, pk_bytes, context, keys, &epoch)
+    }
+
+    /// [`Engine::seal_epoch`] under `epoch`, e.g. the one in which the data
+    /// was collected. Fails with [`CoreError::Config`] if it was shredded.
+    pub fn seal_in_epoch(&self, data: &[u8], pk_bytes: &[u8], context: &[u8], keys: &EpochKeys, epoch: &EpochId) -> CoreResult<(Envelope, String)> {
+        let res = keys.key(epoch).and_then(|key| self.try_seal(data, pk_bytes, context, Marks { epoch: Some((*epoch, &key)), ..Marks::default() }));
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    /// Opens an envelope from [`Engine::seal_epoch`] with the recipient's
+    /// secret key and its epoch's key from `keys`. Fails with
+    /// [`CoreError::Config`] once that epoch has been shredded.
+    pub fn open_epoch(&self, envelope: &Envelope, sk_bytes: &[u8], context: &[u8], keys: &EpochKeys) -> CoreResult<Vec<u8>> {
+        let res = self.permit(Permission::Decrypt)
+            .and_then(|_| self.check_envelope_approved(envelope))
+            .and_then(|_| self.check_constraints(envelope))
+            .and_then(|_| {
+                let epoch = envelope.kdf.epoch.ok_or(CoreError::Format("envelope has no epoch"))?;
+                let key = keys.key(&epoch)?;
+                envelope.decrypt_in_epoch(sk_bytes, context, Some(&key))
+            });
+        let plaintext = self.audited(OpType::Decrypt, &envelope.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &envelope.kem_ct)?;
+        Ok(plaintext)
+    }
+}
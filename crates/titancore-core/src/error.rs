@@ -0,0 +1,181 @@
+use crate::audit::OpType;
+use crate::suite::Suite;
+use std::cell::RefCell;
+use std::fmt;
+
+/// Errors raised by the core engine. Bindings map these onto their own
+/// exception/error types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoreError {
+    RateLimited,
+    Unauthorized,
+    InvalidKey,
+    /// A public key failed import validation; says what is wrong with it.
+    KeyFormat(String),
+    /// A backup mnemonic has an unknown word or a bad checksum; says where.
+    Mnemonic(String),
+    Kdf,
+    Entropy,
+    Encryption,
+    Decryption,
+    Format(&'static str),
+    Storage(String),
+    Config(String),
+    /// A counter or key-usage limit was reached; continuing would risk
+    /// nonce reuse or exceed the AEAD's safe volume.
+    RekeyRequired(&'static str),
+    /// The entropy source failed a health test (or failed outright) and no
+    /// further random values will be drawn from it.
+    DegradedEntropy(&'static str),
+    /// A certificate or certificate chain failed verification.
+    Certificate(&'static str),
+    /// The key has a trusted revocation record.
+    Revoked,
+    /// A FIPS-mode power-on self test failed; the engine does not start.
+    SelfTest(&'static str),
+    /// An envelope's decryption constraints refuse this engine: past its
+    /// deadline, another fingerprint or another role. Says which.
+    Constraint(String),
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::RateLimited => f.write_str("Rate Limit Exceeded"),
+            CoreError::Unauthorized => f.write_str("Authentication Failed"),
+            CoreError::InvalidKey => f.write_str("Invalid PQC Key"),
+            CoreError::KeyFormat(detail) => write!(f, "Malformed public key: {}", detail),
+            CoreError::Mnemonic(detail) => write!(f, "Bad mnemonic: {}", detail),
+            CoreError::Kdf => f.write_str("KDF failed"),
+            CoreError::Entropy => f.write_str("Entropy fail"),
+            CoreError::Encryption => f.write_str("Encryption fail"),
+            CoreError::Decryption => f.write_str("Decryption fail"),
+            CoreError::Format(what) => write!(f, "Malformed envelope: {}", what),
+            CoreError::Storage(msg) => f.write_str(msg),
+            CoreError::Config(msg) => write!(f, "Invalid configuration: {}", msg),
+            CoreError::RekeyRequired(why) => write!(f, "Rekey required: {}", why),
+            CoreError::DegradedEntropy(why) => write!(f, "Degraded entropy: {}", why),
+            CoreError::Certificate(why) => write!(f, "Certificate rejected: {}", why),
+            CoreError::Revoked => f.write_str("Key revoked"),
+            CoreError::SelfTest(which) => write!(f, "Self-test failed: {}", which),
+            CoreError::Constraint(why) => write!(f, "Decryption constraint violated: {}", why),
+        }
+    }
+}
+
+impl CoreError {
+    /// Stable name of the variant, e.g. `"rate_limited"`, for callers that
+    /// branch on the kind of failure rather than on the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoreError::RateLimited => "rate_limited",
+            CoreError::Unauthorized => "unauthorized",
+            CoreError::InvalidKey => "invalid_key",
+            CoreError::KeyFormat(_) => "key_format",
+            CoreError::Mnemonic(_) => "mnemonic",
+            CoreError::Kdf => "kdf",
+            CoreError::Entropy => "entropy",
+            CoreError::Encryption => "encryption",
+            CoreError::Decryption => "decryption",
+            CoreError::Format(_) => "format",
+            CoreError::Storage(_) => "storage",
+            CoreError::Config(_) => "config",
+            CoreError::RekeyRequired(_) => "rekey_required",
+            CoreError::DegradedEntropy(_) => "degraded_entropy",
+            CoreError::Certificate(_) => "certificate",
+            CoreError::Revoked => "revoked",
+            CoreError::SelfTest(_) => "self_test",
+            CoreError::Constraint(_) => "constraint",
+        }
+    }
+
+    /// Numeric form of [`CoreError::code`]. Numbers are never reused; a new
+    /// variant takes the next one.
+    pub fn code_number(&self) -> u16 {
+        match self {
+            CoreError::RateLimited => 1,
+            CoreError::Unauthorized => 2,
+            CoreError::InvalidKey => 3,
+            CoreError::KeyFormat(_) => 4,
+            CoreError::Mnemonic(_) => 5,
+            CoreError::Kdf => 6,
+            CoreError::Entropy => 7,
+            CoreError::Encryption => 8,
+            CoreError::Decryption => 9,
+            CoreError::Format(_) => 10,
+            CoreError::Storage(_) => 11,
+            CoreError::Config(_) => 12,
+            CoreError::RekeyRequired(_) => 13,
+            CoreError::DegradedEntropy(_) => 14,
+            CoreError::Certificate(_) => 15,
+            CoreError::Revoked => 16,
+            CoreError::SelfTest(_) => 17,
+            CoreError::Constraint(_) => 18,
+        }
+    }
+}
+
+impl std::error::Error for CoreError {}
+
+impl From<std::io::Error> for CoreError {
+    fn from(e: std::io::Error) -> Self {
+        CoreError::Storage(format!("Storage error: {}", e))
+    }
+}
+
+pub type CoreResult<T> = Result<T, CoreError>;
+
+/// What the engine knew about a failure: the operation, the counter and
+/// operation ID of the audit entry that recorded it, the suite in use and
+/// the key involved. Fields it did not know, or that do not apply, are
+/// `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub op: Option<OpType>,
+    pub counter: Option<u64>,
+    pub operation_id: Option<[u8; 16]>,
+    pub suite: Option<Suite>,
+    pub key_id: Option<[u8; 32]>,
+}
+
+thread_local! {
+    // The last failure noted on this thread and its context.
+    static LAST: RefCell<Option<(CoreError, ErrorContext)>> = const { RefCell::new(None) };
+}
+
+/// Adds `ctx` to the context of `err`, the failure this thread is handing
+/// back. Fields already noted for it are kept, so the innermost step that
+/// knew a detail supplies it.
+pub(crate) fn note(err: &CoreError, ctx: ErrorContext) {
+    LAST.with(|last| {
+        let mut last = last.borrow_mut();
+        let merged = match last.take() {
+            Some((prev, old)) if prev == *err => ErrorContext {
+                op: old.op.or(ctx.op),
+                counter: old.counter.or(ctx.counter),
+                operation_id: old.operation_id.or(ctx.operation_id),
+                suite: old.suite.or(ctx.suite),
+                key_id: old.key_id.or(ctx.key_id),
+            },
+            _ => ctx,
+        };
+        *last = Some((err.clone(), merged));
+    });
+}
+
+/// Forgets the context noted on this thread, once an operation succeeded.
+pub(crate) fn clear_context() {
+    LAST.with(|last| last.borrow_mut().take());
+}
+
+/// The context the engine noted on this thread for `err`, the error it just
+/// returned; empty if it noted none or the last failure it noted was a
+/// different one. Taking it clears it.
+///
+/// Per-item failures of a batch are not tracked this way.
+pub fn take_context(err: &CoreError) -> ErrorContext {
+    match LAST.with(|last| last.borrow_mut().take()) {
+        Some((prev, ctx)) if prev == *err => ctx,
+        _ => ErrorContext::default(),
+    }
+}
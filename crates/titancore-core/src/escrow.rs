@@ -0,0 +1,274 @@
+//! Key escrow with split custody.
+//!
+//! With an escrow key installed ([`Engine::set_escrow_key`]), every envelope
+//! the engine seals also carries its session key wrapped to that Kyber key.
+//! The escrow secret key is never held whole: [`generate_escrow_key`] splits
+//! it, followed by its BLAKE3 digest so a bad share is caught, into Shamir
+//! shares over GF(2^8), any `threshold` of which rebuild it.
+//! [`Engine::open_escrowed`] writes an `escrow` audit event and signs a
+//! checkpoint over it before the recovered key is used; if either fails,
+//! nothing is decrypted.
+//!
+//! Only native envelopes carry the wrap; streams, COSE, JWE and age output
+//! are not escrowed.
+
+use crate::audit::checkpoint::SignedCheckpoint;
+use crate::audit::{OpType, Outcome};
+use crate::cert::key_id;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::{Envelope, Reader};
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Kdf;
+use crate::stepup::SensitiveOp;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use zeroize::Zeroizing;
+
+pub const SHARE_MAGIC: &[u8; 4] = b"TCES";
+pub const SHARE_VERSION: u8 = 1;
+
+const WRAP_INFO: &[u8] = b"titancore escrow v1";
+const WRAPPED_KEY_LEN: usize = 32 + 16;
+// A Kyber-1024 secret key embeds its public key after the 1536-byte
+// IND-CPA secret.
+const EMBEDDED_PK_OFFSET: usize = 1536;
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut out = 0u8;
+    for _ in 0..8 {
+        out ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    out
+}
+
+// a^254 = a^-1 in GF(2^8).
+fn gf_inv(a: u8) -> u8 {
+    let mut out = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            out = gf_mul(out, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    out
+}
+
+/// One custodian's part of an escrow secret key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EscrowShare {
+    /// [`key_id`] of the escrow public key.
+    pub key_id: [u8; 32],
+    pub threshold: u8,
+    /// Evaluation point, 1..=255.
+    pub index: u8,
+    pub value: Zeroizing<Vec<u8>>,
+}
+
+impl EscrowShare {
+    /// `magic(4) | version(1) | threshold(1) | index(1) | key_id(32) | value`
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(Vec::with_capacity(39 + self.value.len()));
+        out.extend_from_slice(SHARE_MAGIC);
+        out.push(SHARE_VERSION);
+        out.push(self.threshold);
+        out.push(self.index);
+        out.extend_from_slice(&self.key_id);
+        out.extend_from_slice(&self.value);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != SHARE_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != SHARE_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let threshold = r.take(1)?[0];
+        let index = r.take(1)?[0];
+        let key_id = r.array()?;
+        if threshold == 0 || index == 0 || r.buf.is_empty() {
+            return Err(CoreError::Format("bad escrow share"));
+        }
+        Ok(EscrowShare { key_id, threshold, index, value: Zeroizing::new(r.buf.to_vec()) })
+    }
+}
+
+/// Fresh Kyber-1024 escrow keypair, returned as the public key and one
+/// share of the secret key per custodian.
+pub fn generate_escrow_key(threshold: u8, custodians: u8) -> CoreResult<(Vec<u8>, Vec<EscrowShare>)> {
+    let (pk, sk) = crypto::generate_keypair();
+    let shares = split_escrow_key(&pk, &sk, threshold, custodians)?;
+    Ok((pk, shares))
+}
+
+/// Splits an existing escrow secret key into `custodians` shares, any
+/// `threshold` of which recover it.
+pub fn split_escrow_key(public_key: &[u8], secret_key: &[u8], threshold: u8, custodians: u8) -> CoreResult<Vec<EscrowShare>> {
+    if embedded_public_key(secret_key)? != public_key {
+        return Err(CoreError::InvalidKey);
+    }
+    split_secret(key_id(public_key), secret_key, threshold, custodians)
+}
+
+/// Splits any secret key, followed by its BLAKE3 digest, into shares
+/// labelled `id`.
+pub(crate) fn split_secret(id: [u8; 32], secret_key: &[u8], threshold: u8, custodians: u8) -> CoreResult<Vec<EscrowShare>> {
+    if threshold < 2 || threshold > custodians {
+        return Err(CoreError::Config("escrow threshold must be at least 2 and at most the number of custodians".into()));
+    }
+    let mut secret = Zeroizing::new(secret_key.to_vec());
+    secret.extend_from_slice(blake3::hash(secret_key).as_bytes());
+    let mut coefficients = Zeroizing::new(vec![0u8; secret.len() * (threshold as usize - 1)]);
+    entropy::fill(&mut coefficients)?;
+    Ok((1..=custodians).map(|x| {
+        let value = secret.iter().enumerate().map(|(i, &secret)| {
+            // Horner's rule over secret + c1*x + ... + c(t-1)*x^(t-1).
+            let coeffs = &coefficients[i * (threshold as usize - 1)..(i + 1) * (threshold as usize - 1)];
+            coeffs.iter().rev().fold(0u8, |acc, &c| gf_mul(acc ^ c, x)) ^ secret
+        }).collect();
+        EscrowShare { key_id: id, threshold, index: x, value: Zeroizing::new(value) }
+    }).collect())
+}
+
+/// Rebuilds the escrow secret key from at least `threshold` shares of the
+/// same key. Fails with [`CoreError::InvalidKey`] if a share is corrupt.
+pub fn recover_escrow_key(shares: &[EscrowShare]) -> CoreResult<Zeroizing<Vec<u8>>> {
+    let secret_key = combine_shares(shares)?;
+    if key_id(embedded_public_key(&secret_key)?) != shares[0].key_id {
+        return Err(CoreError::InvalidKey);
+    }
+    Ok(secret_key)
+}
+
+/// Reverses [`split_secret`], checking the digest but not which key the
+/// secret belongs to.
+pub(crate) fn combine_shares(shares: &[EscrowShare]) -> CoreResult<Zeroizing<Vec<u8>>> {
+    let first = shares.first().ok_or(CoreError::Config("no escrow shares".into()))?;
+    if shares.iter().any(|s| s.key_id != first.key_id || s.threshold != first.threshold || s.value.len() != first.value.len()) {
+        return Err(CoreError::Config("escrow shares belong to different keys".into()));
+    }
+    let mut used: Vec<&EscrowShare> = Vec::with_capacity(first.threshold as usize);
+    for share in shares {
+        if !used.iter().any(|s| s.index == share.index) {
+            used.push(share);
+        }
+    }
+    if used.len() < first.threshold as usize {
+        return Err(CoreError::Config(format!("escrow needs {} distinct shares, got {}", first.threshold, used.len())));
+    }
+    used.truncate(first.threshold as usize);
+
+    // Lagrange basis at x = 0.
+    let basis: Vec<u8> = used.iter().map(|s| {
+        used.iter().filter(|o| o.index != s.index).fold(1u8, |acc, o| gf_mul(acc, gf_mul(o.index, gf_inv(o.index ^ s.index))))
+    }).collect();
+    let mut secret = Zeroizing::new(vec![0u8; first.value.len()]);
+    for (share, &l) in used.iter().zip(&basis) {
+        for (out, &y) in secret.iter_mut().zip(share.value.iter()) {
+            *out ^= gf_mul(y, l);
+        }
+    }
+    let (secret_key, digest) = secret.split_at(secret.len().saturating_sub(32));
+    if !crypto::ct_eq(blake3::hash(secret_key).as_bytes(), digest) {
+        return Err(CoreError::InvalidKey);
+    }
+    Ok(Zeroizing::new(secret_key.to_vec()))
+}
+
+pub(crate) fn embedded_public_key(secret_key: &[u8]) -> CoreResult<&[u8]> {
+    crypto::parse_secret_key(secret_key)?;
+    Ok(&secret_key[EMBEDDED_PK_OFFSET..EMBEDDED_PK_OFFSET + kyber1024::public_key_bytes()])
+}
+
+fn wrap_aad(envelope_fingerprint: &[u8; 32], counter: u64, kem_ct: &[u8]) -> Vec<u8> {
+    [&envelope_fingerprint[..], &counter.to_be_bytes(), kem_ct].concat()
+}
+
+fn wrap_key(shared_secret: &[u8], kem_ct: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Kdf::HkdfSha256.derive(shared_secret, kem_ct, WRAP_INFO, key.as_mut())?;
+    Ok(key)
+}
+
+/// `key_id(32) | escrow_kem_ct | sealed session key`, the sealed key bound
+/// to the envelope's fingerprint, counter and KEM ciphertext.
+pub(crate) fn wrap(escrow_pk: &[u8], session_key: &[u8; 32], fingerprint: &[u8; 32], counter: u64, kem_ct: &[u8]) -> CoreResult<Vec<u8>> {
+    let (shared_secret, escrow_ct) = crypto::encapsulate(&crypto::parse_public_key(escrow_pk)?);
+    let key = wrap_key(shared_secret.as_bytes(), escrow_ct.as_bytes())?;
+    let mut out = key_id(escrow_pk).to_vec();
+    out.extend_from_slice(escrow_ct.as_bytes());
+    out.extend_from_slice(&crypto::aead_seal_aad(&key, &[0u8; 12], session_key, &wrap_aad(fingerprint, counter, kem_ct))?);
+    Ok(out)
+}
+
+fn unwrap(escrow_sk: &[u8], envelope: &Envelope, wrapped: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut r = Reader { buf: wrapped };
+    r.take(32)?;
+    let escrow_ct = r.take(kyber1024::ciphertext_bytes())?;
+    if r.buf.len() != WRAPPED_KEY_LEN {
+        return Err(CoreError::Format("bad escrow wrap"));
+    }
+    let ct = kyber1024::Ciphertext::from_bytes(escrow_ct).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+    let sk = crypto::parse_secret_key(escrow_sk)?;
+    let shared_secret = crypto::decapsulate(&ct, &sk);
+    let key = wrap_key(shared_secret.as_bytes(), escrow_ct)?;
+    let session_key = Zeroizing::new(crypto::aead_open_aad(&key, &[0u8; 12], r.buf, &wrap_aad(&envelope.fingerprint, envelope.counter, &envelope.kem_ct))?);
+    Ok(Zeroizing::new(session_key.as_slice().try_into().map_err(|_| CoreError::Decryption)?))
+}
+
+impl Engine {
+    /// Wraps every envelope's session key to `public_key` from now on, or
+    /// stops with `None`. Recorded as a `rekey` event bound to the key.
+    /// Needs a [`SensitiveOp::PolicyChange`] grant under step-up.
+    pub fn set_escrow_key(&mut self, public_key: Option<&[u8]>) -> CoreResult<()> {
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, public_key.unwrap_or_default(), res)?;
+        if let Some(pk) = public_key {
+            let checked = crypto::parse_public_key(pk).and_then(|_| self.ensure_not_revoked(pk));
+            self.audited(OpType::Rekey, pk, checked)?;
+        }
+        self.escrow = public_key.map(<[u8]>::to_vec);
+        self.record_event(OpType::Rekey, Outcome::Success, public_key.unwrap_or_default())?;
+        Ok(())
+    }
+
+    pub fn escrow_key(&self) -> Option<&[u8]> {
+        self.escrow.as_deref()
+    }
+
+    /// Recovers an escrowed envelope with custodians' shares, without the
+    /// recipient's key. An `escrow` event bound to the envelope's KEM
+    /// ciphertext is recorded and a checkpoint signed over it first; the
+    /// checkpoint is returned with the plaintext so the access can be
+    /// proven to an auditor. Needs a [`SensitiveOp::EscrowDecrypt`] grant
+    /// under step-up.
+    pub fn open_escrowed(&self, envelope: &Envelope, shares: &[EscrowShare]) -> CoreResult<(Vec<u8>, SignedCheckpoint)> {
+        let res = self.try_open_escrowed(envelope, shares);
+        self.audited(OpType::Escrow, &envelope.kem_ct, res)
+    }
+
+    fn try_open_escrowed(&self, envelope: &Envelope, shares: &[EscrowShare]) -> CoreResult<(Vec<u8>, SignedCheckpoint)> {
+        self.consume_step_up(SensitiveOp::EscrowDecrypt)?;
+        let wrapped = envelope.escrow.as_deref().ok_or(CoreError::Format("envelope has no escrow wrap"))?;
+        if shares.first().map(|s| &s.key_id[..]) != wrapped.get(..32) {
+            return Err(CoreError::InvalidKey);
+        }
+        self.check_key_limit(OpType::Escrow, &shares[0].key_id)?;
+        let escrow_sk = recover_escrow_key(shares)?;
+        self.record_event(OpType::Escrow, Outcome::Success, &envelope.kem_ct)?;
+        let checkpoint = self.checkpoint()?;
+        let session_key = unwrap(&escrow_sk, envelope, wrapped)?;
+        let plaintext = envelope.suite.open(&session_key, &envelope.nonce, &envelope.ciphertext)?;
+        Ok((plaintext, checkpoint))
+    }
+}
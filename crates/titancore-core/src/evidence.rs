@@ -0,0 +1,246 @@
+//! Self-contained evidence for one operation, checkable by an auditor with
+//! nothing but the engine's trusted checkpoint key.
+
+use crate::audit::checkpoint::SignedCheckpoint;
+use crate::audit::{self, merkle, AuditEntry, CiphertextBinding, FailureReason, InclusionProof, OpType, Outcome};
+use crate::envelope::{Envelope, Reader};
+use crate::error::{CoreError, CoreResult};
+
+pub const EVIDENCE_MAGIC: &[u8; 4] = b"TCEB";
+/// Version 2 added the entry's op and outcome, version 3 millisecond time,
+/// sequence and clock flag, version 4 variable-length link nonces, version
+/// 5 the FIPS flag, version 6 the caller role, version 7 the failure
+/// reason, version 8 the operation and correlation IDs, version 9 the key
+/// ID. Older bundles still parse.
+pub const EVIDENCE_VERSION: u8 = 9;
+
+/// Envelope header fields and the ciphertext exactly as the audit link bound
+/// it: the full ciphertext, or its digest under
+/// [`crate::CiphertextBinding::Digest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkData {
+    pub kem_ct: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub bound: Vec<u8>,
+}
+
+/// One audit entry, its inclusion proof, and a signed checkpoint committing
+/// to the proof's batch root. With `link`, the bundle also ties the entry to
+/// a specific envelope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvidenceBundle {
+    pub entry: AuditEntry,
+    pub proof: InclusionProof,
+    pub checkpoint: SignedCheckpoint,
+    pub link: Option<LinkData>,
+}
+
+impl EvidenceBundle {
+    /// `magic(4) | version(1) | prev(32) | curr(32) | counter(8) | timestamp_ms(8)
+    ///  | op(1) | outcome(1) | seq(8) | clock_regressed(1) | fips(1) | role_len(1) | role | reason(1, 0 for none)
+    ///  | operation_len(1) | operation | correlation_len(1) | correlation | key_id_len(1) | key_id
+    ///  | proof_len(4) | proof | cp_len(4) | checkpoint
+    ///  | has_link(1) [| kem_len(2) | kem_ct | nonce_len(1) | nonce | bound_len(4) | bound]`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let proof = self.proof.to_bytes();
+        let checkpoint = self.checkpoint.to_bytes();
+        let mut out = Vec::with_capacity(128 + proof.len() + checkpoint.len());
+        out.extend_from_slice(EVIDENCE_MAGIC);
+        out.push(EVIDENCE_VERSION);
+        out.extend_from_slice(&self.entry.prev);
+        out.extend_from_slice(&self.entry.curr);
+        out.extend_from_slice(&self.entry.counter.to_be_bytes());
+        out.extend_from_slice(&self.entry.timestamp_ms.to_be_bytes());
+        out.extend_from_slice(&[self.entry.op.code(), self.entry.outcome.code()]);
+        out.extend_from_slice(&self.entry.seq.to_be_bytes());
+        out.push(u8::from(self.entry.clock_regressed));
+        out.push(u8::from(self.entry.fips));
+        let role = self.entry.role.as_deref().unwrap_or_default();
+        out.push(role.len() as u8);
+        out.extend_from_slice(role.as_bytes());
+        out.push(self.entry.reason.map_or(0, FailureReason::code));
+        let operation = self.entry.operation_id.as_ref().map_or(&[][..], |id| &id[..]);
+        out.push(operation.len() as u8);
+        out.extend_from_slice(operation);
+        let correlation = self.entry.correlation_id.as_deref().unwrap_or_default();
+        out.push(correlation.len() as u8);
+        out.extend_from_slice(correlation.as_bytes());
+        let key_id = self.entry.key_id.as_ref().map_or(&[][..], |id| &id[..]);
+        out.push(key_id.len() as u8);
+        out.extend_from_slice(key_id);
+        out.extend_from_slice(&(proof.len() as u32).to_be_bytes());
+        out.extend_from_slice(&proof);
+        out.extend_from_slice(&(checkpoint.len() as u32).to_be_bytes());
+        out.extend_from_slice(&checkpoint);
+        match &self.link {
+            None => out.push(0),
+            Some(link) => {
+                out.push(1);
+                out.extend_from_slice(&(link.kem_ct.len() as u16).to_be_bytes());
+                out.extend_from_slice(&link.kem_ct);
+                out.push(link.nonce.len() as u8);
+                out.extend_from_slice(&link.nonce);
+                out.extend_from_slice(&(link.bound.len() as u32).to_be_bytes());
+                out.extend_from_slice(&link.bound);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != EVIDENCE_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        let version = r.take(1)?[0];
+        if !(1..=EVIDENCE_VERSION).contains(&version) {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let prev = r.array()?;
+        let curr = r.array()?;
+        let counter = u64::from_be_bytes(r.array()?);
+        let timestamp = u64::from_be_bytes(r.array()?);
+        let (op, outcome) = match version {
+            1 => (OpType::Encrypt, Outcome::Success),
+            _ => {
+                let [op, outcome] = r.array()?;
+                (OpType::from_code(op).ok_or(CoreError::Format("bad op"))?,
+                 Outcome::from_code(outcome).ok_or(CoreError::Format("bad outcome"))?)
+            }
+        };
+        let (timestamp_ms, seq, clock_regressed) = match version {
+            1 | 2 => (timestamp.saturating_mul(1000), 0, false),
+            _ => {
+                let seq = u64::from_be_bytes(r.array()?);
+                let regressed = match r.take(1)?[0] {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(CoreError::Format("bad clock flag")),
+                };
+                (timestamp, seq, regressed)
+            }
+        };
+        let fips = match version {
+            1..=4 => false,
+            _ => match r.take(1)?[0] {
+                0 => false,
+                1 => true,
+                _ => return Err(CoreError::Format("bad FIPS flag")),
+            },
+        };
+        let role = match version {
+            1..=5 => None,
+            _ => {
+                let len = r.take(1)?[0] as usize;
+                match r.take(len)? {
+                    [] => None,
+                    role => Some(std::str::from_utf8(role).ok().and_then(audit::parse_role).ok_or(CoreError::Format("bad role"))?),
+                }
+            }
+        };
+        let reason = match version {
+            1..=6 => None,
+            _ => match r.take(1)?[0] {
+                0 => None,
+                code => Some(FailureReason::from_code(code).ok_or(CoreError::Format("bad failure reason"))?),
+            },
+        };
+        let (operation_id, correlation_id) = match version {
+            1..=7 => (None, None),
+            _ => {
+                let len = r.take(1)?[0] as usize;
+                let operation_id = match r.take(len)? {
+                    [] => None,
+                    id => Some(id.try_into().map_err(|_| CoreError::Format("bad operation id"))?),
+                };
+                let len = r.take(1)?[0] as usize;
+                let correlation_id = match r.take(len)? {
+                    [] => None,
+                    id => Some(std::str::from_utf8(id).ok().and_then(audit::parse_correlation).ok_or(CoreError::Format("bad correlation id"))?),
+                };
+                (operation_id, correlation_id)
+            }
+        };
+        let key_id = match version {
+            1..=8 => None,
+            _ => {
+                let len = r.take(1)?[0] as usize;
+                match r.take(len)? {
+                    [] => None,
+                    id => Some(id.try_into().map_err(|_| CoreError::Format("bad key id"))?),
+                }
+            }
+        };
+        let entry = AuditEntry {
+            prev, curr, counter, timestamp_ms, op, outcome, seq, clock_regressed, fips, role, reason, operation_id, correlation_id, key_id,
+        };
+        let proof_len = u32::from_be_bytes(r.array()?) as usize;
+        let proof = InclusionProof::from_bytes(r.take(proof_len)?)?;
+        let cp_len = u32::from_be_bytes(r.array()?) as usize;
+        let checkpoint = SignedCheckpoint::from_bytes(r.take(cp_len)?)?;
+        let link = match r.take(1)?[0] {
+            0 => None,
+            1 => {
+                let kem_len = u16::from_be_bytes(r.array()?) as usize;
+                let kem_ct = r.take(kem_len)?.to_vec();
+                let nonce_len = if version < 4 { 12 } else { r.take(1)?[0] as usize };
+                let nonce = r.take(nonce_len)?.to_vec();
+                let bound_len = u32::from_be_bytes(r.array()?) as usize;
+                Some(LinkData { kem_ct, nonce, bound: r.take(bound_len)?.to_vec() })
+            }
+            _ => return Err(CoreError::Format("bad link flag")),
+        };
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        Ok(EvidenceBundle { entry, proof, checkpoint, link })
+    }
+
+    /// Checks, in order: the checkpoint signature under `trusted_pk`; that
+    /// the checkpoint commits to a batch containing the entry; the inclusion
+    /// proof against that batch root; and, with link data, that the entry's
+    /// head recomputes from the envelope fields (only successful encryptions
+    /// carry link data).
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        if !self.checkpoint.verify(trusted_pk) {
+            return false;
+        }
+        let cp = &self.checkpoint.checkpoint;
+        let Some(batch) = &cp.batch else { return false };
+        let e = &self.entry;
+        let in_batch = (batch.first_counter..=batch.last_counter).contains(&e.counter) && batch.last_counter <= cp.counter;
+        if !in_batch || self.proof.size != batch.size || self.proof.counter != e.counter || self.proof.head != e.curr {
+            return false;
+        }
+        if !merkle::verify_inclusion(&self.proof, &batch.root) {
+            return false;
+        }
+        match &self.link {
+            None => true,
+            Some(_) if (e.op, e.outcome) != (OpType::Encrypt, Outcome::Success) => false,
+            Some(l) => audit::entry_hash(&e.prev, e.counter, &cp.fingerprint, &l.kem_ct, &l.nonce, &l.bound) == e.curr,
+        }
+    }
+}
+
+/// Parses and verifies a bundle from [`crate::Engine::export_evidence`].
+/// `Err` means the bytes are not a bundle; `Ok(false)` means they are but
+/// the evidence does not hold under `trusted_pk`.
+pub fn verify_evidence(bundle: &[u8], trusted_pk: &[u8]) -> CoreResult<bool> {
+    Ok(EvidenceBundle::from_bytes(bundle)?.verify(trusted_pk))
+}
+
+/// True if `entry` is the audit entry sealing `envelope` produced and
+/// `evidence` (the hash `seal` returned) is its head: the entry records a
+/// successful encryption under the envelope's counter, and its head
+/// recomputes from its `prev` and the envelope's KEM ciphertext, nonce and
+/// ciphertext. Either [`CiphertextBinding`] is accepted, since both commit
+/// to the same ciphertext. This shows the binding only; whether the entry
+/// belongs to an intact chain is for the log or an [`EvidenceBundle`].
+pub fn verify_evidence_for(envelope: &Envelope, evidence: &[u8; 32], entry: &AuditEntry) -> bool {
+    entry.curr == *evidence
+        && entry.counter == envelope.counter
+        && (entry.op, entry.outcome) == (OpType::Encrypt, Outcome::Success)
+        && [CiphertextBinding::Full, CiphertextBinding::Digest].into_iter()
+            .any(|binding| envelope.evidence_hash_with(&entry.prev, binding) == entry.curr)
+}
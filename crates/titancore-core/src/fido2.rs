@@ -0,0 +1,114 @@
+//! Secrets unlocked by a FIDO2 authenticator's `hmac-secret` extension.
+//!
+//! The authenticator computes HMAC-SHA-256, under a key that never leaves
+//! it, over a salt chosen by the relying party, and only after a touch
+//! (and PIN, if configured). [`lock`] encrypts a secret, such as a secret
+//! key or a keystore passphrase, under a key derived from that output, so
+//! unlocking it later needs the same authenticator present and touched.
+//! This crate does not talk to the device: the host runs the assertion
+//! with its FIDO2 library (e.g. python-fido2's `hmac-secret` extension)
+//! over the [`LockedSecret::salt`] for [`LockedSecret::credential_id`] and
+//! passes the 32-byte output to [`unlock`].
+//!
+//! Layout: `magic(4) | version(1) | cred_len(2) | credential_id | salt(32) |
+//! nonce(12) | ciphertext`. The header is bound as AES-256-GCM-SIV AAD.
+
+use crate::crypto;
+use crate::entropy;
+use crate::envelope::{Reader, TAG_LEN};
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Kdf;
+use zeroize::Zeroizing;
+
+pub const LOCKED_MAGIC: &[u8; 4] = b"TCFL";
+pub const LOCKED_VERSION: u8 = 1;
+/// Length of an `hmac-secret` salt and output.
+pub const HMAC_SECRET_LEN: usize = 32;
+
+const UNLOCK_INFO: &[u8] = b"titancore fido2 unlock v1";
+
+/// A secret sealed by [`lock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedSecret {
+    /// Credential the assertion must be made with.
+    pub credential_id: Vec<u8>,
+    /// Salt to send in the `hmac-secret` extension.
+    pub salt: [u8; HMAC_SECRET_LEN],
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+impl LockedSecret {
+    fn header(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(39 + self.credential_id.len());
+        out.extend_from_slice(LOCKED_MAGIC);
+        out.push(LOCKED_VERSION);
+        out.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.credential_id);
+        out.extend_from_slice(&self.salt);
+        out
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header();
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.ciphertext);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != LOCKED_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != LOCKED_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let cred_len = u16::from_be_bytes(r.array()?) as usize;
+        let credential_id = r.take(cred_len)?.to_vec();
+        let salt = r.array()?;
+        let nonce = r.array()?;
+        if r.buf.len() < TAG_LEN {
+            return Err(CoreError::Format("truncated"));
+        }
+        Ok(LockedSecret { credential_id, salt, nonce, ciphertext: r.buf.to_vec() })
+    }
+}
+
+/// A fresh random salt for [`lock`].
+pub fn new_salt() -> CoreResult<[u8; HMAC_SECRET_LEN]> {
+    let mut salt = [0u8; HMAC_SECRET_LEN];
+    entropy::fill(&mut salt)?;
+    Ok(salt)
+}
+
+/// Encrypts `secret` under the `hmac-secret` output the authenticator
+/// returned for `credential_id` and `salt`.
+pub fn lock(secret: &[u8], credential_id: &[u8], salt: &[u8; HMAC_SECRET_LEN], hmac_output: &[u8]) -> CoreResult<LockedSecret> {
+    if credential_id.is_empty() || credential_id.len() > u16::MAX as usize {
+        return Err(CoreError::Config("bad credential id".into()));
+    }
+    let mut locked = LockedSecret { credential_id: credential_id.to_vec(), salt: *salt, nonce: [0u8; 12], ciphertext: Vec::new() };
+    let key = unlock_key(&locked, hmac_output)?;
+    entropy::fill_hedged(&mut locked.nonce, key.as_ref(), secret);
+    locked.ciphertext = crypto::aead_seal_aad(&key, &locked.nonce, secret, &locked.header())?;
+    Ok(locked)
+}
+
+/// Decrypts `locked` with the authenticator's `hmac-secret` output for its
+/// salt; fails with [`CoreError::Decryption`] for another authenticator or
+/// credential.
+pub fn unlock(locked: &LockedSecret, hmac_output: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+    let key = unlock_key(locked, hmac_output)?;
+    crypto::aead_open_aad(&key, &locked.nonce, &locked.ciphertext, &locked.header()).map(Zeroizing::new)
+}
+
+// HKDF-SHA256 over the output, salted with the credential id.
+fn unlock_key(locked: &LockedSecret, hmac_output: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    if hmac_output.len() != HMAC_SECRET_LEN {
+        return Err(CoreError::Config(format!("hmac-secret output must be {} bytes", HMAC_SECRET_LEN)));
+    }
+    let mut key = Zeroizing::new([0u8; 32]);
+    Kdf::HkdfSha256.derive(hmac_output, &locked.credential_id, UNLOCK_INFO, &mut key[..])?;
+    Ok(key)
+}
@@ -0,0 +1,107 @@
+//! FIPS mode: restricting an engine to NIST-approved primitives.
+//!
+//! With [`crate::EngineConfig::fips_mode`] the engine refuses to start
+//! unless its suite and KDF are on the approved list and the power-on self
+//! tests in [`self_test`] pass, then refuses to open envelopes and streams
+//! sealed under anything else. Every audit entry it writes carries
+//! [`crate::AuditEntry::fips`], and [`crate::Engine::info`] reports the mode.
+//!
+//! Approved here means the SP 800-56C HKDF variants for session keys, the
+//! AES-256 suites for sealing and the built-in Kyber KEMs; BLAKE3 key
+//! derivation, XChaCha20-Poly1305 and registered KEMs are rejected. This is a policy restriction, not a
+//! validated module: AES-GCM-SIV (RFC 8452) is built on the approved AES
+//! block cipher but is not an SP 800-38 mode, and the KEM and signatures
+//! are the round-3 Kyber and Dilithium submissions rather than FIPS 203
+//! ML-KEM and FIPS 204 ML-DSA. Audit chaining and fingerprints still use
+//! BLAKE3, which derives no keys.
+
+use crate::crypto;
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{self, Kdf};
+use crate::kem::{self, Kem};
+use crate::suite::Suite;
+use pqcrypto_dilithium::dilithium5;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::SharedSecret as KEMSharedSecret;
+use sha2::{Digest, Sha256};
+
+// FIPS 180-4 example "abc".
+const SHA256_ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+// RFC 8452 C.2, second vector.
+const GCM_SIV_KEY: [u8; 32] = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+const GCM_SIV_NONCE: [u8; 12] = [3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+const GCM_SIV_PT: [u8; 8] = [1, 0, 0, 0, 0, 0, 0, 0];
+const GCM_SIV_CT: &str = "c2ef328e5c71c83b843122130f7364b761e0b97427e3df28";
+
+/// Whether `suite` may seal and open envelopes in FIPS mode.
+pub fn approved_suite(suite: Suite) -> bool {
+    matches!(suite, Suite::GcmSivCounter | Suite::GcmSivRandom)
+}
+
+/// Whether `kdf` may derive session keys in FIPS mode.
+pub fn approved_kdf(kdf: Kdf) -> bool {
+    matches!(kdf, Kdf::HkdfSha256 | Kdf::HkdfSha512)
+}
+
+/// Whether envelopes may be sealed and opened under `kem` in FIPS mode:
+/// the built-in Kyber parameter sets, not registered ones.
+pub fn approved_kem(kem: &dyn Kem) -> bool {
+    kem::is_builtin(kem.id())
+}
+
+/// Fails with [`CoreError::Config`] unless `kem` is approved.
+pub fn check_approved_kem(kem: &dyn Kem) -> CoreResult<()> {
+    match approved_kem(kem) {
+        true => Ok(()),
+        false => Err(CoreError::Config(format!("KEM {} is not approved in FIPS mode", kem.name()))),
+    }
+}
+
+/// Fails with [`CoreError::Config`] unless `suite` and `kdf` are approved.
+pub fn check_approved(suite: Suite, kdf: Kdf) -> CoreResult<()> {
+    if !approved_suite(suite) {
+        return Err(CoreError::Config(format!("suite {} is not approved in FIPS mode", suite.name())));
+    }
+    if !approved_kdf(kdf) {
+        return Err(CoreError::Config(format!("KDF {} is not approved in FIPS mode", kdf.name())));
+    }
+    Ok(())
+}
+
+/// Power-on self tests: the entropy battery, known answers for SHA-256,
+/// the KDFs and AES-256-GCM-SIV, and pairwise consistency of a fresh
+/// Kyber-1024 and Dilithium5 keypair. Fails with [`CoreError::SelfTest`]
+/// naming the first that did not pass.
+pub fn self_test() -> CoreResult<()> {
+    entropy::self_test()?;
+    if hex::encode(Sha256::digest(b"abc")) != SHA256_ABC {
+        return Err(CoreError::SelfTest("SHA-256 known answer"));
+    }
+    kdf::self_test().map_err(|_| CoreError::SelfTest("KDF known answer"))?;
+
+    let ct = crypto::aead_seal(&GCM_SIV_KEY, &GCM_SIV_NONCE, &GCM_SIV_PT).map_err(|_| CoreError::SelfTest("AES-GCM-SIV seal"))?;
+    if hex::encode(&ct) != GCM_SIV_CT {
+        return Err(CoreError::SelfTest("AES-GCM-SIV known answer"));
+    }
+    let mut tampered = ct;
+    tampered[0] ^= 1;
+    if crypto::aead_open(&GCM_SIV_KEY, &GCM_SIV_NONCE, &tampered).is_ok() {
+        return Err(CoreError::SelfTest("AES-GCM-SIV tag check"));
+    }
+
+    let (pk, sk) = kyber1024::keypair();
+    let (ss, kem_ct) = crypto::encapsulate(&pk);
+    if crypto::decapsulate(&kem_ct, &sk).as_bytes() != ss.as_bytes() {
+        return Err(CoreError::SelfTest("Kyber-1024 pairwise consistency"));
+    }
+
+    let (pk, sk) = dilithium5::keypair();
+    let sig = dilithium5::detached_sign(SHA256_ABC.as_bytes(), &sk);
+    if dilithium5::verify_detached_signature(&sig, SHA256_ABC.as_bytes(), &pk).is_err()
+        || dilithium5::verify_detached_signature(&sig, b"abc", &pk).is_ok()
+    {
+        return Err(CoreError::SelfTest("Dilithium5 pairwise consistency"));
+    }
+    Ok(())
+}
@@ -0,0 +1,301 @@
+//! Guarded memory for long-lived key material.
+//!
+//! Off by default. After [`set_enabled`]`(true)`, the keys of channel,
+//! ratchet, stream and multipart sessions, the KEK that long-lived keys are
+//! kept under and those keys while unwrapped (see [`crate::idle`]) each get
+//! a mapping of their own: pages locked into RAM and left out of core
+//! dumps, between two inaccessible guard pages, with a canary in front of
+//! the secret. The secret ends where the upper guard page starts, so an
+//! overrun faults at once; a write over the canary is caught when the
+//! secret is dropped, and aborts the process. Regions are wiped before they
+//! are unmapped.
+//!
+//! Each region takes at least three pages, so this suits the handful of
+//! keys a process keeps, not every buffer. Locking fails once
+//! `RLIMIT_MEMLOCK` is used up; the secret is still guarded but may be
+//! swapped, and [`stats`] counts it. Outside Linux, and while disabled,
+//! secrets live on the ordinary heap and are wiped on drop.
+
+use crate::secret::PlainBytes;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use zeroize::Zeroize;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REGIONS: AtomicU64 = AtomicU64::new(0);
+static LOCK_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Turns guarded allocation on or off for secrets allocated from now on;
+/// those already allocated keep their storage.
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// What [`stats`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GuardedStats {
+    /// Guarded regions currently mapped.
+    pub regions: u64,
+    /// Regions whose pages could not be locked into RAM since start.
+    pub lock_failures: u64,
+}
+
+pub fn stats() -> GuardedStats {
+    GuardedStats { regions: REGIONS.load(Ordering::Relaxed), lock_failures: LOCK_FAILURES.load(Ordering::Relaxed) }
+}
+
+/// A variable-length secret, in a guarded region if enabled when it was
+/// made.
+pub struct SecretBytes(Region);
+
+impl SecretBytes {
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut region = Region::new(bytes.len());
+        region.bytes_mut().copy_from_slice(bytes);
+        SecretBytes(region)
+    }
+
+    /// Whether this secret sits in a guarded region.
+    pub fn is_guarded(&self) -> bool {
+        self.0.is_guarded()
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0.bytes()
+    }
+}
+
+impl DerefMut for SecretBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.0.bytes_mut()
+    }
+}
+
+impl Clone for SecretBytes {
+    fn clone(&self) -> Self {
+        SecretBytes::new(self)
+    }
+}
+
+impl Default for SecretBytes {
+    fn default() -> Self {
+        SecretBytes::new(&[])
+    }
+}
+
+impl std::fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretBytes(..)")
+    }
+}
+
+/// A fixed-size secret, such as a session key, stored like
+/// [`SecretBytes`].
+pub(crate) struct Guarded<T: PlainBytes>(Region, PhantomData<T>);
+
+impl<T: PlainBytes> Guarded<T> {
+    pub(crate) fn new(value: &T) -> Self {
+        assert_eq!(std::mem::align_of::<T>(), 1, "guarded values are byte arrays");
+        let len = std::mem::size_of::<T>();
+        let mut region = Region::new(len);
+        // SAFETY: `T` is plain bytes, so any `len` of its bytes can be read.
+        let src = unsafe { std::slice::from_raw_parts(value as *const T as *const u8, len) };
+        region.bytes_mut().copy_from_slice(src);
+        Guarded(region, PhantomData)
+    }
+}
+
+impl<T: PlainBytes> Deref for Guarded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the region holds exactly the bytes of a `T`, whose
+        // alignment `new` checked is 1.
+        unsafe { &*(self.0.bytes().as_ptr() as *const T) }
+    }
+}
+
+impl<T: PlainBytes> DerefMut for Guarded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: as in `deref`; any bytes written through `T` are plain.
+        unsafe { &mut *(self.0.bytes_mut().as_mut_ptr() as *mut T) }
+    }
+}
+
+impl<T: PlainBytes> Clone for Guarded<T> {
+    fn clone(&self) -> Self {
+        Guarded::new(&**self)
+    }
+}
+
+enum Region {
+    Heap(Box<[u8]>),
+    #[cfg(target_os = "linux")]
+    Mapped(mapped::Mapping),
+}
+
+impl Region {
+    fn new(len: usize) -> Region {
+        #[cfg(target_os = "linux")]
+        if enabled() {
+            return Region::Mapped(mapped::Mapping::new(len));
+        }
+        Region::Heap(vec![0u8; len].into_boxed_slice())
+    }
+
+    fn is_guarded(&self) -> bool {
+        !matches!(self, Region::Heap(_))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Region::Heap(bytes) => bytes,
+            #[cfg(target_os = "linux")]
+            Region::Mapped(mapping) => mapping.bytes(),
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        match self {
+            Region::Heap(bytes) => bytes,
+            #[cfg(target_os = "linux")]
+            Region::Mapped(mapping) => mapping.bytes_mut(),
+        }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        if let Region::Heap(bytes) = self {
+            bytes.zeroize();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod mapped {
+    use super::{LOCK_FAILURES, REGIONS};
+    use crate::{crypto, entropy};
+    use std::sync::atomic::Ordering;
+    use std::sync::OnceLock;
+    use zeroize::Zeroize;
+
+    const CANARY_LEN: usize = 16;
+
+    // One random canary for the process, so it cannot be guessed from the
+    // binary.
+    fn canary() -> &'static [u8; CANARY_LEN] {
+        static CANARY: OnceLock<[u8; CANARY_LEN]> = OnceLock::new();
+        CANARY.get_or_init(|| {
+            let mut canary = [0u8; CANARY_LEN];
+            entropy::fill_hedged(&mut canary, &[], b"titancore guarded canary");
+            canary
+        })
+    }
+
+    fn page_size() -> usize {
+        // SAFETY: sysconf has no memory effects.
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        usize::try_from(page).ok().filter(|&p| p > 0).unwrap_or(4096)
+    }
+
+    /// `guard page | canary, secret | guard page`, the secret flush
+    /// against the upper guard.
+    pub(super) struct Mapping {
+        base: *mut u8,
+        page: usize,
+        inner: usize,
+        len: usize,
+        locked: bool,
+    }
+
+    // SAFETY: the mapping is owned by this value alone and only reached
+    // through `&self` or `&mut self`.
+    unsafe impl Send for Mapping {}
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        pub(super) fn new(len: usize) -> Mapping {
+            let page = page_size();
+            let inner = (CANARY_LEN + len).next_multiple_of(page);
+            let total = inner + 2 * page;
+            // SAFETY: a fresh anonymous mapping, touched only within
+            // `total` bytes below.
+            let base = unsafe {
+                libc::mmap(std::ptr::null_mut(), total, libc::PROT_READ | libc::PROT_WRITE,
+                           libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+            };
+            if base == libc::MAP_FAILED {
+                std::alloc::handle_alloc_error(std::alloc::Layout::from_size_align(total, page).expect("page-aligned layout"));
+            }
+            let base = base as *mut u8;
+            // SAFETY: every range lies within the mapping made above.
+            let locked = unsafe {
+                let guards = libc::mprotect(base as *mut _, page, libc::PROT_NONE) == 0
+                    && libc::mprotect(base.add(page + inner) as *mut _, page, libc::PROT_NONE) == 0;
+                assert!(guards, "cannot protect guard pages");
+                libc::madvise(base.add(page) as *mut _, inner, libc::MADV_DONTDUMP);
+                libc::mlock(base.add(page) as *const _, inner) == 0
+            };
+            if !locked {
+                LOCK_FAILURES.fetch_add(1, Ordering::Relaxed);
+            }
+            REGIONS.fetch_add(1, Ordering::Relaxed);
+            let mut mapping = Mapping { base, page, inner, len, locked };
+            mapping.canary_mut().copy_from_slice(canary());
+            mapping
+        }
+
+        fn data(&self) -> *mut u8 {
+            // SAFETY: `page + inner - len` is within the mapping.
+            unsafe { self.base.add(self.page + self.inner - self.len) }
+        }
+
+        fn canary_mut(&mut self) -> &mut [u8] {
+            // SAFETY: the canary's bytes precede the secret inside the
+            // readable pages, and `&mut self` makes them ours.
+            unsafe { std::slice::from_raw_parts_mut(self.data().sub(CANARY_LEN), CANARY_LEN) }
+        }
+
+        pub(super) fn bytes(&self) -> &[u8] {
+            // SAFETY: `len` readable bytes start at `data`.
+            unsafe { std::slice::from_raw_parts(self.data(), self.len) }
+        }
+
+        pub(super) fn bytes_mut(&mut self) -> &mut [u8] {
+            // SAFETY: as in `bytes`, with `&mut self` for exclusive access.
+            unsafe { std::slice::from_raw_parts_mut(self.data(), self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            let intact = crypto::ct_eq(self.canary_mut(), canary());
+            self.bytes_mut().zeroize();
+            if !intact {
+                // Something wrote below the secret; nothing else in the
+                // process can be trusted either.
+                eprintln!("titancore: guarded memory canary overwritten");
+                std::process::abort();
+            }
+            // SAFETY: unlocks and unmaps exactly what `new` mapped; nothing
+            // refers to it after this.
+            unsafe {
+                if self.locked {
+                    libc::munlock(self.base.add(self.page) as *const _, self.inner);
+                }
+                libc::munmap(self.base as *mut _, self.inner + 2 * self.page);
+            }
+            REGIONS.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
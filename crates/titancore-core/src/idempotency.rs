@@ -0,0 +1,148 @@
+//! Idempotency keys for sealing.
+//!
+//! With [`EngineConfig::idempotency_window`] set, [`Engine::seal_idempotent`]
+//! remembers the envelope and evidence it returned for each key. A retry
+//! with the same key within the window gets them back unchanged: nothing is
+//! encrypted, charged to the rate limit or key usage, or written to the
+//! audit chain. A key is bound to the caller's role and to the request, so
+//! reusing one for other data, another recipient or another context fails
+//! with [`CoreError::Config`], as does a retry while the first call is still
+//! running. A failed call leaves nothing behind, and its retry runs afresh.
+//! After a repeat, [`operation::last_id`] names the operation that sealed
+//! the envelope.
+//!
+//! Envelopes are kept in memory, at most [`MAX_ENTRIES`] of them and
+//! [`MAX_BYTES`] in all; past either bound the oldest are forgotten before
+//! their window ends, and their keys seal anew.
+//!
+//! [`EngineConfig::idempotency_window`]: crate::EngineConfig::idempotency_window
+
+use crate::audit::OpType;
+use crate::engine::Engine;
+use crate::envelope::Envelope;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Marks;
+use crate::operation;
+use crate::rbac::Permission;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Most envelopes remembered at once.
+pub const MAX_ENTRIES: usize = 10_000;
+/// Most envelope bytes remembered at once.
+pub const MAX_BYTES: usize = 64 << 20;
+/// Longest idempotency key, in bytes.
+pub const MAX_KEY_LEN: usize = 256;
+
+enum Slot {
+    /// The first call with the key is still running.
+    Pending,
+    Done { request: [u8; 32], at: Duration, envelope: Box<Envelope>, evidence: String, operation_id: Option<[u8; 16]> },
+}
+
+#[derive(Default)]
+pub(crate) struct IdempotencyCache {
+    slots: HashMap<[u8; 32], Slot>,
+    // Completed keys in the order they were stored, with the time each was.
+    order: VecDeque<([u8; 32], Duration, usize)>,
+    bytes: usize,
+}
+
+impl IdempotencyCache {
+    fn prune(&mut self, now: Duration, window: Duration) {
+        while let Some(&(key, at, size)) = self.order.front() {
+            let stale = now.saturating_sub(at) >= window;
+            if !stale && self.order.len() <= MAX_ENTRIES && self.bytes <= MAX_BYTES {
+                break;
+            }
+            self.order.pop_front();
+            self.bytes -= size;
+            // The key may have been stored again since; only its own entry goes.
+            if matches!(self.slots.get(&key), Some(Slot::Done { at: stored, .. }) if *stored == at) {
+                self.slots.remove(&key);
+            }
+        }
+    }
+}
+
+// Keys are namespaced by role, so one role cannot collect another's
+// envelopes.
+fn slot_key(role: Option<&str>, key: &str) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("titancore idempotency key v1");
+    let role = role.unwrap_or_default();
+    hasher.update(&(role.len() as u64).to_be_bytes());
+    hasher.update(role.as_bytes());
+    hasher.update(key.as_bytes());
+    hasher.finalize().into()
+}
+
+fn request_hash(data: &[u8], pk_bytes: &[u8], context: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key("titancore idempotency request v1");
+    for part in [pk_bytes, context] {
+        hasher.update(&(part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+impl Engine {
+    /// [`Engine::seal_with_context`] under an idempotency `key` (1 to
+    /// [`MAX_KEY_LEN`] bytes): a repeat within the window returns the first
+    /// call's envelope and evidence. Fails with [`CoreError::Config`]
+    /// without [`EngineConfig::idempotency_window`] set.
+    ///
+    /// [`EngineConfig::idempotency_window`]: crate::EngineConfig::idempotency_window
+    pub fn seal_idempotent(&self, key: &str, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Envelope, String)> {
+        let res = self.try_seal_idempotent(key, data, pk_bytes, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_seal_idempotent(&self, key: &str, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Envelope, String)> {
+        let window = self.idempotency_window.ok_or_else(|| CoreError::Config("no idempotency window configured".into()))?;
+        if !(1..=MAX_KEY_LEN).contains(&key.len()) {
+            return Err(CoreError::Config(format!("idempotency keys are 1 to {} bytes", MAX_KEY_LEN)));
+        }
+        self.permit(Permission::Encrypt)?;
+        let slot = slot_key(self.caller_role().as_deref(), key);
+        let request = request_hash(data, pk_bytes, context);
+        {
+            let mut cache = self.idempotency.lock();
+            cache.prune(self.clock().monotonic(), window);
+            match cache.slots.get(&slot) {
+                Some(Slot::Done { request: stored, envelope, evidence, operation_id, .. }) if *stored == request => {
+                    operation::set_last_id(*operation_id);
+                    return Ok((Envelope::clone(envelope), evidence.clone()));
+                }
+                Some(Slot::Done { .. }) => return Err(CoreError::Config("idempotency key reused for a different request".into())),
+                Some(Slot::Pending) => return Err(CoreError::Config("idempotency key in use by a call still running".into())),
+                None => {
+                    cache.slots.insert(slot, Slot::Pending);
+                }
+            }
+        }
+        let res = self.try_seal(data, pk_bytes, context, Marks::default());
+        let mut cache = self.idempotency.lock();
+        match &res {
+            Ok((envelope, evidence)) => {
+                let at = self.clock().monotonic();
+                let size = envelope.ciphertext.len() + envelope.kem_ct.len() + evidence.len();
+                let operation_id = operation::last_id();
+                cache.slots.insert(slot, Slot::Done { request, at, envelope: Box::new(envelope.clone()), evidence: evidence.clone(), operation_id });
+                cache.order.push_back((slot, at, size));
+                cache.bytes += size;
+                cache.prune(at, window);
+            }
+            Err(_) => {
+                cache.slots.remove(&slot);
+            }
+        }
+        res
+    }
+
+    /// How long [`Engine::seal_idempotent`] remembers a key; `None` if
+    /// idempotency keys are off.
+    pub fn idempotency_window(&self) -> Option<Duration> {
+        self.idempotency_window
+    }
+}
@@ -0,0 +1,294 @@
+//! The engine's long-lived Dilithium5 identity keypair.
+//!
+//! One [`Identity`] signs an engine's checkpoints, attestations, genesis
+//! record and manifests and authenticates it on channels. Generate one
+//! with [`Identity::generate`], keep it wrapped under a 32-byte key the
+//! host holds somewhere safer than the disk (the keychain, DPAPI, the
+//! kernel keyring or a FIDO2 `hmac-secret`) with [`Identity::wrap`] or,
+//! with the `fs` feature, [`Identity::save`], and start engines with it
+//! through [`crate::EngineConfig::identity`].
+//!
+//! [`Engine::rotate_identity`] replaces it: the old key signs a
+//! [`Rotation`] naming the new key, the new key countersigns it, and the
+//! chain records a `rekey` event bound to the statement, so a verifier
+//! that pinned the old key can follow the change without trusting the
+//! host.
+//!
+//! Wrapped layout: `magic(4) | version(1) | pk_len(2) | public_key |
+//! nonce(12) | ciphertext`, the header bound as AES-256-GCM-SIV AAD.
+//! Rotation layout: `magic(4) | version(1) | fingerprint(32) | counter(8) |
+//! timestamp_ms(8) | old_len(2) | old_public_key | new_len(2) |
+//! new_public_key`, then `old_sig_len(2) | old_signature | new_signature`.
+
+use crate::audit::{self, AuditEntry, OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::{Reader, TAG_LEN};
+use crate::error::{CoreError, CoreResult};
+use crate::guarded::SecretBytes;
+use crate::idle::IdleKey;
+use crate::kdf::Kdf;
+use crate::stepup::SensitiveOp;
+use zeroize::Zeroizing;
+
+pub const IDENTITY_MAGIC: &[u8; 4] = b"TCID";
+pub const IDENTITY_VERSION: u8 = 1;
+pub const ROTATION_MAGIC: &[u8; 4] = b"TCRT";
+pub const ROTATION_VERSION: u8 = 1;
+
+const WRAP_INFO: &[u8] = b"titancore identity wrap v1";
+const PROBE: &[u8] = b"titancore checkpoint key";
+
+/// A Dilithium5 keypair known to belong together.
+#[derive(Clone)]
+pub struct Identity {
+    public_key: Vec<u8>,
+    secret_key: IdleKey,
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identity").field("key_id", &hex::encode(self.key_id())).finish_non_exhaustive()
+    }
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        let (public_key, secret_key) = crypto::generate_signing_keypair();
+        Identity { public_key, secret_key: IdleKey::new(&secret_key) }
+    }
+
+    /// Fails with [`CoreError::InvalidKey`] unless `secret_key` signs for
+    /// `public_key`.
+    pub fn new(public_key: &[u8], secret_key: &[u8]) -> CoreResult<Self> {
+        let probe = crypto::sign(secret_key, PROBE)?;
+        if !crypto::verify_signature(public_key, PROBE, &probe) {
+            return Err(CoreError::InvalidKey);
+        }
+        Ok(Identity { public_key: public_key.to_vec(), secret_key: IdleKey::new(secret_key) })
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// The secret key, kept encrypted while idle (see [`crate::idle`]) and
+    /// in the clear only until the result is dropped.
+    pub fn secret_key(&self) -> SecretBytes {
+        self.secret_key.unwrapped()
+    }
+
+    /// BLAKE3 of the public key, as in certificates and revocations.
+    pub fn key_id(&self) -> [u8; 32] {
+        crate::cert::key_id(&self.public_key)
+    }
+
+    pub(crate) fn into_keypair(self) -> (Vec<u8>, IdleKey) {
+        (self.public_key, self.secret_key)
+    }
+
+    /// The keypair encrypted under `wrapping_key`, with the public key in
+    /// the clear.
+    pub fn wrap(&self, wrapping_key: &[u8; 32]) -> CoreResult<Vec<u8>> {
+        let mut out = wrap_header(&self.public_key);
+        let key = wrap_key(wrapping_key, &self.public_key)?;
+        let mut nonce = [0u8; 12];
+        let secret_key = self.secret_key.unwrapped();
+        entropy::fill_hedged(&mut nonce, key.as_ref(), &secret_key);
+        let ct = crypto::aead_seal_aad(&key, &nonce, &secret_key, &out)?;
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ct);
+        Ok(out)
+    }
+
+    /// Reverses [`Identity::wrap`]; fails with [`CoreError::Decryption`]
+    /// under another key.
+    pub fn unwrap(bytes: &[u8], wrapping_key: &[u8; 32]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != IDENTITY_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != IDENTITY_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?;
+        let nonce = r.array()?;
+        if r.buf.len() < TAG_LEN {
+            return Err(CoreError::Format("truncated"));
+        }
+        let header = wrap_header(public_key);
+        let key = wrap_key(wrapping_key, public_key)?;
+        let sk = Zeroizing::new(crypto::aead_open_aad(&key, &nonce, r.buf, &header)?);
+        Self::new(public_key, &sk)
+    }
+
+    /// Writes the wrapped keypair to `path`, replacing it atomically.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>, wrapping_key: &[u8; 32]) -> CoreResult<()> {
+        use std::io::Write;
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&self.wrap(wrapping_key)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a keypair written by [`Identity::save`].
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<std::path::Path>, wrapping_key: &[u8; 32]) -> CoreResult<Self> {
+        Self::unwrap(&std::fs::read(path)?, wrapping_key)
+    }
+}
+
+fn wrap_header(public_key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(7 + public_key.len());
+    out.extend_from_slice(IDENTITY_MAGIC);
+    out.push(IDENTITY_VERSION);
+    out.extend_from_slice(&(public_key.len() as u16).to_be_bytes());
+    out.extend_from_slice(public_key);
+    out
+}
+
+// HKDF-SHA256 over the wrapping key, salted with the key id.
+fn wrap_key(wrapping_key: &[u8; 32], public_key: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Kdf::HkdfSha256.derive(wrapping_key, &crate::cert::key_id(public_key), WRAP_INFO, &mut key[..])?;
+    Ok(key)
+}
+
+/// "Engine `fingerprint` replaced identity `old_public_key` with
+/// `new_public_key` at audit entry `counter`."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    pub fingerprint: [u8; 32],
+    pub counter: u64,
+    pub timestamp_ms: u64,
+    pub old_public_key: Vec<u8>,
+    pub new_public_key: Vec<u8>,
+}
+
+impl Rotation {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(57 + self.old_public_key.len() + self.new_public_key.len());
+        out.extend_from_slice(ROTATION_MAGIC);
+        out.push(ROTATION_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        out.extend_from_slice(&(self.old_public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.old_public_key);
+        out.extend_from_slice(&(self.new_public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.new_public_key);
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != ROTATION_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != ROTATION_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let fingerprint = r.array()?;
+        let counter = u64::from_be_bytes(r.array()?);
+        let timestamp_ms = u64::from_be_bytes(r.array()?);
+        let old_len = u16::from_be_bytes(r.array()?) as usize;
+        let old_public_key = r.take(old_len)?.to_vec();
+        let new_len = u16::from_be_bytes(r.array()?) as usize;
+        let new_public_key = r.take(new_len)?.to_vec();
+        Ok(Rotation { fingerprint, counter, timestamp_ms, old_public_key, new_public_key })
+    }
+
+    /// What the chain's `rekey` entry binds as its subject.
+    pub fn digest(&self) -> [u8; 32] {
+        blake3::derive_key("titancore identity rotation v1", &self.to_bytes())
+    }
+}
+
+/// A [`Rotation`] signed by the old key and countersigned by the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRotation {
+    pub rotation: Rotation,
+    pub old_signature: Vec<u8>,
+    pub new_signature: Vec<u8>,
+}
+
+impl SignedRotation {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.rotation.to_bytes();
+        out.extend_from_slice(&(self.old_signature.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.old_signature);
+        out.extend_from_slice(&self.new_signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let rotation = Rotation::read(&mut r)?;
+        let sig_len = u16::from_be_bytes(r.array()?) as usize;
+        let old_signature = r.take(sig_len)?.to_vec();
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("rotation without countersignature"));
+        }
+        Ok(SignedRotation { rotation, old_signature, new_signature: r.buf.to_vec() })
+    }
+
+    /// True if `trusted_old_pk` is the key being replaced and both it and
+    /// the new key signed the statement. Follow a series of rotations by
+    /// trusting each verified `new_public_key` in turn.
+    pub fn verify(&self, trusted_old_pk: &[u8]) -> bool {
+        let body = self.rotation.to_bytes();
+        self.rotation.old_public_key == trusted_old_pk
+            && crypto::verify_signature(trusted_old_pk, &body, &self.old_signature)
+            && crypto::verify_signature(&self.rotation.new_public_key, &body, &self.new_signature)
+    }
+
+    /// True if `entry` is the chain entry that records this rotation.
+    pub fn links(&self, entry: &AuditEntry) -> bool {
+        let r = &self.rotation;
+        entry.counter == r.counter
+            && entry.op == OpType::Rekey
+            && entry.outcome == Outcome::Success
+            && entry.curr == audit::event_hash(&entry.prev, r.counter, &r.fingerprint, OpType::Rekey, Outcome::Success, &r.digest())
+    }
+}
+
+impl Engine {
+    /// Replaces the identity keypair with `new`, cross-signing the change
+    /// and recording it as a `rekey` event bound to the returned statement.
+    /// Store `new` (e.g. with [`Identity::save`]) before calling, so a
+    /// crash cannot leave the chain naming a key the host no longer has.
+    /// Needs a [`SensitiveOp::PolicyChange`] grant under step-up, and the
+    /// `admin` permission once a role policy is installed.
+    pub fn rotate_identity(&mut self, new: Identity) -> CoreResult<SignedRotation> {
+        self.ensure_open()?;
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, &new.public_key, res)?;
+        if new.public_key == self.signing_key.0 {
+            return Err(CoreError::Config("new identity is the current one".into()));
+        }
+        let ctr = self.next_counters(1)?;
+        let rotation = Rotation {
+            fingerprint: self.fingerprint,
+            counter: ctr,
+            timestamp_ms: self.clock().now_ms(),
+            old_public_key: self.signing_key.0.clone(),
+            new_public_key: new.public_key.clone(),
+        };
+        let body = rotation.to_bytes();
+        let signed = SignedRotation {
+            old_signature: crypto::sign(&self.signing_key.1.unwrapped(), &body)?,
+            new_signature: crypto::sign(&new.secret_key.unwrapped(), &body)?,
+            rotation,
+        };
+        self.record_event_at(ctr, OpType::Rekey, Outcome::Success, &signed.rotation.digest())?;
+        self.signing_key = new.into_keypair();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.sync_watchdog_key();
+        Ok(signed)
+    }
+}
@@ -0,0 +1,78 @@
+//! Secret keys kept encrypted while idle.
+//!
+//! An [`IdleKey`] holds a long-lived secret key (an engine's identity key,
+//! a channel's signing key, a ratchet's current KEM key) encrypted under a
+//! key-encryption key that is drawn when the process first needs one and
+//! never leaves memory. [`IdleKey::unwrapped`] decrypts it in place into a
+//! [`SecretBytes`] for the operation that needs it, wiped once dropped, so
+//! the raw key exists only while it is in use. A full memory dump still
+//! yields the KEK, but a stray read of stale or neighbouring memory finds
+//! ciphertext.
+//!
+//! The KEK is allocated in guarded memory if [`crate::guarded`] is enabled
+//! when the first key is wrapped.
+
+use crate::crypto;
+use crate::entropy;
+use crate::guarded::{Guarded, SecretBytes};
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
+
+static KEK: OnceLock<Guarded<[u8; 32]>> = OnceLock::new();
+
+// Drawn once; hedged with the first key wrapped, so a failed entropy
+// source still gives a KEK no one else knows.
+fn kek(first: &[u8]) -> &'static [u8; 32] {
+    KEK.get_or_init(|| {
+        let mut kek = Zeroizing::new([0u8; 32]);
+        entropy::fill_hedged(kek.as_mut(), first, b"titancore idle kek");
+        Guarded::new(&kek)
+    })
+}
+
+/// A secret key encrypted under the process KEK.
+#[derive(Clone)]
+pub struct IdleKey {
+    nonce: [u8; 12],
+    tag: [u8; 16],
+    ct: Vec<u8>,
+}
+
+impl IdleKey {
+    pub fn new(secret: &[u8]) -> Self {
+        let kek = kek(secret);
+        let mut nonce = [0u8; 12];
+        entropy::fill_hedged(&mut nonce, kek, secret);
+        let mut buf = Zeroizing::new(secret.to_vec());
+        let tag = crypto::aead_seal_in_place(kek, &nonce, &mut buf, &[]).expect("a key fits one AEAD call");
+        IdleKey { nonce, tag, ct: std::mem::take(&mut *buf) }
+    }
+
+    /// The key in the clear, for as long as the result is held.
+    pub fn unwrapped(&self) -> SecretBytes {
+        let kek = KEK.get().expect("set when the key was wrapped");
+        let mut key = SecretBytes::new(&self.ct);
+        crypto::aead_open_in_place(kek, &self.nonce, &mut key, &self.tag, &[]).expect("idle key authenticates");
+        key
+    }
+
+    pub fn len(&self) -> usize {
+        self.ct.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ct.is_empty()
+    }
+}
+
+impl Default for IdleKey {
+    fn default() -> Self {
+        IdleKey::new(&[])
+    }
+}
+
+impl std::fmt::Debug for IdleKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("IdleKey(..)")
+    }
+}
@@ -0,0 +1,182 @@
+//! Integrity-only protection: readable but tamper-evident data.
+//!
+//! [`Engine::protect_signed`] and [`Engine::protect_mac`] wrap a payload,
+//! unencrypted, in a [`ProtectedMessage`] framed like an envelope (magic,
+//! version, counter, fingerprint) and authenticated either by a Dilithium5
+//! signature from the engine's checkpoint key or by a keyed BLAKE3 tag
+//! under a key shared with the verifier. Each message is recorded as a
+//! `sign` event under the message's own counter, bound to the BLAKE3 hash
+//! of the whole message, and counts as one request against the rate limit.
+//!
+//! Layout: `magic(4) | version(1) | mode(1) | counter(8) | fingerprint(32) |
+//! payload_len(8) | payload`, then `pk_len(2) | public_key | signature`
+//! (mode 1) or `tag(32)` (mode 2). The signature or tag covers everything
+//! up to and including the payload.
+
+use crate::audit::{OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::rbac::Permission;
+use zeroize::{Zeroize, Zeroizing};
+
+pub const PROTECTED_MAGIC: &[u8; 4] = b"TCIP";
+pub const PROTECTED_VERSION: u8 = 1;
+
+const MODE_SIGNATURE: u8 = 1;
+const MODE_MAC: u8 = 2;
+const MAC_CONTEXT: &str = "titancore integrity mac v1";
+
+/// How a [`ProtectedMessage`] is authenticated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Protection {
+    /// Dilithium5 signature and the signer's public key. As with
+    /// checkpoints, verify against a key you trust.
+    Signature { public_key: Vec<u8>, signature: Vec<u8> },
+    /// Keyed BLAKE3 tag.
+    Mac([u8; 32]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectedMessage {
+    pub counter: u64,
+    pub fingerprint: [u8; 32],
+    pub payload: Vec<u8>,
+    pub protection: Protection,
+}
+
+impl ProtectedMessage {
+    fn mode(&self) -> u8 {
+        match self.protection {
+            Protection::Signature { .. } => MODE_SIGNATURE,
+            Protection::Mac(_) => MODE_MAC,
+        }
+    }
+
+    // The authenticated bytes: everything up to and including the payload.
+    fn body(mode: u8, counter: u64, fingerprint: &[u8; 32], payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(54 + payload.len());
+        out.extend_from_slice(PROTECTED_MAGIC);
+        out.push(PROTECTED_VERSION);
+        out.push(mode);
+        out.extend_from_slice(&counter.to_be_bytes());
+        out.extend_from_slice(fingerprint);
+        out.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Self::body(self.mode(), self.counter, &self.fingerprint, &self.payload);
+        match &self.protection {
+            Protection::Signature { public_key, signature } => {
+                out.extend_from_slice(&(public_key.len() as u16).to_be_bytes());
+                out.extend_from_slice(public_key);
+                out.extend_from_slice(signature);
+            }
+            Protection::Mac(tag) => out.extend_from_slice(tag),
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != PROTECTED_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != PROTECTED_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let mode = r.take(1)?[0];
+        let counter = u64::from_be_bytes(r.array()?);
+        let fingerprint = r.array()?;
+        let len = usize::try_from(u64::from_be_bytes(r.array()?)).map_err(|_| CoreError::Format("truncated"))?;
+        let payload = r.take(len)?.to_vec();
+        let protection = match mode {
+            MODE_SIGNATURE => {
+                let pk_len = u16::from_be_bytes(r.array()?) as usize;
+                let public_key = r.take(pk_len)?.to_vec();
+                if r.buf.is_empty() {
+                    return Err(CoreError::Format("protected message without signature"));
+                }
+                Protection::Signature { public_key, signature: r.buf.to_vec() }
+            }
+            MODE_MAC => {
+                let tag = r.array()?;
+                if !r.buf.is_empty() {
+                    return Err(CoreError::Format("trailing bytes"));
+                }
+                Protection::Mac(tag)
+            }
+            _ => return Err(CoreError::Format("unknown protection mode")),
+        };
+        Ok(ProtectedMessage { counter, fingerprint, payload, protection })
+    }
+
+    /// True if the message is signed and the signature is valid under
+    /// `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        let Protection::Signature { signature, .. } = &self.protection else { return false };
+        crypto::verify_signature(trusted_pk, &Self::body(MODE_SIGNATURE, self.counter, &self.fingerprint, &self.payload), signature)
+    }
+
+    /// True if the message carries a MAC and it is valid under `key`.
+    pub fn verify_mac(&self, key: &[u8; 32]) -> bool {
+        let Protection::Mac(tag) = &self.protection else { return false };
+        // blake3::Hash compares in constant time.
+        mac(key, &Self::body(MODE_MAC, self.counter, &self.fingerprint, &self.payload)) == blake3::Hash::from(*tag)
+    }
+}
+
+fn mac(key: &[u8; 32], body: &[u8]) -> blake3::Hash {
+    let mac_key = Zeroizing::new(blake3::derive_key(MAC_CONTEXT, key));
+    let mut hasher = blake3::Hasher::new_keyed(&mac_key);
+    crate::audit::hash_payload(&mut hasher, body);
+    let tag = hasher.finalize();
+    hasher.zeroize();
+    tag
+}
+
+impl Engine {
+    /// Wraps `data` in a message signed with the checkpoint key (see
+    /// [`Engine::checkpoint_public_key`]). Returns it with the new chain
+    /// head (hex).
+    pub fn protect_signed(&self, data: &[u8]) -> CoreResult<(ProtectedMessage, String)> {
+        let res = self.try_protect(data, None);
+        self.audited(OpType::Sign, &[], res)
+    }
+
+    /// Wraps `data` in a message tagged with keyed BLAKE3 under `key`.
+    pub fn protect_mac(&self, data: &[u8], key: &[u8; 32]) -> CoreResult<(ProtectedMessage, String)> {
+        let res = self.try_protect(data, Some(key));
+        self.audited(OpType::Sign, &[], res)
+    }
+
+    fn try_protect(&self, data: &[u8], key: Option<&[u8; 32]>) -> CoreResult<(ProtectedMessage, String)> {
+        self.permit(Permission::Sign)?;
+        self.check_rate_limit()?;
+        let ctr = self.next_counters(1)?;
+        let mode = if key.is_some() { MODE_MAC } else { MODE_SIGNATURE };
+        let body = ProtectedMessage::body(mode, ctr, &self.fingerprint, data);
+        let protection = match key {
+            Some(key) => Protection::Mac(*self.hashing(body.len(), || mac(key, &body)).as_bytes()),
+            None => Protection::Signature {
+                public_key: self.signing_key.0.clone(),
+                signature: crypto::sign(&self.signing_key.1.unwrapped(), &body)?,
+            },
+        };
+        let message = ProtectedMessage { counter: ctr, fingerprint: self.fingerprint, payload: data.to_vec(), protection };
+        let bytes = message.to_bytes();
+        let digest = self.hashing(bytes.len(), || crate::audit::ciphertext_digest(&bytes));
+        let evidence = self.record_event_at(ctr, OpType::Sign, Outcome::Success, &digest)?;
+        Ok((message, evidence))
+    }
+
+    /// [`ProtectedMessage::verify`], failing with [`CoreError::Revoked`]
+    /// if `trusted_pk` has been revoked.
+    pub fn verify_protected(&self, message: &ProtectedMessage, trusted_pk: &[u8]) -> CoreResult<bool> {
+        self.ensure_not_revoked(trusted_pk)?;
+        Ok(message.verify(trusted_pk))
+    }
+}
@@ -0,0 +1,218 @@
+//! JWE (RFC 7516) serialization of engine ciphertexts, for web stacks that
+//! already store and pass tokens.
+//!
+//! Tokens use direct key agreement: `alg` is [`JWE_ALG`], the encrypted key
+//! is empty and the Kyber ciphertext travels in the protected header as
+//! `ek`, next to the fields an [`Envelope`](crate::Envelope) carries
+//! (`ctr`, `fpr`, `kdf`, `ms` and, when not default, `ks`/`ki`). The content
+//! key is the envelope session key; `enc` names the suite's AEAD
+//! (`A256GCMSIV` or `XC20P`) and the protected header is its AAD, as the RFC
+//! requires.
+
+use crate::audit::{self, CiphertextBinding, OpType, Outcome};
+use crate::cert;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams, DEFAULT_KDF_INFO};
+use crate::rbac::Permission;
+use crate::suite::Suite;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use serde_json::{json, Map, Value};
+
+pub const JWE_ALG: &str = "KYBER1024";
+const TAG_LEN: usize = 16;
+
+// Registered suites have no JWE name.
+fn enc_name(suite: Suite) -> CoreResult<&'static str> {
+    match suite {
+        Suite::GcmSivCounter | Suite::GcmSivRandom => Ok("A256GCMSIV"),
+        Suite::XChaCha20Poly1305 => Ok("XC20P"),
+        Suite::Registered(_) => Err(CoreError::Config(format!("suite {} has no JWE enc", suite.name()))),
+    }
+}
+
+fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn unb64(s: &str) -> CoreResult<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(s).map_err(|_| CoreError::Format("bad JWE encoding"))
+}
+
+/// A JWE with direct key agreement (no encrypted key).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jwe {
+    /// `BASE64URL(UTF8(protected header))`, kept verbatim because it is the AAD.
+    pub protected: String,
+    pub iv: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+struct Header {
+    suite: Suite,
+    kdf: KdfParams,
+    counter: u64,
+    fingerprint: [u8; 32],
+    kem_ct: Vec<u8>,
+}
+
+impl Jwe {
+    /// `protected..iv.ciphertext.tag` (the encrypted key is empty).
+    pub fn to_compact(&self) -> String {
+        format!("{}..{}.{}.{}", self.protected, b64(&self.iv), b64(&self.ciphertext), b64(&self.tag))
+    }
+
+    /// Flattened JSON serialization.
+    pub fn to_json(&self) -> String {
+        json!({
+            "protected": self.protected,
+            "iv": b64(&self.iv),
+            "ciphertext": b64(&self.ciphertext),
+            "tag": b64(&self.tag),
+        }).to_string()
+    }
+
+    /// Parses the compact form or the flattened/general JSON form.
+    pub fn parse(token: &str) -> CoreResult<Jwe> {
+        let token = token.trim();
+        if !token.starts_with('{') {
+            let parts: Vec<&str> = token.split('.').collect();
+            let [protected, encrypted_key, iv, ciphertext, tag] = parts[..] else {
+                return Err(CoreError::Format("bad JWE"));
+            };
+            if !encrypted_key.is_empty() {
+                return Err(CoreError::Format("unexpected JWE encrypted key"));
+            }
+            return Ok(Jwe { protected: protected.into(), iv: unb64(iv)?, ciphertext: unb64(ciphertext)?, tag: unb64(tag)? });
+        }
+        let value: Value = serde_json::from_str(token).map_err(|_| CoreError::Format("bad JWE JSON"))?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str).ok_or(CoreError::Format("bad JWE JSON"));
+        Ok(Jwe {
+            protected: field("protected")?.into(),
+            iv: unb64(field("iv")?)?,
+            ciphertext: unb64(field("ciphertext")?)?,
+            tag: unb64(field("tag")?)?,
+        })
+    }
+
+    fn header(&self) -> CoreResult<Header> {
+        let bad = CoreError::Format("bad JWE header");
+        let value: Value = serde_json::from_slice(&unb64(&self.protected)?).map_err(|_| bad.clone())?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str);
+        let bytes = |name: &str| field(name).map(unb64).transpose();
+        if field("alg") != Some(JWE_ALG) {
+            return Err(CoreError::Format("unsupported JWE alg"));
+        }
+        let suite = match field("enc") {
+            Some("A256GCMSIV") => Suite::GcmSivCounter,
+            Some("XC20P") => Suite::XChaCha20Poly1305,
+            _ => return Err(CoreError::Format("unsupported JWE enc")),
+        };
+        let algorithm = Kdf::parse(field("kdf").ok_or(bad.clone())?).ok_or(CoreError::Format("unknown KDF"))?;
+        let kdf = KdfParams {
+            algorithm,
+            salt: bytes("ks")?.unwrap_or_default(),
+            info: bytes("ki")?.unwrap_or_else(|| DEFAULT_KDF_INFO.to_vec()),
+            message_salt: bytes("ms")?.map(|s| s.try_into().map_err(|_| CoreError::Format("bad message salt"))).transpose()?,
+            restricted: false,
+            schema: None,
+            epoch: None,
+            constraints: None,
+        };
+        Ok(Header {
+            suite,
+            kdf,
+            counter: value.get("ctr").and_then(Value::as_u64).ok_or(bad.clone())?,
+            fingerprint: bytes("fpr")?.ok_or(bad.clone())?.try_into().map_err(|_| bad.clone())?,
+            kem_ct: bytes("ek")?.ok_or(bad)?,
+        })
+    }
+
+    /// KEM ciphertext from the protected header.
+    pub fn kem_ct(&self) -> CoreResult<Vec<u8>> {
+        Ok(self.header()?.kem_ct)
+    }
+
+    /// Decapsulates with the recipient's Kyber secret key and decrypts.
+    /// `context` must match the one the token was sealed under.
+    pub fn open(&self, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let header = self.header()?;
+        if self.iv.len() != header.suite.nonce_len() || self.tag.len() != TAG_LEN {
+            return Err(CoreError::Format("bad JWE IV or tag length"));
+        }
+        let sk = crypto::parse_secret_key(sk_bytes)?;
+        let kem_ct = kyber1024::Ciphertext::from_bytes(&header.kem_ct)
+            .map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        let shared_secret = crypto::decapsulate(&kem_ct, &sk);
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &header.fingerprint, header.counter, &header.kdf, context)?;
+        let mut sealed = self.ciphertext.clone();
+        sealed.extend_from_slice(&self.tag);
+        header.suite.open_aad(&key, &self.iv, &sealed, self.protected.as_bytes())
+    }
+}
+
+impl Engine {
+    /// Encrypts `data` to the Kyber public key as a JWE and records it like
+    /// [`Engine::seal_with_context`]. The audit link binds the KEM
+    /// ciphertext, IV and `ciphertext | tag`.
+    pub fn seal_jwe(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Jwe, String)> {
+        let res = self.try_seal_jwe(data, pk_bytes, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_seal_jwe(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Jwe, String)> {
+        let enc = enc_name(self.suite)?;
+        self.check_rate_limit()?;
+        let pk = self.recipient_key(pk_bytes, data.len() as u64)?;
+        let ctr = self.next_counters(1)?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let kdf = self.kdf.for_message(shared_secret.as_bytes(), kem_ct.as_bytes());
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, ctr, &kdf, context)?;
+
+        let mut header = Map::new();
+        header.insert("alg".into(), JWE_ALG.into());
+        header.insert("enc".into(), enc.into());
+        header.insert("ek".into(), b64(kem_ct.as_bytes()).into());
+        header.insert("ctr".into(), ctr.into());
+        header.insert("fpr".into(), b64(&self.fingerprint).into());
+        header.insert("kdf".into(), kdf.algorithm.name().into());
+        if let Some(salt) = &kdf.message_salt {
+            header.insert("ms".into(), b64(salt).into());
+        }
+        if !kdf.salt.is_empty() {
+            header.insert("ks".into(), b64(&kdf.salt).into());
+        }
+        if kdf.info != DEFAULT_KDF_INFO {
+            header.insert("ki".into(), b64(&kdf.info).into());
+        }
+        let protected = b64(Value::Object(header).to_string().as_bytes());
+
+        let iv = self.suite.nonce(ctr, &key, data);
+        let mut sealed = self.install(|| self.suite.seal_aad(&key, &iv, data, protected.as_bytes()))?;
+        let bound = match self.ct_binding {
+            CiphertextBinding::Full => sealed.clone(),
+            CiphertextBinding::Digest => self.hashing(sealed.len(), || audit::ciphertext_digest(&sealed)).to_vec(),
+        };
+        let evidence = self.install(|| self.append_to_audit(ctr, &cert::key_id(pk_bytes), &iv, &bound, kem_ct.as_bytes()))?;
+        let tag = sealed.split_off(sealed.len() - TAG_LEN);
+        Ok((Jwe { protected, iv, ciphertext: sealed, tag }, evidence))
+    }
+
+    /// Decrypts a JWE from [`Engine::seal_jwe`], recording a `decrypt` event
+    /// bound to its KEM ciphertext.
+    pub fn open_jwe(&self, jwe: &Jwe, sk_bytes: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let kem_ct = match self.permit(Permission::Decrypt).and_then(|_| jwe.kem_ct()) {
+            Ok(kem_ct) => kem_ct,
+            Err(e) => return self.audited(OpType::Decrypt, &[], Err(e)),
+        };
+        let res = jwe.open(sk_bytes, context);
+        let plaintext = self.audited(OpType::Decrypt, &kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &kem_ct)?;
+        Ok(plaintext)
+    }
+}
@@ -0,0 +1,180 @@
+//! Known-answer test vectors for other implementations of the envelope
+//! format.
+//!
+//! Everything but the Kyber encapsulation is derived from the suite, KDF and
+//! vector index: plaintext, context, counter, fingerprint, nonce and message
+//! salt. Kyber keys and ciphertexts come from the OS RNG, so each vector also
+//! records the secret key and shared secret. A reader replays a vector by
+//! decapsulating, re-deriving the session key and re-sealing with the
+//! recorded nonce, which must reproduce the envelope byte for byte.
+//!
+//! Files use the NIST `.rsp` layout: `name = hex` lines, blank-line separated,
+//! each vector starting at `count`.
+
+use crate::crypto;
+use crate::envelope::Envelope;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams, MESSAGE_SALT_LEN};
+use crate::kem::KYBER1024;
+use crate::suite::Suite;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, PublicKey as KEMPublicKey, SecretKey as KEMSecretKey,
+                           SharedSecret as KEMSharedSecret};
+
+const SEED_CONTEXT: &str = "titancore test vectors v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub count: u64,
+    pub suite: Suite,
+    pub kdf: Kdf,
+    pub context: Vec<u8>,
+    pub plaintext: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub secret_key: Vec<u8>,
+    pub shared_secret: Vec<u8>,
+    pub session_key: [u8; 32],
+    pub envelope: Vec<u8>,
+}
+
+// Deterministic bytes for field `label` of vector `count`.
+fn seeded(suite: Suite, kdf: Kdf, count: u64, label: &str, out: &mut [u8]) {
+    let mut hasher = blake3::Hasher::new_derive_key(SEED_CONTEXT);
+    hasher.update(&[suite.wire_id(kdf)]);
+    hasher.update(&count.to_be_bytes());
+    hasher.update(label.as_bytes());
+    hasher.finalize_xof().fill(out);
+}
+
+/// Builds `count` vectors for `suite` and `kdf`. Plaintexts run from empty
+/// to a few hundred bytes; every other vector uses a context.
+pub fn generate_test_vectors(suite: Suite, kdf: Kdf, count: u64) -> CoreResult<Vec<TestVector>> {
+    (0..count).map(|i| generate_one(suite, kdf, i)).collect()
+}
+
+fn generate_one(suite: Suite, kdf: Kdf, count: u64) -> CoreResult<TestVector> {
+    let mut plaintext = vec![0u8; (count as usize * 37) % 301];
+    seeded(suite, kdf, count, "plaintext", &mut plaintext);
+    let context = if count % 2 == 1 { format!("kat-{}", count).into_bytes() } else { Vec::new() };
+    let mut fingerprint = [0u8; 32];
+    seeded(suite, kdf, count, "fingerprint", &mut fingerprint);
+    let mut salt = [0u8; MESSAGE_SALT_LEN];
+    seeded(suite, kdf, count, "salt", &mut salt);
+    let mut nonce = vec![0u8; suite.nonce_len()];
+    seeded(suite, kdf, count, "nonce", &mut nonce);
+    if suite == Suite::GcmSivCounter {
+        nonce[..8].copy_from_slice(&(count + 1).to_be_bytes());
+    }
+
+    let (pk, sk) = kyber1024::keypair();
+    let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+    let params = KdfParams { algorithm: kdf, message_salt: Some(salt), ..KdfParams::default() };
+    let session_key = crypto::derive_session_key(shared_secret.as_bytes(), &fingerprint, count + 1, &params, &context)?;
+    let ciphertext = suite.seal(&session_key, &nonce, &plaintext)?;
+    let envelope = Envelope {
+        suite,
+        kdf: params,
+        kem: KYBER1024,
+        counter: count + 1,
+        fingerprint,
+        kem_ct: kem_ct.as_bytes().to_vec(),
+        nonce,
+        ciphertext,
+        escrow: None,
+    };
+    Ok(TestVector {
+        count,
+        suite,
+        kdf,
+        context,
+        plaintext,
+        public_key: pk.as_bytes().to_vec(),
+        secret_key: sk.as_bytes().to_vec(),
+        shared_secret: shared_secret.as_bytes().to_vec(),
+        session_key: *session_key,
+        envelope: envelope.to_bytes(),
+    })
+}
+
+impl TestVector {
+    /// Replays the vector: every recorded intermediate must match, the
+    /// envelope must re-seal to the same bytes and open to the plaintext.
+    pub fn verify(&self) -> CoreResult<()> {
+        let envelope = Envelope::from_bytes(&self.envelope)?;
+        if envelope.suite != self.suite || envelope.kdf.algorithm != self.kdf {
+            return Err(CoreError::Format("test vector suite mismatch"));
+        }
+        let sk = crypto::parse_secret_key(&self.secret_key)?;
+        let kem_ct = kyber1024::Ciphertext::from_bytes(&envelope.kem_ct)
+            .map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        if crypto::decapsulate(&kem_ct, &sk).as_bytes() != self.shared_secret {
+            return Err(CoreError::Format("test vector shared secret mismatch"));
+        }
+        let key = crypto::derive_session_key(&self.shared_secret, &envelope.fingerprint, envelope.counter, &envelope.kdf, &self.context)?;
+        if *key != self.session_key {
+            return Err(CoreError::Format("test vector session key mismatch"));
+        }
+        if envelope.suite.seal(&key, &envelope.nonce, &self.plaintext)? != envelope.ciphertext {
+            return Err(CoreError::Format("test vector ciphertext mismatch"));
+        }
+        if envelope.open_with_context(&self.secret_key, &self.context)? != self.plaintext {
+            return Err(CoreError::Format("test vector plaintext mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// Renders vectors as an `.rsp` file.
+pub fn to_rsp(vectors: &[TestVector]) -> String {
+    let mut out = String::from("# titancore envelope KAT v1\n");
+    for v in vectors {
+        out.push_str(&format!(
+            "\ncount = {}\nsuite = {}\nkdf = {}\ncontext = {}\nmsg = {}\npk = {}\nsk = {}\nss = {}\nkey = {}\nenvelope = {}\n",
+            v.count, v.suite.name(), v.kdf.name(), hex::encode(&v.context), hex::encode(&v.plaintext),
+            hex::encode(&v.public_key), hex::encode(&v.secret_key), hex::encode(&v.shared_secret),
+            hex::encode(v.session_key), hex::encode(&v.envelope),
+        ));
+    }
+    out
+}
+
+/// Parses an `.rsp` file written by [`to_rsp`].
+pub fn parse_rsp(text: &str) -> CoreResult<Vec<TestVector>> {
+    let bad = CoreError::Format("bad test vector file");
+    let mut vectors = Vec::new();
+    let mut fields: Vec<(&str, &str)> = Vec::new();
+    let lines = text.lines().map(str::trim).chain(std::iter::once(""));
+    for line in lines {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            if !fields.is_empty() {
+                vectors.push(vector_from_fields(&fields)?);
+                fields.clear();
+            }
+            continue;
+        }
+        let (name, value) = line.split_once('=').ok_or(bad.clone())?;
+        fields.push((name.trim(), value.trim()));
+    }
+    Ok(vectors)
+}
+
+fn vector_from_fields(fields: &[(&str, &str)]) -> CoreResult<TestVector> {
+    let bad = CoreError::Format("bad test vector file");
+    let get = |name: &str| fields.iter().find(|(n, _)| *n == name).map(|(_, v)| *v).ok_or(bad.clone());
+    let bytes = |name: &str| hex::decode(get(name)?).map_err(|_| bad.clone());
+    Ok(TestVector {
+        count: get("count")?.parse().map_err(|_| bad.clone())?,
+        suite: Suite::parse(get("suite")?).ok_or(bad.clone())?,
+        kdf: Kdf::parse(get("kdf")?).ok_or(bad.clone())?,
+        context: bytes("context")?,
+        plaintext: bytes("msg")?,
+        public_key: bytes("pk")?,
+        secret_key: bytes("sk")?,
+        shared_secret: bytes("ss")?,
+        session_key: bytes("key")?.try_into().map_err(|_| bad.clone())?,
+        envelope: bytes("envelope")?,
+    })
+}
@@ -0,0 +1,708 @@
+//! Key encapsulation behind a trait, so envelopes are not tied to one KEM.
+//!
+//! A [`Kem`] makes recipient keypairs, encapsulates a fresh shared secret
+//! to a public key and recovers it with the secret key. Each has a one-byte
+//! ID that envelopes record, so a recipient opens an envelope with the KEM
+//! it was sealed under. Kyber-1024 ([`KYBER1024`]), Kyber-768 and Kyber-512
+//! are built in, as is Classic McEliece ([`MCELIECE6688128`]), for data
+//! that must stay confidential for decades, with the `mceliece` feature,
+//! HQC-256 ([`HQC256`]) with the `hqc` feature and the X25519 + ML-KEM-768
+//! hybrid X-Wing ([`XWING`]) with the `xwing` feature; [`register`] adds
+//! other implementations, and [`EngineConfig::kem`] chooses the one an
+//! engine seals with. Kyber-1024 stays the default, and envelopes sealed
+//! under it keep their old layout.
+//!
+//! A [`Dual`] KEM encapsulates to two KEMs at once, so an envelope stays
+//! confidential unless both are broken. Kyber-1024 paired with HQC-256
+//! ([`KYBER1024_HQC256`]) is built in with the `hqc` feature. The IDs are
+//! fixed here whether or not their feature is on, so envelopes sealed by
+//! different deployments agree.
+//!
+//! Registration is process-wide and permanent: an envelope is only as
+//! readable as the KEM its ID names, so an ID is never reassigned. Streams,
+//! archives, multipart uploads, channels, ratchets, escrow and the JOSE,
+//! COSE and age formats stay on Kyber-1024.
+//!
+//! [`EngineConfig::kem`]: crate::EngineConfig::kem
+
+use crate::crypto;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Kdf;
+use crate::secret::Wiped;
+use parking_lot::RwLock;
+#[cfg(feature = "hqc")]
+use pqcrypto_hqc::hqc256;
+use pqcrypto_kyber::{kyber1024, kyber512, kyber768};
+use pqcrypto_traits::kem::{Ciphertext as _, PublicKey as _, SecretKey as _, SharedSecret as _};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+pub const KYBER1024: u8 = 1;
+pub const KYBER768: u8 = 2;
+pub const KYBER512: u8 = 3;
+/// Classic McEliece `mceliece6688128` ([`McEliece6688128`]), built in with
+/// the `mceliece` feature.
+pub const MCELIECE6688128: u8 = 4;
+/// HQC-256 (`hqc256`), built in with the `hqc` feature.
+pub const HQC256: u8 = 5;
+/// The [`Dual`] pairing of Kyber-1024 with HQC-256 (`kyber1024+hqc256`),
+/// built in with the `hqc` feature.
+pub const KYBER1024_HQC256: u8 = 6;
+/// X-Wing (`x-wing`, [`XWing`]), built in with the `xwing` feature.
+pub const XWING: u8 = 7;
+
+/// Longest KEM ciphertext an envelope can carry: headers record its length
+/// in two bytes.
+pub const MAX_CIPHERTEXT_LEN: usize = u16::MAX as usize;
+
+/// A key encapsulation mechanism envelopes can be sealed under.
+pub trait Kem: Send + Sync {
+    /// Recorded in each envelope; unique among registered KEMs. Zero is
+    /// reserved.
+    fn id(&self) -> u8;
+
+    /// Lowercase name, e.g. `"kyber1024"`; unique among registered KEMs.
+    fn name(&self) -> &'static str;
+
+    fn public_key_len(&self) -> usize;
+
+    fn secret_key_len(&self) -> usize;
+
+    fn ciphertext_len(&self) -> usize;
+
+    /// Fresh keypair as `(public, secret)` bytes. Fails with the
+    /// [`entropy`](crate::entropy) error if randomness cannot be drawn.
+    fn keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)>;
+
+    /// Fails with [`CoreError::InvalidKey`] unless `pk` is a public key of
+    /// this KEM. The default checks only the length.
+    fn check_public_key(&self, pk: &[u8]) -> CoreResult<()> {
+        check_len(pk.len(), self.public_key_len(), CoreError::InvalidKey)
+    }
+
+    /// Fails with [`CoreError::InvalidKey`] unless `sk` is a secret key of
+    /// this KEM. The default checks only the length.
+    fn check_secret_key(&self, sk: &[u8]) -> CoreResult<()> {
+        check_len(sk.len(), self.secret_key_len(), CoreError::InvalidKey)
+    }
+
+    /// A fresh shared secret and its encapsulation to `pk`.
+    fn encapsulate(&self, pk: &[u8]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)>;
+
+    /// Recovers the shared secret from `ct` with `sk`. A secret key of the
+    /// wrong form fails with [`CoreError::InvalidKey`], a ciphertext of the
+    /// wrong length with [`CoreError::Format`].
+    fn decapsulate(&self, sk: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>>;
+}
+
+impl std::fmt::Debug for dyn Kem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Kem({})", self.name())
+    }
+}
+
+/// Kyber-1024, with public keys checked as [`crypto::import_public_key`]
+/// does.
+pub struct Kyber1024;
+
+impl Kem for Kyber1024 {
+    fn id(&self) -> u8 {
+        KYBER1024
+    }
+
+    fn name(&self) -> &'static str {
+        "kyber1024"
+    }
+
+    fn public_key_len(&self) -> usize {
+        kyber1024::public_key_bytes()
+    }
+
+    fn secret_key_len(&self) -> usize {
+        kyber1024::secret_key_bytes()
+    }
+
+    fn ciphertext_len(&self) -> usize {
+        kyber1024::ciphertext_bytes()
+    }
+
+    fn keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+        Ok(crypto::generate_keypair())
+    }
+
+    fn check_public_key(&self, pk: &[u8]) -> CoreResult<()> {
+        crypto::parse_public_key(pk).map(drop)
+    }
+
+    fn encapsulate(&self, pk: &[u8]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+        let (shared_secret, ct) = crypto::encapsulate(&crypto::parse_public_key(pk)?);
+        Ok((Zeroizing::new(shared_secret.as_bytes().to_vec()), ct.as_bytes().to_vec()))
+    }
+
+    fn decapsulate(&self, sk: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+        let sk = crypto::parse_secret_key(sk)?;
+        let ct = kyber1024::Ciphertext::from_bytes(ct).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        Ok(Zeroizing::new(crypto::decapsulate(&ct, &sk).as_bytes().to_vec()))
+    }
+}
+
+// The smaller Kyber parameter sets and HQC, checked for length only.
+macro_rules! pqclean_kem {
+    ($(#[$meta:meta])* $kem:ident, $module:ident, $id:expr, $name:literal) => {
+        #[doc = concat!("`", $name, "`.")]
+        $(#[$meta])*
+        pub struct $kem;
+
+        $(#[$meta])*
+        impl Kem for $kem {
+            fn id(&self) -> u8 {
+                $id
+            }
+
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn public_key_len(&self) -> usize {
+                $module::public_key_bytes()
+            }
+
+            fn secret_key_len(&self) -> usize {
+                $module::secret_key_bytes()
+            }
+
+            fn ciphertext_len(&self) -> usize {
+                $module::ciphertext_bytes()
+            }
+
+            fn keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+                let (pk, sk) = $module::keypair();
+                let sk = Wiped::new(sk);
+                Ok((pk.as_bytes().to_vec(), Zeroizing::new(sk.as_bytes().to_vec())))
+            }
+
+            fn encapsulate(&self, pk: &[u8]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+                let pk = $module::PublicKey::from_bytes(pk).map_err(|_| CoreError::InvalidKey)?;
+                let (shared_secret, ct) = $module::encapsulate(&pk);
+                let shared_secret = Wiped::new(shared_secret);
+                Ok((Zeroizing::new(shared_secret.as_bytes().to_vec()), ct.as_bytes().to_vec()))
+            }
+
+            fn decapsulate(&self, sk: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+                let sk = $module::SecretKey::from_bytes(sk).map(Wiped::new).map_err(|_| CoreError::InvalidKey)?;
+                let ct = $module::Ciphertext::from_bytes(ct).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+                let shared_secret = Wiped::new($module::decapsulate(&ct, &sk));
+                Ok(Zeroizing::new(shared_secret.as_bytes().to_vec()))
+            }
+        }
+    };
+}
+
+pqclean_kem!(Kyber768, kyber768, KYBER768, "kyber768");
+pqclean_kem!(Kyber512, kyber512, KYBER512, "kyber512");
+pqclean_kem!(#[cfg(feature = "hqc")] Hqc256, hqc256, HQC256, "hqc256");
+
+const DUAL_INFO: &[u8] = b"titancore dual kem v1";
+
+fn check_len(len: usize, expected: usize, err: CoreError) -> CoreResult<()> {
+    if len != expected {
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Two KEMs used together, for hedging against a break of either family:
+/// keys and ciphertexts are the two concatenated, first then second, and
+/// the shared secret is HKDF-SHA-256 over both secrets, bound to both
+/// ciphertexts. Registered with [`register`] like any other KEM; Kyber-1024
+/// with HQC-256 is built in as [`KYBER1024_HQC256`].
+pub struct Dual {
+    id: u8,
+    name: &'static str,
+    first: Arc<dyn Kem>,
+    second: Arc<dyn Kem>,
+}
+
+impl Dual {
+    pub fn new(id: u8, name: &'static str, first: Arc<dyn Kem>, second: Arc<dyn Kem>) -> Self {
+        Dual { id, name, first, second }
+    }
+
+    fn combine(&self, first: &[u8], second: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+        let mut ikm = Zeroizing::new(Vec::with_capacity(first.len() + second.len()));
+        ikm.extend_from_slice(first);
+        ikm.extend_from_slice(second);
+        let info = [DUAL_INFO, ct].concat();
+        let mut out = Zeroizing::new(vec![0u8; 32]);
+        Kdf::HkdfSha256.derive(&ikm, &[], &info, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl Kem for Dual {
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn public_key_len(&self) -> usize {
+        self.first.public_key_len() + self.second.public_key_len()
+    }
+
+    fn secret_key_len(&self) -> usize {
+        self.first.secret_key_len() + self.second.secret_key_len()
+    }
+
+    fn ciphertext_len(&self) -> usize {
+        self.first.ciphertext_len() + self.second.ciphertext_len()
+    }
+
+    fn keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+        let (mut pk, mut sk) = self.first.keypair()?;
+        let (pk2, sk2) = self.second.keypair()?;
+        pk.extend_from_slice(&pk2);
+        sk.extend_from_slice(&sk2);
+        Ok((pk, sk))
+    }
+
+    fn check_public_key(&self, pk: &[u8]) -> CoreResult<()> {
+        check_len(pk.len(), self.public_key_len(), CoreError::InvalidKey)?;
+        let (first, second) = pk.split_at(self.first.public_key_len());
+        self.first.check_public_key(first)?;
+        self.second.check_public_key(second)
+    }
+
+    fn check_secret_key(&self, sk: &[u8]) -> CoreResult<()> {
+        check_len(sk.len(), self.secret_key_len(), CoreError::InvalidKey)?;
+        let (first, second) = sk.split_at(self.first.secret_key_len());
+        self.first.check_secret_key(first)?;
+        self.second.check_secret_key(second)
+    }
+
+    fn encapsulate(&self, pk: &[u8]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+        check_len(pk.len(), self.public_key_len(), CoreError::InvalidKey)?;
+        let (pk1, pk2) = pk.split_at(self.first.public_key_len());
+        let (ss1, mut ct) = self.first.encapsulate(pk1)?;
+        let (ss2, ct2) = self.second.encapsulate(pk2)?;
+        ct.extend_from_slice(&ct2);
+        Ok((self.combine(&ss1, &ss2, &ct)?, ct))
+    }
+
+    fn decapsulate(&self, sk: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+        check_len(sk.len(), self.secret_key_len(), CoreError::InvalidKey)?;
+        check_len(ct.len(), self.ciphertext_len(), CoreError::Format("bad KEM ciphertext"))?;
+        let (sk1, sk2) = sk.split_at(self.first.secret_key_len());
+        let (ct1, ct2) = ct.split_at(self.first.ciphertext_len());
+        let ss1 = self.first.decapsulate(sk1, ct1)?;
+        let ss2 = self.second.decapsulate(sk2, ct2)?;
+        self.combine(&ss1, &ss2, ct)
+    }
+}
+
+/// Classic McEliece `mceliece6688128`, checked for length only. Public
+/// keys are 1,044,992 bytes, so keys and buffers stay on the heap, and key
+/// generation, which needs more stack than a worker thread has, runs on a
+/// thread of its own.
+#[cfg(feature = "mceliece")]
+pub struct McEliece6688128;
+
+#[cfg(feature = "mceliece")]
+mod mceliece {
+    pub(super) use pqcrypto_classicmceliece::ffi::{
+        PQCLEAN_MCELIECE6688128_CLEAN_CRYPTO_BYTES as SHARED_SECRET_LEN,
+        PQCLEAN_MCELIECE6688128_CLEAN_CRYPTO_CIPHERTEXTBYTES as CIPHERTEXT_LEN,
+        PQCLEAN_MCELIECE6688128_CLEAN_CRYPTO_PUBLICKEYBYTES as PUBLIC_KEY_LEN,
+        PQCLEAN_MCELIECE6688128_CLEAN_CRYPTO_SECRETKEYBYTES as SECRET_KEY_LEN,
+        PQCLEAN_MCELIECE6688128_CLEAN_crypto_kem_dec as dec, PQCLEAN_MCELIECE6688128_CLEAN_crypto_kem_enc as enc,
+        PQCLEAN_MCELIECE6688128_CLEAN_crypto_kem_keypair as keypair,
+    };
+
+    // The reference key generation keeps its systematic-form matrix, about
+    // 1.4 MB, on the stack.
+    pub(super) const KEYGEN_STACK: usize = 16 << 20;
+}
+
+#[cfg(feature = "mceliece")]
+impl Kem for McEliece6688128 {
+    fn id(&self) -> u8 {
+        MCELIECE6688128
+    }
+
+    fn name(&self) -> &'static str {
+        "mceliece6688128"
+    }
+
+    fn public_key_len(&self) -> usize {
+        mceliece::PUBLIC_KEY_LEN
+    }
+
+    fn secret_key_len(&self) -> usize {
+        mceliece::SECRET_KEY_LEN
+    }
+
+    fn ciphertext_len(&self) -> usize {
+        mceliece::CIPHERTEXT_LEN
+    }
+
+    fn keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+        let generate = || {
+            let mut pk = vec![0u8; mceliece::PUBLIC_KEY_LEN];
+            let mut sk = Zeroizing::new(vec![0u8; mceliece::SECRET_KEY_LEN]);
+            // SAFETY: both buffers have the lengths the C code writes.
+            let rc = unsafe { mceliece::keypair(pk.as_mut_ptr(), sk.as_mut_ptr()) };
+            // The reference code fails only if it cannot draw randomness.
+            if rc != 0 {
+                return Err(CoreError::Entropy);
+            }
+            Ok((pk, sk))
+        };
+        std::thread::Builder::new()
+            .name("titancore-mceliece-keygen".into())
+            .stack_size(mceliece::KEYGEN_STACK)
+            .spawn(generate)?
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    fn encapsulate(&self, pk: &[u8]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+        self.check_public_key(pk)?;
+        let mut ct = vec![0u8; mceliece::CIPHERTEXT_LEN];
+        let mut shared_secret = Zeroizing::new(vec![0u8; mceliece::SHARED_SECRET_LEN]);
+        // SAFETY: the lengths of all three buffers were checked or set above.
+        let rc = unsafe { mceliece::enc(ct.as_mut_ptr(), shared_secret.as_mut_ptr(), pk.as_ptr()) };
+        if rc != 0 {
+            return Err(CoreError::Encryption);
+        }
+        Ok((shared_secret, ct))
+    }
+
+    fn decapsulate(&self, sk: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+        self.check_secret_key(sk)?;
+        check_len(ct.len(), mceliece::CIPHERTEXT_LEN, CoreError::Format("bad KEM ciphertext"))?;
+        let mut shared_secret = Zeroizing::new(vec![0u8; mceliece::SHARED_SECRET_LEN]);
+        // SAFETY: as above. A bad ciphertext decapsulates to an unrelated
+        // secret rather than failing.
+        let rc = unsafe { mceliece::dec(shared_secret.as_mut_ptr(), ct.as_ptr(), sk.as_ptr()) };
+        if rc != 0 {
+            return Err(CoreError::Decryption);
+        }
+        Ok(shared_secret)
+    }
+}
+
+/// X-Wing: X25519 with ML-KEM-768 under the combiner of
+/// draft-connolly-cfrg-xwing-kem, interoperable with other implementations
+/// of the draft. The ML-KEM here is the FIPS 203 one from the `ml-kem`
+/// crate, not the round-3 Kyber the other KEMs use; a [`Dual`] is not
+/// X-Wing. Secret keys are the 32-byte seed both keys expand from, public
+/// keys `ML-KEM-768 key(1184) | X25519 key(32)` and ciphertexts
+/// `ML-KEM-768 ciphertext(1088) | X25519 ephemeral key(32)`.
+#[cfg(feature = "xwing")]
+pub struct XWing;
+
+#[cfg(feature = "xwing")]
+mod xwing {
+    use crate::error::{CoreError, CoreResult};
+    use ml_kem::kem::{Decapsulate, DecapsulationKey, EncapsulationKey};
+    use ml_kem::{B32, EncapsulateDeterministic, EncodedSizeUser, KemCore, MlKem768, MlKem768Params};
+    use sha3::digest::{ExtendableOutput, Update, XofReader};
+    use sha3::{Digest, Sha3_256, Shake256};
+    use x25519_dalek::{PublicKey, StaticSecret};
+    use zeroize::{Zeroize, Zeroizing};
+
+    pub(super) const PUBLIC_KEY_LEN: usize = 1216;
+    pub(super) const SECRET_KEY_LEN: usize = 32;
+    pub(super) const CIPHERTEXT_LEN: usize = 1120;
+    const ML_KEM_PUBLIC_KEY_LEN: usize = 1184;
+    const ML_KEM_CIPHERTEXT_LEN: usize = 1088;
+    const LABEL: &[u8] = b"\\.//^\\";
+
+    struct Expanded {
+        dk: DecapsulationKey<MlKem768Params>,
+        ek: EncapsulationKey<MlKem768Params>,
+        sk_x: StaticSecret,
+        pk_x: PublicKey,
+    }
+
+    fn expand(sk: &[u8; 32]) -> Expanded {
+        let mut seed = Zeroizing::new([0u8; 96]);
+        let mut xof = Shake256::default();
+        xof.update(sk);
+        xof.finalize_xof().read(seed.as_mut());
+        let (d, z) = (B32::from(<[u8; 32]>::try_from(&seed[..32]).unwrap()), B32::from(<[u8; 32]>::try_from(&seed[32..64]).unwrap()));
+        let (dk, ek) = MlKem768::generate_deterministic(&d, &z);
+        let sk_x = StaticSecret::from(<[u8; 32]>::try_from(&seed[64..]).unwrap());
+        let pk_x = PublicKey::from(&sk_x);
+        Expanded { dk, ek, sk_x, pk_x }
+    }
+
+    fn combine(ss_m: &[u8], ss_x: &[u8], ct_x: &[u8], pk_x: &[u8]) -> Zeroizing<Vec<u8>> {
+        let mut hasher = Sha3_256::new();
+        Digest::update(&mut hasher, ss_m);
+        Digest::update(&mut hasher, ss_x);
+        Digest::update(&mut hasher, ct_x);
+        Digest::update(&mut hasher, pk_x);
+        Digest::update(&mut hasher, LABEL);
+        Zeroizing::new(hasher.finalize().to_vec())
+    }
+
+    /// The public key for the seed `sk`.
+    pub(super) fn public_key(sk: &[u8; 32]) -> Vec<u8> {
+        let keys = expand(sk);
+        [keys.ek.as_bytes().as_slice(), keys.pk_x.as_bytes()].concat()
+    }
+
+    /// Encapsulates to `pk` with the 64 bytes of randomness `eseed`.
+    pub(super) fn encapsulate(pk: &[u8], eseed: &[u8; 64]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+        let (pk_m, pk_x) = pk.split_at(ML_KEM_PUBLIC_KEY_LEN);
+        let ek = EncapsulationKey::<MlKem768Params>::from_bytes(pk_m.try_into().map_err(|_| CoreError::InvalidKey)?);
+        let pk_x = PublicKey::from(<[u8; 32]>::try_from(pk_x).map_err(|_| CoreError::InvalidKey)?);
+        let m = B32::from(<[u8; 32]>::try_from(&eseed[..32]).unwrap());
+        let (ct_m, mut ss_m) = ek.encapsulate_deterministic(&m).map_err(|_| CoreError::Encryption)?;
+        let ek_x = StaticSecret::from(<[u8; 32]>::try_from(&eseed[32..]).unwrap());
+        let ct_x = PublicKey::from(&ek_x);
+        let ss_x = ek_x.diffie_hellman(&pk_x);
+        let shared_secret = combine(&ss_m, ss_x.as_bytes(), ct_x.as_bytes(), pk_x.as_bytes());
+        ss_m.as_mut_slice().zeroize();
+        Ok((shared_secret, [ct_m.as_slice(), ct_x.as_bytes()].concat()))
+    }
+
+    /// Recovers the shared secret from `ct` with the seed `sk`.
+    pub(super) fn decapsulate(sk: &[u8; 32], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+        let keys = expand(sk);
+        let (ct_m, ct_x) = ct.split_at(ML_KEM_CIPHERTEXT_LEN);
+        let ct_x = PublicKey::from(<[u8; 32]>::try_from(ct_x).map_err(|_| CoreError::Format("bad KEM ciphertext"))?);
+        let mut ss_m = keys.dk.decapsulate(ct_m.try_into().map_err(|_| CoreError::Format("bad KEM ciphertext"))?)
+            .map_err(|_| CoreError::Decryption)?;
+        let ss_x = keys.sk_x.diffie_hellman(&ct_x);
+        let shared_secret = combine(&ss_m, ss_x.as_bytes(), ct_x.as_bytes(), keys.pk_x.as_bytes());
+        ss_m.as_mut_slice().zeroize();
+        Ok(shared_secret)
+    }
+}
+
+#[cfg(feature = "xwing")]
+impl Kem for XWing {
+    fn id(&self) -> u8 {
+        XWING
+    }
+
+    fn name(&self) -> &'static str {
+        "x-wing"
+    }
+
+    fn public_key_len(&self) -> usize {
+        xwing::PUBLIC_KEY_LEN
+    }
+
+    fn secret_key_len(&self) -> usize {
+        xwing::SECRET_KEY_LEN
+    }
+
+    fn ciphertext_len(&self) -> usize {
+        xwing::CIPHERTEXT_LEN
+    }
+
+    fn keypair(&self) -> CoreResult<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+        let mut sk = Zeroizing::new([0u8; 32]);
+        crate::entropy::fill(sk.as_mut())?;
+        Ok((xwing::public_key(&sk), Zeroizing::new(sk.to_vec())))
+    }
+
+    fn encapsulate(&self, pk: &[u8]) -> CoreResult<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+        self.check_public_key(pk)?;
+        let mut eseed = Zeroizing::new([0u8; 64]);
+        crate::entropy::fill(eseed.as_mut())?;
+        xwing::encapsulate(pk, &eseed)
+    }
+
+    fn decapsulate(&self, sk: &[u8], ct: &[u8]) -> CoreResult<Zeroizing<Vec<u8>>> {
+        let sk = Zeroizing::new(<[u8; 32]>::try_from(sk).map_err(|_| CoreError::InvalidKey)?);
+        check_len(ct.len(), xwing::CIPHERTEXT_LEN, CoreError::Format("bad KEM ciphertext"))?;
+        xwing::decapsulate(&sk, ct)
+    }
+}
+
+static REGISTERED: RwLock<Vec<Arc<dyn Kem>>> = RwLock::new(Vec::new());
+
+/// Whether `id` is one of the built-in Kyber parameter sets. KEMs built in
+/// behind a feature are not counted.
+pub fn is_builtin(id: u8) -> bool {
+    matches!(id, KYBER1024 | KYBER768 | KYBER512)
+}
+
+fn builtin(id: u8) -> Option<Arc<dyn Kem>> {
+    match id {
+        KYBER1024 => Some(Arc::new(Kyber1024)),
+        KYBER768 => Some(Arc::new(Kyber768)),
+        KYBER512 => Some(Arc::new(Kyber512)),
+        #[cfg(feature = "mceliece")]
+        MCELIECE6688128 => Some(Arc::new(McEliece6688128)),
+        #[cfg(feature = "hqc")]
+        HQC256 => Some(Arc::new(Hqc256)),
+        #[cfg(feature = "hqc")]
+        KYBER1024_HQC256 => Some(Arc::new(Dual::new(KYBER1024_HQC256, "kyber1024+hqc256", Arc::new(Kyber1024), Arc::new(Hqc256)))),
+        #[cfg(feature = "xwing")]
+        XWING => Some(Arc::new(XWing)),
+        _ => None,
+    }
+}
+
+fn builtins() -> impl Iterator<Item = Arc<dyn Kem>> {
+    [KYBER1024, KYBER768, KYBER512, MCELIECE6688128, HQC256, KYBER1024_HQC256, XWING].into_iter().filter_map(builtin)
+}
+
+/// Makes `kem` available to every engine in the process under its ID and
+/// name. Fails with [`CoreError::Config`] for ID zero, an ID or name
+/// already taken, built-in ones included, or ciphertexts longer than
+/// [`MAX_CIPHERTEXT_LEN`].
+pub fn register(kem: Arc<dyn Kem>) -> CoreResult<()> {
+    let mut registered = REGISTERED.write();
+    let (id, name) = (kem.id(), kem.name());
+    if id == 0 {
+        return Err(CoreError::Config("KEM id 0 is reserved".into()));
+    }
+    if kem.ciphertext_len() > MAX_CIPHERTEXT_LEN {
+        return Err(CoreError::Config(format!("KEM {} ciphertexts are longer than {} bytes", name, MAX_CIPHERTEXT_LEN)));
+    }
+    if builtins().chain(registered.iter().cloned()).any(|k| k.id() == id || k.name() == name) {
+        return Err(CoreError::Config(format!("KEM {} (id {}) already registered", name, id)));
+    }
+    registered.push(kem);
+    Ok(())
+}
+
+/// The KEM recorded as `id`.
+pub fn find(id: u8) -> Option<Arc<dyn Kem>> {
+    builtin(id).or_else(|| REGISTERED.read().iter().find(|k| k.id() == id).cloned())
+}
+
+/// [`find`], failing with [`CoreError::Format`] for an ID nothing is
+/// registered under, as when reading an envelope.
+pub fn get(id: u8) -> CoreResult<Arc<dyn Kem>> {
+    find(id).ok_or(CoreError::Format("unknown KEM"))
+}
+
+/// Fails with [`CoreError::InvalidKey`] unless `sk` is a secret key of
+/// some built-in or registered KEM, as a check before a batch whose
+/// envelopes may name different ones.
+pub fn check_secret_key(sk: &[u8]) -> CoreResult<()> {
+    if !builtins().chain(REGISTERED.read().iter().cloned()).any(|k| k.check_secret_key(sk).is_ok()) {
+        return Err(CoreError::InvalidKey);
+    }
+    Ok(())
+}
+
+/// Fails with [`CoreError::InvalidKey`] unless `pk` is a public key of
+/// some built-in or registered KEM, as a check before storing it.
+pub fn check_public_key(pk: &[u8]) -> CoreResult<()> {
+    if !builtins().chain(REGISTERED.read().iter().cloned()).any(|k| k.check_public_key(pk).is_ok()) {
+        return Err(CoreError::InvalidKey);
+    }
+    Ok(())
+}
+
+/// The KEM named `name`.
+pub fn parse(name: &str) -> Option<Arc<dyn Kem>> {
+    builtins().chain(REGISTERED.read().iter().cloned()).find(|k| k.name() == name)
+}
+
+/// Names of the built-in and registered KEMs, built-ins first.
+pub fn algorithms() -> Vec<&'static str> {
+    builtins().chain(REGISTERED.read().iter().cloned()).map(|k| k.name()).collect()
+}
+
+#[cfg(all(test, feature = "xwing"))]
+mod tests {
+    use super::*;
+
+    // The first test vector of draft-connolly-cfrg-xwing-kem.
+    const SEED: &str = "7f9c2ba4e88f827d616045507605853ed73b8093f6efbc88eb1a6eacfa66ef26";
+    const ESEED: &str = concat!(
+        "3cb1eea988004b93103cfb0aeefd2a686e01fa4a58e8a3639ca8a1e3f9ae57e235b8cc873c23dc62b8d260169afa2f75",
+        "ab916a58d974918835d25e6a435085b2",
+    );
+    const SHARED_SECRET: &str = "d2df0522128f09dd8e2c92b1e905c793d8f57a54c3da25861f10bf4ca613e384";
+    const PUBLIC_KEY: &str = concat!(
+        "e2236b35a8c24b39b10aa1323a96a919a2ced88400633a7b07131713fc14b2b5b19cfc3da5fa1a92c49f25513e0fd30d",
+        "6b1611c9ab9635d7086727a4b7d21d34244e66969cf15b3b2a785329f61b096b277ea037383479a6b556de7231fe4b7f",
+        "a9c9ac24c0699a0018a5253401bacfa905ca816573e56a2d2e067e9b7287533ba13a937dedb31fa44baced4076992361",
+        "0034ae31e619a170245199b3c5c39864859fe1b4c9717a07c30495bdfb98a0a002ccf56c1286cef5041dede3c44cf16b",
+        "f562c7448518026b3d8b9940680abd38a1575fd27b58da063bfac32c39c30869374c05c1aeb1898b6b303cc68be45534",
+        "6ee0af699636224a148ca2aea10463111c709f69b69c70ce8538746698c4c60a9aef0030c7924ceec42a5d36816f545e",
+        "ae13293460b3acb37ea0e13d70e4aa78686da398a8397c08eaf96882113fe4f7bad4da40b0501e1c753efe73053c8701",
+        "4e8661c33099afe8bede414a5b1aa27d8392b3e131e9a70c1055878240cad0f40d5fe3cdf85236ead97e2a97448363b2",
+        "808caafd516cd25052c5c362543c2517e4acd0e60ec07163009b6425fc32277acee71c24bab53ed9f29e74c66a0a3564",
+        "955998d76b96a9a8b50d1635a4d7a67eb42df5644d330457293a8042f53cc7a69288f17ed55827e82b28e82665a86a14",
+        "fbd96645eca8172c044f83bc0d8c0b4c8626985631ca87af829068f1358963cb333664ca482763ba3b3bb208577f9ba6",
+        "ac62c25f76592743b64be519317714cb4102cb7b2f9a25b2b4f0615de31decd9ca55026d6da0b65111b16fe52feed8a4",
+        "87e144462a6dba93728f500b6ffc49e515569ef25fed17aff520507368253525860f58be3be61c964604a6ac814e6935",
+        "596402a520a4670b3d284318866593d15a4bb01c35e3e587ee0c67d2880d6f2407fb7a70712b838deb96c5d7bf2b44bc",
+        "f6038ccbe33fbcf51a54a584fe90083c91c7a6d43d4fb15f48c60c2fd66e0a8aad4ad64e5c42bb8877c0ebec2b5e387c",
+        "8a988fdc23beb9e16c8757781e0a1499c61e138c21f216c29d076979871caa6942bafc090544bee99b54b16cb9a9a364",
+        "d6246d9f42cce53c66b59c45c8f9ae9299a75d15180c3c952151a91b7a10772429dc4cbae6fcc622fa8018c63439f890",
+        "630b9928db6bb7f9438ae4065ed34d73d486f3f52f90f0807dc88dfdd8c728e954f1ac35c06c000ce41a0582580e3bb5",
+        "7b672972890ac5e7988e7850657116f1b57d0809aaedec0bede1ae148148311c6f7e317346e5189fb8cd635b986f8c0b",
+        "dd27641c584b778b3a911a80be1c9692ab8e1bbb12839573cce19df183b45835bbb55052f9fc66a1678ef2a36dea7841",
+        "1e6c8d60501b4e60592d13698a943b509185db912e2ea10be06171236b327c71716094c964a68b03377f513a05bcd99c",
+        "1f346583bb052977a10a12adfc758034e5617da4c1276585e5774e1f3b9978b09d0e9c44d3bc86151c43aad185712717",
+        "340223ac381d21150a04294e97bb13bbda21b5a182b6da969e19a7fd072737fa8e880a53c2428e3d049b7d2197405296",
+        "ddb361912a7bcf4827ced611d0c7a7da104dde4322095339f64a61d5bb108ff0bf4d780cae509fb22c256914193ff734",
+        "9042581237d522828824ee3bdfd07fb03f1f942d2ea179fe722f06cc03de5b69859edb06eff389b27dce598445702162",
+        "23593d4ba32d9abac8cd049040ef6534",
+    );
+    const CIPHERTEXT: &str = concat!(
+        "b83aa828d4d62b9a83ceffe1d3d3bb1ef31264643c070c5798927e41fb07914a273f8f96e7826cd5375a283d7da88530",
+        "4c5de0516a0f0654243dc5b97f8bfeb831f68251219aabdd723bc6512041acbaef8af44265524942b902e68ffd23221c",
+        "da70b1b55d776a92d1143ea3a0c475f63ee6890157c7116dae3f62bf72f60acd2bb8cc31ce2ba0de364f52b8ed38c79d",
+        "719715963a5dd3842d8e8b43ab704e4759b5327bf027c63c8fa857c4908d5a8a7b88ac7f2be394d93c3706ddd4e698cc",
+        "6ce370101f4d0213254238b4a2e8821b6e414a1cf20f6c1244b699046f5a01caa0a1a55516300b40d2048c77cc73afba",
+        "79afeea9d2c0118bdf2adb8870dc328c5516cc45b1a2058141039e2c90a110a9e16b318dfb53bd49a126d6b73f215787",
+        "517b8917cc01cabd107d06859854ee8b4f9861c226d3764c87339ab16c3667d2f49384e55456dd40414b70a6af841585",
+        "f4c90c68725d57704ee8ee7ce6e2f9be582dbee985e038ffc346ebfb4e22158b6c84374a9ab4a44e1f91de5aac5197f8",
+        "9bc5e5442f51f9a5937b102ba3beaebf6e1c58380a4a5fedce4a4e5026f88f528f59ffd2db41752b3a3d90efabe46389",
+        "9b7d40870c530c8841e8712b733668ed033adbfafb2d49d37a44d4064e5863eb0af0a08d47b3cc888373bc05f7a33b84",
+        "1bc2587c57eb69554e8a3767b7506917b6b70498727f16eac1a36ec8d8cfaf751549f2277db277e8a55a9a5106b23a02",
+        "06b4721fa9b3048552c5bd5b594d6e247f38c18c591aea7f56249c72ce7b117afcc3a8621582f9cf71787e183dee0936",
+        "7976e98409ad9217a497df888042384d7707a6b78f5f7fb8409e3b535175373461b776002d799cbad62860be70573ecb",
+        "e13b246e0da7e93a52168e0fb6a9756b895ef7f0147a0dc81bfa644b088a9228160c0f9acf1379a2941cd28c06ebc80e",
+        "44e17aa2f8177010afd78a97ce0868d1629ebb294c5151812c583daeb88685220f4da9118112e07041fcc24d5564a99f",
+        "dbde28869fe0722387d7a9a4d16e1cc8555917e09944aa5ebaaaec2cf62693afad42a3f518fce67d273cc6c9fb5472b3",
+        "80e8573ec7de06a3ba2fd5f931d725b493026cb0acbd3fe62d00e4c790d965d7a03a3c0b4222ba8c2a9a16e2ac658f57",
+        "2ae0e746eafc4feba023576f08942278a041fb82a70a595d5bacbf297ce2029898a71e5c3b0d1c6228b485b1ade509b3",
+        "5fbca7eca97b2132e7cb6bc465375146b7dceac969308ac0c2ac89e7863eb8943015b24314cafb9c7c0e85fe543d5665",
+        "8c213632599efabfc1ec49dd8c88547bb2cc40c9d38cbd3099b4547840560531d0188cd1e9c23a0ebee0a03d5577d66b",
+        "1d2bcb4baaf21cc7fef1e03806ca96299df0dfbc56e1b2b43e4fc20c37f834c4af62127e7dae86c3c25a2f696ac8b589",
+        "dec71d595bfbe94b5ed4bc07d800b330796fda89edb77be0294136139354eb8cd37591578f9c600dd9be8ec6219fdd50",
+        "7adf3397ed4d68707b8d13b24ce4cd8fb22851bfe9d632407f31ed6f7cb1600de56f17576740ce2a32fc5145030145cf",
+        "b97e63e0e41d354274a079d3e6fb2e15",
+    );
+
+    fn unhex(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    #[test]
+    fn xwing_matches_draft_vector() {
+        let sk: [u8; 32] = unhex(SEED).try_into().unwrap();
+        let eseed: [u8; 64] = unhex(ESEED).try_into().unwrap();
+        assert_eq!(xwing::public_key(&sk), unhex(PUBLIC_KEY));
+        let (shared_secret, ct) = xwing::encapsulate(&unhex(PUBLIC_KEY), &eseed).unwrap();
+        assert_eq!(ct, unhex(CIPHERTEXT));
+        assert_eq!(*shared_secret, unhex(SHARED_SECRET));
+        assert_eq!(*XWing.decapsulate(&sk, &ct).unwrap(), unhex(SHARED_SECRET));
+    }
+
+    #[test]
+    fn xwing_round_trips() {
+        let xwing = parse("x-wing").unwrap();
+        let (pk, sk) = xwing.keypair().unwrap();
+        let (shared_secret, ct) = xwing.encapsulate(&pk).unwrap();
+        assert_eq!(ct.len(), xwing.ciphertext_len());
+        assert_eq!(xwing.decapsulate(&sk, &ct).unwrap(), shared_secret);
+        assert!(matches!(xwing.decapsulate(&sk, &ct[1..]), Err(CoreError::Format(_))));
+    }
+}
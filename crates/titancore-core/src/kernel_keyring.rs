@@ -0,0 +1,141 @@
+//! Secrets held in the Linux kernel keyring.
+//!
+//! [`store`] adds a `user` key to the session keyring (dropped with the
+//! login session) or the user keyring (shared by the user's processes),
+//! optionally expiring after a timeout. The key's bytes live in kernel
+//! memory, outside the process and its swappable pages; read one back
+//! with [`KernelKey::read`] only for as long as it is needed, e.g. a
+//! wrapping key or a session key between requests. Keys are found again
+//! by name with [`find`].
+
+use crate::error::{CoreError, CoreResult};
+use std::ffi::CString;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+const KEY_SPEC_SESSION_KEYRING: i32 = -3;
+const KEY_SPEC_USER_KEYRING: i32 = -4;
+const KEYCTL_REVOKE: libc::c_long = 3;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+const KEYCTL_SET_TIMEOUT: libc::c_long = 15;
+const KEY_TYPE: &[u8] = b"user\0";
+
+/// Which keyring a key is linked into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyringScope {
+    /// The session keyring, gone when the session ends.
+    #[default]
+    Session,
+    /// The user keyring, shared by every process of the user.
+    User,
+}
+
+impl KeyringScope {
+    fn id(self) -> i32 {
+        match self {
+            KeyringScope::Session => KEY_SPEC_SESSION_KEYRING,
+            KeyringScope::User => KEY_SPEC_USER_KEYRING,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyringScope::Session => "session",
+            KeyringScope::User => "user",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<KeyringScope> {
+        match name {
+            "session" => Some(KeyringScope::Session),
+            "user" => Some(KeyringScope::User),
+            _ => None,
+        }
+    }
+}
+
+/// A key in the kernel keyring, by serial number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelKey {
+    pub serial: i32,
+}
+
+impl KernelKey {
+    /// The key's bytes; fails once it has expired or been revoked.
+    pub fn read(&self) -> CoreResult<Zeroizing<Vec<u8>>> {
+        let mut buf = Zeroizing::new(Vec::new());
+        loop {
+            // SAFETY: the kernel writes at most `buf.len()` bytes to `buf`.
+            let len = check(unsafe {
+                libc::syscall(libc::SYS_keyctl, KEYCTL_READ, self.serial, buf.as_mut_ptr(), buf.len())
+            })? as usize;
+            if len <= buf.len() {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            // Grown in a new buffer so no copy is left behind unzeroed.
+            buf = Zeroizing::new(vec![0u8; len]);
+        }
+    }
+
+    /// Expires the key `timeout` from now; `Duration::ZERO` clears the
+    /// timeout.
+    pub fn set_timeout(&self, timeout: Duration) -> CoreResult<()> {
+        let secs = u32::try_from(timeout.as_secs()).map_err(|_| CoreError::Config("keyring timeout too long".into()))?;
+        // SAFETY: no pointers are passed.
+        check(unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_SET_TIMEOUT, self.serial, secs) }).map(drop)
+    }
+
+    /// Revokes the key; later reads fail and the kernel frees it.
+    pub fn revoke(self) -> CoreResult<()> {
+        // SAFETY: no pointers are passed.
+        check(unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_REVOKE, self.serial) }).map(drop)
+    }
+}
+
+/// Adds `secret` as the key `name` in `scope`, replacing the contents of a
+/// key of that name already there. With a `timeout` the kernel drops the
+/// key after it.
+pub fn store(name: &str, secret: &[u8], scope: KeyringScope, timeout: Option<Duration>) -> CoreResult<KernelKey> {
+    let desc = description(name)?;
+    // SAFETY: the type and description are NUL-terminated and the payload
+    // is `secret.len()` readable bytes.
+    let serial = check(unsafe {
+        libc::syscall(libc::SYS_add_key, KEY_TYPE.as_ptr(), desc.as_ptr(), secret.as_ptr(), secret.len(), scope.id())
+    })?;
+    let key = KernelKey { serial: serial as i32 };
+    if let Some(timeout) = timeout {
+        key.set_timeout(timeout)?;
+    }
+    Ok(key)
+}
+
+/// The key `name` in `scope`, if there is one.
+pub fn find(name: &str, scope: KeyringScope) -> CoreResult<Option<KernelKey>> {
+    let desc = description(name)?;
+    // SAFETY: the type and description are NUL-terminated.
+    let res = unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_SEARCH, scope.id(), KEY_TYPE.as_ptr(), desc.as_ptr(), 0) };
+    if res < 0 && matches!(last_errno(), libc::ENOKEY | libc::EKEYEXPIRED | libc::EKEYREVOKED) {
+        return Ok(None);
+    }
+    Ok(Some(KernelKey { serial: check(res)? as i32 }))
+}
+
+fn description(name: &str) -> CoreResult<CString> {
+    if name.is_empty() {
+        return Err(CoreError::Config("keyring key name is empty".into()));
+    }
+    CString::new(name).map_err(|_| CoreError::Config("keyring key name contains NUL".into()))
+}
+
+fn check(res: libc::c_long) -> CoreResult<libc::c_long> {
+    if res < 0 {
+        return Err(CoreError::Storage(format!("keyring error: {}", std::io::Error::last_os_error())));
+    }
+    Ok(res)
+}
+
+fn last_errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
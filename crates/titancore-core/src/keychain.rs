@@ -0,0 +1,132 @@
+//! Secrets held in the macOS Keychain (feature `keychain`).
+//!
+//! [`store`] saves a secret as a generic password under a service and
+//! account name in the user's default keychain, where it is encrypted
+//! under the login credentials and released only to this application
+//! (macOS asks before another one reads it). Suited to a wrapping key the
+//! host would otherwise keep in a file; read it back with [`load`].
+//!
+//! Secure Enclave keys are P-256 only, and every operation here is
+//! Kyber-1024 end to end, so there is no classical half they could back.
+
+use crate::error::{CoreError, CoreResult};
+use std::ffi::c_void;
+use std::ptr;
+use zeroize::Zeroizing;
+
+type OSStatus = i32;
+type CFTypeRef = *const c_void;
+
+const ERR_SEC_SUCCESS: OSStatus = 0;
+const ERR_SEC_ITEM_NOT_FOUND: OSStatus = -25300;
+
+#[link(name = "Security", kind = "framework")]
+extern "C" {
+    fn SecKeychainAddGenericPassword(keychain: CFTypeRef, service_len: u32, service: *const u8, account_len: u32,
+                                     account: *const u8, password_len: u32, password: *const c_void,
+                                     item: *mut CFTypeRef) -> OSStatus;
+    fn SecKeychainFindGenericPassword(keychain: CFTypeRef, service_len: u32, service: *const u8, account_len: u32,
+                                      account: *const u8, password_len: *mut u32, password: *mut *mut c_void,
+                                      item: *mut CFTypeRef) -> OSStatus;
+    fn SecKeychainItemModifyAttributesAndData(item: CFTypeRef, attrs: *const c_void, length: u32, data: *const c_void) -> OSStatus;
+    fn SecKeychainItemFreeContent(attrs: *mut c_void, data: *mut c_void) -> OSStatus;
+    fn SecKeychainItemDelete(item: CFTypeRef) -> OSStatus;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: CFTypeRef);
+}
+
+/// Saves `secret` as the password of (`service`, `account`), replacing an
+/// existing one.
+pub fn store(service: &str, account: &str, secret: &[u8]) -> CoreResult<()> {
+    let (service_len, account_len, secret_len) = (len(service.as_bytes())?, len(account.as_bytes())?, len(secret)?);
+    match find(service, account)? {
+        Some(item) => {
+            // SAFETY: `item` is a live keychain item; `secret` is readable.
+            let status = unsafe { SecKeychainItemModifyAttributesAndData(item.0, ptr::null(), secret_len, secret.as_ptr().cast()) };
+            check(status)
+        }
+        None => {
+            // SAFETY: the lengths describe the passed slices; no item is
+            // returned.
+            let status = unsafe {
+                SecKeychainAddGenericPassword(ptr::null(), service_len, service.as_ptr(), account_len, account.as_ptr(),
+                                              secret_len, secret.as_ptr().cast(), ptr::null_mut())
+            };
+            check(status)
+        }
+    }
+}
+
+/// The password of (`service`, `account`), if there is one.
+pub fn load(service: &str, account: &str) -> CoreResult<Option<Zeroizing<Vec<u8>>>> {
+    let (service_len, account_len) = (len(service.as_bytes())?, len(account.as_bytes())?);
+    let mut data_len = 0u32;
+    let mut data: *mut c_void = ptr::null_mut();
+    // SAFETY: the lengths describe the passed slices; the returned data is
+    // released with `SecKeychainItemFreeContent` below.
+    let status = unsafe {
+        SecKeychainFindGenericPassword(ptr::null(), service_len, service.as_ptr(), account_len, account.as_ptr(),
+                                       &mut data_len, &mut data, ptr::null_mut())
+    };
+    if status == ERR_SEC_ITEM_NOT_FOUND {
+        return Ok(None);
+    }
+    check(status)?;
+    // SAFETY: on success `data` holds `data_len` bytes owned by the
+    // Security framework.
+    unsafe {
+        let bytes = std::slice::from_raw_parts_mut(data.cast::<u8>(), data_len as usize);
+        let secret = Zeroizing::new(bytes.to_vec());
+        zeroize::Zeroize::zeroize(bytes);
+        SecKeychainItemFreeContent(ptr::null_mut(), data);
+        Ok(Some(secret))
+    }
+}
+
+/// Removes (`service`, `account`); returns whether it existed.
+pub fn delete(service: &str, account: &str) -> CoreResult<bool> {
+    let Some(item) = find(service, account)? else { return Ok(false) };
+    // SAFETY: `item` is a live keychain item.
+    check(unsafe { SecKeychainItemDelete(item.0) })?;
+    Ok(true)
+}
+
+// An owned keychain item reference.
+struct Item(CFTypeRef);
+
+impl Drop for Item {
+    fn drop(&mut self) {
+        // SAFETY: the reference was returned to us with +1 retain count.
+        unsafe { CFRelease(self.0) }
+    }
+}
+
+fn find(service: &str, account: &str) -> CoreResult<Option<Item>> {
+    let (service_len, account_len) = (len(service.as_bytes())?, len(account.as_bytes())?);
+    let mut item: CFTypeRef = ptr::null();
+    // SAFETY: the lengths describe the passed slices; no password data is
+    // requested.
+    let status = unsafe {
+        SecKeychainFindGenericPassword(ptr::null(), service_len, service.as_ptr(), account_len, account.as_ptr(),
+                                       ptr::null_mut(), ptr::null_mut(), &mut item)
+    };
+    if status == ERR_SEC_ITEM_NOT_FOUND {
+        return Ok(None);
+    }
+    check(status)?;
+    Ok(Some(Item(item)))
+}
+
+fn len(bytes: &[u8]) -> CoreResult<u32> {
+    u32::try_from(bytes.len()).map_err(|_| CoreError::Config("keychain item too large".into()))
+}
+
+fn check(status: OSStatus) -> CoreResult<()> {
+    match status {
+        ERR_SEC_SUCCESS => Ok(()),
+        _ => Err(CoreError::Storage(format!("keychain error: OSStatus {}", status))),
+    }
+}
@@ -0,0 +1,248 @@
+//! Named, pinned recipient keys.
+//!
+//! A [`Keyring`] maps recipient names to public keys of any available KEM
+//! (see [`kem`](crate::kem)), each with its [`key_id`] as fingerprint and a
+//! [`TrustState`]. [`Engine::seal_named`] seals to a name instead of raw
+//! key bytes and refuses a name the keyring does not know, a key that
+//! differs from the one pinned for it, and a revoked entry, unless the
+//! caller explicitly overrides the first two. An override pins the
+//! presented key on trust-on-first-use terms.
+//!
+//! Keyrings are saved as JSON: `{"version": 1, "keys": [{"name",
+//! "public_key", "fingerprint", "trust", "added_ms"}]}`. A Classic McEliece
+//! key makes its entry about 2 MB of hex.
+
+use crate::audit::{FailureReason, OpType, Outcome};
+use crate::cert::key_id;
+use crate::engine::Engine;
+use crate::envelope::Envelope;
+use crate::error::{CoreError, CoreResult};
+use crate::kem;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+pub const KEYRING_VERSION: u64 = 1;
+
+/// How far a pinned key is trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrustState {
+    /// Pinned the first time it was seen, unchecked.
+    Tofu,
+    /// Checked out of band, e.g. by comparing fingerprints.
+    Verified,
+    /// No longer to be sealed to; kept so the name cannot be re-pinned.
+    Revoked,
+}
+
+impl TrustState {
+    const ALL: [TrustState; 3] = [TrustState::Tofu, TrustState::Verified, TrustState::Revoked];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrustState::Tofu => "tofu",
+            TrustState::Verified => "verified",
+            TrustState::Revoked => "revoked",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<TrustState> {
+        Self::ALL.into_iter().find(|t| t.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyringEntry {
+    pub name: String,
+    pub public_key: Vec<u8>,
+    /// [`key_id`] of the public key.
+    pub fingerprint: [u8; 32],
+    pub trust: TrustState,
+    /// When the key was pinned, in engine clock milliseconds.
+    pub added_ms: u64,
+}
+
+/// Recipient keys by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyring {
+    entries: BTreeMap<String, KeyringEntry>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pins `public_key` as `name`. Re-adding the same key updates its
+    /// trust unless it was revoked; a different key for a known name fails
+    /// with [`CoreError::Config`] (remove the entry first).
+    pub fn add(&mut self, name: &str, public_key: &[u8], trust: TrustState, now_ms: u64) -> CoreResult<&KeyringEntry> {
+        if name.is_empty() {
+            return Err(CoreError::Config("recipient name is empty".into()));
+        }
+        kem::check_public_key(public_key)?;
+        if let Some(entry) = self.entries.get(name) {
+            if entry.public_key != public_key {
+                return Err(changed(name, entry, public_key));
+            }
+            if entry.trust == TrustState::Revoked && trust != TrustState::Revoked {
+                return Err(CoreError::Revoked);
+            }
+        }
+        let added_ms = self.entries.get(name).map_or(now_ms, |e| e.added_ms);
+        let entry = KeyringEntry { name: name.to_string(), public_key: public_key.to_vec(), fingerprint: key_id(public_key), trust, added_ms };
+        self.entries.insert(name.to_string(), entry);
+        Ok(&self.entries[name])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&KeyringEntry> {
+        self.entries.get(name)
+    }
+
+    /// The entry holding the key with id `fingerprint`.
+    pub fn find(&self, fingerprint: &[u8; 32]) -> Option<&KeyringEntry> {
+        self.entries.values().find(|e| &e.fingerprint == fingerprint)
+    }
+
+    /// Entries in name order.
+    pub fn entries(&self) -> impl Iterator<Item = &KeyringEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Marks `name` verified or revoked. A revoked entry stays revoked.
+    pub fn set_trust(&mut self, name: &str, trust: TrustState) -> CoreResult<()> {
+        let entry = self.entries.get_mut(name).ok_or_else(|| unknown(name))?;
+        if entry.trust == TrustState::Revoked && trust != TrustState::Revoked {
+            return Err(CoreError::Revoked);
+        }
+        entry.trust = trust;
+        Ok(())
+    }
+
+    /// Drops `name`; true if it was there.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    /// The key to seal to for `name`, given the key the caller was handed
+    /// for it, if any. Returns the key and whether it was pinned just now.
+    /// Fails with [`CoreError::Revoked`] for a revoked entry, and with
+    /// [`CoreError::Config`] for an unknown name or a `presented` key that
+    /// differs from the pinned one; with `allow_override` those two pin
+    /// `presented` as [`TrustState::Tofu`] instead.
+    pub fn resolve(&mut self, name: &str, presented: Option<&[u8]>, allow_override: bool, now_ms: u64) -> CoreResult<(Vec<u8>, bool)> {
+        match (self.entries.get(name), presented) {
+            (Some(entry), _) if entry.trust == TrustState::Revoked => Err(CoreError::Revoked),
+            (Some(entry), None) => Ok((entry.public_key.clone(), false)),
+            (Some(entry), Some(pk)) if entry.public_key == pk => Ok((entry.public_key.clone(), false)),
+            (Some(entry), Some(pk)) if !allow_override => Err(changed(name, entry, pk)),
+            (None, Some(_)) if !allow_override => Err(unknown(name)),
+            (None, None) => Err(unknown(name)),
+            (_, Some(pk)) => {
+                self.entries.remove(name);
+                self.add(name, pk, TrustState::Tofu, now_ms)?;
+                Ok((pk.to_vec(), true))
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let keys: Vec<Value> = self.entries.values().map(|e| json!({
+            "name": e.name,
+            "public_key": hex::encode(&e.public_key),
+            "fingerprint": hex::encode(e.fingerprint),
+            "trust": e.trust.as_str(),
+            "added_ms": e.added_ms,
+        })).collect();
+        json!({ "version": KEYRING_VERSION, "keys": keys }).to_string()
+    }
+
+    /// Parses [`Keyring::to_json`] output, checking each fingerprint
+    /// against its key.
+    pub fn from_json(s: &str) -> CoreResult<Self> {
+        let bad = CoreError::Format("bad keyring");
+        let value: Value = serde_json::from_str(s).map_err(|_| bad.clone())?;
+        if value.get("version").and_then(Value::as_u64) != Some(KEYRING_VERSION) {
+            return Err(CoreError::Format("unsupported keyring version"));
+        }
+        let mut keyring = Keyring::new();
+        for k in value.get("keys").and_then(Value::as_array).ok_or(bad.clone())? {
+            let text = |name: &str| k.get(name).and_then(Value::as_str).ok_or(bad.clone());
+            let public_key = hex::decode(text("public_key")?).map_err(|_| bad.clone())?;
+            if text("fingerprint")? != hex::encode(key_id(&public_key)) {
+                return Err(CoreError::Format("keyring fingerprint does not match its key"));
+            }
+            let trust = TrustState::parse(text("trust")?).ok_or(bad.clone())?;
+            let added_ms = k.get("added_ms").and_then(Value::as_u64).ok_or(bad.clone())?;
+            let name = text("name")?;
+            if keyring.entries.contains_key(name) {
+                return Err(CoreError::Format("duplicate name in keyring"));
+            }
+            keyring.add(name, &public_key, trust, added_ms)?;
+        }
+        Ok(keyring)
+    }
+
+    /// Writes the keyring to `path`, replacing it atomically.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> CoreResult<()> {
+        use std::io::Write;
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(self.to_json().as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a keyring written by [`Keyring::save`]; a missing file is an
+    /// empty keyring.
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> CoreResult<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_json(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Keyring::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn unknown(name: &str) -> CoreError {
+    CoreError::Config(format!("no key pinned for recipient '{}'", name))
+}
+
+fn changed(name: &str, entry: &KeyringEntry, presented: &[u8]) -> CoreError {
+    CoreError::Config(format!("key for recipient '{}' changed: pinned {}, presented {}",
+                              name, hex::encode(entry.fingerprint), hex::encode(key_id(presented))))
+}
+
+impl Engine {
+    /// [`Engine::seal`] to the key `keyring` holds for `name`, as
+    /// [`Keyring::resolve`] decides. A refusal is recorded as a
+    /// `key-invalid` `encrypt` event bound to the refused key's id (or the
+    /// name); a key pinned by the override as a `rekey` event bound to its
+    /// id.
+    pub fn seal_named(&self, data: &[u8], keyring: &mut Keyring, name: &str, presented: Option<&[u8]>,
+                      allow_override: bool) -> CoreResult<(Envelope, String)> {
+        self.ensure_open()?;
+        let (pk, pinned) = match keyring.resolve(name, presented, allow_override, self.clock().now_ms()) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let subject = presented.or(keyring.get(name).map(|k| &k.public_key[..])).map_or_else(|| key_id(name.as_bytes()), key_id);
+                return self.refuse_key(OpType::Encrypt, Outcome::KeyInvalid, FailureReason::of(&e), &subject, e);
+            }
+        };
+        if pinned {
+            self.record_key_event(OpType::Rekey, Outcome::Success, &key_id(&pk), &key_id(&pk))?;
+        }
+        self.seal(data, &pk)
+    }
+}
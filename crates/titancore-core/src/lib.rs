@@ -0,0 +1,118 @@
+//! TitanCore sovereign engine: Kyber-1024 encapsulation, AES-256-GCM-SIV and a
+//! BLAKE3 hash-chained audit trail.
+//!
+//! This crate is the binding-agnostic core. The Python, WebAssembly and
+//! Kotlin/Swift front-ends live in `titancore-py`, `titancore-wasm` and
+//! `titancore-ffi`.
+
+pub mod activation;
+pub mod acvp;
+pub mod age;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod anchor;
+pub mod archive;
+pub mod armor;
+pub mod attest;
+pub mod audit;
+pub mod bench;
+mod cbor;
+pub mod ceremony;
+pub mod cert;
+pub mod channel;
+pub mod clock;
+pub mod constraint;
+pub mod cose;
+pub mod crypto;
+pub mod document;
+#[cfg(windows)]
+pub mod dpapi;
+pub mod engine;
+pub mod entropy;
+pub mod envelope;
+pub mod epoch;
+pub mod escrow;
+pub mod evidence;
+pub mod error;
+pub mod fido2;
+pub mod fips;
+pub mod guarded;
+pub mod idempotency;
+pub mod identity;
+pub mod idle;
+pub mod integrity;
+pub mod jose;
+pub mod kat;
+pub mod kdf;
+pub mod kem;
+#[cfg(target_os = "linux")]
+pub mod kernel_keyring;
+#[cfg(all(feature = "keychain", target_os = "macos"))]
+pub mod keychain;
+pub mod keyring;
+pub mod mnemonic;
+pub mod multipart;
+pub mod operation;
+pub mod proto;
+pub mod quorum;
+pub mod ratchet;
+pub mod ratelimit;
+pub mod rbac;
+pub mod recovery_kit;
+pub mod revocation;
+pub mod rewrap;
+pub mod rollback;
+pub mod schema;
+pub mod secret;
+pub mod segment;
+#[cfg(feature = "fs")]
+pub mod shred;
+pub mod state;
+pub mod stepup;
+pub mod stream;
+pub mod suite;
+#[cfg(feature = "fs")]
+pub mod tempfile;
+#[cfg(feature = "fs")]
+pub mod tree;
+mod time;
+pub mod usage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watchdog;
+
+pub use attest::{Attestation, SignedAttestation, TpmQuote};
+pub use audit::alarm::{Alarm, AlarmReason, SignedAlarm};
+pub use audit::checkpoint::{Checkpoint, SignedCheckpoint};
+pub use audit::genesis::{Genesis, SignedGenesis};
+pub use audit::snapshot::{LogVerification, LogVerifier, SignedSnapshot, Snapshot};
+pub use bench::{BenchReport, BenchResult};
+pub use cert::{Certificate, CertificateBody};
+pub use audit::{AuditEntry, AuditQuery, AuditRecord, AuditSink, BatchRoot, CiphertextBinding, FailureReason, InclusionProof, MemorySink, NullSink, OpType, Outcome, Recovery};
+#[cfg(not(target_arch = "wasm32"))]
+pub use audit::{BackgroundSink, QueueStats, SyslogSink, SyslogTarget};
+#[cfg(feature = "fs")]
+pub use audit::{FileSink, LogFormat, SyncPolicy};
+#[cfg(feature = "sqlite")]
+pub use audit::SqliteSink;
+pub use clock::{Clock, FixedClock, OffsetClock, SystemClock};
+pub use crypto::generate_keypair;
+pub use document::{DocumentPolicy, Protection};
+pub use engine::{Engine, EngineConfig, EngineInfo};
+pub use identity::{Identity, Rotation, SignedRotation};
+pub use segment::AuditSegment;
+#[cfg(feature = "fs")]
+pub use usage::FileUsageStore;
+pub use usage::{KeyUsage, MemoryUsageStore, UsageCap, UsageStore};
+pub use entropy::EntropyHealth;
+#[cfg(not(target_arch = "wasm32"))]
+pub use watchdog::{AlarmHandler, Watchdog, WatchdogStats};
+pub use state::EngineState;
+pub use envelope::Envelope;
+pub use evidence::{verify_evidence, verify_evidence_for, EvidenceBundle};
+pub use error::{CoreError, CoreResult, ErrorContext};
+pub use integrity::ProtectedMessage;
+pub use kdf::{Kdf, KdfParams};
+pub use kem::Kem;
+pub use keyring::{Keyring, KeyringEntry, TrustState};
+pub use recovery_kit::{KitKeyType, KitProtection, KitSheet, RecoveryKit};
+pub use schema::{FieldType, RecordSchema, SchemaField, SchemaRegistry};
+pub use suite::{Aead, Suite};
@@ -0,0 +1,150 @@
+//! Wordlist encoding of secret keys and escrow shares for paper backups.
+//!
+//! Data is cut into 32-byte chunks and each chunk written as a BIP39
+//! mnemonic over the English wordlist: 11 bits per word, with the first
+//! `len / 4` bits of the chunk's SHA-256 appended as a checksum. So a full
+//! chunk is a standard 24-word phrase, and data of 16 to 32 bytes is one
+//! phrase any BIP39 tool reads. Checksums per chunk mean a mistyped word is
+//! caught and located to its group of 24 when the backup is typed back in.
+//!
+//! [`encode`] takes data whose length is a multiple of four bytes, as
+//! Kyber and Dilithium secret keys are; [`EscrowShare::to_mnemonic`] pads a
+//! share to that first. [`decode`] accepts words in any case, separated by
+//! any whitespace, and words cut to their first four letters, which are
+//! unique in the list.
+
+use crate::error::{CoreError, CoreResult};
+use crate::escrow::EscrowShare;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
+
+/// The BIP39 English wordlist, one word per line.
+pub const WORDLIST: &str = include_str!("../wordlist/english.txt");
+
+/// Words in one full group: 32 bytes of data and eight checksum bits.
+pub const GROUP_WORDS: usize = 24;
+const GROUP_BYTES: usize = 32;
+
+fn words() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| WORDLIST.lines().collect())
+}
+
+/// The mnemonic for `data`, one group of up to 24 words per line. Fails
+/// with [`CoreError::Config`] unless `data` is a non-empty multiple of four
+/// bytes.
+pub fn encode(data: &[u8]) -> CoreResult<Zeroizing<String>> {
+    if data.is_empty() || !data.len().is_multiple_of(4) {
+        return Err(CoreError::Config("mnemonic data must be a non-empty multiple of 4 bytes".into()));
+    }
+    let list = words();
+    let mut out = Zeroizing::new(String::with_capacity(data.len() * 6));
+    for chunk in data.chunks(GROUP_BYTES) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        for (i, index) in chunk_indices(chunk).iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            out.push_str(list[*index as usize]);
+        }
+    }
+    Ok(out)
+}
+
+/// The data [`encode`] wrote `phrase` for. Fails with
+/// [`CoreError::Mnemonic`] naming the first unknown word or the group whose
+/// checksum does not match.
+pub fn decode(phrase: &str) -> CoreResult<Zeroizing<Vec<u8>>> {
+    let indices = Zeroizing::new(phrase.split_whitespace().enumerate()
+        .map(|(i, word)| lookup(word).ok_or_else(|| bad(format!("word {} is not in the wordlist", i + 1))))
+        .collect::<CoreResult<Vec<u16>>>()?);
+    if indices.is_empty() {
+        return Err(bad("no words".into()));
+    }
+    let last = indices.len() % GROUP_WORDS;
+    if !last.is_multiple_of(3) {
+        return Err(bad(format!("{} words: the last group must be a multiple of 3 words", indices.len())));
+    }
+    let mut out = Zeroizing::new(Vec::with_capacity(indices.len() * 4 / 3));
+    for (g, group) in indices.chunks(GROUP_WORDS).enumerate() {
+        if !chunk_from_indices(group, &mut out) {
+            let first = g * GROUP_WORDS + 1;
+            return Err(bad(format!("checksum mismatch in group {} (words {}-{})", g + 1, first, first + group.len() - 1)));
+        }
+    }
+    Ok(out)
+}
+
+fn bad(detail: String) -> CoreError {
+    CoreError::Mnemonic(detail)
+}
+
+// Index of `word`, or of the only word starting with it if it has at
+// least four letters.
+fn lookup(word: &str) -> Option<u16> {
+    let word = word.to_ascii_lowercase();
+    let list = words();
+    match list.binary_search(&word.as_str()) {
+        Ok(i) => Some(i as u16),
+        Err(i) if word.len() >= 4 && list.get(i).is_some_and(|w| w.starts_with(&word)) => Some(i as u16),
+        Err(_) => None,
+    }
+}
+
+// Bit `i` of `chunk` followed by its checksum.
+fn bit(chunk: &[u8], hash: &[u8], i: usize) -> u16 {
+    let bits = chunk.len() * 8;
+    let (src, i) = if i < bits { (chunk, i) } else { (hash, i - bits) };
+    u16::from(src[i / 8] >> (7 - i % 8) & 1)
+}
+
+fn chunk_indices(chunk: &[u8]) -> Zeroizing<Vec<u16>> {
+    let hash = Sha256::digest(chunk);
+    let count = chunk.len() * 3 / 4;
+    Zeroizing::new((0..count).map(|w| (0..11).fold(0u16, |acc, b| acc << 1 | bit(chunk, &hash, w * 11 + b))).collect())
+}
+
+// Appends the bytes `group` encodes to `out`; false if its checksum is
+// wrong.
+fn chunk_from_indices(group: &[u16], out: &mut Vec<u8>) -> bool {
+    let len = group.len() * 4 / 3;
+    let mut bits = Zeroizing::new(vec![0u8; (group.len() * 11).div_ceil(8)]);
+    for (w, index) in group.iter().enumerate() {
+        for b in 0..11 {
+            let i = w * 11 + b;
+            bits[i / 8] |= ((index >> (10 - b) & 1) as u8) << (7 - i % 8);
+        }
+    }
+    let (chunk, checksum) = bits.split_at(len);
+    let hash = Sha256::digest(chunk);
+    let cs_bits = len / 4;
+    let matches = (0..cs_bits).all(|i| (checksum[i / 8] >> (7 - i % 8) & 1) == (hash[i / 8] >> (7 - i % 8) & 1));
+    if matches {
+        out.extend_from_slice(chunk);
+    }
+    matches
+}
+
+impl EscrowShare {
+    /// [`EscrowShare::to_bytes`] as a mnemonic, padded to a multiple of
+    /// four bytes with 1 to 4 bytes each holding the pad length.
+    pub fn to_mnemonic(&self) -> CoreResult<Zeroizing<String>> {
+        let mut bytes = self.to_bytes();
+        let pad = 4 - bytes.len() % 4;
+        bytes.extend(std::iter::repeat_n(pad as u8, pad));
+        encode(&bytes)
+    }
+
+    /// Parses [`EscrowShare::to_mnemonic`] output.
+    pub fn from_mnemonic(phrase: &str) -> CoreResult<Self> {
+        let bytes = decode(phrase)?;
+        let pad = *bytes.last().expect("decode returns data") as usize;
+        if !(1..=4).contains(&pad) || pad > bytes.len() || bytes[bytes.len() - pad..].iter().any(|&b| b as usize != pad) {
+            return Err(CoreError::Format("bad escrow share padding"));
+        }
+        EscrowShare::from_bytes(&bytes[..bytes.len() - pad])
+    }
+}
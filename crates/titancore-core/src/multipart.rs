@@ -0,0 +1,337 @@
+//! Fixed-size encrypted parts for multipart uploads and resumable
+//! transfers.
+//!
+//! [`Engine::multipart_upload`] encapsulates once and returns a
+//! [`MultipartUpload`] whose parts can be sealed in any order and from any
+//! thread, e.g. one S3 upload part each. Every part but the last holds
+//! exactly `part_size` plaintext bytes, so its ciphertext is
+//! `part_size + 16` bytes; part `i` uses nonce `prefix || i` and AAD `i`.
+//! [`Engine::finish_multipart`] signs a [`PartManifest`] of the header,
+//! total size and the BLAKE3 hash of every part, which lets anyone holding
+//! the signer's key check parts as they arrive and the set for
+//! completeness. A [`MultipartOpener`] built from the header decrypts parts
+//! independently.
+//!
+//! Header: `magic(4) | version(1) | suite(1) | counter(8) | fingerprint(32) |
+//! kem_len(2) | kem_ct | ext | nonce_prefix(8) | part_size(4)`.
+
+use crate::audit::{OpType, Outcome};
+use crate::cert;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::guarded::Guarded;
+use crate::kdf::KdfParams;
+use crate::rbac::Permission;
+use crate::stream::{chunk_nonce, TAG_LEN};
+use crate::suite::Suite;
+use parking_lot::Mutex;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use std::collections::BTreeMap;
+
+pub const HEADER_MAGIC: &[u8; 4] = b"TCMH";
+pub const MANIFEST_MAGIC: &[u8; 4] = b"TCMM";
+pub const MULTIPART_VERSION: u8 = 1;
+pub const MAX_PART_SIZE: usize = 1 << 30;
+
+struct PartHeader {
+    counter: u64,
+    fingerprint: [u8; 32],
+    kem_ct: Vec<u8>,
+    kdf: KdfParams,
+    nonce_prefix: [u8; 8],
+    part_size: u32,
+}
+
+impl PartHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.kem_ct.len());
+        out.extend_from_slice(HEADER_MAGIC);
+        out.push(MULTIPART_VERSION);
+        out.push(Suite::GcmSivCounter.wire_id(self.kdf.algorithm));
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&(self.kem_ct.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.kem_ct);
+        out.extend_from_slice(&self.kdf.encode_ext());
+        out.extend_from_slice(&self.nonce_prefix);
+        out.extend_from_slice(&self.part_size.to_be_bytes());
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != HEADER_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != MULTIPART_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let algorithm = match Suite::from_wire_id(r.take(1)?[0])? {
+            (Suite::GcmSivCounter, kdf) => kdf,
+            _ => return Err(CoreError::Format("unsupported multipart suite")),
+        };
+        let counter = u64::from_be_bytes(r.array()?);
+        let fingerprint = r.array()?;
+        let kem_len = u16::from_be_bytes(r.array()?) as usize;
+        crypto::check_kem_len(kem_len)?;
+        let kem_ct = r.take(kem_len)?.to_vec();
+        let ext_len = u16::from_be_bytes(r.array()?) as usize;
+        let kdf = KdfParams::decode_ext(algorithm, r.take(ext_len)?)?;
+        let nonce_prefix = r.array()?;
+        let part_size = u32::from_be_bytes(r.array()?);
+        if part_size == 0 || part_size as usize > MAX_PART_SIZE {
+            return Err(CoreError::Format("bad part size"));
+        }
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        Ok(PartHeader { counter, fingerprint, kem_ct, kdf, nonce_prefix, part_size })
+    }
+}
+
+/// An upload in progress. Parts may be sealed concurrently.
+pub struct MultipartUpload {
+    header: PartHeader,
+    header_bytes: Vec<u8>,
+    key: Guarded<[u8; 32]>,
+    key_id: [u8; 32],
+    // index -> (plaintext size, part hash)
+    parts: Mutex<BTreeMap<u32, (u64, [u8; 32])>>,
+}
+
+impl MultipartUpload {
+    /// Header bytes a [`MultipartOpener`] needs; also carried in the
+    /// manifest.
+    pub fn header(&self) -> &[u8] {
+        &self.header_bytes
+    }
+
+    pub fn part_size(&self) -> usize {
+        self.header.part_size as usize
+    }
+
+    /// Seals part `index` (from 0). `data` must be exactly
+    /// [`MultipartUpload::part_size`] bytes unless this is the last part.
+    /// Sealing an index again (a retried upload) replaces the earlier
+    /// part; AES-GCM-SIV keeps the repeated nonce from leaking more than
+    /// whether the data changed.
+    pub fn seal_part(&self, index: u32, data: &[u8]) -> CoreResult<Vec<u8>> {
+        if data.len() > self.part_size() {
+            return Err(CoreError::Config(format!("part exceeds the part size of {} bytes", self.part_size())));
+        }
+        let ct = crypto::aead_seal_aad(&self.key, &chunk_nonce(&self.header.nonce_prefix, index), data, &index.to_be_bytes())?;
+        self.parts.lock().insert(index, (data.len() as u64, blake3::hash(&ct).into()));
+        Ok(ct)
+    }
+}
+
+/// What [`Engine::finish_multipart`] signs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartManifest {
+    pub header: Vec<u8>,
+    pub total_size: u64,
+    /// BLAKE3 of each part's ciphertext, by index.
+    pub parts: Vec<[u8; 32]>,
+}
+
+impl PartManifest {
+    /// `magic(4) | version(1) | header_len(4) | header | total_size(8) | count(4) | hashes(32 each)`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(21 + self.header.len() + 32 * self.parts.len());
+        out.extend_from_slice(MANIFEST_MAGIC);
+        out.push(MULTIPART_VERSION);
+        out.extend_from_slice(&(self.header.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.header);
+        out.extend_from_slice(&self.total_size.to_be_bytes());
+        out.extend_from_slice(&(self.parts.len() as u32).to_be_bytes());
+        for hash in &self.parts {
+            out.extend_from_slice(hash);
+        }
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != MANIFEST_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != MULTIPART_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let header_len = u32::from_be_bytes(r.array()?) as usize;
+        let header = r.take(header_len)?.to_vec();
+        let part_size = PartHeader::from_bytes(&header)?.part_size as u64;
+        let total_size = u64::from_be_bytes(r.array()?);
+        let count = u32::from_be_bytes(r.array()?) as usize;
+        if r.buf.len() < count * 32 {
+            return Err(CoreError::Format("truncated"));
+        }
+        // All parts but the last are full.
+        let full = (count as u64).saturating_sub(1) * part_size;
+        if count == 0 || total_size < full || total_size - full > part_size {
+            return Err(CoreError::Format("bad multipart size"));
+        }
+        let parts = (0..count).map(|_| r.array()).collect::<CoreResult<Vec<_>>>()?;
+        Ok(PartManifest { header, total_size, parts })
+    }
+
+    /// Ciphertext size of part `index`, or `None` past the last part.
+    pub fn part_len(&self, index: u32) -> Option<u64> {
+        let part_size = PartHeader::from_bytes(&self.header).ok()?.part_size as u64;
+        let count = self.parts.len() as u64;
+        match index as u64 {
+            i if i + 1 < count => Some(part_size + TAG_LEN as u64),
+            i if i + 1 == count => Some(self.total_size - part_size * i + TAG_LEN as u64),
+            _ => None,
+        }
+    }
+
+    /// True if `part` is the part recorded at `index`. Needs no key.
+    pub fn verify_part(&self, index: u32, part: &[u8]) -> bool {
+        self.parts.get(index as usize).is_some_and(|hash| *hash == <[u8; 32]>::from(blake3::hash(part)))
+    }
+}
+
+/// A [`PartManifest`] with a Dilithium5 signature and the signer's public
+/// key; verify against a key you trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedPartManifest {
+    pub manifest: PartManifest,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedPartManifest {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.manifest.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let manifest = PartManifest::read(&mut r)?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("manifest without signature"));
+        }
+        Ok(SignedPartManifest { manifest, public_key, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.manifest.to_bytes(), &self.signature)
+    }
+}
+
+/// Decrypts the parts of one upload, in any order.
+pub struct MultipartOpener {
+    header: PartHeader,
+    key: Guarded<[u8; 32]>,
+}
+
+impl MultipartOpener {
+    pub fn part_size(&self) -> usize {
+        self.header.part_size as usize
+    }
+
+    pub fn open_part(&self, index: u32, part: &[u8]) -> CoreResult<Vec<u8>> {
+        if part.len() > self.part_size() + TAG_LEN {
+            return Err(CoreError::Format("bad part length"));
+        }
+        crypto::aead_open_aad(&self.key, &chunk_nonce(&self.header.nonce_prefix, index), part, &index.to_be_bytes())
+    }
+}
+
+impl Engine {
+    /// Starts an upload to `pk_bytes` in parts of `part_size` bytes. A
+    /// non-empty `context` is mixed into the key as in
+    /// [`Engine::seal_with_context`]. The upload counts as one request
+    /// against the rate limit.
+    pub fn multipart_upload(&self, pk_bytes: &[u8], part_size: usize, context: &[u8]) -> CoreResult<MultipartUpload> {
+        let res = self.try_multipart_upload(pk_bytes, part_size, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_multipart_upload(&self, pk_bytes: &[u8], part_size: usize, context: &[u8]) -> CoreResult<MultipartUpload> {
+        if part_size == 0 || part_size > MAX_PART_SIZE {
+            return Err(CoreError::Config(format!("part size must be 1..={}", MAX_PART_SIZE)));
+        }
+        self.check_rate_limit()?;
+        let pk = self.recipient_key(pk_bytes, 0)?;
+        let counter = self.next_counters(1)?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let kdf = self.kdf.for_message(shared_secret.as_bytes(), kem_ct.as_bytes());
+        let key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, counter, &kdf, context)?;
+        let mut nonce_prefix = [0u8; 8];
+        entropy::fill_hedged(&mut nonce_prefix, key.as_ref(), &counter.to_be_bytes());
+        let header = PartHeader {
+            counter,
+            fingerprint: self.fingerprint,
+            kem_ct: kem_ct.as_bytes().to_vec(),
+            kdf,
+            nonce_prefix,
+            part_size: part_size as u32,
+        };
+        Ok(MultipartUpload { header_bytes: header.to_bytes(), header, key: Guarded::new(&key), key_id: cert::key_id(pk_bytes), parts: Mutex::new(BTreeMap::new()) })
+    }
+
+    /// Signs the manifest of `upload` with the checkpoint key and records
+    /// the upload in the audit chain, bound to a digest of the part
+    /// hashes. Fails unless parts `0..n` were all sealed and all but the
+    /// last are full.
+    pub fn finish_multipart(&self, upload: MultipartUpload) -> CoreResult<SignedPartManifest> {
+        let res = self.try_finish_multipart(&upload);
+        self.audited(OpType::Encrypt, &upload.header.kem_ct, res)
+    }
+
+    fn try_finish_multipart(&self, upload: &MultipartUpload) -> CoreResult<SignedPartManifest> {
+        let parts = upload.parts.lock();
+        let count = parts.len();
+        let mut total_size = 0u64;
+        let mut hashes = Vec::with_capacity(count);
+        for (i, (&index, &(size, hash))) in parts.iter().enumerate() {
+            if index as usize != i {
+                return Err(CoreError::Config(format!("part {} was never sealed", i)));
+            }
+            if i + 1 < count && size != upload.part_size() as u64 {
+                return Err(CoreError::Config(format!("part {} is short but not last", i)));
+            }
+            total_size += size;
+            hashes.push(hash);
+        }
+        if count == 0 {
+            return Err(CoreError::Config("no parts sealed".into()));
+        }
+        let manifest = PartManifest { header: upload.header_bytes.clone(), total_size, parts: hashes };
+        let digest: [u8; 32] = blake3::hash(&manifest.to_bytes()).into();
+        let signature = crypto::sign(&self.signing_key.1.unwrapped(), &manifest.to_bytes())?;
+        self.append_to_audit(upload.header.counter, &upload.key_id, &upload.header.nonce_prefix, &digest, &upload.header.kem_ct)?;
+        Ok(SignedPartManifest { manifest, public_key: self.signing_key.0.clone(), signature })
+    }
+
+    /// Opener for the parts of the upload with `header` (from
+    /// [`MultipartUpload::header`] or a manifest). Records a `decrypt`
+    /// event bound to the upload's KEM ciphertext.
+    pub fn multipart_opener(&self, header: &[u8], sk_bytes: &[u8], context: &[u8]) -> CoreResult<MultipartOpener> {
+        let header = match self.permit(Permission::Decrypt).and_then(|_| PartHeader::from_bytes(header)) {
+            Ok(header) => header,
+            Err(e) => return self.audited(OpType::Decrypt, &[], Err(e)),
+        };
+        let res = crypto::parse_secret_key(sk_bytes).and_then(|sk| {
+            let ct = kyber1024::Ciphertext::from_bytes(&header.kem_ct).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+            let shared_secret = crypto::decapsulate(&ct, &sk);
+            crypto::derive_session_key(shared_secret.as_bytes(), &header.fingerprint, header.counter, &header.kdf, context)
+        });
+        let key = self.audited(OpType::Decrypt, &header.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &header.kem_ct)?;
+        Ok(MultipartOpener { header, key: Guarded::new(&key) })
+    }
+}
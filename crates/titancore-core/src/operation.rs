@@ -0,0 +1,143 @@
+//! Operation and correlation IDs, for joining application logs to the
+//! audit chain.
+//!
+//! Every audit entry carries the ID of the operation that wrote it
+//! ([`AuditEntry::operation_id`]): 16 random bytes. Inside a scope from
+//! [`Engine::begin_operation`], every entry the thread writes, including
+//! those written by the engine's worker pool on its behalf, shares the
+//! scope's ID and the caller's correlation ID, if one was given. Calls that
+//! write one entry per item (batches, directory rewraps) open a scope of
+//! their own when none is active. Outside a scope each entry gets a fresh
+//! ID. [`last_id`] returns the ID of the last entry written for this
+//! thread, so it can be logged beside the result.
+//!
+//! Like the caller role, both IDs are recorded beside the link, not hashed
+//! into it: they are not authenticated, and whoever can write the log can
+//! change them without breaking the chain. Keeping them out of the hash
+//! lets an envelope's evidence be checked from the envelope alone.
+//!
+//! [`AuditEntry::operation_id`]: crate::AuditEntry::operation_id
+
+use crate::engine::Engine;
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+
+/// Longest correlation ID; IDs are letters, digits and `-_.:/@+`.
+pub const MAX_CORRELATION_LEN: usize = 128;
+
+pub(crate) fn valid_correlation(id: &str) -> bool {
+    (1..=MAX_CORRELATION_LEN).contains(&id.len())
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':' | b'/' | b'@' | b'+'))
+}
+
+/// The operation this thread's entries belong to.
+#[derive(Clone)]
+pub(crate) struct Operation {
+    id: [u8; 16],
+    correlation: Option<String>,
+}
+
+impl Operation {
+    fn fresh(correlation: Option<String>) -> Operation {
+        let mut id = [0u8; 16];
+        entropy::fill_hedged(&mut id, &[], b"titancore operation id");
+        Operation { id, correlation }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Operation>> = const { RefCell::new(None) };
+    static LAST_ID: Cell<Option<[u8; 16]>> = const { Cell::new(None) };
+}
+
+pub(crate) fn current() -> Option<Operation> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// The operation and correlation IDs for an entry about to be written: the
+/// current scope's, or a fresh operation's outside one.
+pub(crate) fn for_entry() -> ([u8; 16], Option<String>) {
+    let op = current().unwrap_or_else(|| Operation::fresh(None));
+    LAST_ID.with(|last| last.set(Some(op.id)));
+    (op.id, op.correlation)
+}
+
+/// The operation ID of the last audit entry written for this thread.
+pub fn last_id() -> Option<[u8; 16]> {
+    LAST_ID.with(Cell::get)
+}
+
+pub(crate) fn set_last_id(id: Option<[u8; 16]>) {
+    if id.is_some() {
+        LAST_ID.with(|last| last.set(id));
+    }
+}
+
+/// Runs `f` as part of `op` on a worker thread, returning what it returned
+/// and the ID of the last entry it wrote, if any.
+#[cfg(feature = "parallel")]
+pub(crate) fn scoped<R>(op: Option<Operation>, f: impl FnOnce() -> R) -> (R, Option<[u8; 16]>) {
+    let _scope = OperationScope::set(op);
+    LAST_ID.with(|last| last.set(None));
+    let out = f();
+    (out, last_id())
+}
+
+/// Opens a scope with a fresh ID for a call that writes several entries,
+/// unless one is already active.
+pub(crate) fn implicit() -> Option<OperationScope> {
+    if current().is_some() {
+        return None;
+    }
+    let op = Operation::fresh(None);
+    LAST_ID.with(|last| last.set(Some(op.id)));
+    Some(OperationScope::set(Some(op)))
+}
+
+/// Groups the entries this thread writes under one operation until dropped;
+/// see [`Engine::begin_operation`]. Tied to the thread that made it. Scopes
+/// nest: dropping one restores the operation before it.
+pub struct OperationScope {
+    prev: Option<Operation>,
+    id: [u8; 16],
+    correlation: Option<String>,
+    _thread: PhantomData<*const ()>,
+}
+
+impl OperationScope {
+    fn set(op: Option<Operation>) -> Self {
+        let (id, correlation) = op.as_ref().map_or(([0u8; 16], None), |op| (op.id, op.correlation.clone()));
+        let prev = CURRENT.with(|c| c.replace(op));
+        OperationScope { prev, id, correlation, _thread: PhantomData }
+    }
+
+    pub fn id(&self) -> [u8; 16] {
+        self.id
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation.as_deref()
+    }
+}
+
+impl Drop for OperationScope {
+    fn drop(&mut self) {
+        let prev = self.prev.take();
+        CURRENT.with(|c| *c.borrow_mut() = prev);
+    }
+}
+
+impl Engine {
+    /// Starts an operation with a fresh ID on this thread, tagged with the
+    /// caller's `correlation` ID, until the scope is dropped. A correlation
+    /// ID that is empty, longer than [`MAX_CORRELATION_LEN`] or has other
+    /// characters than those allowed fails with [`CoreError::Config`].
+    pub fn begin_operation(&self, correlation: Option<&str>) -> CoreResult<OperationScope> {
+        if let Some(id) = correlation.filter(|id| !valid_correlation(id)) {
+            return Err(CoreError::Config(format!("bad correlation id {:?}", id)));
+        }
+        Ok(OperationScope::set(Some(Operation::fresh(correlation.map(str::to_string)))))
+    }
+}
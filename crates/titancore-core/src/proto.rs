@@ -0,0 +1,309 @@
+//! Protobuf encodings of [`Envelope`] and [`AuditEntry`], matching
+//! [`PROTO_SCHEMA`] (`proto/titancore.proto`), for consumers that use
+//! generated code. They are conversions only: engines still seal, hash and
+//! log the native formats, so a message converted back compares equal.
+//!
+//! Decoding follows proto3 rules: fields may come in any order, the last
+//! occurrence wins and unknown fields are skipped.
+
+use crate::audit::{self, AuditEntry, FailureReason, OpType, Outcome};
+use crate::envelope::{self, Envelope, Reader};
+use crate::constraint::SignedConstraints;
+use crate::epoch::EpochId;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams, DEFAULT_KDF_INFO};
+use crate::kem::KYBER1024;
+use crate::suite::Suite;
+
+/// The `.proto` definition, for publishing alongside a release.
+pub const PROTO_SCHEMA: &str = include_str!("../proto/titancore.proto");
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_I64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_I32: u8 = 5;
+
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn put_key(out: &mut Vec<u8>, field: u32, wire: u8) {
+    put_varint(out, (field as u64) << 3 | wire as u64);
+}
+
+// Scalars at their default are omitted, as proto3 encoders do.
+fn put_uint(out: &mut Vec<u8>, field: u32, n: u64) {
+    if n != 0 {
+        put_key(out, field, WIRE_VARINT);
+        put_varint(out, n);
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        put_present_bytes(out, field, bytes);
+    }
+}
+
+// For `optional` fields, whose presence is meaningful even when empty.
+fn put_present_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_key(out, field, WIRE_LEN);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+fn read_varint(r: &mut Reader) -> CoreResult<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = r.take(1)?[0];
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(CoreError::Format("bad protobuf varint"))
+}
+
+/// Calls `f(field, value)` for each field of a message.
+fn for_each_field<'a>(bytes: &'a [u8], mut f: impl FnMut(u32, Field<'a>) -> CoreResult<()>) -> CoreResult<()> {
+    let mut r = Reader { buf: bytes };
+    while !r.buf.is_empty() {
+        let key = read_varint(&mut r)?;
+        let field = u32::try_from(key >> 3).ok().filter(|&n| n != 0).ok_or(CoreError::Format("bad protobuf field"))?;
+        let value = match (key & 7) as u8 {
+            WIRE_VARINT => Field::Varint(read_varint(&mut r)?),
+            WIRE_LEN => {
+                let len = usize::try_from(read_varint(&mut r)?).map_err(|_| CoreError::Format("truncated"))?;
+                Field::Bytes(r.take(len)?)
+            }
+            WIRE_I64 => {
+                r.take(8)?;
+                Field::Fixed
+            }
+            WIRE_I32 => {
+                r.take(4)?;
+                Field::Fixed
+            }
+            _ => return Err(CoreError::Format("unsupported protobuf wire type")),
+        };
+        f(field, value)?;
+    }
+    Ok(())
+}
+
+fn varint(value: Field<'_>) -> CoreResult<u64> {
+    match value {
+        Field::Varint(n) => Ok(n),
+        _ => Err(CoreError::Format("bad protobuf field type")),
+    }
+}
+
+fn len_field(value: Field<'_>) -> CoreResult<&[u8]> {
+    match value {
+        Field::Bytes(b) => Ok(b),
+        _ => Err(CoreError::Format("bad protobuf field type")),
+    }
+}
+
+fn hash(value: &[u8]) -> CoreResult<[u8; 32]> {
+    value.try_into().map_err(|_| CoreError::Format("bad protobuf hash length"))
+}
+
+fn encode_kdf(kdf: &KdfParams) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_uint(&mut out, 1, kdf.algorithm.code() as u64);
+    put_bytes(&mut out, 2, &kdf.salt);
+    if kdf.info != DEFAULT_KDF_INFO {
+        put_present_bytes(&mut out, 3, &kdf.info);
+    }
+    if let Some(salt) = &kdf.message_salt {
+        put_bytes(&mut out, 4, salt);
+    }
+    put_uint(&mut out, 5, u64::from(kdf.restricted));
+    if let Some(schema) = &kdf.schema {
+        put_bytes(&mut out, 6, schema);
+    }
+    if let Some(epoch) = &kdf.epoch {
+        put_bytes(&mut out, 7, &epoch.to_bytes());
+    }
+    if let Some(constraints) = &kdf.constraints {
+        put_bytes(&mut out, 8, &constraints.to_bytes());
+    }
+    out
+}
+
+fn decode_kdf(body: &[u8]) -> CoreResult<KdfParams> {
+    let mut kdf = KdfParams::default();
+    for_each_field(body, |field, value| {
+        match field {
+            1 => {
+                let code = u8::try_from(varint(value)?).ok().and_then(Kdf::from_code);
+                kdf.algorithm = code.ok_or(CoreError::Format("unknown KDF"))?;
+            }
+            2 => kdf.salt = len_field(value)?.to_vec(),
+            3 => kdf.info = len_field(value)?.to_vec(),
+            4 => {
+                let salt = len_field(value)?;
+                kdf.message_salt = match salt.len() {
+                    0 => None,
+                    _ => Some(salt.try_into().map_err(|_| CoreError::Format("bad message salt"))?),
+                };
+            }
+            5 => kdf.restricted = varint(value)? != 0,
+            6 => kdf.schema = Some(len_field(value)?.try_into().map_err(|_| CoreError::Format("bad schema id"))?),
+            7 => kdf.epoch = Some(EpochId::from_bytes(len_field(value)?)?),
+            8 => kdf.constraints = Some(SignedConstraints::from_bytes(len_field(value)?)?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(kdf)
+}
+
+impl Envelope {
+    /// `titancore.v1.Envelope` message.
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.kem_ct.len() + self.nonce.len() + self.ciphertext.len());
+        put_uint(&mut out, 1, self.suite.id() as u64);
+        let kdf = encode_kdf(&self.kdf);
+        if !kdf.is_empty() {
+            put_present_bytes(&mut out, 2, &kdf);
+        }
+        put_uint(&mut out, 3, self.counter);
+        put_bytes(&mut out, 4, &self.fingerprint);
+        put_bytes(&mut out, 5, &self.kem_ct);
+        put_bytes(&mut out, 6, &self.nonce);
+        put_bytes(&mut out, 7, &self.ciphertext);
+        if let Some(escrow) = &self.escrow {
+            put_bytes(&mut out, 8, escrow);
+        }
+        if self.kem != KYBER1024 {
+            put_uint(&mut out, 9, self.kem as u64);
+        }
+        out
+    }
+
+    pub fn from_protobuf(bytes: &[u8]) -> CoreResult<Self> {
+        let (mut suite, mut kdf, mut counter, mut fingerprint) = (None, KdfParams::default(), 0, None);
+        let (mut kem_ct, mut nonce, mut ciphertext, mut escrow) = (Vec::new(), Vec::new(), Vec::new(), None);
+        let mut kem = KYBER1024;
+        for_each_field(bytes, |field, value| {
+            match field {
+                1 => suite = Some(u8::try_from(varint(value)?).ok().and_then(Suite::from_id).ok_or(CoreError::Format("unknown suite"))?),
+                2 => kdf = decode_kdf(len_field(value)?)?,
+                3 => counter = varint(value)?,
+                4 => fingerprint = Some(hash(len_field(value)?)?),
+                5 => kem_ct = len_field(value)?.to_vec(),
+                6 => nonce = len_field(value)?.to_vec(),
+                7 => ciphertext = len_field(value)?.to_vec(),
+                8 => escrow = Some(len_field(value)?.to_vec()).filter(|e| !e.is_empty()),
+                9 => kem = u8::try_from(varint(value)?).ok().filter(|&k| k != 0).ok_or(CoreError::Format("unknown KEM"))?,
+                _ => {}
+            }
+            Ok(())
+        })?;
+        let suite = suite.ok_or(CoreError::Format("envelope without suite"))?;
+        envelope::check_kem_len(kem, kem_ct.len())?;
+        if nonce.len() != suite.nonce_len() {
+            return Err(CoreError::Format("bad nonce length"));
+        }
+        let fingerprint = fingerprint.ok_or(CoreError::Format("envelope without fingerprint"))?;
+        Ok(Envelope { suite, kdf, kem, counter, fingerprint, kem_ct, nonce, ciphertext, escrow })
+    }
+}
+
+impl AuditEntry {
+    /// `titancore.v1.AuditEntry` message.
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(96);
+        put_bytes(&mut out, 1, &self.prev);
+        put_bytes(&mut out, 2, &self.curr);
+        put_uint(&mut out, 3, self.counter);
+        put_uint(&mut out, 4, self.timestamp_ms);
+        put_uint(&mut out, 5, self.op.code() as u64);
+        put_uint(&mut out, 6, self.outcome.code() as u64);
+        put_uint(&mut out, 7, self.seq);
+        put_uint(&mut out, 8, u64::from(self.clock_regressed));
+        put_uint(&mut out, 9, u64::from(self.fips));
+        if let Some(role) = &self.role {
+            put_bytes(&mut out, 10, role.as_bytes());
+        }
+        if let Some(reason) = self.reason {
+            put_uint(&mut out, 11, reason.code() as u64);
+        }
+        if let Some(id) = &self.operation_id {
+            put_bytes(&mut out, 12, id);
+        }
+        if let Some(id) = &self.correlation_id {
+            put_bytes(&mut out, 13, id.as_bytes());
+        }
+        if let Some(id) = &self.key_id {
+            put_bytes(&mut out, 14, id);
+        }
+        out
+    }
+
+    pub fn from_protobuf(bytes: &[u8]) -> CoreResult<Self> {
+        // Hashes are always 32 bytes, so a missing one is an error rather
+        // than the proto3 empty default.
+        let (mut prev, mut curr) = (None, None);
+        let mut entry = AuditEntry {
+            prev: [0; 32],
+            curr: [0; 32],
+            counter: 0,
+            timestamp_ms: 0,
+            op: OpType::default(),
+            outcome: Outcome::default(),
+            seq: 0,
+            clock_regressed: false,
+            fips: false,
+            role: None,
+            reason: None,
+            operation_id: None,
+            correlation_id: None,
+            key_id: None,
+        };
+        for_each_field(bytes, |field, value| {
+            match field {
+                1 => prev = Some(hash(len_field(value)?)?),
+                2 => curr = Some(hash(len_field(value)?)?),
+                3 => entry.counter = varint(value)?,
+                4 => entry.timestamp_ms = varint(value)?,
+                5 => entry.op = u8::try_from(varint(value)?).ok().and_then(OpType::from_code).ok_or(CoreError::Format("unknown op"))?,
+                6 => entry.outcome = u8::try_from(varint(value)?).ok().and_then(Outcome::from_code).ok_or(CoreError::Format("unknown outcome"))?,
+                7 => entry.seq = varint(value)?,
+                8 => entry.clock_regressed = varint(value)? != 0,
+                9 => entry.fips = varint(value)? != 0,
+                10 => {
+                    let role = std::str::from_utf8(len_field(value)?).ok().and_then(audit::parse_role);
+                    entry.role = Some(role.ok_or(CoreError::Format("bad role"))?);
+                }
+                11 => {
+                    let reason = u8::try_from(varint(value)?).ok().and_then(FailureReason::from_code);
+                    entry.reason = Some(reason.ok_or(CoreError::Format("unknown failure reason"))?);
+                }
+                12 => entry.operation_id = Some(len_field(value)?.try_into().map_err(|_| CoreError::Format("bad operation id"))?),
+                13 => {
+                    let id = std::str::from_utf8(len_field(value)?).ok().and_then(audit::parse_correlation);
+                    entry.correlation_id = Some(id.ok_or(CoreError::Format("bad correlation id"))?);
+                }
+                14 => entry.key_id = Some(hash(len_field(value)?)?),
+                _ => {}
+            }
+            Ok(())
+        })?;
+        entry.prev = prev.ok_or(CoreError::Format("audit entry without prev"))?;
+        entry.curr = curr.ok_or(CoreError::Format("audit entry without curr"))?;
+        Ok(entry)
+    }
+}
@@ -0,0 +1,260 @@
+//! M-of-N approval for sensitive decryptions.
+//!
+//! [`Engine::seal_restricted`] marks an envelope `restricted`; the mark is
+//! part of the key derivation, and [`Envelope::open`] refuses such
+//! envelopes. They open only through [`Engine::open_restricted`], which
+//! needs a [`DecryptionRequest`] naming the envelope and [`Approval`]s over
+//! it from at least `threshold` of the policy's Dilithium5 approver keys.
+//! The quorum is recorded as an `approval` audit event before the key is
+//! unwrapped.
+
+use crate::audit::{OpType, Outcome};
+use crate::cert::key_id;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::{Envelope, Reader};
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Marks;
+use crate::rbac::Permission;
+use crate::stepup::SensitiveOp;
+
+pub const REQUEST_MAGIC: &[u8; 4] = b"TCQR";
+pub const APPROVAL_MAGIC: &[u8; 4] = b"TCQA";
+pub const QUORUM_VERSION: u8 = 1;
+
+/// What approvers sign: one envelope, who wants it opened and why, and
+/// until when (Unix seconds) the approval holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptionRequest {
+    /// BLAKE3 of the native envelope bytes.
+    pub envelope: [u8; 32],
+    pub requester: String,
+    pub reason: String,
+    pub expires_at: u64,
+}
+
+impl DecryptionRequest {
+    pub fn new(envelope: &Envelope, requester: &str, reason: &str, expires_at: u64) -> Self {
+        DecryptionRequest {
+            envelope: blake3::hash(&envelope.to_bytes()).into(),
+            requester: requester.to_string(),
+            reason: reason.to_string(),
+            expires_at,
+        }
+    }
+
+    /// `magic(4) | version(1) | envelope(32) | expires_at(8) | requester_len(2) | requester |
+    ///  reason_len(2) | reason`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(49 + self.requester.len() + self.reason.len());
+        out.extend_from_slice(REQUEST_MAGIC);
+        out.push(QUORUM_VERSION);
+        out.extend_from_slice(&self.envelope);
+        out.extend_from_slice(&self.expires_at.to_be_bytes());
+        out.extend_from_slice(&(self.requester.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.requester.as_bytes());
+        out.extend_from_slice(&(self.reason.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.reason.as_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != REQUEST_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != QUORUM_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let envelope = r.array()?;
+        let expires_at = u64::from_be_bytes(r.array()?);
+        let mut text = || {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            String::from_utf8(r.take(len)?.to_vec()).map_err(|_| CoreError::Format("bad request text"))
+        };
+        let requester = text()?;
+        let reason = text()?;
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        Ok(DecryptionRequest { envelope, requester, reason, expires_at })
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        blake3::hash(&self.to_bytes()).into()
+    }
+
+    /// Signs the request with an approver's Dilithium5 keypair.
+    pub fn approve(&self, approver_public_key: &[u8], approver_secret_key: &[u8]) -> CoreResult<Approval> {
+        if self.requester.len() > u16::MAX as usize || self.reason.len() > u16::MAX as usize {
+            return Err(CoreError::Config("requester and reason must be at most 65535 bytes".into()));
+        }
+        let signature = crypto::sign(approver_secret_key, &self.to_bytes())?;
+        Ok(Approval { approver: key_id(approver_public_key), signature })
+    }
+}
+
+/// One approver's signature over a [`DecryptionRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Approval {
+    /// [`key_id`] of the approver's key.
+    pub approver: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl Approval {
+    /// `magic(4) | version(1) | approver(32) | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(37 + self.signature.len());
+        out.extend_from_slice(APPROVAL_MAGIC);
+        out.push(QUORUM_VERSION);
+        out.extend_from_slice(&self.approver);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != APPROVAL_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != QUORUM_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let approver = r.array()?;
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("approval without signature"));
+        }
+        Ok(Approval { approver, signature: r.buf.to_vec() })
+    }
+
+    /// True if `approver_pk` is the named approver and signed `request`.
+    pub fn verify(&self, request: &DecryptionRequest, approver_pk: &[u8]) -> bool {
+        key_id(approver_pk) == self.approver && crypto::verify_signature(approver_pk, &request.to_bytes(), &self.signature)
+    }
+}
+
+/// The approver keys and how many of them must sign.
+#[derive(Debug, Clone)]
+pub struct QuorumPolicy {
+    approvers: Vec<Vec<u8>>,
+    threshold: usize,
+}
+
+impl QuorumPolicy {
+    /// Fails with [`CoreError::Config`] if a key is listed twice, since it
+    /// approves only once, or `threshold` is not between 1 and the number
+    /// of approvers.
+    pub fn new(approvers: Vec<Vec<u8>>, threshold: usize) -> CoreResult<Self> {
+        let ids = approvers.iter().map(|pk| key_id(pk)).collect::<Vec<_>>();
+        if let Some((i, id)) = ids.iter().enumerate().find(|(i, id)| ids[..*i].contains(id)) {
+            return Err(CoreError::Config(format!("approver {} is listed twice ({})", i, hex::encode(id))));
+        }
+        if threshold == 0 || threshold > approvers.len() {
+            return Err(CoreError::Config("quorum threshold must be between 1 and the number of approvers".into()));
+        }
+        Ok(QuorumPolicy { approvers, threshold })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn approvers(&self) -> &[Vec<u8>] {
+        &self.approvers
+    }
+
+    /// [`key_id`]s of the distinct policy approvers with a valid approval
+    /// of `request`.
+    pub fn approved_by(&self, request: &DecryptionRequest, approvals: &[Approval]) -> Vec<[u8; 32]> {
+        let mut approved = Vec::new();
+        for pk in &self.approvers {
+            let id = key_id(pk);
+            if !approved.contains(&id) && approvals.iter().any(|a| a.verify(request, pk)) {
+                approved.push(id);
+            }
+        }
+        approved
+    }
+
+    // approver count(2) | threshold(2) | key ids, as recorded when installed.
+    fn summary(&self) -> Vec<u8> {
+        let mut out = (self.approvers.len() as u16).to_be_bytes().to_vec();
+        out.extend_from_slice(&(self.threshold as u16).to_be_bytes());
+        for pk in &self.approvers {
+            out.extend_from_slice(&key_id(pk));
+        }
+        out
+    }
+}
+
+impl Engine {
+    /// Requires `policy` for [`Engine::open_restricted`]. Recorded as a
+    /// `rekey` event bound to the threshold and approver key ids. Needs a
+    /// [`SensitiveOp::PolicyChange`] grant under step-up.
+    pub fn set_quorum_policy(&mut self, policy: QuorumPolicy) -> CoreResult<()> {
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, &policy.summary(), res)?;
+        self.record_event(OpType::Rekey, Outcome::Success, &policy.summary())?;
+        self.quorum = Some(policy);
+        Ok(())
+    }
+
+    /// [`Engine::seal_with_context`], marking the envelope restricted.
+    pub fn seal_restricted(&self, data: &[u8], pk_bytes: &[u8], context: &[u8]) -> CoreResult<(Envelope, String)> {
+        let res = self.try_seal(data, pk_bytes, context, Marks { restricted: true, ..Marks::default() });
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    /// Decrypts `envelope` once `approvals` of `request` meet the quorum
+    /// policy. The quorum is recorded as an `approval` event bound to the
+    /// request digest and approver key ids, then the attempt as a `decrypt`
+    /// event. Fails with [`CoreError::Unauthorized`] if the request names
+    /// another envelope, has expired or lacks approvals.
+    pub fn open_restricted(&self, envelope: &Envelope, sk_bytes: &[u8], context: &[u8], request: &DecryptionRequest,
+                           approvals: &[Approval]) -> CoreResult<Vec<u8>> {
+        let digest = request.digest();
+        self.audited(OpType::Decrypt, &[], self.permit(Permission::Decrypt))?;
+        let approved = self.audited(OpType::Approval, &digest, self.check_quorum(envelope, request, approvals))?;
+        let mut subject = digest.to_vec();
+        approved.iter().for_each(|id| subject.extend_from_slice(id));
+        self.record_event(OpType::Approval, Outcome::Success, &subject)?;
+
+        let res = self.check_envelope_approved(envelope)
+            .and_then(|_| self.check_constraints(envelope))
+            .and_then(|_| envelope.decrypt(sk_bytes, context));
+        let plaintext = self.audited(OpType::Decrypt, &envelope.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &envelope.kem_ct)?;
+        Ok(plaintext)
+    }
+
+    fn check_quorum(&self, envelope: &Envelope, request: &DecryptionRequest, approvals: &[Approval]) -> CoreResult<Vec<[u8; 32]>> {
+        let policy = self.quorum.as_ref().ok_or(CoreError::Config("no quorum policy installed".into()))?;
+        if request.envelope != <[u8; 32]>::from(blake3::hash(&envelope.to_bytes())) {
+            return Err(CoreError::Unauthorized);
+        }
+        if self.clock().now_ms() / 1000 > request.expires_at {
+            return Err(CoreError::Unauthorized);
+        }
+        let approved = policy.approved_by(request, approvals);
+        if approved.len() < policy.threshold {
+            return Err(CoreError::Unauthorized);
+        }
+        Ok(approved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_approvers_are_refused() {
+        let (a, _) = crypto::generate_signing_keypair();
+        let (b, _) = crypto::generate_signing_keypair();
+        let res = QuorumPolicy::new(vec![a.clone(), a.clone(), b.clone()], 3);
+        assert!(matches!(res, Err(CoreError::Config(msg)) if msg.contains("listed twice")));
+        assert!(QuorumPolicy::new(vec![a.clone(), a.clone(), b.clone()], 2).is_err());
+        assert_eq!(QuorumPolicy::new(vec![a, b], 2).unwrap().threshold(), 2);
+    }
+}
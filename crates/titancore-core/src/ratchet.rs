@@ -0,0 +1,395 @@
+//! Post-quantum double ratchet for asynchronous messaging.
+//!
+//! Sessions follow the Signal double ratchet with a Kyber-1024 KEM in place
+//! of Diffie-Hellman: whenever the conversation changes direction, the new
+//! sender generates a fresh Kyber keypair, encapsulates to the peer's latest
+//! one and mixes the shared secret into the root key. Between turns each
+//! message key comes from a symmetric chain, so every message has its own key
+//! and a used key is gone.
+//!
+//! Message keys skipped over by a gap are kept (up to the session's
+//! `max_skip`) so late or reordered messages still open; a message that does
+//! not open leaves the session unchanged. [`RatchetSession::to_bytes`] holds
+//! live secrets and should be stored encrypted.
+
+use crate::crypto;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::guarded::Guarded;
+use crate::idle::IdleKey;
+use crate::kdf::Kdf;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use zeroize::Zeroizing;
+
+pub const MESSAGE_MAGIC: &[u8; 4] = b"TCRM";
+pub const STATE_MAGIC: &[u8; 4] = b"TCRS";
+pub const RATCHET_VERSION: u8 = 1;
+/// Skipped message keys a session keeps by default.
+pub const DEFAULT_MAX_SKIP: u32 = 1000;
+
+const ROOT_INFO: &[u8] = b"titancore ratchet v1 root";
+const CHAIN_INFO: &[u8] = b"titancore ratchet v1 chain";
+
+// Root and chain keys, held for the session's life, are guarded (see
+// `crate::guarded`); message keys live only as long as their message or
+// in the skipped list, which may be long.
+type Key = Guarded<[u8; 32]>;
+type MessageKey = Zeroizing<[u8; 32]>;
+
+fn new_key(bytes: [u8; 32]) -> Key {
+    Guarded::new(&bytes)
+}
+
+// (root key, chain key) = HKDF(salt = root key, ikm = KEM shared secret)
+fn kdf_root(root: &[u8; 32], shared_secret: &[u8]) -> CoreResult<(Key, Key)> {
+    let mut out = Zeroizing::new([0u8; 64]);
+    Kdf::HkdfSha256.derive(shared_secret, root, ROOT_INFO, out.as_mut())?;
+    Ok((new_key(out[..32].try_into().expect("32 bytes")), new_key(out[32..].try_into().expect("32 bytes"))))
+}
+
+// (next chain key, message key)
+fn kdf_chain(chain: &[u8; 32]) -> CoreResult<(Key, MessageKey)> {
+    let mut out = Zeroizing::new([0u8; 64]);
+    Kdf::HkdfSha256.derive(chain, &[], CHAIN_INFO, out.as_mut())?;
+    Ok((new_key(out[..32].try_into().expect("32 bytes")), Zeroizing::new(out[32..].try_into().expect("32 bytes"))))
+}
+
+fn epoch_id(sender_pk: &[u8], kem_ct: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(sender_pk);
+    hasher.update(kem_ct);
+    hasher.finalize().into()
+}
+
+fn put_var(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_var<'a>(r: &mut Reader<'a>) -> CoreResult<&'a [u8]> {
+    let len = u16::from_be_bytes(r.array()?) as usize;
+    r.take(len)
+}
+
+fn read_u32(r: &mut Reader) -> CoreResult<u32> {
+    Ok(u32::from_be_bytes(r.array()?))
+}
+
+#[derive(Clone)]
+struct SendChain {
+    key: Key,
+    n: u32,
+    public_key: Vec<u8>,
+    kem_ct: Vec<u8>,
+}
+
+#[derive(Clone)]
+struct RecvChain {
+    epoch: [u8; 32],
+    key: Key,
+    n: u32,
+}
+
+#[derive(Clone)]
+struct Skipped {
+    epoch: [u8; 32],
+    n: u32,
+    key: MessageKey,
+}
+
+/// The header of a ratchet message.
+struct Header<'a> {
+    public_key: &'a [u8],
+    kem_ct: &'a [u8],
+    prev_count: u32,
+    n: u32,
+}
+
+impl Header<'_> {
+    /// `magic(4) | version(1) | pk_len(2) | sender_pk | ct_len(2) | kem_ct | prev_count(4) | n(4)`
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(19 + self.public_key.len() + self.kem_ct.len());
+        out.extend_from_slice(MESSAGE_MAGIC);
+        out.push(RATCHET_VERSION);
+        put_var(&mut out, self.public_key);
+        put_var(&mut out, self.kem_ct);
+        out.extend_from_slice(&self.prev_count.to_be_bytes());
+        out.extend_from_slice(&self.n.to_be_bytes());
+        out
+    }
+
+    // Header and the ciphertext after it.
+    fn parse(message: &[u8]) -> CoreResult<(Header<'_>, &[u8], &[u8])> {
+        let mut r = Reader { buf: message };
+        if r.take(4)? != MESSAGE_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != RATCHET_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let public_key = read_var(&mut r)?;
+        let kem_ct = read_var(&mut r)?;
+        let prev_count = read_u32(&mut r)?;
+        let n = read_u32(&mut r)?;
+        let header_len = message.len() - r.buf.len();
+        Ok((Header { public_key, kem_ct, prev_count, n }, &message[..header_len], r.buf))
+    }
+}
+
+/// One side of a ratchet conversation. The initiator sends first; the
+/// responder can reply once it has received a message.
+#[derive(Clone)]
+pub struct RatchetSession {
+    root: Key,
+    // Our current Kyber keypair; the secret half is destroyed once the peer
+    // has encapsulated to it.
+    own: Option<(Vec<u8>, IdleKey)>,
+    remote: Option<Vec<u8>>,
+    send: Option<SendChain>,
+    recv: Option<RecvChain>,
+    prev_send: u32,
+    skipped: Vec<Skipped>,
+    max_skip: u32,
+}
+
+impl RatchetSession {
+    /// Starts a session to the Kyber key the responder published.
+    /// `root_secret` comes from whatever both sides already share (a
+    /// pre-shared key, a key exchange; empty for none) and keeps the session
+    /// bound to it.
+    pub fn initiate(root_secret: &[u8], remote_public_key: &[u8]) -> CoreResult<Self> {
+        crypto::parse_public_key(remote_public_key)?;
+        let mut session = Self::new(root_secret)?;
+        session.remote = Some(remote_public_key.to_vec());
+        Ok(session)
+    }
+
+    /// Accepts sessions addressed to the published Kyber keypair.
+    pub fn respond(root_secret: &[u8], public_key: &[u8], secret_key: &[u8]) -> CoreResult<Self> {
+        crypto::parse_secret_key(secret_key)?;
+        let mut session = Self::new(root_secret)?;
+        session.own = Some((public_key.to_vec(), IdleKey::new(secret_key)));
+        Ok(session)
+    }
+
+    fn new(root_secret: &[u8]) -> CoreResult<Self> {
+        let mut root = new_key([0u8; 32]);
+        Kdf::HkdfSha256.derive(root_secret, &[], ROOT_INFO, root.as_mut())?;
+        Ok(RatchetSession {
+            root,
+            own: None,
+            remote: None,
+            send: None,
+            recv: None,
+            prev_send: 0,
+            skipped: Vec::new(),
+            max_skip: DEFAULT_MAX_SKIP,
+        })
+    }
+
+    /// How many skipped message keys to keep, and so the largest gap a
+    /// single message may jump.
+    pub fn with_max_skip(mut self, max_skip: u32) -> Self {
+        self.max_skip = max_skip;
+        self
+    }
+
+    /// Encrypts `data`; `context` must match on [`RatchetSession::open`].
+    pub fn seal(&mut self, data: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        if self.send.is_none() {
+            self.step_send()?;
+        }
+        let send = self.send.as_mut().expect("sending chain");
+        if send.n == u32::MAX {
+            return Err(CoreError::RekeyRequired("ratchet chain exhausted"));
+        }
+        let (next, message_key) = kdf_chain(&send.key)?;
+        let header = Header { public_key: &send.public_key, kem_ct: &send.kem_ct, prev_count: self.prev_send, n: send.n }.to_bytes();
+        send.key = next;
+        send.n += 1;
+        let mut message = header.clone();
+        message.extend_from_slice(&crypto::aead_seal_aad(&message_key, &[0u8; 12], data, &[&header, context].concat())?);
+        Ok(message)
+    }
+
+    // Fresh keypair and encapsulation to the peer's latest key.
+    fn step_send(&mut self) -> CoreResult<()> {
+        let remote = self.remote.as_ref().ok_or(CoreError::Config("no message received yet".into()))?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&crypto::parse_public_key(remote)?);
+        let (public_key, secret_key) = crypto::generate_keypair();
+        let (root, chain) = kdf_root(&self.root, shared_secret.as_bytes())?;
+        self.root = root;
+        self.send = Some(SendChain { key: chain, n: 0, public_key: public_key.clone(), kem_ct: kem_ct.as_bytes().to_vec() });
+        self.own = Some((public_key, IdleKey::new(&secret_key)));
+        Ok(())
+    }
+
+    /// Decrypts a message from the peer, in any order. Fails with
+    /// [`CoreError::Decryption`] for a replayed, forged or too-old message,
+    /// leaving the session as it was.
+    pub fn open(&mut self, message: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let mut next = self.clone();
+        let data = next.try_open(message, context)?;
+        *self = next;
+        Ok(data)
+    }
+
+    fn try_open(&mut self, message: &[u8], context: &[u8]) -> CoreResult<Vec<u8>> {
+        let (header, header_bytes, ciphertext) = Header::parse(message)?;
+        let epoch = epoch_id(header.public_key, header.kem_ct);
+        let aad = [header_bytes, context].concat();
+        if let Some(i) = self.skipped.iter().position(|s| s.epoch == epoch && s.n == header.n) {
+            let skipped = self.skipped.remove(i);
+            return crypto::aead_open_aad(&skipped.key, &[0u8; 12], ciphertext, &aad);
+        }
+        if self.recv.as_ref().map(|r| r.epoch) != Some(epoch) {
+            if let Some(recv) = &self.recv {
+                let prev = recv.epoch;
+                self.skip_to(prev, header.prev_count)?;
+            }
+            self.step_recv(&header, epoch)?;
+        }
+        self.skip_to(epoch, header.n)?;
+        let recv = self.recv.as_mut().expect("receiving chain");
+        if recv.n != header.n {
+            return Err(CoreError::Decryption);
+        }
+        let (next, message_key) = kdf_chain(&recv.key)?;
+        recv.key = next;
+        recv.n += 1;
+        crypto::aead_open_aad(&message_key, &[0u8; 12], ciphertext, &aad)
+    }
+
+    // Decapsulates with our current secret key, which is then destroyed.
+    fn step_recv(&mut self, header: &Header, epoch: [u8; 32]) -> CoreResult<()> {
+        let secret_key = match self.own.take() {
+            Some((_, secret_key)) => secret_key,
+            None => return Err(CoreError::Decryption),
+        };
+        let kem_ct = kyber1024::Ciphertext::from_bytes(header.kem_ct).map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        crypto::parse_public_key(header.public_key)?;
+        let sk = crypto::parse_secret_key(&secret_key.unwrapped())?;
+        let shared_secret = crypto::decapsulate(&kem_ct, &sk);
+        let (root, chain) = kdf_root(&self.root, shared_secret.as_bytes())?;
+        self.root = root;
+        self.recv = Some(RecvChain { epoch, key: chain, n: 0 });
+        self.remote = Some(header.public_key.to_vec());
+        self.prev_send = self.send.take().map_or(0, |s| s.n);
+        Ok(())
+    }
+
+    // Stores message keys of the receiving chain up to (not including) `until`.
+    fn skip_to(&mut self, epoch: [u8; 32], until: u32) -> CoreResult<()> {
+        let Some(recv) = self.recv.as_mut().filter(|r| r.epoch == epoch) else { return Ok(()) };
+        if until <= recv.n {
+            return Ok(());
+        }
+        if until - recv.n > self.max_skip {
+            return Err(CoreError::Decryption);
+        }
+        while recv.n < until {
+            let (next, key) = kdf_chain(&recv.key)?;
+            self.skipped.push(Skipped { epoch, n: recv.n, key });
+            recv.key = next;
+            recv.n += 1;
+        }
+        let excess = self.skipped.len().saturating_sub(self.max_skip as usize);
+        self.skipped.drain(..excess);
+        Ok(())
+    }
+
+    /// Skipped message keys currently held.
+    pub fn skipped_keys(&self) -> usize {
+        self.skipped.len()
+    }
+
+    /// `magic(4) | version(1) | root(32) | max_skip(4) | prev_send(4) | flags(1) |
+    ///  [own_pk | own_sk] | [remote_pk] | [send_key(32) | n(4) | pk | kem_ct] |
+    ///  [epoch(32) | recv_key(32) | n(4)] | skipped_count(4) | (epoch(32) | n(4) | key(32))*`,
+    /// variable fields as `len(2) | bytes`; `flags` bits 0-3 mark which of
+    /// the bracketed parts are present.
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(Vec::with_capacity(8192 + self.skipped.len() * 68));
+        out.extend_from_slice(STATE_MAGIC);
+        out.push(RATCHET_VERSION);
+        out.extend_from_slice(self.root.as_ref());
+        out.extend_from_slice(&self.max_skip.to_be_bytes());
+        out.extend_from_slice(&self.prev_send.to_be_bytes());
+        let flags = u8::from(self.own.is_some()) | u8::from(self.remote.is_some()) << 1
+            | u8::from(self.send.is_some()) << 2 | u8::from(self.recv.is_some()) << 3;
+        out.push(flags);
+        if let Some((pk, sk)) = &self.own {
+            put_var(&mut out, pk);
+            put_var(&mut out, &sk.unwrapped());
+        }
+        if let Some(remote) = &self.remote {
+            put_var(&mut out, remote);
+        }
+        if let Some(send) = &self.send {
+            out.extend_from_slice(send.key.as_ref());
+            out.extend_from_slice(&send.n.to_be_bytes());
+            put_var(&mut out, &send.public_key);
+            put_var(&mut out, &send.kem_ct);
+        }
+        if let Some(recv) = &self.recv {
+            out.extend_from_slice(&recv.epoch);
+            out.extend_from_slice(recv.key.as_ref());
+            out.extend_from_slice(&recv.n.to_be_bytes());
+        }
+        out.extend_from_slice(&(self.skipped.len() as u32).to_be_bytes());
+        for s in &self.skipped {
+            out.extend_from_slice(&s.epoch);
+            out.extend_from_slice(&s.n.to_be_bytes());
+            out.extend_from_slice(s.key.as_ref());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != STATE_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != RATCHET_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let root = new_key(r.array()?);
+        let max_skip = read_u32(&mut r)?;
+        let prev_send = read_u32(&mut r)?;
+        let flags = r.take(1)?[0];
+        if flags & !0x0f != 0 {
+            return Err(CoreError::Format("bad ratchet state flags"));
+        }
+        let own = match flags & 1 != 0 {
+            true => Some((read_var(&mut r)?.to_vec(), IdleKey::new(read_var(&mut r)?))),
+            false => None,
+        };
+        let remote = (flags & 2 != 0).then(|| read_var(&mut r).map(<[u8]>::to_vec)).transpose()?;
+        let send = match flags & 4 != 0 {
+            true => Some(SendChain {
+                key: new_key(r.array()?),
+                n: read_u32(&mut r)?,
+                public_key: read_var(&mut r)?.to_vec(),
+                kem_ct: read_var(&mut r)?.to_vec(),
+            }),
+            false => None,
+        };
+        let recv = match flags & 8 != 0 {
+            true => Some(RecvChain { epoch: r.array()?, key: new_key(r.array()?), n: read_u32(&mut r)? }),
+            false => None,
+        };
+        let count = read_u32(&mut r)? as usize;
+        if count > r.buf.len() / 68 {
+            return Err(CoreError::Format("truncated"));
+        }
+        let mut skipped = Vec::with_capacity(count);
+        for _ in 0..count {
+            skipped.push(Skipped { epoch: r.array()?, n: read_u32(&mut r)?, key: Zeroizing::new(r.array()?) });
+        }
+        if !r.buf.is_empty() {
+            return Err(CoreError::Format("trailing bytes"));
+        }
+        Ok(RatchetSession { root, own, remote, send, recv, prev_send, skipped, max_skip })
+    }
+}
@@ -0,0 +1,215 @@
+//! Request quotas.
+//!
+//! Every sealing call asks the engine's [`RateLimiter`] for one request
+//! under the engine's rate-limit key (the hex fingerprint unless
+//! [`crate::EngineConfig::rate_limit_key`] names a tenant). The default
+//! [`SlidingWindow`] limits one process; with the `redis` feature,
+//! [`RedisRateLimiter`] keeps the window in Redis so a fleet of workers
+//! using the same key shares one quota.
+//!
+//! Separately, [`Engine::set_key_limit`] caps how often one recipient key
+//! may be used, whatever the engine-wide quota.
+
+use crate::audit::{FailureReason, OpType, Outcome};
+use crate::engine::{Engine, MAX_BURST_REQUESTS, RATE_LIMIT_WINDOW};
+use crate::error::{CoreError, CoreResult};
+use crate::stepup::SensitiveOp;
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+/// Decides whether one more request under `key` is within quota.
+pub trait RateLimiter: Send + Sync {
+    /// Records a request for `key` and returns `false` if it is over
+    /// quota (refused requests are not counted). `now` is the engine's
+    /// monotonic clock; shared backends may use their own time instead.
+    fn acquire(&self, key: &str, now: Duration) -> CoreResult<bool>;
+}
+
+impl fmt::Debug for dyn RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RateLimiter")
+    }
+}
+
+/// In-process sliding window: at most `max` requests per key in any
+/// `window_secs`. A request leaves the window once it is more than
+/// `window_secs` whole seconds old.
+pub struct SlidingWindow {
+    max: usize,
+    window_secs: u64,
+    history: Mutex<HashMap<String, VecDeque<Duration>>>,
+}
+
+impl SlidingWindow {
+    pub fn new(max: usize, window_secs: u64) -> Self {
+        SlidingWindow { max, window_secs, history: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl Default for SlidingWindow {
+    /// [`MAX_BURST_REQUESTS`] per [`RATE_LIMIT_WINDOW`] seconds.
+    fn default() -> Self {
+        Self::new(MAX_BURST_REQUESTS, RATE_LIMIT_WINDOW)
+    }
+}
+
+impl RateLimiter for SlidingWindow {
+    fn acquire(&self, key: &str, now: Duration) -> CoreResult<bool> {
+        let mut history = self.history.lock();
+        let window = history.entry(key.to_string()).or_insert_with(|| VecDeque::with_capacity(self.max));
+        while let Some(&t) = window.front() {
+            if now.saturating_sub(t).as_secs() > self.window_secs { window.pop_front(); }
+            else { break; }
+        }
+        if window.len() >= self.max {
+            return Ok(false);
+        }
+        window.push_back(now);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_backend::RedisRateLimiter;
+
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use super::RateLimiter;
+    use crate::engine::{MAX_BURST_REQUESTS, RATE_LIMIT_WINDOW};
+    use crate::entropy;
+    use crate::error::{CoreError, CoreResult};
+    use parking_lot::Mutex;
+    use std::time::Duration;
+
+    // A sorted set per key, scored by the Redis server's clock in
+    // microseconds, so workers with different clocks agree on the window.
+    const SLIDING_WINDOW: &str = r"
+        local t = redis.call('TIME')
+        local now = tonumber(t[1]) * 1000000 + tonumber(t[2])
+        local window = tonumber(ARGV[1])
+        redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+        if redis.call('ZCARD', KEYS[1]) >= tonumber(ARGV[2]) then
+            return 0
+        end
+        redis.call('ZADD', KEYS[1], now, ARGV[3])
+        redis.call('PEXPIRE', KEYS[1], math.ceil(window / 1000))
+        return 1
+    ";
+
+    /// Sliding window kept in Redis: at most `max` requests per key in any
+    /// `window_secs`, across every process using the same server and key.
+    /// Needs Redis 5 or later (the script reads the server clock). If the
+    /// server cannot be reached, requests fail with
+    /// [`CoreError::Storage`] rather than going unlimited.
+    pub struct RedisRateLimiter {
+        client: redis::Client,
+        conn: Mutex<Option<redis::Connection>>,
+        script: redis::Script,
+        prefix: String,
+        max: usize,
+        window: Duration,
+    }
+
+    fn redis_err(e: redis::RedisError) -> CoreError {
+        CoreError::Storage(format!("Redis error: {}", e))
+    }
+
+    impl RedisRateLimiter {
+        /// [`MAX_BURST_REQUESTS`] per [`RATE_LIMIT_WINDOW`] seconds on the
+        /// server at `url` (`redis://host:port/db`).
+        pub fn new(url: &str) -> CoreResult<Self> {
+            Self::with_quota(url, MAX_BURST_REQUESTS, RATE_LIMIT_WINDOW)
+        }
+
+        pub fn with_quota(url: &str, max: usize, window_secs: u64) -> CoreResult<Self> {
+            let client = redis::Client::open(url).map_err(|e| CoreError::Config(format!("Redis URL: {}", e)))?;
+            Ok(RedisRateLimiter {
+                client,
+                conn: Mutex::new(None),
+                script: redis::Script::new(SLIDING_WINDOW),
+                prefix: "titancore:ratelimit:".into(),
+                max,
+                window: Duration::from_secs(window_secs),
+            })
+        }
+
+        /// Namespace for the Redis keys; default `titancore:ratelimit:`.
+        pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+            self.prefix = prefix.into();
+            self
+        }
+    }
+
+    impl RateLimiter for RedisRateLimiter {
+        fn acquire(&self, key: &str, _now: Duration) -> CoreResult<bool> {
+            let mut member = [0u8; 16];
+            entropy::fill(&mut member)?;
+            let mut conn = self.conn.lock();
+            if conn.is_none() {
+                *conn = Some(self.client.get_connection().map_err(redis_err)?);
+            }
+            let res: redis::RedisResult<i64> = self.script
+                .key(format!("{}{}", self.prefix, key))
+                .arg(self.window.as_micros() as u64)
+                .arg(self.max)
+                .arg(hex::encode(member))
+                .invoke(conn.as_mut().expect("connected above"));
+            match res {
+                Ok(allowed) => Ok(allowed == 1),
+                Err(e) => {
+                    // Reconnect on the next request.
+                    *conn = None;
+                    Err(redis_err(e))
+                }
+            }
+        }
+    }
+}
+
+impl Engine {
+    /// Allows at most `max` uses of the key with id `key_id` (see
+    /// [`crate::cert::key_id`]) in any `window_secs`, e.g. the escrow key 5
+    /// times a day. A use is one sealing call to the key as recipient, or
+    /// one escrow decryption for the escrow key. A call over the limit
+    /// fails with [`CoreError::RateLimited`] and is recorded as a
+    /// `rate-limited` event bound to the key id. Counts are kept in memory
+    /// and start over with the engine. Replacing a limit resets its count.
+    /// Needs a [`SensitiveOp::PolicyChange`] grant under step-up, and the
+    /// `admin` permission once a role policy is installed.
+    pub fn set_key_limit(&mut self, key_id: [u8; 32], max: usize, window_secs: u64) -> CoreResult<()> {
+        if max == 0 || window_secs == 0 {
+            return Err(CoreError::Config("key limit needs a positive count and window".into()));
+        }
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, &key_id, res)?;
+        self.key_limits.insert(key_id, SlidingWindow::new(max, window_secs));
+        self.record_event(OpType::Rekey, Outcome::Success, &key_id)?;
+        Ok(())
+    }
+
+    /// Removes the limit on `key_id`; true if there was one. Gated as
+    /// [`Engine::set_key_limit`] is.
+    pub fn clear_key_limit(&mut self, key_id: &[u8; 32]) -> CoreResult<bool> {
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, key_id, res)?;
+        let removed = self.key_limits.remove(key_id).is_some();
+        self.record_event(OpType::Rekey, Outcome::Success, key_id)?;
+        Ok(removed)
+    }
+
+    /// `(key_id, max, window_secs)` for every configured limit.
+    pub fn key_limits(&self) -> Vec<([u8; 32], usize, u64)> {
+        self.key_limits.iter().map(|(id, w)| (*id, w.max, w.window_secs)).collect()
+    }
+
+    /// Counts one use of `key_id` for `op` against its limit, if it has one.
+    pub(crate) fn check_key_limit(&self, op: OpType, key_id: &[u8; 32]) -> CoreResult<()> {
+        let Some(window) = self.key_limits.get(key_id) else { return Ok(()) };
+        if window.acquire("", self.clock().monotonic())? {
+            return Ok(());
+        }
+        self.refuse_key(op, Outcome::RateLimited, FailureReason::KeyLimit, key_id, CoreError::RateLimited)
+    }
+}
@@ -0,0 +1,323 @@
+//! Role-based authorization of engine operations.
+//!
+//! [`Engine::set_roles`] installs a [`RolePolicy`]: named roles, the
+//! [`Permission`]s each holds, and the bearer tokens that act as each role.
+//! From then on a guarded operation runs only inside a caller scope from
+//! [`Engine::enter_caller`], which resolves a token to its role for the
+//! calls this thread makes until the scope is dropped, including work the
+//! engine hands to its worker pool. Outside one, or without the
+//! permission, the operation fails with [`CoreError::Unauthorized`] and is
+//! recorded as failed like any other failure. Every audit entry written in
+//! a scope carries the role ([`AuditEntry::role`]). The role is recorded
+//! beside the link, not hashed into it, so the chain does not authenticate
+//! it.
+//!
+//! - `encrypt` covers sealing to a recipient key in any format, including
+//!   the manifests signed for encrypted trees and multipart uploads.
+//! - `decrypt` covers opening envelopes, streams, multipart uploads,
+//!   archives and JWE, COSE and age messages; rewrapping needs it as well
+//!   as `encrypt`.
+//! - `sign` covers protected messages, signed or MACed, and attestations.
+//! - `key_export` covers what [`SensitiveOp::KeyExport`] does.
+//! - `admin` covers policy changes ([`SensitiveOp::PolicyChange`]:
+//!   replacing the role policy, the identity key, the revocation checker,
+//!   key limits and usage caps) and escrow decryption.
+//!
+//! Step-up still applies on top. Tokens are kept as SHA-256 hashes and
+//! compared in constant time.
+//!
+//! [`AuditEntry::role`]: crate::AuditEntry::role
+
+use crate::audit::{OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::error::{CoreError, CoreResult};
+use crate::stepup::SensitiveOp;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Shortest token accepted.
+pub const MIN_TOKEN_LEN: usize = 16;
+/// Longest role name; names are letters, digits, `_`, `-` and `.`.
+pub const MAX_ROLE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Encrypt,
+    Decrypt,
+    Sign,
+    KeyExport,
+    Admin,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [Permission::Encrypt, Permission::Decrypt, Permission::Sign, Permission::KeyExport, Permission::Admin];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::Encrypt => "encrypt",
+            Permission::Decrypt => "decrypt",
+            Permission::Sign => "sign",
+            Permission::KeyExport => "key_export",
+            Permission::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Permission> {
+        Self::ALL.into_iter().find(|p| p.as_str() == s)
+    }
+}
+
+impl SensitiveOp {
+    /// The permission a caller needs for this operation.
+    pub fn permission(self) -> Permission {
+        match self {
+            SensitiveOp::KeyExport => Permission::KeyExport,
+            SensitiveOp::EscrowDecrypt | SensitiveOp::PolicyChange => Permission::Admin,
+        }
+    }
+}
+
+/// Roles, their permissions and the tokens that act as them.
+#[derive(Debug, Clone, Default)]
+pub struct RolePolicy {
+    roles: Vec<(String, Vec<Permission>)>,
+    // SHA-256 of each token, and the index of its role.
+    tokens: Vec<([u8; 32], usize)>,
+}
+
+impl RolePolicy {
+    pub fn new() -> Self {
+        RolePolicy::default()
+    }
+
+    /// Adds role `name` holding `permissions`. Fails with
+    /// [`CoreError::Config`] if the name is taken or not a valid name.
+    pub fn add_role(&mut self, name: &str, permissions: &[Permission]) -> CoreResult<()> {
+        if !valid_role(name) {
+            return Err(CoreError::Config(format!("bad role name {:?}", name)));
+        }
+        if self.role_index(name).is_some() {
+            return Err(CoreError::Config(format!("role {} defined twice", name)));
+        }
+        self.roles.push((name.to_string(), permissions.to_vec()));
+        Ok(())
+    }
+
+    /// Lets `token` act as `role`, which must already be added. Fails with
+    /// [`CoreError::Config`] for a short or reused token.
+    pub fn add_token(&mut self, role: &str, token: &str) -> CoreResult<()> {
+        let index = self.role_index(role).ok_or_else(|| CoreError::Config(format!("unknown role {}", role)))?;
+        if token.len() < MIN_TOKEN_LEN {
+            return Err(CoreError::Config(format!("tokens must be at least {} bytes", MIN_TOKEN_LEN)));
+        }
+        if self.resolve(token).is_some() {
+            return Err(CoreError::Config("token already assigned".into()));
+        }
+        self.tokens.push((token_hash(token), index));
+        Ok(())
+    }
+
+    /// Each role and its permissions, in the order added.
+    pub fn roles(&self) -> impl Iterator<Item = (&str, &[Permission])> {
+        self.roles.iter().map(|(name, perms)| (name.as_str(), perms.as_slice()))
+    }
+
+    /// The role `token` acts as.
+    pub fn resolve(&self, token: &str) -> Option<&str> {
+        let hash = token_hash(token);
+        // Every entry is compared, so the time taken does not say which
+        // matched.
+        let found = self.tokens.iter().fold(None, |found, (h, i)| if crypto::ct_eq(h, &hash) { Some(*i) } else { found });
+        found.map(|i| self.roles[i].0.as_str())
+    }
+
+    pub fn allows(&self, role: &str, permission: Permission) -> bool {
+        self.role_index(role).is_some_and(|i| self.roles[i].1.contains(&permission))
+    }
+
+    fn role_index(&self, name: &str) -> Option<usize> {
+        self.roles.iter().position(|(n, _)| n == name)
+    }
+
+    // Bound into the `rekey` event that installs the policy.
+    fn summary(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, perms) in &self.roles {
+            out.extend_from_slice(name.as_bytes());
+            out.push(b'=');
+            out.extend_from_slice(perms.iter().map(|p| p.as_str()).collect::<Vec<_>>().join(",").as_bytes());
+            out.push(b';');
+        }
+        out
+    }
+}
+
+pub(crate) fn valid_role(name: &str) -> bool {
+    (1..=MAX_ROLE_LEN).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'))
+}
+
+fn token_hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+// Tells installed policies apart, so a scope entered under one does not
+// carry over to its replacement or to another engine.
+static POLICY_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The caller this thread acts for: the installed policy the token was
+/// resolved against and its role.
+#[derive(Clone)]
+pub(crate) struct Caller {
+    policy: u64,
+    role: String,
+}
+
+thread_local! {
+    static CALLER: RefCell<Option<Caller>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn current() -> Option<Caller> {
+    CALLER.with(|c| c.borrow().clone())
+}
+
+/// Runs `f` as `caller`, e.g. on a worker thread doing part of a call.
+#[cfg(feature = "parallel")]
+pub(crate) fn scoped<R>(caller: Option<Caller>, f: impl FnOnce() -> R) -> R {
+    let _scope = CallerScope::set(caller);
+    f()
+}
+
+/// Acts as a role until dropped; see [`Engine::enter_caller`]. Tied to the
+/// thread that made it. Scopes nest: dropping one restores the caller
+/// before it.
+pub struct CallerScope {
+    prev: Option<Caller>,
+    _thread: PhantomData<*const ()>,
+}
+
+impl CallerScope {
+    fn set(caller: Option<Caller>) -> Self {
+        let prev = CALLER.with(|c| c.replace(caller));
+        CallerScope { prev, _thread: PhantomData }
+    }
+}
+
+impl Drop for CallerScope {
+    fn drop(&mut self) {
+        let prev = self.prev.take();
+        CALLER.with(|c| *c.borrow_mut() = prev);
+    }
+}
+
+impl Engine {
+    /// Requires every guarded operation to run as a role of `policy`;
+    /// `None` lifts the requirement. Recorded as a `rekey` event bound to
+    /// the roles and their permissions. Needs a
+    /// [`SensitiveOp::PolicyChange`] grant under step-up, and the `admin`
+    /// permission once a policy is installed.
+    pub fn set_roles(&mut self, policy: Option<RolePolicy>) -> CoreResult<()> {
+        let summary = policy.as_ref().map(RolePolicy::summary).unwrap_or_default();
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, &summary, res)?;
+        // Recorded first, so the entry carries the role that made the change.
+        self.record_event(OpType::Rekey, Outcome::Success, &summary)?;
+        self.roles = policy.map(|p| (POLICY_ID.fetch_add(1, Ordering::Relaxed), p));
+        Ok(())
+    }
+
+    pub fn role_policy(&self) -> Option<&RolePolicy> {
+        self.roles.as_ref().map(|(_, policy)| policy)
+    }
+
+    /// Acts as the role `token` belongs to on this thread until the scope
+    /// is dropped. An unknown token fails with [`CoreError::Unauthorized`],
+    /// and without a policy installed any token fails with
+    /// [`CoreError::Config`]; both are recorded as a failed `approval`
+    /// event.
+    pub fn enter_caller(&self, token: &str) -> CoreResult<CallerScope> {
+        let res = match &self.roles {
+            Some((id, policy)) => policy.resolve(token).map(str::to_string).ok_or(CoreError::Unauthorized)
+                .map(|role| Caller { policy: *id, role }),
+            None => Err(CoreError::Config("no role policy installed".into())),
+        };
+        let caller = self.audited(OpType::Approval, b"role", res)?;
+        Ok(CallerScope::set(Some(caller)))
+    }
+
+    /// The role this thread currently acts as towards this engine.
+    pub fn caller_role(&self) -> Option<String> {
+        let (id, _) = self.roles.as_ref()?;
+        current().filter(|c| c.policy == *id).map(|c| c.role)
+    }
+
+    /// Fails with [`CoreError::Unauthorized`] unless no policy is installed
+    /// or the current caller's role holds `permission`.
+    pub(crate) fn permit(&self, permission: Permission) -> CoreResult<()> {
+        self.ensure_activated()?;
+        let Some((_, policy)) = &self.roles else { return Ok(()) };
+        match self.caller_role() {
+            Some(role) if policy.allows(&role, permission) => Ok(()),
+            _ => Err(CoreError::Unauthorized),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::MemorySink;
+    use crate::identity::Identity;
+    use crate::revocation::{RevocationChecker, RevocationList};
+    use crate::usage::UsageCap;
+    use std::time::Duration;
+
+    const ADMIN: &str = "admin-token-0123456789";
+    const OPS: &str = "ops-token-0123456789";
+
+    fn engine() -> Engine {
+        let mut policy = RolePolicy::new();
+        policy.add_role("admin", &[Permission::Admin]).unwrap();
+        policy.add_role("ops", &[Permission::Encrypt, Permission::Decrypt]).unwrap();
+        policy.add_token("admin", ADMIN).unwrap();
+        policy.add_token("ops", OPS).unwrap();
+        let mut engine = Engine::new("hw", "seed", Box::new(MemorySink::new())).unwrap();
+        engine.set_roles(Some(policy)).unwrap();
+        engine
+    }
+
+    fn checker() -> RevocationChecker {
+        RevocationChecker::new(Box::new(RevocationList::new()), Vec::new(), Duration::from_secs(60))
+    }
+
+    #[test]
+    fn identity_and_policy_changes_need_admin() {
+        let mut engine = engine();
+        let identity = Identity::generate();
+        let (pk, sk) = crypto::generate_signing_keypair();
+        let key_id = [7u8; 32];
+        let cap = UsageCap { max_operations: Some(10), max_bytes: None };
+        for token in [None, Some(OPS)] {
+            let _scope = token.map(|t| engine.enter_caller(t).unwrap());
+            assert!(matches!(engine.set_signing_keypair(&pk, &sk), Err(CoreError::Unauthorized)));
+            assert!(matches!(engine.rotate_identity(Identity::generate()), Err(CoreError::Unauthorized)));
+            assert!(matches!(engine.set_revocation_checker(checker()), Err(CoreError::Unauthorized)));
+            assert!(matches!(engine.set_key_limit(key_id, 5, 60), Err(CoreError::Unauthorized)));
+            assert!(matches!(engine.clear_key_limit(&key_id), Err(CoreError::Unauthorized)));
+            assert!(matches!(engine.set_key_usage_cap(key_id, cap), Err(CoreError::Unauthorized)));
+            assert!(matches!(engine.clear_key_usage_cap(&key_id), Err(CoreError::Unauthorized)));
+        }
+
+        let _scope = engine.enter_caller(ADMIN).unwrap();
+        engine.set_signing_keypair(&pk, &sk).unwrap();
+        engine.rotate_identity(identity).unwrap();
+        engine.set_revocation_checker(checker()).unwrap();
+        engine.set_key_limit(key_id, 5, 60).unwrap();
+        assert!(engine.clear_key_limit(&key_id).unwrap());
+        engine.set_key_usage_cap(key_id, cap).unwrap();
+        assert!(engine.clear_key_usage_cap(&key_id).unwrap());
+    }
+}
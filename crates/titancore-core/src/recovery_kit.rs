@@ -0,0 +1,357 @@
+//! Printable recovery kits for secret keys.
+//!
+//! A [`RecoveryKit`] is one or more [`KitSheet`]s, each a page of plain
+//! text meant to be printed and sealed in an envelope: the key id, what
+//! kind of key it is, how it is protected, restore instructions and a
+//! verification code, followed by an armored block holding the sheet
+//! itself. A kit either wraps the key under a 32-byte key kept elsewhere
+//! (one sheet), or splits it into Shamir shares as escrow does (one sheet
+//! per custodian, any `threshold` of which restore it).
+//!
+//! The verification code is the first eight bytes of the BLAKE3 hash of
+//! the sheet's bytes. Whoever types or scans a sheet back in can compare
+//! it with the printed one, and [`KitSheet::from_text`] checks it when the
+//! printed line is included.
+//!
+//! Sheet layout: `magic(4) | version(1) | key_type(1) | created_ms(8) |
+//! pk_len(2) | public_key | protection(1)`, then `nonce(12) | ciphertext`
+//! for a wrapped key (the header bound as AES-256-GCM-SIV AAD) or
+//! [`EscrowShare::to_bytes`] for a share.
+
+use crate::armor::{self, ArmorKind};
+use crate::audit::{OpType, Outcome};
+use crate::cert::key_id;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::entropy;
+use crate::envelope::{Reader, TAG_LEN};
+use crate::error::{CoreError, CoreResult};
+use crate::escrow::{self, EscrowShare};
+use crate::identity::Identity;
+use crate::kdf::Kdf;
+use crate::stepup::SensitiveOp;
+use crate::time::rfc3339_millis;
+use pqcrypto_dilithium::dilithium5;
+use pqcrypto_kyber::kyber1024;
+use zeroize::Zeroizing;
+
+pub const KIT_MAGIC: &[u8; 4] = b"TCRK";
+pub const KIT_VERSION: u8 = 1;
+
+const WRAP_INFO: &[u8] = b"titancore recovery kit v1";
+const WRAPPED: u8 = 1;
+const SHARE: u8 = 2;
+
+/// Which kind of secret key a kit holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KitKeyType {
+    /// A Dilithium5 identity (checkpoint signing) keypair.
+    Identity,
+    /// A Kyber-1024 recipient keypair.
+    Recipient,
+}
+
+impl KitKeyType {
+    /// The type of key `public_key` is, by its length.
+    pub fn of(public_key: &[u8]) -> Option<Self> {
+        match public_key.len() {
+            n if n == dilithium5::public_key_bytes() => Some(KitKeyType::Identity),
+            n if n == kyber1024::public_key_bytes() => Some(KitKeyType::Recipient),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KitKeyType::Identity => "identity",
+            KitKeyType::Recipient => "recipient",
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            KitKeyType::Identity => 1,
+            KitKeyType::Recipient => 2,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(KitKeyType::Identity),
+            2 => Some(KitKeyType::Recipient),
+            _ => None,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            KitKeyType::Identity => "engine identity (Dilithium5 signing key)",
+            KitKeyType::Recipient => "recipient key (Kyber-1024)",
+        }
+    }
+
+    // Fails with [`CoreError::InvalidKey`] unless the halves belong
+    // together.
+    fn check_pair(self, public_key: &[u8], secret_key: &[u8]) -> CoreResult<()> {
+        match self {
+            KitKeyType::Identity => Identity::new(public_key, secret_key).map(|_| ()),
+            KitKeyType::Recipient if escrow::embedded_public_key(secret_key)? == public_key => Ok(()),
+            KitKeyType::Recipient => Err(CoreError::InvalidKey),
+        }
+    }
+}
+
+/// How a kit protects its key.
+#[derive(Debug, Clone, Copy)]
+pub enum KitProtection<'a> {
+    /// Encrypted under a 32-byte key that must be stored apart from the
+    /// sheet.
+    Wrapped(&'a [u8; 32]),
+    /// Split into `custodians` sheets, any `threshold` of which restore it.
+    Shares { threshold: u8, custodians: u8 },
+}
+
+/// What one sheet carries besides its header.
+#[derive(Clone, PartialEq, Eq)]
+pub enum SheetBody {
+    /// `nonce(12) | ciphertext` of the secret key.
+    Wrapped(Vec<u8>),
+    Share(EscrowShare),
+}
+
+/// One printable page of a [`RecoveryKit`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct KitSheet {
+    pub key_type: KitKeyType,
+    pub public_key: Vec<u8>,
+    /// When the kit was made, in engine clock milliseconds.
+    pub created_ms: u64,
+    pub body: SheetBody,
+}
+
+impl std::fmt::Debug for KitSheet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KitSheet")
+            .field("key_id", &hex::encode(self.key_id()))
+            .field("key_type", &self.key_type)
+            .field("verification_code", &self.verification_code())
+            .finish_non_exhaustive()
+    }
+}
+
+impl KitSheet {
+    /// [`key_id`] of the key the sheet restores.
+    pub fn key_id(&self) -> [u8; 32] {
+        key_id(&self.public_key)
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(17 + self.public_key.len());
+        out.extend_from_slice(KIT_MAGIC);
+        out.push(KIT_VERSION);
+        out.push(self.key_type.code());
+        out.extend_from_slice(&self.created_ms.to_be_bytes());
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.push(match self.body {
+            SheetBody::Wrapped(_) => WRAPPED,
+            SheetBody::Share(_) => SHARE,
+        });
+        out
+    }
+
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut out = Zeroizing::new(self.header());
+        match &self.body {
+            SheetBody::Wrapped(sealed) => out.extend_from_slice(sealed),
+            SheetBody::Share(share) => out.extend_from_slice(&share.to_bytes()),
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != KIT_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != KIT_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let key_type = KitKeyType::from_code(r.take(1)?[0]).ok_or(CoreError::Format("unknown recovery kit key type"))?;
+        let created_ms = u64::from_be_bytes(r.array()?);
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        if KitKeyType::of(&public_key) != Some(key_type) {
+            return Err(CoreError::Format("recovery kit public key does not match its type"));
+        }
+        let body = match r.take(1)?[0] {
+            WRAPPED if r.buf.len() > 12 + TAG_LEN => SheetBody::Wrapped(r.buf.to_vec()),
+            WRAPPED => return Err(CoreError::Format("truncated")),
+            SHARE => {
+                let share = EscrowShare::from_bytes(r.buf)?;
+                if share.key_id != key_id(&public_key) {
+                    return Err(CoreError::Format("recovery kit share belongs to another key"));
+                }
+                SheetBody::Share(share)
+            }
+            _ => return Err(CoreError::Format("unknown recovery kit protection")),
+        };
+        Ok(KitSheet { key_type, public_key, created_ms, body })
+    }
+
+    /// `XXXX-XXXX-XXXX-XXXX`: the first eight bytes of BLAKE3 over
+    /// [`KitSheet::to_bytes`], in hex.
+    pub fn verification_code(&self) -> String {
+        let hash = blake3::hash(&self.to_bytes());
+        let hex = hex::encode_upper(&hash.as_bytes()[..8]);
+        hex.as_bytes().chunks(4).map(|c| std::str::from_utf8(c).expect("hex is ASCII")).collect::<Vec<_>>().join("-")
+    }
+
+    /// The printable page: header, instructions and the armored sheet.
+    pub fn to_text(&self) -> Zeroizing<String> {
+        let id = hex::encode(self.key_id());
+        let groups: Vec<&str> = (0..8).map(|i| &id[i * 8..i * 8 + 8]).collect();
+        let (title, protection, instructions) = match &self.body {
+            SheetBody::Wrapped(_) => (
+                "TITANCORE RECOVERY KIT".to_string(),
+                "wrapped under a 32-byte key kept apart from this sheet".to_string(),
+                "Seal this sheet in an envelope. It is useless without the wrapping\n\
+                 key; never store the two together.\n\n\
+                 To restore, type or scan the armored block below, check that it\n\
+                 gives the verification code above, and pass it with the wrapping\n\
+                 key to import_recovery_kit.".to_string(),
+            ),
+            SheetBody::Share(share) => (
+                format!("TITANCORE RECOVERY KIT - SHEET {}", share.index),
+                format!("Shamir share {}; any {} sheets of this kit restore the key", share.index, share.threshold),
+                format!("Seal this sheet in its own envelope for one custodian. Fewer than\n\
+                         {} sheets reveal nothing about the key.\n\n\
+                         To restore, collect {} sheets with this key id, type or scan the\n\
+                         armored block of each, check that each gives the verification\n\
+                         code printed on it, and pass them to import_recovery_kit.", share.threshold, share.threshold),
+            ),
+        };
+        let mut out = Zeroizing::new(String::new());
+        out.push_str(&format!("{}\n\n", title));
+        out.push_str(&format!("Key id:            {}\n", groups[..4].join(" ")));
+        out.push_str(&format!("                   {}\n", groups[4..].join(" ")));
+        out.push_str(&format!("Key type:          {}\n", self.key_type.describe()));
+        out.push_str(&format!("Created:           {}\n", rfc3339_millis(self.created_ms)));
+        out.push_str(&format!("Protection:        {}\n", protection));
+        out.push_str(&format!("Verification code: {}\n\n", self.verification_code()));
+        out.push_str(&instructions);
+        out.push_str("\n\n");
+        out.push_str(&armor::armor(ArmorKind::RecoveryKit, &self.to_bytes()));
+        out
+    }
+
+    /// Reads a sheet from its printed text, or just its armored block. If
+    /// the text has a `Verification code:` line, the sheet must match it.
+    pub fn from_text(text: &str) -> CoreResult<Self> {
+        let bytes = Zeroizing::new(armor::dearmor_as(ArmorKind::RecoveryKit, text.as_bytes())?);
+        let sheet = KitSheet::from_bytes(&bytes)?;
+        let printed = text.lines().find_map(|l| l.trim().strip_prefix("Verification code:")).map(str::trim);
+        if printed.is_some_and(|code| !code.eq_ignore_ascii_case(&sheet.verification_code())) {
+            return Err(CoreError::Format("recovery kit sheet does not match its verification code"));
+        }
+        Ok(sheet)
+    }
+}
+
+/// The sheets protecting one secret key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryKit {
+    pub sheets: Vec<KitSheet>,
+}
+
+impl RecoveryKit {
+    /// A kit for the keypair `public_key`/`secret_key`, whose type is told
+    /// by the public key's length. Fails with [`CoreError::InvalidKey`]
+    /// unless the halves belong together.
+    pub fn create(public_key: &[u8], secret_key: &[u8], protection: KitProtection<'_>, now_ms: u64) -> CoreResult<Self> {
+        let key_type = KitKeyType::of(public_key).ok_or(CoreError::InvalidKey)?;
+        key_type.check_pair(public_key, secret_key)?;
+        let sheet = |body| KitSheet { key_type, public_key: public_key.to_vec(), created_ms: now_ms, body };
+        let sheets = match protection {
+            KitProtection::Wrapped(wrapping_key) => {
+                let mut unsealed = sheet(SheetBody::Wrapped(Vec::new()));
+                let header = unsealed.header();
+                let key = wrap_key(wrapping_key, public_key)?;
+                let mut nonce = [0u8; 12];
+                entropy::fill_hedged(&mut nonce, key.as_ref(), secret_key);
+                let mut sealed = nonce.to_vec();
+                sealed.extend_from_slice(&crypto::aead_seal_aad(&key, &nonce, secret_key, &header)?);
+                unsealed.body = SheetBody::Wrapped(sealed);
+                vec![unsealed]
+            }
+            KitProtection::Shares { threshold, custodians } => escrow::split_secret(key_id(public_key), secret_key, threshold, custodians)?
+                .into_iter().map(|share| sheet(SheetBody::Share(share))).collect(),
+        };
+        Ok(RecoveryKit { sheets })
+    }
+
+    pub fn key_id(&self) -> [u8; 32] {
+        self.sheets.first().map_or([0u8; 32], KitSheet::key_id)
+    }
+
+    /// Each sheet's printable page.
+    pub fn to_text(&self) -> Vec<Zeroizing<String>> {
+        self.sheets.iter().map(KitSheet::to_text).collect()
+    }
+
+    /// The key type and keypair the sheets restore: one wrapped sheet and
+    /// its `wrapping_key`, or at least the threshold of share sheets for
+    /// the same key. Fails with [`CoreError::Decryption`] under the wrong
+    /// wrapping key and [`CoreError::InvalidKey`] if the shares do not
+    /// rebuild the key.
+    pub fn restore(sheets: &[KitSheet], wrapping_key: Option<&[u8; 32]>) -> CoreResult<(KitKeyType, Vec<u8>, Zeroizing<Vec<u8>>)> {
+        let first = sheets.first().ok_or(CoreError::Config("no recovery kit sheets".into()))?;
+        if sheets.iter().any(|s| s.public_key != first.public_key || s.key_type != first.key_type) {
+            return Err(CoreError::Config("recovery kit sheets belong to different keys".into()));
+        }
+        let secret_key = match &first.body {
+            SheetBody::Wrapped(sealed) => {
+                let wrapping_key = wrapping_key.ok_or(CoreError::Config("a wrapped recovery kit needs its wrapping key".into()))?;
+                let (nonce, ct) = sealed.split_at(12);
+                let nonce: [u8; 12] = nonce.try_into().expect("12 bytes");
+                let key = wrap_key(wrapping_key, &first.public_key)?;
+                Zeroizing::new(crypto::aead_open_aad(&key, &nonce, ct, &first.header())?)
+            }
+            SheetBody::Share(_) => {
+                let shares = sheets.iter().map(|s| match &s.body {
+                    SheetBody::Share(share) => Ok(share.clone()),
+                    SheetBody::Wrapped(_) => Err(CoreError::Config("recovery kit mixes wrapped and share sheets".into())),
+                }).collect::<CoreResult<Vec<_>>>()?;
+                escrow::combine_shares(&shares)?
+            }
+        };
+        first.key_type.check_pair(&first.public_key, &secret_key)?;
+        Ok((first.key_type, first.public_key.clone(), secret_key))
+    }
+}
+
+// HKDF-SHA256 over the wrapping key, salted with the key id.
+fn wrap_key(wrapping_key: &[u8; 32], public_key: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Kdf::HkdfSha256.derive(wrapping_key, &key_id(public_key), WRAP_INFO, &mut key[..])?;
+    Ok(key)
+}
+
+impl Engine {
+    /// A recovery kit for the secret key this engine holds under
+    /// `key_id`, i.e. its identity (checkpoint signing) key. Needs a
+    /// [`SensitiveOp::KeyExport`] grant under step-up; recorded as a
+    /// `keygen` event bound to the public key.
+    pub fn export_recovery_kit(&self, key_id: &[u8; 32], protection: KitProtection<'_>) -> CoreResult<RecoveryKit> {
+        self.ensure_open()?;
+        let (public_key, secret_key) = &self.signing_key;
+        if crate::cert::key_id(public_key) != *key_id {
+            return Err(CoreError::Config(format!("this engine holds no secret key with id {}", hex::encode(key_id))));
+        }
+        let res = self.consume_step_up(SensitiveOp::KeyExport);
+        self.audited(OpType::Keygen, public_key, res)?;
+        let kit = self.audited(OpType::Keygen, public_key, RecoveryKit::create(public_key, &secret_key.unwrapped(), protection, self.clock().now_ms()))?;
+        self.record_event(OpType::Keygen, Outcome::Success, public_key)?;
+        Ok(kit)
+    }
+}
@@ -0,0 +1,241 @@
+//! Signed revocation records for recipient and signing keys, and the cached
+//! status lookups an engine makes before encrypting to a recipient or
+//! trusting a checkpoint signature.
+//!
+//! Keys are named by [`key_id`](crate::cert::key_id). A record must be
+//! signed by one of the checker's trusted issuers (typically the CA that
+//! certified the key); records from anyone else are ignored. Revocation is
+//! permanent, so revoked answers are cached for good and only "good" answers
+//! expire.
+
+use crate::cert::key_id;
+use crate::clock::Clock;
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const REVOCATION_MAGIC: &[u8; 4] = b"TCRV";
+pub const REVOCATION_VERSION: u8 = 1;
+/// How long a "good" answer is reused before the source is asked again.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RevocationReason {
+    #[default]
+    Unspecified,
+    KeyCompromise,
+    Superseded,
+    CessationOfOperation,
+}
+
+impl RevocationReason {
+    const ALL: [RevocationReason; 4] = [
+        RevocationReason::Unspecified, RevocationReason::KeyCompromise,
+        RevocationReason::Superseded, RevocationReason::CessationOfOperation,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RevocationReason::Unspecified => "unspecified",
+            RevocationReason::KeyCompromise => "key-compromise",
+            RevocationReason::Superseded => "superseded",
+            RevocationReason::CessationOfOperation => "cessation-of-operation",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<RevocationReason> {
+        Self::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<RevocationReason> {
+        Self::ALL.get(code as usize).copied()
+    }
+}
+
+/// "Key `key_id` is revoked from `revoked_at` (Unix seconds)".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revocation {
+    pub key_id: [u8; 32],
+    pub revoked_at: u64,
+    pub reason: RevocationReason,
+}
+
+impl Revocation {
+    /// `magic(4) | version(1) | key_id(32) | revoked_at(8) | reason(1) | issuer(32)`
+    fn body(&self, issuer: &[u8; 32]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(78);
+        out.extend_from_slice(REVOCATION_MAGIC);
+        out.push(REVOCATION_VERSION);
+        out.extend_from_slice(&self.key_id);
+        out.extend_from_slice(&self.revoked_at.to_be_bytes());
+        out.push(self.reason.code());
+        out.extend_from_slice(issuer);
+        out
+    }
+
+    pub fn sign(self, issuer_public_key: &[u8], issuer_secret_key: &[u8]) -> CoreResult<SignedRevocation> {
+        let issuer = key_id(issuer_public_key);
+        let signature = crypto::sign(issuer_secret_key, &self.body(&issuer))?;
+        Ok(SignedRevocation { revocation: self, issuer, signature })
+    }
+}
+
+/// A [`Revocation`] signed with an issuer's Dilithium5 key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRevocation {
+    pub revocation: Revocation,
+    /// [`key_id`] of the signing key.
+    pub issuer: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl SignedRevocation {
+    /// `body | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.revocation.body(&self.issuer);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != REVOCATION_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != REVOCATION_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let key_id = r.array()?;
+        let revoked_at = u64::from_be_bytes(r.array()?);
+        let reason = RevocationReason::from_code(r.take(1)?[0]).ok_or(CoreError::Format("bad revocation reason"))?;
+        let issuer = r.array()?;
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("revocation without signature"));
+        }
+        Ok(SignedRevocation { revocation: Revocation { key_id, revoked_at, reason }, issuer, signature: r.buf.to_vec() })
+    }
+
+    /// True if `issuer_pk` is the named issuer and its signature is valid.
+    pub fn verify(&self, issuer_pk: &[u8]) -> bool {
+        key_id(issuer_pk) == self.issuer
+            && crypto::verify_signature(issuer_pk, &self.revocation.body(&self.issuer), &self.signature)
+    }
+}
+
+/// Where revocation records come from: a local list, a CRL download, an
+/// OCSP-like service.
+pub trait RevocationSource: Send + Sync {
+    /// Records naming `key_id`, or none. Unsigned or foreign records are
+    /// filtered out by the caller.
+    fn lookup(&self, key_id: &[u8; 32]) -> CoreResult<Vec<SignedRevocation>>;
+}
+
+/// In-memory [`RevocationSource`].
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    records: Mutex<HashMap<[u8; 32], Vec<SignedRevocation>>>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, record: SignedRevocation) {
+        self.records.lock().entry(record.revocation.key_id).or_default().push(record);
+    }
+}
+
+impl RevocationSource for RevocationList {
+    fn lookup(&self, key_id: &[u8; 32]) -> CoreResult<Vec<SignedRevocation>> {
+        Ok(self.records.lock().get(key_id).cloned().unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    Good,
+    Revoked { revoked_at: u64, reason: RevocationReason },
+}
+
+struct CacheEntry {
+    fetched: Duration,
+    revocation: Option<Revocation>,
+}
+
+/// Answers [`KeyStatus`] queries from a [`RevocationSource`], keeping only
+/// records signed by `trusted_issuers`. A failing source is an error, never
+/// "good".
+pub struct RevocationChecker {
+    source: Box<dyn RevocationSource>,
+    trusted_issuers: Vec<Vec<u8>>,
+    ttl: Duration,
+    cache: Mutex<HashMap<[u8; 32], CacheEntry>>,
+}
+
+impl RevocationChecker {
+    pub fn new(source: Box<dyn RevocationSource>, trusted_issuers: Vec<Vec<u8>>, ttl: Duration) -> Self {
+        RevocationChecker { source, trusted_issuers, ttl, cache: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn status(&self, key_id: &[u8; 32], clock: &dyn Clock) -> CoreResult<KeyStatus> {
+        let now = clock.monotonic();
+        let cached = self.cache.lock().get(key_id).and_then(|entry| {
+            let fresh = entry.revocation.is_some() || now.saturating_sub(entry.fetched) < self.ttl;
+            fresh.then(|| entry.revocation.clone())
+        });
+        let revocation = match cached {
+            Some(revocation) => revocation,
+            None => {
+                let revocation = self.fetch(key_id)?;
+                self.cache.lock().insert(*key_id, CacheEntry { fetched: now, revocation: revocation.clone() });
+                revocation
+            }
+        };
+        Ok(match revocation {
+            Some(r) if clock.now_ms() / 1000 >= r.revoked_at => KeyStatus::Revoked { revoked_at: r.revoked_at, reason: r.reason },
+            _ => KeyStatus::Good,
+        })
+    }
+
+    // Earliest trusted revocation of `key_id`.
+    fn fetch(&self, key_id: &[u8; 32]) -> CoreResult<Option<Revocation>> {
+        let records = self.source.lookup(key_id)?;
+        Ok(records.into_iter()
+            .filter(|r| r.revocation.key_id == *key_id && self.trusted_issuers.iter().any(|pk| r.verify(pk)))
+            .map(|r| r.revocation)
+            .min_by_key(|r| r.revoked_at))
+    }
+
+    /// Drops every cached answer.
+    pub fn clear_cache(&self) {
+        self.cache.lock().clear();
+    }
+}
+
+impl Engine {
+    /// Status of the key with `key_id`; always [`KeyStatus::Good`] without a
+    /// checker.
+    pub fn check_key_status(&self, key_id: &[u8; 32]) -> CoreResult<KeyStatus> {
+        match &self.revocation {
+            Some(checker) => checker.status(key_id, self.clock()),
+            None => Ok(KeyStatus::Good),
+        }
+    }
+
+    /// Fails with [`CoreError::Revoked`] if `public_key` is revoked.
+    pub(crate) fn ensure_not_revoked(&self, public_key: &[u8]) -> CoreResult<()> {
+        match self.check_key_status(&key_id(public_key))? {
+            KeyStatus::Good => Ok(()),
+            KeyStatus::Revoked { .. } => Err(CoreError::Revoked),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Key rotation for stored ciphertext.
+//!
+//! A session key is derived from the KEM shared secret together with the
+//! sealing engine's fingerprint and counter, so there is no data key that
+//! could be rewrapped on its own. [`Engine::rewrap`] decrypts with the old
+//! secret key and seals the plaintext afresh to the new public key, in
+//! memory, under this engine's fingerprint, counter, suite, KDF, KEM and
+//! escrow key; the context and record schema are kept. Restricted
+//! envelopes fail with [`CoreError::Unauthorized`](crate::CoreError::Unauthorized):
+//! open them through a quorum and seal again. Envelopes sealed in an epoch
+//! fail with [`CoreError::Config`](crate::CoreError::Config): open them
+//! with [`Engine::open_epoch`] and seal again.
+//!
+//! Each rotated object is recorded as a `rekey` event bound to its old KEM
+//! ciphertext, followed by the `encrypt` entry of its replacement, and a
+//! checkpoint signed over them is returned so the rotation can be shown to
+//! an auditor. [`Engine::rewrap_many`] and [`Engine::rewrap_dir`] sign one
+//! checkpoint after the last object and count as one request against the
+//! rate limit.
+
+use crate::audit::checkpoint::SignedCheckpoint;
+use crate::audit::{OpType, Outcome};
+use crate::cert;
+use crate::engine::Engine;
+use crate::envelope::{Envelope, TAG_LEN};
+use crate::error::CoreResult;
+use crate::kdf::Marks;
+use crate::kem;
+use crate::operation;
+use crate::rbac::Permission;
+use zeroize::Zeroizing;
+#[cfg(feature = "fs")]
+use crate::error::CoreError;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+/// Outcome of [`Engine::rewrap_dir`]. Paths are relative to the directory.
+#[cfg(feature = "fs")]
+#[derive(Debug)]
+pub struct RewrapReport {
+    pub rotated: Vec<PathBuf>,
+    /// Objects left as they were, with the reason.
+    pub failed: Vec<(PathBuf, CoreError)>,
+    /// Files that are neither envelopes nor streams.
+    pub skipped: Vec<PathBuf>,
+    pub checkpoint: SignedCheckpoint,
+}
+
+impl Engine {
+    /// Re-encrypts `envelope` from the holder of `old_sk` to `new_pk`.
+    /// Returns the new envelope and a checkpoint signed over the rotation.
+    /// A failure is recorded as a `rekey` event bound to the old KEM
+    /// ciphertext.
+    pub fn rewrap(&self, envelope: &Envelope, old_sk: &[u8], new_pk: &[u8], context: &[u8]) -> CoreResult<(Envelope, SignedCheckpoint)> {
+        let res = self.try_rewrap(envelope, old_sk, new_pk, context);
+        self.audited(OpType::Rekey, &envelope.kem_ct, res)
+    }
+
+    fn try_rewrap(&self, envelope: &Envelope, old_sk: &[u8], new_pk: &[u8], context: &[u8]) -> CoreResult<(Envelope, SignedCheckpoint)> {
+        self.permit(Permission::Decrypt)?;
+        self.check_rate_limit()?;
+        self.envelope_recipient(new_pk, plaintext_len(envelope))?;
+        let ctr = self.next_counters(1)?;
+        let sealed = self.install(|| self.reseal(ctr, envelope, old_sk, new_pk, context))?;
+        let rotated = self.record_rewrap(envelope, sealed, new_pk)?;
+        Ok((rotated, self.checkpoint()?))
+    }
+
+    /// [`Engine::rewrap`] for every envelope, on the worker pool. Results
+    /// and audit entries keep the input order; an envelope that fails is
+    /// recorded and left out of the rotation. Only a malformed key fails
+    /// the whole batch.
+    pub fn rewrap_many(&self, envelopes: &[Envelope], old_sk: &[u8], new_pk: &[u8], context: &[u8])
+                       -> CoreResult<(Vec<CoreResult<Envelope>>, SignedCheckpoint)> {
+        let _op = operation::implicit();
+        let res = self.try_rewrap_many(envelopes, old_sk, new_pk, context);
+        self.audited(OpType::Rekey, &[], res)
+    }
+
+    fn try_rewrap_many(&self, envelopes: &[Envelope], old_sk: &[u8], new_pk: &[u8], context: &[u8])
+                       -> CoreResult<(Vec<CoreResult<Envelope>>, SignedCheckpoint)> {
+        self.permit(Permission::Decrypt)?;
+        kem::check_secret_key(old_sk)?;
+        self.check_rate_limit()?;
+        self.envelope_recipient(new_pk, envelopes.iter().map(plaintext_len).sum())?;
+        let base_ctr = self.next_counters(envelopes.len() as u64)?;
+
+        let sealed = self.par_map(envelopes, |i, envelope| self.reseal(base_ctr + i as u64, envelope, old_sk, new_pk, context));
+        let rotated = sealed.into_iter().zip(envelopes).map(|(res, envelope)| {
+            let sealed = self.audited(OpType::Rekey, &envelope.kem_ct, res)?;
+            self.audited(OpType::Rekey, &envelope.kem_ct, self.record_rewrap(envelope, sealed, new_pk))
+        }).collect();
+        Ok((rotated, self.checkpoint()?))
+    }
+
+    // Decrypts `envelope` and seals its plaintext to `pk` under counter `ctr`.
+    fn reseal(&self, ctr: u64, envelope: &Envelope, old_sk: &[u8], pk: &[u8], context: &[u8])
+              -> CoreResult<(Envelope, Option<[u8; 32]>)> {
+        self.check_constraints(envelope)?;
+        let plaintext = Zeroizing::new(envelope.open_unconstrained(old_sk, context)?);
+        let marks = Marks { schema: envelope.kdf.schema, constraints: envelope.kdf.constraints.as_ref(), ..Marks::default() };
+        self.seal_one(ctr, pk, &plaintext, context, marks)
+    }
+
+    // The `rekey` event for `old`, then the `encrypt` entry for its replacement to `new_pk`.
+    fn record_rewrap(&self, old: &Envelope, (rotated, digest): (Envelope, Option<[u8; 32]>), new_pk: &[u8]) -> CoreResult<Envelope> {
+        self.record_event(OpType::Rekey, Outcome::Success, &old.kem_ct)?;
+        let bound = digest.as_ref().map_or(&rotated.ciphertext[..], |d| &d[..]);
+        self.install(|| self.append_to_audit(rotated.counter, &cert::key_id(new_pk), &rotated.nonce, bound, &rotated.kem_ct))?;
+        Ok(rotated)
+    }
+}
+
+// What resealing `envelope` protects, counted against the new key.
+fn plaintext_len(envelope: &Envelope) -> u64 {
+    envelope.ciphertext.len().saturating_sub(TAG_LEN) as u64
+}
+
+#[cfg(feature = "fs")]
+impl Engine {
+    /// Rotates every native envelope and chunked stream under `dir`
+    /// (recursively, in path order) to `new_pk`, in place. Each object is
+    /// written beside the original and renamed over it, so a failure leaves
+    /// the old object intact. Streams keep their chunk size and framing;
+    /// their plaintext passes through an
+    /// [`crate::tempfile::EncryptedTempFile`]. Streams sealed with
+    /// [`crate::stream::StreamOptions::aad`] fail to open and are reported,
+    /// as do streams, which stay on Kyber-1024, when the engine seals
+    /// envelopes under another KEM.
+    /// Armored and other files are skipped. A file that cannot be read,
+    /// opened or replaced is reported and the run goes on. An
+    /// [`Engine::encrypt_tree`] manifest no longer matches its objects
+    /// afterwards.
+    pub fn rewrap_dir(&self, dir: impl AsRef<Path>, old_sk: &[u8], new_pk: &[u8], context: &[u8]) -> CoreResult<RewrapReport> {
+        let _op = operation::implicit();
+        let res = self.try_rewrap_dir(dir.as_ref(), old_sk, new_pk, context);
+        self.audited(OpType::Rekey, &[], res)
+    }
+
+    fn try_rewrap_dir(&self, dir: &Path, old_sk: &[u8], new_pk: &[u8], context: &[u8]) -> CoreResult<RewrapReport> {
+        use crate::envelope::ENVELOPE_MAGIC;
+        use crate::stream::STREAM_MAGIC;
+        use std::fs;
+        use std::io::Read;
+
+        self.permit(Permission::Decrypt)?;
+        kem::check_secret_key(old_sk)?;
+        self.check_rate_limit()?;
+        self.envelope_recipient(new_pk, 0)?;
+        let mut files = Vec::new();
+        crate::tree::collect_files(dir, &fs::canonicalize(dir)?, &mut files)?;
+
+        let (mut rotated, mut failed, mut skipped) = (Vec::new(), Vec::new(), Vec::new());
+        for file in files {
+            let rel = file.strip_prefix(dir).unwrap_or(&file).to_path_buf();
+            let mut magic = [0u8; 4];
+            let res = fs::File::open(&file).and_then(|f| f.take(4).read(&mut magic)).map_err(CoreError::from).and_then(|n| {
+                match &magic[..n] {
+                    m if m == ENVELOPE_MAGIC => self.rewrap_envelope_file(&file, old_sk, new_pk, context).map(Some),
+                    m if m == STREAM_MAGIC => self.rewrap_stream_file(&file, old_sk, new_pk, context).map(Some),
+                    _ => Ok(None),
+                }
+            });
+            match res {
+                Ok(Some(())) => rotated.push(rel),
+                Ok(None) => skipped.push(rel),
+                Err(e) => failed.push((rel, e)),
+            }
+        }
+        Ok(RewrapReport { rotated, failed, skipped, checkpoint: self.checkpoint()? })
+    }
+
+    fn rewrap_envelope_file(&self, path: &Path, old_sk: &[u8], pk: &[u8], context: &[u8]) -> CoreResult<()> {
+        let res = Envelope::from_bytes(&std::fs::read(path)?);
+        let envelope = self.audited(OpType::Rekey, &[], res)?;
+        let res = self.next_counters(1).and_then(|ctr| self.install(|| self.reseal(ctr, &envelope, old_sk, pk, context)));
+        let sealed = self.audited(OpType::Rekey, &envelope.kem_ct, res)?;
+        let rotated = self.audited(OpType::Rekey, &envelope.kem_ct, self.record_rewrap(&envelope, sealed, pk))?;
+        replace_file(path, |w| Ok(std::io::Write::write_all(w, &rotated.to_bytes())?))
+    }
+
+    fn rewrap_stream_file(&self, path: &Path, old_sk: &[u8], new_pk: &[u8], context: &[u8]) -> CoreResult<()> {
+        use crate::stream::{StreamHeader, StreamOptions};
+        use crate::tempfile::EncryptedTempFile;
+        use std::io::{self, Seek, SeekFrom};
+
+        let mut reader = io::BufReader::new(std::fs::File::open(path)?);
+        let header = self.audited(OpType::Rekey, &[], StreamHeader::read_from(&mut reader))?;
+        let res = EncryptedTempFile::new().and_then(|mut plain| {
+            self.open_chunks(&header, reader, &mut plain, old_sk, &[], context)?;
+            plain.seek(SeekFrom::Start(0))?;
+            Ok(plain)
+        });
+        let mut plain = self.audited(OpType::Rekey, &header.kem_ct, res)?;
+        self.record_event(OpType::Rekey, Outcome::Success, &header.kem_ct)?;
+        let res = replace_file(path, |w| {
+            let options = StreamOptions { chunk_size: header.chunk_size as usize, framing: header.framing, aad: Vec::new() };
+            self.try_seal_stream(&mut plain, w, new_pk, &options, context, false).map(drop)
+        });
+        self.audited(OpType::Rekey, &header.kem_ct, res)
+    }
+}
+
+// Writes the new contents of `path` to a sibling file and renames it over
+// `path` once complete.
+#[cfg(feature = "fs")]
+fn replace_file(path: &Path, write: impl FnOnce(&mut std::io::BufWriter<std::fs::File>) -> CoreResult<()>) -> CoreResult<()> {
+    let name = path.file_name().ok_or_else(|| CoreError::Config(format!("not a file: {}", path.display())))?;
+    let tmp = path.with_file_name(format!(".{}.rewrap", name.to_string_lossy()));
+    let res = std::fs::File::create(&tmp).map_err(CoreError::from).and_then(|file| {
+        let mut writer = std::io::BufWriter::new(file);
+        write(&mut writer)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(())
+    });
+    match res.and_then(|()| Ok(std::fs::rename(&tmp, path)?)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
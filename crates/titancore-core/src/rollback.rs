@@ -0,0 +1,249 @@
+//! Anti-rollback for the operation counter and chain head.
+//!
+//! An engine resumes its counter and chain head from the audit sink, so a
+//! log restored from an old backup, or an old [`EngineState`], would rewind
+//! them and let counter nonces repeat. With [`EngineConfig::high_water`]
+//! set, the engine keeps a [`HighWaterMark`] (sequence number, counter
+//! and head of the chain) signed with its identity key in a
+//! [`HighWaterStore`]. On start, a chain behind the mark fails with
+//! [`CoreError::Storage`]: one that ends before the mark's entry, has a
+//! lower counter, or reaches the same entry with another head.
+//!
+//! The mark only ever names entries that are durable, or a crash would
+//! leave the log behind it. Over a sink that syncs every entry before
+//! `append` returns ([`AuditSink::durable`]), it moves after each entry;
+//! otherwise (a `Buffered` or `Periodic` file sink, or
+//! [`EngineConfig::audit_queue`]) only on [`Engine::flush_audit`] and
+//! [`Engine::close`], and a rollback of the entries since goes unnoticed.
+//! The mark is signed and stored outside the chain lock, and entries
+//! appended while one is being stored share the next.
+//!
+//! [`EngineConfig::allow_rollback`] starts the engine anyway. The counter
+//! then moves past the mark's, and the override is recorded as an
+//! `approval` event whose subject is the mark.
+//!
+//! The store must be kept out of the backups it guards against:
+//! [`FileHighWaterStore`] on another volume, or a store over a TPM NV
+//! index. A missing mark passes, so deleting it defeats the check. A
+//! persistent [`EngineConfig::identity`] is required, or no mark could be
+//! verified after a restart.
+//!
+//! [`EngineState`]: crate::EngineState
+//! [`AuditSink::durable`]: crate::AuditSink::durable
+//! [`EngineConfig::audit_queue`]: crate::EngineConfig::audit_queue
+//! [`EngineConfig::high_water`]: crate::EngineConfig::high_water
+//! [`EngineConfig::allow_rollback`]: crate::EngineConfig::allow_rollback
+//! [`EngineConfig::identity`]: crate::EngineConfig::identity
+
+use crate::audit::{OpType, Outcome};
+use crate::crypto;
+use crate::engine::{Engine, OPERATION_CTR};
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use std::fmt;
+use std::sync::atomic::Ordering;
+
+pub const HIGH_WATER_MAGIC: &[u8; 4] = b"TCHW";
+pub const HIGH_WATER_VERSION: u8 = 1;
+
+/// The furthest point an engine's chain is known to have reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighWaterMark {
+    pub fingerprint: [u8; 32],
+    pub seq: u64,
+    pub counter: u64,
+    pub head: [u8; 32],
+    pub timestamp_ms: u64,
+}
+
+impl HighWaterMark {
+    /// `magic(4) | version(1) | fingerprint(32) | seq(8) | counter(8) | head(32) | timestamp_ms(8)`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(93);
+        out.extend_from_slice(HIGH_WATER_MAGIC);
+        out.push(HIGH_WATER_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.seq.to_be_bytes());
+        out.extend_from_slice(&self.counter.to_be_bytes());
+        out.extend_from_slice(&self.head);
+        out.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != HIGH_WATER_MAGIC {
+            return Err(CoreError::Format("bad high-water mark magic"));
+        }
+        if r.take(1)?[0] != HIGH_WATER_VERSION {
+            return Err(CoreError::Format("unsupported high-water mark version"));
+        }
+        let fingerprint = r.array()?;
+        let seq = u64::from_be_bytes(r.array()?);
+        let counter = u64::from_be_bytes(r.array()?);
+        let head = r.array()?;
+        let timestamp_ms = u64::from_be_bytes(r.array()?);
+        Ok(HighWaterMark { fingerprint, seq, counter, head, timestamp_ms })
+    }
+
+    /// True if a chain ending at entry `seq` with `head`, whose highest
+    /// counter is `counter`, is behind this mark.
+    pub fn is_ahead_of(&self, seq: u64, counter: u64, head: &[u8; 32]) -> bool {
+        seq < self.seq || counter < self.counter || (seq == self.seq && *head != self.head)
+    }
+
+    pub fn sign(self, secret_key: &[u8]) -> CoreResult<SignedHighWaterMark> {
+        let signature = crypto::sign(secret_key, &self.to_bytes())?;
+        Ok(SignedHighWaterMark { mark: self, signature })
+    }
+}
+
+/// A [`HighWaterMark`] with the engine's Dilithium5 signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedHighWaterMark {
+    pub mark: HighWaterMark,
+    pub signature: Vec<u8>,
+}
+
+impl SignedHighWaterMark {
+    /// `body | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.mark.to_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let mark = HighWaterMark::read(&mut r)?;
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("high-water mark without signature"));
+        }
+        Ok(SignedHighWaterMark { mark, signature: r.buf.to_vec() })
+    }
+
+    pub fn verify(&self, public_key: &[u8]) -> bool {
+        crypto::verify_signature(public_key, &self.mark.to_bytes(), &self.signature)
+    }
+}
+
+/// Where the high-water mark is kept.
+pub trait HighWaterStore: Send + Sync {
+    /// The latest mark stored; `None` if there is none yet.
+    fn load(&self) -> CoreResult<Option<SignedHighWaterMark>>;
+
+    /// Replaces the mark with a later one. Calls are serialized.
+    fn store(&self, mark: &SignedHighWaterMark) -> CoreResult<()>;
+}
+
+impl fmt::Debug for dyn HighWaterStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HighWaterStore")
+    }
+}
+
+/// The mark in a file, rewritten and synced each time the mark moves and
+/// replaced atomically, with the directory synced after the rename. One
+/// engine should own the file.
+#[cfg(feature = "fs")]
+pub struct FileHighWaterStore {
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "fs")]
+impl FileHighWaterStore {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        FileHighWaterStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "fs")]
+impl HighWaterStore for FileHighWaterStore {
+    fn load(&self) -> CoreResult<Option<SignedHighWaterMark>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => SignedHighWaterMark::from_bytes(&bytes).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(&self, mark: &SignedHighWaterMark) -> CoreResult<()> {
+        use std::io::Write;
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&mark.to_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        crate::audit::sync_parent_dir(&self.path)
+    }
+}
+
+impl Engine {
+    /// Stores a mark for the chain head if it moved since the last one.
+    /// Entries other threads append meanwhile wait for the next mark; those
+    /// that find the head already marked skip it. Over a sink that is not
+    /// durable on append, flushes after reading the head, so the mark never
+    /// names an entry the flush did not cover.
+    pub(crate) fn advance_high_water(&self) -> CoreResult<()> {
+        let Some(store) = &self.high_water else { return Ok(()) };
+        let mut marked = self.high_water_seq.lock();
+        let mark = {
+            let chain = self.chain.lock();
+            HighWaterMark { fingerprint: self.fingerprint, seq: chain.seq, counter: chain.counter, head: chain.head, timestamp_ms: chain.last_ms }
+        };
+        if mark.seq <= *marked {
+            return Ok(());
+        }
+        if !self.sink.durable() {
+            self.sink.flush()?;
+        }
+        let seq = mark.seq;
+        store.store(&mark.sign(&self.signing_key.1.unwrapped())?)?;
+        *marked = seq;
+        Ok(())
+    }
+
+    /// Checks the chain against the stored mark before anything is
+    /// appended. Returns the mark if a rollback was let through by
+    /// [`EngineConfig::allow_rollback`](crate::EngineConfig::allow_rollback),
+    /// with the counter already moved past it.
+    pub(crate) fn check_rollback(&self) -> CoreResult<Option<SignedHighWaterMark>> {
+        let Some(store) = &self.high_water else { return Ok(None) };
+        let Some(signed) = store.load()? else { return Ok(None) };
+        let mark = &signed.mark;
+        let rolled_back = {
+            let chain = self.chain.lock();
+            if mark.fingerprint != self.fingerprint {
+                Some("high-water mark belongs to another engine".to_string())
+            } else if !signed.verify(&self.signing_key.0) {
+                Some("high-water mark has a bad signature".to_string())
+            } else if mark.is_ahead_of(chain.seq, chain.counter, &chain.head) {
+                Some(format!("audit log rolled back: at seq {} (counter {}), high-water mark at seq {} (counter {})",
+                             chain.seq, chain.counter, mark.seq, mark.counter))
+            } else {
+                None
+            }
+        };
+        match rolled_back {
+            None => Ok(None),
+            Some(why) if !self.allow_rollback => Err(CoreError::Storage(why)),
+            Some(_) => {
+                OPERATION_CTR.fetch_max(mark.counter, Ordering::Relaxed);
+                let mut chain = self.chain.lock();
+                chain.counter = chain.counter.max(mark.counter);
+                Ok(Some(signed))
+            }
+        }
+    }
+
+    /// Records a rollback let through by [`Engine::check_rollback`].
+    pub(crate) fn record_rollback_override(&self, overridden: Option<SignedHighWaterMark>) -> CoreResult<()> {
+        match overridden {
+            Some(signed) => self.record_event(OpType::Approval, Outcome::Success, &signed.mark.to_bytes()).map(drop),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Registered record schemas.
+//!
+//! A [`RecordSchema`] describes a JSON record: its fields, each with a
+//! [`FieldType`], whether it is required, an optional [`Protection`] and,
+//! for encrypted fields, the [`key_id`] of the key it is sealed to. Its
+//! [`RecordSchema::id`] hashes all of that, so each version has its own ID.
+//! Applications register schemas in a [`SchemaRegistry`].
+//!
+//! [`Engine::seal_record`] checks a record against its schema, protects its
+//! fields as [`Engine::apply_policy`] would (each under its normalized path,
+//! e.g. `$['ssn']`) and seals it with the schema ID in the envelope, bound
+//! into the session key. [`Engine::open_record`] looks the ID up and checks
+//! that what it opens has the schema's fields, types and protections.
+//! Records move to another schema only along a migration the registry
+//! declares, through [`Engine::migrate_record`]; fields that stay protected
+//! the same way keep their protected value, so encrypted fields keep their
+//! names.
+//!
+//! Registries are saved as JSON: `{"version": 1, "schemas": [{"name",
+//! "version", "fields": [{"name", "type", "required", "protection",
+//! "key_id"}]}], "migrations": [[from, to]]}`, IDs in hex.
+//!
+//! [`Engine::apply_policy`]: crate::Engine::apply_policy
+
+use crate::audit::OpType;
+use crate::cert::key_id;
+use crate::document::{self, Protection};
+use crate::engine::Engine;
+use crate::envelope::Envelope;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Marks;
+use crate::operation;
+use serde_json::{json, Map, Value};
+use zeroize::Zeroizing;
+
+pub const REGISTRY_VERSION: u64 = 1;
+pub const SCHEMA_ID_LEN: usize = 16;
+/// Longest schema or field name; names are letters, digits, `_`, `-` and `.`.
+pub const MAX_NAME_LEN: usize = 64;
+
+pub type SchemaId = [u8; SCHEMA_ID_LEN];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Any,
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Object,
+    Array,
+}
+
+impl FieldType {
+    pub const ALL: [FieldType; 7] = [FieldType::Any, FieldType::String, FieldType::Integer, FieldType::Number,
+                                     FieldType::Boolean, FieldType::Object, FieldType::Array];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FieldType::Any => "any",
+            FieldType::String => "string",
+            FieldType::Integer => "integer",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Object => "object",
+            FieldType::Array => "array",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<FieldType> {
+        Self::ALL.into_iter().find(|t| t.as_str() == s)
+    }
+
+    pub fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldType::Any => true,
+            FieldType::String => value.is_string(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Number => value.is_number(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Object => value.is_object(),
+            FieldType::Array => value.is_array(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaField {
+    pub name: String,
+    pub kind: FieldType,
+    pub required: bool,
+    pub protection: Option<Protection>,
+    /// [`key_id`] of the key an encrypted field is sealed to; `None` seals
+    /// it to the record's recipient.
+    pub key_id: Option<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordSchema {
+    pub name: String,
+    pub version: u32,
+    pub fields: Vec<SchemaField>,
+}
+
+fn valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'))
+}
+
+impl RecordSchema {
+    /// BLAKE3 over the name, version and every field, cut to
+    /// [`SCHEMA_ID_LEN`] bytes.
+    pub fn id(&self) -> SchemaId {
+        let mut hasher = blake3::Hasher::new_derive_key("titancore record schema v1");
+        let mut put = |bytes: &[u8]| {
+            hasher.update(&(bytes.len() as u64).to_be_bytes());
+            hasher.update(bytes);
+        };
+        put(self.name.as_bytes());
+        put(&self.version.to_be_bytes());
+        for field in &self.fields {
+            put(field.name.as_bytes());
+            put(field.kind.as_str().as_bytes());
+            put(&[u8::from(field.required)]);
+            put(field.protection.map_or("", Protection::as_str).as_bytes());
+            put(field.key_id.as_ref().map_or(&[][..], |id| &id[..]));
+        }
+        let mut id = [0u8; SCHEMA_ID_LEN];
+        id.copy_from_slice(&hasher.finalize().as_bytes()[..SCHEMA_ID_LEN]);
+        id
+    }
+
+    pub fn field(&self, name: &str) -> Option<&SchemaField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    fn check(&self) -> CoreResult<()> {
+        if !valid_name(&self.name) {
+            return Err(CoreError::Config(format!("bad schema name {:?}", self.name)));
+        }
+        for (i, field) in self.fields.iter().enumerate() {
+            if !valid_name(&field.name) {
+                return Err(CoreError::Config(format!("bad field name {:?}", field.name)));
+            }
+            if self.fields[..i].iter().any(|f| f.name == field.name) {
+                return Err(CoreError::Config(format!("field {} defined twice", field.name)));
+            }
+            if field.key_id.is_some() && field.protection != Some(Protection::Encrypt) {
+                return Err(CoreError::Config(format!("field {} has a key id but is not encrypted", field.name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Why `record` is not a record of this schema in `form`.
+    fn mismatch(&self, record: &Value, form: Form) -> Option<String> {
+        let Some(map) = record.as_object() else { return Some("record is not an object".into()) };
+        if let Some(name) = map.keys().find(|name| self.field(name).is_none()) {
+            return Some(format!("field {} is not in schema {}", name, self.name));
+        }
+        for field in &self.fields {
+            let ok = match (map.get(&field.name), field.protection, form) {
+                (None, _, _) => !field.required,
+                (Some(value), Some(protection), Form::Sealed) => document::is_protected(protection, value),
+                (Some(value), Some(protection), Form::Either) => document::is_protected(protection, value) || field.kind.matches(value),
+                (Some(value), _, _) => field.kind.matches(value),
+            };
+            if !ok {
+                return Some(format!("field {} does not match schema {}", field.name, self.name));
+            }
+        }
+        None
+    }
+}
+
+// Whether protected fields are expected in plain form (about to be sealed),
+// protected form (as sealed) or either (mid-migration).
+#[derive(Clone, Copy)]
+enum Form {
+    Plain,
+    Sealed,
+    Either,
+}
+
+/// Record schemas by ID, and the migrations allowed between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaRegistry {
+    schemas: Vec<(SchemaId, RecordSchema)>,
+    migrations: Vec<(SchemaId, SchemaId)>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `schema` and returns its ID; registering it again is a no-op.
+    /// Fails with [`CoreError::Config`] for a bad name, a repeated field or
+    /// a key ID on an unencrypted field, or if another schema already has
+    /// this name and version.
+    pub fn register(&mut self, schema: RecordSchema) -> CoreResult<SchemaId> {
+        schema.check()?;
+        let id = schema.id();
+        match self.find(&schema.name, schema.version) {
+            Some(existing) if existing.id() == id => return Ok(id),
+            Some(_) => return Err(CoreError::Config(format!("schema {} version {} already registered", schema.name, schema.version))),
+            None => {}
+        }
+        self.schemas.push((id, schema));
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &SchemaId) -> Option<&RecordSchema> {
+        self.schemas.iter().find(|(i, _)| i == id).map(|(_, s)| s)
+    }
+
+    pub fn find(&self, name: &str, version: u32) -> Option<&RecordSchema> {
+        self.schemas.iter().map(|(_, s)| s).find(|s| s.name == name && s.version == version)
+    }
+
+    /// The highest version registered under `name`.
+    pub fn latest(&self, name: &str) -> Option<&RecordSchema> {
+        self.schemas.iter().map(|(_, s)| s).filter(|s| s.name == name).max_by_key(|s| s.version)
+    }
+
+    /// Schemas in the order registered.
+    pub fn schemas(&self) -> impl Iterator<Item = &RecordSchema> {
+        self.schemas.iter().map(|(_, s)| s)
+    }
+
+    /// Allows records of `from` to move to `to`, a later version of the
+    /// same schema. Fails with [`CoreError::Config`] otherwise or if either
+    /// is not registered.
+    pub fn add_migration(&mut self, from: &SchemaId, to: &SchemaId) -> CoreResult<()> {
+        let (Some(a), Some(b)) = (self.get(from), self.get(to)) else {
+            return Err(CoreError::Config("migration between unregistered schemas".into()));
+        };
+        if a.name != b.name || a.version >= b.version {
+            return Err(CoreError::Config(format!("cannot migrate {} v{} to {} v{}", a.name, a.version, b.name, b.version)));
+        }
+        if !self.allows_migration(from, to) {
+            self.migrations.push((*from, *to));
+        }
+        Ok(())
+    }
+
+    pub fn allows_migration(&self, from: &SchemaId, to: &SchemaId) -> bool {
+        self.migrations.contains(&(*from, *to))
+    }
+
+    fn schema(&self, id: &SchemaId) -> CoreResult<&RecordSchema> {
+        self.get(id).ok_or_else(|| CoreError::Config(format!("schema {} not registered", hex::encode(id))))
+    }
+
+    pub fn to_json(&self) -> String {
+        let schemas: Vec<Value> = self.schemas().map(|s| json!({
+            "name": s.name,
+            "version": s.version,
+            "fields": s.fields.iter().map(|f| json!({
+                "name": f.name,
+                "type": f.kind.as_str(),
+                "required": f.required,
+                "protection": f.protection.map(Protection::as_str),
+                "key_id": f.key_id.map(hex::encode),
+            })).collect::<Vec<_>>(),
+        })).collect();
+        let migrations: Vec<Value> = self.migrations.iter().map(|(a, b)| json!([hex::encode(a), hex::encode(b)])).collect();
+        json!({ "version": REGISTRY_VERSION, "schemas": schemas, "migrations": migrations }).to_string()
+    }
+
+    /// Parses [`SchemaRegistry::to_json`] output.
+    pub fn from_json(s: &str) -> CoreResult<Self> {
+        let bad = CoreError::Format("bad schema registry");
+        let value: Value = serde_json::from_str(s).map_err(|_| bad.clone())?;
+        if value.get("version").and_then(Value::as_u64) != Some(REGISTRY_VERSION) {
+            return Err(CoreError::Format("unsupported schema registry version"));
+        }
+        let text = |v: &Value, name: &str| v.get(name).and_then(Value::as_str).map(str::to_string).ok_or(bad.clone());
+        let id = |v: &Value| -> CoreResult<SchemaId> {
+            hex::decode(v.as_str().ok_or(bad.clone())?).ok().and_then(|b| b.try_into().ok()).ok_or(bad.clone())
+        };
+        let mut registry = SchemaRegistry::new();
+        for s in value.get("schemas").and_then(Value::as_array).ok_or(bad.clone())? {
+            let mut fields = Vec::new();
+            for f in s.get("fields").and_then(Value::as_array).ok_or(bad.clone())? {
+                let protection = match f.get("protection") {
+                    None | Some(Value::Null) => None,
+                    Some(p) => Some(p.as_str().and_then(Protection::parse).ok_or(bad.clone())?),
+                };
+                let key_id = match f.get("key_id") {
+                    None | Some(Value::Null) => None,
+                    Some(k) => Some(hex::decode(k.as_str().ok_or(bad.clone())?).ok().and_then(|b| b.try_into().ok()).ok_or(bad.clone())?),
+                };
+                fields.push(SchemaField {
+                    name: text(f, "name")?,
+                    kind: FieldType::parse(&text(f, "type")?).ok_or(bad.clone())?,
+                    required: f.get("required").and_then(Value::as_bool).ok_or(bad.clone())?,
+                    protection,
+                    key_id,
+                });
+            }
+            let version = s.get("version").and_then(Value::as_u64).and_then(|v| u32::try_from(v).ok()).ok_or(bad.clone())?;
+            registry.register(RecordSchema { name: text(s, "name")?, version, fields })?;
+        }
+        for m in value.get("migrations").and_then(Value::as_array).ok_or(bad.clone())? {
+            match m.as_array().map(Vec::as_slice) {
+                Some([from, to]) => registry.add_migration(&id(from)?, &id(to)?)?,
+                _ => return Err(bad),
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Writes the registry to `path`, replacing it atomically.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> CoreResult<()> {
+        use std::io::Write;
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(self.to_json().as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a registry written by [`SchemaRegistry::save`]; a missing file
+    /// is an empty registry.
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> CoreResult<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_json(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SchemaRegistry::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Engine {
+    /// Checks `record` against schema `schema_id`, protects its fields and
+    /// seals it to `pk_bytes`. Encrypted fields with a key ID are sealed to
+    /// the key among `keys` with that ID, the rest to `pk_bytes`; blind
+    /// indexes are keyed by `index_key`. Returns the envelope and its
+    /// evidence; the fields' entries share its operation ID. A record that
+    /// does not match, or a missing key, fails with [`CoreError::Config`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal_record(&self, record: &Value, registry: &SchemaRegistry, schema_id: &SchemaId, pk_bytes: &[u8], keys: &[Vec<u8>],
+                       index_key: Option<&[u8; 32]>, context: &[u8]) -> CoreResult<(Envelope, String)> {
+        let _op = operation::implicit();
+        let res = registry.schema(schema_id).and_then(|schema| {
+            match schema.mismatch(record, Form::Plain) {
+                Some(why) => Err(CoreError::Config(why)),
+                None => Ok(schema),
+            }
+        });
+        let schema = self.audited(OpType::Encrypt, &[], res)?;
+        self.seal_fields(record, schema, pk_bytes, keys, index_key, context, false)
+    }
+
+    // Protects `record`'s fields as `schema` says and seals it. With
+    // `keep`, fields already in their protected form stay as they are.
+    #[allow(clippy::too_many_arguments)]
+    fn seal_fields(&self, record: &Value, schema: &RecordSchema, pk_bytes: &[u8], keys: &[Vec<u8>], index_key: Option<&[u8; 32]>,
+                   context: &[u8], keep: bool) -> CoreResult<(Envelope, String)> {
+        let mut out = Map::new();
+        for (name, value) in record.as_object().into_iter().flatten() {
+            let protection = schema.field(name).and_then(|f| f.protection.map(|p| (p, f.key_id)));
+            let value = match protection {
+                Some((protection, _)) if keep && document::is_protected(protection, value) => value.clone(),
+                Some((protection, key)) => {
+                    let res = match (protection, key) {
+                        (Protection::BlindIndex, _) if index_key.is_none() => Err(CoreError::Config("blind_index fields need an index key".into())),
+                        (_, Some(id)) => keys.iter().find(|pk| key_id(pk) == id).map(Vec::as_slice)
+                            .ok_or_else(|| CoreError::Config(format!("no key {} for field {}", hex::encode(id), name))),
+                        (_, None) => Ok(pk_bytes),
+                    };
+                    let pk = self.audited(OpType::Encrypt, &[], res)?;
+                    let path = document::member_path(name);
+                    Value::String(self.protect_value(protection, value, &path, &path, pk, index_key)?.0)
+                }
+                None => value.clone(),
+            };
+            out.insert(name.clone(), value);
+        }
+        let record = Value::Object(out);
+        if let Some(why) = schema.mismatch(&record, Form::Sealed) {
+            return self.audited(OpType::Encrypt, &[], Err(CoreError::Config(why)));
+        }
+        let data = Zeroizing::new(record.to_string().into_bytes());
+        let res = self.try_seal(&data, pk_bytes, context, Marks { schema: Some(schema.id()), ..Marks::default() });
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    /// Opens a record from [`Engine::seal_record`] and returns it, fields
+    /// still protected, with its schema ID. An envelope without a schema ID
+    /// or a record that does not match its schema fails with
+    /// [`CoreError::Format`], one whose schema `registry` lacks with
+    /// [`CoreError::Config`]; both are recorded as failed `decrypt` events.
+    pub fn open_record(&self, envelope: &Envelope, sk_bytes: &[u8], registry: &SchemaRegistry, context: &[u8]) -> CoreResult<(Value, SchemaId)> {
+        let res = envelope.kdf.schema.ok_or(CoreError::Format("envelope has no record schema"))
+            .and_then(|id| registry.schema(&id).map(|schema| (id, schema)));
+        let (id, schema) = self.audited(OpType::Decrypt, &envelope.kem_ct, res)?;
+        let plaintext = Zeroizing::new(self.open_with_context(envelope, sk_bytes, context)?);
+        let res = serde_json::from_slice(&plaintext).map_err(|_| CoreError::Format("record is not JSON"))
+            .and_then(|record: Value| match schema.mismatch(&record, Form::Sealed) {
+                Some(_) => Err(CoreError::Format("record does not match its schema")),
+                None => Ok(record),
+            });
+        Ok((self.audited(OpType::Decrypt, &envelope.kem_ct, res)?, id))
+    }
+
+    /// Moves a record to schema `to` along a migration `registry` declares:
+    /// opens it, passes it to `transform` and seals the result under `to`
+    /// as [`Engine::seal_record`] would. Fields `to` protects the same way
+    /// and that are still protected are kept as they are; others are
+    /// protected afresh. Without the migration declared, fails with
+    /// [`CoreError::Config`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn migrate_record(&self, envelope: &Envelope, sk_bytes: &[u8], registry: &SchemaRegistry, to: &SchemaId,
+                          transform: impl FnOnce(Value) -> CoreResult<Value>, pk_bytes: &[u8], keys: &[Vec<u8>],
+                          index_key: Option<&[u8; 32]>, context: &[u8]) -> CoreResult<(Envelope, String)> {
+        let _op = operation::implicit();
+        let res = match envelope.kdf.schema {
+            Some(from) if registry.allows_migration(&from, to) => registry.schema(to),
+            Some(from) => Err(CoreError::Config(format!("no migration from schema {} to {}", hex::encode(from), hex::encode(to)))),
+            None => Err(CoreError::Format("envelope has no record schema")),
+        };
+        let target = self.audited(OpType::Encrypt, &envelope.kem_ct, res)?;
+        let (record, _) = self.open_record(envelope, sk_bytes, registry, context)?;
+        let res = transform(record).and_then(|record| match target.mismatch(&record, Form::Either) {
+            Some(why) => Err(CoreError::Config(why)),
+            None => Ok(record),
+        });
+        let record = self.audited(OpType::Encrypt, &[], res)?;
+        self.seal_fields(&record, target, pk_bytes, keys, index_key, context, true)
+    }
+}
@@ -0,0 +1,140 @@
+//! Wiping of secrets held in types that do not wipe themselves.
+//!
+//! pqcrypto's secret keys and shared secrets are `Copy` byte arrays with
+//! no `Drop`, so a parsed secret key or a decapsulated shared secret would
+//! stay in memory after use. [`Wiped`] owns one and overwrites it when
+//! dropped. [`crate::crypto`] hands these values out only in that form, and
+//! what is derived from them (session keys, KDF input, wrapped keys) lives
+//! in [`zeroize::Zeroizing`] buffers.
+//!
+//! Debug builds carry a canary: [`live`] counts the values not yet
+//! dropped. A test that takes [`live`] before an operation and checks it
+//! again after catches a secret that was kept or forgotten instead of
+//! wiped.
+
+use pqcrypto_dilithium::dilithium5;
+#[cfg(feature = "hqc")]
+use pqcrypto_hqc::hqc256;
+use pqcrypto_kyber::{kyber1024, kyber512, kyber768};
+use std::ops::Deref;
+use zeroize::Zeroize;
+
+#[cfg(debug_assertions)]
+static LIVE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// Types that are plain byte arrays, for which all zeros is a valid value.
+///
+/// # Safety
+///
+/// The type must hold only bytes inline: no pointers, padding or niches,
+/// and be aligned to one byte.
+pub(crate) unsafe trait PlainBytes: Copy {}
+
+// SAFETY: each is a `[u8; N]` or a newtype over one.
+unsafe impl<const N: usize> PlainBytes for [u8; N] {}
+unsafe impl PlainBytes for kyber1024::SecretKey {}
+unsafe impl PlainBytes for kyber1024::SharedSecret {}
+unsafe impl PlainBytes for kyber768::SecretKey {}
+unsafe impl PlainBytes for kyber768::SharedSecret {}
+unsafe impl PlainBytes for kyber512::SecretKey {}
+unsafe impl PlainBytes for kyber512::SharedSecret {}
+unsafe impl PlainBytes for dilithium5::SecretKey {}
+#[cfg(feature = "hqc")]
+unsafe impl PlainBytes for hqc256::SecretKey {}
+#[cfg(feature = "hqc")]
+unsafe impl PlainBytes for hqc256::SharedSecret {}
+
+/// A secret value wiped on drop.
+pub(crate) struct Wiped<T: PlainBytes>(T);
+
+impl<T: PlainBytes> Wiped<T> {
+    pub(crate) fn new(value: T) -> Self {
+        #[cfg(debug_assertions)]
+        LIVE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Wiped(value)
+    }
+}
+
+impl<T: PlainBytes> Deref for Wiped<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: PlainBytes> Drop for Wiped<T> {
+    fn drop(&mut self) {
+        let ptr = &mut self.0 as *mut T as *mut u8;
+        let len = std::mem::size_of::<T>();
+        // SAFETY: `T` is plain bytes (see `PlainBytes`) owned by `self`.
+        unsafe { std::slice::from_raw_parts_mut(ptr, len) }.zeroize();
+        #[cfg(debug_assertions)]
+        LIVE.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Reads `out` from `hasher`'s output stream, then wipes the hasher and the
+/// stream, which both hold the key material that went in.
+pub(crate) fn finish_blake3(mut hasher: blake3::Hasher, out: &mut [u8]) {
+    let mut reader = hasher.finalize_xof();
+    reader.fill(out);
+    reader.zeroize();
+    hasher.zeroize();
+}
+
+/// Secret keys and shared secrets currently held by this process and not
+/// yet wiped. Debug builds only.
+#[cfg(debug_assertions)]
+pub fn live() -> usize {
+    LIVE.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use crate::crypto;
+    use pqcrypto_traits::kem::SharedSecret as _;
+
+    // `live` counts for the whole process; tests that read it take turns.
+    static SERIAL: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+    #[test]
+    fn drop_is_counted() {
+        let _serial = SERIAL.lock();
+        let before = live();
+        {
+            let first = Wiped::new([0xa5u8; 64]);
+            let _second = Wiped::new([0x5au8; 32]);
+            assert_eq!(live(), before + 2);
+            assert_eq!(first[..], [0xa5u8; 64][..]);
+        }
+        assert_eq!(live(), before);
+    }
+
+    #[test]
+    fn drop_zeroes_storage() {
+        let _serial = SERIAL.lock();
+        let mut secret = std::mem::ManuallyDrop::new(Wiped::new([0xa5u8; 64]));
+        // SAFETY: `secret` is not used as a `Wiped` again; its storage, plain
+        // bytes, stays in place to be read back.
+        unsafe { std::mem::ManuallyDrop::drop(&mut secret) };
+        let storage = &secret.0 as *const [u8; 64];
+        // SAFETY: as above; volatile so the read sees memory, not the
+        // compiler's idea of it.
+        assert_eq!(unsafe { storage.read_volatile() }, [0u8; 64]);
+    }
+
+    #[test]
+    fn kem_round_trip_leaves_nothing_live() {
+        let _serial = SERIAL.lock();
+        let before = live();
+        let (pk, sk) = crypto::generate_keypair();
+        let (sent, kem_ct) = crypto::encapsulate(&crypto::parse_public_key(&pk).unwrap());
+        let received = crypto::decapsulate(&kem_ct, &crypto::parse_secret_key(&sk).unwrap());
+        assert_eq!(sent.as_bytes(), received.as_bytes());
+        assert_eq!(live(), before + 2);
+        drop((sent, received));
+        assert_eq!(live(), before);
+    }
+}
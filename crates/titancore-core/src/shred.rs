@@ -0,0 +1,61 @@
+//! Best-effort overwriting of plaintext files before removal.
+//!
+//! [`secure_delete`] overwrites a file in place with random data, syncing
+//! after each pass, then truncates and removes it. The file APIs use it for
+//! partial plaintext left by a failed decryption, and for source files when
+//! [`EngineConfig::shred_sources`](crate::EngineConfig::shred_sources) is
+//! set.
+//!
+//! Overwriting in place only reaches the original blocks on filesystems and
+//! devices that rewrite them there. SSDs (wear levelling), copy-on-write
+//! and log-structured filesystems (btrfs, ZFS, APFS), journals, snapshots
+//! and backups can all keep old copies; on those, full-disk encryption is
+//! the real protection.
+
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+const SHRED_BLOCK: usize = 64 * 1024;
+
+/// Overwrites the regular file at `path` `passes` times with random data,
+/// then truncates and removes it. Symbolic links are refused rather than
+/// followed.
+pub fn secure_delete(path: impl AsRef<Path>, passes: u32) -> CoreResult<()> {
+    let path = path.as_ref();
+    if passes == 0 {
+        return Err(CoreError::Config("secure_delete needs at least one pass".into()));
+    }
+    if !fs::symlink_metadata(path)?.file_type().is_file() {
+        return Err(CoreError::Config(format!("not a regular file: {}", path.display())));
+    }
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut block = vec![0u8; SHRED_BLOCK];
+    for _ in 0..passes {
+        entropy::fill(&mut block)?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut left = len;
+        while left > 0 {
+            let n = left.min(SHRED_BLOCK as u64) as usize;
+            file.write_all(&block[..n])?;
+            left -= n as u64;
+        }
+        file.sync_all()?;
+    }
+    file.set_len(0)?;
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+/// [`secure_delete`] with one pass, falling back to plain removal, for
+/// cleaning up after an error.
+pub(crate) fn discard(path: &Path) {
+    if secure_delete(path, 1).is_err() {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,337 @@
+//! Engine state snapshots.
+//!
+//! [`Engine::export_state`] captures what it takes to bring an engine back
+//! after a deploy: its configuration, chain head and counter, and policy
+//! (escrow key, quorum, key limits and usage caps). No secret key is included; the
+//! checkpoint signing key travels only in public form, so pass the keypair
+//! as [`crate::EngineConfig::identity`] or reinstall it with
+//! [`Engine::set_signing_keypair`]. Step-up and revocation
+//! checkers hold live verifiers and are not part of the snapshot either.
+//!
+//! [`Engine::restore_state`] rebuilds the engine and checks the snapshot
+//! against what its audit sink holds, so a log that was rolled back or
+//! swapped is caught before anything new is written.
+
+use crate::audit::{AuditSink, CiphertextBinding};
+use crate::engine::{ChainHead, Engine, EngineConfig, OPERATION_CTR};
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::{Kdf, KdfParams};
+use crate::kem;
+use crate::quorum::QuorumPolicy;
+use crate::suite::Suite;
+use crate::usage::UsageCap;
+use serde_json::{json, Value};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+pub const STATE_VERSION: u64 = 1;
+
+/// Everything [`Engine::restore_state`] needs besides the sink, clock and
+/// rate-limit backend.
+#[derive(Debug, Clone)]
+pub struct EngineState {
+    pub fingerprint: [u8; 32],
+    /// Chain head, highest counter, entry count and latest timestamp when
+    /// the snapshot was taken.
+    pub head: [u8; 32],
+    pub counter: u64,
+    pub seq: u64,
+    pub timestamp_ms: u64,
+    pub worker_threads: Option<usize>,
+    pub hash_threads: Option<usize>,
+    pub audit_queue: Option<usize>,
+    pub ct_binding: CiphertextBinding,
+    pub merkle_batch: Option<usize>,
+    pub snapshot_every: Option<u64>,
+    pub suite: Suite,
+    pub kdf: KdfParams,
+    /// [`crate::Kem::id`] envelopes are sealed under.
+    pub kem: u8,
+    pub shred_sources: Option<u32>,
+    pub rate_limit_key: String,
+    pub rate_limit_wait: Option<Duration>,
+    pub fips_mode: bool,
+    pub omit_failures: bool,
+    pub idempotency_window: Option<Duration>,
+    pub escrow_key: Option<Vec<u8>>,
+    pub quorum: Option<QuorumPolicy>,
+    /// `(key_id, max, window_secs)`, as [`Engine::key_limits`].
+    pub key_limits: Vec<([u8; 32], usize, u64)>,
+    /// As [`Engine::key_usage_caps`]. The totals stay in the usage store.
+    pub key_usage_caps: Vec<([u8; 32], UsageCap)>,
+    /// For verifying the restored engine's checkpoints once its keypair is
+    /// reinstalled.
+    pub checkpoint_public_key: Vec<u8>,
+}
+
+impl EngineState {
+    /// `config` with every setting the snapshot records replaced; the clock
+    /// and rate limiter are kept.
+    pub fn configure(&self, config: EngineConfig) -> EngineConfig {
+        EngineConfig {
+            worker_threads: self.worker_threads,
+            hash_threads: self.hash_threads,
+            audit_queue: self.audit_queue,
+            ct_binding: self.ct_binding,
+            merkle_batch: self.merkle_batch,
+            snapshot_every: self.snapshot_every,
+            suite: self.suite,
+            kdf: self.kdf.clone(),
+            kem: Some(self.kem),
+            shred_sources: self.shred_sources,
+            rate_limit_key: Some(self.rate_limit_key.clone()),
+            rate_limit_wait: self.rate_limit_wait,
+            fips_mode: self.fips_mode,
+            omit_failures: self.omit_failures,
+            idempotency_window: self.idempotency_window,
+            ..config
+        }
+    }
+
+    /// JSON object with hex-encoded binary fields.
+    pub fn to_json(&self) -> String {
+        let quorum = self.quorum.as_ref().map(|q| json!({
+            "threshold": q.threshold(),
+            "approvers": q.approvers().iter().map(hex::encode).collect::<Vec<_>>(),
+        }));
+        let key_limits: Vec<Value> = self.key_limits.iter()
+            .map(|(id, max, window)| json!({ "key_id": hex::encode(id), "max": max, "window_secs": window }))
+            .collect();
+        let key_usage_caps: Vec<Value> = self.key_usage_caps.iter()
+            .map(|(id, cap)| json!({ "key_id": hex::encode(id), "max_operations": cap.max_operations, "max_bytes": cap.max_bytes }))
+            .collect();
+        json!({
+            "version": STATE_VERSION,
+            "fingerprint": hex::encode(self.fingerprint),
+            "chain": {
+                "head": hex::encode(self.head),
+                "counter": self.counter,
+                "seq": self.seq,
+                "timestamp_ms": self.timestamp_ms,
+            },
+            "config": {
+                "worker_threads": self.worker_threads,
+                "hash_threads": self.hash_threads,
+                "audit_queue": self.audit_queue,
+                "ct_binding": match self.ct_binding {
+                    CiphertextBinding::Full => "full",
+                    CiphertextBinding::Digest => "digest",
+                },
+                "merkle_batch": self.merkle_batch,
+                "snapshot_every": self.snapshot_every,
+                "suite": self.suite.name(),
+                "kdf": self.kdf.algorithm.name(),
+                "kdf_salt": hex::encode(&self.kdf.salt),
+                "kdf_info": hex::encode(&self.kdf.info),
+                "kem": kem::find(self.kem).map(|k| k.name()),
+                "shred_sources": self.shred_sources,
+                "rate_limit_key": self.rate_limit_key,
+                "rate_limit_wait_ms": self.rate_limit_wait.map(|w| w.as_millis() as u64),
+                "fips_mode": self.fips_mode,
+                "omit_failures": self.omit_failures,
+                "idempotency_window_ms": self.idempotency_window.map(|w| w.as_millis() as u64),
+            },
+            "policy": {
+                "escrow_key": self.escrow_key.as_ref().map(hex::encode),
+                "quorum": quorum,
+                "key_limits": key_limits,
+                "key_usage_caps": key_usage_caps,
+            },
+            "checkpoint_public_key": hex::encode(&self.checkpoint_public_key),
+        }).to_string()
+    }
+
+    pub fn from_json(s: &str) -> CoreResult<Self> {
+        let bad = CoreError::Format("bad state snapshot");
+        let value: Value = serde_json::from_str(s).map_err(|_| bad.clone())?;
+        if value.get("version").and_then(Value::as_u64) != Some(STATE_VERSION) {
+            return Err(CoreError::Format("unsupported state snapshot version"));
+        }
+        let section = |name: &str| value.get(name).filter(|v| v.is_object()).ok_or(bad.clone());
+        let (chain, config, policy) = (section("chain")?, section("config")?, section("policy")?);
+        let num = |v: &Value, name: &str| v.get(name).and_then(Value::as_u64).ok_or(bad.clone());
+        let opt_num = |v: &Value, name: &str| match v.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(n) => n.as_u64().map(Some).ok_or(bad.clone()),
+        };
+        let text = |v: &Value, name: &str| v.get(name).and_then(Value::as_str).map(str::to_string).ok_or(bad.clone());
+        let bytes = |s: &str| hex::decode(s).map_err(|_| bad.clone());
+        let hash = |s: &str| bytes(s)?.try_into().map_err(|_| bad.clone());
+
+        let kdf = KdfParams {
+            algorithm: Kdf::parse(&text(config, "kdf")?).ok_or(CoreError::Format("unknown KDF"))?,
+            salt: bytes(&text(config, "kdf_salt")?)?,
+            info: bytes(&text(config, "kdf_info")?)?,
+            ..KdfParams::default()
+        };
+        let quorum = match policy.get("quorum") {
+            None | Some(Value::Null) => None,
+            Some(q) => {
+                let approvers = q.get("approvers").and_then(Value::as_array).ok_or(bad.clone())?
+                    .iter().map(|a| a.as_str().ok_or(bad.clone()).and_then(bytes)).collect::<CoreResult<Vec<_>>>()?;
+                Some(QuorumPolicy::new(approvers, num(q, "threshold")? as usize)?)
+            }
+        };
+        let key_limits = policy.get("key_limits").and_then(Value::as_array).ok_or(bad.clone())?
+            .iter()
+            .map(|l| Ok((hash(&text(l, "key_id")?)?, num(l, "max")? as usize, num(l, "window_secs")?)))
+            .collect::<CoreResult<Vec<_>>>()?;
+        // Absent from snapshots taken before usage caps existed.
+        let key_usage_caps = match policy.get("key_usage_caps") {
+            None => Vec::new(),
+            Some(caps) => caps.as_array().ok_or(bad.clone())?
+                .iter()
+                .map(|c| Ok((hash(&text(c, "key_id")?)?, UsageCap {
+                    max_operations: opt_num(c, "max_operations")?,
+                    max_bytes: opt_num(c, "max_bytes")?,
+                })))
+                .collect::<CoreResult<Vec<_>>>()?,
+        };
+        Ok(EngineState {
+            fingerprint: hash(&text(&value, "fingerprint")?)?,
+            head: hash(&text(chain, "head")?)?,
+            counter: num(chain, "counter")?,
+            seq: num(chain, "seq")?,
+            timestamp_ms: num(chain, "timestamp_ms")?,
+            worker_threads: opt_num(config, "worker_threads")?.map(|n| n as usize),
+            hash_threads: opt_num(config, "hash_threads")?.map(|n| n as usize),
+            audit_queue: opt_num(config, "audit_queue")?.map(|n| n as usize),
+            ct_binding: match text(config, "ct_binding")?.as_str() {
+                "full" => CiphertextBinding::Full,
+                "digest" => CiphertextBinding::Digest,
+                _ => return Err(bad),
+            },
+            merkle_batch: opt_num(config, "merkle_batch")?.map(|n| n as usize),
+            snapshot_every: opt_num(config, "snapshot_every")?,
+            suite: Suite::parse(&text(config, "suite")?).ok_or(CoreError::Format("unknown suite"))?,
+            kdf,
+            // Absent from snapshots taken before the KEM could be chosen.
+            kem: match config.get("kem") {
+                None => kem::KYBER1024,
+                Some(v) => kem::parse(v.as_str().ok_or(bad.clone())?).ok_or(CoreError::Format("unknown KEM"))?.id(),
+            },
+            shred_sources: opt_num(config, "shred_sources")?.map(|n| n as u32),
+            rate_limit_key: text(config, "rate_limit_key")?,
+            rate_limit_wait: opt_num(config, "rate_limit_wait_ms")?.map(Duration::from_millis),
+            // Absent from snapshots taken before FIPS mode existed.
+            fips_mode: match config.get("fips_mode") {
+                None => false,
+                Some(v) => v.as_bool().ok_or(bad.clone())?,
+            },
+            // Absent from snapshots taken before failures could be omitted.
+            omit_failures: match config.get("omit_failures") {
+                None => false,
+                Some(v) => v.as_bool().ok_or(bad.clone())?,
+            },
+            idempotency_window: opt_num(config, "idempotency_window_ms")?.map(Duration::from_millis),
+            escrow_key: match policy.get("escrow_key") {
+                None | Some(Value::Null) => None,
+                Some(k) => Some(bytes(k.as_str().ok_or(bad.clone())?)?),
+            },
+            quorum,
+            key_limits,
+            key_usage_caps,
+            checkpoint_public_key: bytes(&text(&value, "checkpoint_public_key")?)?,
+        })
+    }
+}
+
+impl Engine {
+    /// Snapshot of this engine's configuration, chain head and policy.
+    pub fn export_state(&self) -> EngineState {
+        let chain = self.chain.lock();
+        #[cfg(feature = "parallel")]
+        let worker_threads = self.pool.as_ref().map(|p| p.current_num_threads());
+        #[cfg(feature = "parallel")]
+        let hash_threads = self.hash_pool.as_ref().map(|p| p.current_num_threads());
+        #[cfg(not(feature = "parallel"))]
+        let (worker_threads, hash_threads) = (None, None);
+        #[cfg(not(target_arch = "wasm32"))]
+        let audit_queue = self.audit_queue_stats().map(|q| q.capacity);
+        #[cfg(target_arch = "wasm32")]
+        let audit_queue = None;
+        #[cfg(feature = "fs")]
+        let shred_sources = self.shred_sources;
+        #[cfg(not(feature = "fs"))]
+        let shred_sources = None;
+        EngineState {
+            fingerprint: self.fingerprint,
+            head: chain.head,
+            counter: chain.counter,
+            seq: chain.seq,
+            timestamp_ms: chain.last_ms,
+            worker_threads,
+            hash_threads,
+            audit_queue,
+            ct_binding: self.ct_binding,
+            merkle_batch: self.merkle.as_ref().map(|m| m.lock().batch_size()),
+            snapshot_every: self.snapshots.as_ref().map(|s| s.lock().every()),
+            suite: self.suite,
+            kdf: self.kdf.clone(),
+            kem: self.kem.id(),
+            shred_sources,
+            rate_limit_key: self.rate_limit_key().to_string(),
+            rate_limit_wait: self.rate_limit_wait(),
+            fips_mode: self.fips_mode,
+            omit_failures: self.omit_failures,
+            idempotency_window: self.idempotency_window,
+            escrow_key: self.escrow.clone(),
+            quorum: self.quorum.clone(),
+            key_limits: self.key_limits(),
+            key_usage_caps: self.key_usage_caps(),
+            checkpoint_public_key: self.checkpoint_public_key().to_vec(),
+        }
+    }
+
+    /// Rebuilds the engine `state` was taken from, on `sink`. `config`
+    /// supplies the clock and rate limiter; every other setting comes from
+    /// the snapshot. An empty sink (a new log, or a `NullSink`) continues
+    /// the chain from the snapshot head; otherwise the sink must pass
+    /// [`Engine::verify_state`]. Counters resume above the snapshot's
+    /// either way, and no genesis record is written. The restored policy is
+    /// recorded as `rekey` events.
+    pub fn restore_state(hw_info: &str, seed: &str, sink: Box<dyn AuditSink>, state: &EngineState, config: EngineConfig) -> CoreResult<Engine> {
+        let mut engine = Engine::build(hw_info, seed, sink, state.configure(config))?;
+        if engine.recovery().is_none() {
+            *engine.chain.lock() = ChainHead { head: state.head, counter: state.counter, seq: state.seq, last_ms: state.timestamp_ms };
+        }
+        engine.verify_state(state)?;
+        OPERATION_CTR.fetch_max(state.counter, Ordering::Relaxed);
+        let overridden = engine.check_rollback()?;
+        engine.record_rollback_override(overridden)?;
+        if let Some(pk) = &state.escrow_key {
+            engine.set_escrow_key(Some(pk))?;
+        }
+        if let Some(policy) = &state.quorum {
+            engine.set_quorum_policy(policy.clone())?;
+        }
+        for &(key_id, max, window_secs) in &state.key_limits {
+            engine.set_key_limit(key_id, max, window_secs)?;
+        }
+        for &(key_id, cap) in &state.key_usage_caps {
+            engine.set_key_usage_cap(key_id, cap)?;
+        }
+        Ok(engine)
+    }
+
+    /// Checks `state` against the audit chain and returns how many entries
+    /// were written after it. Fails with [`CoreError::Config`] for another
+    /// engine's snapshot, and with [`CoreError::Storage`] if the chain is
+    /// shorter than the snapshot (rolled back) or, at the same length, ends
+    /// on a different head.
+    pub fn verify_state(&self, state: &EngineState) -> CoreResult<u64> {
+        if state.fingerprint != self.fingerprint {
+            return Err(CoreError::Config("state snapshot is for a different engine".into()));
+        }
+        let chain = self.chain.lock();
+        if chain.seq < state.seq {
+            return Err(CoreError::Storage(format!(
+                "audit log has {} entries but the state snapshot has {}", chain.seq, state.seq,
+            )));
+        }
+        if chain.seq == state.seq && chain.head != state.head {
+            return Err(CoreError::Storage("audit chain head does not match the state snapshot".into()));
+        }
+        Ok(chain.seq - state.seq)
+    }
+}
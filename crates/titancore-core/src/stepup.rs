@@ -0,0 +1,165 @@
+//! Step-up authorization for high-risk operations.
+//!
+//! [`Engine::set_step_up`] names the [`SensitiveOp`]s that need a fresh
+//! token and the [`StepUpVerifier`] that judges it: a [`Totp`] secret or a
+//! binding-supplied callback. [`Engine::authorize`] checks a token and, if
+//! it passes, grants one use of the operation within [`STEP_UP_TTL_MS`];
+//! the guarded call consumes the grant or fails with
+//! [`CoreError::Unauthorized`]. Every check is recorded as an `approval`
+//! audit event bound to the operation name.
+
+use crate::audit::{OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::error::{CoreError, CoreResult};
+use hmac::{Hmac, Mac};
+use parking_lot::Mutex;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// How long a granted authorization stays usable.
+pub const STEP_UP_TTL_MS: u64 = 60_000;
+pub const TOTP_STEP: u64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Shortest TOTP secret accepted (RFC 4226 recommends 160 bits).
+pub const MIN_TOTP_SECRET: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensitiveOp {
+    /// Secret key material leaving the engine.
+    KeyExport,
+    /// [`Engine::open_escrowed`].
+    EscrowDecrypt,
+    /// Installing escrow, quorum or step-up configuration.
+    PolicyChange,
+}
+
+impl SensitiveOp {
+    pub const ALL: [SensitiveOp; 3] = [SensitiveOp::KeyExport, SensitiveOp::EscrowDecrypt, SensitiveOp::PolicyChange];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SensitiveOp::KeyExport => "key_export",
+            SensitiveOp::EscrowDecrypt => "escrow_decrypt",
+            SensitiveOp::PolicyChange => "policy_change",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<SensitiveOp> {
+        Self::ALL.into_iter().find(|op| op.as_str() == s)
+    }
+}
+
+/// Judges a step-up token for `op`. `now_ms` is the engine clock.
+pub trait StepUpVerifier: Send + Sync {
+    fn verify(&self, op: SensitiveOp, token: &str, now_ms: u64) -> bool;
+}
+
+/// RFC 6238 time-based one-time passwords with HMAC-SHA256, six digits and
+/// 30-second steps; codes one step either side of now are accepted. A code
+/// is refused once it, or a later one, has been used.
+pub struct Totp {
+    secret: Zeroizing<Vec<u8>>,
+    last_step: Mutex<Option<u64>>,
+}
+
+impl Totp {
+    pub fn new(secret: &[u8]) -> CoreResult<Self> {
+        if secret.len() < MIN_TOTP_SECRET {
+            return Err(CoreError::Config(format!("TOTP secret must be at least {} bytes", MIN_TOTP_SECRET)));
+        }
+        Ok(Totp { secret: Zeroizing::new(secret.to_vec()), last_step: Mutex::new(None) })
+    }
+
+    /// The code for Unix time `unix_secs`.
+    pub fn code(&self, unix_secs: u64) -> String {
+        totp_code(&self.secret, unix_secs / TOTP_STEP)
+    }
+}
+
+impl StepUpVerifier for Totp {
+    fn verify(&self, _op: SensitiveOp, token: &str, now_ms: u64) -> bool {
+        let now = now_ms / 1000 / TOTP_STEP;
+        let mut last = self.last_step.lock();
+        let matched = (now.saturating_sub(1)..=now + 1)
+            .filter(|&step| last.is_none_or(|l| step > l))
+            .find(|&step| crypto::ct_eq(totp_code(&self.secret, step).as_bytes(), token.as_bytes()));
+        if let Some(step) = matched {
+            *last = Some(step);
+        }
+        matched.is_some()
+    }
+}
+
+// RFC 4226 dynamic truncation of HMAC(secret, step).
+fn totp_code(secret: &[u8], step: u64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes any key length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes(digest[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    format!("{:0width$}", value % 10u32.pow(TOTP_DIGITS), width = TOTP_DIGITS as usize)
+}
+
+/// The guarded operations, their verifier and the unused grants.
+pub(crate) struct StepUp {
+    ops: Vec<SensitiveOp>,
+    verifier: Box<dyn StepUpVerifier>,
+    grants: Mutex<Vec<(SensitiveOp, u64)>>,
+}
+
+impl Engine {
+    /// Requires a step-up token for each of `ops`, judged by `verifier`.
+    /// Replaces any previous configuration, which needs a
+    /// [`SensitiveOp::PolicyChange`] grant if that configuration guarded
+    /// it; an empty `ops` turns step-up off. Recorded as a `rekey` event
+    /// bound to the operation names.
+    pub fn set_step_up(&mut self, ops: &[SensitiveOp], verifier: Box<dyn StepUpVerifier>) -> CoreResult<()> {
+        let names = ops.iter().map(|op| op.as_str()).collect::<Vec<_>>().join(",");
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, names.as_bytes(), res)?;
+        self.step_up = Some(StepUp { ops: ops.to_vec(), verifier, grants: Mutex::new(Vec::new()) });
+        self.record_event(OpType::Rekey, Outcome::Success, names.as_bytes())?;
+        Ok(())
+    }
+
+    /// True if `op` currently needs [`Engine::authorize`] first.
+    pub fn requires_step_up(&self, op: SensitiveOp) -> bool {
+        self.step_up.as_ref().is_some_and(|s| s.ops.contains(&op))
+    }
+
+    /// Checks `token` for `op` and grants one use of it within
+    /// [`STEP_UP_TTL_MS`]. A no-op for operations that are not guarded.
+    pub fn authorize(&self, op: SensitiveOp, token: &str) -> CoreResult<()> {
+        let Some(step_up) = self.step_up.as_ref().filter(|s| s.ops.contains(&op)) else { return Ok(()) };
+        let subject = op.as_str().as_bytes();
+        let now_ms = self.clock().now_ms();
+        let res = match step_up.verifier.verify(op, token, now_ms) {
+            true => Ok(()),
+            false => Err(CoreError::Unauthorized),
+        };
+        self.audited(OpType::Approval, subject, res)?;
+        self.record_event(OpType::Approval, Outcome::Success, subject)?;
+        let mut grants = step_up.grants.lock();
+        grants.retain(|&(_, expires)| expires > now_ms);
+        grants.push((op, now_ms + STEP_UP_TTL_MS));
+        Ok(())
+    }
+
+    /// Checks the caller holds `op`'s permission (see [`crate::rbac`]),
+    /// then takes an unexpired grant for `op` if it is guarded.
+    pub(crate) fn consume_step_up(&self, op: SensitiveOp) -> CoreResult<()> {
+        self.permit(op.permission())?;
+        let Some(step_up) = self.step_up.as_ref().filter(|s| s.ops.contains(&op)) else { return Ok(()) };
+        let now_ms = self.clock().now_ms();
+        let mut grants = step_up.grants.lock();
+        grants.retain(|&(_, expires)| expires > now_ms);
+        match grants.iter().position(|&(granted, _)| granted == op) {
+            Some(i) => {
+                grants.remove(i);
+                Ok(())
+            }
+            None => Err(CoreError::Unauthorized),
+        }
+    }
+}
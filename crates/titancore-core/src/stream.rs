@@ -0,0 +1,706 @@
+//! Chunked encryption for payloads too large to hold in memory.
+//!
+//! One Kyber encapsulation per stream; each chunk is sealed under the session
+//! key with nonce `prefix || index` and AAD `index || last || aad`, so chunks
+//! can't be reordered, dropped or the stream truncated without detection.
+//! `aad` is the caller's [`StreamOptions::aad`], empty by default. Chunks are
+//! processed in batches on the engine's worker pool and written in order.
+//! [`StreamSealer`] and [`StreamOpener`] produce and consume the same format
+//! a piece at a time, for input that is pushed rather than read.
+//!
+//! Layout: `magic(4) | version(1) | [suite(1) |] counter(8) | fingerprint(32) |
+//! kem_len(2) | kem_ct | [ext |] nonce_prefix(8) | chunk_size(4) | [framing(1)]`,
+//! then the chunk ciphertexts, framed as `len(4) | ciphertext` or, under
+//! [`Framing::Fixed`], back to back in `chunk_size + 16` bytes each (the last
+//! may be shorter). `ext` (from version 2) carries non-default
+//! [`KdfParams`] and the per-message salt, the suite byte (version 3) the
+//! KDF, as in envelopes, and the framing byte (version 4, written only for
+//! fixed framing) the framing; chunks are always AES-256-GCM-SIV. Version 1
+//! streams (no extension) still open.
+
+use crate::audit::{self, OpType, Outcome};
+use crate::cert;
+use crate::crypto;
+use crate::engine::{Engine, MAX_KEY_VOLUME};
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use crate::fips;
+use crate::guarded::Guarded;
+use crate::kdf::{Kdf, KdfParams};
+use crate::rbac::Permission;
+#[cfg(feature = "fs")]
+use crate::shred;
+use crate::suite::Suite;
+use pqcrypto_kyber::kyber1024;
+use pqcrypto_traits::kem::{Ciphertext as KEMCiphertext, SharedSecret as KEMSharedSecret};
+use std::io::{self, Read, Write};
+use zeroize::Zeroizing;
+
+pub const STREAM_MAGIC: &[u8; 4] = b"TCST";
+pub const STREAM_VERSION: u8 = 4;
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+pub(crate) const TAG_LEN: usize = 16;
+const CHUNKS_PER_BATCH: usize = 64;
+
+/// How chunk ciphertexts are laid out after the header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Each chunk preceded by its 4-byte length.
+    #[default]
+    LengthPrefixed,
+    /// Chunks back to back, every one but the last exactly
+    /// `chunk_size + 16` bytes, so chunk `i` starts at a computable offset
+    /// and maps onto fixed-size storage records.
+    Fixed,
+}
+
+impl Framing {
+    fn id(self) -> u8 {
+        match self {
+            Framing::LengthPrefixed => 0,
+            Framing::Fixed => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> CoreResult<Framing> {
+        match id {
+            0 => Ok(Framing::LengthPrefixed),
+            1 => Ok(Framing::Fixed),
+            _ => Err(CoreError::Format("unknown stream framing")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::LengthPrefixed => "length-prefixed",
+            Framing::Fixed => "fixed",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Framing> {
+        [Framing::LengthPrefixed, Framing::Fixed].into_iter().find(|f| f.name() == name)
+    }
+}
+
+/// Layout and authentication choices for [`Engine::seal_stream_with`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// Plaintext bytes per chunk, 1..=[`MAX_CHUNK_SIZE`]. Larger chunks
+    /// cost more memory per batch and less framing overhead.
+    pub chunk_size: usize,
+    pub framing: Framing,
+    /// Bound into every chunk's AAD after its index and final flag, e.g.
+    /// the object's storage key. Not stored in the stream: the opener must
+    /// pass the same bytes.
+    pub aad: Vec<u8>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        StreamOptions { chunk_size: DEFAULT_CHUNK_SIZE, framing: Framing::LengthPrefixed, aad: Vec::new() }
+    }
+}
+
+pub(crate) struct StreamHeader {
+    counter: u64,
+    fingerprint: [u8; 32],
+    pub(crate) kem_ct: Vec<u8>,
+    kdf: KdfParams,
+    nonce_prefix: [u8; 8],
+    pub(crate) chunk_size: u32,
+    pub(crate) framing: Framing,
+}
+
+impl StreamHeader {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let fixed = self.framing != Framing::LengthPrefixed;
+        let legacy = self.kdf.is_default() && !fixed;
+        w.write_all(STREAM_MAGIC)?;
+        if legacy {
+            w.write_all(&[1])?;
+        } else {
+            let version = if fixed { STREAM_VERSION } else { 3 };
+            w.write_all(&[version, Suite::GcmSivCounter.wire_id(self.kdf.algorithm)])?;
+        }
+        w.write_all(&self.counter.to_be_bytes())?;
+        w.write_all(&self.fingerprint)?;
+        w.write_all(&(self.kem_ct.len() as u16).to_be_bytes())?;
+        w.write_all(&self.kem_ct)?;
+        if !legacy {
+            w.write_all(&self.kdf.encode_ext())?;
+        }
+        w.write_all(&self.nonce_prefix)?;
+        w.write_all(&self.chunk_size.to_be_bytes())?;
+        if fixed {
+            w.write_all(&[self.framing.id()])?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_from<R: Read>(r: &mut R) -> CoreResult<Self> {
+        if read_array::<4, _>(r)? != *STREAM_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        let version = read_array::<1, _>(r)?[0];
+        if !(1..=STREAM_VERSION).contains(&version) {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let algorithm = match version {
+            3.. => match Suite::from_wire_id(read_array::<1, _>(r)?[0])? {
+                (Suite::GcmSivCounter, kdf) => kdf,
+                _ => return Err(CoreError::Format("unsupported stream suite")),
+            },
+            _ => Kdf::HkdfSha256,
+        };
+        let counter = u64::from_be_bytes(read_array(r)?);
+        let fingerprint = read_array(r)?;
+        let kem_len = u16::from_be_bytes(read_array(r)?) as usize;
+        crypto::check_kem_len(kem_len)?;
+        let mut kem_ct = vec![0u8; kem_len];
+        r.read_exact(&mut kem_ct).map_err(|_| CoreError::Format("truncated"))?;
+        let kdf = match version {
+            1 => KdfParams::default(),
+            _ => {
+                let mut ext = vec![0u8; u16::from_be_bytes(read_array(r)?) as usize];
+                r.read_exact(&mut ext).map_err(|_| CoreError::Format("truncated"))?;
+                KdfParams::decode_ext(algorithm, &ext)?
+            }
+        };
+        let nonce_prefix = read_array(r)?;
+        let chunk_size = u32::from_be_bytes(read_array(r)?);
+        if chunk_size == 0 || chunk_size as usize > MAX_CHUNK_SIZE {
+            return Err(CoreError::Format("bad chunk size"));
+        }
+        let framing = match version {
+            4.. => Framing::from_id(read_array::<1, _>(r)?[0])?,
+            _ => Framing::LengthPrefixed,
+        };
+        Ok(StreamHeader { counter, fingerprint, kem_ct, kdf, nonce_prefix, chunk_size, framing })
+    }
+
+    fn session_key(&self, sk: &kyber1024::SecretKey, context: &[u8]) -> CoreResult<Zeroizing<[u8; 32]>> {
+        let kem_ct = kyber1024::Ciphertext::from_bytes(&self.kem_ct)
+            .map_err(|_| CoreError::Format("bad KEM ciphertext"))?;
+        let shared_secret = crypto::decapsulate(&kem_ct, sk);
+        crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, self.counter, &self.kdf, context)
+    }
+
+    // The next chunk ciphertext, or `None` at the end of the stream.
+    fn read_chunk_ct<R: Read>(&self, r: &mut R) -> CoreResult<Option<Vec<u8>>> {
+        let max_frame = self.chunk_size as usize + TAG_LEN;
+        match self.framing {
+            Framing::LengthPrefixed => read_frame(r, max_frame),
+            Framing::Fixed => match read_chunk(r, max_frame)? {
+                ct if ct.is_empty() => Ok(None),
+                ct if ct.len() < TAG_LEN => Err(CoreError::Format("bad chunk length")),
+                ct => Ok(Some(ct)),
+            },
+        }
+    }
+}
+
+impl Engine {
+    /// Encrypts everything from `reader` into `writer` as a chunked stream and
+    /// records one audit entry for it. Returns the evidence hash. A
+    /// non-empty `context` is mixed into the session key as in
+    /// [`Engine::seal_with_context`].
+    pub fn seal_stream<R: Read, W: Write>(&self, reader: R, writer: W, pk_bytes: &[u8], chunk_size: usize, context: &[u8]) -> CoreResult<String> {
+        self.seal_stream_with(reader, writer, pk_bytes, &StreamOptions { chunk_size, ..StreamOptions::default() }, context)
+    }
+
+    /// [`Engine::seal_stream`] with a choice of framing and per-chunk AAD.
+    pub fn seal_stream_with<R: Read, W: Write>(&self, reader: R, writer: W, pk_bytes: &[u8], options: &StreamOptions,
+                                               context: &[u8]) -> CoreResult<String> {
+        let res = self.try_seal_stream(reader, writer, pk_bytes, options, context, true);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    /// `rate_limited: false` is for callers that already counted the
+    /// request and the key use, e.g. one stream per file of a tree.
+    pub(crate) fn try_seal_stream<R: Read, W: Write>(&self, mut reader: R, mut writer: W, pk_bytes: &[u8], options: &StreamOptions,
+                                                     context: &[u8], rate_limited: bool) -> CoreResult<String> {
+        let (header, sess_key) = self.start_stream(pk_bytes, options, context, rate_limited)?;
+        let (ctr, nonce_prefix, chunk_size) = (header.counter, header.nonce_prefix, options.chunk_size);
+        header.write_to(&mut writer)?;
+
+        // The audit entry covers a digest of all chunk ciphertexts.
+        let mut digest = blake3::Hasher::new();
+        let mut next = read_chunk(&mut reader, chunk_size)?;
+        let mut index = 0u64;
+        let mut volume = 0u64;
+        loop {
+            let mut batch = Vec::with_capacity(CHUNKS_PER_BATCH);
+            let mut done = false;
+            while batch.len() < CHUNKS_PER_BATCH && !done {
+                let cur = std::mem::replace(&mut next, read_chunk(&mut reader, chunk_size)?);
+                done = next.is_empty();
+                volume += cur.len() as u64;
+                batch.push(cur);
+            }
+            if volume > MAX_KEY_VOLUME {
+                return Err(CoreError::RekeyRequired("stream exceeds the per-key volume"));
+            }
+            let last = batch.len() - 1;
+            let sealed = self.par_map(&batch, |i, chunk| {
+                let idx = chunk_index(index + i as u64)?;
+                let chunk_ad = [&chunk_aad(idx, done && i == last)[..], &options.aad].concat();
+                crypto::aead_seal_aad(&sess_key, &chunk_nonce(&nonce_prefix, idx), chunk, &chunk_ad)
+            });
+            for ct in sealed {
+                let ct = ct?;
+                if options.framing == Framing::LengthPrefixed {
+                    writer.write_all(&(ct.len() as u32).to_be_bytes())?;
+                }
+                writer.write_all(&ct)?;
+                self.hashing(ct.len(), || audit::hash_payload(&mut digest, &ct));
+            }
+            index += batch.len() as u64;
+            if done { break; }
+        }
+        writer.flush()?;
+        self.count_key_bytes(&cert::key_id(pk_bytes), volume)?;
+
+        let ct_digest: [u8; 32] = digest.finalize().into();
+        self.append_to_audit(ctr, &cert::key_id(pk_bytes), &nonce_prefix, &ct_digest, &header.kem_ct)
+    }
+
+    // The header and session key of a new stream.
+    fn start_stream(&self, pk_bytes: &[u8], options: &StreamOptions, context: &[u8], rate_limited: bool)
+                    -> CoreResult<(StreamHeader, Zeroizing<[u8; 32]>)> {
+        let chunk_size = options.chunk_size;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(CoreError::Config(format!("chunk size must be 1..={}", MAX_CHUNK_SIZE)));
+        }
+        let pk = if rate_limited {
+            self.check_rate_limit()?;
+            self.recipient_key(pk_bytes, 0)?
+        } else {
+            self.parse_recipient(pk_bytes)?
+        };
+        let ctr = self.next_counters(1)?;
+        let (shared_secret, kem_ct) = crypto::encapsulate(&pk);
+        let kdf = self.kdf.for_message(shared_secret.as_bytes(), kem_ct.as_bytes());
+        let sess_key = crypto::derive_session_key(shared_secret.as_bytes(), &self.fingerprint, ctr, &kdf, context)?;
+        let mut nonce_prefix = [0u8; 8];
+        entropy::fill_hedged(&mut nonce_prefix, sess_key.as_ref(), &ctr.to_be_bytes());
+
+        let header = StreamHeader {
+            counter: ctr,
+            fingerprint: self.fingerprint,
+            kem_ct: kem_ct.as_bytes().to_vec(),
+            kdf,
+            nonce_prefix,
+            chunk_size: chunk_size as u32,
+            framing: options.framing,
+        };
+        Ok((header, sess_key))
+    }
+
+    /// Decrypts a stream produced by [`Engine::seal_stream`]. Returns the
+    /// number of plaintext bytes written. Output written before an
+    /// authentication failure must be discarded by the caller. Records a
+    /// `decrypt` event bound to the stream's KEM ciphertext.
+    pub fn open_stream<R: Read, W: Write>(&self, reader: R, writer: W, sk_bytes: &[u8], context: &[u8]) -> CoreResult<u64> {
+        self.open_stream_with(reader, writer, sk_bytes, &[], context)
+    }
+
+    /// Opens a stream sealed with [`StreamOptions::aad`] set to `aad`. The
+    /// chunk size and framing are read from the header.
+    pub fn open_stream_with<R: Read, W: Write>(&self, mut reader: R, writer: W, sk_bytes: &[u8], aad: &[u8], context: &[u8]) -> CoreResult<u64> {
+        let header = match self.permit(Permission::Decrypt).and_then(|_| StreamHeader::read_from(&mut reader)) {
+            Ok(header) => header,
+            Err(e) => return self.audited(OpType::Decrypt, &[], Err(e)),
+        };
+        let res = self.open_chunks(&header, reader, writer, sk_bytes, aad, context);
+        let total = self.audited(OpType::Decrypt, &header.kem_ct, res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, &header.kem_ct)?;
+        Ok(total)
+    }
+
+    pub(crate) fn open_chunks<R: Read, W: Write>(&self, header: &StreamHeader, mut reader: R, mut writer: W, sk_bytes: &[u8], aad: &[u8],
+                                                 context: &[u8]) -> CoreResult<u64> {
+        self.check_approved(Suite::GcmSivCounter, header.kdf.algorithm)?;
+        let sk = crypto::parse_secret_key(sk_bytes)?;
+        let sess_key = header.session_key(&sk, context)?;
+
+        let mut next = header.read_chunk_ct(&mut reader)?;
+        if next.is_none() {
+            return Err(CoreError::Format("missing final chunk"));
+        }
+        let mut index = 0u64;
+        let mut total = 0u64;
+        loop {
+            let mut batch = Vec::with_capacity(CHUNKS_PER_BATCH);
+            let mut done = false;
+            while batch.len() < CHUNKS_PER_BATCH && !done {
+                let cur = std::mem::replace(&mut next, header.read_chunk_ct(&mut reader)?);
+                done = next.is_none();
+                batch.extend(cur);
+            }
+            let last = batch.len() - 1;
+            let opened = self.par_map(&batch, |i, ct| {
+                let idx = chunk_index(index + i as u64)?;
+                let chunk_ad = [&chunk_aad(idx, done && i == last)[..], aad].concat();
+                crypto::aead_open_aad(&sess_key, &chunk_nonce(&header.nonce_prefix, idx), ct, &chunk_ad)
+            });
+            for pt in opened {
+                let pt = pt?;
+                writer.write_all(&pt)?;
+                total += pt.len() as u64;
+            }
+            index += batch.len() as u64;
+            if done { break; }
+        }
+        writer.flush()?;
+        Ok(total)
+    }
+}
+
+/// Push-style sealer from [`Engine::stream_sealer`], for input that arrives
+/// in pieces (an upload, a socket). Produces the same stream as
+/// [`Engine::seal_stream_with`]. The last chunk is marked as such, so one
+/// chunk of input is held back until more arrives or the stream is
+/// finished with [`Engine::finish_stream_sealer`].
+pub struct StreamSealer {
+    header: StreamHeader,
+    // Not yet returned by `update`.
+    header_bytes: Option<Vec<u8>>,
+    key: Guarded<[u8; 32]>,
+    aad: Vec<u8>,
+    pending: Vec<u8>,
+    index: u64,
+    volume: u64,
+    digest: blake3::Hasher,
+    error: Option<CoreError>,
+    key_id: [u8; 32],
+}
+
+impl StreamSealer {
+    /// Takes the next piece of plaintext and returns the stream bytes now
+    /// complete (the header first). After an error every later call, and
+    /// the finish, fails the same way.
+    pub fn update(&mut self, data: &[u8]) -> CoreResult<Vec<u8>> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        let res = self.try_update(data);
+        if let Err(e) = &res {
+            self.error = Some(e.clone());
+        }
+        res
+    }
+
+    fn try_update(&mut self, data: &[u8]) -> CoreResult<Vec<u8>> {
+        self.volume += data.len() as u64;
+        if self.volume > MAX_KEY_VOLUME {
+            return Err(CoreError::RekeyRequired("stream exceeds the per-key volume"));
+        }
+        let mut out = self.header_bytes.take().unwrap_or_default();
+        self.pending.extend_from_slice(data);
+        let chunk_size = self.header.chunk_size as usize;
+        // Every full chunk but one is known not to be the last.
+        let ready = self.pending.len().saturating_sub(1) / chunk_size * chunk_size;
+        let pending = std::mem::take(&mut self.pending);
+        for chunk in pending[..ready].chunks(chunk_size) {
+            self.seal_chunk(chunk, false, &mut out)?;
+        }
+        self.pending = pending[ready..].to_vec();
+        Ok(out)
+    }
+
+    fn seal_chunk(&mut self, chunk: &[u8], last: bool, out: &mut Vec<u8>) -> CoreResult<()> {
+        let idx = chunk_index(self.index)?;
+        let chunk_ad = [&chunk_aad(idx, last)[..], &self.aad].concat();
+        let ct = crypto::aead_seal_aad(&self.key, &chunk_nonce(&self.header.nonce_prefix, idx), chunk, &chunk_ad)?;
+        if self.header.framing == Framing::LengthPrefixed {
+            out.extend_from_slice(&(ct.len() as u32).to_be_bytes());
+        }
+        out.extend_from_slice(&ct);
+        audit::hash_payload(&mut self.digest, &ct);
+        self.index += 1;
+        Ok(())
+    }
+}
+
+/// Push-style opener from [`Engine::stream_opener`]: takes a stream in
+/// pieces of any size and returns plaintext as chunks complete. As with
+/// [`Engine::open_stream`], plaintext returned before a failure must be
+/// discarded; only [`Engine::finish_stream_opener`] succeeding shows the
+/// stream was whole.
+pub struct StreamOpener {
+    sk: Guarded<kyber1024::SecretKey>,
+    aad: Vec<u8>,
+    context: Vec<u8>,
+    fips_mode: bool,
+    buf: Vec<u8>,
+    // Set once the header has been read.
+    stream: Option<(StreamHeader, Guarded<[u8; 32]>)>,
+    // The last complete chunk, opened once it is known whether it is final.
+    held: Option<Vec<u8>>,
+    index: u64,
+    error: Option<CoreError>,
+}
+
+impl StreamOpener {
+    /// Takes the next piece of the stream. After an error every later
+    /// call, and the finish, fails the same way.
+    pub fn update(&mut self, data: &[u8]) -> CoreResult<Vec<u8>> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        let res = self.try_update(data);
+        if let Err(e) = &res {
+            self.error = Some(e.clone());
+        }
+        res
+    }
+
+    fn try_update(&mut self, data: &[u8]) -> CoreResult<Vec<u8>> {
+        self.buf.extend_from_slice(data);
+        let mut out = Vec::new();
+        if self.stream.is_none() {
+            let mut r = &self.buf[..];
+            let header = match StreamHeader::read_from(&mut r) {
+                Ok(header) => header,
+                Err(CoreError::Format("truncated")) => return Ok(out),
+                Err(e) => return Err(e),
+            };
+            self.buf.drain(..self.buf.len() - r.len());
+            if self.fips_mode {
+                fips::check_approved(Suite::GcmSivCounter, header.kdf.algorithm)?;
+            }
+            let key = header.session_key(&self.sk, &self.context)?;
+            self.stream = Some((header, Guarded::new(&key)));
+        }
+        while let Some(ct) = self.next_frame()? {
+            if let Some(prev) = self.held.replace(ct) {
+                out.extend(self.open_chunk(&prev, false)?);
+            }
+        }
+        Ok(out)
+    }
+
+    // Splits the next complete frame off the buffer. Under fixed framing a
+    // short frame can only be the last and is left for `finish`.
+    fn next_frame(&mut self) -> CoreResult<Option<Vec<u8>>> {
+        let Some((header, _)) = &self.stream else { return Ok(None) };
+        let max_frame = header.chunk_size as usize + TAG_LEN;
+        let (skip, len) = match header.framing {
+            Framing::LengthPrefixed => {
+                let Some(len) = self.buf.get(..4) else { return Ok(None) };
+                let len = u32::from_be_bytes(len.try_into().expect("4 bytes")) as usize;
+                if len < TAG_LEN || len > max_frame {
+                    return Err(CoreError::Format("bad chunk length"));
+                }
+                (4, len)
+            }
+            Framing::Fixed => (0, max_frame),
+        };
+        if self.buf.len() < skip + len {
+            return Ok(None);
+        }
+        let ct = self.buf[skip..skip + len].to_vec();
+        self.buf.drain(..skip + len);
+        Ok(Some(ct))
+    }
+
+    fn open_chunk(&mut self, ct: &[u8], last: bool) -> CoreResult<Vec<u8>> {
+        let Some((header, key)) = &self.stream else { return Err(CoreError::Format("truncated")) };
+        let idx = chunk_index(self.index)?;
+        let chunk_ad = [&chunk_aad(idx, last)[..], &self.aad].concat();
+        let pt = crypto::aead_open_aad(key, &chunk_nonce(&header.nonce_prefix, idx), ct, &chunk_ad)?;
+        self.index += 1;
+        Ok(pt)
+    }
+
+    fn finish(&mut self) -> CoreResult<Vec<u8>> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let Some((header, _)) = &self.stream else { return Err(CoreError::Format("truncated")) };
+        let tail = std::mem::take(&mut self.buf);
+        let fixed = header.framing == Framing::Fixed;
+        let mut out = Vec::new();
+        let last = match self.held.take() {
+            Some(prev) if fixed && !tail.is_empty() => {
+                out = self.open_chunk(&prev, false)?;
+                tail
+            }
+            Some(prev) if tail.is_empty() => prev,
+            None if fixed && !tail.is_empty() => tail,
+            None => return Err(CoreError::Format("missing final chunk")),
+            Some(_) => return Err(CoreError::Format("truncated")),
+        };
+        if last.len() < TAG_LEN {
+            return Err(CoreError::Format("bad chunk length"));
+        }
+        out.extend(self.open_chunk(&last, true)?);
+        Ok(out)
+    }
+
+    fn kem_ct(&self) -> &[u8] {
+        self.stream.as_ref().map_or(&[], |(header, _)| &header.kem_ct)
+    }
+}
+
+impl Engine {
+    /// Starts a stream to `pk_bytes` that is fed with
+    /// [`StreamSealer::update`]. Counts as one request against the rate
+    /// limit.
+    pub fn stream_sealer(&self, pk_bytes: &[u8], options: &StreamOptions, context: &[u8]) -> CoreResult<StreamSealer> {
+        let res = self.start_stream(pk_bytes, options, context, true).map(|(header, key)| {
+            let mut header_bytes = Vec::new();
+            header.write_to(&mut header_bytes).expect("writing to a Vec");
+            StreamSealer {
+                header,
+                header_bytes: Some(header_bytes),
+                key: Guarded::new(&key),
+                aad: options.aad.clone(),
+                pending: Vec::new(),
+                index: 0,
+                volume: 0,
+                digest: blake3::Hasher::new(),
+                key_id: cert::key_id(pk_bytes),
+                error: None,
+            }
+        });
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    /// Seals what `sealer` still holds as the last chunk and records the
+    /// stream in the audit chain as [`Engine::seal_stream`] does. Returns
+    /// the remaining stream bytes and the evidence hash.
+    pub fn finish_stream_sealer(&self, mut sealer: StreamSealer) -> CoreResult<(Vec<u8>, String)> {
+        let res = sealer.error.take().map_or(Ok(()), Err).and_then(|()| {
+            let mut out = sealer.header_bytes.take().unwrap_or_default();
+            let last = std::mem::take(&mut sealer.pending);
+            sealer.seal_chunk(&last, true, &mut out)?;
+            let ct_digest: [u8; 32] = std::mem::take(&mut sealer.digest).finalize().into();
+            self.count_key_bytes(&sealer.key_id, sealer.volume)?;
+            let header = &sealer.header;
+            Ok((out, self.append_to_audit(header.counter, &sealer.key_id, &header.nonce_prefix, &ct_digest, &header.kem_ct)?))
+        });
+        self.audited(OpType::Encrypt, &sealer.header.kem_ct, res)
+    }
+
+    /// Starts opening a stream sealed to `sk_bytes` that is fed with
+    /// [`StreamOpener::update`]. `aad` is the stream's
+    /// [`StreamOptions::aad`].
+    pub fn stream_opener(&self, sk_bytes: &[u8], aad: &[u8], context: &[u8]) -> CoreResult<StreamOpener> {
+        let res = self.permit(Permission::Decrypt).and_then(|_| crypto::parse_secret_key(sk_bytes)).map(|sk| StreamOpener {
+            sk: Guarded::new(&sk),
+            aad: aad.to_vec(),
+            context: context.to_vec(),
+            fips_mode: self.fips_mode,
+            buf: Vec::new(),
+            stream: None,
+            held: None,
+            index: 0,
+            error: None,
+        });
+        self.audited(OpType::Decrypt, &[], res)
+    }
+
+    /// Opens the last chunk held by `opener` and returns its plaintext.
+    /// Fails if the stream was cut short. Records a `decrypt` event bound
+    /// to the stream's KEM ciphertext, for a failure in an earlier
+    /// [`StreamOpener::update`] too.
+    pub fn finish_stream_opener(&self, mut opener: StreamOpener) -> CoreResult<Vec<u8>> {
+        let res = opener.finish();
+        let tail = self.audited(OpType::Decrypt, opener.kem_ct(), res)?;
+        self.record_event(OpType::Decrypt, Outcome::Success, opener.kem_ct())?;
+        Ok(tail)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Engine {
+    /// Encrypts `src` into `dst`. With [`crate::EngineConfig::shred_sources`]
+    /// set, `src` is then shredded.
+    pub fn seal_file(&self, src: impl AsRef<std::path::Path>, dst: impl AsRef<std::path::Path>, pk_bytes: &[u8], chunk_size: usize, context: &[u8]) -> CoreResult<String> {
+        self.seal_file_with(src, dst, pk_bytes, &StreamOptions { chunk_size, ..StreamOptions::default() }, context)
+    }
+
+    pub fn seal_file_with(&self, src: impl AsRef<std::path::Path>, dst: impl AsRef<std::path::Path>, pk_bytes: &[u8], options: &StreamOptions,
+                          context: &[u8]) -> CoreResult<String> {
+        let reader = io::BufReader::new(std::fs::File::open(&src)?);
+        let writer = io::BufWriter::new(std::fs::File::create(&dst)?);
+        let evidence = self.seal_stream_with(reader, writer, pk_bytes, options, context).inspect_err(|_| {
+            let _ = std::fs::remove_file(&dst);
+        })?;
+        self.shred_source(src.as_ref())?;
+        Ok(evidence)
+    }
+
+    /// Decrypts `src` into `dst`; `dst` is shredded if authentication fails.
+    pub fn open_file(&self, src: impl AsRef<std::path::Path>, dst: impl AsRef<std::path::Path>, sk_bytes: &[u8], context: &[u8]) -> CoreResult<u64> {
+        self.open_file_with(src, dst, sk_bytes, &[], context)
+    }
+
+    pub fn open_file_with(&self, src: impl AsRef<std::path::Path>, dst: impl AsRef<std::path::Path>, sk_bytes: &[u8], aad: &[u8],
+                          context: &[u8]) -> CoreResult<u64> {
+        let reader = io::BufReader::new(std::fs::File::open(src)?);
+        let writer = io::BufWriter::new(std::fs::File::create(&dst)?);
+        self.open_stream_with(reader, writer, sk_bytes, aad, context).inspect_err(|_| shred::discard(dst.as_ref()))
+    }
+
+    /// Overwrite passes for encrypted source files, if shredding is on.
+    pub fn shred_sources(&self) -> Option<u32> {
+        self.shred_sources
+    }
+
+    pub(crate) fn shred_source(&self, path: &std::path::Path) -> CoreResult<()> {
+        match self.shred_sources {
+            Some(passes) => shred::secure_delete(path, passes),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn chunk_index(i: u64) -> CoreResult<u32> {
+    u32::try_from(i).map_err(|_| CoreError::Format("too many chunks"))
+}
+
+pub(crate) fn chunk_nonce(prefix: &[u8; 8], index: u32) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(prefix);
+    nonce[8..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+pub(crate) fn chunk_aad(index: u32, last: bool) -> [u8; 5] {
+    let mut aad = [0u8; 5];
+    aad[..4].copy_from_slice(&index.to_be_bytes());
+    aad[4] = last as u8;
+    aad
+}
+
+/// Reads up to `size` bytes, short only at end of input.
+pub(crate) fn read_chunk<R: Read>(r: &mut R, size: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(size);
+    r.by_ref().take(size as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+pub(crate) fn read_frame<R: Read>(r: &mut R, max_len: usize) -> CoreResult<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len < TAG_LEN || len > max_len {
+        return Err(CoreError::Format("bad chunk length"));
+    }
+    let mut ct = vec![0u8; len];
+    r.read_exact(&mut ct).map_err(|_| CoreError::Format("truncated"))?;
+    Ok(Some(ct))
+}
+
+pub(crate) fn read_array<const N: usize, R: Read>(r: &mut R) -> CoreResult<[u8; N]> {
+    let mut out = [0u8; N];
+    r.read_exact(&mut out).map_err(|_| CoreError::Format("truncated"))?;
+    Ok(out)
+}
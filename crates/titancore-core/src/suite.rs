@@ -0,0 +1,202 @@
+//! Cipher suites: which AEAD seals an envelope and how its nonce is chosen.
+//! The suite is recorded in the envelope so the recipient opens it the same
+//! way, together with the [`Kdf`] that derived the session key.
+//!
+//! The three built-in suites are fixed. Others implement [`Aead`] and are
+//! added with [`register`] under a suite ID from [`MIN_REGISTERED_ID`] to
+//! [`MAX_ID`] (the ID shares the header byte with the KDF code), e.g. from
+//! a crate feature that brings in AEGIS-256 or Ascon. They show up as
+//! [`Suite::Registered`] and are chosen like any other suite through
+//! [`EngineConfig::suite`](crate::EngineConfig::suite). Registration is
+//! process-wide and permanent, so an ID never comes to mean another
+//! cipher and existing ciphertexts keep opening. Registered suites seal
+//! envelopes; streams, archives and multipart uploads always use their own
+//! suites, and JOSE and COSE refuse to seal under a registered one.
+
+use crate::crypto;
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use crate::kdf::Kdf;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// Lowest ID [`register`] accepts; the built-in suites take 1 to 3.
+pub const MIN_REGISTERED_ID: u8 = 4;
+/// Highest suite ID, the largest that fits the header's low nibble.
+pub const MAX_ID: u8 = 15;
+
+/// A 256-bit-key AEAD that a registered suite seals with. Nonces are
+/// random (see [`entropy::fill_hedged`]), and the output ends in a 16-byte
+/// tag, as with the built-in suites.
+pub trait Aead: Send + Sync {
+    /// Suite ID recorded in each envelope.
+    fn id(&self) -> u8;
+
+    /// Lowercase name, e.g. `"aegis-256"`; unique among suites.
+    fn name(&self) -> &'static str;
+
+    fn nonce_len(&self) -> usize;
+
+    /// Fails with [`CoreError::Encryption`].
+    fn seal(&self, key: &[u8; 32], nonce: &[u8], data: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>>;
+
+    /// Fails with [`CoreError::Decryption`] if `ct` does not authenticate.
+    fn open(&self, key: &[u8; 32], nonce: &[u8], ct: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>>;
+}
+
+static REGISTERED: RwLock<Vec<Arc<dyn Aead>>> = RwLock::new(Vec::new());
+
+/// Makes `aead` available as a suite to every engine in the process. Fails
+/// with [`CoreError::Config`] for an ID outside [`MIN_REGISTERED_ID`] to
+/// [`MAX_ID`], or an ID or name already taken, built-in ones included.
+pub fn register(aead: Arc<dyn Aead>) -> CoreResult<()> {
+    let mut registered = REGISTERED.write();
+    let (id, name) = (aead.id(), aead.name());
+    if !(MIN_REGISTERED_ID..=MAX_ID).contains(&id) {
+        return Err(CoreError::Config(format!("suite ids {} to {} are free to register", MIN_REGISTERED_ID, MAX_ID)));
+    }
+    if Suite::BUILTIN.iter().any(|s| s.name() == name) || registered.iter().any(|a| a.id() == id || a.name() == name) {
+        return Err(CoreError::Config(format!("suite {} (id {}) already registered", name, id)));
+    }
+    registered.push(aead);
+    Ok(())
+}
+
+fn registered(id: u8) -> Option<Arc<dyn Aead>> {
+    REGISTERED.read().iter().find(|a| a.id() == id).cloned()
+}
+
+/// Names of the built-in and registered suites, built-ins first.
+pub fn algorithms() -> Vec<&'static str> {
+    let mut names: Vec<_> = Suite::BUILTIN.iter().map(|s| s.name()).collect();
+    names.extend(REGISTERED.read().iter().map(|a| a.name()));
+    names
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Suite {
+    /// AES-256-GCM-SIV with 8 bytes of operation counter and 4 random bytes.
+    /// The original format; uniqueness rests on the counter.
+    #[default]
+    GcmSivCounter,
+    /// AES-256-GCM-SIV with a fully random 96-bit nonce. GCM-SIV stays safe
+    /// if a random nonce ever repeats (it only reveals equal plaintexts), so
+    /// this does not depend on the counter surviving restarts.
+    GcmSivRandom,
+    /// XChaCha20-Poly1305 with a random 192-bit nonce; collisions are
+    /// negligible, and it is fast without AES hardware.
+    XChaCha20Poly1305,
+    /// An [`Aead`] added with [`register`], by its ID. Only
+    /// [`Suite::from_id`] and [`Suite::parse`] make one, so the ID is
+    /// always registered.
+    Registered(u8),
+}
+
+impl Suite {
+    const BUILTIN: [Suite; 3] = [Suite::GcmSivCounter, Suite::GcmSivRandom, Suite::XChaCha20Poly1305];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Suite::GcmSivCounter => 1,
+            Suite::GcmSivRandom => 2,
+            Suite::XChaCha20Poly1305 => 3,
+            Suite::Registered(id) => id,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Suite> {
+        Self::BUILTIN.into_iter().find(|s| s.id() == id)
+            .or_else(|| registered(id).map(|a| Suite::Registered(a.id())))
+    }
+
+    // The cipher behind a registered suite.
+    fn aead(id: u8) -> Arc<dyn Aead> {
+        registered(id).expect("registered suites stay registered")
+    }
+
+    /// Header suite byte: the suite id in the low nibble, the KDF code in
+    /// the high nibble. HKDF-SHA256 is zero, so older suite bytes still read.
+    pub fn wire_id(self, kdf: Kdf) -> u8 {
+        self.id() | kdf.code() << 4
+    }
+
+    pub fn from_wire_id(byte: u8) -> CoreResult<(Suite, Kdf)> {
+        let suite = Suite::from_id(byte & 0x0f).ok_or(CoreError::Format("unknown suite"))?;
+        let kdf = Kdf::from_code(byte >> 4).ok_or(CoreError::Format("unknown KDF"))?;
+        Ok((suite, kdf))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Suite::GcmSivCounter => "aes-256-gcm-siv",
+            Suite::GcmSivRandom => "aes-256-gcm-siv-random",
+            Suite::XChaCha20Poly1305 => "xchacha20-poly1305",
+            Suite::Registered(id) => Self::aead(id).name(),
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Suite> {
+        Self::BUILTIN.into_iter().find(|s| s.name() == name)
+            .or_else(|| REGISTERED.read().iter().find(|a| a.name() == name).map(|a| Suite::Registered(a.id())))
+    }
+
+    /// Whether this is one of the three built-in suites.
+    pub fn is_builtin(self) -> bool {
+        !matches!(self, Suite::Registered(_))
+    }
+
+    pub fn nonce_len(self) -> usize {
+        match self {
+            Suite::GcmSivCounter | Suite::GcmSivRandom => 12,
+            Suite::XChaCha20Poly1305 => 24,
+            Suite::Registered(id) => Self::aead(id).nonce_len(),
+        }
+    }
+
+    /// Fresh nonce for the operation with counter `ctr` sealing `data`
+    /// under `key`, hedged as [`entropy::fill_hedged`] describes.
+    pub(crate) fn nonce(self, ctr: u64, key: &[u8; 32], data: &[u8]) -> Vec<u8> {
+        if self == Suite::GcmSivCounter {
+            return crypto::counter_nonce(ctr, key, data).to_vec();
+        }
+        let mut nonce = vec![0u8; self.nonce_len()];
+        entropy::fill_hedged(&mut nonce, key, data);
+        nonce
+    }
+
+    pub(crate) fn seal(self, key: &[u8; 32], nonce: &[u8], data: &[u8]) -> CoreResult<Vec<u8>> {
+        match self {
+            Suite::GcmSivCounter | Suite::GcmSivRandom => crypto::aead_seal(key, nonce_array(nonce)?, data),
+            Suite::XChaCha20Poly1305 => crypto::xchacha_seal(key, nonce_array(nonce)?, data),
+            Suite::Registered(id) => Self::aead(id).seal(key, nonce, data, &[]),
+        }
+    }
+
+    pub(crate) fn open(self, key: &[u8; 32], nonce: &[u8], ct: &[u8]) -> CoreResult<Vec<u8>> {
+        match self {
+            Suite::GcmSivCounter | Suite::GcmSivRandom => crypto::aead_open(key, nonce_array(nonce)?, ct),
+            Suite::XChaCha20Poly1305 => crypto::xchacha_open(key, nonce_array(nonce)?, ct),
+            Suite::Registered(id) => Self::aead(id).open(key, nonce, ct, &[]),
+        }
+    }
+
+    pub(crate) fn seal_aad(self, key: &[u8; 32], nonce: &[u8], data: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>> {
+        match self {
+            Suite::GcmSivCounter | Suite::GcmSivRandom => crypto::aead_seal_aad(key, nonce_array(nonce)?, data, aad),
+            Suite::XChaCha20Poly1305 => crypto::xchacha_seal_aad(key, nonce_array(nonce)?, data, aad),
+            Suite::Registered(id) => Self::aead(id).seal(key, nonce, data, aad),
+        }
+    }
+
+    pub(crate) fn open_aad(self, key: &[u8; 32], nonce: &[u8], ct: &[u8], aad: &[u8]) -> CoreResult<Vec<u8>> {
+        match self {
+            Suite::GcmSivCounter | Suite::GcmSivRandom => crypto::aead_open_aad(key, nonce_array(nonce)?, ct, aad),
+            Suite::XChaCha20Poly1305 => crypto::xchacha_open_aad(key, nonce_array(nonce)?, ct, aad),
+            Suite::Registered(id) => Self::aead(id).open(key, nonce, ct, aad),
+        }
+    }
+}
+
+fn nonce_array<const N: usize>(nonce: &[u8]) -> CoreResult<&[u8; N]> {
+    nonce.try_into().map_err(|_| CoreError::Format("bad nonce length"))
+}
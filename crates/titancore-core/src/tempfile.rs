@@ -0,0 +1,207 @@
+//! Scratch files that never hold plaintext on disk.
+//!
+//! An [`EncryptedTempFile`] is a seekable read/write file whose contents are
+//! stored in [`TEMP_BLOCK`]-byte blocks, each sealed with AES-256-GCM-SIV
+//! under a random key that exists only in memory. Dropping the file zeroes
+//! the key and removes the ciphertext, so nothing written can be recovered
+//! afterwards, even from a copy of the disk.
+//!
+//! On disk each block is a fixed-size slot `nonce(12) | ciphertext(TEMP_BLOCK) | tag(16)`
+//! authenticated with its block index, so blocks cannot be moved around. The
+//! file length is kept in memory only; padding in the last block is zero.
+
+use crate::crypto;
+use crate::entropy;
+use crate::error::{CoreError, CoreResult};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Plaintext bytes per block.
+pub const TEMP_BLOCK: usize = 4096;
+const SLOT: u64 = (12 + TEMP_BLOCK + 16) as u64;
+
+struct Block {
+    index: u64,
+    data: Zeroizing<Vec<u8>>,
+    dirty: bool,
+}
+
+pub struct EncryptedTempFile {
+    file: File,
+    path: PathBuf,
+    key: Zeroizing<[u8; 32]>,
+    ctr: u64,
+    len: u64,
+    pos: u64,
+    /// Blocks written to disk so far.
+    stored: u64,
+    block: Option<Block>,
+}
+
+impl EncryptedTempFile {
+    /// Creates a file in the system temporary directory.
+    pub fn new() -> CoreResult<Self> {
+        Self::new_in(std::env::temp_dir())
+    }
+
+    /// Creates a file with a random name in `dir`.
+    pub fn new_in(dir: impl AsRef<Path>) -> CoreResult<Self> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        entropy::fill(key.as_mut())?;
+        let mut name = [0u8; 16];
+        entropy::fill(&mut name)?;
+        let path = dir.as_ref().join(format!("titancore-{}.tmp", hex::encode(name)));
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        // Windows cannot remove an open file; let the handle do it.
+        #[cfg(windows)]
+        std::os::windows::fs::OpenOptionsExt::custom_flags(&mut options, 0x0400_0000); // FILE_FLAG_DELETE_ON_CLOSE
+        let file = options.open(&path)?;
+        Ok(EncryptedTempFile { file, path, key, ctr: 0, len: 0, pos: 0, stored: 0, block: None })
+    }
+
+    /// Where the ciphertext lives.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Plaintext length.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Cuts or zero-extends the plaintext to `len` bytes.
+    pub fn set_len(&mut self, len: u64) -> CoreResult<()> {
+        if len < self.len {
+            let keep = len.div_ceil(TEMP_BLOCK as u64);
+            self.flush_block()?;
+            self.block = None;
+            self.stored = self.stored.min(keep);
+            self.file.set_len(self.stored * SLOT)?;
+            // Zero the tail of the new last block, so a later extension
+            // reads zeroes rather than the cut bytes.
+            let tail = (len % TEMP_BLOCK as u64) as usize;
+            if tail != 0 {
+                self.load(len / TEMP_BLOCK as u64)?;
+                if let Some(block) = self.block.as_mut() {
+                    block.data[tail..].fill(0);
+                    block.dirty = true;
+                }
+            }
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    fn load(&mut self, index: u64) -> CoreResult<()> {
+        if self.block.as_ref().is_some_and(|b| b.index == index) {
+            return Ok(());
+        }
+        self.flush_block()?;
+        let mut data = Zeroizing::new(vec![0u8; TEMP_BLOCK]);
+        if index < self.stored {
+            let mut slot = vec![0u8; SLOT as usize];
+            self.file.seek(SeekFrom::Start(index * SLOT))?;
+            self.file.read_exact(&mut slot)?;
+            let nonce: [u8; 12] = slot[..12].try_into().unwrap();
+            let pt = Zeroizing::new(crypto::aead_open_aad(&self.key, &nonce, &slot[12..], &index.to_be_bytes())?);
+            data.copy_from_slice(&pt);
+        }
+        self.block = Some(Block { index, data, dirty: false });
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> CoreResult<()> {
+        let Some(block) = self.block.take() else { return Ok(()) };
+        if block.dirty {
+            // Blocks skipped over by a seek past the end read back as zeroes.
+            let zero = Zeroizing::new(vec![0u8; TEMP_BLOCK]);
+            while self.stored < block.index {
+                self.write_slot(self.stored, &zero)?;
+                self.stored += 1;
+            }
+            self.write_slot(block.index, &block.data)?;
+            self.stored = self.stored.max(block.index + 1);
+        }
+        self.block = Some(Block { dirty: false, ..block });
+        Ok(())
+    }
+
+    fn write_slot(&mut self, index: u64, data: &[u8]) -> CoreResult<()> {
+        let nonce = crypto::counter_nonce(self.ctr, self.key.as_ref(), data);
+        self.ctr += 1;
+        let ct = crypto::aead_seal_aad(&self.key, &nonce, data, &index.to_be_bytes())?;
+        self.file.seek(SeekFrom::Start(index * SLOT))?;
+        self.file.write_all(&nonce)?;
+        self.file.write_all(&ct)?;
+        Ok(())
+    }
+}
+
+fn to_io(e: CoreError) -> io::Error {
+    match e {
+        CoreError::Decryption => io::Error::new(io::ErrorKind::InvalidData, e),
+        e => io::Error::other(e),
+    }
+}
+
+impl Read for EncryptedTempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.len {
+            return Ok(0);
+        }
+        let offset = (self.pos % TEMP_BLOCK as u64) as usize;
+        self.load(self.pos / TEMP_BLOCK as u64).map_err(to_io)?;
+        let n = buf.len().min(TEMP_BLOCK - offset).min((self.len - self.pos) as usize);
+        let block = self.block.as_ref().expect("block just loaded");
+        buf[..n].copy_from_slice(&block.data[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for EncryptedTempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = (self.pos % TEMP_BLOCK as u64) as usize;
+        self.load(self.pos / TEMP_BLOCK as u64).map_err(to_io)?;
+        let n = buf.len().min(TEMP_BLOCK - offset);
+        let block = self.block.as_mut().expect("block just loaded");
+        block.data[offset..offset + n].copy_from_slice(&buf[..n]);
+        block.dirty = true;
+        self.pos += n as u64;
+        self.len = self.len.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_block().map_err(to_io)?;
+        self.file.flush()
+    }
+}
+
+impl Seek for EncryptedTempFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl Drop for EncryptedTempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,307 @@
+//! Directory tree encryption for backups.
+//!
+//! [`Engine::encrypt_tree`] seals every regular file under a directory as
+//! its own chunked stream (`00000000.tcs`, `00000001.tcs`, ... in the
+//! destination) and writes a [`SignedManifest`] to [`MANIFEST_FILE`]
+//! listing each file's relative path, size and BLAKE3 hash and the hash of
+//! its encrypted object. The manifest is signed with the engine's
+//! checkpoint key and is not encrypted: paths and sizes are visible to
+//! whoever holds the backup.
+//!
+//! [`Engine::verify_tree`] checks the signature and every object against
+//! the manifest without any secret key; [`Engine::restore_tree`] also
+//! decrypts each object and checks the restored file's size and hash.
+//! Symbolic links and special files are skipped.
+
+use crate::audit::{OpType, Outcome};
+use crate::crypto;
+use crate::engine::Engine;
+use crate::envelope::Reader;
+use crate::error::{CoreError, CoreResult};
+use crate::shred;
+use crate::stream::StreamOptions;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+pub const MANIFEST_MAGIC: &[u8; 4] = b"TCTM";
+pub const MANIFEST_VERSION: u8 = 1;
+/// Name of the manifest inside an encrypted tree.
+pub const MANIFEST_FILE: &str = "manifest.tctm";
+
+/// One file of an encrypted tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Relative to the tree root, `/`-separated.
+    pub path: String,
+    pub size: u64,
+    /// BLAKE3 of the plaintext.
+    pub hash: [u8; 32],
+    /// BLAKE3 of the encrypted object.
+    pub object_hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeManifest {
+    pub fingerprint: [u8; 32],
+    /// Unix seconds.
+    pub created: u64,
+    /// In object order: entry `i` is stored as [`object_name`]`(i)`.
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Object file name for the `index`th entry.
+pub fn object_name(index: usize) -> String {
+    format!("{:08}.tcs", index)
+}
+
+impl TreeManifest {
+    /// `magic(4) | version(1) | fingerprint(32) | created(8) | count(4)`, then per entry
+    /// `path_len(2) | path | size(8) | hash(32) | object_hash(32)`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(49 + self.entries.len() * 96);
+        out.extend_from_slice(MANIFEST_MAGIC);
+        out.push(MANIFEST_VERSION);
+        out.extend_from_slice(&self.fingerprint);
+        out.extend_from_slice(&self.created.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for e in &self.entries {
+            out.extend_from_slice(&(e.path.len() as u16).to_be_bytes());
+            out.extend_from_slice(e.path.as_bytes());
+            out.extend_from_slice(&e.size.to_be_bytes());
+            out.extend_from_slice(&e.hash);
+            out.extend_from_slice(&e.object_hash);
+        }
+        out
+    }
+
+    fn read(r: &mut Reader<'_>) -> CoreResult<Self> {
+        if r.take(4)? != MANIFEST_MAGIC {
+            return Err(CoreError::Format("bad magic"));
+        }
+        if r.take(1)?[0] != MANIFEST_VERSION {
+            return Err(CoreError::Format("unsupported version"));
+        }
+        let fingerprint = r.array()?;
+        let created = u64::from_be_bytes(r.array()?);
+        let count = u32::from_be_bytes(r.array()?);
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            let path = String::from_utf8(r.take(len)?.to_vec()).map_err(|_| CoreError::Format("bad manifest path"))?;
+            let size = u64::from_be_bytes(r.array()?);
+            entries.push(ManifestEntry { path, size, hash: r.array()?, object_hash: r.array()? });
+        }
+        Ok(TreeManifest { fingerprint, created, entries })
+    }
+
+    pub fn sign(self, public_key: &[u8], secret_key: &[u8]) -> CoreResult<SignedManifest> {
+        let signature = crypto::sign(secret_key, &self.to_bytes())?;
+        Ok(SignedManifest { manifest: self, public_key: public_key.to_vec(), signature })
+    }
+}
+
+/// A [`TreeManifest`] with a Dilithium5 signature and the signer's public
+/// key. As with checkpoints, verify against a key you trust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedManifest {
+    pub manifest: TreeManifest,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedManifest {
+    /// `body | pk_len(2) | public_key | signature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.manifest.to_bytes();
+        out.extend_from_slice(&(self.public_key.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut r = Reader { buf: bytes };
+        let manifest = TreeManifest::read(&mut r)?;
+        let pk_len = u16::from_be_bytes(r.array()?) as usize;
+        let public_key = r.take(pk_len)?.to_vec();
+        if r.buf.is_empty() {
+            return Err(CoreError::Format("manifest without signature"));
+        }
+        Ok(SignedManifest { manifest, public_key, signature: r.buf.to_vec() })
+    }
+
+    /// True if the signature is valid under `trusted_pk`.
+    pub fn verify(&self, trusted_pk: &[u8]) -> bool {
+        crypto::verify_signature(trusted_pk, &self.manifest.to_bytes(), &self.signature)
+    }
+}
+
+/// Counts and hashes what passes through.
+struct Hashing<T> {
+    inner: T,
+    hasher: blake3::Hasher,
+    len: u64,
+}
+
+impl<T> Hashing<T> {
+    fn new(inner: T) -> Self {
+        Hashing { inner, hasher: blake3::Hasher::new(), len: 0 }
+    }
+
+    fn digest(&self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Engine {
+    /// Encrypts every regular file under `src` to `pk_bytes` into `dst`
+    /// (created if missing) and writes the signed manifest there. Each file
+    /// gets its own audit entry; the manifest is recorded as a `sign` event
+    /// bound to its BLAKE3 hash. The tree counts as one request against the
+    /// rate limit. With [`crate::EngineConfig::shred_sources`] set, the
+    /// source files are shredded once the manifest is written.
+    pub fn encrypt_tree(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>, pk_bytes: &[u8], chunk_size: usize,
+                        context: &[u8]) -> CoreResult<SignedManifest> {
+        let res = self.try_encrypt_tree(src.as_ref(), dst.as_ref(), pk_bytes, chunk_size, context);
+        self.audited(OpType::Encrypt, &[], res)
+    }
+
+    fn try_encrypt_tree(&self, src: &Path, dst: &Path, pk_bytes: &[u8], chunk_size: usize, context: &[u8]) -> CoreResult<SignedManifest> {
+        self.check_rate_limit()?;
+        self.recipient_key(pk_bytes, 0)?;
+        fs::create_dir_all(dst)?;
+        let mut files = Vec::new();
+        collect_files(src, &fs::canonicalize(dst)?, &mut files)?;
+        let options = StreamOptions { chunk_size, ..StreamOptions::default() };
+
+        let mut entries = Vec::with_capacity(files.len());
+        for (i, file) in files.iter().enumerate() {
+            let path = relative_path(src, file)?;
+            let mut reader = Hashing::new(io::BufReader::new(fs::File::open(file)?));
+            let mut writer = Hashing::new(io::BufWriter::new(fs::File::create(dst.join(object_name(i)))?));
+            self.try_seal_stream(&mut reader, &mut writer, pk_bytes, &options, context, false)?;
+            entries.push(ManifestEntry { path, size: reader.len, hash: reader.digest(), object_hash: writer.digest() });
+        }
+
+        let manifest = TreeManifest { fingerprint: self.fingerprint, created: self.clock().now_ms() / 1000, entries };
+        let signed = manifest.sign(&self.signing_key.0, &self.signing_key.1.unwrapped())?;
+        let bytes = signed.to_bytes();
+        fs::write(dst.join(MANIFEST_FILE), &bytes)?;
+        self.record_event(OpType::Sign, Outcome::Success, blake3::hash(&signed.manifest.to_bytes()).as_bytes())?;
+        for file in &files {
+            self.shred_source(file)?;
+        }
+        Ok(signed)
+    }
+
+    /// Checks the manifest in `dir` against `trusted_pk` and every object's
+    /// hash against it. Fails with [`CoreError::Revoked`] if `trusted_pk`
+    /// has been revoked and [`CoreError::Format`] on a bad signature, a
+    /// changed object or an unsafe path.
+    pub fn verify_tree(&self, dir: impl AsRef<Path>, trusted_pk: &[u8]) -> CoreResult<SignedManifest> {
+        let dir = dir.as_ref();
+        self.ensure_not_revoked(trusted_pk)?;
+        let signed = SignedManifest::from_bytes(&fs::read(dir.join(MANIFEST_FILE))?)?;
+        if !signed.verify(trusted_pk) {
+            return Err(CoreError::Format("manifest signature invalid"));
+        }
+        for (i, entry) in signed.manifest.entries.iter().enumerate() {
+            safe_path(&entry.path)?;
+            let mut object = Hashing::new(fs::File::open(dir.join(object_name(i)))?);
+            io::copy(&mut object, &mut io::sink())?;
+            if object.digest() != entry.object_hash {
+                return Err(CoreError::Format("object does not match manifest"));
+            }
+        }
+        Ok(signed)
+    }
+
+    /// [`Engine::verify_tree`], then decrypts every object of `dir` into
+    /// `out` under its recorded path. A file whose size or hash differs
+    /// from the manifest is shredded and the restore stops.
+    pub fn restore_tree(&self, dir: impl AsRef<Path>, out: impl AsRef<Path>, sk_bytes: &[u8], trusted_pk: &[u8],
+                        context: &[u8]) -> CoreResult<SignedManifest> {
+        let (dir, out) = (dir.as_ref(), out.as_ref());
+        let signed = self.verify_tree(dir, trusted_pk)?;
+        for (i, entry) in signed.manifest.entries.iter().enumerate() {
+            let target = out.join(safe_path(&entry.path)?);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let reader = io::BufReader::new(fs::File::open(dir.join(object_name(i)))?);
+            let mut writer = Hashing::new(io::BufWriter::new(fs::File::create(&target)?));
+            let res = self.open_stream(reader, &mut writer, sk_bytes, context).and_then(|_| {
+                match writer.len == entry.size && writer.digest() == entry.hash {
+                    true => Ok(()),
+                    false => Err(CoreError::Format("restored file does not match manifest")),
+                }
+            });
+            if let Err(e) = res {
+                drop(writer);
+                shred::discard(&target);
+                return Err(e);
+            }
+        }
+        Ok(signed)
+    }
+}
+
+// Regular files under `dir` in path order, skipping `exclude` (the
+// destination, if it lies inside the source).
+pub(crate) fn collect_files(dir: &Path, exclude: &Path, out: &mut Vec<PathBuf>) -> CoreResult<()> {
+    let mut children = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<io::Result<Vec<_>>>()?;
+    children.sort();
+    for path in children {
+        let kind = fs::symlink_metadata(&path)?.file_type();
+        if kind.is_dir() && fs::canonicalize(&path)? != exclude {
+            collect_files(&path, exclude, out)?;
+        } else if kind.is_file() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn relative_path(root: &Path, file: &Path) -> CoreResult<String> {
+    let rel = file.strip_prefix(root).map_err(|_| CoreError::Config("file outside the tree".into()))?;
+    let parts = rel.components().map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| CoreError::Config(format!("path is not UTF-8: {}", rel.display())))?;
+    let path = parts.join("/");
+    if path.len() > u16::MAX as usize {
+        return Err(CoreError::Config(format!("path too long: {}", rel.display())));
+    }
+    Ok(path)
+}
+
+// A manifest path as a relative path that cannot leave the restore root.
+fn safe_path(path: &str) -> CoreResult<PathBuf> {
+    let rel = PathBuf::from_iter(path.split('/'));
+    if path.is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(CoreError::Format("unsafe path in manifest"));
+    }
+    Ok(rel)
+}
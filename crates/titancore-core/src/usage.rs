@@ -0,0 +1,260 @@
+//! Lifetime use of each recipient key.
+//!
+//! Every sealing call to a recipient key counts one operation, plus the
+//! plaintext bytes it protects, under the key's id (see
+//! [`crate::cert::key_id`]) in the engine's [`UsageStore`]. Single-shot
+//! seals and rewraps count their payload up front; streams count theirs
+//! when they finish; archives and multipart uploads count the operation
+//! only. Unlike [`Engine::set_key_limit`], which limits a rate and starts
+//! over with the engine, these totals only grow, and [`FileUsageStore`]
+//! keeps them across restarts.
+//!
+//! [`Engine::set_key_usage_cap`] bounds a key's lifetime operations or
+//! bytes. A key at its cap fails with [`CoreError::RekeyRequired`], so the
+//! holder must rotate to a new key.
+
+use crate::audit::{FailureReason, OpType, Outcome};
+use crate::engine::Engine;
+use crate::error::{CoreError, CoreResult};
+use crate::stepup::SensitiveOp;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Totals for one key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyUsage {
+    pub operations: u64,
+    pub bytes: u64,
+    /// Engine clock times of the first and latest use; zero if unused.
+    pub first_used_ms: u64,
+    pub last_used_ms: u64,
+}
+
+impl KeyUsage {
+    fn add(&mut self, operations: u64, bytes: u64, now_ms: u64) {
+        if self.operations == 0 && self.first_used_ms == 0 {
+            self.first_used_ms = now_ms;
+        }
+        self.operations = self.operations.saturating_add(operations);
+        self.bytes = self.bytes.saturating_add(bytes);
+        self.last_used_ms = self.last_used_ms.max(now_ms);
+    }
+}
+
+/// Lifetime bounds for one key; `None` leaves that dimension open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageCap {
+    pub max_operations: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl UsageCap {
+    // True if one more operation of `bytes` would go past the cap.
+    fn exceeded_by(&self, usage: &KeyUsage, bytes: u64) -> bool {
+        self.max_operations.is_some_and(|max| usage.operations >= max)
+            || self.max_bytes.is_some_and(|max| usage.bytes >= max || usage.bytes.saturating_add(bytes) > max)
+    }
+}
+
+/// Where key usage totals are kept.
+pub trait UsageStore: Send + Sync {
+    /// Totals for `key_id`, zero if it was never used.
+    fn get(&self, key_id: &[u8; 32]) -> CoreResult<KeyUsage>;
+
+    /// Adds `operations` and `bytes` to `key_id` at `now_ms` and returns
+    /// the new totals.
+    fn add(&self, key_id: &[u8; 32], operations: u64, bytes: u64, now_ms: u64) -> CoreResult<KeyUsage>;
+}
+
+impl fmt::Debug for dyn UsageStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UsageStore")
+    }
+}
+
+/// Totals for this process only.
+#[derive(Default)]
+pub struct MemoryUsageStore {
+    usage: Mutex<HashMap<[u8; 32], KeyUsage>>,
+}
+
+impl MemoryUsageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl UsageStore for MemoryUsageStore {
+    fn get(&self, key_id: &[u8; 32]) -> CoreResult<KeyUsage> {
+        Ok(self.usage.lock().get(key_id).copied().unwrap_or_default())
+    }
+
+    fn add(&self, key_id: &[u8; 32], operations: u64, bytes: u64, now_ms: u64) -> CoreResult<KeyUsage> {
+        let mut usage = self.usage.lock();
+        let entry = usage.entry(*key_id).or_default();
+        entry.add(operations, bytes, now_ms);
+        Ok(*entry)
+    }
+}
+
+/// Totals in a JSON file, rewritten and synced on every use and replaced
+/// atomically, so a crash loses no counted use. One process should own
+/// the file.
+#[cfg(feature = "fs")]
+pub struct FileUsageStore {
+    path: std::path::PathBuf,
+    usage: Mutex<HashMap<[u8; 32], KeyUsage>>,
+}
+
+#[cfg(feature = "fs")]
+impl FileUsageStore {
+    /// Loads the totals at `path`; a missing file starts empty.
+    pub fn open(path: impl Into<std::path::PathBuf>) -> CoreResult<Self> {
+        let path = path.into();
+        let usage = match std::fs::read_to_string(&path) {
+            Ok(text) => parse_usage(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(FileUsageStore { path, usage: Mutex::new(usage) })
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    fn save(&self, usage: &HashMap<[u8; 32], KeyUsage>) -> CoreResult<()> {
+        use std::io::Write;
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(usage_json(usage).as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl UsageStore for FileUsageStore {
+    fn get(&self, key_id: &[u8; 32]) -> CoreResult<KeyUsage> {
+        Ok(self.usage.lock().get(key_id).copied().unwrap_or_default())
+    }
+
+    fn add(&self, key_id: &[u8; 32], operations: u64, bytes: u64, now_ms: u64) -> CoreResult<KeyUsage> {
+        let mut usage = self.usage.lock();
+        let before = usage.get(key_id).copied();
+        let entry = usage.entry(*key_id).or_default();
+        entry.add(operations, bytes, now_ms);
+        let after = *entry;
+        if let Err(e) = self.save(&usage) {
+            // Keep memory in step with the file.
+            match before {
+                Some(before) => usage.insert(*key_id, before),
+                None => usage.remove(key_id),
+            };
+            return Err(e);
+        }
+        Ok(after)
+    }
+}
+
+/// `{"version": 1, "keys": {key_id_hex: {"operations", "bytes",
+/// "first_used_ms", "last_used_ms"}}}`
+#[cfg(feature = "fs")]
+fn usage_json(usage: &HashMap<[u8; 32], KeyUsage>) -> String {
+    let keys: serde_json::Map<String, serde_json::Value> = usage.iter().map(|(id, u)| {
+        (hex::encode(id), serde_json::json!({
+            "operations": u.operations,
+            "bytes": u.bytes,
+            "first_used_ms": u.first_used_ms,
+            "last_used_ms": u.last_used_ms,
+        }))
+    }).collect();
+    serde_json::json!({ "version": 1, "keys": keys }).to_string()
+}
+
+#[cfg(feature = "fs")]
+fn parse_usage(text: &str) -> CoreResult<HashMap<[u8; 32], KeyUsage>> {
+    use serde_json::Value;
+    let bad = CoreError::Format("bad key usage file");
+    let value: Value = serde_json::from_str(text).map_err(|_| bad.clone())?;
+    if value.get("version").and_then(Value::as_u64) != Some(1) {
+        return Err(CoreError::Format("unsupported key usage file version"));
+    }
+    let keys = value.get("keys").and_then(Value::as_object).ok_or(bad.clone())?;
+    keys.iter().map(|(id, u)| {
+        let mut key_id = [0u8; 32];
+        hex::decode_to_slice(id, &mut key_id).map_err(|_| bad.clone())?;
+        let num = |name: &str| u.get(name).and_then(Value::as_u64).ok_or(bad.clone());
+        Ok((key_id, KeyUsage {
+            operations: num("operations")?,
+            bytes: num("bytes")?,
+            first_used_ms: num("first_used_ms")?,
+            last_used_ms: num("last_used_ms")?,
+        }))
+    }).collect()
+}
+
+impl Engine {
+    /// Lifetime totals for the key with id `key_id`.
+    pub fn key_usage(&self, key_id: &[u8; 32]) -> CoreResult<KeyUsage> {
+        self.usage.get(key_id)
+    }
+
+    /// Bounds the lifetime use of `key_id`. Once a use would pass the cap,
+    /// sealing to the key fails with [`CoreError::RekeyRequired`] and is
+    /// recorded as a `failed` event bound to the key id. Needs a
+    /// [`SensitiveOp::PolicyChange`] grant under step-up, and the `admin`
+    /// permission once a role policy is installed.
+    pub fn set_key_usage_cap(&mut self, key_id: [u8; 32], cap: UsageCap) -> CoreResult<()> {
+        if cap.max_operations == Some(0) || cap.max_bytes == Some(0) {
+            return Err(CoreError::Config("key usage cap must be positive".into()));
+        }
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, &key_id, res)?;
+        self.usage_caps.insert(key_id, cap);
+        self.record_event(OpType::Rekey, Outcome::Success, &key_id)?;
+        Ok(())
+    }
+
+    /// Removes the cap on `key_id`; true if there was one. Its totals are
+    /// kept. Gated as [`Engine::set_key_usage_cap`] is.
+    pub fn clear_key_usage_cap(&mut self, key_id: &[u8; 32]) -> CoreResult<bool> {
+        let res = self.consume_step_up(SensitiveOp::PolicyChange);
+        self.audited(OpType::Rekey, key_id, res)?;
+        let removed = self.usage_caps.remove(key_id).is_some();
+        self.record_event(OpType::Rekey, Outcome::Success, key_id)?;
+        Ok(removed)
+    }
+
+    /// Every configured cap.
+    pub fn key_usage_caps(&self) -> Vec<([u8; 32], UsageCap)> {
+        self.usage_caps.iter().map(|(id, cap)| (*id, *cap)).collect()
+    }
+
+    /// Checks one use of `key_id` protecting `bytes` against its cap and,
+    /// if it fits, counts it.
+    pub(crate) fn count_key_use(&self, op: OpType, key_id: &[u8; 32], bytes: u64) -> CoreResult<()> {
+        let Some(cap) = self.usage_caps.get(key_id) else {
+            self.usage.add(key_id, 1, bytes, self.clock().now_ms())?;
+            return Ok(());
+        };
+        // Check and count together, so concurrent seals cannot both take
+        // the last use.
+        let _capped = self.usage_lock.lock();
+        if cap.exceeded_by(&self.usage.get(key_id)?, bytes) {
+            return self.refuse_key(op, Outcome::Failed, FailureReason::UsageCap, key_id,
+                                   CoreError::RekeyRequired("key usage cap reached; rotate to a new key"));
+        }
+        self.usage.add(key_id, 1, bytes, self.clock().now_ms())?;
+        Ok(())
+    }
+
+    /// Counts `bytes` more for a use already counted, e.g. a finished
+    /// stream.
+    pub(crate) fn count_key_bytes(&self, key_id: &[u8; 32], bytes: u64) -> CoreResult<()> {
+        self.usage.add(key_id, 0, bytes, self.clock().now_ms())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Periodic re-reading of the audit log, so a log edited, truncated or
+//! deleted behind the engine's back is noticed while the engine runs rather
+//! than at the next restart or audit.
+//!
+//! [`crate::Engine::start_watchdog`] runs [`crate::Engine::check_chain`] on
+//! a dedicated thread and hands each new [`SignedAlarm`] to an
+//! [`AlarmHandler`].
+
+use crate::audit::alarm::{AlarmReason, SignedAlarm};
+use crate::engine::ChainProbe;
+use crate::error::{CoreError, CoreResult};
+use crate::idle::IdleKey;
+use parking_lot::{Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Receives alarms raised by the watchdog.
+pub trait AlarmHandler: Send + Sync {
+    fn alarm(&self, alarm: &SignedAlarm) -> CoreResult<()>;
+}
+
+impl<F> AlarmHandler for F
+where
+    F: Fn(&SignedAlarm) -> CoreResult<()> + Send + Sync,
+{
+    fn alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        self(alarm)
+    }
+}
+
+/// Counts from a [`Watchdog`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchdogStats {
+    pub checks: u64,
+    pub alarms: u64,
+    /// Checks that failed outright, and alarms the sink or handler refused.
+    pub failed: u64,
+    pub last_alarm: Option<SignedAlarm>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+    signing_key: Mutex<(Vec<u8>, IdleKey)>,
+    checks: AtomicU64,
+    alarms: AtomicU64,
+    failed: AtomicU64,
+    last_alarm: Mutex<Option<SignedAlarm>>,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Checks the chain every interval from a dedicated thread. The thread
+/// signs with its own copy of the checkpoint key, which the engine updates
+/// when the key changes.
+pub struct Watchdog {
+    shared: Arc<Shared>,
+}
+
+impl Watchdog {
+    pub(crate) fn spawn(probe: ChainProbe, signing_key: (Vec<u8>, IdleKey), interval: Duration, tail: usize,
+                        handler: Box<dyn AlarmHandler>) -> CoreResult<Self> {
+        let shared = Arc::new(Shared { signing_key: Mutex::new(signing_key), ..Shared::default() });
+        let worker = shared.clone();
+        std::thread::Builder::new()
+            .name("titan-watchdog".into())
+            .spawn(move || watchdog_loop(probe, handler, interval, tail, worker))
+            .map_err(|e| CoreError::Config(format!("watchdog thread: {}", e)))?;
+        Ok(Watchdog { shared })
+    }
+
+    pub(crate) fn set_signing_key(&self, signing_key: (Vec<u8>, IdleKey)) {
+        *self.shared.signing_key.lock() = signing_key;
+    }
+
+    pub fn stats(&self) -> WatchdogStats {
+        WatchdogStats {
+            checks: self.shared.checks.load(Ordering::Relaxed),
+            alarms: self.shared.alarms.load(Ordering::Relaxed),
+            failed: self.shared.failed.load(Ordering::Relaxed),
+            last_alarm: self.shared.last_alarm.lock().clone(),
+            last_error: self.shared.last_error.lock().clone(),
+        }
+    }
+}
+
+fn watchdog_loop(probe: ChainProbe, handler: Box<dyn AlarmHandler>, interval: Duration, tail: usize, shared: Arc<Shared>) {
+    let fail = |e: CoreError| {
+        shared.failed.fetch_add(1, Ordering::Relaxed);
+        *shared.last_error.lock() = Some(e.to_string());
+    };
+    // What the log showed at the last alarm; the same state is not
+    // reported again until a check passes.
+    let mut reported: Option<(AlarmReason, [u8; 32], u64)> = None;
+    loop {
+        {
+            let deadline = Instant::now() + interval;
+            let mut stopped = shared.stopped.lock();
+            while !*stopped && !shared.wake.wait_until(&mut stopped, deadline).timed_out() {}
+            if *stopped {
+                return;
+            }
+        }
+        shared.checks.fetch_add(1, Ordering::Relaxed);
+        let alarm = match probe.inspect(tail) {
+            Ok(Some(alarm)) => alarm,
+            Ok(None) => {
+                reported = None;
+                continue;
+            }
+            Err(e) => {
+                fail(e);
+                continue;
+            }
+        };
+        let state = (alarm.reason, alarm.found_head, alarm.found_seq);
+        if reported == Some(state) {
+            continue;
+        }
+        reported = Some(state);
+        let signed = {
+            let key = shared.signing_key.lock();
+            alarm.sign(&key.0, &key.1.unwrapped())
+        };
+        let signed = match signed {
+            Ok(signed) => signed,
+            Err(e) => {
+                fail(e);
+                continue;
+            }
+        };
+        shared.alarms.fetch_add(1, Ordering::Relaxed);
+        *shared.last_alarm.lock() = Some(signed.clone());
+        // Stored outside the chain even if the handler then fails.
+        if let Err(e) = probe.store(&signed) {
+            fail(e);
+        }
+        if let Err(e) = handler.alarm(&signed) {
+            fail(e);
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        // Not joined, as with the anchor worker: a check in progress may be
+        // waiting on the GIL or the chain lock held by whoever drops us.
+        *self.shared.stopped.lock() = true;
+        self.shared.wake.notify_one();
+    }
+}
@@ -0,0 +1,164 @@
+//! UniFFI (Kotlin/Swift) bindings over `titancore-core`.
+
+use std::fmt;
+use std::sync::Arc;
+use titancore_core::{crypto, AuditEntry, CoreError, Engine, Envelope, MemorySink};
+use zeroize::Zeroizing;
+
+uniffi::setup_scaffolding!();
+
+/// Foreign-facing mirror of [`CoreError`]; surfaces as `TitanException`
+/// (Kotlin) / `TitanError` (Swift).
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum TitanError {
+    RateLimited(String),
+    Unauthorized(String),
+    InvalidKey(String),
+    KeyFormat(String),
+    Mnemonic(String),
+    Kdf(String),
+    Entropy(String),
+    Encryption(String),
+    Decryption(String),
+    Format(String),
+    Storage(String),
+    Config(String),
+    RekeyRequired(String),
+    DegradedEntropy(String),
+    Certificate(String),
+    Revoked(String),
+    SelfTest(String),
+    Constraint(String),
+}
+
+impl From<CoreError> for TitanError {
+    fn from(e: CoreError) -> Self {
+        let msg = e.to_string();
+        match e {
+            CoreError::RateLimited => TitanError::RateLimited(msg),
+            CoreError::Unauthorized => TitanError::Unauthorized(msg),
+            CoreError::InvalidKey => TitanError::InvalidKey(msg),
+            CoreError::KeyFormat(_) => TitanError::KeyFormat(msg),
+            CoreError::Mnemonic(_) => TitanError::Mnemonic(msg),
+            CoreError::Kdf => TitanError::Kdf(msg),
+            CoreError::Entropy => TitanError::Entropy(msg),
+            CoreError::Encryption => TitanError::Encryption(msg),
+            CoreError::Decryption => TitanError::Decryption(msg),
+            CoreError::Format(_) => TitanError::Format(msg),
+            CoreError::Storage(_) => TitanError::Storage(msg),
+            CoreError::Config(_) => TitanError::Config(msg),
+            CoreError::RekeyRequired(_) => TitanError::RekeyRequired(msg),
+            CoreError::DegradedEntropy(_) => TitanError::DegradedEntropy(msg),
+            CoreError::Certificate(_) => TitanError::Certificate(msg),
+            CoreError::Revoked => TitanError::Revoked(msg),
+            CoreError::SelfTest(_) => TitanError::SelfTest(msg),
+            CoreError::Constraint(_) => TitanError::Constraint(msg),
+        }
+    }
+}
+
+impl fmt::Display for TitanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TitanError::RateLimited(msg) | TitanError::Unauthorized(msg) | TitanError::InvalidKey(msg)
+            | TitanError::KeyFormat(msg) | TitanError::Mnemonic(msg) | TitanError::Kdf(msg)
+            | TitanError::Entropy(msg) | TitanError::Encryption(msg)
+            | TitanError::Decryption(msg) | TitanError::Format(msg) | TitanError::Storage(msg)
+            | TitanError::Config(msg) | TitanError::RekeyRequired(msg) | TitanError::DegradedEntropy(msg)
+            | TitanError::Certificate(msg) | TitanError::Revoked(msg) | TitanError::SelfTest(msg)
+            | TitanError::Constraint(msg) => f.write_str(msg),
+        }
+    }
+}
+
+type FfiResult<T> = Result<T, TitanError>;
+
+/// Kotlin/Swift engine. Audit entries are buffered in memory and drained by
+/// the app, which persists or uploads them alongside the envelopes.
+#[derive(uniffi::Object)]
+pub struct MobileEngine {
+    inner: Engine,
+    sink: Arc<MemorySink>,
+}
+
+#[derive(uniffi::Record)]
+pub struct SealResult {
+    pub envelope: Vec<u8>,
+    pub evidence: String,
+}
+
+#[derive(uniffi::Record)]
+pub struct KeyPair {
+    pub public_key: Vec<u8>,
+    pub secret_key: Vec<u8>,
+}
+
+#[uniffi::export]
+impl MobileEngine {
+    #[uniffi::constructor]
+    pub fn new(hw_info: String, seed: String) -> FfiResult<Arc<Self>> {
+        let sink = Arc::new(MemorySink::new());
+        let inner = Engine::new(&hw_info, &seed, Box::new(sink.clone()))?;
+        Ok(Arc::new(MobileEngine { inner, sink }))
+    }
+
+    pub fn seal(&self, data: Vec<u8>, public_key: Vec<u8>) -> FfiResult<SealResult> {
+        let (env, evidence) = self.inner.seal(&data, &public_key)?;
+        Ok(SealResult { envelope: env.to_bytes(), evidence })
+    }
+
+    pub fn fingerprint(&self) -> String {
+        hex::encode(self.inner.fingerprint())
+    }
+
+    /// Audit lines produced since the last call, in the flat-file format.
+    pub fn drain_audit(&self) -> Vec<String> {
+        self.sink.drain().iter().map(AuditEntry::to_line).collect()
+    }
+}
+
+#[uniffi::export]
+pub fn open(envelope: Vec<u8>, secret_key: Vec<u8>) -> FfiResult<Vec<u8>> {
+    let secret_key = Zeroizing::new(secret_key);
+    Ok(Envelope::from_bytes(&envelope)?.open(&secret_key)?)
+}
+
+#[uniffi::export]
+pub fn generate_keypair() -> KeyPair {
+    let (pk, sk) = crypto::generate_keypair();
+    KeyPair { public_key: pk, secret_key: sk.to_vec() }
+}
+
+/// Validates a Kyber-1024 public key and, if `expected_fingerprint` (hex
+/// key id) is given, that it names the key. Fails with `KeyFormat`.
+#[uniffi::export]
+pub fn import_public_key(public_key: Vec<u8>, expected_fingerprint: Option<String>) -> FfiResult<Vec<u8>> {
+    let expected: Option<[u8; 32]> = match expected_fingerprint {
+        Some(hex_id) => Some(hex::decode(&hex_id).ok().and_then(|v| v.try_into().ok())
+            .ok_or(TitanError::Format("bad fingerprint".into()))?),
+        None => None,
+    };
+    Ok(crypto::import_public_key(&public_key, expected.as_ref())?)
+}
+
+/// Checks that `evidence` is the chain link `envelope` produced on top of
+/// `prev_head` (both hex, as found in the audit log).
+#[uniffi::export]
+pub fn verify_evidence(envelope: Vec<u8>, prev_head: String, evidence: String) -> FfiResult<bool> {
+    let env = Envelope::from_bytes(&envelope)?;
+    let prev: [u8; 32] = hex::decode(&prev_head).ok().and_then(|v| v.try_into().ok())
+        .ok_or(TitanError::Format("bad chain head".into()))?;
+    Ok(hex::encode(env.evidence_hash(&prev)) == evidence.to_ascii_lowercase())
+}
+
+/// Checks that `audit_line` (from `drain_audit`) is the entry sealing
+/// `envelope` produced and that `evidence` (hex) is its head.
+#[uniffi::export]
+pub fn verify_evidence_for(envelope: Vec<u8>, evidence: String, audit_line: String) -> FfiResult<bool> {
+    let env = Envelope::from_bytes(&envelope)?;
+    let evidence: [u8; 32] = hex::decode(&evidence).ok().and_then(|v| v.try_into().ok())
+        .ok_or(TitanError::Format("bad evidence hash".into()))?;
+    let entry = AuditEntry::parse_line(audit_line.trim_end()).ok_or(TitanError::Format("bad audit line".into()))?;
+    Ok(titancore_core::verify_evidence_for(&env, &evidence, &entry))
+}
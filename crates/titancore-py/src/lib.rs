@@ -0,0 +1,4275 @@
+// pyo3 0.20 macros expand to impls that newer rustc flags as non-local.
+#![allow(non_local_definitions)]
+
+//! Python bindings (`import titancore_free`) over `titancore-core`.
+
+use pyo3::exceptions::{PyIOError, PyPermissionError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::buffer::PyBuffer;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyBytes, PyDict};
+use std::borrow::Cow;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use titancore_core::anchor::{Anchor, HttpAnchor, S3Anchor};
+use titancore_core::armor::{self, ArmorKind};
+use titancore_core::audit::merkle;
+use titancore_core::ceremony::{Ceremony, CeremonyPurpose, Commitment, Contribution, Reveal, SignedTranscript};
+use titancore_core::activation::{ActivationRequest, SignedActivation};
+use titancore_core::cert;
+use titancore_core::constraint::Constraints;
+use titancore_core::channel::{ChannelInitiator, ChannelResponder, SecureTransport};
+use titancore_core::cose::{self, CoseEncrypt};
+#[cfg(windows)]
+use titancore_core::dpapi;
+use titancore_core::error;
+use titancore_core::epoch::{EpochId, EpochKeys, Granularity};
+use titancore_core::escrow::{self, EscrowShare};
+use titancore_core::fido2::{self, LockedSecret};
+use titancore_core::jose::Jwe;
+use titancore_core::acvp;
+use titancore_core::kat;
+use titancore_core::kem;
+#[cfg(target_os = "macos")]
+use titancore_core::keychain;
+#[cfg(target_os = "linux")]
+use titancore_core::kernel_keyring;
+use titancore_core::mnemonic;
+use titancore_core::operation::{self, OperationScope};
+use titancore_core::multipart::{MultipartOpener, MultipartUpload, SignedPartManifest};
+use titancore_core::quorum::{Approval, DecryptionRequest, QuorumPolicy};
+use titancore_core::ratchet::RatchetSession;
+use titancore_core::ratelimit::{RateLimiter, RedisRateLimiter};
+use titancore_core::rbac::{CallerScope, Permission, RolePolicy};
+use titancore_core::revocation::{self, KeyStatus, Revocation, RevocationChecker, RevocationList, RevocationReason, RevocationSource,
+                                 SignedRevocation};
+use titancore_core::rollback::{FileHighWaterStore, HighWaterStore};
+use titancore_core::schema::{SchemaId, SCHEMA_ID_LEN};
+use titancore_core::shred;
+use titancore_core::stream::{Framing, StreamOpener, StreamOptions, StreamSealer};
+use titancore_core::stepup::{SensitiveOp, StepUpVerifier, Totp};
+use titancore_core::tempfile::EncryptedTempFile;
+use titancore_core::tree::{self, SignedManifest};
+use zeroize::Zeroizing;
+use titancore_core::{crypto, envelope, stream, suite, AlarmHandler, AuditEntry, AuditQuery, AuditSegment, AuditSink, BatchRoot, Certificate, CertificateBody, Checkpoint, CiphertextBinding, Clock, CoreError, CoreResult, DocumentPolicy, Engine, EngineConfig, ErrorContext, FailureReason, FieldType,
+                     EngineState, Envelope, FileSink, Keyring, KeyringEntry, KitProtection, KitSheet, RecoveryKit, TrustState, FileUsageStore, FixedClock, Identity, LogFormat, InclusionProof, Kdf, KdfParams, OffsetClock, OpType, Outcome, Protection, ProtectedMessage, RecordSchema, SchemaField, SchemaRegistry, SignedAlarm, SignedAttestation, SignedCheckpoint, SignedGenesis, SignedRotation, SignedSnapshot, SqliteSink, Suite, SyslogSink,
+                     SyncPolicy, SyslogTarget, SystemClock, TpmQuote, UsageCap};
+
+pyo3::create_exception!(titancore_free, RekeyRequired, PyRuntimeError,
+    "A counter or key-usage limit was reached; start a new engine/log or split the payload.");
+pyo3::create_exception!(titancore_free, DegradedEntropy, PyRuntimeError,
+    "The OS entropy source failed a health test; no further random values are drawn this process.");
+pyo3::create_exception!(titancore_free, KeyFormatError, PyValueError,
+    "A public key failed import validation; the message says what is wrong with it.");
+pyo3::create_exception!(titancore_free, ConstraintViolation, PyPermissionError,
+    "An envelope's decryption constraints refuse this engine; the message says which.");
+
+/// Code of a `ValueError` the bindings raise for an argument they reject
+/// before the engine sees it; codes of the bindings' own start at 100.
+const INVALID_ARGUMENT: (&str, u16) = ("invalid_argument", 100);
+/// Code of the `ValueError` raised when a protected message fails
+/// verification.
+const VERIFICATION_FAILED: (&str, u16) = ("verification_failed", 101);
+
+/// Sets `code`, `code_number` and `context` on the exception `err`.
+/// `context` is a dict of `operation`, `counter`, `operation_id`, `suite`
+/// and `key_id`, each `None` where the engine noted nothing.
+fn with_code(err: PyErr, (code, number): (&str, u16), context: ErrorContext) -> PyErr {
+    Python::with_gil(|py| {
+        let set = || -> PyResult<()> {
+            let value = err.value(py);
+            value.setattr("code", code)?;
+            value.setattr("code_number", number)?;
+            let dict = PyDict::new(py);
+            dict.set_item("operation", context.op.map(OpType::as_str))?;
+            dict.set_item("counter", context.counter)?;
+            dict.set_item("operation_id", context.operation_id.map(hex::encode))?;
+            dict.set_item("suite", context.suite.map(Suite::name))?;
+            dict.set_item("key_id", context.key_id.map(hex::encode))?;
+            value.setattr("context", dict)
+        };
+        set().expect("exception attributes are settable");
+    });
+    err
+}
+
+fn invalid_argument(msg: impl Into<String>) -> PyErr {
+    with_code(PyValueError::new_err(msg.into()), INVALID_ARGUMENT, ErrorContext::default())
+}
+
+fn to_py_err(e: CoreError) -> PyErr {
+    let code = (e.code(), e.code_number());
+    let context = error::take_context(&e);
+    let err = match e {
+        CoreError::RekeyRequired(_) => RekeyRequired::new_err(e.to_string()),
+        CoreError::DegradedEntropy(_) => DegradedEntropy::new_err(e.to_string()),
+        CoreError::KeyFormat(_) => KeyFormatError::new_err(e.to_string()),
+        CoreError::Constraint(_) => ConstraintViolation::new_err(e.to_string()),
+        CoreError::Storage(msg) => PyIOError::new_err(msg),
+        CoreError::Unauthorized | CoreError::Revoked => PyPermissionError::new_err(e.to_string()),
+        CoreError::Format(_) | CoreError::Config(_) | CoreError::Certificate(_) | CoreError::Mnemonic(_) => PyValueError::new_err(e.to_string()),
+        _ => PyRuntimeError::new_err(e.to_string()),
+    };
+    with_code(err, code, context)
+}
+
+fn batch_dict<'py>(py: Python<'py>, batch: &BatchRoot) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("first_counter", batch.first_counter)?;
+    dict.set_item("last_counter", batch.last_counter)?;
+    dict.set_item("size", batch.size)?;
+    dict.set_item("root", hex::encode(batch.root))?;
+    Ok(dict)
+}
+
+fn entry_dict<'py>(py: Python<'py>, entry: &AuditEntry) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("counter", entry.counter)?;
+    dict.set_item("seq", entry.seq)?;
+    dict.set_item("timestamp_ms", entry.timestamp_ms)?;
+    dict.set_item("op", entry.op.as_str())?;
+    dict.set_item("outcome", entry.outcome.as_str())?;
+    dict.set_item("prev", hex::encode(entry.prev))?;
+    dict.set_item("curr", hex::encode(entry.curr))?;
+    dict.set_item("clock_regressed", entry.clock_regressed)?;
+    dict.set_item("fips", entry.fips)?;
+    dict.set_item("role", entry.role.as_deref())?;
+    dict.set_item("reason", entry.reason.map(FailureReason::as_str))?;
+    dict.set_item("operation_id", entry.operation_id.map(hex::encode))?;
+    dict.set_item("correlation_id", entry.correlation_id.as_deref())?;
+    dict.set_item("key_id", entry.key_id.map(hex::encode))?;
+    Ok(dict)
+}
+
+// An audit entry given as a flat-file log line or as `entry_dict` makes it.
+fn entry_from(obj: &PyAny) -> PyResult<AuditEntry> {
+    if let Ok(line) = obj.extract::<&str>() {
+        return AuditEntry::parse_line(line.trim_end()).ok_or_else(|| invalid_argument("bad audit line"));
+    }
+    let dict: &PyDict = obj.downcast().map_err(|_| invalid_argument("audit entry must be a log line or a dict"))?;
+    let field = |name: &str| dict.get_item(name).ok().flatten().ok_or_else(|| invalid_argument(format!("audit entry has no {}", name)));
+    let hash = |name: &str| -> PyResult<[u8; 32]> {
+        let mut out = [0u8; 32];
+        hex::decode_to_slice(field(name)?.extract::<&str>()?, &mut out).map_err(|_| invalid_argument(format!("bad {}", name)))?;
+        Ok(out)
+    };
+    let optional = |name: &str| dict.get_item(name).ok().flatten();
+    let op: &str = field("op")?.extract()?;
+    let outcome: &str = field("outcome")?.extract()?;
+    Ok(AuditEntry {
+        prev: hash("prev")?,
+        curr: hash("curr")?,
+        counter: field("counter")?.extract()?,
+        timestamp_ms: optional("timestamp_ms").map(|v| v.extract()).transpose()?.unwrap_or(0),
+        op: OpType::parse(op).ok_or_else(|| invalid_argument(format!("unknown op: {}", op)))?,
+        outcome: Outcome::parse(outcome).ok_or_else(|| invalid_argument(format!("unknown outcome: {}", outcome)))?,
+        seq: optional("seq").map(|v| v.extract()).transpose()?.unwrap_or(0),
+        clock_regressed: optional("clock_regressed").map(|v| v.extract()).transpose()?.unwrap_or(false),
+        fips: optional("fips").map(|v| v.extract()).transpose()?.unwrap_or(false),
+        role: optional("role").map(|v| v.extract()).transpose()?.flatten(),
+        reason: match optional("reason").map(|v| v.extract::<Option<&str>>()).transpose()?.flatten() {
+            Some(reason) => Some(FailureReason::parse(reason).ok_or_else(|| invalid_argument(format!("unknown reason: {}", reason)))?),
+            None => None,
+        },
+        operation_id: match optional("operation_id").map(|v| v.extract::<Option<&str>>()).transpose()?.flatten() {
+            Some(id) => Some(operation_id(id)?),
+            None => None,
+        },
+        correlation_id: optional("correlation_id").map(|v| v.extract()).transpose()?.flatten(),
+        key_id: match optional("key_id").map(|v| v.extract::<Option<&str>>()).transpose()?.flatten() {
+            Some(id) => Some(hex_key_id(id)?),
+            None => None,
+        },
+    })
+}
+
+fn operation_id(id: &str) -> PyResult<[u8; 16]> {
+    let mut out = [0u8; 16];
+    hex::decode_to_slice(id, &mut out).map_err(|_| invalid_argument("bad operation id"))?;
+    Ok(out)
+}
+
+fn checkpoint_dict<'py>(py: Python<'py>, cp: &SignedCheckpoint) -> PyResult<&'py PyDict> {
+    checkpoint_fields(py, &cp.checkpoint, &cp.to_bytes())
+}
+
+fn checkpoint_fields<'py>(py: Python<'py>, cp: &Checkpoint, bytes: &[u8]) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(cp.fingerprint))?;
+    dict.set_item("counter", cp.counter)?;
+    dict.set_item("head", hex::encode(cp.head))?;
+    dict.set_item("timestamp", cp.timestamp)?;
+    dict.set_item("batch", cp.batch.as_ref().map(|b| batch_dict(py, b)).transpose()?)?;
+    dict.set_item("bytes", PyBytes::new(py, bytes))?;
+    Ok(dict)
+}
+
+fn alarm_dict<'py>(py: Python<'py>, signed: &SignedAlarm) -> PyResult<&'py PyDict> {
+    let a = &signed.alarm;
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(a.fingerprint))?;
+    dict.set_item("timestamp_ms", a.timestamp_ms)?;
+    dict.set_item("reason", a.reason.as_str())?;
+    dict.set_item("expected_head", hex::encode(a.expected_head))?;
+    dict.set_item("expected_seq", a.expected_seq)?;
+    dict.set_item("found_head", hex::encode(a.found_head))?;
+    dict.set_item("found_seq", a.found_seq)?;
+    dict.set_item("bytes", PyBytes::new(py, &signed.to_bytes()))?;
+    Ok(dict)
+}
+
+// Accepts `bytes` raw or as an armored block of `kind`.
+fn unarmor(kind: ArmorKind, bytes: Vec<u8>) -> PyResult<Vec<u8>> {
+    if !armor::is_armored(&bytes) {
+        return Ok(bytes);
+    }
+    armor::dearmor_as(kind, &bytes).map_err(to_py_err)
+}
+
+// [`unarmor`] for input read in place.
+fn unarmor_ref(kind: ArmorKind, bytes: &[u8]) -> PyResult<Cow<'_, [u8]>> {
+    if !armor::is_armored(bytes) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    armor::dearmor_as(kind, bytes).map(Cow::Owned).map_err(to_py_err)
+}
+
+/// A bytes-like argument: `bytes`, or any object exporting a C-contiguous
+/// buffer (`bytearray`, `memoryview`, `mmap`, NumPy arrays of any dtype,
+/// `pyarrow.Buffer`), viewed as its raw bytes. `bytes` and read-only
+/// buffers are read in place; writable buffers and other sequences of ints
+/// are copied, since another thread could write to them once the call
+/// releases the GIL.
+pub enum BytesLike<'py> {
+    Bytes(&'py [u8]),
+    Buffer(PyBuffer<u8>),
+    Owned(Vec<u8>),
+}
+
+impl<'py> FromPyObject<'py> for BytesLike<'py> {
+    fn extract(ob: &'py PyAny) -> PyResult<Self> {
+        if let Ok(bytes) = ob.downcast::<PyBytes>() {
+            return Ok(BytesLike::Bytes(bytes.as_bytes()));
+        }
+        // SAFETY: only queries whether `ob` exports a buffer.
+        if unsafe { pyo3::ffi::PyObject_CheckBuffer(ob.as_ptr()) } == 1 {
+            let buf = byte_buffer(ob)?;
+            if buf.readonly() {
+                return Ok(BytesLike::Buffer(buf));
+            }
+            return Ok(BytesLike::Owned(buf.to_vec(ob.py())?));
+        }
+        Ok(BytesLike::Owned(ob.extract()?))
+    }
+}
+
+impl std::ops::Deref for BytesLike<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            BytesLike::Bytes(bytes) => bytes,
+            // SAFETY: byte_buffer checked the buffer is C-contiguous bytes,
+            // and extract kept only read-only ones; the export keeps it
+            // alive and in place until dropped.
+            BytesLike::Buffer(buf) if buf.len_bytes() > 0 => unsafe {
+                std::slice::from_raw_parts(buf.buf_ptr() as *const u8, buf.len_bytes())
+            },
+            BytesLike::Buffer(_) => &[],
+            BytesLike::Owned(bytes) => bytes,
+        }
+    }
+}
+
+impl AsRef<[u8]> for BytesLike<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// A secret key argument, copied out of the Python object into a buffer
+/// that is wiped when the call returns.
+pub struct SecretArg(Zeroizing<Vec<u8>>);
+
+impl<'py> FromPyObject<'py> for SecretArg {
+    fn extract(ob: &'py PyAny) -> PyResult<Self> {
+        Ok(SecretArg(Zeroizing::new(ob.extract()?)))
+    }
+}
+
+impl SecretArg {
+    /// [`unarmor`] into another wiped buffer.
+    fn unarmor(self, kind: ArmorKind) -> PyResult<SecretArg> {
+        if !armor::is_armored(&self.0) {
+            return Ok(self);
+        }
+        armor::dearmor_as(kind, &self.0).map(|bytes| SecretArg(Zeroizing::new(bytes))).map_err(to_py_err)
+    }
+}
+
+impl std::ops::Deref for SecretArg {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// The buffer `ob` exports, reinterpreted as a flat run of bytes.
+fn byte_buffer(ob: &PyAny) -> PyResult<PyBuffer<u8>> {
+    // SAFETY: PyMemoryView_FromObject returns a new reference or NULL with
+    // an exception set.
+    let view: &PyAny = unsafe { ob.py().from_owned_ptr_or_err(pyo3::ffi::PyMemoryView_FromObject(ob.as_ptr()))? };
+    let flat = view.call_method1("cast", ("B",))
+        .map_err(|_| invalid_argument("buffer is not C-contiguous; copy it first (e.g. numpy.ascontiguousarray)"))?;
+    let buf = PyBuffer::<u8>::get(flat)?;
+    if !buf.is_c_contiguous() {
+        return Err(invalid_argument("buffer is not C-contiguous"));
+    }
+    Ok(buf)
+}
+
+/// One cell of a column for `encrypt_column`: `str` as UTF-8, anything
+/// else as [`BytesLike`].
+pub enum ColumnValue<'py> {
+    Text(&'py str),
+    Bytes(BytesLike<'py>),
+}
+
+impl<'py> FromPyObject<'py> for ColumnValue<'py> {
+    fn extract(ob: &'py PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>() {
+            Ok(text) => Ok(ColumnValue::Text(text)),
+            Err(_) => Ok(ColumnValue::Bytes(ob.extract()?)),
+        }
+    }
+}
+
+impl AsRef<[u8]> for ColumnValue<'_> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            ColumnValue::Text(text) => text.as_bytes(),
+            ColumnValue::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+// The cells of a column-like object, `None` for nulls. Arrow scalars are
+// converted with `as_py()`.
+fn column_cells(values: &PyAny) -> PyResult<Vec<Option<&PyAny>>> {
+    values.iter()?.map(|cell| {
+        let mut cell = cell?;
+        if cell.hasattr("as_py")? {
+            cell = cell.call_method0("as_py")?;
+        }
+        let null = cell.is_none() || cell.extract::<f64>().is_ok_and(f64::is_nan);
+        Ok((!null).then_some(cell))
+    }).collect()
+}
+
+// Copies `data` to the start of the writable buffer `out`; returns the length.
+fn write_into(out: &PyAny, data: &[u8]) -> PyResult<usize> {
+    let buf = byte_buffer(out)?;
+    if buf.readonly() {
+        return Err(invalid_argument("output buffer is read-only"));
+    }
+    if buf.len_bytes() < data.len() {
+        return Err(invalid_argument(format!("output buffer holds {} bytes, need {}", buf.len_bytes(), data.len())));
+    }
+    // SAFETY: checked writable, contiguous and long enough.
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), buf.buf_ptr() as *mut u8, data.len()) };
+    Ok(data.len())
+}
+
+fn maybe_armor(py: Python<'_>, kind: ArmorKind, bytes: &[u8], armored: bool) -> PyObject {
+    match armored {
+        true => PyBytes::new(py, armor::armor(kind, bytes).as_bytes()).into(),
+        false => PyBytes::new(py, bytes).into(),
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn sensitive_op(name: &str) -> PyResult<SensitiveOp> {
+    SensitiveOp::parse(name).ok_or_else(|| invalid_argument(format!("unknown operation: {}", name)))
+}
+
+// Grants `op` with `token` first, if one was passed.
+fn step_up(engine: &Engine, op: SensitiveOp, token: Option<&str>) -> PyResult<()> {
+    match token {
+        Some(token) => engine.authorize(op, token).map_err(to_py_err),
+        None => Ok(()),
+    }
+}
+
+fn hex_key_id(key_id: &str) -> PyResult<[u8; 32]> {
+    let mut id = [0u8; 32];
+    hex::decode_to_slice(key_id, &mut id).map_err(|_| invalid_argument("bad key id"))?;
+    Ok(id)
+}
+
+fn hex_fingerprint(fingerprint: &str) -> PyResult<[u8; 32]> {
+    let mut out = [0u8; 32];
+    hex::decode_to_slice(fingerprint, &mut out).map_err(|_| invalid_argument("bad fingerprint"))?;
+    Ok(out)
+}
+
+fn mac_key(key: &[u8]) -> PyResult<[u8; 32]> {
+    key.try_into().map_err(|_| invalid_argument("MAC key must be 32 bytes"))
+}
+
+fn index_key(key: Option<Vec<u8>>) -> PyResult<Option<[u8; 32]>> {
+    key.map(|key| key.as_slice().try_into().map_err(|_| invalid_argument("index key must be 32 bytes"))).transpose()
+}
+
+fn schema_id(id: &str) -> PyResult<SchemaId> {
+    let mut out = [0u8; SCHEMA_ID_LEN];
+    hex::decode_to_slice(id, &mut out).map_err(|_| invalid_argument("bad schema id"))?;
+    Ok(out)
+}
+
+// Python objects cross into the core as JSON, by way of the json module.
+fn to_json_value(py: Python<'_>, obj: &PyAny) -> PyResult<serde_json::Value> {
+    let text: String = py.import("json")?.call_method1("dumps", (obj,))?.extract()?;
+    serde_json::from_str(&text).map_err(|e| invalid_argument(format!("bad document: {}", e)))
+}
+
+fn from_json_value(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (value.to_string(),))?.into())
+}
+
+fn identity_from(public_key: Vec<u8>, secret_key: Vec<u8>) -> PyResult<Identity> {
+    let public_key = unarmor(ArmorKind::SigningPublicKey, public_key)?;
+    let secret_key = unarmor(ArmorKind::SigningSecretKey, secret_key)?;
+    Identity::new(&public_key, &secret_key).map_err(to_py_err)
+}
+
+fn wrapping_key(key: &[u8]) -> PyResult<[u8; 32]> {
+    key.try_into().map_err(|_| invalid_argument("wrapping key must be 32 bytes"))
+}
+
+// A wrapping key, or a threshold and custodian count, but not both.
+fn kit_protection(wrapping_key: Option<&[u8; 32]>, threshold: Option<u8>, custodians: Option<u8>) -> PyResult<KitProtection<'_>> {
+    match (wrapping_key, threshold, custodians) {
+        (Some(key), None, None) => Ok(KitProtection::Wrapped(key)),
+        (None, Some(threshold), Some(custodians)) => Ok(KitProtection::Shares { threshold, custodians }),
+        _ => Err(invalid_argument("pass either wrapping_key or both threshold and custodians")),
+    }
+}
+
+fn kit_pages(kit: &RecoveryKit) -> Vec<String> {
+    kit.to_text().iter().map(|page| page.to_string()).collect()
+}
+
+fn random_serial(serial: Option<u64>) -> PyResult<u64> {
+    if let Some(serial) = serial {
+        return Ok(serial);
+    }
+    let mut bytes = [0u8; 8];
+    titancore_core::entropy::fill(&mut bytes).map_err(to_py_err)?;
+    Ok(u64::from_be_bytes(bytes) >> 1)
+}
+
+fn parse_chain(chain: Vec<Vec<u8>>) -> PyResult<Vec<Certificate>> {
+    chain.into_iter().map(|c| Certificate::from_bytes(&unarmor(ArmorKind::Certificate, c)?).map_err(to_py_err)).collect()
+}
+
+fn manifest_dict<'py>(py: Python<'py>, signed: &SignedManifest) -> PyResult<&'py PyDict> {
+    let entries = signed.manifest.entries.iter().enumerate().map(|(i, e)| {
+        let entry = PyDict::new(py);
+        entry.set_item("path", &e.path)?;
+        entry.set_item("size", e.size)?;
+        entry.set_item("hash", hex::encode(e.hash))?;
+        entry.set_item("object", tree::object_name(i))?;
+        Ok(entry)
+    }).collect::<PyResult<Vec<_>>>()?;
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(signed.manifest.fingerprint))?;
+    dict.set_item("created", signed.manifest.created)?;
+    dict.set_item("entries", entries)?;
+    dict.set_item("bytes", PyBytes::new(py, &signed.to_bytes()))?;
+    Ok(dict)
+}
+
+fn certificate_dict<'py>(py: Python<'py>, cert: &Certificate) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("serial", cert.body.serial)?;
+    dict.set_item("subject", &cert.body.subject)?;
+    dict.set_item("fingerprint", cert.body.fingerprint.map(hex::encode))?;
+    dict.set_item("issuer", hex::encode(cert.body.issuer))?;
+    dict.set_item("public_key", PyBytes::new(py, &cert.body.public_key))?;
+    dict.set_item("not_before", cert.body.not_before)?;
+    dict.set_item("not_after", cert.body.not_after)?;
+    Ok(dict)
+}
+
+fn check_output_format(output_format: &str) -> PyResult<()> {
+    match output_format {
+        "native" | "cose" => Ok(()),
+        _ => Err(invalid_argument(format!("unknown output format: {}", output_format))),
+    }
+}
+
+/// Hands checkpoints to a Python callable on the anchor thread.
+struct PyAnchor(PyObject);
+
+impl Anchor for PyAnchor {
+    fn publish(&self, checkpoint: &SignedCheckpoint) -> CoreResult<()> {
+        Python::with_gil(|py| {
+            let dict = checkpoint_dict(py, checkpoint)?;
+            self.0.call1(py, (dict,)).map(|_| ())
+        })
+        .map_err(|e| CoreError::Storage(format!("anchor callback: {}", e)))
+    }
+}
+
+/// Hands alarms to a Python callable on the watchdog thread.
+struct PyAlarmHandler(Option<PyObject>);
+
+impl AlarmHandler for PyAlarmHandler {
+    fn alarm(&self, alarm: &SignedAlarm) -> CoreResult<()> {
+        let Some(callback) = &self.0 else { return Ok(()) };
+        Python::with_gil(|py| {
+            let dict = alarm_dict(py, alarm)?;
+            callback.call1(py, (dict,)).map(|_| ())
+        })
+        .map_err(|e| CoreError::Storage(format!("alarm callback: {}", e)))
+    }
+}
+
+/// Revocation records from a fixed list plus, optionally, a Python callable
+/// `lookup(key_id_hex)` returning a list of record bytes (or `None`).
+struct PyRevocationSource {
+    list: RevocationList,
+    lookup: Option<PyObject>,
+}
+
+impl RevocationSource for PyRevocationSource {
+    fn lookup(&self, key_id: &[u8; 32]) -> CoreResult<Vec<SignedRevocation>> {
+        let mut records = self.list.lookup(key_id)?;
+        if let Some(lookup) = &self.lookup {
+            let fetched = Python::with_gil(|py| lookup.call1(py, (hex::encode(key_id),))?.extract::<Option<Vec<Vec<u8>>>>(py))
+                .map_err(|e| CoreError::Storage(format!("revocation lookup: {}", e)))?;
+            for bytes in fetched.unwrap_or_default() {
+                records.push(SignedRevocation::from_bytes(&bytes)?);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Asks a Python callable `callback(operation, token)` whether a step-up
+/// token is good. Anything but `True`, including an exception, refuses it.
+struct PyStepUpCallback(PyObject);
+
+impl StepUpVerifier for PyStepUpCallback {
+    fn verify(&self, op: SensitiveOp, token: &str, _now_ms: u64) -> bool {
+        Python::with_gil(|py| self.0.call1(py, (op.as_str(), token))?.extract::<bool>(py)).unwrap_or(false)
+    }
+}
+
+/// Reads unix milliseconds from a Python callable. Falls back to the system
+/// clock if the callable raises or returns something else.
+struct PyCallbackClock {
+    callback: PyObject,
+    fallback: SystemClock,
+}
+
+impl Clock for PyCallbackClock {
+    fn now_ms(&self) -> u64 {
+        Python::with_gil(|py| self.callback.call0(py)?.extract::<u64>(py))
+            .unwrap_or_else(|_| self.fallback.now_ms())
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.fallback.monotonic()
+    }
+}
+
+/// Manually driven clock for deterministic tests: pass as `clock=` to
+/// `SovereignEngine`.
+#[pyclass(name = "FixedClock")]
+pub struct PyFixedClock {
+    inner: Arc<FixedClock>,
+}
+
+#[pymethods]
+impl PyFixedClock {
+    #[new]
+    #[pyo3(signature = (now_ms=0))]
+    fn new(now_ms: u64) -> Self {
+        PyFixedClock { inner: Arc::new(FixedClock::new(now_ms)) }
+    }
+
+    /// Sets wall time; setting it backwards simulates a clock step.
+    fn set(&self, now_ms: u64) {
+        self.inner.set(now_ms)
+    }
+
+    fn advance(&self, ms: u64) {
+        self.inner.advance(Duration::from_millis(ms))
+    }
+
+    #[getter]
+    fn now_ms(&self) -> u64 {
+        self.inner.now_ms()
+    }
+}
+
+/// Trusted recipient public keys by name, each with its fingerprint (the
+/// hex `key_id`) and a trust state: `"tofu"`, `"verified"` or `"revoked"`.
+/// Pass as `keyring=` to `vault_execute` to seal to a name. With `path`,
+/// the keyring is loaded from that file if it exists and saved after
+/// every change.
+#[pyclass(name = "Keyring")]
+pub struct PyKeyring {
+    inner: Keyring,
+    path: Option<PathBuf>,
+}
+
+impl PyKeyring {
+    fn changed(&self) -> PyResult<()> {
+        match &self.path {
+            Some(path) => self.inner.save(path).map_err(to_py_err),
+            None => Ok(()),
+        }
+    }
+}
+
+fn keyring_entry_dict<'py>(py: Python<'py>, entry: &KeyringEntry) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("name", &entry.name)?;
+    dict.set_item("public_key", PyBytes::new(py, &entry.public_key))?;
+    dict.set_item("fingerprint", hex::encode(entry.fingerprint))?;
+    dict.set_item("trust", entry.trust.as_str())?;
+    dict.set_item("added_ms", entry.added_ms)?;
+    Ok(dict)
+}
+
+#[pymethods]
+impl PyKeyring {
+    #[new]
+    #[pyo3(signature = (path=None))]
+    fn new(path: Option<PathBuf>) -> PyResult<Self> {
+        let inner = match &path {
+            Some(path) => Keyring::load(path).map_err(to_py_err)?,
+            None => Keyring::new(),
+        };
+        Ok(PyKeyring { inner, path })
+    }
+
+    /// Pins `public_key` as `name`. Raises `ValueError` if `name` already
+    /// holds a different key; `remove` it first.
+    #[pyo3(signature = (name, public_key, trust="verified"))]
+    fn add(&mut self, py: Python<'_>, name: &str, public_key: Vec<u8>, trust: &str) -> PyResult<PyObject> {
+        let public_key = unarmor(ArmorKind::PublicKey, public_key)?;
+        let trust = TrustState::parse(trust).ok_or_else(|| invalid_argument(format!("unknown trust state: {}", trust)))?;
+        let entry = self.inner.add(name, &public_key, trust, SystemClock::new().now_ms()).map_err(to_py_err)?.clone();
+        self.changed()?;
+        Ok(keyring_entry_dict(py, &entry)?.into())
+    }
+
+    /// `{"name", "public_key", "fingerprint", "trust", "added_ms"}` for
+    /// `name`, or `None`.
+    fn get(&self, py: Python<'_>, name: &str) -> PyResult<Option<PyObject>> {
+        self.inner.get(name).map(|e| Ok(keyring_entry_dict(py, e)?.into())).transpose()
+    }
+
+    /// The entry whose key has hex `key_id` `fingerprint`, or `None`.
+    fn find(&self, py: Python<'_>, fingerprint: &str) -> PyResult<Option<PyObject>> {
+        let fingerprint = hex_key_id(fingerprint)?;
+        self.inner.find(&fingerprint).map(|e| Ok(keyring_entry_dict(py, e)?.into())).transpose()
+    }
+
+    /// Marks `name` as checked out of band.
+    fn verify(&mut self, name: &str) -> PyResult<()> {
+        self.inner.set_trust(name, TrustState::Verified).map_err(to_py_err)?;
+        self.changed()
+    }
+
+    /// Refuses `name` from now on; the entry is kept so the name cannot be
+    /// pinned again by accident.
+    fn revoke(&mut self, name: &str) -> PyResult<()> {
+        self.inner.set_trust(name, TrustState::Revoked).map_err(to_py_err)?;
+        self.changed()
+    }
+
+    fn remove(&mut self, name: &str) -> PyResult<bool> {
+        let removed = self.inner.remove(name);
+        self.changed()?;
+        Ok(removed)
+    }
+
+    /// Every entry, in name order.
+    fn entries(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.inner.entries().map(|e| Ok(keyring_entry_dict(py, e)?.into())).collect()
+    }
+
+    /// Writes the keyring to `path`, or to the one it was opened with.
+    #[pyo3(signature = (path=None))]
+    fn save(&self, path: Option<PathBuf>) -> PyResult<()> {
+        let path = path.or_else(|| self.path.clone()).ok_or_else(|| invalid_argument("no path to save the keyring to"))?;
+        self.inner.save(path).map_err(to_py_err)
+    }
+
+    fn to_json(&self) -> String {
+        self.inner.to_json()
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        Ok(PyKeyring { inner: Keyring::from_json(json).map_err(to_py_err)?, path: None })
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.inner.get(name).is_some()
+    }
+}
+
+/// One participant's part in a `new_ceremony`: fresh entropy, kept until
+/// revealed. Send `commit()` first; once every participant's commitment
+/// is in, send `reveal()` to the engine running the ceremony, and to it
+/// alone.
+#[pyclass(name = "CeremonyContribution")]
+pub struct PyContribution {
+    ceremony: Ceremony,
+    inner: Contribution,
+}
+
+#[pymethods]
+impl PyContribution {
+    #[new]
+    fn new(ceremony: Vec<u8>, public_key: Vec<u8>) -> PyResult<Self> {
+        let ceremony = Ceremony::from_bytes(&ceremony).map_err(to_py_err)?;
+        let inner = Contribution::new(&ceremony, &unarmor(ArmorKind::SigningPublicKey, public_key)?).map_err(to_py_err)?;
+        Ok(PyContribution { ceremony, inner })
+    }
+
+    /// The signed commitment to the entropy.
+    fn commit(&self, py: Python<'_>, secret_key: Vec<u8>) -> PyResult<PyObject> {
+        let commitment = self.inner.commit(&unarmor(ArmorKind::SigningSecretKey, secret_key)?).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &commitment.to_bytes()).into())
+    }
+
+    /// The signed entropy, given every participant's commitment. Raises
+    /// `ValueError` if one is missing or does not verify.
+    fn reveal(&self, py: Python<'_>, commitments: Vec<Vec<u8>>, secret_key: Vec<u8>) -> PyResult<PyObject> {
+        let commitments = commitments.iter().map(|c| Commitment::from_bytes(c)).collect::<CoreResult<Vec<_>>>().map_err(to_py_err)?;
+        let secret_key = unarmor(ArmorKind::SigningSecretKey, secret_key)?;
+        let reveal = self.inner.reveal(&self.ceremony, &commitments, &secret_key).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &reveal.to_bytes()).into())
+    }
+}
+
+/// Data keys per hour, day or month (`granularity`), for
+/// `vault_seal_epoch`. Epochs are counted from the Unix epoch;
+/// `epoch_at()` gives the current one. Once `shred(epoch)` or
+/// `shred_before(epoch)` runs, nothing sealed in those epochs opens again.
+/// With `path`, the store is kept encrypted under the 32-byte
+/// `wrapping_key`: loaded from that file if it exists, else created, and
+/// saved after every change. Old copies of the file still hold shredded
+/// keys; keep none.
+#[pyclass(name = "EpochKeys")]
+pub struct PyEpochKeys {
+    inner: EpochKeys,
+    path: Option<(PathBuf, Zeroizing<[u8; 32]>)>,
+}
+
+impl PyEpochKeys {
+    fn changed(&self) -> PyResult<()> {
+        match &self.path {
+            Some((path, key)) => self.inner.save(path, key).map_err(to_py_err),
+            None => Ok(()),
+        }
+    }
+
+    fn epoch(&self, index: u32) -> EpochId {
+        EpochId { granularity: self.inner.granularity(), index }
+    }
+}
+
+#[pymethods]
+impl PyEpochKeys {
+    #[new]
+    #[pyo3(signature = (granularity="day", path=None, wrapping_key=None))]
+    fn new(granularity: &str, path: Option<PathBuf>, wrapping_key: Option<Vec<u8>>) -> PyResult<Self> {
+        let granularity = Granularity::parse(granularity).ok_or_else(|| invalid_argument(format!("unknown epoch granularity: {}", granularity)))?;
+        let path = match (path, wrapping_key) {
+            (Some(path), Some(key)) => Some((path, Zeroizing::new(self::wrapping_key(&key)?))),
+            (None, None) => None,
+            _ => return Err(invalid_argument("path and wrapping_key go together")),
+        };
+        let inner = match &path {
+            Some((path, key)) if path.exists() => EpochKeys::load(path, key).map_err(to_py_err)?,
+            _ => EpochKeys::new(granularity).map_err(to_py_err)?,
+        };
+        if inner.granularity() != granularity {
+            return Err(invalid_argument(format!("stored epoch keys are per {}", inner.granularity().as_str())));
+        }
+        let keys = PyEpochKeys { inner, path };
+        keys.changed()?;
+        Ok(keys)
+    }
+
+    #[getter]
+    fn granularity(&self) -> &'static str {
+        self.inner.granularity().as_str()
+    }
+
+    /// The epoch holding Unix time `unix_time`, by default now.
+    #[pyo3(signature = (unix_time=None))]
+    fn epoch_at(&self, unix_time: Option<u64>) -> PyResult<u32> {
+        Ok(self.inner.epoch_at(unix_time.unwrap_or_else(unix_now)).map_err(to_py_err)?.index)
+    }
+
+    /// Unix time at which `epoch` starts.
+    fn epoch_start(&self, epoch: u32) -> u64 {
+        self.epoch(epoch).start()
+    }
+
+    /// False once `epoch` has been shredded.
+    fn holds(&self, epoch: u32) -> bool {
+        self.inner.holds(&self.epoch(epoch))
+    }
+
+    /// Deletes the key of `epoch`; returns False if it was already gone.
+    fn shred(&mut self, epoch: u32) -> PyResult<bool> {
+        let shredded = self.inner.shred(&self.epoch(epoch)).map_err(to_py_err)?;
+        self.changed()?;
+        Ok(shredded)
+    }
+
+    /// Deletes the key of every epoch before `epoch`.
+    fn shred_before(&mut self, epoch: u32) -> PyResult<()> {
+        self.inner.shred_before(&self.epoch(epoch)).map_err(to_py_err)?;
+        self.changed()
+    }
+
+    /// The store encrypted under a 32-byte `wrapping_key`.
+    fn wrap(&self, py: Python<'_>, wrapping_key: Vec<u8>) -> PyResult<PyObject> {
+        let wrapped = self.inner.wrap(&self::wrapping_key(&wrapping_key)?).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &wrapped).into())
+    }
+
+    #[staticmethod]
+    fn unwrap(wrapped: Vec<u8>, wrapping_key: Vec<u8>) -> PyResult<Self> {
+        let inner = EpochKeys::unwrap(&wrapped, &self::wrapping_key(&wrapping_key)?).map_err(to_py_err)?;
+        Ok(PyEpochKeys { inner, path: None })
+    }
+}
+
+/// Record schemas and the migrations allowed between them. A schema is
+/// registered as a name, a version and a list of fields, each a dict with
+/// `"name"`, `"type"` (`"any"`, `"string"`, `"integer"`, `"number"`,
+/// `"boolean"`, `"object"` or `"array"`) and optionally `"required"`,
+/// `"protection"` (`"encrypt"`, `"blind_index"` or `"mask"`) and `"key_id"`
+/// (hex), the key an encrypted field is sealed to. Each version has its own
+/// hex ID, carried in the envelopes `vault_seal_record` makes. With `path`,
+/// the registry is loaded from that file if it exists and saved after every
+/// change.
+#[pyclass(name = "SchemaRegistry")]
+pub struct PySchemaRegistry {
+    inner: SchemaRegistry,
+    path: Option<PathBuf>,
+}
+
+impl PySchemaRegistry {
+    fn changed(&self) -> PyResult<()> {
+        match &self.path {
+            Some(path) => self.inner.save(path).map_err(to_py_err),
+            None => Ok(()),
+        }
+    }
+}
+
+fn schema_dict<'py>(py: Python<'py>, schema: &RecordSchema) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("id", hex::encode(schema.id()))?;
+    dict.set_item("name", &schema.name)?;
+    dict.set_item("version", schema.version)?;
+    let fields = schema.fields.iter().map(|f| {
+        let field = PyDict::new(py);
+        field.set_item("name", &f.name)?;
+        field.set_item("type", f.kind.as_str())?;
+        field.set_item("required", f.required)?;
+        field.set_item("protection", f.protection.map(Protection::as_str))?;
+        field.set_item("key_id", f.key_id.map(hex::encode))?;
+        Ok(field)
+    }).collect::<PyResult<Vec<_>>>()?;
+    dict.set_item("fields", fields)?;
+    Ok(dict)
+}
+
+#[pymethods]
+impl PySchemaRegistry {
+    #[new]
+    #[pyo3(signature = (path=None))]
+    fn new(path: Option<PathBuf>) -> PyResult<Self> {
+        let inner = match &path {
+            Some(path) => SchemaRegistry::load(path).map_err(to_py_err)?,
+            None => SchemaRegistry::new(),
+        };
+        Ok(PySchemaRegistry { inner, path })
+    }
+
+    /// Registers a schema and returns its ID. Raises `ValueError` for a bad
+    /// field, or if `name` already has a different schema at `version`.
+    fn register(&mut self, name: &str, version: u32, fields: Vec<&PyDict>) -> PyResult<String> {
+        let fields = fields.into_iter().map(|f| {
+            let get = |key: &str| f.get_item(key).ok().flatten().filter(|v| !v.is_none());
+            let name: String = get("name").ok_or_else(|| invalid_argument("schema field without a name"))?.extract()?;
+            let kind: &str = get("type").ok_or_else(|| invalid_argument(format!("field {} has no type", name)))?.extract()?;
+            let kind = FieldType::parse(kind).ok_or_else(|| invalid_argument(format!("unknown field type: {}", kind)))?;
+            let protection = get("protection").map(|p| {
+                let p: &str = p.extract()?;
+                Protection::parse(p).ok_or_else(|| invalid_argument(format!("unknown protection: {}", p)))
+            }).transpose()?;
+            let key_id = get("key_id").map(|k| hex_key_id(k.extract()?)).transpose()?;
+            let required = get("required").map(|r| r.extract()).transpose()?.unwrap_or(false);
+            Ok(SchemaField { name, kind, required, protection, key_id })
+        }).collect::<PyResult<Vec<_>>>()?;
+        let id = self.inner.register(RecordSchema { name: name.to_string(), version, fields }).map_err(to_py_err)?;
+        self.changed()?;
+        Ok(hex::encode(id))
+    }
+
+    /// `{"id", "name", "version", "fields"}` for hex `schema_id`, or `None`.
+    fn get(&self, py: Python<'_>, schema_id: &str) -> PyResult<Option<PyObject>> {
+        self.inner.get(&self::schema_id(schema_id)?).map(|s| Ok(schema_dict(py, s)?.into())).transpose()
+    }
+
+    /// The highest version registered under `name`, or `None`.
+    fn latest(&self, py: Python<'_>, name: &str) -> PyResult<Option<PyObject>> {
+        self.inner.latest(name).map(|s| Ok(schema_dict(py, s)?.into())).transpose()
+    }
+
+    /// Allows records of `from_id` to move to `to_id`, a later version of
+    /// the same schema, through `vault_migrate_record`.
+    fn add_migration(&mut self, from_id: &str, to_id: &str) -> PyResult<()> {
+        self.inner.add_migration(&schema_id(from_id)?, &schema_id(to_id)?).map_err(to_py_err)?;
+        self.changed()
+    }
+
+    /// Every schema, in the order registered.
+    fn schemas(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.inner.schemas().map(|s| Ok(schema_dict(py, s)?.into())).collect()
+    }
+
+    /// Writes the registry to `path`, or to the one it was opened with.
+    #[pyo3(signature = (path=None))]
+    fn save(&self, path: Option<PathBuf>) -> PyResult<()> {
+        let path = path.or_else(|| self.path.clone()).ok_or_else(|| invalid_argument("no path to save the registry to"))?;
+        self.inner.save(path).map_err(to_py_err)
+    }
+
+    fn to_json(&self) -> String {
+        self.inner.to_json()
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        Ok(PySchemaRegistry { inner: SchemaRegistry::from_json(json).map_err(to_py_err)?, path: None })
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.schemas().count()
+    }
+}
+
+/// Opening side of an engine-to-engine channel: send `hello()`, pass the
+/// reply to `finish()`, send the confirmation it returns.
+#[pyclass(name = "ChannelInitiator")]
+pub struct PyChannelInitiator {
+    inner: ChannelInitiator,
+}
+
+#[pymethods]
+impl PyChannelInitiator {
+    /// Authenticates with a Dilithium5 keypair; only a responder holding
+    /// `responder_public_key` is accepted.
+    #[new]
+    fn new(public_key: Vec<u8>, secret_key: Vec<u8>, responder_public_key: Vec<u8>) -> PyResult<Self> {
+        let public_key = unarmor(ArmorKind::SigningPublicKey, public_key)?;
+        let secret_key = unarmor(ArmorKind::SigningSecretKey, secret_key)?;
+        let responder = unarmor(ArmorKind::SigningPublicKey, responder_public_key)?;
+        let inner = ChannelInitiator::new(&public_key, &secret_key, &responder).map_err(to_py_err)?;
+        Ok(PyChannelInitiator { inner })
+    }
+
+    fn hello(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let hello = self.inner.hello().map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &hello).into())
+    }
+
+    /// Returns `(confirm, transport)`; raises `PermissionError` if the
+    /// responder is not the expected key.
+    fn finish(&mut self, py: Python<'_>, reply: Vec<u8>) -> PyResult<(PyObject, PySecureTransport)> {
+        let (confirm, transport) = py.allow_threads(|| self.inner.finish(&reply)).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &confirm).into(), PySecureTransport { inner: transport }))
+    }
+}
+
+/// Accepting side of an engine-to-engine channel: answer the hello with
+/// `reply()`, then pass the confirmation to `finish()`.
+#[pyclass(name = "ChannelResponder")]
+pub struct PyChannelResponder {
+    inner: ChannelResponder,
+}
+
+#[pymethods]
+impl PyChannelResponder {
+    /// Authenticates with a Dilithium5 keypair; only initiators holding one
+    /// of `trusted_initiators` are accepted.
+    #[new]
+    fn new(public_key: Vec<u8>, secret_key: Vec<u8>, trusted_initiators: Vec<Vec<u8>>) -> PyResult<Self> {
+        let public_key = unarmor(ArmorKind::SigningPublicKey, public_key)?;
+        let secret_key = unarmor(ArmorKind::SigningSecretKey, secret_key)?;
+        let trusted = trusted_initiators.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+        let inner = ChannelResponder::new(&public_key, &secret_key, trusted).map_err(to_py_err)?;
+        Ok(PyChannelResponder { inner })
+    }
+
+    fn reply(&mut self, py: Python<'_>, hello: Vec<u8>) -> PyResult<PyObject> {
+        let reply = py.allow_threads(|| self.inner.reply(&hello)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &reply).into())
+    }
+
+    /// Raises `PermissionError` for an untrusted initiator.
+    fn finish(&mut self, py: Python<'_>, confirm: Vec<u8>) -> PyResult<PySecureTransport> {
+        let transport = py.allow_threads(|| self.inner.finish(&confirm)).map_err(to_py_err)?;
+        Ok(PySecureTransport { inner: transport })
+    }
+}
+
+/// Encrypted records over a finished handshake. `send()` returns a
+/// `len(4) | record` frame to write to the socket; `recv()` takes one such
+/// frame. Frames must be received in the order they were sent.
+#[pyclass(name = "SecureTransport")]
+pub struct PySecureTransport {
+    inner: SecureTransport,
+}
+
+#[pymethods]
+impl PySecureTransport {
+    fn send(&mut self, py: Python<'_>, data: Vec<u8>) -> PyResult<PyObject> {
+        let frame = self.inner.seal_frame(&data).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &frame).into())
+    }
+
+    fn recv(&mut self, py: Python<'_>, frame: Vec<u8>) -> PyResult<PyObject> {
+        let data = self.inner.open_frame(&frame).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &data).into())
+    }
+
+    /// Hex transcript hash, the same on both ends.
+    #[getter]
+    fn session_id(&self) -> String {
+        hex::encode(self.inner.session_id())
+    }
+
+    #[getter]
+    fn peer_public_key(&self, py: Python<'_>) -> PyObject {
+        PyBytes::new(py, self.inner.peer_public_key()).into()
+    }
+}
+
+/// One side of a post-quantum double-ratchet conversation. Create with
+/// `RatchetSession.initiate` (sends first) or `RatchetSession.respond`;
+/// persist with `to_bytes()` / `RatchetSession.from_bytes()`.
+#[pyclass(name = "RatchetSession")]
+pub struct PyRatchetSession {
+    inner: RatchetSession,
+}
+
+#[pymethods]
+impl PyRatchetSession {
+    /// Session to the responder's published Kyber public key. `root_secret`
+    /// is anything both sides already share (may be empty).
+    #[staticmethod]
+    #[pyo3(signature = (remote_public_key, root_secret=Vec::new(), max_skip=titancore_core::ratchet::DEFAULT_MAX_SKIP))]
+    fn initiate(remote_public_key: Vec<u8>, root_secret: Vec<u8>, max_skip: u32) -> PyResult<Self> {
+        let remote = unarmor(ArmorKind::PublicKey, remote_public_key)?;
+        let inner = RatchetSession::initiate(&root_secret, &remote).map_err(to_py_err)?.with_max_skip(max_skip);
+        Ok(PyRatchetSession { inner })
+    }
+
+    /// Session answering messages to the published Kyber keypair.
+    #[staticmethod]
+    #[pyo3(signature = (public_key, secret_key, root_secret=Vec::new(), max_skip=titancore_core::ratchet::DEFAULT_MAX_SKIP))]
+    fn respond(public_key: Vec<u8>, secret_key: Vec<u8>, root_secret: Vec<u8>, max_skip: u32) -> PyResult<Self> {
+        let public_key = unarmor(ArmorKind::PublicKey, public_key)?;
+        let secret_key = unarmor(ArmorKind::SecretKey, secret_key)?;
+        let inner = RatchetSession::respond(&root_secret, &public_key, &secret_key).map_err(to_py_err)?.with_max_skip(max_skip);
+        Ok(PyRatchetSession { inner })
+    }
+
+    /// Restores a session saved with `to_bytes()`.
+    #[staticmethod]
+    fn from_bytes(state: Vec<u8>) -> PyResult<Self> {
+        Ok(PyRatchetSession { inner: RatchetSession::from_bytes(&state).map_err(to_py_err)? })
+    }
+
+    /// Session state including live keys; store it encrypted.
+    fn to_bytes(&self, py: Python<'_>) -> PyObject {
+        PyBytes::new(py, &self.inner.to_bytes()).into()
+    }
+
+    #[pyo3(signature = (data, context=None))]
+    fn seal(&mut self, py: Python<'_>, data: Vec<u8>, context: Option<Vec<u8>>) -> PyResult<PyObject> {
+        let context = context.unwrap_or_default();
+        let message = py.allow_threads(|| self.inner.seal(&data, &context)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &message).into())
+    }
+
+    /// Opens messages in any order; a failed message leaves the session
+    /// unchanged.
+    #[pyo3(signature = (message, context=None))]
+    fn open(&mut self, py: Python<'_>, message: Vec<u8>, context: Option<Vec<u8>>) -> PyResult<PyObject> {
+        let context = context.unwrap_or_default();
+        let data = py.allow_threads(|| self.inner.open(&message, &context)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &data).into())
+    }
+
+    #[getter]
+    fn skipped_keys(&self) -> usize {
+        self.inner.skipped_keys()
+    }
+}
+
+/// Parts of one upload from `SovereignEngine.multipart_upload`; `seal_part`
+/// may be called from several threads at once.
+#[pyclass(name = "MultipartUpload")]
+pub struct PyMultipartUpload {
+    inner: Option<MultipartUpload>,
+}
+
+impl PyMultipartUpload {
+    fn upload(&self) -> PyResult<&MultipartUpload> {
+        self.inner.as_ref().ok_or_else(|| invalid_argument("upload already finished"))
+    }
+}
+
+#[pymethods]
+impl PyMultipartUpload {
+    /// Header bytes for `multipart_opener`; also carried in the manifest.
+    #[getter]
+    fn header(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyBytes::new(py, self.upload()?.header()).into())
+    }
+
+    #[getter]
+    fn part_size(&self) -> PyResult<usize> {
+        Ok(self.upload()?.part_size())
+    }
+
+    /// Encrypted part `index` (from 0); `data` is exactly `part_size` bytes
+    /// except in the last part.
+    fn seal_part(&self, py: Python<'_>, index: u32, data: Vec<u8>) -> PyResult<PyObject> {
+        let upload = self.upload()?;
+        let part = py.allow_threads(|| upload.seal_part(index, &data)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &part).into())
+    }
+}
+
+#[pyclass(name = "MultipartOpener")]
+pub struct PyMultipartOpener {
+    inner: MultipartOpener,
+}
+
+#[pymethods]
+impl PyMultipartOpener {
+    /// Decrypts part `index`; parts can be opened in any order.
+    fn open_part(&self, py: Python<'_>, index: u32, part: Vec<u8>) -> PyResult<PyObject> {
+        let data = py.allow_threads(|| self.inner.open_part(index, &part)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &data).into())
+    }
+}
+
+/// Incremental encryptor from `SovereignEngine.stream_sealer`; pass it to
+/// `finish_stream_sealer` at the end of the input.
+#[pyclass(name = "StreamSealer")]
+pub struct PyStreamSealer {
+    inner: Option<StreamSealer>,
+}
+
+#[pymethods]
+impl PyStreamSealer {
+    /// Encrypts the next piece of plaintext; returns the stream bytes now
+    /// complete, possibly none.
+    fn update(&mut self, py: Python<'_>, data: BytesLike<'_>) -> PyResult<PyObject> {
+        let sealer = self.inner.as_mut().ok_or_else(|| invalid_argument("stream already finished"))?;
+        let out = py.allow_threads(|| sealer.update(&data)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &out).into())
+    }
+}
+
+/// Incremental decryptor from `SovereignEngine.stream_opener`; pass it to
+/// `finish_stream_opener` at the end of the stream.
+#[pyclass(name = "StreamOpener")]
+pub struct PyStreamOpener {
+    inner: Option<StreamOpener>,
+}
+
+#[pymethods]
+impl PyStreamOpener {
+    /// Decrypts the next piece of the stream, of any size; returns the
+    /// plaintext now complete, possibly none.
+    fn update(&mut self, py: Python<'_>, data: BytesLike<'_>) -> PyResult<PyObject> {
+        let opener = self.inner.as_mut().ok_or_else(|| invalid_argument("stream already finished"))?;
+        let out = py.allow_threads(|| opener.update(&data)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &out).into())
+    }
+}
+
+/// Acts as the role of a token inside a `with` block; from
+/// `SovereignEngine.caller`. Belongs to the thread that made it.
+#[pyclass(name = "CallerScope", unsendable)]
+pub struct PyCallerScope {
+    engine: Py<SovereignEngine>,
+    token: Zeroizing<String>,
+    scope: Option<CallerScope>,
+}
+
+#[pymethods]
+impl PyCallerScope {
+    /// The role entered; `PermissionError` for an unknown token.
+    fn __enter__(&mut self, py: Python<'_>) -> PyResult<Option<String>> {
+        let engine = self.engine.borrow(py);
+        self.scope = Some(engine.inner.enter_caller(&self.token).map_err(to_py_err)?);
+        Ok(engine.inner.caller_role())
+    }
+
+    fn __exit__(&mut self, _exc_type: PyObject, _exc: PyObject, _tb: PyObject) -> bool {
+        self.scope = None;
+        false
+    }
+}
+
+/// Groups audit entries under one operation ID inside a `with` block; from
+/// `SovereignEngine.operation`. Belongs to the thread that made it.
+#[pyclass(name = "OperationScope", unsendable)]
+pub struct PyOperationScope {
+    engine: Py<SovereignEngine>,
+    correlation_id: Option<String>,
+    scope: Option<OperationScope>,
+}
+
+#[pymethods]
+impl PyOperationScope {
+    /// The new operation ID (hex); `ValueError` for a bad correlation ID.
+    fn __enter__(&mut self, py: Python<'_>) -> PyResult<String> {
+        let engine = self.engine.borrow(py);
+        let scope = engine.inner.begin_operation(self.correlation_id.as_deref()).map_err(to_py_err)?;
+        let id = hex::encode(scope.id());
+        self.scope = Some(scope);
+        Ok(id)
+    }
+
+    fn __exit__(&mut self, _exc_type: PyObject, _exc: PyObject, _tb: PyObject) -> bool {
+        self.scope = None;
+        false
+    }
+
+    #[getter]
+    fn correlation_id(&self) -> Option<String> {
+        self.correlation_id.clone()
+    }
+}
+
+// Async generators behind `encrypt_stream` and `decrypt_stream`. The reader
+// is anything with an awaitable `read(n)` (aiohttp's `StreamReader`,
+// Starlette's `UploadFile`) or an async iterable of bytes (`request.stream()`).
+// Each piece is processed on a worker thread; when one fails, the finish
+// records the failure and raises it.
+const ASYNC_STREAMS: &str = r#"
+import asyncio
+
+async def _pieces(reader, size):
+    read = getattr(reader, "read", None)
+    if read is None:
+        async for piece in reader:
+            yield piece
+        return
+    while True:
+        piece = await read(size)
+        if not piece:
+            return
+        yield piece
+
+async def _run(reader, size, state, finish):
+    async for piece in _pieces(reader, size):
+        try:
+            out = await asyncio.to_thread(state.update, piece)
+        except Exception:
+            await asyncio.to_thread(finish, state)
+            raise
+        if out:
+            yield out
+    out = await asyncio.to_thread(finish, state)
+    if out:
+        yield out
+
+def encrypt_stream(engine, reader, size, sealer):
+    return _run(reader, size, sealer, lambda s: engine.finish_stream_sealer(s)[0])
+
+def decrypt_stream(engine, reader, size, opener):
+    return _run(reader, size, opener, engine.finish_stream_opener)
+"#;
+
+static ASYNC_STREAMS_MODULE: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+
+fn async_streams(py: Python<'_>) -> PyResult<&PyModule> {
+    let module = ASYNC_STREAMS_MODULE.get_or_try_init(py, || {
+        PyResult::Ok(PyModule::from_code(py, ASYNC_STREAMS, "titancore_free/_streams.py", "titancore_free._streams")?.into())
+    })?;
+    Ok(module.as_ref(py))
+}
+
+// Unpickling for `vault_open_object`: globals (classes, functions) resolve
+// only if named in the allow-list, so a pickle cannot reach `os.system` and
+// the like. Plain containers and scalars need no globals.
+const RESTRICTED_PICKLE: &str = r#"
+import io
+import pickle
+
+class _Unpickler(pickle.Unpickler):
+    def __init__(self, data, allowed):
+        super().__init__(io.BytesIO(data))
+        self._allowed = allowed
+
+    def find_class(self, module, name):
+        if f"{module}.{name}" not in self._allowed:
+            raise pickle.UnpicklingError(f"global {module}.{name} is not allowed")
+        return super().find_class(module, name)
+
+def loads(data, allowed):
+    return _Unpickler(data, frozenset(allowed)).load()
+"#;
+
+static RESTRICTED_PICKLE_MODULE: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+
+fn restricted_pickle(py: Python<'_>) -> PyResult<&PyModule> {
+    let module = RESTRICTED_PICKLE_MODULE.get_or_try_init(py, || {
+        PyResult::Ok(PyModule::from_code(py, RESTRICTED_PICKLE, "titancore_free/_pickle.py", "titancore_free._pickle")?.into())
+    })?;
+    Ok(module.as_ref(py))
+}
+
+// Objects are sealed as `magic(4) | version(1) | serializer(1) | body`, so
+// the opener knows how to read them back.
+const OBJECT_MAGIC: &[u8; 4] = b"TCOB";
+const OBJECT_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Serializer {
+    Json,
+    Pickle,
+    Msgpack,
+}
+
+impl Serializer {
+    const ALL: [Serializer; 3] = [Serializer::Json, Serializer::Pickle, Serializer::Msgpack];
+
+    fn code(self) -> u8 {
+        match self {
+            Serializer::Json => 1,
+            Serializer::Pickle => 2,
+            Serializer::Msgpack => 3,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Serializer::Json => "json",
+            Serializer::Pickle => "pickle",
+            Serializer::Msgpack => "msgpack",
+        }
+    }
+
+    fn parse(name: &str) -> PyResult<Serializer> {
+        Self::ALL.into_iter().find(|s| s.name() == name).ok_or_else(|| invalid_argument(format!("unknown serializer: {}", name)))
+    }
+
+    fn dumps(self, py: Python<'_>, obj: &PyAny) -> PyResult<Vec<u8>> {
+        let body = match self {
+            Serializer::Json => py.import("json")?.call_method1("dumps", (obj,))?.extract::<String>()?.into_bytes(),
+            Serializer::Pickle => py.import("pickle")?.call_method1("dumps", (obj,))?.extract()?,
+            Serializer::Msgpack => py.import("msgpack")?.call_method1("packb", (obj,))?.extract()?,
+        };
+        let mut out = Vec::with_capacity(OBJECT_MAGIC.len() + 2 + body.len());
+        out.extend_from_slice(OBJECT_MAGIC);
+        out.extend_from_slice(&[OBJECT_VERSION, self.code()]);
+        out.extend_from_slice(&body);
+        Ok(out)
+    }
+
+    fn loads(py: Python<'_>, data: &[u8], allowed: &[String]) -> PyResult<PyObject> {
+        let header = OBJECT_MAGIC.len() + 2;
+        if data.len() < header || &data[..4] != OBJECT_MAGIC || data[4] != OBJECT_VERSION {
+            return Err(invalid_argument("not a sealed object"));
+        }
+        let serializer = Self::ALL.into_iter().find(|s| s.code() == data[5]).ok_or_else(|| invalid_argument("unknown serializer"))?;
+        let body = PyBytes::new(py, &data[header..]);
+        let obj = match serializer {
+            Serializer::Json => py.import("json")?.call_method1("loads", (body,))?,
+            Serializer::Pickle => restricted_pickle(py)?.call_method1("loads", (body, allowed.to_vec()))?,
+            Serializer::Msgpack => py.import("msgpack")?.call_method1("unpackb", (body,))?,
+        };
+        Ok(obj.into())
+    }
+}
+
+/// Seekable scratch file encrypted under a key held only in memory; the
+/// file is removed on `close()`, on leaving a `with` block, or when
+/// garbage-collected.
+#[pyclass(name = "EncryptedTempFile")]
+pub struct PyEncryptedTempFile {
+    inner: Option<EncryptedTempFile>,
+}
+
+impl PyEncryptedTempFile {
+    fn file(&mut self) -> PyResult<&mut EncryptedTempFile> {
+        self.inner.as_mut().ok_or_else(|| invalid_argument("I/O operation on closed file"))
+    }
+}
+
+#[pymethods]
+impl PyEncryptedTempFile {
+    /// Creates the file in `dir`, or the system temporary directory.
+    #[new]
+    #[pyo3(signature = (dir=None))]
+    fn new(dir: Option<PathBuf>) -> PyResult<Self> {
+        let file = match dir {
+            Some(dir) => EncryptedTempFile::new_in(dir),
+            None => EncryptedTempFile::new(),
+        };
+        Ok(PyEncryptedTempFile { inner: Some(file.map_err(to_py_err)?) })
+    }
+
+    /// Path of the ciphertext on disk.
+    #[getter]
+    fn name(&mut self) -> PyResult<PathBuf> {
+        Ok(self.file()?.path().to_path_buf())
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    fn write(&mut self, data: Vec<u8>) -> PyResult<usize> {
+        self.file()?.write_all(&data)?;
+        Ok(data.len())
+    }
+
+    /// Up to `size` bytes from the current position; all of the rest if
+    /// `size` is negative.
+    #[pyo3(signature = (size=-1))]
+    fn read(&mut self, py: Python<'_>, size: i64) -> PyResult<PyObject> {
+        let file = self.file()?;
+        let mut out = Vec::new();
+        match u64::try_from(size) {
+            Ok(size) => file.take(size).read_to_end(&mut out)?,
+            Err(_) => file.read_to_end(&mut out)?,
+        };
+        Ok(PyBytes::new(py, &out).into())
+    }
+
+    /// `whence` is 0 (start), 1 (current) or 2 (end), as for `io`.
+    #[pyo3(signature = (offset, whence=0))]
+    fn seek(&mut self, offset: i64, whence: u8) -> PyResult<u64> {
+        let pos = match whence {
+            0 => SeekFrom::Start(u64::try_from(offset).map_err(|_| invalid_argument("negative seek position"))?),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(invalid_argument(format!("invalid whence: {}", whence))),
+        };
+        Ok(self.file()?.seek(pos)?)
+    }
+
+    fn tell(&mut self) -> PyResult<u64> {
+        Ok(self.file()?.stream_position()?)
+    }
+
+    /// Cuts or zero-extends the file to `size` bytes, by default the
+    /// current position.
+    #[pyo3(signature = (size=None))]
+    fn truncate(&mut self, size: Option<u64>) -> PyResult<u64> {
+        let file = self.file()?;
+        let size = match size {
+            Some(size) => size,
+            None => file.stream_position()?,
+        };
+        file.set_len(size).map_err(to_py_err)?;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> PyResult<()> {
+        Ok(self.file()?.flush()?)
+    }
+
+    /// Forgets the key and removes the file; safe to call twice.
+    fn close(&mut self) {
+        self.inner = None;
+    }
+
+    fn __len__(&mut self) -> PyResult<usize> {
+        Ok(self.file()?.len() as usize)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, _exc_type: PyObject, _exc: PyObject, _tb: PyObject) -> bool {
+        self.close();
+        false
+    }
+}
+
+/// Picklable recipe for a `SovereignEngine`, for `multiprocessing` and
+/// process pools. An engine must not cross a fork: the copy shares its
+/// parent's chain head, log handle and locks, and raises `ValueError` if
+/// used. Pass a handle instead; `engine()` opens one engine per process on
+/// first use, with its own log, counters and random state.
+///
+/// `log_path` may contain `{pid}`; otherwise processes other than the one
+/// that created the handle log to `<log_path>.<pid>`, so every chain has a
+/// single writer. Options are the constructor's keyword arguments and must
+/// pickle. Policy set on one process's engine stays in that process.
+/// Opened engines live until the process exits, and pool workers exit
+/// without cleanup: keep a `sync_policy` that writes every entry, or
+/// `close()` the engine at the end of the worker's job.
+#[pyclass(name = "EngineHandle", module = "titancore_free")]
+pub struct PyEngineHandle {
+    hw_info: String,
+    seed: String,
+    license_sig: String,
+    log_path: String,
+    options: Py<PyDict>,
+    owner: u32,
+}
+
+// Engines opened through handles, by process and log path, so copies of a
+// handle unpickled in one process share its engine.
+static HANDLE_ENGINES: std::sync::Mutex<Vec<(u32, String, Py<SovereignEngine>)>> = std::sync::Mutex::new(Vec::new());
+
+#[pymethods]
+impl PyEngineHandle {
+    #[new]
+    #[pyo3(signature = (hw_info, seed, license_sig, log_path, **options))]
+    fn new(py: Python<'_>, hw_info: String, seed: String, license_sig: String, log_path: String, options: Option<&PyDict>) -> Self {
+        let options = options.map_or_else(|| PyDict::new(py).into(), |o| o.copy().expect("dict copy").into());
+        PyEngineHandle { hw_info, seed, license_sig, log_path, options, owner: std::process::id() }
+    }
+
+    /// Log path for the calling process.
+    #[getter]
+    fn log_path(&self) -> String {
+        let pid = std::process::id();
+        if self.log_path.contains("{pid}") {
+            self.log_path.replace("{pid}", &pid.to_string())
+        } else if pid == self.owner {
+            self.log_path.clone()
+        } else {
+            format!("{}.{}", self.log_path, pid)
+        }
+    }
+
+    /// This process's engine, opened on first call. Handles for the same
+    /// log share it.
+    fn engine(&self, py: Python<'_>) -> PyResult<Py<SovereignEngine>> {
+        let pid = std::process::id();
+        let log_path = self.log_path();
+        let find = |engines: &mut Vec<(u32, String, Py<SovereignEngine>)>| {
+            // Engines inherited through a fork belong to the parent; dropping
+            // them here would flush and close the parent's log.
+            let (mine, stale): (Vec<_>, Vec<_>) = std::mem::take(engines).into_iter().partition(|(owner, _, _)| *owner == pid);
+            *engines = mine;
+            std::mem::forget(stale);
+            engines.iter().find(|(_, path, _)| *path == log_path).map(|(_, _, e)| e.clone_ref(py))
+        };
+        if let Some(engine) = find(&mut HANDLE_ENGINES.lock().unwrap_or_else(|e| e.into_inner())) {
+            return Ok(engine);
+        }
+        // Built without the lock held: the constructor may run Python code.
+        let args = (&self.hw_info, &self.seed, &self.license_sig, &log_path);
+        let engine: Py<SovereignEngine> = py.get_type::<SovereignEngine>().call(args, Some(self.options.as_ref(py)))?.extract()?;
+        let mut engines = HANDLE_ENGINES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(existing) = find(&mut engines) {
+            return Ok(existing);
+        }
+        engines.push((pid, log_path, engine.clone_ref(py)));
+        Ok(engine)
+    }
+
+    fn __reduce__(&self, py: Python<'_>) -> PyResult<(PyObject, PyObject, PyObject)> {
+        let args = (&self.hw_info, &self.seed, &self.license_sig, &self.log_path).into_py(py);
+        let state = (self.options.clone_ref(py), self.owner).into_py(py);
+        Ok((py.get_type::<PyEngineHandle>().into_py(py), args, state))
+    }
+
+    fn __setstate__(&mut self, state: (Py<PyDict>, u32)) {
+        (self.options, self.owner) = state;
+    }
+}
+
+fn parse_sync_policy(name: &str, every: usize, interval_ms: u64) -> PyResult<SyncPolicy> {
+    match name {
+        "always" => Ok(SyncPolicy::Always),
+        "periodic" => Ok(SyncPolicy::Periodic { entries: every, interval: Duration::from_millis(interval_ms) }),
+        "buffered" => Ok(SyncPolicy::Buffered),
+        other => Err(invalid_argument(format!("unknown sync_policy: {}", other))),
+    }
+}
+
+#[pyclass]
+pub struct SovereignEngine {
+    inner: Engine,
+    #[pyo3(get)]
+    log_path: String,
+    #[pyo3(get)]
+    is_authorized: bool,
+    license_sig: String,
+}
+
+#[pymethods]
+impl SovereignEngine {
+    /// `sync_policy` is `"always"` (fsync per entry, the default),
+    /// `"periodic"` (fsync every `sync_every` entries or `sync_interval_ms`,
+    /// whichever first) or `"buffered"` (write + fsync only on `flush()`).
+    /// The relaxed policies trade crash durability of the newest entries for
+    /// throughput.
+    ///
+    /// `audit_format="binary"` writes a new file log as length-prefixed
+    /// binary records instead of text lines: smaller and quicker to verify.
+    /// An existing log must already be in that format (`ValueError`
+    /// otherwise); read either with `read_audit_log`.
+    ///
+    /// `audit_backend="sqlite"` keeps the log in an SQLite database at
+    /// `log_path` instead, committing every entry (`sync_policy` does not
+    /// apply) and labelling it with the engine fingerprint; search it with
+    /// `query_audit`.
+    ///
+    /// `audit_forward` also mirrors each entry to syslog or journald:
+    /// `"syslog"` (RFC 5424 to `/dev/log`), `"udp://host:port"`,
+    /// `"unix:///path"` or `"journald"`. The local log stays the record;
+    /// copies that cannot be sent are dropped.
+    ///
+    /// `rate_limit_redis` (`redis://host:port/db`) keeps the request quota
+    /// in Redis, shared by every engine using the same server and
+    /// `rate_limit_key` (default: the fingerprint), e.g. per tenant.
+    ///
+    /// With `rate_limit_wait_ms`, a call over the rate limit waits (without
+    /// holding the GIL) up to that long for capacity before raising; batch
+    /// jobs can then loop without retrying themselves.
+    ///
+    /// `worker_threads` sizes the pool for batch and streaming crypto
+    /// (default: shared with every engine) and `hash_threads` a separate
+    /// pool for hashing large ciphertexts (default: the worker pool), so the
+    /// engine's CPU use can be pinned under a container CPU limit.
+    ///
+    /// With `audit_queue=N`, entries are written by a background thread
+    /// through a queue of N entries; call `flush()` when evidence must be on
+    /// disk before continuing.
+    ///
+    /// `clock` is a `FixedClock` or a callable returning unix milliseconds
+    /// (e.g. a trusted external time source); `clock_offset_ms` shifts
+    /// whichever clock is in use.
+    ///
+    /// `suite` picks the envelope AEAD: `"aes-256-gcm-siv"` (counter-based
+    /// nonce, the default), `"aes-256-gcm-siv-random"` (random 96-bit nonce)
+    /// or `"xchacha20-poly1305"` (random 192-bit nonce), or one a Rust
+    /// extension registered (see `suite_algorithms()`). The choice is
+    /// recorded in each envelope.
+    ///
+    /// `kem` picks the KEM envelopes are sealed under: `"kyber1024"` (the
+    /// default), `"kyber768"`, `"kyber512"`, `"mceliece6688128"`,
+    /// `"hqc256"`, `"kyber1024+hqc256"`, `"x-wing"` or one a Rust extension
+    /// registered (see `kem_algorithms()`). `"kyber1024+hqc256"` is dual-KEM mode: each
+    /// envelope is encapsulated to both, and stays confidential unless both
+    /// are broken. The KEM is recorded in each envelope; other formats stay
+    /// on Kyber-1024. `generate_keypair()` on the engine makes keys for it.
+    ///
+    /// `kdf` picks the session-key KDF: `"hkdf-sha256"` (the default),
+    /// `"hkdf-sha512"` or `"blake3"`. `kdf_salt` and `kdf_info` set a
+    /// deployment-specific salt and application context string (defaults: no
+    /// salt, `TITAN_V18_1_DIAMOND`). Non-default values are recorded in each
+    /// envelope and stream header, so any engine can decrypt them.
+    ///
+    /// `state` (from `export_state`) recreates an engine after a deploy:
+    /// its recorded settings replace the ones given here, its policy is
+    /// reinstalled, and the log at `log_path` is checked against it
+    /// (`IOError` if the log was rolled back or does not match; an empty log
+    /// continues from the snapshot).
+    ///
+    /// `tpm_quote=(attest, signature)` is a TPM 2.0 quote over the PCRs the
+    /// engine should be bound to (as written by `tpm2_quote -m -s`): its PCR
+    /// digest becomes part of the fingerprint, and `attest` includes it.
+    ///
+    /// `fips_mode=True` allows only the AES-256-GCM-SIV suites and the HKDF
+    /// KDFs, rejects envelopes and streams sealed under anything else, and
+    /// runs the power-on self tests first (`RuntimeError` if one fails).
+    /// Every audit entry is marked `fips`; see `engine_info()`.
+    ///
+    /// A new log starts with a genesis record signed by the checkpoint key,
+    /// naming the fingerprint, `license_sig`, a hash of these settings and
+    /// the start time; see `genesis()`.
+    ///
+    /// `identity=(public_key, secret_key)` is the long-lived Dilithium5
+    /// keypair (e.g. from `unwrap_identity`) that signs checkpoints, the
+    /// genesis record and attestations; without it the engine uses an
+    /// ephemeral one. Replace it later with `rotate_identity`.
+    ///
+    /// `snapshot_every=N` signs a snapshot of the chain every N entries,
+    /// so `verify_audit_log` can start from the latest one it trusts; see
+    /// `audit_snapshots()`.
+    ///
+    /// Operations and bytes sealed to each recipient key are counted in
+    /// `key_usage_path` (default `<log_path>.usage`), so they survive
+    /// restarts; see `get_key_usage` and `set_key_usage_cap`.
+    ///
+    /// Failed and denied operations (rate limits, rejected keys, refused
+    /// step-up, quorum or role checks, failed decryptions) are recorded
+    /// with a `reason`; `audit_failures=False` leaves them out of the log.
+    ///
+    /// With `idempotency_window_ms`, `vault_seal(..., idempotency_key=k)`
+    /// returns the first call's result for a repeat of `k` within the
+    /// window, without sealing or logging again.
+    ///
+    /// `vendor_key` is the vendor's Dilithium5 public key that
+    /// `install_activation` checks offline activations against.
+    ///
+    /// `constraint_signers` lists the Dilithium5 public keys, besides this
+    /// engine's identity, whose signed decryption constraints `vault_open`
+    /// accepts: the identities of the engines that seal to this one.
+    ///
+    /// `high_water_path` keeps a signed high-water mark of the chain in
+    /// that file, moved once entries are durable: after each one with
+    /// `sync_policy="always"`, otherwise on `flush` and `close`; keep
+    /// it out of the log's backups. It needs `identity`. A log restored from an old backup then
+    /// fails to start with `OSError`, unless `allow_rollback=True`, which
+    /// moves the counter past the mark and logs the override.
+    #[new]
+    #[pyo3(signature = (hw_info, seed, license_sig, log_path, worker_threads=None, audit_digest=false,
+                        sync_policy="always", sync_every=64, sync_interval_ms=1000, audit_queue=None,
+                        merkle_batch=None, clock=None, clock_offset_ms=0, suite="aes-256-gcm-siv",
+                        kdf="hkdf-sha256", kdf_salt=None, kdf_info=None, shred_sources=None, audit_backend="file",
+                        audit_forward=None, rate_limit_redis=None, rate_limit_key=None,
+                        rate_limit_wait_ms=None, state=None, hash_threads=None, tpm_quote=None, fips_mode=false,
+                        identity=None, snapshot_every=None, audit_format="text", key_usage_path=None,
+                        audit_failures=true, idempotency_window_ms=None, kem=None, vendor_key=None,
+                        high_water_path=None, allow_rollback=false, constraint_signers=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(py: Python<'_>, hw_info: String, seed: String, license_sig: String, log_path: String, worker_threads: Option<usize>,
+           audit_digest: bool, sync_policy: &str, sync_every: usize, sync_interval_ms: u64, audit_queue: Option<usize>,
+           merkle_batch: Option<usize>, clock: Option<PyObject>, clock_offset_ms: i64, suite: &str,
+           kdf: &str, kdf_salt: Option<Vec<u8>>, kdf_info: Option<Vec<u8>>, shred_sources: Option<u32>,
+           audit_backend: &str, audit_forward: Option<&str>, rate_limit_redis: Option<&str>,
+           rate_limit_key: Option<String>, rate_limit_wait_ms: Option<u64>, state: Option<&str>,
+           hash_threads: Option<usize>, tpm_quote: Option<(Vec<u8>, Vec<u8>)>, fips_mode: bool,
+           identity: Option<(Vec<u8>, Vec<u8>)>, snapshot_every: Option<u64>, audit_format: &str,
+           key_usage_path: Option<String>, audit_failures: bool, idempotency_window_ms: Option<u64>,
+           kem: Option<&str>, vendor_key: Option<Vec<u8>>, high_water_path: Option<String>,
+           allow_rollback: bool, constraint_signers: Option<Vec<Vec<u8>>>) -> PyResult<Self> {
+        let tpm_quote = tpm_quote.map(|(attest, signature)| TpmQuote::new(attest, signature)).transpose().map_err(to_py_err)?;
+        let identity = identity.map(|(pk, sk)| identity_from(pk, sk)).transpose()?;
+        let vendor_key = vendor_key.map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).transpose()?;
+        let constraint_signers = constraint_signers.unwrap_or_default().into_iter()
+            .map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+        let policy = parse_sync_policy(sync_policy, sync_every, sync_interval_ms)?;
+        let store: Box<dyn AuditSink> = match audit_backend {
+            "file" => {
+                let format = match audit_format {
+                    "text" => LogFormat::Text,
+                    "binary" => LogFormat::Binary,
+                    other => return Err(invalid_argument(format!("unknown audit_format: {}", other))),
+                };
+                Box::new(FileSink::with_policy(log_path.clone(), policy).with_format(format))
+            }
+            "sqlite" => {
+                let fingerprint = match &tpm_quote {
+                    Some(quote) => Engine::fingerprint_with_pcrs(&hw_info, &seed, quote.pcr_digest()),
+                    None => Engine::fingerprint_for(&hw_info, &seed),
+                };
+                Box::new(SqliteSink::open(&log_path, hex::encode(fingerprint)).map_err(to_py_err)?)
+            }
+            other => return Err(invalid_argument(format!("unknown audit_backend: {}", other))),
+        };
+        let sink: Box<dyn AuditSink> = match audit_forward {
+            None => store,
+            Some(target) => {
+                let target = SyslogTarget::parse(target)
+                    .ok_or_else(|| invalid_argument(format!("unknown audit_forward target: {}", target)))?;
+                Box::new(SyslogSink::new(store, target).map_err(to_py_err)?)
+            }
+        };
+        let ct_binding = if audit_digest { CiphertextBinding::Digest } else { CiphertextBinding::Full };
+        let clock: Arc<dyn Clock> = match clock {
+            None => Arc::new(SystemClock::new()),
+            Some(obj) => {
+                let fixed = obj.extract::<PyRef<PyFixedClock>>(py).map(|c| c.inner.clone());
+                match fixed {
+                    Ok(fixed) => fixed,
+                    Err(_) if obj.as_ref(py).is_callable() => Arc::new(PyCallbackClock { callback: obj, fallback: SystemClock::new() }),
+                    Err(_) => return Err(invalid_argument("clock must be a FixedClock or a callable")),
+                }
+            }
+        };
+        let clock: Arc<dyn Clock> = match clock_offset_ms {
+            0 => clock,
+            offset => Arc::new(OffsetClock::new(clock, offset)),
+        };
+        let suite = Suite::parse(suite).ok_or_else(|| invalid_argument(format!("unknown suite: {}", suite)))?;
+        let algorithm = Kdf::parse(kdf).ok_or_else(|| invalid_argument(format!("unknown KDF: {}", kdf)))?;
+        let kem = kem.map(|name| kem::parse(name).map(|k| k.id()).ok_or_else(|| invalid_argument(format!("unknown KEM: {}", name))))
+            .transpose()?;
+        let mut kdf = KdfParams { algorithm, ..KdfParams::default() };
+        if let Some(salt) = kdf_salt {
+            kdf.salt = salt;
+        }
+        if let Some(info) = kdf_info {
+            kdf.info = info;
+        }
+        let rate_limiter = match rate_limit_redis {
+            Some(url) => Some(Arc::new(RedisRateLimiter::new(url).map_err(to_py_err)?) as Arc<dyn RateLimiter>),
+            None => None,
+        };
+        let usage_store = FileUsageStore::open(key_usage_path.unwrap_or_else(|| format!("{}.usage", log_path))).map_err(to_py_err)?;
+        let config = EngineConfig {
+            worker_threads, ct_binding, merkle_batch, snapshot_every, clock: Some(clock), suite, kdf, shred_sources, rate_limiter, rate_limit_key,
+            rate_limit_wait: rate_limit_wait_ms.map(Duration::from_millis), hash_threads, audit_queue, tpm_quote, fips_mode,
+            license: Some(license_sig.clone()), identity, usage_store: Some(Arc::new(usage_store)), omit_failures: !audit_failures,
+            idempotency_window: idempotency_window_ms.map(Duration::from_millis), kem, vendor_key,
+            high_water: high_water_path.map(|path| Arc::new(FileHighWaterStore::new(path)) as Arc<dyn HighWaterStore>), allow_rollback,
+            constraint_signers,
+        };
+        let inner = match state {
+            Some(state) => {
+                let state = EngineState::from_json(state).map_err(to_py_err)?;
+                Engine::restore_state(&hw_info, &seed, sink, &state, config)
+            }
+            None => Engine::with_config(&hw_info, &seed, sink, config),
+        }.map_err(to_py_err)?;
+        Ok(SovereignEngine { inner, log_path, is_authorized: true, license_sig })
+    }
+
+    /// Seals `data` to `recipient`, a public key or the name of one pinned
+    /// in `keyring`. A name the keyring does not know, or whose pinned key
+    /// differs from `public_key`, raises `ValueError`, and a revoked one
+    /// `PermissionError`; `override=True` instead pins `public_key` for the
+    /// name (trust `"tofu"`). Returns `(ciphertext, kem_ct, evidence)`.
+    #[pyo3(signature = (data, recipient, keyring=None, public_key=None, r#override=false))]
+    pub fn vault_execute(&self, py: Python<'_>, data: BytesLike<'_>, recipient: &PyAny, keyring: Option<PyRefMut<'_, PyKeyring>>,
+                         public_key: Option<Vec<u8>>, r#override: bool) -> PyResult<(Vec<u8>, Vec<u8>, String)> {
+        let (env, evidence) = match recipient.extract::<&str>() {
+            Ok(name) => {
+                let mut keyring = keyring.ok_or_else(|| invalid_argument("sealing to a recipient name needs a keyring"))?;
+                let presented = public_key.map(|pk| unarmor(ArmorKind::PublicKey, pk)).transpose()?;
+                // Only an override can pin a key.
+                let before = r#override.then(|| keyring.inner.clone());
+                let ring = &mut keyring.inner;
+                let res = py.allow_threads(|| self.inner.seal_named(&data, ring, name, presented.as_deref(), r#override));
+                if before.is_some_and(|b| b != keyring.inner) {
+                    keyring.changed()?;
+                }
+                res.map_err(to_py_err)?
+            }
+            Err(_) => {
+                let pk_bytes: Vec<u8> = recipient.extract()?;
+                py.allow_threads(|| self.inner.seal(&data, &pk_bytes)).map_err(to_py_err)?
+            }
+        };
+        Ok((env.ciphertext, env.kem_ct, evidence))
+    }
+
+    /// Like `vault_execute` but returns a decryptable envelope: `(envelope, evidence)`.
+    /// A `context` (e.g. `"backups"`) is mixed into the session key; the
+    /// envelope then opens only with the same `context`. `output_format`
+    /// `"cose"` returns a `COSE_Encrypt` instead of the native envelope, and
+    /// `armor=True` wraps either in a `BEGIN TITAN ENVELOPE` block.
+    /// `restricted=True` (native only) seals an envelope that opens only
+    /// through `vault_open_restricted`. With an `idempotency_key` (native,
+    /// unrestricted only; needs `idempotency_window_ms`), a repeat of the
+    /// same call within the window returns the first result unchanged; the
+    /// key reused for a different call raises `ValueError`.
+    #[pyo3(signature = (data, pk_bytes, context=None, output_format="native", armor=false, restricted=false,
+                        idempotency_key=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn vault_seal(&self, py: Python<'_>, data: BytesLike<'_>, pk_bytes: Vec<u8>, context: Option<String>,
+                      output_format: &str, armor: bool, restricted: bool, idempotency_key: Option<String>) -> PyResult<(PyObject, String)> {
+        check_output_format(output_format)?;
+        if restricted && output_format != "native" {
+            return Err(invalid_argument("restricted envelopes are native only"));
+        }
+        if idempotency_key.is_some() && (restricted || output_format != "native") {
+            return Err(invalid_argument("idempotency keys apply to native unrestricted envelopes only"));
+        }
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        let (bytes, evidence) = py.allow_threads(|| match output_format {
+            "cose" => self.inner.seal_cose(&data, &pk_bytes, context.as_bytes()).map(|(msg, ev)| (msg.to_bytes(), ev)),
+            _ if restricted => self.inner.seal_restricted(&data, &pk_bytes, context.as_bytes()).map(|(env, ev)| (env.to_bytes(), ev)),
+            _ if idempotency_key.is_some() => self.inner.seal_idempotent(idempotency_key.as_deref().unwrap_or_default(), &data, &pk_bytes, context.as_bytes())
+                .map(|(env, ev)| (env.to_bytes(), ev)),
+            _ => self.inner.seal_with_context(&data, &pk_bytes, context.as_bytes()).map(|(env, ev)| (env.to_bytes(), ev)),
+        }).map_err(to_py_err)?;
+        Ok((maybe_armor(py, ArmorKind::Envelope, &bytes, armor), evidence))
+    }
+
+    /// Wraps `data`, unencrypted, in a tamper-evident message: `mode`
+    /// `"sign"` signs it with the checkpoint key, `"mac"` tags it with keyed
+    /// BLAKE3 under the 32-byte `key`. Returns `(message, evidence)`; the
+    /// message is recorded as a `sign` event.
+    #[pyo3(signature = (data, mode="sign", key=None))]
+    pub fn vault_protect(&self, py: Python<'_>, data: BytesLike<'_>, mode: &str, key: Option<Vec<u8>>) -> PyResult<(PyObject, String)> {
+        let (message, evidence) = match (mode, key) {
+            ("sign", None) => py.allow_threads(|| self.inner.protect_signed(&data)),
+            ("mac", Some(key)) => {
+                let key = mac_key(&key)?;
+                py.allow_threads(|| self.inner.protect_mac(&data, &key))
+            }
+            ("sign", Some(_)) => return Err(invalid_argument("mode \"sign\" takes no key")),
+            ("mac", None) => return Err(invalid_argument("mode \"mac\" needs a key")),
+            _ => return Err(invalid_argument(format!("unknown mode: {}", mode))),
+        }.map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &message.to_bytes()).into(), evidence))
+    }
+
+    /// Checks a message from `vault_protect` against the signer's
+    /// `trusted_pk` or the MAC `key` and returns its payload; raises
+    /// `ValueError` if it does not verify.
+    #[pyo3(signature = (message, trusted_pk=None, key=None))]
+    pub fn verify_protected(&self, py: Python<'_>, message: Vec<u8>, trusted_pk: Option<Vec<u8>>, key: Option<Vec<u8>>) -> PyResult<PyObject> {
+        let message = ProtectedMessage::from_bytes(&message).map_err(to_py_err)?;
+        let valid = match (trusted_pk, key) {
+            (Some(pk), None) => {
+                let pk = unarmor(ArmorKind::SigningPublicKey, pk)?;
+                py.allow_threads(|| self.inner.verify_protected(&message, &pk)).map_err(to_py_err)?
+            }
+            (None, Some(key)) => message.verify_mac(&mac_key(&key)?),
+            _ => return Err(invalid_argument("pass exactly one of trusted_pk and key")),
+        };
+        if !valid {
+            return Err(with_code(PyValueError::new_err("protected message failed verification"), VERIFICATION_FAILED, ErrorContext::default()));
+        }
+        Ok(PyBytes::new(py, &message.payload).into())
+    }
+
+    /// Seals a native envelope whose 16-byte authentication tag is returned
+    /// separately: `(envelope, tag, evidence)`. The envelope's ciphertext is
+    /// as long as `data`; keep the tag alongside it for `vault_open_detached`.
+    #[pyo3(signature = (data, pk_bytes, context=None))]
+    pub fn vault_seal_detached(&self, py: Python<'_>, data: BytesLike<'_>, pk_bytes: Vec<u8>, context: Option<String>) -> PyResult<(PyObject, PyObject, String)> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        let (env, tag, evidence) = py.allow_threads(|| self.inner.seal_detached(&data, &pk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &env.to_bytes()).into(), PyBytes::new(py, &tag).into(), evidence))
+    }
+
+    /// Decrypts an envelope from `vault_seal_detached` given its `tag`.
+    #[pyo3(signature = (envelope, tag, sk_bytes, context=None))]
+    pub fn vault_open_detached(&self, py: Python<'_>, envelope: BytesLike<'_>, tag: Vec<u8>, sk_bytes: SecretArg, context: Option<String>) -> PyResult<PyObject> {
+        let tag: [u8; envelope::TAG_LEN] = tag.as_slice().try_into()
+            .map_err(|_| invalid_argument(format!("tag must be {} bytes", envelope::TAG_LEN)))?;
+        let envelope = self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&unarmor_ref(ArmorKind::Envelope, &envelope)?)).map_err(to_py_err)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let pt = py.allow_threads(|| self.inner.open_detached(&envelope, &tag, &sk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &pt).into())
+    }
+
+    /// Decrypts a native envelope or `COSE_Encrypt`, raw or armored; the
+    /// attempt is recorded as a `decrypt` audit event whether or not it
+    /// succeeds. With `out`, a writable buffer such as a preallocated NumPy
+    /// array, the plaintext is written to its start and its length returned
+    /// instead of `bytes`.
+    #[pyo3(signature = (envelope, sk_bytes, context=None, out=None))]
+    pub fn vault_open(&self, py: Python<'_>, envelope: BytesLike<'_>, sk_bytes: SecretArg, context: Option<String>,
+                      out: Option<&PyAny>) -> PyResult<PyObject> {
+        let envelope = unarmor_ref(ArmorKind::Envelope, &envelope)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let pt = py.allow_threads(|| {
+            if cose::is_cose(&envelope) {
+                let message = self.inner.audited(OpType::Decrypt, &[], CoseEncrypt::from_bytes(&envelope))?;
+                return self.inner.open_cose(&message, &sk_bytes, context.as_bytes());
+            }
+            let envelope = self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&envelope))?;
+            self.inner.open_with_context(&envelope, &sk_bytes, context.as_bytes())
+        }).map_err(to_py_err)?;
+        match out {
+            Some(out) => Ok(write_into(out, &pt)?.into_py(py)),
+            None => Ok(PyBytes::new(py, &pt).into()),
+        }
+    }
+
+    /// Serializes `obj` with `serializer` (`"json"`, `"pickle"` or
+    /// `"msgpack"`, which needs the `msgpack` package) and seals it like
+    /// `vault_seal`: `(envelope, evidence)`. The serializer is recorded
+    /// inside the ciphertext for `vault_open_object`.
+    #[pyo3(signature = (obj, pk_bytes, serializer="json", context=None))]
+    pub fn vault_execute_object(&self, py: Python<'_>, obj: &PyAny, pk_bytes: Vec<u8>, serializer: &str,
+                                context: Option<String>) -> PyResult<(PyObject, String)> {
+        let data = Zeroizing::new(Serializer::parse(serializer)?.dumps(py, obj)?);
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        let (env, evidence) = py.allow_threads(|| self.inner.seal_with_context(&data, &pk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &env.to_bytes()).into(), evidence))
+    }
+
+    /// Opens an envelope from `vault_execute_object` and returns the object.
+    /// Pickled objects may refer only to the globals named in `allowed`, as
+    /// `"module.name"` strings (e.g. `"datetime.datetime"`); by default only
+    /// plain containers and scalars load, and anything else raises
+    /// `pickle.UnpicklingError`. Data that is not a sealed object raises
+    /// `ValueError`.
+    #[pyo3(signature = (envelope, sk_bytes, context=None, allowed=Vec::new()))]
+    pub fn vault_open_object(&self, py: Python<'_>, envelope: BytesLike<'_>, sk_bytes: SecretArg, context: Option<String>,
+                             allowed: Vec<String>) -> PyResult<PyObject> {
+        let envelope = unarmor_ref(ArmorKind::Envelope, &envelope)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let pt = py.allow_threads(|| {
+            let envelope = self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&envelope))?;
+            self.inner.open_with_context(&envelope, &sk_bytes, context.as_bytes())
+        }).map(Zeroizing::new).map_err(to_py_err)?;
+        Serializer::loads(py, &pt, &allowed)
+    }
+
+    /// Protects the fields of `document`, anything `json.dumps` accepts, as
+    /// `policy` says and returns `(document, evidence)`: the rewritten
+    /// document and the evidence of each encrypted field. `policy` is a
+    /// dict from JSONPath expressions (e.g. `"$.users[*].ssn"`) to
+    /// `"encrypt"`, `"blind_index"` or `"mask"`, applied in order.
+    /// Encrypted fields become `"tcenc:"` plus a base64 envelope, which
+    /// `vault_open` opens with the field's normalized path (e.g.
+    /// `"$['users'][0]['ssn']"`) as `context`. Blind indexes are keyed by
+    /// the 32-byte `index_key`, required when a rule uses them.
+    #[pyo3(signature = (document, policy, pk_bytes, index_key=None))]
+    pub fn apply_policy(&self, py: Python<'_>, document: &PyAny, policy: &PyDict, pk_bytes: Vec<u8>,
+                        index_key: Option<Vec<u8>>) -> PyResult<(PyObject, Vec<String>)> {
+        let document = to_json_value(py, document)?;
+        let mut rules = DocumentPolicy::new();
+        for (path, protection) in policy.iter() {
+            let (path, protection): (&str, &str) = (path.extract()?, protection.extract()?);
+            let protection = Protection::parse(protection).ok_or_else(|| invalid_argument(format!("unknown protection: {}", protection)))?;
+            rules.add(path, protection).map_err(to_py_err)?;
+        }
+        let index_key = self::index_key(index_key)?;
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let (out, evidence) = py.allow_threads(|| self.inner.apply_policy(&document, &rules, &pk_bytes, index_key.as_ref())).map_err(to_py_err)?;
+        Ok((from_json_value(py, &out)?, evidence))
+    }
+
+    /// Checks `record` (a dict) against schema `schema_id` of `registry`,
+    /// protects its fields as the schema says and seals it to `pk_bytes`
+    /// with the schema ID in the envelope: `(envelope, evidence)`.
+    /// Encrypted fields with a `key_id` are sealed to the key in `keys`
+    /// with that ID; blind indexes need the 32-byte `index_key`. A record
+    /// that does not match raises `ValueError`.
+    #[pyo3(signature = (record, registry, schema_id, pk_bytes, keys=Vec::new(), index_key=None, context=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn vault_seal_record(&self, py: Python<'_>, record: &PyAny, registry: PyRef<'_, PySchemaRegistry>, schema_id: &str, pk_bytes: Vec<u8>,
+                             keys: Vec<Vec<u8>>, index_key: Option<Vec<u8>>, context: Option<String>) -> PyResult<(PyObject, String)> {
+        let record = to_json_value(py, record)?;
+        let id = self::schema_id(schema_id)?;
+        let (pk_bytes, index_key) = (unarmor(ArmorKind::PublicKey, pk_bytes)?, self::index_key(index_key)?);
+        let keys = keys.into_iter().map(|k| unarmor(ArmorKind::PublicKey, k)).collect::<PyResult<Vec<_>>>()?;
+        let context = context.unwrap_or_default();
+        let registry = &registry.inner;
+        let (env, evidence) = py.allow_threads(|| self.inner.seal_record(&record, registry, &id, &pk_bytes, &keys, index_key.as_ref(), context.as_bytes()))
+            .map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &env.to_bytes()).into(), evidence))
+    }
+
+    /// Opens a record from `vault_seal_record`: `(record, schema_id)`, its
+    /// fields still protected. Raises `ValueError` if the envelope has no
+    /// schema, the schema is not in `registry` or the record does not
+    /// match it.
+    #[pyo3(signature = (envelope, sk_bytes, registry, context=None))]
+    pub fn vault_open_record(&self, py: Python<'_>, envelope: BytesLike<'_>, sk_bytes: SecretArg, registry: PyRef<'_, PySchemaRegistry>,
+                             context: Option<String>) -> PyResult<(PyObject, String)> {
+        let envelope = self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&unarmor_ref(ArmorKind::Envelope, &envelope)?)).map_err(to_py_err)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let registry = &registry.inner;
+        let (record, id) = py.allow_threads(|| self.inner.open_record(&envelope, &sk_bytes, registry, context.as_bytes())).map_err(to_py_err)?;
+        Ok((from_json_value(py, &record)?, hex::encode(id)))
+    }
+
+    /// Moves a sealed record to schema `to_schema` along a migration
+    /// `registry` declares and reseals it to `pk_bytes`:
+    /// `(envelope, evidence)`. `transform`, if given, is called with the
+    /// record (fields still protected) and returns the record for the new
+    /// schema; fields it protects the same way keep their protected value.
+    #[pyo3(signature = (envelope, sk_bytes, registry, to_schema, pk_bytes, transform=None, keys=Vec::new(), index_key=None, context=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn vault_migrate_record(&self, py: Python<'_>, envelope: BytesLike<'_>, sk_bytes: SecretArg, registry: PyRef<'_, PySchemaRegistry>,
+                                to_schema: &str, pk_bytes: Vec<u8>, transform: Option<PyObject>, keys: Vec<Vec<u8>>,
+                                index_key: Option<Vec<u8>>, context: Option<String>) -> PyResult<(PyObject, String)> {
+        let envelope = self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&unarmor_ref(ArmorKind::Envelope, &envelope)?)).map_err(to_py_err)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let to = schema_id(to_schema)?;
+        let (pk_bytes, index_key) = (unarmor(ArmorKind::PublicKey, pk_bytes)?, self::index_key(index_key)?);
+        let keys = keys.into_iter().map(|k| unarmor(ArmorKind::PublicKey, k)).collect::<PyResult<Vec<_>>>()?;
+        let context = context.unwrap_or_default();
+        // The transform runs under the GIL; its exception is raised as is.
+        let mut raised = None;
+        let transform = |record: serde_json::Value| match &transform {
+            None => Ok(record),
+            Some(f) => from_json_value(py, &record).and_then(|r| f.call1(py, (r,))).and_then(|r| to_json_value(py, r.as_ref(py)))
+                .map_err(|e| {
+                    raised = Some(e);
+                    CoreError::Config("record transform failed".into())
+                }),
+        };
+        let res = self.inner.migrate_record(&envelope, &sk_bytes, &registry.inner, &to, transform, &pk_bytes, &keys, index_key.as_ref(), context.as_bytes());
+        if let Some(e) = raised {
+            return Err(e);
+        }
+        let (env, evidence) = res.map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &env.to_bytes()).into(), evidence))
+    }
+
+    /// Decrypts a native envelope with custodians' escrow shares instead of
+    /// the recipient key. An `escrow` audit event is recorded and a
+    /// checkpoint signed over it before the data key is recovered; returns
+    /// `(plaintext, checkpoint)`. `auth_token` is checked first when
+    /// `escrow_decrypt` needs step-up.
+    #[pyo3(signature = (envelope, shares, auth_token=None))]
+    pub fn vault_open_escrowed(&self, py: Python<'_>, envelope: Vec<u8>, shares: Vec<Vec<u8>>,
+                               auth_token: Option<&str>) -> PyResult<(PyObject, PyObject)> {
+        let envelope = Envelope::from_bytes(&unarmor(ArmorKind::Envelope, envelope)?).map_err(to_py_err)?;
+        let shares = shares.iter().map(|s| EscrowShare::from_bytes(s)).collect::<CoreResult<Vec<_>>>().map_err(to_py_err)?;
+        step_up(&self.inner, SensitiveOp::EscrowDecrypt, auth_token)?;
+        let (pt, checkpoint) = py.allow_threads(|| self.inner.open_escrowed(&envelope, &shares)).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &pt).into(), PyBytes::new(py, &checkpoint.to_bytes()).into()))
+    }
+
+    /// Decrypts a restricted envelope once `approvals` (from
+    /// `approve_request`) of `request` meet the quorum set with
+    /// `set_quorum`. Raises `PermissionError` if the request is for another
+    /// envelope, has expired or lacks approvals.
+    #[pyo3(signature = (envelope, sk_bytes, request, approvals, context=None))]
+    pub fn vault_open_restricted(&self, py: Python<'_>, envelope: Vec<u8>, sk_bytes: SecretArg, request: Vec<u8>,
+                                 approvals: Vec<Vec<u8>>, context: Option<String>) -> PyResult<PyObject> {
+        let envelope = Envelope::from_bytes(&unarmor(ArmorKind::Envelope, envelope)?).map_err(to_py_err)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let request = DecryptionRequest::from_bytes(&request).map_err(to_py_err)?;
+        let approvals = approvals.iter().map(|a| Approval::from_bytes(a)).collect::<CoreResult<Vec<_>>>().map_err(to_py_err)?;
+        let context = context.unwrap_or_default();
+        let pt = py.allow_threads(|| self.inner.open_restricted(&envelope, &sk_bytes, context.as_bytes(), &request, &approvals))
+            .map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &pt).into())
+    }
+
+    /// Encrypts `data` to `pk_bytes` with signed decryption constraints;
+    /// returns `(envelope, evidence)`. `vault_open` then refuses it with
+    /// `ConstraintViolation`, a `PermissionError`, after `not_after` (Unix
+    /// seconds), on an engine whose fingerprint (hex) is not among
+    /// `fingerprints`, or for a caller without `role`. At least one must be
+    /// given. The constraints are signed with this engine's identity, which
+    /// an opening engine other than this one needs in its
+    /// `constraint_signers`.
+    #[pyo3(signature = (data, pk_bytes, not_after=None, fingerprints=None, role=None, context=None, armor=false))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn vault_seal_constrained(&self, py: Python<'_>, data: BytesLike<'_>, pk_bytes: Vec<u8>, not_after: Option<u64>,
+                                  fingerprints: Option<Vec<String>>, role: Option<String>, context: Option<String>,
+                                  armor: bool) -> PyResult<(PyObject, String)> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let fingerprints = fingerprints.unwrap_or_default().iter().map(|fp| hex_fingerprint(fp)).collect::<PyResult<Vec<_>>>()?;
+        let constraints = Constraints { not_after, fingerprints, role };
+        let context = context.unwrap_or_default();
+        let (env, evidence) = py.allow_threads(|| self.inner.seal_constrained(&data, &pk_bytes, context.as_bytes(), &constraints))
+            .map_err(to_py_err)?;
+        Ok((maybe_armor(py, ArmorKind::Envelope, &env.to_bytes(), armor), evidence))
+    }
+
+    /// Encrypts `data` to `pk_bytes` under the key of `epoch` in `keys`,
+    /// by default the current one; returns `(envelope, evidence)`. It opens
+    /// only with `vault_open_epoch`, until the epoch is shredded.
+    #[pyo3(signature = (data, pk_bytes, keys, epoch=None, context=None))]
+    pub fn vault_seal_epoch(&self, py: Python<'_>, data: BytesLike<'_>, pk_bytes: Vec<u8>, keys: PyRef<'_, PyEpochKeys>, epoch: Option<u32>,
+                            context: Option<String>) -> PyResult<(PyObject, String)> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        let (keys, epoch) = (&keys.inner, epoch.map(|index| keys.epoch(index)));
+        let (env, evidence) = py.allow_threads(|| match &epoch {
+            Some(epoch) => self.inner.seal_in_epoch(&data, &pk_bytes, context.as_bytes(), keys, epoch),
+            None => self.inner.seal_epoch(&data, &pk_bytes, context.as_bytes(), keys),
+        }).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &env.to_bytes()).into(), evidence))
+    }
+
+    /// Decrypts a `vault_seal_epoch` envelope. Raises `ValueError` once its
+    /// epoch has been shredded.
+    #[pyo3(signature = (envelope, sk_bytes, keys, context=None))]
+    pub fn vault_open_epoch(&self, py: Python<'_>, envelope: BytesLike<'_>, sk_bytes: SecretArg, keys: PyRef<'_, PyEpochKeys>,
+                            context: Option<String>) -> PyResult<PyObject> {
+        let envelope = Envelope::from_bytes(&unarmor_ref(ArmorKind::Envelope, &envelope)?).map_err(to_py_err)?;
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let keys = &keys.inner;
+        let pt = py.allow_threads(|| self.inner.open_epoch(&envelope, &sk_bytes, context.as_bytes(), keys)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &pt).into())
+    }
+
+    /// Encrypts `data` as an age file with a `titancore-kyber1024` recipient
+    /// stanza (ASCII-armored unless `armor=False`); returns `(file, evidence)`.
+    #[pyo3(signature = (data, pk_bytes, armor=true))]
+    pub fn vault_seal_age(&self, py: Python<'_>, data: BytesLike<'_>, pk_bytes: Vec<u8>, armor: bool) -> PyResult<(PyObject, String)> {
+        let (file, evidence) = py.allow_threads(|| self.inner.seal_age(&data, &pk_bytes, armor)).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &file).into(), evidence))
+    }
+
+    /// Decrypts an age file from `vault_seal_age`, binary or armored.
+    pub fn vault_open_age(&self, py: Python<'_>, file: Vec<u8>, sk_bytes: SecretArg) -> PyResult<PyObject> {
+        let pt = py.allow_threads(|| self.inner.open_age(&file, &sk_bytes)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &pt).into())
+    }
+
+    /// Encrypts `data` as a JWE (`alg` `KYBER1024`, direct key agreement);
+    /// returns `(token, evidence)`. `serialization` is `"compact"` or
+    /// `"json"` (flattened).
+    #[pyo3(signature = (data, pk_bytes, serialization="compact", context=None))]
+    pub fn vault_seal_jwe(&self, py: Python<'_>, data: BytesLike<'_>, pk_bytes: Vec<u8>, serialization: &str,
+                          context: Option<String>) -> PyResult<(String, String)> {
+        if !matches!(serialization, "compact" | "json") {
+            return Err(invalid_argument(format!("unknown serialization: {}", serialization)));
+        }
+        let context = context.unwrap_or_default();
+        let (jwe, evidence) = py.allow_threads(|| self.inner.seal_jwe(&data, &pk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        let token = if serialization == "json" { jwe.to_json() } else { jwe.to_compact() };
+        Ok((token, evidence))
+    }
+
+    /// Decrypts a JWE from `vault_seal_jwe`, in either serialization.
+    #[pyo3(signature = (token, sk_bytes, context=None))]
+    pub fn vault_open_jwe(&self, py: Python<'_>, token: &str, sk_bytes: SecretArg, context: Option<String>) -> PyResult<PyObject> {
+        let context = context.unwrap_or_default();
+        let pt = py.allow_threads(|| {
+            let jwe = self.inner.audited(OpType::Decrypt, &[], Jwe::parse(token))?;
+            self.inner.open_jwe(&jwe, &sk_bytes, context.as_bytes())
+        }).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &pt).into())
+    }
+
+    /// Like the module-level `generate_keypair`, but records a `keygen`
+    /// audit event for the new public key.
+    pub fn generate_keypair(&self, py: Python<'_>) -> PyResult<(PyObject, PyObject)> {
+        let (pk, sk) = py.allow_threads(|| self.inner.generate_keypair()).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &pk).into(), PyBytes::new(py, &sk).into()))
+    }
+
+    /// Records an operation performed outside the engine. `op` is one of
+    /// `encrypt`, `decrypt`, `sign`, `keygen`, `rekey`; `outcome` one of
+    /// `success`, `rate-limited`, `key-invalid`, `failed`. Returns the new
+    /// chain head.
+    #[pyo3(signature = (op, outcome, subject=Vec::new()))]
+    pub fn record_event(&self, py: Python<'_>, op: &str, outcome: &str, subject: Vec<u8>) -> PyResult<String> {
+        let op = OpType::parse(op).ok_or_else(|| invalid_argument(format!("unknown op: {}", op)))?;
+        let outcome = Outcome::parse(outcome).ok_or_else(|| invalid_argument(format!("unknown outcome: {}", outcome)))?;
+        py.allow_threads(|| self.inner.record_event(op, outcome, &subject)).map_err(to_py_err)
+    }
+
+    /// Seals each item on the worker pool; returns `[(envelope, evidence), ...]`
+    /// in input order and raises on the first item that failed.
+    #[pyo3(signature = (items, pk_bytes, context=None))]
+    pub fn vault_execute_many(&self, py: Python<'_>, items: Vec<BytesLike<'_>>, pk_bytes: Vec<u8>, context: Option<String>) -> PyResult<Vec<(PyObject, String)>> {
+        let context = context.unwrap_or_default();
+        let results = py.allow_threads(|| self.inner.seal_many_with_context(&items, &pk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        results.into_iter().map(|res| {
+            let (env, evidence) = res.map_err(to_py_err)?;
+            Ok((PyBytes::new(py, &env.to_bytes()).into(), evidence))
+        }).collect()
+    }
+
+    /// Encrypts a whole column (a list, NumPy/pandas/Polars/Arrow array, or
+    /// any iterable) of `bytes`, `str` (as UTF-8) or buffer values to one
+    /// recipient, in parallel and without holding the GIL. Returns a list
+    /// of envelopes in order; nulls (`None`, NaN) stay `None`. Counts as one
+    /// request against the rate limit; raises on the first value that fails.
+    #[pyo3(signature = (values, pk_bytes, context=None))]
+    pub fn encrypt_column(&self, py: Python<'_>, values: &PyAny, pk_bytes: Vec<u8>, context: Option<String>) -> PyResult<Vec<PyObject>> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        let cells = column_cells(values)?;
+        let items = cells.iter().flatten().map(|v| v.extract::<ColumnValue>()).collect::<PyResult<Vec<_>>>()?;
+        let results = py.allow_threads(|| self.inner.seal_many_with_context(&items, &pk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        let mut sealed = results.into_iter();
+        cells.iter().map(|cell| match cell {
+            Some(_) => {
+                let (env, _) = sealed.next().expect("one result per value").map_err(to_py_err)?;
+                Ok(PyBytes::new(py, &env.to_bytes()).into())
+            }
+            None => Ok(py.None()),
+        }).collect()
+    }
+
+    /// Reverses `encrypt_column`: decrypts every envelope in `values` with
+    /// one secret key, in parallel, keeping `None` cells. With `encoding`
+    /// (e.g. `"utf-8"`) the plaintexts are returned as `str`. Raises on the
+    /// first envelope that fails; every attempt is recorded.
+    #[pyo3(signature = (values, sk_bytes, context=None, encoding=None))]
+    pub fn decrypt_column(&self, py: Python<'_>, values: &PyAny, sk_bytes: SecretArg, context: Option<String>,
+                          encoding: Option<&str>) -> PyResult<Vec<PyObject>> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let cells = column_cells(values)?;
+        let envelopes = cells.iter().flatten().map(|v| {
+            let bytes = v.extract::<BytesLike>()?;
+            let bytes = unarmor_ref(ArmorKind::Envelope, &bytes)?;
+            self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&bytes)).map_err(to_py_err)
+        }).collect::<PyResult<Vec<_>>>()?;
+        let results = py.allow_threads(|| self.inner.open_many_with_context(&envelopes, &sk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        let mut opened = results.into_iter();
+        cells.iter().map(|cell| {
+            if cell.is_none() {
+                return Ok(py.None());
+            }
+            let pt = opened.next().expect("one result per envelope").map_err(to_py_err)?;
+            let bytes = PyBytes::new(py, &pt);
+            match encoding {
+                Some(encoding) => Ok(bytes.call_method1("decode", (encoding,))?.into()),
+                None => Ok(bytes.into()),
+            }
+        }).collect()
+    }
+
+    /// Decrypts native envelopes (raw or armored) with one secret key, in
+    /// parallel and without holding the GIL. Returns, in order, the
+    /// plaintext or the exception for each envelope; every attempt is
+    /// recorded as a `decrypt` event. Only a malformed key raises.
+    #[pyo3(signature = (envelopes, sk_bytes, context=None))]
+    pub fn vault_open_many(&self, py: Python<'_>, envelopes: Vec<BytesLike<'_>>, sk_bytes: SecretArg, context: Option<String>) -> PyResult<Vec<PyObject>> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        // Unparsable envelopes are recorded here and skip the batch.
+        let mut valid = Vec::new();
+        let mut failed = Vec::new();
+        for envelope in envelopes {
+            let parsed = unarmor_ref(ArmorKind::Envelope, &envelope)
+                .and_then(|env| self.inner.audited(OpType::Decrypt, &[], Envelope::from_bytes(&env)).map_err(to_py_err));
+            match parsed {
+                Ok(envelope) => {
+                    valid.push(envelope);
+                    failed.push(None);
+                }
+                Err(e) => failed.push(Some(e)),
+            }
+        }
+        let results = py.allow_threads(|| self.inner.open_many_with_context(&valid, &sk_bytes, context.as_bytes())).map_err(to_py_err)?;
+        let mut opened = results.into_iter();
+        Ok(failed.into_iter().map(|err| {
+            let res = match err {
+                Some(e) => Err(e),
+                None => opened.next().expect("one result per parsed envelope").map_err(to_py_err),
+            };
+            match res {
+                Ok(pt) => PyBytes::new(py, &pt).into(),
+                Err(e) => e.into_value(py).into(),
+            }
+        }).collect())
+    }
+
+    /// Re-encrypts a native envelope (raw or armored) from `old_sk` to
+    /// `new_pk`, recording a `rekey` event. Returns `(envelope, checkpoint)`,
+    /// the checkpoint signed over the rotation.
+    #[pyo3(signature = (envelope, old_sk, new_pk, context=None))]
+    pub fn vault_rewrap(&self, py: Python<'_>, envelope: Vec<u8>, old_sk: SecretArg, new_pk: Vec<u8>,
+                        context: Option<String>) -> PyResult<(PyObject, PyObject)> {
+        let envelope = unarmor(ArmorKind::Envelope, envelope)
+            .and_then(|env| self.inner.audited(OpType::Rekey, &[], Envelope::from_bytes(&env)).map_err(to_py_err))?;
+        let old_sk = old_sk.unarmor(ArmorKind::SecretKey)?;
+        let new_pk = unarmor(ArmorKind::PublicKey, new_pk)?;
+        let context = context.unwrap_or_default();
+        let (rotated, checkpoint) = py.allow_threads(|| self.inner.rewrap(&envelope, &old_sk, &new_pk, context.as_bytes()))
+            .map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &rotated.to_bytes()).into(), PyBytes::new(py, &checkpoint.to_bytes()).into()))
+    }
+
+    /// `vault_rewrap` for many envelopes, in parallel. Returns
+    /// `(results, checkpoint)` where `results` holds, in order, the new
+    /// envelope or the exception for each input; one checkpoint covers the
+    /// batch. Only a malformed key raises.
+    #[pyo3(signature = (envelopes, old_sk, new_pk, context=None))]
+    pub fn vault_rewrap_many(&self, py: Python<'_>, envelopes: Vec<Vec<u8>>, old_sk: SecretArg, new_pk: Vec<u8>,
+                             context: Option<String>) -> PyResult<(Vec<PyObject>, PyObject)> {
+        let old_sk = old_sk.unarmor(ArmorKind::SecretKey)?;
+        let new_pk = unarmor(ArmorKind::PublicKey, new_pk)?;
+        let context = context.unwrap_or_default();
+        let parsed = envelopes.into_iter().map(|envelope| {
+            unarmor(ArmorKind::Envelope, envelope)
+                .and_then(|env| self.inner.audited(OpType::Rekey, &[], Envelope::from_bytes(&env)).map_err(to_py_err))
+        }).collect::<Vec<_>>();
+        let valid = parsed.iter().filter_map(|res| res.as_ref().ok().cloned()).collect::<Vec<_>>();
+        let (results, checkpoint) = py.allow_threads(|| self.inner.rewrap_many(&valid, &old_sk, &new_pk, context.as_bytes()))
+            .map_err(to_py_err)?;
+        let mut rotated = results.into_iter();
+        let results = parsed.into_iter().map(|res| {
+            let res = res.and_then(|_| rotated.next().expect("one result per parsed envelope").map_err(to_py_err));
+            match res {
+                Ok(env) => PyBytes::new(py, &env.to_bytes()).into(),
+                Err(e) => e.into_value(py).into(),
+            }
+        }).collect();
+        Ok((results, PyBytes::new(py, &checkpoint.to_bytes()).into()))
+    }
+
+    /// Rotates every native envelope and chunked stream under `dir` to
+    /// `new_pk` in place, each written beside the original and renamed over
+    /// it. Returns a dict of relative paths: `rotated`, `failed` (as
+    /// `(path, exception)`), `skipped` (other files), and the `checkpoint`
+    /// signed after the last object.
+    #[pyo3(signature = (dir, old_sk, new_pk, context=None))]
+    pub fn rewrap_dir(&self, py: Python<'_>, dir: PathBuf, old_sk: SecretArg, new_pk: Vec<u8>,
+                      context: Option<String>) -> PyResult<PyObject> {
+        let old_sk = old_sk.unarmor(ArmorKind::SecretKey)?;
+        let new_pk = unarmor(ArmorKind::PublicKey, new_pk)?;
+        let context = context.unwrap_or_default();
+        let report = py.allow_threads(|| self.inner.rewrap_dir(&dir, &old_sk, &new_pk, context.as_bytes())).map_err(to_py_err)?;
+        let failed = report.failed.into_iter()
+            .map(|(path, e)| (path, to_py_err(e).into_value(py)))
+            .collect::<Vec<_>>();
+        let dict = PyDict::new(py);
+        dict.set_item("rotated", report.rotated)?;
+        dict.set_item("failed", failed)?;
+        dict.set_item("skipped", report.skipped)?;
+        dict.set_item("checkpoint", PyBytes::new(py, &report.checkpoint.to_bytes()))?;
+        Ok(dict.into())
+    }
+
+    /// Encrypts the file at `src` into a chunked stream at `dst`; returns the evidence hash.
+    /// `framing` is `"length-prefixed"` or `"fixed"` (every chunk but the
+    /// last exactly `chunk_size + 16` bytes, no length fields). `aad` is
+    /// bound into every chunk and must be passed again to `vault_open_file`.
+    #[pyo3(signature = (src, dst, pk_bytes, chunk_size=stream::DEFAULT_CHUNK_SIZE, context=None, framing="length-prefixed", aad=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn vault_seal_file(&self, py: Python<'_>, src: PathBuf, dst: PathBuf, pk_bytes: Vec<u8>, chunk_size: usize,
+                           context: Option<String>, framing: &str, aad: Option<Vec<u8>>) -> PyResult<String> {
+        let framing = Framing::parse(framing).ok_or_else(|| invalid_argument(format!("unknown framing: {}", framing)))?;
+        let options = StreamOptions { chunk_size, framing, aad: aad.unwrap_or_default() };
+        let context = context.unwrap_or_default();
+        py.allow_threads(|| self.inner.seal_file_with(&src, &dst, &pk_bytes, &options, context.as_bytes())).map_err(to_py_err)
+    }
+
+    /// Decrypts a file written by `vault_seal_file`; returns the plaintext size.
+    #[pyo3(signature = (src, dst, sk_bytes, context=None, aad=None))]
+    pub fn vault_open_file(&self, py: Python<'_>, src: PathBuf, dst: PathBuf, sk_bytes: SecretArg, context: Option<String>,
+                           aad: Option<Vec<u8>>) -> PyResult<u64> {
+        let context = context.unwrap_or_default();
+        let aad = aad.unwrap_or_default();
+        py.allow_threads(|| self.inner.open_file_with(&src, &dst, &sk_bytes, &aad, context.as_bytes())).map_err(to_py_err)
+    }
+
+    /// Incremental form of `vault_seal_file` for data that arrives in
+    /// pieces: feed them to the sealer's `update` and end with
+    /// `finish_stream_sealer`. Options are as for `vault_seal_file`.
+    #[pyo3(signature = (pk_bytes, chunk_size=stream::DEFAULT_CHUNK_SIZE, context=None, framing="length-prefixed", aad=None))]
+    pub fn stream_sealer(&self, pk_bytes: Vec<u8>, chunk_size: usize, context: Option<String>, framing: &str,
+                         aad: Option<Vec<u8>>) -> PyResult<PyStreamSealer> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let framing = Framing::parse(framing).ok_or_else(|| invalid_argument(format!("unknown framing: {}", framing)))?;
+        let options = StreamOptions { chunk_size, framing, aad: aad.unwrap_or_default() };
+        let sealer = self.inner.stream_sealer(&pk_bytes, &options, context.unwrap_or_default().as_bytes()).map_err(to_py_err)?;
+        Ok(PyStreamSealer { inner: Some(sealer) })
+    }
+
+    /// Ends the stream of `sealer`; returns its last bytes and the evidence
+    /// hash.
+    pub fn finish_stream_sealer(&self, py: Python<'_>, sealer: &mut PyStreamSealer) -> PyResult<(PyObject, String)> {
+        let inner = sealer.inner.take().ok_or_else(|| invalid_argument("stream already finished"))?;
+        let (out, evidence) = py.allow_threads(|| self.inner.finish_stream_sealer(inner)).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &out).into(), evidence))
+    }
+
+    /// Incremental form of `vault_open_file`: feed the stream to the
+    /// opener's `update` and end with `finish_stream_opener`.
+    #[pyo3(signature = (sk_bytes, context=None, aad=None))]
+    pub fn stream_opener(&self, sk_bytes: SecretArg, context: Option<String>, aad: Option<Vec<u8>>) -> PyResult<PyStreamOpener> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let aad = aad.unwrap_or_default();
+        let opener = self.inner.stream_opener(&sk_bytes, &aad, context.unwrap_or_default().as_bytes()).map_err(to_py_err)?;
+        Ok(PyStreamOpener { inner: Some(opener) })
+    }
+
+    /// Ends the stream of `opener`; returns the last plaintext. Raises if
+    /// the stream was cut short or an earlier `update` failed, and only
+    /// then is the output known to be complete.
+    pub fn finish_stream_opener(&self, py: Python<'_>, opener: &mut PyStreamOpener) -> PyResult<PyObject> {
+        let inner = opener.inner.take().ok_or_else(|| invalid_argument("stream already finished"))?;
+        let out = py.allow_threads(|| self.inner.finish_stream_opener(inner)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &out).into())
+    }
+
+    /// Async generator of the encrypted stream (as `vault_seal_file`
+    /// writes it) of what `reader` provides: an object with an awaitable
+    /// `read(n)`, such as aiohttp's `StreamReader` or an `UploadFile`, or an
+    /// async iterable of bytes. Chunks are encrypted on a worker thread, so
+    /// the event loop is not blocked and nothing is buffered beyond one
+    /// chunk.
+    #[pyo3(signature = (reader, pk_bytes, chunk_size=stream::DEFAULT_CHUNK_SIZE, context=None, framing="length-prefixed", aad=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn encrypt_stream(slf: &PyCell<Self>, py: Python<'_>, reader: PyObject, pk_bytes: Vec<u8>, chunk_size: usize,
+                          context: Option<String>, framing: &str, aad: Option<Vec<u8>>) -> PyResult<PyObject> {
+        let sealer = slf.borrow().stream_sealer(pk_bytes, chunk_size, context, framing, aad)?;
+        Ok(async_streams(py)?.call_method1("encrypt_stream", (slf, reader, chunk_size, sealer))?.into())
+    }
+
+    /// Async generator of the plaintext of a stream read from `reader` (as
+    /// for `encrypt_stream`). Plaintext yielded before an exception must be
+    /// discarded; the generator raises if the stream is cut short.
+    #[pyo3(signature = (reader, sk_bytes, context=None, aad=None))]
+    pub fn decrypt_stream(slf: &PyCell<Self>, py: Python<'_>, reader: PyObject, sk_bytes: SecretArg, context: Option<String>,
+                          aad: Option<Vec<u8>>) -> PyResult<PyObject> {
+        let opener = slf.borrow().stream_opener(sk_bytes, context, aad)?;
+        Ok(async_streams(py)?.call_method1("decrypt_stream", (slf, reader, stream::DEFAULT_CHUNK_SIZE, opener))?.into())
+    }
+
+    /// Writes an archive of `entries`, `(name, path)` pairs, to `dst` under
+    /// one encapsulation; returns the evidence hash. Entries can later be
+    /// read one by one with `vault_extract`. The source files are shredded
+    /// afterwards if the engine was built with `shred_sources`.
+    #[pyo3(signature = (dst, entries, pk_bytes, chunk_size=stream::DEFAULT_CHUNK_SIZE, context=None))]
+    pub fn vault_seal_archive(&self, py: Python<'_>, dst: PathBuf, entries: Vec<(String, PathBuf)>, pk_bytes: Vec<u8>,
+                              chunk_size: usize, context: Option<String>) -> PyResult<String> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        py.allow_threads(|| {
+            let file = std::io::BufWriter::new(std::fs::File::create(&dst)?);
+            let mut archive = self.inner.archive_writer(file, &pk_bytes, chunk_size, context.as_bytes())?;
+            for (name, path) in &entries {
+                archive.add(name, std::io::BufReader::new(std::fs::File::open(path)?))?;
+            }
+            archive.finish()
+        }).inspect_err(|_| {
+            let _ = std::fs::remove_file(&dst);
+        }).and_then(|evidence| {
+            if let Some(passes) = self.inner.shred_sources() {
+                entries.iter().try_for_each(|(_, path)| shred::secure_delete(path, passes))?;
+            }
+            Ok(evidence)
+        }).map_err(to_py_err)
+    }
+
+    /// `[{"name", "size"}]` for the archive at `src`; decrypts only the index.
+    #[pyo3(signature = (src, sk_bytes, context=None))]
+    pub fn vault_list_archive(&self, py: Python<'_>, src: PathBuf, sk_bytes: SecretArg, context: Option<String>) -> PyResult<Vec<PyObject>> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let archive = py.allow_threads(|| {
+            self.inner.open_archive(std::io::BufReader::new(std::fs::File::open(&src)?), &sk_bytes, context.as_bytes())
+        }).map_err(to_py_err)?;
+        archive.entries().iter().map(|e| {
+            let dict = PyDict::new(py);
+            dict.set_item("name", &e.name)?;
+            dict.set_item("size", e.size)?;
+            Ok(dict.into())
+        }).collect()
+    }
+
+    /// Decrypts just the entries `names` of the archive at `src`; returns
+    /// `{name: bytes}`.
+    #[pyo3(signature = (src, sk_bytes, names, context=None))]
+    pub fn vault_extract(&self, py: Python<'_>, src: PathBuf, sk_bytes: SecretArg, names: Vec<String>,
+                         context: Option<String>) -> PyResult<PyObject> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let context = context.unwrap_or_default();
+        let extracted = py.allow_threads(|| {
+            let file = std::io::BufReader::new(std::fs::File::open(&src)?);
+            let mut archive = self.inner.open_archive(file, &sk_bytes, context.as_bytes())?;
+            names.iter().map(|name| {
+                let mut data = Vec::new();
+                archive.extract(name, &mut data)?;
+                Ok((name, data))
+            }).collect::<CoreResult<Vec<_>>>()
+        }).map_err(to_py_err)?;
+        let dict = PyDict::new(py);
+        for (name, data) in extracted {
+            dict.set_item(name, PyBytes::new(py, &data))?;
+        }
+        Ok(dict.into())
+    }
+
+    /// Starts an upload encrypted to `pk_bytes` in parts of `part_size`
+    /// plaintext bytes, each sealed to exactly `part_size + 16` bytes (the
+    /// last may be shorter).
+    #[pyo3(signature = (pk_bytes, part_size, context=None))]
+    pub fn multipart_upload(&self, pk_bytes: Vec<u8>, part_size: usize, context: Option<String>) -> PyResult<PyMultipartUpload> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let upload = self.inner.multipart_upload(&pk_bytes, part_size, context.unwrap_or_default().as_bytes()).map_err(to_py_err)?;
+        Ok(PyMultipartUpload { inner: Some(upload) })
+    }
+
+    /// Ends `upload` and returns its manifest, signed with the checkpoint
+    /// key, listing the hash of every part. Raises `ValueError` if a part is
+    /// missing.
+    pub fn finish_multipart(&self, py: Python<'_>, upload: &mut PyMultipartUpload) -> PyResult<PyObject> {
+        let inner = upload.inner.take().ok_or_else(|| invalid_argument("upload already finished"))?;
+        let manifest = self.inner.finish_multipart(inner).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &manifest.to_bytes()).into())
+    }
+
+    /// Decrypts the parts of the upload with `header` (its `header`, or a
+    /// manifest's).
+    #[pyo3(signature = (header, sk_bytes, context=None))]
+    pub fn multipart_opener(&self, header: Vec<u8>, sk_bytes: SecretArg, context: Option<String>) -> PyResult<PyMultipartOpener> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let inner = self.inner.multipart_opener(&header, &sk_bytes, context.unwrap_or_default().as_bytes()).map_err(to_py_err)?;
+        Ok(PyMultipartOpener { inner })
+    }
+
+    /// Encrypts every regular file under `src_dir` into `dst_dir` and writes
+    /// a manifest of paths, sizes and hashes signed with the checkpoint key.
+    /// Returns the manifest as a dict with `fingerprint`, `created`,
+    /// `entries` (`path`, `size`, `hash`, `object`) and the signed `bytes`.
+    /// Paths and sizes are not encrypted.
+    #[pyo3(signature = (src_dir, dst_dir, pk_bytes, chunk_size=stream::DEFAULT_CHUNK_SIZE, context=None))]
+    pub fn encrypt_tree(&self, py: Python<'_>, src_dir: PathBuf, dst_dir: PathBuf, pk_bytes: Vec<u8>, chunk_size: usize,
+                        context: Option<String>) -> PyResult<PyObject> {
+        let pk_bytes = unarmor(ArmorKind::PublicKey, pk_bytes)?;
+        let context = context.unwrap_or_default();
+        let signed = py.allow_threads(|| self.inner.encrypt_tree(&src_dir, &dst_dir, &pk_bytes, chunk_size, context.as_bytes()))
+            .map_err(to_py_err)?;
+        Ok(manifest_dict(py, &signed)?.into())
+    }
+
+    /// Checks the signed manifest of a tree from `encrypt_tree` against
+    /// `trusted_pk` and every encrypted object against the manifest; no
+    /// secret key needed. Returns the manifest dict; raises `ValueError` on
+    /// any mismatch.
+    pub fn verify_tree(&self, py: Python<'_>, dir: PathBuf, trusted_pk: Vec<u8>) -> PyResult<PyObject> {
+        let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+        let signed = py.allow_threads(|| self.inner.verify_tree(&dir, &trusted_pk)).map_err(to_py_err)?;
+        Ok(manifest_dict(py, &signed)?.into())
+    }
+
+    /// `verify_tree`, then decrypts the tree into `out_dir`, checking each
+    /// restored file's size and hash against the manifest.
+    #[pyo3(signature = (dir, out_dir, sk_bytes, trusted_pk, context=None))]
+    pub fn restore_tree(&self, py: Python<'_>, dir: PathBuf, out_dir: PathBuf, sk_bytes: SecretArg, trusted_pk: Vec<u8>,
+                        context: Option<String>) -> PyResult<PyObject> {
+        let sk_bytes = sk_bytes.unarmor(ArmorKind::SecretKey)?;
+        let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+        let context = context.unwrap_or_default();
+        let signed = py.allow_threads(|| self.inner.restore_tree(&dir, &out_dir, &sk_bytes, &trusted_pk, context.as_bytes()))
+            .map_err(to_py_err)?;
+        Ok(manifest_dict(py, &signed)?.into())
+    }
+
+    /// Writes and syncs any audit entries held back by the sync policy.
+    pub fn flush(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.inner.flush_audit()).map_err(to_py_err)
+    }
+
+    /// Flushes the audit log, writes a final signed checkpoint to
+    /// `<log_path>.checkpoint` and returns it, then forgets the checkpoint
+    /// signing key. The engine is unusable afterwards: calls that record
+    /// or sign raise `ValueError`. Returns `None` if already closed.
+    pub fn close(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        if self.inner.is_closed() {
+            return Ok(None);
+        }
+        let inner = &mut self.inner;
+        let checkpoint = py.allow_threads(|| inner.close()).map_err(to_py_err)?;
+        self.is_authorized = false;
+        let bytes = checkpoint.to_bytes();
+        std::fs::write(format!("{}.checkpoint", self.log_path), &bytes)?;
+        Ok(Some(PyBytes::new(py, &bytes).into()))
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, py: Python<'_>, _exc_type: PyObject, _exc: PyObject, _tb: PyObject) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+
+    /// `(proof, root)` for the entry with `counter`, where `root` is
+    /// `{"first_counter", "last_counter", "size", "root"}`; `None` until the
+    /// entry's Merkle batch is sealed (full or flushed).
+    pub fn prove_inclusion(&self, py: Python<'_>, counter: u64) -> PyResult<Option<(PyObject, PyObject)>> {
+        let Some((proof, batch)) = self.inner.prove_inclusion(counter) else { return Ok(None) };
+        Ok(Some((PyBytes::new(py, &proof.to_bytes()).into(), batch_dict(py, &batch)?.into())))
+    }
+
+    /// Self-contained evidence bundle for `counter` (entry, inclusion proof,
+    /// signed checkpoint) for `verify_evidence`. Passing the operation's
+    /// `envelope` also binds the bundle to it. `None` when
+    /// `prove_inclusion` would be. `armor=True` returns a `BEGIN TITAN
+    /// EVIDENCE` block.
+    #[pyo3(signature = (counter, envelope=None, armor=false))]
+    pub fn export_evidence(&self, py: Python<'_>, counter: u64, envelope: Option<Vec<u8>>, armor: bool) -> PyResult<Option<PyObject>> {
+        let envelope = envelope.map(|e| unarmor(ArmorKind::Envelope, e)).transpose()?;
+        let bundle = py.allow_threads(|| {
+            let envelope = envelope.as_deref().map(Envelope::from_bytes).transpose()?;
+            self.inner.export_evidence(counter, envelope.as_ref())
+        }).map_err(to_py_err)?;
+        Ok(bundle.map(|b| maybe_armor(py, ArmorKind::Evidence, &b.to_bytes(), armor)))
+    }
+
+    /// Background writer queue metrics (`depth`, `capacity`, `high_water`,
+    /// `written`), or `None` when audit writes are synchronous.
+    pub fn audit_queue_stats(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let Some(stats) = self.inner.audit_queue_stats() else { return Ok(None) };
+        let dict = PyDict::new(py);
+        dict.set_item("depth", stats.depth)?;
+        dict.set_item("capacity", stats.capacity)?;
+        dict.set_item("high_water", stats.high_water)?;
+        dict.set_item("written", stats.written)?;
+        Ok(Some(dict.into()))
+    }
+
+    /// Signed checkpoint of the current chain head, as bytes for
+    /// `verify_checkpoint`; a Dilithium5 `COSE_Sign1` with
+    /// `output_format="cose"`, and armored with `armor=True`.
+    #[pyo3(signature = (output_format="native", armor=false))]
+    pub fn checkpoint(&self, py: Python<'_>, output_format: &str, armor: bool) -> PyResult<PyObject> {
+        check_output_format(output_format)?;
+        let bytes = py.allow_threads(|| match output_format {
+            "cose" => self.inner.checkpoint_cose(),
+            _ => self.inner.checkpoint().map(|cp| cp.to_bytes()),
+        }).map_err(to_py_err)?;
+        Ok(maybe_armor(py, ArmorKind::Checkpoint, &bytes, armor))
+    }
+
+    /// Unsigned engine certificate for `sign_certificate`, binding the
+    /// checkpoint signing key to this engine's fingerprint and
+    /// `license_serial`. Call after `set_signing_keypair`.
+    #[pyo3(signature = (license_serial, valid_days=365, serial=None))]
+    pub fn certificate_request(&self, py: Python<'_>, license_serial: &str, valid_days: u64, serial: Option<u64>) -> PyResult<PyObject> {
+        let now = unix_now();
+        let body = self.inner.certificate_request(license_serial, random_serial(serial)?, now, now + valid_days * 86_400);
+        Ok(PyBytes::new(py, &body.to_bytes()).into())
+    }
+
+    /// Armored request for the vendor to answer with `grant_activation`:
+    /// this engine's fingerprint and a fresh nonce.
+    pub fn generate_activation_request(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let request = self.inner.activation_request().map_err(to_py_err)?;
+        Ok(maybe_armor(py, ArmorKind::ActivationRequest, &request.to_bytes(), true))
+    }
+
+    /// Installs the vendor's answer to `generate_activation_request`, raw
+    /// or armored, and returns it as a dict like `activation`. Raises
+    /// `ValueError` unless it is signed with `vendor_key`, names this
+    /// engine and a request it made, and is valid now. It still installs
+    /// after a restart with the same `identity`, so keep it and install it
+    /// again at each start.
+    pub fn install_activation(&mut self, py: Python<'_>, response: Vec<u8>) -> PyResult<PyObject> {
+        let response = SignedActivation::from_bytes(&unarmor(ArmorKind::Activation, response)?).map_err(to_py_err)?;
+        self.inner.install_activation(&response).map_err(to_py_err)?;
+        Ok(activation_dict(py, &response, self.inner.is_activated())?.into())
+    }
+
+    /// The installed activation as a dict (`fingerprint`, `license`,
+    /// `not_before`, `not_after`, and `active`, whether it is valid now),
+    /// or `None`.
+    #[getter]
+    fn activation(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.inner.activation().map(|a| Ok(activation_dict(py, a, self.inner.is_activated())?.into())).transpose()
+    }
+
+    /// Checks recipients (before encrypting) and checkpoint keys (in
+    /// `verify_checkpoint`) for revocation records signed by one of
+    /// `trusted_issuers`, from `records` and/or a `lookup(key_id_hex)`
+    /// callable returning a list of record bytes. A "good" answer is cached
+    /// for `cache_ttl_ms`; a revocation for good. A raising `lookup` fails
+    /// the operation rather than passing the key.
+    #[pyo3(signature = (trusted_issuers, records=Vec::new(), lookup=None, cache_ttl_ms=revocation::DEFAULT_CACHE_TTL.as_millis() as u64,
+                        auth_token=None))]
+    pub fn set_revocation(&mut self, trusted_issuers: Vec<Vec<u8>>, records: Vec<Vec<u8>>, lookup: Option<PyObject>,
+                          cache_ttl_ms: u64, auth_token: Option<&str>) -> PyResult<()> {
+        let trusted_issuers = trusted_issuers.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+        let list = RevocationList::new();
+        for bytes in records {
+            list.add(SignedRevocation::from_bytes(&bytes).map_err(to_py_err)?);
+        }
+        let source = PyRevocationSource { list, lookup };
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_revocation_checker(RevocationChecker::new(Box::new(source), trusted_issuers, Duration::from_millis(cache_ttl_ms)))
+            .map_err(to_py_err)
+    }
+
+    /// `{"status": "good"}` or `{"status": "revoked", "revoked_at", "reason"}`
+    /// for the key with hex `key_id` (see `key_id()`).
+    pub fn check_key_status(&self, py: Python<'_>, key_id: &str) -> PyResult<PyObject> {
+        let mut id = [0u8; 32];
+        hex::decode_to_slice(key_id, &mut id).map_err(|_| invalid_argument("bad key id"))?;
+        let status = py.allow_threads(|| self.inner.check_key_status(&id)).map_err(to_py_err)?;
+        let dict = PyDict::new(py);
+        match status {
+            KeyStatus::Good => dict.set_item("status", "good")?,
+            KeyStatus::Revoked { revoked_at, reason } => {
+                dict.set_item("status", "revoked")?;
+                dict.set_item("revoked_at", revoked_at)?;
+                dict.set_item("reason", reason.as_str())?;
+            }
+        }
+        Ok(dict.into())
+    }
+
+    /// Attestation of this engine's fingerprint, license, chain head and TPM
+    /// quote, answering the verifier's `nonce`, signed with the checkpoint
+    /// key. `quote=(attest, signature)` sends a fresh quote (e.g. taken over
+    /// the same nonce) instead of the one the engine was built with; it
+    /// must cover the same PCR values. Check with `verify_attestation`.
+    #[pyo3(signature = (nonce=None, quote=None))]
+    pub fn attest(&self, py: Python<'_>, nonce: Option<Vec<u8>>, quote: Option<(Vec<u8>, Vec<u8>)>) -> PyResult<PyObject> {
+        let quote = quote.map(|(attest, signature)| TpmQuote::new(attest, signature)).transpose().map_err(to_py_err)?;
+        let signed = self.inner.attest(&self.license_sig, &nonce.unwrap_or_default(), quote).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &signed.to_bytes()).into())
+    }
+
+    /// Measures, for about `seconds` in total, Kyber encapsulation and
+    /// decapsulation, session-key derivation, sealing and opening
+    /// `payload_size`-byte payloads under this engine's suite, and audit
+    /// writes. Audit entries go to a scratch log next to `log_path`,
+    /// written under `sync_policy` and removed afterwards; the engine's own
+    /// log and rate limit are untouched. Returns `{"payload_size",
+    /// "suite", "aes_hardware", "results": [{"name", "ops", "ops_per_sec",
+    /// "bytes_per_sec", "mean_us", "p50_us", "p99_us"}]}`; `aes_hardware`
+    /// is false if the CPU lacks the AES instructions.
+    #[pyo3(signature = (payload_size=4096, seconds=5.0, sync_policy="always", sync_every=64, sync_interval_ms=1000))]
+    pub fn benchmark(&self, py: Python<'_>, payload_size: usize, seconds: f64, sync_policy: &str, sync_every: usize,
+                     sync_interval_ms: u64) -> PyResult<PyObject> {
+        let duration = Duration::try_from_secs_f64(seconds).map_err(|_| invalid_argument("bad seconds"))?;
+        let policy = parse_sync_policy(sync_policy, sync_every, sync_interval_ms)?;
+        let path = format!("{}.bench.{}", self.log_path, std::process::id());
+        let report = py.allow_threads(|| {
+            let scratch = FileSink::with_policy(path.clone(), policy);
+            let report = self.inner.benchmark(payload_size, duration, &scratch);
+            drop(scratch);
+            let _ = std::fs::remove_file(&path);
+            report
+        }).map_err(to_py_err)?;
+        let results = report.results.iter().map(|r| {
+            let dict = PyDict::new(py);
+            dict.set_item("name", r.name)?;
+            dict.set_item("ops", r.ops)?;
+            dict.set_item("ops_per_sec", r.ops_per_sec)?;
+            dict.set_item("bytes_per_sec", r.bytes_per_sec)?;
+            dict.set_item("mean_us", r.mean_us)?;
+            dict.set_item("p50_us", r.p50_us)?;
+            dict.set_item("p99_us", r.p99_us)?;
+            Ok(dict.into())
+        }).collect::<PyResult<Vec<PyObject>>>()?;
+        let dict = PyDict::new(py);
+        dict.set_item("payload_size", report.payload_size)?;
+        dict.set_item("suite", report.suite.name())?;
+        dict.set_item("aes_hardware", report.aes_hardware)?;
+        dict.set_item("results", results)?;
+        Ok(dict.into())
+    }
+
+    /// The signed record the audit log starts with, for `verify_genesis`;
+    /// `None` for a log started before genesis records or restored from
+    /// `state` onto an empty log.
+    fn genesis(&self, py: Python<'_>) -> Option<PyObject> {
+        self.inner.genesis().map(|g| PyBytes::new(py, &g.to_bytes()).into())
+    }
+
+    /// Signed chain snapshots written so far, oldest first, as bytes for
+    /// `verify_audit_log`'s `from_snapshot`.
+    fn audit_snapshots(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let snapshots = py.allow_threads(|| self.inner.audit_snapshots()).map_err(to_py_err)?;
+        Ok(snapshots.iter().map(|s| PyBytes::new(py, &s.to_bytes()).into()).collect())
+    }
+
+    /// Like the module-level `verify_checkpoint`, but raises
+    /// `PermissionError` if `trusted_pk` has been revoked.
+    pub fn verify_checkpoint(&self, py: Python<'_>, checkpoint: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+        let checkpoint = unarmor(ArmorKind::Checkpoint, checkpoint)?;
+        let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+        let cp = SignedCheckpoint::from_bytes(&checkpoint).map_err(to_py_err)?;
+        if !py.allow_threads(|| self.inner.verify_checkpoint(&cp, &trusted_pk)).map_err(to_py_err)? {
+            return Ok(None);
+        }
+        Ok(Some(checkpoint_dict(py, &cp)?.into()))
+    }
+
+    /// Channel initiator authenticated by this engine's checkpoint signing
+    /// key, to the engine whose `checkpoint_public_key` is
+    /// `responder_public_key`.
+    pub fn channel_initiator(&self, responder_public_key: Vec<u8>) -> PyResult<PyChannelInitiator> {
+        let responder = unarmor(ArmorKind::SigningPublicKey, responder_public_key)?;
+        let inner = self.inner.channel_initiator(&responder).map_err(to_py_err)?;
+        Ok(PyChannelInitiator { inner })
+    }
+
+    /// Channel responder authenticated by this engine's checkpoint signing
+    /// key, accepting the engines whose checkpoint keys are
+    /// `trusted_initiators`. Revoked keys are not accepted.
+    pub fn channel_responder(&self, trusted_initiators: Vec<Vec<u8>>) -> PyResult<PyChannelResponder> {
+        let trusted = trusted_initiators.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+        let inner = self.inner.channel_responder(trusted).map_err(to_py_err)?;
+        Ok(PyChannelResponder { inner })
+    }
+
+    /// Requires approvals from `threshold` of the Dilithium5 `approvers`
+    /// keys for `vault_open_restricted`. Recorded as a `rekey` event. A key
+    /// listed twice is refused, since it approves only once.
+    #[pyo3(signature = (approvers, threshold, auth_token=None))]
+    pub fn set_quorum(&mut self, approvers: Vec<Vec<u8>>, threshold: usize, auth_token: Option<&str>) -> PyResult<()> {
+        let approvers = approvers.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+        let policy = QuorumPolicy::new(approvers, threshold).map_err(to_py_err)?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_quorum_policy(policy).map_err(to_py_err)
+    }
+
+    /// Allows at most `max` uses of the key with hex `key_id` (see `key_id`)
+    /// per `window_seconds`: sealing to it, or `vault_open_escrowed` for the
+    /// escrow key. Calls over the limit raise `RuntimeError("Rate Limit
+    /// Exceeded")` and are recorded. Counts reset when the engine restarts.
+    #[pyo3(signature = (key_id, max, window_seconds, auth_token=None))]
+    pub fn set_key_limit(&mut self, key_id: &str, max: usize, window_seconds: u64, auth_token: Option<&str>) -> PyResult<()> {
+        let key_id = hex_key_id(key_id)?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_key_limit(key_id, max, window_seconds).map_err(to_py_err)
+    }
+
+    /// Lifetime use of the key with hex `key_id`: `operations`, `bytes`
+    /// sealed to it, and `first_used_ms` / `last_used_ms` (zero if unused).
+    pub fn get_key_usage(&self, py: Python<'_>, key_id: &str) -> PyResult<PyObject> {
+        let usage = self.inner.key_usage(&hex_key_id(key_id)?).map_err(to_py_err)?;
+        let dict = PyDict::new(py);
+        dict.set_item("operations", usage.operations)?;
+        dict.set_item("bytes", usage.bytes)?;
+        dict.set_item("first_used_ms", usage.first_used_ms)?;
+        dict.set_item("last_used_ms", usage.last_used_ms)?;
+        Ok(dict.into())
+    }
+
+    /// Caps the lifetime operations and/or bytes sealed to the key with hex
+    /// `key_id`. Past the cap, sealing to it raises `RekeyRequired` until a
+    /// new key is used. Unlike `set_key_limit`, counts persist.
+    #[pyo3(signature = (key_id, max_operations=None, max_bytes=None, auth_token=None))]
+    pub fn set_key_usage_cap(&mut self, key_id: &str, max_operations: Option<u64>, max_bytes: Option<u64>,
+                             auth_token: Option<&str>) -> PyResult<()> {
+        let key_id = hex_key_id(key_id)?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_key_usage_cap(key_id, UsageCap { max_operations, max_bytes }).map_err(to_py_err)
+    }
+
+    /// Removes the cap on `key_id`, keeping its counts; returns whether
+    /// there was one.
+    #[pyo3(signature = (key_id, auth_token=None))]
+    pub fn clear_key_usage_cap(&mut self, key_id: &str, auth_token: Option<&str>) -> PyResult<bool> {
+        let key_id = hex_key_id(key_id)?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.clear_key_usage_cap(&key_id).map_err(to_py_err)
+    }
+
+    /// `{key_id: (max_operations, max_bytes)}` for every configured cap.
+    #[getter]
+    fn key_usage_caps(&self) -> std::collections::HashMap<String, (Option<u64>, Option<u64>)> {
+        self.inner.key_usage_caps().into_iter().map(|(id, cap)| (hex::encode(id), (cap.max_operations, cap.max_bytes))).collect()
+    }
+
+    /// Removes the limit on `key_id`; returns whether there was one.
+    #[pyo3(signature = (key_id, auth_token=None))]
+    pub fn clear_key_limit(&mut self, key_id: &str, auth_token: Option<&str>) -> PyResult<bool> {
+        let key_id = hex_key_id(key_id)?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.clear_key_limit(&key_id).map_err(to_py_err)
+    }
+
+    /// JSON snapshot of the settings, chain head and policy (no secret
+    /// keys), for the constructor's `state` argument.
+    pub fn export_state(&self) -> String {
+        self.inner.export_state().to_json()
+    }
+
+    /// Checks a snapshot against the audit chain; returns how many entries
+    /// were written after it.
+    pub fn verify_state(&self, state: &str) -> PyResult<u64> {
+        let state = EngineState::from_json(state).map_err(to_py_err)?;
+        self.inner.verify_state(&state).map_err(to_py_err)
+    }
+
+    /// Milliseconds a call over the rate limit waits for capacity; `None`
+    /// raises straight away.
+    #[getter]
+    fn rate_limit_wait_ms(&self) -> Option<u64> {
+        self.inner.rate_limit_wait().map(|w| w.as_millis() as u64)
+    }
+
+    #[setter]
+    fn set_rate_limit_wait_ms(&mut self, wait_ms: Option<u64>) {
+        self.inner.set_rate_limit_wait(wait_ms.map(Duration::from_millis));
+    }
+
+    /// Milliseconds `vault_seal` remembers an idempotency key; `None` if
+    /// idempotency keys are off.
+    #[getter]
+    fn idempotency_window_ms(&self) -> Option<u64> {
+        self.inner.idempotency_window().map(|w| w.as_millis() as u64)
+    }
+
+    /// `{key_id: (max, window_seconds)}` for every configured limit.
+    #[getter]
+    fn key_limits(&self) -> std::collections::HashMap<String, (usize, u64)> {
+        self.inner.key_limits().into_iter().map(|(id, max, window)| (hex::encode(id), (max, window))).collect()
+    }
+
+    /// Wraps the data key of every native envelope sealed from now on to the
+    /// escrow public key (from `generate_escrow_key`); `None` turns escrow
+    /// off. Recorded as a `rekey` event.
+    #[pyo3(signature = (public_key=None, auth_token=None))]
+    pub fn set_escrow_key(&mut self, public_key: Option<Vec<u8>>, auth_token: Option<&str>) -> PyResult<()> {
+        let public_key = public_key.map(|pk| unarmor(ArmorKind::PublicKey, pk)).transpose()?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_escrow_key(public_key.as_deref()).map_err(to_py_err)
+    }
+
+    /// Requires a fresh token before each of `operations` (`"key_export"`,
+    /// `"escrow_decrypt"`, `"policy_change"`), judged by `callback(operation,
+    /// token) -> bool` or as a TOTP code (HMAC-SHA256, 6 digits, 30 s) for
+    /// `totp_secret`. Pass the token as `auth_token=` to the guarded call,
+    /// or to `authorize` just before it. Replacing a configuration that
+    /// guards `policy_change` needs `auth_token`; `operations=[]` turns
+    /// step-up off.
+    #[pyo3(signature = (operations, callback=None, totp_secret=None, auth_token=None))]
+    pub fn set_step_up(&mut self, operations: Vec<String>, callback: Option<PyObject>, totp_secret: Option<Vec<u8>>,
+                       auth_token: Option<&str>) -> PyResult<()> {
+        let ops = operations.iter().map(|name| sensitive_op(name)).collect::<PyResult<Vec<_>>>()?;
+        let verifier: Box<dyn StepUpVerifier> = match (callback, totp_secret) {
+            (Some(callback), None) => Box::new(PyStepUpCallback(callback)),
+            (None, Some(secret)) => Box::new(Totp::new(&secret).map_err(to_py_err)?),
+            _ => return Err(invalid_argument("pass exactly one of callback and totp_secret")),
+        };
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_step_up(&ops, verifier).map_err(to_py_err)
+    }
+
+    /// Requires every encrypt, decrypt, sign, key export and policy change
+    /// to run as one of `roles` (`{name: [permission, ...]}`, permissions
+    /// from `"encrypt"`, `"decrypt"`, `"sign"`, `"key_export"` and
+    /// `"admin"`) inside `with engine.caller(token):`, where `tokens` maps
+    /// each token (at least 16 characters) to its role. Other calls raise
+    /// `PermissionError`, and audit entries record the caller's role.
+    /// `roles=None` lifts the requirement. Recorded as a `rekey` event;
+    /// once roles are set, changing them needs the `admin` permission.
+    #[pyo3(signature = (roles=None, tokens=None, auth_token=None))]
+    pub fn set_roles(&mut self, roles: Option<std::collections::BTreeMap<String, Vec<String>>>,
+                     tokens: Option<std::collections::BTreeMap<String, String>>, auth_token: Option<&str>) -> PyResult<()> {
+        let policy = match roles {
+            Some(roles) => {
+                let mut policy = RolePolicy::new();
+                for (name, perms) in &roles {
+                    let perms = perms.iter().map(|p| Permission::parse(p).ok_or_else(|| invalid_argument(format!("unknown permission: {}", p))))
+                        .collect::<PyResult<Vec<_>>>()?;
+                    policy.add_role(name, &perms).map_err(to_py_err)?;
+                }
+                for (token, role) in tokens.iter().flatten() {
+                    policy.add_token(role, token).map_err(to_py_err)?;
+                }
+                Some(policy)
+            }
+            None if tokens.is_some() => return Err(invalid_argument("tokens given without roles")),
+            None => None,
+        };
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_roles(policy).map_err(to_py_err)
+    }
+
+    /// `{name: [permission, ...]}` of the configured roles; `None` without.
+    #[getter]
+    fn roles(&self) -> Option<std::collections::BTreeMap<String, Vec<&'static str>>> {
+        self.inner.role_policy().map(|policy| {
+            policy.roles().map(|(name, perms)| (name.to_string(), perms.iter().map(|p| p.as_str()).collect())).collect()
+        })
+    }
+
+    /// Context manager acting as the role `token` belongs to on this
+    /// thread: `with engine.caller(token) as role: ...`. Entering it with
+    /// an unknown token raises `PermissionError`, recorded as a failed
+    /// `approval` event.
+    pub fn caller(slf: Py<Self>, token: String) -> PyCallerScope {
+        PyCallerScope { engine: slf, token: Zeroizing::new(token), scope: None }
+    }
+
+    /// Role this thread acts as, inside `caller`.
+    #[getter]
+    fn caller_role(&self) -> Option<String> {
+        self.inner.caller_role()
+    }
+
+    /// Context manager grouping the audit entries this thread writes under
+    /// a new operation ID, tagged with `correlation_id` (up to 128 letters,
+    /// digits and `-_.:/@+`) if given: `with engine.operation("req-42") as
+    /// operation_id: ...`. Outside one, each entry gets its own ID.
+    #[pyo3(signature = (correlation_id=None))]
+    pub fn operation(slf: Py<Self>, correlation_id: Option<String>) -> PyOperationScope {
+        PyOperationScope { engine: slf, correlation_id, scope: None }
+    }
+
+    /// Operation ID (hex) of the last audit entry written for this thread,
+    /// e.g. by the call that just returned.
+    #[getter]
+    fn last_operation_id(&self) -> Option<String> {
+        operation::last_id().map(hex::encode)
+    }
+
+    /// Checks a step-up `token` for `operation` and allows one use of it in
+    /// the next minute. Raises `PermissionError` if the token is refused.
+    pub fn authorize(&self, operation: &str, token: &str) -> PyResult<()> {
+        self.inner.authorize(sensitive_op(operation)?, token).map_err(to_py_err)
+    }
+
+    /// `(public_key, secret_key)` of the checkpoint signing key, for
+    /// `set_signing_keypair` on another engine. Recorded as a `keygen`
+    /// event.
+    #[pyo3(signature = (auth_token=None))]
+    pub fn export_signing_keypair(&self, py: Python<'_>, auth_token: Option<&str>) -> PyResult<(PyObject, PyObject)> {
+        step_up(&self.inner, SensitiveOp::KeyExport, auth_token)?;
+        let (pk, sk) = self.inner.export_signing_keypair().map_err(to_py_err)?;
+        Ok((PyBytes::new(py, &pk).into(), PyBytes::new(py, &sk).into()))
+    }
+
+    /// Printable recovery kit pages for the engine's identity key, whose
+    /// hex `key_id` must be given: one page wrapped under `wrapping_key`,
+    /// or `custodians` Shamir share pages, any `threshold` of which restore
+    /// it. Recorded as a `keygen` event.
+    #[pyo3(signature = (key_id, wrapping_key=None, threshold=None, custodians=None, auth_token=None))]
+    pub fn export_recovery_kit(&self, py: Python<'_>, key_id: &str, wrapping_key: Option<Vec<u8>>, threshold: Option<u8>,
+                               custodians: Option<u8>, auth_token: Option<&str>) -> PyResult<Vec<String>> {
+        let id = hex_key_id(key_id)?;
+        let wrapping_key = wrapping_key.map(|k| self::wrapping_key(&k)).transpose()?;
+        let protection = kit_protection(wrapping_key.as_ref(), threshold, custodians)?;
+        step_up(&self.inner, SensitiveOp::KeyExport, auth_token)?;
+        let kit = py.allow_threads(|| self.inner.export_recovery_kit(&id, protection)).map_err(to_py_err)?;
+        Ok(kit_pages(&kit))
+    }
+
+    /// Finishes a `new_ceremony` from every participant's commitment and
+    /// reveal (see `CeremonyContribution`): returns `(key, transcript)`,
+    /// the 32-byte key and the engine-signed transcript, recorded as a
+    /// `keygen` event. Raises `ValueError` if a participant's commitment
+    /// or reveal is missing, does not match or is not signed by them.
+    #[pyo3(signature = (ceremony, commitments, reveals, auth_token=None))]
+    pub fn run_ceremony(&self, py: Python<'_>, ceremony: Vec<u8>, commitments: Vec<Vec<u8>>, reveals: Vec<Vec<u8>>,
+                        auth_token: Option<&str>) -> PyResult<(PyObject, PyObject)> {
+        let ceremony = Ceremony::from_bytes(&ceremony).map_err(to_py_err)?;
+        let commitments = commitments.iter().map(|c| Commitment::from_bytes(c)).collect::<CoreResult<Vec<_>>>().map_err(to_py_err)?;
+        let reveals = reveals.iter().map(|r| Reveal::from_bytes(r)).collect::<CoreResult<Vec<_>>>().map_err(to_py_err)?;
+        step_up(&self.inner, SensitiveOp::KeyExport, auth_token)?;
+        let (key, transcript) = py.allow_threads(|| self.inner.run_ceremony(&ceremony, &commitments, &reveals)).map_err(to_py_err)?;
+        Ok((PyBytes::new(py, key.as_ref()).into(), PyBytes::new(py, &transcript.to_bytes()).into()))
+    }
+
+    /// Uses a long-lived Dilithium5 key (from `generate_signing_keypair`) for
+    /// checkpoints instead of the per-engine ephemeral one.
+    #[pyo3(signature = (public_key, secret_key, auth_token=None))]
+    pub fn set_signing_keypair(&mut self, public_key: Vec<u8>, secret_key: Vec<u8>, auth_token: Option<&str>) -> PyResult<()> {
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        self.inner.set_signing_keypair(&public_key, &secret_key).map_err(to_py_err)
+    }
+
+    /// Replaces the identity keypair with a new one (store it first, e.g.
+    /// with `wrap_identity`), cross-signed by both keys and recorded as a
+    /// `rekey` event. Returns the rotation statement for `verify_rotation`.
+    #[pyo3(signature = (public_key, secret_key, auth_token=None))]
+    pub fn rotate_identity(&mut self, py: Python<'_>, public_key: Vec<u8>, secret_key: Vec<u8>, auth_token: Option<&str>) -> PyResult<PyObject> {
+        let identity = identity_from(public_key, secret_key)?;
+        step_up(&self.inner, SensitiveOp::PolicyChange, auth_token)?;
+        let signed = self.inner.rotate_identity(identity).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &signed.to_bytes()).into())
+    }
+
+    /// Calls `callback(checkpoint)` from a background thread every `every`
+    /// audit entries and on `flush()`. `checkpoint` is a dict with
+    /// `fingerprint`, `counter`, `head`, `timestamp` and the signed `bytes`.
+    /// Exceptions are counted in `anchor_stats()`, not raised.
+    #[pyo3(signature = (callback, every=1000))]
+    pub fn set_anchor(&mut self, callback: PyObject, every: u64) -> PyResult<()> {
+        self.inner.set_anchor(Box::new(PyAnchor(callback)), every).map_err(to_py_err)
+    }
+
+    /// POSTs checkpoints as JSON to a transparency-log-style endpoint.
+    #[pyo3(signature = (url, every=1000, bearer_token=None))]
+    pub fn anchor_to_url(&mut self, url: String, every: u64, bearer_token: Option<String>) -> PyResult<()> {
+        let mut anchor = HttpAnchor::new(url);
+        if let Some(token) = bearer_token {
+            anchor = anchor.with_bearer(token);
+        }
+        self.inner.set_anchor(Box::new(anchor), every).map_err(to_py_err)
+    }
+
+    /// Writes each checkpoint to a new, never-overwritten S3 object under
+    /// `prefix`.
+    #[pyo3(signature = (endpoint, region, bucket, access_key, secret_key, prefix="", session_token=None, every=1000))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn anchor_to_s3(&mut self, endpoint: &str, region: &str, bucket: &str, access_key: &str, secret_key: &str,
+                        prefix: &str, session_token: Option<String>, every: u64) -> PyResult<()> {
+        let mut anchor = S3Anchor::new(endpoint, region, bucket, access_key, secret_key).map_err(to_py_err)?.with_prefix(prefix);
+        if let Some(token) = session_token {
+            anchor = anchor.with_session_token(token);
+        }
+        self.inner.set_anchor(Box::new(anchor), every).map_err(to_py_err)
+    }
+
+    /// `published`, `failed`, `last_counter` and `last_error` for the
+    /// installed anchor, or `None` without one.
+    pub fn anchor_stats(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let Some(stats) = self.inner.anchor_stats() else { return Ok(None) };
+        let dict = PyDict::new(py);
+        dict.set_item("published", stats.published)?;
+        dict.set_item("failed", stats.failed)?;
+        dict.set_item("last_counter", stats.last_counter)?;
+        dict.set_item("last_error", stats.last_error)?;
+        Ok(Some(dict.into()))
+    }
+
+    /// Reads the last `tail` audit entries back and compares them with the
+    /// chain head in memory. On divergence, writes a signed alarm next to
+    /// the log (`<log>.alarms` for a file sink) and returns it as a dict
+    /// with `reason` (`head-mismatch`, `tail-broken`, `log-missing` or
+    /// `unreadable`), `expected_head`, `expected_seq`, `found_head`,
+    /// `found_seq`, `timestamp_ms` and the signed `bytes`; `None` if the
+    /// log matches.
+    #[pyo3(signature = (tail=64))]
+    pub fn check_chain(&self, py: Python<'_>, tail: usize) -> PyResult<Option<PyObject>> {
+        let alarm = py.allow_threads(|| self.inner.check_chain(tail)).map_err(to_py_err)?;
+        alarm.map(|a| Ok(alarm_dict(py, &a)?.into())).transpose()
+    }
+
+    /// Runs `check_chain(tail)` from a background thread every
+    /// `interval_ms` and calls `callback(alarm)` with each new alarm. A
+    /// divergence that persists is reported once. Exceptions are counted in
+    /// `watchdog_stats()`, not raised. Replaces any running watchdog.
+    #[pyo3(signature = (callback=None, interval_ms=60000, tail=64))]
+    pub fn start_watchdog(&mut self, callback: Option<PyObject>, interval_ms: u64, tail: usize) -> PyResult<()> {
+        self.inner.start_watchdog(Duration::from_millis(interval_ms), tail, Box::new(PyAlarmHandler(callback))).map_err(to_py_err)
+    }
+
+    pub fn stop_watchdog(&mut self) {
+        self.inner.stop_watchdog();
+    }
+
+    /// `checks`, `alarms`, `failed`, `last_alarm` (a dict) and `last_error`
+    /// for the running watchdog, or `None` without one.
+    pub fn watchdog_stats(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let Some(stats) = self.inner.watchdog_stats() else { return Ok(None) };
+        let dict = PyDict::new(py);
+        dict.set_item("checks", stats.checks)?;
+        dict.set_item("alarms", stats.alarms)?;
+        dict.set_item("failed", stats.failed)?;
+        dict.set_item("last_alarm", stats.last_alarm.map(|a| alarm_dict(py, &a)).transpose()?)?;
+        dict.set_item("last_error", stats.last_error)?;
+        Ok(Some(dict.into()))
+    }
+
+    /// Startup validation of the existing audit log: `head`, `counter`,
+    /// `seq`, `timestamp_ms`, `entries_checked`, `quarantined_bytes`, `quarantine_path`; `None` for a
+    /// fresh log.
+    #[getter]
+    fn recovery(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let Some(r) = self.inner.recovery() else { return Ok(None) };
+        let dict = PyDict::new(py);
+        dict.set_item("head", hex::encode(r.head))?;
+        dict.set_item("counter", r.counter)?;
+        dict.set_item("seq", r.seq)?;
+        dict.set_item("timestamp_ms", r.timestamp_ms)?;
+        dict.set_item("entries_checked", r.entries_checked)?;
+        dict.set_item("quarantined_bytes", r.quarantined_bytes)?;
+        dict.set_item("quarantine_path", &r.quarantine_path)?;
+        Ok(Some(dict.into()))
+    }
+
+    /// Audit entries matching every given filter, in chain order, as dicts
+    /// with `engine`, `counter`, `seq`, `timestamp_ms`, `op`, `outcome`,
+    /// `prev`, `curr`, `clock_regressed`, `fips`, `role`, `reason`,
+    /// `operation_id`, `correlation_id` and `key_id`. Ranges are inclusive.
+    /// `engine` is the fingerprint (hex) a shared log files the entry
+    /// under; `key_id` (hex) is the recipient or other key the operation
+    /// used. The SQLite backend answers from its indexes; the file backend
+    /// scans the whole log, and its entries have an empty `engine`.
+    #[pyo3(signature = (counter_from=None, counter_to=None, since_ms=None, until_ms=None, key_id=None, op=None,
+                        outcome=None, limit=None, operation_id=None, correlation_id=None, engine=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn query_audit(&self, py: Python<'_>, counter_from: Option<u64>, counter_to: Option<u64>, since_ms: Option<u64>,
+                   until_ms: Option<u64>, key_id: Option<&str>, op: Option<&str>, outcome: Option<&str>,
+                   limit: Option<usize>, operation_id: Option<&str>, correlation_id: Option<String>,
+                   engine: Option<String>) -> PyResult<Vec<PyObject>> {
+        let op = op.map(|op| OpType::parse(op).ok_or_else(|| invalid_argument(format!("unknown op: {}", op)))).transpose()?;
+        let outcome = outcome
+            .map(|o| Outcome::parse(o).ok_or_else(|| invalid_argument(format!("unknown outcome: {}", o))))
+            .transpose()?;
+        let operation_id = operation_id.map(self::operation_id).transpose()?;
+        let key_id = key_id.map(hex_key_id).transpose()?;
+        let query = AuditQuery {
+            counter_from, counter_to, since_ms, until_ms, engine, key_id, op, outcome, operation_id, correlation_id, limit,
+        };
+        let records = py.allow_threads(|| self.inner.query_audit(&query)).map_err(to_py_err)?;
+        records.iter().map(|r| {
+            let dict = entry_dict(py, &r.entry)?;
+            dict.set_item("engine", &r.engine)?;
+            Ok(dict.into())
+        }).collect()
+    }
+
+    /// The audit entries from the first with a counter of at least
+    /// `from_counter` to the last with one of at most `to_counter`, in chain
+    /// order, between signed checkpoints of the heads before and after
+    /// them, as bytes for `verify_segment`. Needs a backend that
+    /// `query_audit` works on.
+    pub fn export_segment(&self, py: Python<'_>, from_counter: u64, to_counter: u64) -> PyResult<PyObject> {
+        let segment = py.allow_threads(|| self.inner.export_segment(from_counter, to_counter)).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &segment.to_bytes()).into())
+    }
+
+    /// `{"version", "fingerprint", "suite", "kdf", "kem", "audit_binding",
+    /// "fips_mode", "config_hash", "closed"}`; `config_hash` is the hex hash
+    /// recorded in the genesis record.
+    fn engine_info(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let info = self.inner.info();
+        let dict = PyDict::new(py);
+        dict.set_item("version", info.version)?;
+        dict.set_item("fingerprint", hex::encode(info.fingerprint))?;
+        dict.set_item("suite", info.suite.name())?;
+        dict.set_item("kdf", info.kdf.name())?;
+        dict.set_item("kem", kem::find(info.kem).map(|k| k.name()))?;
+        dict.set_item("audit_binding", match info.ct_binding {
+            CiphertextBinding::Full => "full",
+            CiphertextBinding::Digest => "digest",
+        })?;
+        dict.set_item("fips_mode", info.fips_mode)?;
+        dict.set_item("config_hash", hex::encode(info.config_hash))?;
+        dict.set_item("closed", info.closed)?;
+        Ok(dict.into())
+    }
+
+    #[getter]
+    fn fingerprint(&self) -> String {
+        hex::encode(self.inner.fingerprint())
+    }
+
+    #[getter]
+    fn checkpoint_public_key(&self, py: Python<'_>) -> PyObject {
+        PyBytes::new(py, self.inner.checkpoint_public_key()).into()
+    }
+}
+
+/// Returns a fresh keypair as `(public_key, secret_key)` bytes, for
+/// Kyber-1024 unless `kem` names another KEM (see `kem_algorithms()`).
+#[pyfunction]
+#[pyo3(signature = (kem=None))]
+fn generate_keypair(py: Python<'_>, kem: Option<&str>) -> PyResult<(PyObject, PyObject)> {
+    let (pk, sk) = match kem {
+        None => crypto::generate_keypair(),
+        Some(name) => kem::parse(name).ok_or_else(|| invalid_argument(format!("unknown KEM: {}", name)))?.keypair().map_err(to_py_err)?,
+    };
+    Ok((PyBytes::new(py, &pk).into(), PyBytes::new(py, &sk).into()))
+}
+
+/// Names of the KEMs envelopes can be sealed under: the built-in Kyber
+/// parameter sets, `"mceliece6688128"`, `"hqc256"`, the dual
+/// `"kyber1024+hqc256"` and `"x-wing"`, then any a Rust extension
+/// registered.
+#[pyfunction]
+fn kem_algorithms() -> Vec<&'static str> {
+    kem::algorithms()
+}
+
+/// Names of the envelope suites: the built-in ones, then any a Rust
+/// extension registered.
+#[pyfunction]
+fn suite_algorithms() -> Vec<&'static str> {
+    suite::algorithms()
+}
+
+/// Returns a fresh Dilithium5 checkpoint signing keypair as `(public_key, secret_key)` bytes.
+#[pyfunction]
+fn generate_signing_keypair(py: Python<'_>) -> (PyObject, PyObject) {
+    let (pk, sk) = crypto::generate_signing_keypair();
+    (PyBytes::new(py, &pk).into(), PyBytes::new(py, &sk).into())
+}
+
+/// Encrypts a Dilithium5 identity keypair under a 32-byte `wrapping_key`
+/// (e.g. one kept in the OS keychain) for storage; raises `ValueError` if
+/// the halves do not match.
+#[pyfunction]
+fn wrap_identity(py: Python<'_>, public_key: Vec<u8>, secret_key: Vec<u8>, wrapping_key: Vec<u8>) -> PyResult<PyObject> {
+    let identity = identity_from(public_key, secret_key)?;
+    let wrapped = identity.wrap(&self::wrapping_key(&wrapping_key)?).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &wrapped).into())
+}
+
+/// Reverses `wrap_identity`, returning `(public_key, secret_key)`.
+#[pyfunction]
+fn unwrap_identity(py: Python<'_>, wrapped: Vec<u8>, wrapping_key: Vec<u8>) -> PyResult<(PyObject, PyObject)> {
+    let identity = Identity::unwrap(&wrapped, &self::wrapping_key(&wrapping_key)?).map_err(to_py_err)?;
+    Ok((PyBytes::new(py, identity.public_key()).into(), PyBytes::new(py, &identity.secret_key()).into()))
+}
+
+/// Printable recovery kit pages for a keypair the caller holds, Kyber-1024
+/// or Dilithium5: one page wrapped under `wrapping_key`, or `custodians`
+/// Shamir share pages, any `threshold` of which restore it.
+#[pyfunction]
+#[pyo3(signature = (public_key, secret_key, wrapping_key=None, threshold=None, custodians=None))]
+fn recovery_kit(public_key: Vec<u8>, secret_key: Vec<u8>, wrapping_key: Option<Vec<u8>>, threshold: Option<u8>,
+                custodians: Option<u8>) -> PyResult<Vec<String>> {
+    let wrapping_key = wrapping_key.map(|k| self::wrapping_key(&k)).transpose()?;
+    let protection = kit_protection(wrapping_key.as_ref(), threshold, custodians)?;
+    let kit = RecoveryKit::create(&public_key, &secret_key, protection, SystemClock::new().now_ms()).map_err(to_py_err)?;
+    Ok(kit_pages(&kit))
+}
+
+/// Restores a key from recovery kit pages (the printed text, or just each
+/// armored block): the wrapped page and its `wrapping_key`, or enough share
+/// pages. Returns `{"key_id", "key_type", "public_key", "secret_key",
+/// "verification_codes"}`; `key_type` is `identity` or `recipient`.
+#[pyfunction]
+#[pyo3(signature = (pages, wrapping_key=None))]
+fn import_recovery_kit(py: Python<'_>, pages: Vec<String>, wrapping_key: Option<Vec<u8>>) -> PyResult<PyObject> {
+    let wrapping_key = wrapping_key.map(|k| self::wrapping_key(&k)).transpose()?;
+    let sheets = pages.iter().map(|p| KitSheet::from_text(p)).collect::<CoreResult<Vec<_>>>().map_err(to_py_err)?;
+    let (key_type, pk, sk) = RecoveryKit::restore(&sheets, wrapping_key.as_ref()).map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("key_id", hex::encode(cert::key_id(&pk)))?;
+    dict.set_item("key_type", key_type.as_str())?;
+    dict.set_item("public_key", PyBytes::new(py, &pk))?;
+    dict.set_item("secret_key", PyBytes::new(py, &sk))?;
+    dict.set_item("verification_codes", sheets.iter().map(KitSheet::verification_code).collect::<Vec<_>>())?;
+    Ok(dict.into())
+}
+
+/// Checks a `rotate_identity` statement against the key being replaced;
+/// returns `{"fingerprint", "counter", "timestamp_ms", "old_public_key",
+/// "new_public_key"}`, or `None` unless both keys signed it. Trust
+/// `new_public_key` from then on.
+#[pyfunction]
+fn verify_rotation(py: Python<'_>, rotation: Vec<u8>, trusted_old_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+    let trusted = unarmor(ArmorKind::SigningPublicKey, trusted_old_pk)?;
+    let signed = SignedRotation::from_bytes(&rotation).map_err(to_py_err)?;
+    if !signed.verify(&trusted) {
+        return Ok(None);
+    }
+    let r = &signed.rotation;
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(r.fingerprint))?;
+    dict.set_item("counter", r.counter)?;
+    dict.set_item("timestamp_ms", r.timestamp_ms)?;
+    dict.set_item("old_public_key", PyBytes::new(py, &r.old_public_key))?;
+    dict.set_item("new_public_key", PyBytes::new(py, &r.new_public_key))?;
+    Ok(Some(dict.into()))
+}
+
+/// Fresh Kyber-1024 escrow keypair as `(public_key, shares)`: the secret
+/// key is split into `custodians` Shamir shares, any `threshold` of which
+/// recover it for `vault_open_escrowed`.
+#[pyfunction]
+fn generate_escrow_key(py: Python<'_>, threshold: u8, custodians: u8) -> PyResult<(PyObject, Vec<PyObject>)> {
+    let (pk, shares) = escrow::generate_escrow_key(threshold, custodians).map_err(to_py_err)?;
+    let shares = shares.iter().map(|s| PyBytes::new(py, &s.to_bytes()).into()).collect();
+    Ok((PyBytes::new(py, &pk).into(), shares))
+}
+
+/// Current TOTP code for `secret` as accepted by `set_step_up`, or the
+/// code for Unix time `unix_time`.
+#[pyfunction]
+#[pyo3(signature = (secret, unix_time=None))]
+fn totp_code(secret: Vec<u8>, unix_time: Option<u64>) -> PyResult<String> {
+    let totp = Totp::new(&secret).map_err(to_py_err)?;
+    Ok(totp.code(unix_time.unwrap_or_else(unix_now)))
+}
+
+/// Request to open a restricted envelope, for approvers to sign with
+/// `approve_request`; valid for `valid_seconds`.
+#[pyfunction]
+#[pyo3(signature = (envelope, requester, reason, valid_seconds=3600))]
+fn decryption_request(py: Python<'_>, envelope: Vec<u8>, requester: &str, reason: &str, valid_seconds: u64) -> PyResult<PyObject> {
+    let envelope = Envelope::from_bytes(&unarmor(ArmorKind::Envelope, envelope)?).map_err(to_py_err)?;
+    let request = DecryptionRequest::new(&envelope, requester, reason, unix_now() + valid_seconds);
+    Ok(PyBytes::new(py, &request.to_bytes()).into())
+}
+
+/// Signs a `decryption_request` with an approver's Dilithium5 keypair.
+#[pyfunction]
+fn approve_request(py: Python<'_>, request: Vec<u8>, approver_public_key: Vec<u8>, approver_secret_key: Vec<u8>) -> PyResult<PyObject> {
+    let request = DecryptionRequest::from_bytes(&request).map_err(to_py_err)?;
+    let public_key = unarmor(ArmorKind::SigningPublicKey, approver_public_key)?;
+    let secret_key = unarmor(ArmorKind::SigningSecretKey, approver_secret_key)?;
+    let approval = request.approve(&public_key, &secret_key).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &approval.to_bytes()).into())
+}
+
+/// A key generation ceremony among `participants` (2 to 255 Dilithium5
+/// public keys), for `purpose` `"master_kek"` or `"escrow_key"`. Hand the
+/// bytes to every participant and to the engine that runs it.
+#[pyfunction]
+fn new_ceremony(py: Python<'_>, purpose: &str, participants: Vec<Vec<u8>>) -> PyResult<PyObject> {
+    let purpose = CeremonyPurpose::parse(purpose).ok_or_else(|| invalid_argument(format!("unknown ceremony purpose: {}", purpose)))?;
+    let participants = participants.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+    let ceremony = Ceremony::new(purpose, participants).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &ceremony.to_bytes()).into())
+}
+
+/// Checks a `run_ceremony` transcript against `trusted_pk`; returns
+/// `{"ceremony", "purpose", "fingerprint", "counter", "timestamp",
+/// "participants"}` (hex IDs), or `None` if the signature does not verify
+/// or `key` is given and is not the ceremony's key.
+#[pyfunction]
+#[pyo3(signature = (transcript, trusted_pk, key=None))]
+fn verify_ceremony_transcript(py: Python<'_>, transcript: Vec<u8>, trusted_pk: Vec<u8>, key: Option<Vec<u8>>) -> PyResult<Option<PyObject>> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let signed = SignedTranscript::from_bytes(&transcript).map_err(to_py_err)?;
+    let key: Option<[u8; 32]> = key.map(|k| k.as_slice().try_into().map_err(|_| invalid_argument("ceremony keys are 32 bytes"))).transpose()?;
+    if !signed.verify(&trusted_pk) || key.is_some_and(|k| !signed.transcript.matches(&k)) {
+        return Ok(None);
+    }
+    let t = &signed.transcript;
+    let dict = PyDict::new(py);
+    dict.set_item("ceremony", hex::encode(t.ceremony))?;
+    dict.set_item("purpose", t.purpose.as_str())?;
+    dict.set_item("fingerprint", hex::encode(t.fingerprint))?;
+    dict.set_item("counter", t.counter)?;
+    dict.set_item("timestamp", t.timestamp)?;
+    dict.set_item("participants", t.commitments.iter().map(|(p, _)| hex::encode(p)).collect::<Vec<_>>())?;
+    Ok(Some(dict.into()))
+}
+
+/// Checks a `finish_multipart` manifest against `trusted_pk`; returns
+/// `{"header", "total_size", "parts"}` (the part count), or `None` if the
+/// signature does not verify.
+#[pyfunction]
+fn verify_part_manifest(py: Python<'_>, manifest: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let signed = SignedPartManifest::from_bytes(&manifest).map_err(to_py_err)?;
+    if !signed.verify(&trusted_pk) {
+        return Ok(None);
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("header", PyBytes::new(py, &signed.manifest.header))?;
+    dict.set_item("total_size", signed.manifest.total_size)?;
+    dict.set_item("parts", signed.manifest.parts.len())?;
+    Ok(Some(dict.into()))
+}
+
+/// True if `part` is part `index` of the upload in `manifest`, without
+/// decrypting it. Check the manifest with `verify_part_manifest` first.
+#[pyfunction]
+fn verify_part(manifest: Vec<u8>, index: u32, part: Vec<u8>) -> PyResult<bool> {
+    let signed = SignedPartManifest::from_bytes(&manifest).map_err(to_py_err)?;
+    Ok(signed.manifest.verify_part(index, &part))
+}
+
+/// Checks an `attest` statement against `trusted_pk` and the `nonce` the
+/// verifier sent; returns a dict of its fields (`quote` holds `attest`,
+/// `signature`, the hex `pcr_digest` and `pcrs` by hash algorithm id), or
+/// `None` if it does not verify. The quote's signature is the TPM's and
+/// must be checked separately against its attestation key.
+#[pyfunction]
+#[pyo3(signature = (attestation, trusted_pk, nonce=None))]
+fn verify_attestation(py: Python<'_>, attestation: Vec<u8>, trusted_pk: Vec<u8>, nonce: Option<Vec<u8>>) -> PyResult<Option<PyObject>> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let signed = SignedAttestation::from_bytes(&attestation).map_err(to_py_err)?;
+    if !signed.verify(&trusted_pk, &nonce.unwrap_or_default()) {
+        return Ok(None);
+    }
+    let a = &signed.attestation;
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(a.fingerprint))?;
+    dict.set_item("counter", a.counter)?;
+    dict.set_item("head", hex::encode(a.head))?;
+    dict.set_item("timestamp", a.timestamp)?;
+    dict.set_item("license", &a.license)?;
+    dict.set_item("nonce", PyBytes::new(py, &a.nonce))?;
+    let quote = match &a.quote {
+        Some(quote) => {
+            let q = PyDict::new(py);
+            q.set_item("attest", PyBytes::new(py, &quote.attest))?;
+            q.set_item("signature", PyBytes::new(py, &quote.signature))?;
+            q.set_item("pcr_digest", hex::encode(quote.pcr_digest()))?;
+            q.set_item("pcrs", quote.pcrs().into_iter().collect::<std::collections::HashMap<_, _>>())?;
+            Some(q)
+        }
+        None => None,
+    };
+    dict.set_item("quote", quote)?;
+    dict.set_item("bytes", PyBytes::new(py, &attestation))?;
+    Ok(Some(dict.into()))
+}
+
+/// Checks a `genesis()` record against `trusted_pk`; returns `{"fingerprint",
+/// "public_key", "license", "config_hash", "timestamp_ms", "bytes"}`, or
+/// `None` if it does not verify. The record is signed by its own
+/// `public_key`, so pass a key pinned out of band.
+#[pyfunction]
+fn verify_genesis(py: Python<'_>, record: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let signed = SignedGenesis::from_bytes(&record).map_err(to_py_err)?;
+    if !signed.verify(&trusted_pk) {
+        return Ok(None);
+    }
+    let g = &signed.genesis;
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(g.fingerprint))?;
+    dict.set_item("public_key", PyBytes::new(py, &g.public_key))?;
+    dict.set_item("license", &g.license)?;
+    dict.set_item("config_hash", hex::encode(g.config_hash))?;
+    dict.set_item("timestamp_ms", g.timestamp_ms)?;
+    dict.set_item("bytes", PyBytes::new(py, &record))?;
+    Ok(Some(dict.into()))
+}
+
+/// Decodes checkpoint bytes (native or `COSE_Sign1`) and checks the signature against `trusted_pk`;
+/// returns the checkpoint dict, or `None` if the signature does not verify.
+#[pyfunction]
+fn verify_checkpoint(py: Python<'_>, checkpoint: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+    let checkpoint = unarmor(ArmorKind::Checkpoint, checkpoint)?;
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    if cose::is_cose(&checkpoint) {
+        let Some(cp) = cose::verify_checkpoint(&checkpoint, &trusted_pk).map_err(to_py_err)? else { return Ok(None) };
+        return Ok(Some(checkpoint_fields(py, &cp, &checkpoint)?.into()));
+    }
+    let cp = SignedCheckpoint::from_bytes(&checkpoint).map_err(to_py_err)?;
+    if !cp.verify(&trusted_pk) {
+        return Ok(None);
+    }
+    Ok(Some(checkpoint_dict(py, &cp)?.into()))
+}
+
+/// Checks a segment from `export_segment` against the engine's trusted
+/// checkpoint key; returns `{"entries": [...], "opening", "closing"}` with
+/// entries as `query_audit` gives them and the bracketing checkpoint
+/// dicts, or `None` if it does not verify.
+#[pyfunction]
+fn verify_segment(py: Python<'_>, segment: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let segment = AuditSegment::from_bytes(&segment).map_err(to_py_err)?;
+    if !segment.verify(&trusted_pk) {
+        return Ok(None);
+    }
+    let entries = segment.entries.iter().map(|e| Ok(entry_dict(py, e)?.into())).collect::<PyResult<Vec<PyObject>>>()?;
+    let dict = PyDict::new(py);
+    dict.set_item("entries", entries)?;
+    dict.set_item("opening", checkpoint_dict(py, &segment.opening)?)?;
+    dict.set_item("closing", checkpoint_dict(py, &segment.closing)?)?;
+    Ok(Some(dict.into()))
+}
+
+/// Verifies the file audit log at `log_path` against the engine's trusted
+/// checkpoint key: every entry links to the one before and every snapshot
+/// matches the entries it covers. With `from_snapshot` (a snapshot already
+/// verified, e.g. `latest` from the last run) only the entries after it are
+/// read. Returns `{"entries_checked", "snapshots_checked", "head", "seq",
+/// "latest"}` with `latest` as snapshot bytes or `None`; raises `IOError`
+/// naming the seq where the log stops matching.
+#[pyfunction]
+#[pyo3(signature = (log_path, trusted_pk, from_snapshot=None))]
+fn verify_audit_log(py: Python<'_>, log_path: String, trusted_pk: Vec<u8>, from_snapshot: Option<Vec<u8>>) -> PyResult<PyObject> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let from = from_snapshot.map(|s| SignedSnapshot::from_bytes(&s)).transpose().map_err(to_py_err)?;
+    let report = py.allow_threads(|| FileSink::new(log_path).verify_log(&trusted_pk, from.as_ref())).map_err(to_py_err)?;
+    let dict = PyDict::new(py);
+    dict.set_item("entries_checked", report.entries_checked)?;
+    dict.set_item("snapshots_checked", report.snapshots_checked)?;
+    dict.set_item("head", hex::encode(report.head))?;
+    dict.set_item("seq", report.seq)?;
+    dict.set_item("latest", report.latest.map(|s| PyBytes::new(py, &s.to_bytes())))?;
+    Ok(dict.into())
+}
+
+/// Checks an alarm from `check_chain` or the watchdog against the engine's
+/// trusted checkpoint key; returns the alarm dict, or `None` if the
+/// signature does not verify.
+#[pyfunction]
+fn verify_alarm(py: Python<'_>, alarm: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<Option<PyObject>> {
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    let alarm = SignedAlarm::from_bytes(&alarm).map_err(to_py_err)?;
+    if !alarm.verify(&trusted_pk) {
+        return Ok(None);
+    }
+    Ok(Some(alarm_dict(py, &alarm)?.into()))
+}
+
+/// Checks a bundle from `export_evidence` against the engine's trusted
+/// checkpoint key, without access to the engine or its log.
+#[pyfunction]
+fn verify_evidence(bundle: Vec<u8>, trusted_pk: Vec<u8>) -> PyResult<bool> {
+    let bundle = unarmor(ArmorKind::Evidence, bundle)?;
+    let trusted_pk = unarmor(ArmorKind::SigningPublicKey, trusted_pk)?;
+    titancore_core::verify_evidence(&bundle, &trusted_pk).map_err(to_py_err)
+}
+
+/// Checks that `audit_entry` (a line of the audit log, or an entry dict
+/// from `query_audit`) is the one sealing `envelope` produced and that
+/// `evidence`, the hex hash `vault_seal` returned, is its head. This ties
+/// the evidence to that ciphertext; it does not check the rest of the chain.
+#[pyfunction]
+fn verify_evidence_for(envelope: Vec<u8>, evidence: &str, audit_entry: &PyAny) -> PyResult<bool> {
+    let envelope = Envelope::from_bytes(&unarmor(ArmorKind::Envelope, envelope)?).map_err(to_py_err)?;
+    let mut hash = [0u8; 32];
+    hex::decode_to_slice(evidence, &mut hash).map_err(|_| invalid_argument("bad evidence hash"))?;
+    let entry = entry_from(audit_entry)?;
+    Ok(titancore_core::verify_evidence_for(&envelope, &hash, &entry))
+}
+
+/// Checks an inclusion proof from `prove_inclusion` against a hex batch root.
+#[pyfunction]
+fn verify_inclusion(proof: Vec<u8>, root: &str) -> PyResult<bool> {
+    let proof = InclusionProof::from_bytes(&proof).map_err(to_py_err)?;
+    let mut root_bytes = [0u8; 32];
+    hex::decode_to_slice(root, &mut root_bytes).map_err(|_| invalid_argument("bad root"))?;
+    Ok(merkle::verify_inclusion(&proof, &root_bytes))
+}
+
+/// Runs the KDF known-answer tests; raises if any KDF gives a wrong output.
+#[pyfunction]
+fn kdf_self_test() -> PyResult<()> {
+    titancore_core::kdf::self_test().map_err(to_py_err)
+}
+
+/// Entropy health-test state: `{"samples", "batteries", "degraded",
+/// "mixing", "hedged_fallbacks"}`, where `degraded` is the failure reason or
+/// `None` and `hedged_fallbacks` counts nonces and salts derived from key and
+/// message alone because no fresh bytes were available.
+#[pyfunction]
+fn entropy_health(py: Python<'_>) -> PyResult<PyObject> {
+    let health = titancore_core::entropy::health();
+    let dict = PyDict::new(py);
+    dict.set_item("samples", health.samples)?;
+    dict.set_item("batteries", health.batteries)?;
+    dict.set_item("degraded", health.degraded)?;
+    dict.set_item("mixing", health.mixing)?;
+    dict.set_item("hedged_fallbacks", health.hedged_fallbacks)?;
+    Ok(dict.into())
+}
+
+/// Feeds nonces and salts from a local DRBG that mixes the OS source with
+/// `RDSEED`, a TPM (`tpm_device`, e.g. `"/dev/tpmrm0"`) and/or a seed file.
+/// Applies to every engine in the process. Raises `ValueError` if a
+/// requested source is unavailable.
+#[pyfunction]
+#[pyo3(signature = (rdseed=false, tpm_device=None, seed_file=None))]
+fn enable_entropy_mixing(rdseed: bool, tpm_device: Option<String>, seed_file: Option<String>) -> PyResult<()> {
+    let sources = titancore_core::entropy::EntropySources { rdseed, tpm_device, seed_file };
+    titancore_core::entropy::enable_mixing(sources).map_err(to_py_err)
+}
+
+/// Keeps identity keys and session keys allocated from now on in locked
+/// memory between guard pages (Linux; elsewhere a no-op). Applies to every
+/// engine in the process; `enable=False` turns it off for later keys.
+#[pyfunction]
+#[pyo3(signature = (enable=true))]
+fn enable_guarded_memory(enable: bool) {
+    titancore_core::guarded::set_enabled(enable);
+}
+
+/// `{"enabled", "regions", "lock_failures"}`: whether guarded memory is on,
+/// the guarded regions now mapped, and how many could not be locked into
+/// RAM (see `RLIMIT_MEMLOCK`).
+#[pyfunction]
+fn guarded_memory_stats(py: Python<'_>) -> PyResult<PyObject> {
+    let stats = titancore_core::guarded::stats();
+    let dict = PyDict::new(py);
+    dict.set_item("enabled", titancore_core::guarded::enabled())?;
+    dict.set_item("regions", stats.regions)?;
+    dict.set_item("lock_failures", stats.lock_failures)?;
+    Ok(dict.into())
+}
+
+/// Builds `count` known-answer vectors for `suite` and `kdf` and returns them
+/// as `.rsp` text (see `verify_test_vectors`).
+#[pyfunction]
+#[pyo3(signature = (suite="aes-256-gcm-siv", count=8, kdf="hkdf-sha256"))]
+fn generate_test_vectors(suite: &str, count: u64, kdf: &str) -> PyResult<String> {
+    let suite = Suite::parse(suite).ok_or_else(|| invalid_argument(format!("unknown suite: {}", suite)))?;
+    let kdf = Kdf::parse(kdf).ok_or_else(|| invalid_argument(format!("unknown KDF: {}", kdf)))?;
+    let vectors = kat::generate_test_vectors(suite, kdf, count).map_err(to_py_err)?;
+    Ok(kat::to_rsp(&vectors))
+}
+
+/// Replays every vector in `.rsp` text; returns how many passed and raises
+/// `ValueError` naming the first that did not.
+#[pyfunction]
+fn verify_test_vectors(text: &str) -> PyResult<usize> {
+    let vectors = kat::parse_rsp(text).map_err(to_py_err)?;
+    for v in &vectors {
+        v.verify().map_err(|e| invalid_argument(format!("vector {}: {}", v.count, e)))?;
+    }
+    Ok(vectors.len())
+}
+
+/// Checks an ACVP vector set (JSON text with expected results, e.g. an
+/// ACVP-Server `internalProjection.json`) for AES-GCM-SIV, SHA-2 or HKDF.
+/// Returns `{"algorithm", "mode", "revision", "passed", "failed": [tcId],
+/// "skipped": [{"tgId", "tests", "reason"}]}`; groups this build cannot
+/// run, including all ML-KEM and ML-DSA groups, are skipped rather than
+/// passed. Raises `ValueError` for an unknown algorithm or a file without
+/// expected results.
+#[pyfunction]
+fn run_acvp(py: Python<'_>, text: &str) -> PyResult<PyObject> {
+    let report = py.allow_threads(|| acvp::run(text)).map_err(to_py_err)?;
+    let skipped = report.skipped.iter().map(|(tg_id, tests, reason)| {
+        let dict = PyDict::new(py);
+        dict.set_item("tgId", tg_id)?;
+        dict.set_item("tests", tests)?;
+        dict.set_item("reason", reason)?;
+        Ok(dict.into())
+    }).collect::<PyResult<Vec<PyObject>>>()?;
+    let dict = PyDict::new(py);
+    dict.set_item("algorithm", &report.algorithm)?;
+    dict.set_item("mode", &report.mode)?;
+    dict.set_item("revision", &report.revision)?;
+    dict.set_item("passed", report.passed)?;
+    dict.set_item("failed", &report.failed)?;
+    dict.set_item("skipped", skipped)?;
+    Ok(dict.into())
+}
+
+/// Converts a native envelope to a `titancore.v1.Envelope` protobuf message
+/// (schema from `protobuf_schema()`).
+#[pyfunction]
+fn envelope_to_protobuf(py: Python<'_>, envelope: Vec<u8>) -> PyResult<PyObject> {
+    let envelope = Envelope::from_bytes(&envelope).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &envelope.to_protobuf()).into())
+}
+
+/// Converts a `titancore.v1.Envelope` message back to a native envelope for
+/// `vault_open`.
+#[pyfunction]
+fn envelope_from_protobuf(py: Python<'_>, message: Vec<u8>) -> PyResult<PyObject> {
+    let envelope = Envelope::from_protobuf(&message).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &envelope.to_bytes()).into())
+}
+
+/// Every entry of the file audit log at `log_path`, text or binary, as
+/// `query_audit` gives them; entries that do not parse are skipped.
+#[pyfunction]
+fn read_audit_log(py: Python<'_>, log_path: String) -> PyResult<Vec<PyObject>> {
+    let records = py.allow_threads(|| FileSink::new(log_path).query(&AuditQuery::default())).map_err(to_py_err)?;
+    records.iter().map(|r| Ok(entry_dict(py, &r.entry)?.into())).collect()
+}
+
+/// Converts one audit log line to a `titancore.v1.AuditEntry` message.
+#[pyfunction]
+fn audit_entry_to_protobuf(py: Python<'_>, line: &str) -> PyResult<PyObject> {
+    let entry = AuditEntry::parse_line(line.trim_end()).ok_or_else(|| invalid_argument("bad audit log line"))?;
+    Ok(PyBytes::new(py, &entry.to_protobuf()).into())
+}
+
+/// Converts a `titancore.v1.AuditEntry` message back to a log line.
+#[pyfunction]
+fn audit_entry_from_protobuf(message: Vec<u8>) -> PyResult<String> {
+    Ok(AuditEntry::from_protobuf(&message).map_err(to_py_err)?.to_line())
+}
+
+/// Wraps `data` in a `-----BEGIN TITAN ...-----` block with a CRC-24 line.
+/// `kind` is one of `envelope`, `public-key`, `secret-key`,
+/// `signing-public-key`, `signing-secret-key`, `checkpoint`, `evidence`,
+/// `certificate`.
+/// Engine methods accept armored input wherever they take these.
+#[pyfunction]
+#[pyo3(name = "armor", signature = (data, kind="envelope"))]
+fn armor_bytes(py: Python<'_>, data: Vec<u8>, kind: &str) -> PyResult<PyObject> {
+    let kind = ArmorKind::parse(kind).ok_or_else(|| invalid_argument(format!("unknown armor kind: {}", kind)))?;
+    Ok(maybe_armor(py, kind, &data, true))
+}
+
+/// Decodes the first armored block in `text` as `(kind, data)`; raises
+/// `ValueError` on a bad checksum.
+#[pyfunction]
+fn dearmor(py: Python<'_>, text: Vec<u8>) -> PyResult<(String, PyObject)> {
+    let (kind, data) = armor::dearmor(&text).map_err(to_py_err)?;
+    Ok((kind.name(), PyBytes::new(py, &data).into()))
+}
+
+/// BIP39 English words for a secret key or other backup `data` (a multiple
+/// of 4 bytes), one checksummed group of up to 24 words per line. Up to 32
+/// bytes is a single standard BIP39 phrase.
+#[pyfunction]
+fn to_mnemonic(data: Vec<u8>) -> PyResult<String> {
+    Ok(mnemonic::encode(&data).map_err(to_py_err)?.to_string())
+}
+
+/// Decodes `to_mnemonic` output. Words may be in any case, split by any
+/// whitespace, or cut to their first four letters; raises `ValueError`
+/// naming an unknown word or the group whose checksum fails.
+#[pyfunction]
+fn from_mnemonic(py: Python<'_>, phrase: &str) -> PyResult<PyObject> {
+    let data = mnemonic::decode(phrase).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &data).into())
+}
+
+/// An escrow share (as from `generate_escrow_key`) as a mnemonic.
+#[pyfunction]
+fn share_to_mnemonic(share: Vec<u8>) -> PyResult<String> {
+    let share = EscrowShare::from_bytes(&share).map_err(to_py_err)?;
+    Ok(share.to_mnemonic().map_err(to_py_err)?.to_string())
+}
+
+/// The escrow share bytes `share_to_mnemonic` wrote `phrase` for.
+#[pyfunction]
+fn share_from_mnemonic(py: Python<'_>, phrase: &str) -> PyResult<PyObject> {
+    let share = EscrowShare::from_mnemonic(phrase).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &share.to_bytes()).into())
+}
+
+/// Unsigned CA certificate for organization `name` and its Dilithium5
+/// `public_key`. Sign it with the same keypair for a root, or with a parent
+/// CA's key for an intermediate.
+#[pyfunction]
+#[pyo3(signature = (name, public_key, valid_days=3650, serial=None))]
+fn ca_certificate_request(py: Python<'_>, name: &str, public_key: Vec<u8>, valid_days: u64, serial: Option<u64>) -> PyResult<PyObject> {
+    let now = unix_now();
+    let body = CertificateBody {
+        serial: random_serial(serial)?,
+        issuer: [0; 32],
+        subject: name.to_string(),
+        fingerprint: None,
+        public_key: unarmor(ArmorKind::SigningPublicKey, public_key)?,
+        not_before: now,
+        not_after: now + valid_days * 86_400,
+    };
+    Ok(PyBytes::new(py, &body.to_bytes()).into())
+}
+
+/// Signs a certificate request with the issuer's Dilithium5 keypair and
+/// returns the certificate.
+#[pyfunction]
+fn sign_certificate(py: Python<'_>, request: Vec<u8>, issuer_public_key: Vec<u8>, issuer_secret_key: Vec<u8>) -> PyResult<PyObject> {
+    let issuer_public_key = unarmor(ArmorKind::SigningPublicKey, issuer_public_key)?;
+    let issuer_secret_key = unarmor(ArmorKind::SigningSecretKey, issuer_secret_key)?;
+    let cert = CertificateBody::from_bytes(&request).and_then(|b| b.sign(&issuer_public_key, &issuer_secret_key)).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &cert.to_bytes()).into())
+}
+
+/// Vendor side of offline activation: answers `request` (from
+/// `generate_activation_request`, raw or armored) with an armored
+/// activation of `license` for `valid_days` from now, signed with the
+/// vendor's Dilithium5 secret key.
+#[pyfunction]
+#[pyo3(signature = (request, license, vendor_secret_key, valid_days=365))]
+fn grant_activation(py: Python<'_>, request: Vec<u8>, license: &str, vendor_secret_key: SecretArg, valid_days: u64) -> PyResult<PyObject> {
+    let request = ActivationRequest::from_bytes(&unarmor(ArmorKind::ActivationRequest, request)?).map_err(to_py_err)?;
+    let vendor_secret_key = vendor_secret_key.unarmor(ArmorKind::SigningSecretKey)?;
+    let now = unix_now();
+    let signed = request.grant(license, now, now + valid_days * 86_400).sign(&vendor_secret_key).map_err(to_py_err)?;
+    Ok(maybe_armor(py, ArmorKind::Activation, &signed.to_bytes(), true))
+}
+
+fn activation_dict<'py>(py: Python<'py>, signed: &SignedActivation, active: bool) -> PyResult<&'py PyDict> {
+    let a = &signed.activation;
+    let dict = PyDict::new(py);
+    dict.set_item("fingerprint", hex::encode(a.fingerprint))?;
+    dict.set_item("license", &a.license)?;
+    dict.set_item("not_before", a.not_before)?;
+    dict.set_item("not_after", a.not_after)?;
+    dict.set_item("active", active)?;
+    Ok(dict)
+}
+
+/// Checks `chain` (leaf first) against the trusted root public keys at the
+/// current time; returns the leaf as a dict (`serial`, `subject`,
+/// `fingerprint`, `issuer`, `public_key`, `not_before`, `not_after`) or
+/// raises `ValueError`.
+#[pyfunction]
+fn verify_certificate_chain(py: Python<'_>, chain: Vec<Vec<u8>>, trusted_roots: Vec<Vec<u8>>) -> PyResult<PyObject> {
+    let chain = parse_chain(chain)?;
+    let roots = trusted_roots.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+    let leaf = cert::verify_chain(&chain, &roots, unix_now()).map_err(to_py_err)?;
+    Ok(certificate_dict(py, leaf)?.into())
+}
+
+/// Like `verify_checkpoint`, but the signing key comes from an engine
+/// certificate chain rooted in `trusted_roots`; the dict also carries the
+/// engine's `license_serial`. Raises `ValueError` if anything fails.
+#[pyfunction]
+fn verify_attributed_checkpoint(py: Python<'_>, checkpoint: Vec<u8>, chain: Vec<Vec<u8>>, trusted_roots: Vec<Vec<u8>>) -> PyResult<PyObject> {
+    let cp = SignedCheckpoint::from_bytes(&unarmor(ArmorKind::Checkpoint, checkpoint)?).map_err(to_py_err)?;
+    let chain = parse_chain(chain)?;
+    let roots = trusted_roots.into_iter().map(|pk| unarmor(ArmorKind::SigningPublicKey, pk)).collect::<PyResult<Vec<_>>>()?;
+    let leaf = cert::verify_attributed_checkpoint(&cp, &chain, &roots, unix_now()).map_err(to_py_err)?;
+    let dict = checkpoint_dict(py, &cp)?;
+    dict.set_item("license_serial", &leaf.body.subject)?;
+    Ok(dict.into())
+}
+
+/// Hex key id (`BLAKE3(public_key)`) naming a key in certificates and
+/// revocation records.
+#[pyfunction]
+fn key_id(public_key: Vec<u8>) -> PyResult<String> {
+    Ok(hex::encode(cert::key_id(&unarmor(ArmorKind::PublicKey, public_key)?)))
+}
+
+/// Validates a Kyber-1024 recipient public key (raw or armored) and returns
+/// its raw bytes. With `expected_fingerprint` (hex key id, as from
+/// `key_id`) the key must also be the one it names. Fails with
+/// `KeyFormatError` saying what is wrong.
+#[pyfunction]
+#[pyo3(signature = (public_key, expected_fingerprint=None))]
+fn import_public_key(py: Python<'_>, public_key: Vec<u8>, expected_fingerprint: Option<&str>) -> PyResult<PyObject> {
+    let expected = expected_fingerprint.map(hex_key_id).transpose()?;
+    let pk = crypto::import_public_key(&unarmor(ArmorKind::PublicKey, public_key)?, expected.as_ref()).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &pk).into())
+}
+
+/// Revocation record for the key with hex `key_id`, signed with the
+/// issuer's Dilithium5 keypair. `reason` is one of `unspecified`,
+/// `key-compromise`, `superseded`, `cessation-of-operation`; `revoked_at`
+/// (Unix seconds) defaults to now.
+#[pyfunction]
+#[pyo3(signature = (key_id, issuer_public_key, issuer_secret_key, reason="unspecified", revoked_at=None))]
+fn revoke_key(py: Python<'_>, key_id: &str, issuer_public_key: Vec<u8>, issuer_secret_key: Vec<u8>, reason: &str,
+              revoked_at: Option<u64>) -> PyResult<PyObject> {
+    let mut id = [0u8; 32];
+    hex::decode_to_slice(key_id, &mut id).map_err(|_| invalid_argument("bad key id"))?;
+    let reason = RevocationReason::parse(reason).ok_or_else(|| invalid_argument(format!("unknown reason: {}", reason)))?;
+    let issuer_public_key = unarmor(ArmorKind::SigningPublicKey, issuer_public_key)?;
+    let issuer_secret_key = unarmor(ArmorKind::SigningSecretKey, issuer_secret_key)?;
+    let revocation = Revocation { key_id: id, revoked_at: revoked_at.unwrap_or_else(unix_now), reason };
+    let record = revocation.sign(&issuer_public_key, &issuer_secret_key).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &record.to_bytes()).into())
+}
+
+/// Random 32-byte salt for `fido2_lock`.
+#[pyfunction]
+fn fido2_salt(py: Python<'_>) -> PyResult<PyObject> {
+    Ok(PyBytes::new(py, &fido2::new_salt().map_err(to_py_err)?).into())
+}
+
+/// Encrypts `secret` so that it opens only with the FIDO2 `hmac-secret`
+/// output for `credential_id` and `salt`. `hmac_output` is that output,
+/// from the assertion the host made (with a touch) when locking.
+#[pyfunction]
+fn fido2_lock(py: Python<'_>, secret: BytesLike<'_>, credential_id: Vec<u8>, salt: Vec<u8>, hmac_output: Vec<u8>) -> PyResult<PyObject> {
+    let salt: [u8; fido2::HMAC_SECRET_LEN] = salt.try_into().map_err(|_| invalid_argument("salt must be 32 bytes"))?;
+    let locked = fido2::lock(&secret, &credential_id, &salt, &hmac_output).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &locked.to_bytes()).into())
+}
+
+/// `(credential_id, salt)` to run the `hmac-secret` assertion with before
+/// `fido2_unlock`.
+#[pyfunction]
+fn fido2_locked_params(py: Python<'_>, locked: Vec<u8>) -> PyResult<(PyObject, PyObject)> {
+    let locked = LockedSecret::from_bytes(&locked).map_err(to_py_err)?;
+    Ok((PyBytes::new(py, &locked.credential_id).into(), PyBytes::new(py, &locked.salt).into()))
+}
+
+/// The secret in `locked`, given the authenticator's `hmac-secret` output.
+#[pyfunction]
+fn fido2_unlock(py: Python<'_>, locked: Vec<u8>, hmac_output: Vec<u8>) -> PyResult<PyObject> {
+    let locked = LockedSecret::from_bytes(&locked).map_err(to_py_err)?;
+    let secret = fido2::unlock(&locked, &hmac_output).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &secret).into())
+}
+
+/// Overwrites the file at `path` `passes` times with random data, then
+/// truncates and removes it. Best effort only: SSDs, copy-on-write
+/// filesystems and snapshots may keep earlier copies.
+#[pyfunction]
+#[pyo3(signature = (path, passes=1))]
+fn secure_delete(py: Python<'_>, path: PathBuf, passes: u32) -> PyResult<()> {
+    py.allow_threads(|| shred::secure_delete(&path, passes)).map_err(to_py_err)
+}
+
+/// Encrypts `data` with Windows DPAPI for the current user (`scope="user"`)
+/// or any account on this machine (`"machine"`). `entropy`, if given, must
+/// be passed again to `dpapi_unprotect`.
+#[cfg(windows)]
+#[pyfunction]
+#[pyo3(signature = (data, scope="user", entropy=None))]
+fn dpapi_protect(py: Python<'_>, data: BytesLike<'_>, scope: &str, entropy: Option<Vec<u8>>) -> PyResult<PyObject> {
+    let scope = dpapi::DpapiScope::parse(scope).ok_or_else(|| invalid_argument(format!("unknown scope: {}", scope)))?;
+    let blob = dpapi::protect(&data, scope, &entropy.unwrap_or_default()).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &blob).into())
+}
+
+/// Decrypts a `dpapi_protect` blob; raises under another account or
+/// machine.
+#[cfg(windows)]
+#[pyfunction]
+#[pyo3(signature = (blob, entropy=None))]
+fn dpapi_unprotect(py: Python<'_>, blob: Vec<u8>, entropy: Option<Vec<u8>>) -> PyResult<PyObject> {
+    let data = dpapi::unprotect(&blob, &entropy.unwrap_or_default()).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &data).into())
+}
+
+/// Stores `secret` in the Linux kernel keyring as `name`, in the
+/// `"session"` or `"user"` keyring, expiring after `timeout` seconds if
+/// given. Returns the key's serial number.
+#[cfg(target_os = "linux")]
+#[pyfunction]
+#[pyo3(signature = (name, secret, scope="session", timeout=None))]
+fn kernel_keyring_store(name: &str, secret: BytesLike<'_>, scope: &str, timeout: Option<u64>) -> PyResult<i32> {
+    let scope = keyring_scope(scope)?;
+    let key = kernel_keyring::store(name, &secret, scope, timeout.map(Duration::from_secs)).map_err(to_py_err)?;
+    Ok(key.serial)
+}
+
+/// The secret stored as `name`, or `None` if there is none or it expired.
+#[cfg(target_os = "linux")]
+#[pyfunction]
+#[pyo3(signature = (name, scope="session"))]
+fn kernel_keyring_read(py: Python<'_>, name: &str, scope: &str) -> PyResult<Option<PyObject>> {
+    let Some(key) = kernel_keyring::find(name, keyring_scope(scope)?).map_err(to_py_err)? else { return Ok(None) };
+    let secret = key.read().map_err(to_py_err)?;
+    Ok(Some(PyBytes::new(py, &secret).into()))
+}
+
+/// Revokes the key stored as `name`; returns whether there was one.
+#[cfg(target_os = "linux")]
+#[pyfunction]
+#[pyo3(signature = (name, scope="session"))]
+fn kernel_keyring_revoke(name: &str, scope: &str) -> PyResult<bool> {
+    match kernel_keyring::find(name, keyring_scope(scope)?).map_err(to_py_err)? {
+        Some(key) => key.revoke().map(|()| true).map_err(to_py_err),
+        None => Ok(false),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_scope(scope: &str) -> PyResult<kernel_keyring::KeyringScope> {
+    kernel_keyring::KeyringScope::parse(scope).ok_or_else(|| invalid_argument(format!("unknown keyring: {}", scope)))
+}
+
+/// Saves `secret` in the macOS Keychain as the password of (`service`,
+/// `account`), replacing an existing one.
+#[cfg(target_os = "macos")]
+#[pyfunction]
+fn keychain_store(service: &str, account: &str, secret: BytesLike<'_>) -> PyResult<()> {
+    keychain::store(service, account, &secret).map_err(to_py_err)
+}
+
+/// The Keychain password of (`service`, `account`), or `None`.
+#[cfg(target_os = "macos")]
+#[pyfunction]
+fn keychain_load(py: Python<'_>, service: &str, account: &str) -> PyResult<Option<PyObject>> {
+    let secret = keychain::load(service, account).map_err(to_py_err)?;
+    Ok(secret.map(|secret| PyBytes::new(py, &secret).into()))
+}
+
+/// Removes (`service`, `account`) from the Keychain; returns whether it
+/// existed.
+#[cfg(target_os = "macos")]
+#[pyfunction]
+fn keychain_delete(service: &str, account: &str) -> PyResult<bool> {
+    keychain::delete(service, account).map_err(to_py_err)
+}
+
+/// The `.proto` definition of the protobuf messages.
+#[pyfunction]
+fn protobuf_schema() -> &'static str {
+    titancore_core::proto::PROTO_SCHEMA
+}
+
+#[pymodule]
+fn titancore_free(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("RekeyRequired", py.get_type::<RekeyRequired>())?;
+    m.add("DegradedEntropy", py.get_type::<DegradedEntropy>())?;
+    m.add("KeyFormatError", py.get_type::<KeyFormatError>())?;
+    m.add("ConstraintViolation", py.get_type::<ConstraintViolation>())?;
+    m.add_class::<SovereignEngine>()?;
+    m.add_class::<PyFixedClock>()?;
+    m.add_class::<PyKeyring>()?;
+    m.add_class::<PyContribution>()?;
+    m.add_class::<PyEpochKeys>()?;
+    m.add_class::<PySchemaRegistry>()?;
+    m.add_class::<PyChannelInitiator>()?;
+    m.add_class::<PyChannelResponder>()?;
+    m.add_class::<PySecureTransport>()?;
+    m.add_class::<PyRatchetSession>()?;
+    m.add_class::<PyMultipartUpload>()?;
+    m.add_class::<PyMultipartOpener>()?;
+    m.add_class::<PyStreamSealer>()?;
+    m.add_class::<PyStreamOpener>()?;
+    m.add_class::<PyCallerScope>()?;
+    m.add_class::<PyOperationScope>()?;
+    m.add_class::<PyEncryptedTempFile>()?;
+    m.add_class::<PyEngineHandle>()?;
+    m.add_function(wrap_pyfunction!(generate_keypair, m)?)?;
+    m.add_function(wrap_pyfunction!(kem_algorithms, m)?)?;
+    m.add_function(wrap_pyfunction!(suite_algorithms, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_signing_keypair, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_escrow_key, m)?)?;
+    m.add_function(wrap_pyfunction!(totp_code, m)?)?;
+    m.add_function(wrap_pyfunction!(decryption_request, m)?)?;
+    m.add_function(wrap_pyfunction!(approve_request, m)?)?;
+    m.add_function(wrap_pyfunction!(new_ceremony, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_ceremony_transcript, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_checkpoint, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_attestation, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_genesis, m)?)?;
+    m.add_function(wrap_pyfunction!(wrap_identity, m)?)?;
+    m.add_function(wrap_pyfunction!(unwrap_identity, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_rotation, m)?)?;
+    m.add_function(wrap_pyfunction!(recovery_kit, m)?)?;
+    m.add_function(wrap_pyfunction!(import_recovery_kit, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_segment, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_audit_log, m)?)?;
+    m.add_function(wrap_pyfunction!(read_audit_log, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_alarm, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_part_manifest, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_part, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_inclusion, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_evidence, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_evidence_for, m)?)?;
+    m.add_function(wrap_pyfunction!(kdf_self_test, m)?)?;
+    m.add_function(wrap_pyfunction!(entropy_health, m)?)?;
+    m.add_function(wrap_pyfunction!(enable_entropy_mixing, m)?)?;
+    m.add_function(wrap_pyfunction!(enable_guarded_memory, m)?)?;
+    m.add_function(wrap_pyfunction!(guarded_memory_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_test_vectors, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_test_vectors, m)?)?;
+    m.add_function(wrap_pyfunction!(run_acvp, m)?)?;
+    m.add_function(wrap_pyfunction!(envelope_to_protobuf, m)?)?;
+    m.add_function(wrap_pyfunction!(envelope_from_protobuf, m)?)?;
+    m.add_function(wrap_pyfunction!(audit_entry_to_protobuf, m)?)?;
+    m.add_function(wrap_pyfunction!(audit_entry_from_protobuf, m)?)?;
+    m.add_function(wrap_pyfunction!(protobuf_schema, m)?)?;
+    m.add_function(wrap_pyfunction!(armor_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(dearmor, m)?)?;
+    m.add_function(wrap_pyfunction!(to_mnemonic, m)?)?;
+    m.add_function(wrap_pyfunction!(from_mnemonic, m)?)?;
+    m.add_function(wrap_pyfunction!(share_to_mnemonic, m)?)?;
+    m.add_function(wrap_pyfunction!(share_from_mnemonic, m)?)?;
+    m.add_function(wrap_pyfunction!(ca_certificate_request, m)?)?;
+    m.add_function(wrap_pyfunction!(sign_certificate, m)?)?;
+    m.add_function(wrap_pyfunction!(grant_activation, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_certificate_chain, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_attributed_checkpoint, m)?)?;
+    m.add_function(wrap_pyfunction!(key_id, m)?)?;
+    m.add_function(wrap_pyfunction!(import_public_key, m)?)?;
+    m.add_function(wrap_pyfunction!(revoke_key, m)?)?;
+    m.add_function(wrap_pyfunction!(secure_delete, m)?)?;
+    m.add_function(wrap_pyfunction!(fido2_salt, m)?)?;
+    m.add_function(wrap_pyfunction!(fido2_lock, m)?)?;
+    m.add_function(wrap_pyfunction!(fido2_locked_params, m)?)?;
+    m.add_function(wrap_pyfunction!(fido2_unlock, m)?)?;
+    #[cfg(target_os = "linux")]
+    m.add_function(wrap_pyfunction!(kernel_keyring_store, m)?)?;
+    #[cfg(target_os = "linux")]
+    m.add_function(wrap_pyfunction!(kernel_keyring_read, m)?)?;
+    #[cfg(target_os = "linux")]
+    m.add_function(wrap_pyfunction!(kernel_keyring_revoke, m)?)?;
+    #[cfg(target_os = "macos")]
+    m.add_function(wrap_pyfunction!(keychain_store, m)?)?;
+    #[cfg(target_os = "macos")]
+    m.add_function(wrap_pyfunction!(keychain_load, m)?)?;
+    #[cfg(target_os = "macos")]
+    m.add_function(wrap_pyfunction!(keychain_delete, m)?)?;
+    #[cfg(windows)]
+    m.add_function(wrap_pyfunction!(dpapi_protect, m)?)?;
+    #[cfg(windows)]
+    m.add_function(wrap_pyfunction!(dpapi_unprotect, m)?)?;
+    Ok(())
+}
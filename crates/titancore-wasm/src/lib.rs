@@ -0,0 +1,49 @@
+//! WebAssembly bindings over `titancore-core`.
+//!
+//! Build with `cargo build -p titancore-wasm --target wasm32-unknown-unknown`.
+
+use std::sync::Arc;
+use titancore_core::{crypto, AuditEntry, Engine, Envelope, MemorySink};
+use wasm_bindgen::prelude::*;
+
+/// Browser/edge engine. There is no file audit on wasm: entries are buffered
+/// in memory and handed to the host via `drainAudit()`.
+#[wasm_bindgen]
+pub struct WasmEngine {
+    inner: Engine,
+    sink: Arc<MemorySink>,
+}
+
+#[wasm_bindgen]
+impl WasmEngine {
+    #[wasm_bindgen(constructor)]
+    pub fn new(hw_info: &str, seed: &str) -> Result<WasmEngine, JsError> {
+        let sink = Arc::new(MemorySink::new());
+        let inner = Engine::new(hw_info, seed, Box::new(sink.clone()))?;
+        Ok(WasmEngine { inner, sink })
+    }
+
+    /// Seals `data` to `pk` and returns the envelope bytes.
+    pub fn seal(&self, data: &[u8], pk: &[u8]) -> Result<Vec<u8>, JsError> {
+        let (env, _) = self.inner.seal(data, pk)?;
+        Ok(env.to_bytes())
+    }
+
+    /// Audit lines produced since the last call, in the flat-file format.
+    #[wasm_bindgen(js_name = drainAudit)]
+    pub fn drain_audit(&self) -> Vec<String> {
+        self.sink.drain().iter().map(AuditEntry::to_line).collect()
+    }
+}
+
+#[wasm_bindgen]
+pub fn open(envelope: &[u8], sk: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(Envelope::from_bytes(envelope)?.open(sk)?)
+}
+
+/// Returns `[publicKey, secretKey]`.
+#[wasm_bindgen(js_name = generateKeypair)]
+pub fn generate_keypair() -> Vec<js_sys::Uint8Array> {
+    let (pk, sk) = crypto::generate_keypair();
+    vec![js_sys::Uint8Array::from(&pk[..]), js_sys::Uint8Array::from(&sk[..])]
+}